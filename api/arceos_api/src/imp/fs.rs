@@ -7,6 +7,7 @@ pub use axfs::fops::FileAttr as AxFileAttr;
 pub use axfs::fops::FilePerm as AxFilePerm;
 pub use axfs::fops::FileType as AxFileType;
 pub use axfs::fops::OpenOptions as AxOpenOptions;
+pub use axfs::fsck::FsckReport as AxFsckReport;
 pub use axio::SeekFrom as AxSeekFrom;
 
 #[cfg(feature = "myfs")]
@@ -58,6 +59,19 @@ pub fn ax_file_attr(file: &AxFileHandle) -> AxResult<AxFileAttr> {
     file.0.get_attr()
 }
 
+pub fn ax_lock_file(file: &AxFileHandle, exclusive: bool, wait: bool) -> AxResult {
+    match (exclusive, wait) {
+        (true, true) => file.0.lock(),
+        (false, true) => file.0.lock_shared(),
+        (true, false) => file.0.try_lock(),
+        (false, false) => file.0.try_lock_shared(),
+    }
+}
+
+pub fn ax_unlock_file(file: &AxFileHandle) -> AxResult {
+    file.0.unlock()
+}
+
 pub fn ax_read_dir(dir: &mut AxDirHandle, dirents: &mut [AxDirEntry]) -> AxResult<usize> {
     dir.0.read_dir(dirents)
 }
@@ -78,6 +92,22 @@ pub fn ax_rename(old: &str, new: &str) -> AxResult {
     axfs::api::rename(old, new)
 }
 
+pub fn ax_hard_link(original: &str, link: &str) -> AxResult {
+    axfs::api::hard_link(original, link)
+}
+
+pub fn ax_symlink(original: &str, link: &str) -> AxResult {
+    axfs::api::symlink(original, link)
+}
+
+pub fn ax_read_link(path: &str) -> AxResult<String> {
+    axfs::api::read_link(path)
+}
+
+pub fn ax_set_max_symlink_follows(max: usize) -> usize {
+    axfs::api::set_max_symlink_follows(max)
+}
+
 pub fn ax_current_dir() -> AxResult<String> {
     axfs::api::current_dir()
 }
@@ -85,3 +115,15 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_umask() -> u32 {
+    axfs::api::umask()
+}
+
+pub fn ax_set_umask(umask: u32) -> u32 {
+    axfs::api::set_umask(umask)
+}
+
+pub fn ax_fsck(path: &str, repair: bool) -> AxResult<AxFsckReport> {
+    axfs::fsck::check_device(path, repair)
+}
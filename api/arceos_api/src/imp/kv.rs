@@ -0,0 +1,19 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use axerrno::AxResult;
+
+pub fn ax_kv_get(key: &str) -> AxResult<Option<Vec<u8>>> {
+    axkv::get(key)
+}
+
+pub fn ax_kv_put(key: &str, value: &[u8]) -> AxResult {
+    axkv::put(key, value)
+}
+
+pub fn ax_kv_remove(key: &str) -> AxResult {
+    axkv::remove(key)
+}
+
+pub fn ax_kv_keys() -> AxResult<Vec<String>> {
+    axkv::keys()
+}
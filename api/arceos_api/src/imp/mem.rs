@@ -3,6 +3,9 @@ use core::alloc::Layout;
 cfg_alloc! {
     use core::ptr::NonNull;
 
+    /// Statistics of the global allocator.
+    pub use axalloc::MemStats as AxMemStats;
+
     pub fn ax_alloc(layout: Layout) -> Option<NonNull<u8>> {
         axalloc::global_allocator().alloc(layout).ok()
     }
@@ -10,6 +13,10 @@ cfg_alloc! {
     pub fn ax_dealloc(ptr: NonNull<u8>, layout: Layout) {
         axalloc::global_allocator().dealloc(ptr, layout)
     }
+
+    pub fn ax_mem_stats() -> AxMemStats {
+        axalloc::global_allocator().stats()
+    }
 }
 
 cfg_dma! {
@@ -16,22 +16,106 @@ cfg_display! {
     pub use display::*;
 }
 
+cfg_kv! {
+    mod kv;
+    pub use kv::*;
+}
+
+cfg_update! {
+    mod update;
+    pub use update::*;
+}
+
+cfg_uspace! {
+    mod process;
+    pub use process::*;
+}
+
 mod stdio {
     use core::fmt;
+    use core::time::Duration;
+
+    use axerrno::AxError;
 
     /// read bytes
     /// 
     /// - from ulib::axstd::io::StdinRaw.read(buf)
     pub fn ax_console_read_bytes(buf: &mut [u8]) -> crate::AxResult<usize> {
-        // 调用硬件抽象层的read_bytes, 这里先看x86_64
-        let len = axhal::console::read_bytes(buf);
-        // 把回车替换为换行
-        for c in &mut buf[..len] {
-            if *c == b'\r' {
-                *c = b'\n';
+        // 调用硬件抽象层的read_bytes, 回车已被转换为换行
+        Ok(axhal::console::read_bytes(buf))
+    }
+
+    pub fn ax_console_read_bytes_blocking(buf: &mut [u8]) -> crate::AxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            #[cfg(all(feature = "irq", feature = "multitask"))]
+            let events = axhal::console::rx_events();
+            let len = axhal::console::read_bytes(buf);
+            if len > 0 {
+                return Ok(len);
+            }
+            #[cfg(all(feature = "irq", feature = "multitask"))]
+            wait_for_input(events, POLL_INTERVAL);
+            #[cfg(all(not(feature = "irq"), feature = "multitask"))]
+            axtask::yield_now();
+            #[cfg(not(feature = "multitask"))]
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn ax_console_read_bytes_timeout(
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> crate::AxResult<usize> {
+        let deadline = axhal::time::monotonic_time() + timeout;
+        loop {
+            #[cfg(all(feature = "irq", feature = "multitask"))]
+            let events = axhal::console::rx_events();
+            let len = axhal::console::read_bytes(buf);
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
             }
+            let now = axhal::time::monotonic_time();
+            if now >= deadline {
+                return Err(AxError::TimedOut);
+            }
+            #[cfg(all(feature = "irq", feature = "multitask"))]
+            wait_for_input(events, POLL_INTERVAL.min(deadline - now));
+            #[cfg(all(not(feature = "irq"), feature = "multitask"))]
+            axtask::yield_now();
+            #[cfg(not(feature = "multitask"))]
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Input of consoles without receive interrupts is polled at this
+    /// interval.
+    #[cfg(all(feature = "irq", feature = "multitask"))]
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Sleeps until the console receives input after `events` (see
+    /// [`axhal::console::rx_events`]), or for at most `timeout`.
+    #[cfg(all(feature = "irq", feature = "multitask"))]
+    fn wait_for_input(events: usize, timeout: Duration) {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        use axtask::WaitQueue;
+
+        static RX_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+        static NOTIFIER_SET: AtomicBool = AtomicBool::new(false);
+
+        if !NOTIFIER_SET.swap(true, Ordering::AcqRel) {
+            axhal::console::set_rx_notifier(|| RX_WAIT_QUEUE.notify_all(false));
         }
-        Ok(len)
+        RX_WAIT_QUEUE.wait_timeout_until(timeout, || axhal::console::rx_events() != events);
+    }
+
+    pub use axhal::console::ConsoleMode as AxConsoleMode;
+
+    pub fn ax_console_set_mode(mode: AxConsoleMode) {
+        axhal::console::set_mode(mode);
     }
 
     pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize> {
@@ -40,7 +124,17 @@ mod stdio {
     }
 
     pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result {
-        axlog::print_fmt(args)
+        /// Writes the output of applications, unlike the writer of `axlog`.
+        struct StdoutWriter;
+
+        impl fmt::Write for StdoutWriter {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                axhal::console::write_bytes(s.as_bytes());
+                Ok(())
+            }
+        }
+
+        axlog::with_print_lock(|| fmt::Write::write_fmt(&mut StdoutWriter, args))
     }
 }
 
@@ -55,5 +149,57 @@ pub use self::stdio::*;
 pub use self::task::*;
 pub use self::time::*;
 
-pub use axhal::misc::terminate as ax_terminate;
+pub use axhal::misc::exit as ax_terminate;
 pub use axio::PollState as AxPollState;
+pub use axlog::{max_level as ax_log_level, set_target_level as ax_set_log_target_level};
+pub use axruntime::buildinfo::{BuildInfo as AxBuildInfo, build_info as ax_build_info};
+pub use axruntime::sysinfo::{SystemInfo as AxSystemInfo, system_info as ax_system_info};
+
+#[cfg(feature = "irq")]
+pub use axhal::irq::set_enable as ax_irq_set_enable;
+
+#[cfg(feature = "watch")]
+pub use axhal::watch::Watchpoint as AxWatchpoint;
+
+#[cfg(feature = "watch")]
+pub fn ax_watch(addr: usize, len: usize) -> crate::AxResult<usize> {
+    use axerrno::AxError;
+
+    if !axhal::watch::is_supported() {
+        return Err(AxError::Unsupported);
+    }
+    if !matches!(len, 1 | 2 | 4 | 8) || addr % len != 0 {
+        return Err(AxError::InvalidInput);
+    }
+    axhal::watch::set(addr, len).ok_or(AxError::ResourceBusy)
+}
+
+#[cfg(feature = "watch")]
+pub fn ax_unwatch(index: usize) -> crate::AxResult {
+    axhal::watch::clear(index)
+        .map(|_| ())
+        .ok_or(axerrno::AxError::NotFound)
+}
+
+#[cfg(feature = "watch")]
+pub fn ax_watch_list() -> alloc::vec::Vec<(usize, AxWatchpoint)> {
+    axhal::watch::list()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, watchpoint)| Some((index, watchpoint?)))
+        .collect()
+}
+
+pub fn ax_set_log_level(level: &str) -> bool {
+    const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+    if !LEVELS.iter().any(|l| l.eq_ignore_ascii_case(level)) {
+        return false;
+    }
+    axlog::set_max_level(level);
+    true
+}
+
+#[cfg(feature = "guest-agent")]
+pub use axruntime::guest_agent::{
+    AgentCommand as AxAgentCommand, register_command as ax_register_agent_command,
+};
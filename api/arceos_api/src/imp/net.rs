@@ -9,6 +9,9 @@ pub struct AxTcpSocketHandle(TcpSocket);
 /// A handle to a UDP socket.
 pub struct AxUdpSocketHandle(UdpSocket);
 
+/// The addresses and the statistics of the network interface.
+pub use axnet::InterfaceInfo as AxNetIfaceInfo;
+
 ////////////////////////////////////////////////////////////////////////////////
 // TCP socket
 ////////////////////////////////////////////////////////////////////////////////
@@ -129,3 +132,23 @@ pub fn ax_poll_interfaces() -> AxResult {
     axnet::poll_interfaces();
     Ok(())
 }
+
+pub fn ax_ping(
+    addr: IpAddr,
+    seq: u16,
+    timeout: core::time::Duration,
+) -> AxResult<core::time::Duration> {
+    axnet::ping(addr, seq, timeout)
+}
+
+pub fn ax_net_iface_info() -> AxNetIfaceInfo {
+    axnet::interface_info()
+}
+
+pub fn ax_net_mtu() -> (usize, usize) {
+    (axnet::mtu(), axnet::max_mtu())
+}
+
+pub fn ax_net_set_mtu(mtu: usize) -> AxResult {
+    axnet::set_mtu(mtu)
+}
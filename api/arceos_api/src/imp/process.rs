@@ -0,0 +1,21 @@
+use alloc::sync::Arc;
+
+use axerrno::AxResult;
+
+/// A handle to a user process.
+pub struct AxProcessHandle(Arc<axprocess::Process>);
+
+impl AxProcessHandle {
+    /// Returns the process ID.
+    pub fn pid(&self) -> u64 {
+        self.0.pid()
+    }
+}
+
+pub fn ax_spawn_process(path: &str, args: &[&str]) -> AxResult<AxProcessHandle> {
+    axprocess::spawn_path(path, args).map(AxProcessHandle)
+}
+
+pub fn ax_wait_process_exit(process: &AxProcessHandle) -> i32 {
+    process.0.wait_exit()
+}
@@ -29,7 +29,7 @@ pub fn ax_exit(_exit_code: i32) -> ! {
     #[cfg(feature = "multitask")]
     axtask::exit(_exit_code);
     #[cfg(not(feature = "multitask"))]
-    axhal::misc::terminate();
+    axhal::misc::exit(_exit_code);
 }
 
 cfg_task! {
@@ -51,6 +51,24 @@ cfg_task! {
     /// A mask to specify the CPU affinity.
     pub use axtask::AxCpuMask;
 
+    /// The state of a task.
+    pub use axtask::TaskState as AxTaskState;
+
+    /// Information about a task, see [`ax_task_list`].
+    #[derive(Debug, Clone)]
+    pub struct AxTaskInfo {
+        /// The task ID.
+        pub id: u64,
+        /// The name of the task.
+        pub name: alloc::string::String,
+        /// The state of the task.
+        pub state: AxTaskState,
+        /// The priority of the task, 0 if it was never set.
+        pub priority: isize,
+        /// The CPU time consumed by the task.
+        pub cpu_time: Duration,
+    }
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -65,7 +83,7 @@ cfg_task! {
     }
 
     pub fn ax_current_task_id() -> u64 {
-        axtask::current().id().as_u64()
+        axtask::current().ns_id()
     }
 
     pub fn ax_spawn<F>(f: F, name: alloc::string::String, stack_size: usize) -> AxTaskHandle
@@ -74,15 +92,72 @@ cfg_task! {
     {
         let inner = axtask::spawn_raw(f, name, stack_size);
         AxTaskHandle {
-            id: inner.id().as_u64(),
+            id: inner.ns_id(),
             inner,
         }
     }
 
+    pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo> {
+        axtask::ns_tasks()
+            .iter()
+            .map(|task| AxTaskInfo {
+                id: task.ns_id(),
+                name: task.name().into(),
+                state: task.state(),
+                priority: task.priority(),
+                cpu_time: task.cpu_time(),
+            })
+            .collect()
+    }
+
+    pub fn ax_ns_create(flags: u32) -> crate::AxResult {
+        use crate::task::{AX_NS_FS, AX_NS_NET, AX_NS_TASK};
+
+        if flags & !(AX_NS_FS | AX_NS_NET | AX_NS_TASK) != 0 {
+            return axerrno::ax_err!(InvalidInput, "ax_ns_create: unknown flags");
+        }
+        if (flags & AX_NS_FS != 0 && !cfg!(feature = "fs"))
+            || (flags & AX_NS_NET != 0 && !cfg!(feature = "net"))
+        {
+            return axerrno::ax_err!(Unsupported, "ax_ns_create: feature not enabled");
+        }
+        #[cfg(feature = "fs")]
+        if flags & AX_NS_FS != 0 {
+            axfs::api::unshare_root()?;
+        }
+        #[cfg(feature = "net")]
+        if flags & AX_NS_NET != 0 {
+            axnet::unshare_net();
+        }
+        if flags & AX_NS_TASK != 0 {
+            axtask::unshare_task_namespace();
+        }
+        Ok(())
+    }
+
     pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32> {
         task.inner.join()
     }
 
+    pub fn ax_yield_to(task: &AxTaskHandle) -> bool {
+        axtask::yield_to(&task.inner)
+    }
+
+    pub fn ax_set_foreground() {
+        let curr = axtask::current();
+        curr.clear_cancelled();
+        axtask::set_foreground(Some(curr.as_task_ref().clone()));
+    }
+
+    pub fn ax_is_cancelled(clear: bool) -> bool {
+        let curr = axtask::current();
+        if clear {
+            curr.clear_cancelled()
+        } else {
+            curr.is_cancelled()
+        }
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
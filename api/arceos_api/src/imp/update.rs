@@ -0,0 +1,27 @@
+use axerrno::AxResult;
+
+pub use axupdate::Slot as AxUpdateSlot;
+
+pub fn ax_update_booted_slot() -> AxResult<AxUpdateSlot> {
+    axupdate::booted_slot()
+}
+
+pub fn ax_update_is_on_trial() -> AxResult<bool> {
+    axupdate::is_on_trial()
+}
+
+pub fn ax_update_mark_healthy() -> AxResult {
+    axupdate::mark_healthy()
+}
+
+pub fn ax_update_begin() -> AxResult<AxUpdateSlot> {
+    axupdate::begin_update()
+}
+
+pub fn ax_update_write(offset: u64, data: &[u8]) -> AxResult {
+    axupdate::write_image(offset, data)
+}
+
+pub fn ax_update_finish(size: u64) -> AxResult<AxUpdateSlot> {
+    axupdate::finish_update(size)
+}
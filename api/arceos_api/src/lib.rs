@@ -10,6 +10,7 @@
 #[cfg(any(
     feature = "alloc",
     feature = "fs",
+    feature = "kv",
     feature = "net",
     feature = "multitask",
     feature = "dummy-if-not-enabled"
@@ -29,9 +30,86 @@ pub mod config {
 
 /// System operations.
 pub mod sys {
+    define_api_type! {
+        pub type AxSystemInfo;
+        pub type AxBuildInfo;
+    }
+
     define_api! {
         /// Shutdown the whole system and all CPUs.
-        pub fn ax_terminate() -> !;
+        ///
+        /// The exit code is reported to QEMU if the `qemu-exit` feature is
+        /// enabled, otherwise it is ignored.
+        pub fn ax_terminate(exit_code: i32) -> !;
+        /// Returns a summary of the running system, including the platform,
+        /// CPUs online, memory, enabled features, drivers and boot duration.
+        ///
+        /// The same information is printed as a line of JSON at the end of
+        /// boot.
+        pub fn ax_system_info() -> AxSystemInfo;
+        /// Returns the metadata of the build of the running kernel: the
+        /// version, git commit, application, platform, build time, compiler
+        /// and enabled features.
+        pub fn ax_build_info() -> &'static AxBuildInfo;
+
+        /// Returns the maximum level of the log records printed, one of
+        /// `off`, `error`, `warn`, `info`, `debug`, `trace`.
+        pub fn ax_log_level() -> &'static str;
+        /// Sets the maximum level of the log records printed. Returns `false`
+        /// if `level` is not one of those returned by [`ax_log_level`].
+        ///
+        /// It has no effect on the levels filtered out at build time with the
+        /// `log-level-*` features.
+        pub fn ax_set_log_level(level: &str) -> bool;
+        /// Sets the maximum level of the log records of a module and its
+        /// submodules (e.g., `axnet`), or resets it to that set by
+        /// [`ax_set_log_level`] if `level` is `None`.
+        ///
+        /// Returns `false` if `level` is not valid, or if too many modules
+        /// have their own level.
+        pub fn ax_set_log_target_level(target: &str, level: Option<&str>) -> bool;
+    }
+
+    define_api! {
+        @cfg "irq";
+        /// Enables or disables an interrupt (a vector on x86_64).
+        pub fn ax_irq_set_enable(irq_num: usize, enabled: bool);
+    }
+
+    define_api_type! {
+        @cfg "watch";
+        /// A range of memory watched by a hardware watchpoint.
+        pub type AxWatchpoint;
+    }
+
+    define_api! {
+        @cfg "watch";
+        /// Watches the writes to `len` bytes at `addr` with a hardware
+        /// watchpoint, and returns its index. The task, the PC and a
+        /// backtrace are printed when it is hit.
+        ///
+        /// `len` must be 1, 2, 4 or 8, and `addr` aligned to it. Fails with
+        /// [`crate::AxError::ResourceBusy`] if all the watchpoints are in use, and
+        /// [`crate::AxError::Unsupported`] if the CPU has none supported.
+        pub fn ax_watch(addr: usize, len: usize) -> crate::AxResult<usize>;
+        /// Removes a watchpoint set with [`ax_watch`].
+        pub fn ax_unwatch(index: usize) -> crate::AxResult;
+        /// Returns the watchpoints set, with their indexes.
+        pub fn ax_watch_list() -> alloc::vec::Vec<(usize, AxWatchpoint)>;
+    }
+
+    define_api_type! {
+        @cfg "guest-agent";
+        /// A command that can be run by the `guest-exec` command of the guest
+        /// agent. It receives the arguments joined by spaces.
+        pub type AxAgentCommand;
+    }
+
+    define_api! {
+        @cfg "guest-agent";
+        /// Registers a command that can be run by the `guest-exec` command of
+        /// the guest agent, replacing the command with the same name.
+        pub fn ax_register_agent_command(name: &'static str, cmd: AxAgentCommand);
     }
 }
 
@@ -75,6 +153,19 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api_type! {
+        @cfg "alloc";
+        pub type AxMemStats;
+    }
+
+    define_api! {
+        @cfg "alloc";
+        /// Returns the statistics of the global allocator: the usage of the
+        /// heap and of the physical pages, their peaks, and their largest
+        /// free blocks.
+        pub fn ax_mem_stats() -> AxMemStats;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
@@ -105,9 +196,37 @@ pub mod mem {
 /// Standard input and output.
 pub mod stdio {
     use core::fmt;
+    define_api_type! {
+        /// The mode of the console input, either raw or cooked (echoed and
+        /// edited line by line, the default).
+        pub type AxConsoleMode;
+    }
+
     define_api! {
         /// Reads a slice of bytes from the console, returns the number of bytes written.
         pub fn ax_console_read_bytes(buf: &mut [u8]) -> crate::AxResult<usize>;
+        /// Reads a slice of bytes from the console, blocking until at least
+        /// one byte is read, returns the number of bytes read.
+        ///
+        /// With the `irq` and `multitask` features, the current task sleeps
+        /// until the console receives input, otherwise the console is polled.
+        pub fn ax_console_read_bytes_blocking(buf: &mut [u8]) -> crate::AxResult<usize>;
+        /// Reads a slice of bytes from the console, blocking until at least
+        /// one byte is read or the timeout expires, returns the number of
+        /// bytes read.
+        ///
+        /// Returns [`AxError::TimedOut`](crate::AxError::TimedOut) if nothing
+        /// is read within the timeout. It waits as
+        /// [`ax_console_read_bytes_blocking`] does.
+        pub fn ax_console_read_bytes_timeout(
+            buf: &mut [u8],
+            timeout: core::time::Duration,
+        ) -> crate::AxResult<usize>;
+        /// Sets the mode of the console input.
+        ///
+        /// Programs doing their own echo and line editing switch to the raw
+        /// mode, so each byte is read as soon as it is received.
+        pub fn ax_console_set_mode(mode: AxConsoleMode);
         /// Writes a slice of bytes to the console, returns the number of bytes written.
         pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize>;
         /// Writes a formatted string to the console.
@@ -117,11 +236,23 @@ pub mod stdio {
 
 /// Multi-threading management.
 pub mod task {
+    /// Flag of [`ax_ns_create`] to make the current directory the root
+    /// directory.
+    pub const AX_NS_FS: u32 = 1 << 0;
+    /// Flag of [`ax_ns_create`] to give the tasks a network stack of their
+    /// own, with only a loopback interface.
+    pub const AX_NS_NET: u32 = 1 << 1;
+    /// Flag of [`ax_ns_create`] to number the tasks from 1, and only see the
+    /// tasks in the same namespace.
+    pub const AX_NS_TASK: u32 = 1 << 2;
+
     define_api_type! {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
         pub type AxCpuMask;
+        pub type AxTaskState;
+        pub type AxTaskInfo;
     }
 
     define_api! {
@@ -143,7 +274,7 @@ pub mod task {
     define_api! {
         @cfg "multitask";
 
-        /// Returns the current task's ID.
+        /// Returns the current task's ID, in its task ID namespace.
         pub fn ax_current_task_id() -> u64;
         /// Spawns a new task with the given entry point and other arguments.
         pub fn ax_spawn(
@@ -151,9 +282,35 @@ pub mod task {
             name: alloc::string::String,
             stack_size: usize
         ) -> AxTaskHandle;
+        /// Returns the ID, name, state, priority and CPU time of the tasks
+        /// which have not been dropped yet, in the order of their creation.
+        ///
+        /// Only the tasks in the task ID namespace of the current task are
+        /// listed, with their IDs in it.
+        pub fn ax_task_list() -> alloc::vec::Vec<AxTaskInfo>;
+        /// Moves the current task, and the tasks it creates afterwards, to new
+        /// namespaces, as selected by `flags` ([`AX_NS_FS`], [`AX_NS_NET`] and
+        /// [`AX_NS_TASK`]), to run them as a lightweight container.
+        ///
+        /// Returns [`AxError::Unsupported`](crate::AxError::Unsupported) if a
+        /// namespace needs a feature which is not enabled (`fs` or `net`).
+        pub fn ax_ns_create(flags: u32) -> crate::AxResult;
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Gives up the CPU time to the given task, which runs next if it is
+        /// ready on the current CPU, e.g., a task serving the requests the
+        /// current task just queued. Otherwise, it yields like
+        /// [`ax_yield_now`].
+        ///
+        /// Returns `true` if it switched to the given task.
+        pub fn ax_yield_to(task: &AxTaskHandle) -> bool;
+        /// Makes the current task the foreground task, cancelled when
+        /// `Ctrl-C` is pressed on the console, and clears its cancellation.
+        pub fn ax_set_foreground();
+        /// Whether the current task is cancelled, e.g., by `Ctrl-C` (see
+        /// [`ax_set_foreground`]). If `clear`, the cancellation is cleared.
+        pub fn ax_is_cancelled(clear: bool) -> bool;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
         /// Sets the cpu affinity of the current task.
@@ -192,6 +349,7 @@ pub mod fs {
         pub type AxFilePerm;
         pub type AxDirEntry;
         pub type AxSeekFrom;
+        pub type AxFsckReport;
         #[cfg(feature = "myfs")]
         pub type AxDisk;
         #[cfg(feature = "myfs")]
@@ -236,6 +394,11 @@ pub mod fs {
         pub fn ax_seek_file(file: &mut AxFileHandle, pos: AxSeekFrom) -> AxResult<u64>;
         /// Returns attributes of the file.
         pub fn ax_file_attr(file: &AxFileHandle) -> AxResult<AxFileAttr>;
+        /// Takes an advisory lock on the file, exclusive or shared, waiting
+        /// for it if `wait` is set, or failing with `WouldBlock` otherwise.
+        pub fn ax_lock_file(file: &AxFileHandle, exclusive: bool, wait: bool) -> AxResult;
+        /// Releases the advisory lock held by the file handle, if any.
+        pub fn ax_unlock_file(file: &AxFileHandle) -> AxResult;
 
         /// Reads directory entries starts from the current position into the
         /// given buffer, returns the number of entries read.
@@ -255,11 +418,32 @@ pub mod fs {
         ///
         /// It will delete the original file if `old` already exists.
         pub fn ax_rename(old: &str, new: &str) -> AxResult;
+        /// Creates a hard link `link` to the file `original`, in a same RAM
+        /// filesystem.
+        pub fn ax_hard_link(original: &str, link: &str) -> AxResult;
+        /// Creates a symbolic link `link` pointing to the path `original`.
+        pub fn ax_symlink(original: &str, link: &str) -> AxResult;
+        /// Returns the path that a symbolic link points to.
+        pub fn ax_read_link(path: &str) -> AxResult<alloc::string::String>;
+        /// Sets the maximum number of symbolic links followed when resolving
+        /// a path, and returns the previous one.
+        pub fn ax_set_max_symlink_follows(max: usize) -> usize;
 
         /// Returns the current working directory.
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+        /// Returns the file mode creation mask of the current task.
+        pub fn ax_umask() -> u32;
+        /// Sets the file mode creation mask of the current task, and returns
+        /// the previous one.
+        pub fn ax_set_umask(umask: u32) -> u32;
+
+        /// Checks the FAT filesystem on a block device file, e.g.,
+        /// `/dev/sda1`, and repairs it if `repair` is set.
+        ///
+        /// The device of the mounted main filesystem can only be checked.
+        pub fn ax_fsck(path: &str, repair: bool) -> AxResult<AxFsckReport>;
     }
 }
 
@@ -272,6 +456,7 @@ pub mod net {
         @cfg "net";
         pub type AxTcpSocketHandle;
         pub type AxUdpSocketHandle;
+        pub type AxNetIfaceInfo;
     }
 
     define_api! {
@@ -348,13 +533,35 @@ pub mod net {
 
         // Miscellaneous
 
-        /// Resolves the host name to a list of IP addresses.
+        /// Resolves the host name to a list of IP addresses, the IPv4 ones
+        /// first, then the IPv6 ones.
         pub fn ax_dns_query(domain_name: &str) -> AxResult<alloc::vec::Vec<IpAddr>>;
         /// Poll the network stack.
         ///
         /// It may receive packets from the NIC and process them, and transmit queued
         /// packets to the NIC.
         pub fn ax_poll_interfaces() -> AxResult;
+        /// Sends an ICMP echo request to the address, with the sequence
+        /// number `seq`, and returns the round-trip time of the reply.
+        ///
+        /// Returns [`AxError::TimedOut`](crate::AxError::TimedOut) if there
+        /// is no reply within `timeout`.
+        pub fn ax_ping(
+            addr: IpAddr,
+            seq: u16,
+            timeout: core::time::Duration
+        ) -> AxResult<core::time::Duration>;
+        /// Returns the MAC and IP addresses, the gateway, the MTU and the
+        /// packet counters of the network interface.
+        pub fn ax_net_iface_info() -> AxNetIfaceInfo;
+        /// Returns the current and the maximum MTU of the network interface,
+        /// in bytes.
+        pub fn ax_net_mtu() -> (usize, usize);
+        /// Sets the MTU of the network interface, in bytes.
+        ///
+        /// MTUs above 1500 bytes (jumbo frames) are only accepted if the NIC
+        /// supports them.
+        pub fn ax_net_set_mtu(mtu: usize) -> AxResult;
     }
 }
 
@@ -374,6 +581,76 @@ pub mod display {
     }
 }
 
+/// Persistent key-value store operations.
+pub mod kv {
+    use crate::AxResult;
+
+    define_api! {
+        @cfg "kv";
+        /// Returns the value of the given key, or `None` if it does not
+        /// exist.
+        pub fn ax_kv_get(key: &str) -> AxResult<Option<alloc::vec::Vec<u8>>>;
+        /// Sets the value of the given key. It is written to the block device
+        /// before returning, so it survives reboots and crashes.
+        pub fn ax_kv_put(key: &str, value: &[u8]) -> AxResult;
+        /// Removes the given key. The removal is written to the block device
+        /// before returning.
+        pub fn ax_kv_remove(key: &str) -> AxResult;
+        /// Returns all keys, in order.
+        pub fn ax_kv_keys() -> AxResult<alloc::vec::Vec<alloc::string::String>>;
+    }
+}
+
+/// A/B image update operations.
+pub mod update {
+    use crate::AxResult;
+
+    define_api_type! {
+        @cfg "update";
+        /// An image slot, A or B.
+        pub type AxUpdateSlot;
+    }
+
+    define_api! {
+        @cfg "update";
+        /// Returns the image slot booted.
+        pub fn ax_update_booted_slot() -> AxResult<AxUpdateSlot>;
+        /// Whether the booted slot is on trial, i.e., it is rolled back at
+        /// the next reboot unless marked healthy.
+        pub fn ax_update_is_on_trial() -> AxResult<bool>;
+        /// Reports that the booted slot is healthy, so it keeps booting.
+        pub fn ax_update_mark_healthy() -> AxResult;
+        /// Starts an update, which writes a new image to the inactive slot,
+        /// and returns that slot.
+        pub fn ax_update_begin() -> AxResult<AxUpdateSlot>;
+        /// Writes a part of the new image at the given offset.
+        pub fn ax_update_write(offset: u64, data: &[u8]) -> AxResult;
+        /// Finishes the update with an image of the given size, which boots
+        /// once after the next reboot.
+        pub fn ax_update_finish(size: u64) -> AxResult<AxUpdateSlot>;
+    }
+}
+
+/// User process management.
+pub mod process {
+    use crate::AxResult;
+
+    define_api_type! {
+        @cfg "uspace";
+        /// A handle to a user process.
+        pub type AxProcessHandle;
+    }
+
+    define_api! {
+        @cfg "uspace";
+        /// Spawns a user process running the ELF executable at `path` in a
+        /// new address space, with `args` as its `argv`.
+        pub fn ax_spawn_process(path: &str, args: &[&str]) -> AxResult<AxProcessHandle>;
+        /// Waits for the given process to exit, and returns its exit code.
+        pub fn ax_wait_process_exit(process: &AxProcessHandle) -> i32;
+    }
+}
+
 /// Input/output operations.
 pub mod io {
     define_api_type! {
@@ -402,10 +679,16 @@ pub mod modules {
     pub use axdriver;
     #[cfg(feature = "fs")]
     pub use axfs;
+    #[cfg(feature = "kv")]
+    pub use axkv;
     #[cfg(feature = "paging")]
     pub use axmm;
     #[cfg(feature = "net")]
     pub use axnet;
+    #[cfg(feature = "uspace")]
+    pub use axprocess;
     #[cfg(feature = "multitask")]
     pub use axtask;
+    #[cfg(feature = "update")]
+    pub use axupdate;
 }
@@ -101,6 +101,14 @@ macro_rules! cfg_net {
     ($($item:item)*) => { _cfg_common!{ "net" $($item)* } }
 }
 
+macro_rules! cfg_kv {
+    ($($item:item)*) => { _cfg_common!{ "kv" $($item)* } }
+}
+
+macro_rules! cfg_update {
+    ($($item:item)*) => { _cfg_common!{ "update" $($item)* } }
+}
+
 macro_rules! cfg_display {
     ($($item:item)*) => { _cfg_common!{ "display" $($item)* } }
 }
@@ -108,3 +116,7 @@ macro_rules! cfg_display {
 macro_rules! cfg_task {
     ($($item:item)*) => { _cfg_common!{ "multitask" $($item)* } }
 }
+
+macro_rules! cfg_uspace {
+    ($($item:item)*) => { _cfg_common!{ "uspace" $($item)* } }
+}
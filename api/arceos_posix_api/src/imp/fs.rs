@@ -99,6 +99,9 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     if flags & ctypes::O_EXEC != 0 {
         options.create_new(true);
     }
+    if flags & ctypes::O_DIRECT != 0 {
+        options.direct(true);
+    }
     options
 }
 
@@ -469,7 +469,10 @@ pub unsafe fn sys_getaddrinfo(
             if let Ok(a) = domain.parse::<IpAddr>() {
                 vec![a]
             } else {
-                axnet::dns_query(domain)?
+                // Only `AF_INET` sockets are supported.
+                let mut addrs = axnet::dns_query(domain)?;
+                addrs.retain(IpAddr::is_ipv4);
+                addrs
             }
         } else {
             vec![Ipv4Addr::LOCALHOST.into()]
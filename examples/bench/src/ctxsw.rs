@@ -0,0 +1,29 @@
+//! Context switch latency: two threads yield to each other.
+//!
+//! It is only meaningful with one CPU (`SMP=1`), on which each yield switches
+//! to the other thread.
+
+use std::thread;
+use std::time::Instant;
+
+use crate::harness::{Bench, BenchResult};
+
+/// The number of yields of each thread.
+const YIELDS: u32 = 50_000;
+
+pub fn run(bench: &Bench) -> BenchResult {
+    let start = Instant::now();
+    let other = thread::spawn(|| {
+        for _ in 0..YIELDS {
+            thread::yield_now();
+        }
+    });
+    for _ in 0..YIELDS {
+        thread::yield_now();
+    }
+    other.join().unwrap();
+    let switches = 2 * YIELDS as u128;
+    let latency = start.elapsed().as_nanos() / switches;
+    bench.report("switch_latency", latency as u64, "ns");
+    Ok(())
+}
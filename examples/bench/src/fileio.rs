@@ -0,0 +1,42 @@
+//! File I/O throughput: a file written, then read back.
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::time::Instant;
+use std::vec;
+
+use crate::harness::{Bench, BenchResult};
+
+/// The file written, removed at the end.
+const PATH: &str = "/bench.tmp";
+const FILE_SIZE: usize = 4 << 20;
+const CHUNK_SIZE: usize = 64 << 10;
+
+pub fn run(bench: &Bench) -> BenchResult {
+    let mut buf = vec![0x5a; CHUNK_SIZE];
+    let start = Instant::now();
+    let mut file = File::create(PATH)?;
+    for _ in 0..FILE_SIZE / CHUNK_SIZE {
+        file.write_all(&buf)?;
+    }
+    file.flush()?;
+    drop(file);
+    bench.report_rate("write_throughput", FILE_SIZE as u64, start.elapsed(), "B");
+
+    let start = Instant::now();
+    let mut file = File::open(PATH)?;
+    let mut read = 0;
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    drop(file);
+    bench.report_rate("read_throughput", read as u64, start.elapsed(), "B");
+    fs::remove_file(PATH)?;
+    if read != FILE_SIZE {
+        return Err(format!("read {} bytes of {}", read, FILE_SIZE).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! The common harness of the benchmarks.
+//!
+//! Each result is printed on its own line, as a JSON object after the
+//! [`MARKER`], so it can be picked from the rest of the console output, e.g.:
+//!
+//! ```text
+//! BENCH {"bench":"ctxsw","metric":"switch_latency","value":412,"unit":"ns"}
+//! ```
+//!
+//! A failed benchmark prints a line with an `"error"` instead, and the last
+//! line gives the number of benchmarks run and failed.
+
+use core::fmt;
+use std::string::String;
+use std::time::Duration;
+
+/// The prefix of the lines with results.
+pub const MARKER: &str = "BENCH";
+
+/// A string formatted as a quoted JSON string, with the quotes, the
+/// backslashes and the control characters escaped.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// The error of a failed benchmark, converted from any error, e.g., of I/O.
+pub struct BenchError(String);
+
+impl<E: fmt::Display> From<E> for BenchError {
+    fn from(e: E) -> Self {
+        Self(format!("{}", e))
+    }
+}
+
+pub type BenchResult = Result<(), BenchError>;
+
+/// A benchmark: its name and the function running it.
+pub type BenchFn = (&'static str, fn(&Bench) -> BenchResult);
+
+/// The benchmark being run, which reports its results.
+pub struct Bench {
+    name: &'static str,
+}
+
+impl Bench {
+    /// Reports a result of the benchmark.
+    pub fn report(&self, metric: &str, value: u64, unit: &str) {
+        println!(
+            "{} {{\"bench\":\"{}\",\"metric\":\"{}\",\"value\":{},\"unit\":\"{}\"}}",
+            MARKER, self.name, metric, value, unit
+        );
+    }
+
+    /// Reports the rate of `amount` units processed in `time`, per second.
+    pub fn report_rate(&self, metric: &str, amount: u64, time: Duration, unit: &str) {
+        let rate = amount as u128 * 1_000_000_000 / time.as_nanos().max(1);
+        self.report(metric, rate as u64, &format!("{}/s", unit));
+    }
+
+    /// Reports the minimum, median and maximum of samples, as `<metric>_min`,
+    /// `<metric>_median` and `<metric>_max`.
+    pub fn report_samples(&self, metric: &str, samples: &mut [u64], unit: &str) {
+        if samples.is_empty() {
+            return;
+        }
+        samples.sort_unstable();
+        let stats = [
+            ("min", samples[0]),
+            ("median", samples[samples.len() / 2]),
+            ("max", samples[samples.len() - 1]),
+        ];
+        for (stat, value) in stats {
+            self.report(&format!("{}_{}", metric, stat), value, unit);
+        }
+    }
+}
+
+/// Runs the benchmarks in order, and returns the number of failed ones.
+pub fn run_all(benches: &[BenchFn]) -> usize {
+    let mut failed = 0;
+    for &(name, run) in benches {
+        if let Err(BenchError(error)) = run(&Bench { name }) {
+            println!(
+                "{} {{\"bench\":\"{}\",\"error\":{}}}",
+                MARKER,
+                name,
+                JsonStr(&error)
+            );
+            failed += 1;
+        }
+    }
+    println!(
+        "{} {{\"benches\":{},\"failed\":{}}}",
+        MARKER,
+        benches.len(),
+        failed
+    );
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(JsonStr("timed out").to_string(), r#""timed out""#);
+        assert_eq!(JsonStr("a\"b\\c'd").to_string(), r#""a\"b\\c'd""#);
+        assert_eq!(
+            JsonStr("\x1b[0m\n\té").to_string(),
+            r#""\u001b[0m\u000a\u0009é""#
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Benchmarks of the subsystems, with machine-readable results, to track
+//! performance regressions from release to release.
+//!
+//! The benchmarks are run in order, each printing its results as `BENCH`
+//! lines (see [`harness`]), and the exit code is the number of failed ones,
+//! reported to QEMU with the `qemu-exit` feature:
+//!
+//! - `ctxsw`: context switch latency.
+//! - `sleep`: how late sleeping threads wake up.
+//! - `uart`: console write throughput.
+//! - `fileio`: file write and read throughput (`fs` app feature).
+//! - `tcp`: TCP round trip latency and send throughput (`net` app feature).
+//!
+//! `scripts/bench/run.sh` runs them and collects the results, e.g.:
+//!
+//! ```sh
+//! scripts/bench/run.sh ARCH=riscv64 APP_FEATURES=fs,net BLK=y NET=y
+//! ```
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+mod ctxsw;
+#[cfg(feature = "fs")]
+mod fileio;
+mod harness;
+mod sleep;
+#[cfg(feature = "net")]
+mod tcp;
+mod uart;
+
+use harness::BenchFn;
+
+const BENCHES: &[BenchFn] = &[
+    ("ctxsw", ctxsw::run),
+    ("sleep", sleep::run),
+    ("uart", uart::run),
+    #[cfg(feature = "fs")]
+    ("fileio", fileio::run),
+    #[cfg(feature = "net")]
+    ("tcp", tcp::run),
+];
+
+#[cfg_attr(feature = "axstd", unsafe(no_mangle))]
+fn main() {
+    let failed = harness::run_all(BENCHES);
+    std::process::exit(failed as i32);
+}
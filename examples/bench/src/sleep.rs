@@ -0,0 +1,23 @@
+//! Sleep overshoot: how late a sleeping thread runs again after its
+//! deadline, including the timer resolution and the scheduling delay.
+
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::harness::{Bench, BenchResult};
+
+const SAMPLES: usize = 200;
+const SLEEP: Duration = Duration::from_millis(1);
+
+pub fn run(bench: &Bench) -> BenchResult {
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        thread::sleep(SLEEP);
+        let late = start.elapsed().saturating_sub(SLEEP);
+        samples.push(late.as_nanos() as u64);
+    }
+    bench.report_samples("sleep_overshoot", &mut samples, "ns");
+    Ok(())
+}
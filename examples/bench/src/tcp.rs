@@ -0,0 +1,46 @@
+//! TCP latency and throughput, against `scripts/bench/peer.py` on the host,
+//! reached through the gateway of the QEMU user network.
+//!
+//! The peer echoes what is sent to [`ECHO_PEER`], and on [`SINK_PEER`],
+//! reads the number of bytes given by an 8-byte big-endian header, then
+//! sends a byte back once it has received them all.
+
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::Instant;
+use std::vec;
+use std::vec::Vec;
+
+use crate::harness::{Bench, BenchResult};
+
+const ECHO_PEER: &str = "10.0.2.2:5556";
+const SINK_PEER: &str = "10.0.2.2:5557";
+
+const ROUND_TRIPS: usize = 200;
+const SEND_SIZE: usize = 8 << 20;
+const CHUNK_SIZE: usize = 16 << 10;
+
+pub fn run(bench: &Bench) -> BenchResult {
+    let mut stream = TcpStream::connect(ECHO_PEER)?;
+    let mut samples = Vec::with_capacity(ROUND_TRIPS);
+    let mut byte = [0];
+    for _ in 0..ROUND_TRIPS {
+        let start = Instant::now();
+        stream.write_all(b"x")?;
+        stream.read_exact(&mut byte)?;
+        samples.push(start.elapsed().as_nanos() as u64);
+    }
+    drop(stream);
+    bench.report_samples("round_trip_latency", &mut samples, "ns");
+
+    let mut stream = TcpStream::connect(SINK_PEER)?;
+    let chunk = vec![0x5a; CHUNK_SIZE];
+    let start = Instant::now();
+    stream.write_all(&(SEND_SIZE as u64).to_be_bytes())?;
+    for _ in 0..SEND_SIZE / CHUNK_SIZE {
+        stream.write_all(&chunk)?;
+    }
+    stream.read_exact(&mut byte)?;
+    bench.report_rate("send_throughput", SEND_SIZE as u64, start.elapsed(), "B");
+    Ok(())
+}
@@ -0,0 +1,21 @@
+//! UART throughput: lines written to the console.
+
+use std::io::{self, prelude::*};
+use std::time::Instant;
+
+use crate::harness::{Bench, BenchResult};
+
+const LINES: usize = 512;
+const LINE: &[u8] = b"[bench] the quick brown fox jumps over the lazy dog, 0123456789\n";
+
+pub fn run(bench: &Bench) -> BenchResult {
+    let mut stdout = io::stdout();
+    let start = Instant::now();
+    for _ in 0..LINES {
+        stdout.write_all(LINE)?;
+    }
+    stdout.flush()?;
+    let bytes = (LINES * LINE.len()) as u64;
+    bench.report_rate("write_throughput", bytes, start.elapsed(), "B");
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Console write benchmark.
+//!
+//! Several threads write lines to the console at the same time, like heavy
+//! logging on several CPUs, first with one write per byte, as the console
+//! device was locked for each byte before, and then with one write per line.
+//! The time taken by each is printed at the end.
+//!
+//! ```sh
+//! make A=examples/consolebench SMP=4 run
+//! ```
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+use std::io::{self, prelude::*};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+const NUM_THREADS: usize = 4;
+const LINES_PER_THREAD: usize = 200;
+const LINE: &[u8] = b"[consolebench] the quick brown fox jumps over the lazy dog\n";
+
+/// Writes the lines from all threads, each line with one write, or with one
+/// write per byte if `per_byte` is `true`.
+fn run(per_byte: bool) -> Duration {
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            thread::spawn(move || {
+                let mut stdout = io::stdout();
+                for _ in 0..LINES_PER_THREAD {
+                    if per_byte {
+                        for c in LINE.chunks(1) {
+                            stdout.write_all(c).unwrap();
+                        }
+                    } else {
+                        stdout.write_all(LINE).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in tasks {
+        t.join().unwrap();
+    }
+    start.elapsed()
+}
+
+#[cfg_attr(feature = "axstd", unsafe(no_mangle))]
+fn main() {
+    let per_byte = run(true);
+    let per_line = run(false);
+    let bytes = NUM_THREADS * LINES_PER_THREAD * LINE.len();
+    println!(
+        "consolebench: {} threads, {} bytes each run",
+        NUM_THREADS, bytes
+    );
+    for (name, time) in [("per byte", per_byte), ("per line", per_line)] {
+        let rate = bytes as u128 * 1_000_000 / time.as_micros().max(1);
+        println!("  {:>8}: {:?}, {} bytes/s", name, time, rate);
+    }
+}
@@ -1,34 +1,159 @@
-use std::fs::{self, File, FileType};
-use std::io::{self, prelude::*};
+use core::fmt;
+use std::env;
+use std::fs::{self, File, FileType, OpenOptions};
+use std::io::{self, SeekFrom, prelude::*};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::Duration;
 use std::{string::String, vec::Vec};
 
+#[cfg(feature = "axstd")]
+use std::fs::symlink;
 #[cfg(all(not(feature = "axstd"), unix))]
-use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt, symlink};
 
 use crate::path_to_str;
 
+/// Prints an error, and marks the command being run as failed.
 macro_rules! print_err {
-    ($cmd: literal, $msg: expr) => {
-        println!("{}: {}", $cmd, $msg);
+    ($cmd: expr, $msg: expr) => {{
+        with_cmd_io(|io| io.failed = true);
+        println!("{}: {}", $cmd, $msg)
+    }};
+    ($cmd: expr, $arg: expr, $err: expr) => {{
+        with_cmd_io(|io| io.failed = true);
+        println!("{}: {}: {}", $cmd, $arg, $err)
+    }};
+}
+
+/// Prints the usage of a command given invalid arguments, and marks it as
+/// failed.
+macro_rules! print_usage {
+    ($usage: literal) => {{
+        with_cmd_io(|io| io.failed = true);
+        println!(concat!("usage: ", $usage))
+    }};
+}
+
+/// Prints the output of a command, see [`write_output`].
+macro_rules! out {
+    ($($arg:tt)*) => {
+        print_output(format_args!($($arg)*))
     };
-    ($cmd: literal, $arg: expr, $err: expr) => {
-        println!("{}: {}: {}", $cmd, $arg, $err);
+}
+
+macro_rules! outln {
+    () => {
+        out!("\n")
+    };
+    ($($arg:tt)*) => {
+        out!("{}\n", format_args!($($arg)*))
     };
 }
 
 type CmdHandler = fn(&str);
 
+/// The state of the command being run by a thread: the shell, or a
+/// background job.
+#[derive(Default)]
+struct CmdIo {
+    /// The input of the command from a pipe or a file, if any.
+    input: Option<Vec<u8>>,
+    /// The output of the command, if it goes to a pipe or a file rather than
+    /// to the console.
+    output: Option<Vec<u8>>,
+    /// Whether the command failed, i.e., printed an error.
+    failed: bool,
+    /// Whether the job running the command is killed, for the commands to
+    /// stop early.
+    killed: bool,
+    /// The number of scripts being run, nested.
+    script_depth: usize,
+    /// Whether the command runs in a background job, which does not read the
+    /// console.
+    background: bool,
+}
+
+/// The state of the commands being run, by thread.
+static CMD_IO: Mutex<Vec<(ThreadId, CmdIo)>> = Mutex::new(Vec::new());
+
+/// The maximum nesting of scripts running scripts.
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+/// A command line run by a background job.
+struct Job {
+    id: usize,
+    line: String,
+    thread: JoinHandle<()>,
+    done: Arc<AtomicBool>,
+}
+
+/// The background jobs, which have not been waited for.
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
 const CMD_TABLE: &[(&str, CmdHandler)] = &[
+    ("alias", do_alias),
     ("cat", do_cat),
     ("cd", do_cd),
     ("echo", do_echo),
+    ("env", do_env),
+    #[cfg(feature = "uspace")]
+    ("exec", do_exec),
     ("exit", do_exit),
+    ("export", do_export),
+    ("fg", do_fg),
+    #[cfg(feature = "axstd")]
+    ("find", do_find),
+    #[cfg(feature = "axstd")]
+    ("free", do_free),
+    #[cfg(feature = "axstd")]
+    ("fsck", do_fsck),
+    ("grep", do_grep),
     ("help", do_help),
+    ("hexdump", do_hexdump),
+    #[cfg(feature = "net")]
+    ("ifconfig", do_ifconfig),
+    #[cfg(feature = "axstd")]
+    ("irq", do_irq),
+    ("jobs", do_jobs),
+    ("kill", do_kill),
+    ("ln", do_ln),
+    #[cfg(feature = "axstd")]
+    ("loglevel", do_loglevel),
+    #[cfg(feature = "axstd")]
+    ("logmod", do_logmod),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    #[cfg(feature = "mem-debug")]
+    ("peek", do_peek),
+    #[cfg(feature = "net")]
+    ("ping", do_ping),
+    #[cfg(feature = "mem-debug")]
+    ("poke", do_poke),
+    #[cfg(feature = "axstd")]
+    ("ps", do_ps),
     ("pwd", do_pwd),
+    ("readlink", do_readlink),
     ("rm", do_rm),
+    ("sh", do_sh),
+    ("sleep", do_sleep),
+    ("source", do_source),
+    #[cfg(feature = "axstd")]
+    ("top", do_top),
+    #[cfg(feature = "axstd")]
+    ("trace", do_trace),
+    ("unalias", do_unalias),
     ("uname", do_uname),
+    ("unset", do_unset),
+    #[cfg(feature = "axstd")]
+    ("version", do_version),
+    #[cfg(feature = "wasm")]
+    ("wasm", do_wasm),
+    #[cfg(feature = "watch")]
+    ("watch", do_watch),
+    #[cfg(feature = "net")]
+    ("wget", do_wget),
 ];
 
 fn file_type_to_char(ty: FileType) -> char {
@@ -78,13 +203,18 @@ fn do_ls(args: &str) {
     let name_count = args.split_whitespace().count();
 
     fn show_entry_info(path: &str, entry: &str) -> io::Result<()> {
+        if let Ok(target) = fs::read_link(path) {
+            let target = path_to_str(&target);
+            outln!("lrwxrwxrwx {:>8} {} -> {}", target.len(), entry, target);
+            return Ok(());
+        }
         let metadata = fs::metadata(path)?;
         let size = metadata.len();
         let file_type = metadata.file_type();
         let file_type_char = file_type_to_char(file_type);
         let rwx = file_perm_to_rwx(metadata.permissions().mode());
         let rwx = unsafe { core::str::from_utf8_unchecked(&rwx) };
-        println!("{}{} {:>8} {}", file_type_char, rwx, size, entry);
+        outln!("{}{} {:>8} {}", file_type_char, rwx, size, entry);
         Ok(())
     }
 
@@ -95,7 +225,7 @@ fn do_ls(args: &str) {
         }
 
         if print_name {
-            println!("{}:", name);
+            outln!("{}:", name);
         }
         let mut entries = fs::read_dir(name)?
             .filter_map(|e| e.ok())
@@ -115,7 +245,7 @@ fn do_ls(args: &str) {
 
     for (i, name) in args.split_whitespace().enumerate() {
         if i > 0 {
-            println!();
+            outln!();
         }
         if let Err(e) = list_one(name, name_count > 1) {
             print_err!("ls", name, e);
@@ -125,21 +255,25 @@ fn do_ls(args: &str) {
 
 fn do_cat(args: &str) {
     if args.is_empty() {
-        print_err!("cat", "no file specified");
+        match take_input() {
+            Some(input) => write_output(&input),
+            None => print_err!("cat", "no file specified"),
+        }
         return;
     }
 
     fn cat_one(fname: &str) -> io::Result<()> {
         let mut buf = [0; 1024];
         let mut file = File::open(fname)?;
-        loop {
+        while !killed() {
             let n = file.read(&mut buf)?;
             if n > 0 {
-                io::stdout().write_all(&buf[..n])?;
+                write_output(&buf[..n]);
             } else {
-                return Ok(());
+                break;
             }
         }
+        Ok(())
     }
 
     for fname in args.split_whitespace() {
@@ -149,37 +283,582 @@ fn do_cat(args: &str) {
     }
 }
 
-fn do_echo(args: &str) {
-    fn echo_file(fname: &str, text_list: &[&str]) -> io::Result<()> {
-        let mut file = File::create(fname)?;
-        for text in text_list {
-            file.write_all(text.as_bytes())?;
+fn do_grep(args: &str) {
+    let (pattern, fnames) = split_whitespace(args);
+    if pattern.is_empty() {
+        print_usage!("grep <pattern> [file...]");
+        return;
+    }
+
+    fn grep_one(pattern: &str, prefix: &str, data: &[u8]) {
+        for line in String::from_utf8_lossy(data).lines() {
+            if line.contains(pattern) {
+                outln!("{}{}", prefix, line);
+            }
+        }
+    }
+
+    if fnames.is_empty() {
+        match take_input() {
+            Some(input) => grep_one(pattern, "", &input),
+            None => print_err!("grep", "no file specified"),
+        }
+        return;
+    }
+    let fnames: Vec<&str> = fnames.split_whitespace().collect();
+    for fname in &fnames {
+        if killed() {
+            return;
+        }
+        // The files in a directory are searched recursively.
+        #[cfg(feature = "axstd")]
+        if fs::metadata(fname).is_ok_and(|m| m.is_dir()) {
+            for entry in fs::walk_dir(fname) {
+                match entry {
+                    Ok(entry) if entry.file_type().is_file() => match fs::read(entry.path()) {
+                        Ok(data) => grep_one(pattern, &(String::from(entry.path()) + ":"), &data),
+                        Err(e) => print_err!("grep", entry.path(), e),
+                    },
+                    Ok(_) => {}
+                    Err(e) => print_err!("grep", fname, e),
+                }
+                if killed() {
+                    return;
+                }
+            }
+            continue;
+        }
+        // The file names are shown if there are several files.
+        let prefix = match fnames.len() {
+            1 => String::new(),
+            _ => String::from(*fname) + ":",
+        };
+        match fs::read(fname) {
+            Ok(data) => grep_one(pattern, &prefix, &data),
+            Err(e) => print_err!("grep", fname, e),
+        }
+    }
+}
+
+/// Lists the files in a directory and its subdirectories, or only the ones
+/// whose name matches a pattern, where `*` matches any characters and `?`
+/// any single character.
+#[cfg(feature = "axstd")]
+fn do_find(args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (dir, pattern) = match args.as_slice() {
+        [dir] => (*dir, "*"),
+        [dir, pattern] => (*dir, *pattern),
+        _ => {
+            print_usage!("find <dir> [pattern]");
+            return;
+        }
+    };
+    for entry in fs::walk_dir(dir) {
+        match entry {
+            Ok(entry) if glob_match(pattern, entry.file_name()) => outln!("{}", entry.path()),
+            Ok(_) => {}
+            Err(e) => print_err!("find", dir, e),
+        }
+        if killed() {
+            return;
+        }
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any characters and
+/// `?` any single character.
+#[cfg(feature = "axstd")]
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*`, and the position in the name it
+    // matches up to, to backtrack to.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Dumps a file, or a part of it from `offset`, in hexadecimal and ASCII.
+fn do_hexdump(args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (fname, offset, len) = match args.as_slice() {
+        [] => {
+            match take_input() {
+                Some(input) => print_hexdump(0, &input),
+                None => print_err!("hexdump", "no file specified"),
+            }
+            return;
+        }
+        [fname] => (fname, "0", None),
+        [fname, offset] => (fname, *offset, None),
+        [fname, offset, len] => (fname, *offset, Some(*len)),
+        _ => {
+            print_usage!("hexdump <file> [offset] [len]");
+            return;
+        }
+    };
+    let Some(offset) = parse_num(offset) else {
+        print_err!("hexdump", offset, "invalid offset");
+        return;
+    };
+    let len = match len {
+        None => u64::MAX,
+        Some(len) => match parse_num(len) {
+            Some(len) => len,
+            None => {
+                print_err!("hexdump", len, "invalid length");
+                return;
+            }
+        },
+    };
+
+    fn hexdump_one(fname: &str, offset: u64, mut len: u64) -> io::Result<()> {
+        let mut file = File::open(fname)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = [0; 4096];
+        let mut addr = offset as usize;
+        while len > 0 && !killed() {
+            // The buffer is filled, so that the lines are only cut at the end.
+            let want = buf.len().min(len.try_into().unwrap_or(usize::MAX));
+            let mut n = 0;
+            while n < want {
+                match file.read(&mut buf[n..want])? {
+                    0 => break,
+                    m => n += m,
+                }
+            }
+            print_hexdump(addr, &buf[..n]);
+            if n < want {
+                break;
+            }
+            addr += n;
+            len -= n as u64;
         }
         Ok(())
     }
 
-    if let Some(pos) = args.rfind('>') {
-        let text_before = args[..pos].trim();
-        let (fname, text_after) = split_whitespace(&args[pos + 1..]);
-        if fname.is_empty() {
-            print_err!("echo", "no file specified");
+    if let Err(e) = hexdump_one(fname, offset, len) {
+        print_err!("hexdump", fname, e);
+    }
+}
+
+/// Prints bytes like `hexdump -C`, 16 per line, the first one at `addr`.
+fn print_hexdump(addr: usize, data: &[u8]) {
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let mut line = format!("{:08x} ", addr + i * 16);
+        for j in 0..16 {
+            if j % 8 == 0 {
+                line.push(' ');
+            }
+            match chunk.get(j) {
+                Some(byte) => line += &format!("{:02x} ", byte),
+                None => line += "   ",
+            }
+        }
+        line.push_str(" |");
+        line.extend(chunk.iter().map(|&byte| match byte {
+            b' '..=b'~' => byte as char,
+            _ => '.',
+        }));
+        line.push('|');
+        outln!("{}", line);
+    }
+}
+
+/// Parses a number, in hexadecimal if it starts with `0x`.
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses the options of `peek` and `poke`: `-p` if the address is physical,
+/// and the width of the accesses in bytes (4 by default) with `-w`. Returns
+/// them with the other arguments, or `None` if they are invalid.
+#[cfg(feature = "mem-debug")]
+fn parse_mem_options(args: &str) -> Option<(bool, usize, Vec<&str>)> {
+    let (mut physical, mut width) = (false, 4);
+    let mut args = args.split_whitespace().peekable();
+    while let Some(&opt) = args.peek() {
+        match opt {
+            "-p" => physical = true,
+            "-w" => {
+                args.next();
+                width = args.peek()?.parse().ok()?;
+                if !matches!(width, 1 | 2 | 4 | 8) {
+                    return None;
+                }
+            }
+            _ => break,
+        }
+        args.next();
+    }
+    Some((physical, width, args.collect()))
+}
+
+/// Parses the address of `peek` and `poke`, which must be aligned to the
+/// width of the accesses. Returns it with its virtual address.
+#[cfg(feature = "mem-debug")]
+fn parse_mem_addr(cmd: &str, addr: &str, physical: bool, width: usize) -> Option<(usize, usize)> {
+    use std::os::arceos::modules::axhal::mem::{PhysAddr, phys_to_virt};
+
+    let Some(addr) = parse_num(addr).map(|addr| addr as usize) else {
+        print_err!(cmd, addr, "invalid address");
+        return None;
+    };
+    if addr % width != 0 {
+        print_err!(cmd, format!("{:#x}", addr), "unaligned address");
+        return None;
+    }
+    match physical {
+        true => Some((addr, phys_to_virt(PhysAddr::from(addr)).as_usize())),
+        false => Some((addr, addr)),
+    }
+}
+
+/// Reads memory, e.g., the registers of a device: `peek [-p] [-w <width>]
+/// <addr> [len]`, 64 bytes by default, with volatile reads of `width` bytes.
+///
+/// The address is not checked, so reading unmapped memory crashes the kernel.
+#[cfg(feature = "mem-debug")]
+fn do_peek(args: &str) {
+    let options = parse_mem_options(args);
+    let (physical, width, addr, len) = match options
+        .as_ref()
+        .map(|(physical, width, args)| (*physical, *width, args.as_slice()))
+    {
+        Some((physical, width, [addr])) => (physical, width, *addr, "64"),
+        Some((physical, width, [addr, len])) => (physical, width, *addr, *len),
+        _ => {
+            print_usage!("peek [-p] [-w 1|2|4|8] <addr> [len]");
             return;
+        }
+    };
+    let Some(len) = parse_num(len).filter(|&len| len > 0) else {
+        print_err!("peek", len, "invalid length");
+        return;
+    };
+    let Some((addr, vaddr)) = parse_mem_addr("peek", addr, physical, width) else {
+        return;
+    };
+
+    let len = len as usize;
+    let mut data = Vec::with_capacity(len.next_multiple_of(width));
+    for offset in (0..len).step_by(width) {
+        // SAFETY: the user asked for it, see above.
+        let value = unsafe {
+            let ptr = (vaddr + offset) as *const u8;
+            match width {
+                1 => ptr.read_volatile() as u64,
+                2 => (ptr as *const u16).read_volatile() as u64,
+                4 => (ptr as *const u32).read_volatile() as u64,
+                _ => (ptr as *const u64).read_volatile(),
+            }
         };
+        data.extend_from_slice(&value.to_le_bytes()[..width]);
+    }
+    data.truncate(len);
+    print_hexdump(addr, &data);
+}
 
-        let text_list = [
-            text_before,
-            if !text_after.is_empty() { " " } else { "" },
-            text_after,
-            "\n",
-        ];
-        if let Err(e) = echo_file(fname, &text_list) {
-            print_err!("echo", fname, e);
+/// Writes a value to memory, e.g., to the register of a device: `poke [-p]
+/// [-w <width>] <addr> <value>`, with a volatile write of `width` bytes.
+///
+/// The address is not checked, so writing to unmapped memory crashes the
+/// kernel, and writing to the memory of the kernel may corrupt it.
+#[cfg(feature = "mem-debug")]
+fn do_poke(args: &str) {
+    let options = parse_mem_options(args);
+    let (physical, width, addr, value) = match options
+        .as_ref()
+        .map(|(physical, width, args)| (*physical, *width, args.as_slice()))
+    {
+        Some((physical, width, [addr, value])) => (physical, width, *addr, *value),
+        _ => {
+            print_usage!("poke [-p] [-w 1|2|4|8] <addr> <value>");
+            return;
         }
-    } else {
-        println!("{}", args)
+    };
+    let bits = width as u32 * 8;
+    let Some(value) = parse_num(value).filter(|&v| bits == 64 || v >> bits == 0) else {
+        print_err!("poke", value, "invalid value");
+        return;
+    };
+    let Some((_, vaddr)) = parse_mem_addr("poke", addr, physical, width) else {
+        return;
+    };
+
+    // SAFETY: the user asked for it, see above.
+    unsafe {
+        let ptr = vaddr as *mut u8;
+        match width {
+            1 => ptr.write_volatile(value as u8),
+            2 => (ptr as *mut u16).write_volatile(value as u16),
+            4 => (ptr as *mut u32).write_volatile(value as u32),
+            _ => (ptr as *mut u64).write_volatile(value),
+        }
+    }
+}
+
+/// Watches the writes to memory with a hardware watchpoint: `watch <addr>
+/// <len>`, printing the task, the PC and a backtrace on each write, e.g., to
+/// find what corrupts it. `watch` lists the watchpoints, and `watch -d <n>`
+/// removes one.
+#[cfg(feature = "watch")]
+fn do_watch(args: &str) {
+    use std::os::arceos::api::sys::{ax_unwatch, ax_watch, ax_watch_list};
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    match args.as_slice() {
+        [] => {
+            for (index, watchpoint) in ax_watch_list() {
+                outln!(
+                    "{}: {:#x} ({} bytes)",
+                    index,
+                    watchpoint.addr,
+                    watchpoint.len
+                );
+            }
+        }
+        ["-d", index] => match index.parse() {
+            Ok(n) => {
+                if let Err(e) = ax_unwatch(n) {
+                    print_err!("watch", index, e);
+                }
+            }
+            Err(_) => print_err!("watch", index, "invalid index"),
+        },
+        [addr, len] => {
+            let Some(addr_num) = parse_num(addr) else {
+                print_err!("watch", addr, "invalid address");
+                return;
+            };
+            let Some(len_num) = parse_num(len) else {
+                print_err!("watch", len, "invalid length");
+                return;
+            };
+            match ax_watch(addr_num as usize, len_num as usize) {
+                Ok(index) => outln!("watchpoint {}", index),
+                Err(e) => print_err!("watch", addr, e),
+            }
+        }
+        _ => print_usage!("watch [-d <n> | <addr> <1|2|4|8>]"),
+    }
+}
+
+fn do_echo(args: &str) {
+    outln!("{}", args)
+}
+
+fn do_env(_args: &str) {
+    for (name, value) in env::vars() {
+        outln!("{}={}", name, value);
     }
 }
 
+/// Sets environment variables, given as `NAME=value`, where the value may be
+/// quoted, or lists them if none is given.
+fn do_export(args: &str) {
+    if args.is_empty() {
+        do_env(args);
+        return;
+    }
+    for assignment in split_quoted(args) {
+        match assignment.split_once('=') {
+            Some((name, value)) if is_var_name(name) => unsafe { env::set_var(name, value) },
+            _ => print_err!("export", &assignment, "not a valid assignment"),
+        }
+    }
+}
+
+fn do_unset(args: &str) {
+    if args.is_empty() {
+        print_err!("unset", "missing operand");
+        return;
+    }
+    for name in args.split_whitespace() {
+        if is_var_name(name) {
+            unsafe { env::remove_var(name) };
+        } else {
+            print_err!("unset", name, "not a valid name");
+        }
+    }
+}
+
+/// The prefix of the environment variables holding the aliases, e.g.,
+/// `ALIAS_ll` for `ll`.
+const ALIAS_PREFIX: &str = "ALIAS_";
+
+/// Defines aliases, given as `name=value`, where the value may be quoted,
+/// e.g., `alias ll='ls -l'`. Shows the aliases given by their names, or all
+/// of them if none is given.
+fn do_alias(args: &str) {
+    if args.is_empty() {
+        for (name, value) in env::vars() {
+            if let Some(name) = name.strip_prefix(ALIAS_PREFIX) {
+                outln!("alias {}='{}'", name, value);
+            }
+        }
+        return;
+    }
+    for arg in split_quoted(args) {
+        match arg.split_once('=') {
+            Some((name, value)) if is_var_name(name) => unsafe {
+                env::set_var(format!("{}{}", ALIAS_PREFIX, name), value)
+            },
+            Some((name, _)) => print_err!("alias", name, "not a valid name"),
+            None => match env::var(format!("{}{}", ALIAS_PREFIX, arg)) {
+                Ok(value) => outln!("alias {}='{}'", arg, value),
+                Err(_) => print_err!("alias", arg, "not found"),
+            },
+        }
+    }
+}
+
+fn do_unalias(args: &str) {
+    if args.is_empty() {
+        print_err!("unalias", "missing operand");
+        return;
+    }
+    for name in args.split_whitespace() {
+        let var = format!("{}{}", ALIAS_PREFIX, name);
+        if is_var_name(name) && env::var(&var).is_ok() {
+            unsafe { env::remove_var(var) };
+        } else {
+            print_err!("unalias", name, "not found");
+        }
+    }
+}
+
+/// Replaces the first word of each command of `line` by its alias, if it
+/// has one. The aliases are not expanded again in the values of aliases.
+fn expand_aliases(line: &str) -> String {
+    let commands: Vec<String> = split_unquoted(line, '|')
+        .into_iter()
+        .map(|cmd| {
+            let (name, args) = split_whitespace(cmd);
+            match env::var(format!("{}{}", ALIAS_PREFIX, name)) {
+                Ok(value) if is_var_name(name) && args.is_empty() => value,
+                Ok(value) if is_var_name(name) => value + " " + args,
+                _ => String::from(cmd),
+            }
+        })
+        .collect();
+    commands.join("|")
+}
+
+/// Returns the prompt, from the template in `PS1` (`\h:\w$ ` by default),
+/// where `\w` is replaced by the current directory, `\W` by its last
+/// component, `\h` by the host name (`HOSTNAME`, `arceos` by default), and
+/// `\?` by the exit status of the last command, 1 if it failed, or 0.
+pub fn prompt() -> String {
+    let template = env::var("PS1").unwrap_or_else(|_| String::from("\\h:\\w$ "));
+    let mut prompt = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('w') => prompt += &current_dir(),
+            Some('W') => {
+                let dir = current_dir();
+                prompt += match dir.trim_end_matches('/').rsplit('/').next() {
+                    Some("") | None => "/",
+                    Some(name) => name,
+                };
+            }
+            Some('h') => prompt += &env::var("HOSTNAME").unwrap_or_else(|_| "arceos".into()),
+            Some('?') => prompt.push(char::from(b'0' + with_cmd_io(|io| io.failed) as u8)),
+            Some(c) => prompt.push(c),
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+fn current_dir() -> String {
+    env::current_dir().map_or_else(|_| String::new(), |dir| path_to_str(&dir).into())
+}
+
+/// Splits arguments at whitespace, except in quotes, which are removed, e.g.,
+/// `PS1='$ ' A=b` gives `PS1=$ ` and `A=b`.
+fn split_quoted(args: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in args.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_default().push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Returns the position of the first character matching `pat` out of quotes.
+fn find_unquoted(s: &str, pat: impl Fn(char) -> bool) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if pat(c) => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Splits at the `sep` characters out of quotes, e.g., the commands of a
+/// pipeline, so `echo '|'` is a single command.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = s;
+    while let Some(pos) = find_unquoted(rest, |c| c == sep) {
+        parts.push(&rest[..pos]);
+        rest = &rest[pos + sep.len_utf8()..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn is_var_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 fn do_mkdir(args: &str) {
     if args.is_empty() {
         print_err!("mkdir", "missing operand");
@@ -227,6 +906,39 @@ fn do_rm(args: &str) {
     }
 }
 
+fn do_ln(args: &str) {
+    let (symbolic, args) = match args.strip_prefix("-s ") {
+        Some(args) => (true, args),
+        None => (false, args),
+    };
+    let mut paths = args.split_whitespace();
+    let (Some(original), Some(link), None) = (paths.next(), paths.next(), paths.next()) else {
+        print_usage!("ln [-s] <target> <link>");
+        return;
+    };
+    let res = if symbolic {
+        symlink(original, link)
+    } else {
+        fs::hard_link(original, link)
+    };
+    if let Err(e) = res {
+        print_err!("ln", format_args!("cannot create link '{link}'"), e);
+    }
+}
+
+fn do_readlink(args: &str) {
+    if args.is_empty() {
+        print_usage!("readlink <link>...");
+        return;
+    }
+    for path in args.split_whitespace() {
+        match fs::read_link(path) {
+            Ok(target) => outln!("{}", path_to_str(&target)),
+            Err(e) => print_err!("readlink", path, e),
+        }
+    }
+}
+
 fn do_cd(mut args: &str) {
     if args.is_empty() {
         args = "/";
@@ -242,7 +954,7 @@ fn do_cd(mut args: &str) {
 
 fn do_pwd(_args: &str) {
     let pwd = std::env::current_dir().unwrap();
-    println!("{}", path_to_str(&pwd));
+    outln!("{}", path_to_str(&pwd));
 }
 
 fn do_uname(_args: &str) {
@@ -253,7 +965,7 @@ fn do_uname(_args: &str) {
         _ => " SMP",
     };
     let version = option_env!("CARGO_PKG_VERSION").unwrap_or("0.1.0");
-    println!(
+    outln!(
         "ArceOS {ver}{smp} {arch} {plat}",
         ver = version,
         smp = smp,
@@ -262,34 +974,1257 @@ fn do_uname(_args: &str) {
     );
 }
 
+#[cfg(feature = "axstd")]
+fn do_version(_args: &str) {
+    use std::os::arceos::api::sys::ax_build_info;
+
+    let info = ax_build_info();
+    let or_unknown = |s: &'static str| if s.is_empty() { "unknown" } else { s };
+    outln!("ArceOS {}", info.version);
+    outln!("commit:   {}", or_unknown(info.git_commit));
+    outln!("app:      {}", or_unknown(info.app));
+    outln!("platform: {} ({})", info.platform, or_unknown(info.target));
+    outln!("mode:     {}", or_unknown(info.build_mode));
+    outln!("built:    {}", or_unknown(info.build_time));
+    outln!("rustc:    {}", or_unknown(info.rustc));
+    outln!("features: {}", info.features.join(" "));
+}
+
 fn do_help(_args: &str) {
-    println!("Available commands:");
+    outln!("Available commands:");
     for (name, _) in CMD_TABLE {
-        println!("  {}", name);
+        outln!("  {}", name);
     }
 }
 
-fn do_exit(_args: &str) {
-    println!("Bye~");
-    std::process::exit(0);
+#[cfg(feature = "net")]
+fn do_ifconfig(args: &str) {
+    use std::os::arceos::api::net::{ax_net_iface_info, ax_net_mtu, ax_net_set_mtu};
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    match args.as_slice() {
+        [] | ["eth0"] => {
+            let info = ax_net_iface_info();
+            let (mtu, max_mtu) = ax_net_mtu();
+            outln!("{}: mtu {} (max {})", info.name, mtu, max_mtu);
+            let mac = info.mac.map(|b| format!("{b:02x}"));
+            outln!("        ether {}", mac.join(":"));
+            match (info.ip, info.gateway) {
+                (Some((ip, prefix)), Some(gateway)) => {
+                    outln!("        inet {}/{}  gateway {}", ip, prefix, gateway)
+                }
+                (Some((ip, prefix)), None) => outln!("        inet {}/{}", ip, prefix),
+                _ => {}
+            }
+            for (ip, prefix) in &info.ipv6 {
+                outln!("        inet6 {}/{}", ip, prefix);
+            }
+            if let Some(gateway) = info.gateway6 {
+                outln!("        gateway6 {}", gateway);
+            }
+            outln!(
+                "        RX packets {}  bytes {} ({})",
+                info.rx_packets,
+                info.rx_bytes,
+                format_size(info.rx_bytes as usize)
+            );
+            outln!(
+                "        TX packets {}  bytes {} ({})",
+                info.tx_packets,
+                info.tx_bytes,
+                format_size(info.tx_bytes as usize)
+            );
+        }
+        ["eth0", "mtu", mtu] => match mtu.parse() {
+            Ok(mtu) => {
+                if let Err(e) = ax_net_set_mtu(mtu) {
+                    print_err!("ifconfig", mtu, e);
+                }
+            }
+            Err(_) => print_err!("ifconfig", mtu, "invalid MTU"),
+        },
+        [name, ..] if *name != "eth0" => print_err!("ifconfig", name, "no such interface"),
+        _ => print_usage!("ifconfig [eth0 [mtu <bytes>]]"),
+    }
 }
 
-pub fn run_cmd(line: &[u8]) {
-    let line_str = unsafe { core::str::from_utf8_unchecked(line) };
-    let (cmd, args) = split_whitespace(line_str);
-    if !cmd.is_empty() {
-        for (name, func) in CMD_TABLE {
-            if cmd == *name {
-                func(args);
+/// Resolves a host name, or parses an IP address.
+#[cfg(feature = "net")]
+fn resolve_host(host: &str) -> Option<std::net::IpAddr> {
+    use std::os::arceos::api::net::ax_dns_query;
+
+    if let Ok(addr) = host.parse() {
+        return Some(addr);
+    }
+    match ax_dns_query(host) {
+        Ok(addrs) if !addrs.is_empty() => Some(addrs[0]),
+        Ok(_) => {
+            print_err!("shell", host, "no address found");
+            None
+        }
+        Err(e) => {
+            print_err!("shell", host, e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+fn do_ping(args: &str) {
+    use std::os::arceos::api::net::ax_ping;
+
+    const DATA_LEN: usize = 56;
+    const INTERVAL: Duration = Duration::from_secs(1);
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (count, host) = match args.as_slice() {
+        [host] => (4, host),
+        ["-c", count, host] => match count.parse::<u16>() {
+            Ok(count) if count > 0 => (count, host),
+            _ => {
+                print_err!("ping", count, "invalid count");
                 return;
             }
+        },
+        _ => {
+            print_usage!("ping [-c <count>] <host>");
+            return;
+        }
+    };
+    let Some(addr) = resolve_host(host) else {
+        return;
+    };
+
+    outln!("PING {} ({}) {} bytes of data.", host, addr, DATA_LEN);
+    let (mut transmitted, mut received) = (0, 0);
+    for seq in 1..=count {
+        if killed() {
+            break;
+        }
+        transmitted += 1;
+        let rtt = match ax_ping(addr, seq, TIMEOUT) {
+            Ok(rtt) => {
+                received += 1;
+                outln!(
+                    "{} bytes from {}: icmp_seq={} time={:.3} ms",
+                    DATA_LEN + 8,
+                    addr,
+                    seq,
+                    rtt.as_secs_f64() * 1000.0
+                );
+                rtt
+            }
+            Err(e) => {
+                outln!("from {}: icmp_seq={} {}", addr, seq, e);
+                TIMEOUT
+            }
+        };
+        if seq < count {
+            thread::sleep(INTERVAL.saturating_sub(rtt));
         }
-        println!("{}: command not found", cmd);
     }
+    outln!("--- {} ping statistics ---", host);
+    outln!(
+        "{} packets transmitted, {} received, {}% packet loss",
+        transmitted,
+        received,
+        (transmitted - received) * 100 / transmitted.max(1)
+    );
 }
 
-fn split_whitespace(str: &str) -> (&str, &str) {
-    let str = str.trim();
-    str.find(char::is_whitespace)
-        .map_or((str, ""), |n| (&str[..n], str[n + 1..].trim()))
+#[cfg(feature = "net")]
+fn do_wget(args: &str) {
+    use std::net::TcpStream;
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (url, file) = match args.as_slice() {
+        [url] => (*url, None),
+        [url, file] => (*url, Some(*file)),
+        _ => {
+            print_usage!("wget <url> [file]");
+            return;
+        }
+    };
+    let Some(rest) = url.strip_prefix("http://") else {
+        print_err!("wget", url, "only http:// URLs are supported");
+        return;
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(port) => (host, port),
+            Err(_) => {
+                print_err!("wget", port, "invalid port");
+                return;
+            }
+        },
+        None => (authority, 80),
+    };
+    let file = file.unwrap_or_else(|| match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name,
+        _ => "index.html",
+    });
+    let Some(addr) = resolve_host(host) else {
+        return;
+    };
+
+    let get = |out: &mut File| -> io::Result<Result<usize, String>> {
+        let mut stream = TcpStream::connect((addr, port))?;
+        write!(
+            stream,
+            "GET {path} HTTP/1.0\r\nHost: {authority}\r\nConnection: close\r\n\r\n"
+        )?;
+        // Reads until the end of the header, then streams the body.
+        let mut buf = [0; 4096];
+        let mut header = Vec::new();
+        let body_start = loop {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(Err("connection closed before the response".into()));
+            }
+            header.extend_from_slice(&buf[..n]);
+            if let Some(i) = header.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+        };
+        let status_end = header.iter().position(|&b| b == b'\r').unwrap();
+        let status = String::from_utf8_lossy(&header[..status_end]).into_owned();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Ok(Err(status));
+        }
+        out.write_all(&header[body_start..])?;
+        let mut size = header.len() - body_start;
+        loop {
+            if killed() {
+                return Ok(Err("interrupted".into()));
+            }
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(Ok(size));
+            }
+            out.write_all(&buf[..n])?;
+            size += n;
+        }
+    };
+
+    outln!("Connecting to {} ({}:{})...", host, addr, port);
+    let mut out = match File::create(file) {
+        Ok(out) => out,
+        Err(e) => {
+            print_err!("wget", file, e);
+            return;
+        }
+    };
+    match get(&mut out) {
+        Ok(Ok(size)) => outln!("saved {} bytes to '{}'", size, file),
+        Ok(Err(msg)) => print_err!("wget", url, msg),
+        Err(e) => print_err!("wget", url, e),
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_loglevel(args: &str) {
+    use std::os::arceos::api::sys::{ax_log_level, ax_set_log_level};
+
+    match args {
+        "" => outln!("{}", ax_log_level()),
+        level if ax_set_log_level(level) => {}
+        level => print_err!("loglevel", level, "invalid level"),
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_logmod(args: &str) {
+    use std::os::arceos::api::sys::ax_set_log_target_level;
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (target, level) = match args.as_slice() {
+        [target, "default"] => (target, None),
+        [target, level] => (target, Some(*level)),
+        _ => {
+            print_usage!("logmod <target> <off|error|warn|info|debug|trace|default>");
+            return;
+        }
+    };
+    if !ax_set_log_target_level(target, level) {
+        print_err!("logmod", target, "invalid level or too many targets");
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_trace(args: &str) {
+    use std::os::arceos::api::sys::ax_set_log_target_level;
+
+    // The events of a module are its trace records.
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (target, level) = match args.as_slice() {
+        ["on", target] => (target, Some("trace")),
+        ["off", target] => (target, None),
+        _ => {
+            print_usage!("trace on|off <target>");
+            return;
+        }
+    };
+    if !ax_set_log_target_level(target, level) {
+        print_err!("trace", target, "too many targets");
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_irq(args: &str) {
+    use std::os::arceos::api::sys::ax_irq_set_enable;
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (enabled, irq) = match args.as_slice() {
+        ["on", irq] => (true, irq),
+        ["off", irq] => (false, irq),
+        _ => {
+            print_usage!("irq on|off <n>");
+            return;
+        }
+    };
+    match irq.parse() {
+        Ok(irq_num) => ax_irq_set_enable(irq_num, enabled),
+        Err(_) => print_err!("irq", irq, "invalid IRQ number"),
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_fsck(args: &str) {
+    use std::os::arceos::api::fs::ax_fsck;
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (repair, dev) = match args.as_slice() {
+        ["-r", dev] => (true, dev),
+        [dev] => (false, dev),
+        _ => {
+            print_usage!("fsck [-r] <device>");
+            return;
+        }
+    };
+    let report = match ax_fsck(dev, repair) {
+        Ok(report) => report,
+        Err(e) => {
+            print_err!("fsck", dev, e);
+            return;
+        }
+    };
+    for problem in &report.problems {
+        outln!("{}", problem);
+    }
+    outln!(
+        "{}: {:?}, {} files, {} directories, {}/{} clusters free",
+        dev,
+        report.fat_type,
+        report.files,
+        report.dirs,
+        report.free_clusters,
+        report.num_clusters
+    );
+    if report.repaired {
+        outln!("{}: {} problems repaired", dev, report.problems.len());
+    } else if !report.problems.is_empty() {
+        outln!(
+            "{}: {} problems, run `fsck -r` to repair",
+            dev,
+            report.problems.len()
+        );
+    }
+}
+
+/// Formats a CPU time as seconds, with milliseconds.
+#[cfg(feature = "axstd")]
+fn format_cpu_time(time: Duration) -> String {
+    format!("{}.{:03}", time.as_secs(), time.subsec_millis())
+}
+
+/// Waits for a key pressed on the console, until the timeout. A background
+/// job does not read the console, so it only waits.
+#[cfg(feature = "axstd")]
+fn wait_key(timeout: Duration) -> Option<u8> {
+    use std::os::arceos::api::stdio::ax_console_read_bytes_timeout;
+
+    if with_cmd_io(|io| io.background) {
+        thread::sleep(timeout);
+        return None;
+    }
+    let mut key = [0];
+    match ax_console_read_bytes_timeout(&mut key, timeout) {
+        Ok(1) => Some(key[0]),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "axstd")]
+fn do_ps(_args: &str) {
+    use std::os::arceos::api::task::ax_task_list;
+
+    outln!(
+        "{:>5} {:<8} {:>5} {:>10} NAME",
+        "ID",
+        "STATE",
+        "PRIO",
+        "TIME"
+    );
+    for task in ax_task_list() {
+        // The derived `Debug` ignores the width, so the state is padded once
+        // formatted.
+        let state = format!("{:?}", task.state);
+        outln!(
+            "{:>5} {:<8} {:>5} {:>10} {}",
+            task.id,
+            state,
+            task.priority,
+            format_cpu_time(task.cpu_time),
+            task.name
+        );
+    }
+}
+
+/// Shows the tasks using the most CPU time, refreshed every second until `q`
+/// or Ctrl-C is pressed, or `count` refreshes are shown.
+#[cfg(feature = "axstd")]
+fn do_top(args: &str) {
+    use std::os::arceos::api::task::ax_task_list;
+    use std::time::Instant;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+    const CTRL_C: u8 = 0x03;
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let count = match args.as_slice() {
+        [] => None,
+        ["-n", count] => match count.parse::<usize>() {
+            Ok(count) if count > 0 => Some(count),
+            _ => {
+                print_err!("top", count, "invalid count");
+                return;
+            }
+        },
+        _ => {
+            print_usage!("top [-n <count>]");
+            return;
+        }
+    };
+    // The screen is only cleared on the console, not in a pipe or a file.
+    let clear = with_cmd_io(|io| io.output.is_none());
+
+    let mut last: Vec<(u64, Duration)> = ax_task_list()
+        .iter()
+        .map(|task| (task.id, task.cpu_time))
+        .collect();
+    let mut last_time = Instant::now();
+    let mut shown = 0;
+    loop {
+        if matches!(wait_key(INTERVAL), Some(b'q' | CTRL_C)) || killed() {
+            break;
+        }
+        let tasks = ax_task_list();
+        let now = Instant::now();
+        let elapsed = (now - last_time).as_secs_f64();
+
+        // The CPU time used by each task since the last refresh.
+        let mut rows: Vec<_> = tasks
+            .iter()
+            .map(|task| {
+                let prev = last
+                    .iter()
+                    .find(|(id, _)| *id == task.id)
+                    .map_or(Duration::ZERO, |(_, time)| *time);
+                let used = task.cpu_time.saturating_sub(prev).as_secs_f64();
+                (task, used * 100.0 / elapsed)
+            })
+            .collect();
+        rows.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        if clear {
+            out!("\x1b[H\x1b[2J");
+        }
+        outln!(
+            "{} tasks, press q to quit\n{:>5} {:<8} {:>5} {:>5} {:>10} NAME",
+            tasks.len(),
+            "ID",
+            "STATE",
+            "PRIO",
+            "%CPU",
+            "TIME"
+        );
+        for (task, usage) in &rows {
+            let state = format!("{:?}", task.state);
+            outln!(
+                "{:>5} {:<8} {:>5} {:>5.1} {:>10} {}",
+                task.id,
+                state,
+                task.priority,
+                usage,
+                format_cpu_time(task.cpu_time),
+                task.name
+            );
+        }
+
+        last = tasks.iter().map(|task| (task.id, task.cpu_time)).collect();
+        last_time = now;
+        shown += 1;
+        if count.is_some_and(|count| shown >= count) {
+            break;
+        }
+    }
+}
+
+/// Formats a size in bytes with a binary unit, e.g., `1.5M`.
+#[cfg(feature = "axstd")]
+fn format_size(bytes: usize) -> String {
+    const UNITS: [char; 4] = ['K', 'M', 'G', 'T'];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// Shows the usage of the heap and of the physical pages.
+#[cfg(feature = "axstd")]
+fn do_free(_args: &str) {
+    use std::os::arceos::api::mem::ax_mem_stats;
+
+    const PAGE_SIZE: usize = 0x1000;
+
+    let stats = ax_mem_stats();
+    outln!(
+        "{:<6} {:>9} {:>9} {:>9} {:>9} {:>9} {:>6}",
+        "",
+        "total",
+        "used",
+        "free",
+        "peak",
+        "largest",
+        "frag"
+    );
+    let rows = [
+        (
+            "heap",
+            stats.heap_total,
+            stats.heap_used,
+            stats.heap_peak,
+            stats.heap_largest_free,
+            stats.heap_fragmentation(),
+        ),
+        (
+            "pages",
+            stats.total_pages * PAGE_SIZE,
+            stats.used_pages * PAGE_SIZE,
+            stats.peak_pages * PAGE_SIZE,
+            stats.largest_free_pages * PAGE_SIZE,
+            stats.page_fragmentation(),
+        ),
+    ];
+    for (name, total, used, peak, largest, frag) in rows {
+        outln!(
+            "{:<6} {:>9} {:>9} {:>9} {:>9} {:>9} {:>5.1}%",
+            name,
+            format_size(total),
+            format_size(used),
+            format_size(total - used),
+            format_size(peak),
+            format_size(largest),
+            frag
+        );
+    }
+}
+
+/// Lists the background jobs, and forgets the ones which are done.
+fn do_jobs(_args: &str) {
+    let mut jobs = lock(&JOBS);
+    for job in jobs.iter() {
+        let state = match job.done.load(Ordering::Acquire) {
+            true => "Done",
+            false => "Running",
+        };
+        outln!("[{}] {:<8} {}", job.id, state, job.line);
+    }
+    jobs.retain(|job| !job.done.load(Ordering::Acquire));
+}
+
+/// Waits for a background job, the last one by default.
+fn do_fg(args: &str) {
+    let mut jobs = lock(&JOBS);
+    let Some(index) = find_job(&jobs, args) else {
+        drop(jobs);
+        match args {
+            "" => print_err!("fg", "no current job"),
+            _ => print_err!("fg", args, "no such job"),
+        }
+        return;
+    };
+    let job = jobs.remove(index);
+    drop(jobs);
+    println!("{}", job.line);
+    // The job is killed if the shell is, e.g., with `Ctrl-C`.
+    while !job.done.load(Ordering::Acquire) {
+        if killed() {
+            with_thread_cmd_io(job.thread.thread().id(), |io| io.killed = true);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let _ = job.thread.join();
+}
+
+/// Kills a background job. It stops at the next point where its command
+/// checks it, e.g., in `sleep` or between the lines of a script, as commands
+/// can not be interrupted. A job in the foreground is killed with `Ctrl-C`,
+/// see [`do_fg`].
+fn do_kill(args: &str) {
+    if args.is_empty() {
+        print_usage!("kill <job>");
+        return;
+    }
+    let jobs = lock(&JOBS);
+    match find_job(&jobs, args) {
+        Some(index) if !jobs[index].done.load(Ordering::Acquire) => {
+            let id = jobs[index].thread.thread().id();
+            with_thread_cmd_io(id, |io| io.killed = true);
+        }
+        Some(_) => {}
+        None => {
+            drop(jobs);
+            print_err!("kill", args, "no such job");
+        }
+    }
+}
+
+/// Sleeps for the given number of seconds, or until the job is killed.
+fn do_sleep(args: &str) {
+    const STEP: Duration = Duration::from_millis(100);
+
+    let secs = args.parse().ok();
+    let Some(mut left) = secs.and_then(|secs| Duration::try_from_secs_f64(secs).ok()) else {
+        print_usage!("sleep <seconds>");
+        return;
+    };
+    while !left.is_zero() && !killed() {
+        let step = left.min(STEP);
+        thread::sleep(step);
+        left -= step;
+    }
+}
+
+/// Runs a script, going back to the current directory after it.
+fn do_sh(args: &str) {
+    let Some(path) = args.split_whitespace().next() else {
+        print_err!("sh", "missing file operand");
+        return;
+    };
+    let dir = env::current_dir();
+    run_script(path);
+    if let Ok(dir) = dir {
+        let _ = env::set_current_dir(&dir);
+    }
+}
+
+/// Runs a script in the current shell, e.g., to change the current directory
+/// or set environment variables for the next commands.
+fn do_source(args: &str) {
+    let Some(path) = args.split_whitespace().next() else {
+        print_err!("source", "missing file operand");
+        return;
+    };
+    run_script(path);
+}
+
+/// Runs an ELF executable as a user process, and waits for it to exit. Its
+/// standard input and output are on the console, even in a pipe.
+#[cfg(feature = "uspace")]
+fn do_exec(args: &str) {
+    use std::os::arceos::api::process::{ax_spawn_process, ax_wait_process_exit};
+
+    let argv: Vec<&str> = args.split_whitespace().collect();
+    let Some(&path) = argv.first() else {
+        print_usage!("exec <path> [args...]");
+        return;
+    };
+    let process = match ax_spawn_process(path, &argv) {
+        Ok(process) => process,
+        Err(e) => {
+            print_err!("exec", path, e);
+            return;
+        }
+    };
+    let code = ax_wait_process_exit(&process);
+    outln!("[{}] {}: exit code {}", process.pid(), path, code);
+    if code != 0 {
+        with_cmd_io(|io| io.failed = true);
+    }
+}
+
+/// Runs a WebAssembly application in a sandbox, which can only access files
+/// with `--fs` and the network with `--net`, and runs at most `--fuel`
+/// instructions.
+#[cfg(feature = "wasm")]
+fn do_wasm(args: &str) {
+    let mut sandbox = axwasm::Sandbox::default();
+    let mut args = args.split_whitespace().peekable();
+    while let Some(&opt) = args.peek() {
+        match opt {
+            "--fs" => sandbox.fs = true,
+            "--net" => sandbox.net = true,
+            "--fuel" => {
+                args.next();
+                match args.peek().and_then(|fuel| fuel.parse().ok()) {
+                    Some(fuel) => sandbox.fuel = Some(fuel),
+                    None => {
+                        print_usage!("wasm [--fs] [--net] [--fuel <n>] <file> [args...]");
+                        return;
+                    }
+                }
+            }
+            _ => break,
+        }
+        args.next();
+    }
+    let argv: Vec<&str> = args.collect();
+    let Some(&path) = argv.first() else {
+        print_usage!("wasm [--fs] [--net] [--fuel <n>] <file> [args...]");
+        return;
+    };
+    let res = fs::read(path).and_then(|wasm| axwasm::run(&wasm, &argv, &sandbox));
+    match res {
+        Ok(0) => {}
+        Ok(code) => print_err!("wasm", path, format!("exit code {}", code)),
+        Err(e) => print_err!("wasm", path, e),
+    }
+}
+
+fn do_exit(_args: &str) {
+    crate::set_bracketed_paste(false);
+    crate::set_raw_mode(false);
+    println!("Bye~");
+    std::process::exit(0);
+}
+
+/// Locks a mutex of std, or of axstd, which is not poisoned.
+#[cfg(not(feature = "axstd"))]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap()
+}
+
+#[cfg(feature = "axstd")]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock()
+}
+
+/// Calls `f` with the state of the command being run by the current thread.
+fn with_cmd_io<R>(f: impl FnOnce(&mut CmdIo) -> R) -> R {
+    with_thread_cmd_io(thread::current().id(), f)
+}
+
+/// Calls `f` with the state of the command being run by a thread.
+fn with_thread_cmd_io<R>(id: ThreadId, f: impl FnOnce(&mut CmdIo) -> R) -> R {
+    let mut cmd_io = lock(&CMD_IO);
+    let index = match cmd_io.iter().position(|(thread, _)| *thread == id) {
+        Some(index) => index,
+        None => {
+            cmd_io.push((id, CmdIo::default()));
+            cmd_io.len() - 1
+        }
+    };
+    f(&mut cmd_io[index].1)
+}
+
+/// Writes to the output of the command being run: to the console, or to the
+/// buffer of a pipe or a redirection.
+fn write_output(bytes: &[u8]) {
+    let to_console = with_cmd_io(|io| match io.output.as_mut() {
+        Some(output) => {
+            output.extend_from_slice(bytes);
+            false
+        }
+        None => true,
+    });
+    if to_console {
+        let _ = io::stdout().write_all(bytes);
+    }
+}
+
+fn print_output(args: fmt::Arguments) {
+    struct Output;
+
+    impl fmt::Write for Output {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            write_output(s.as_bytes());
+            Ok(())
+        }
+    }
+
+    let _ = fmt::Write::write_fmt(&mut Output, args);
+}
+
+/// Takes the input of the command being run, or returns `None` if it has
+/// none, i.e., if it would read the console.
+fn take_input() -> Option<Vec<u8>> {
+    with_cmd_io(|io| io.input.take())
+}
+
+/// Whether the job running the command is killed, see [`do_kill`], or the
+/// command is cancelled with `Ctrl-C`, see [`run_foreground`].
+fn killed() -> bool {
+    with_cmd_io(|io| io.killed) || cancelled(false)
+}
+
+/// Makes the shell the foreground task, so that `Ctrl-C` on the console
+/// cancels the command it runs instead of being read as input.
+pub fn set_foreground() {
+    #[cfg(feature = "axstd")]
+    std::os::arceos::api::task::ax_set_foreground();
+}
+
+/// Whether the shell is cancelled with `Ctrl-C`, which is then cleared if
+/// `clear`. Only the shell task is cancelled, not the background jobs.
+#[cfg(feature = "axstd")]
+fn cancelled(clear: bool) -> bool {
+    std::os::arceos::api::task::ax_is_cancelled(clear)
+}
+
+#[cfg(not(feature = "axstd"))]
+fn cancelled(_clear: bool) -> bool {
+    false
+}
+
+/// Runs a command line typed at the prompt, see [`run_cmd`]. It stops at the
+/// next point where its command checks [`killed`] if `Ctrl-C` is pressed,
+/// and then fails.
+pub fn run_foreground(line: &[u8]) {
+    // Ignore `Ctrl-C` pressed while the line was edited.
+    cancelled(true);
+    run_cmd(line);
+    if cancelled(true) {
+        println!("^C");
+        with_cmd_io(|io| io.failed = true);
+    }
+}
+
+/// Runs a command line in a background job, on another thread.
+fn spawn_job(line: &str) {
+    if line.is_empty() {
+        print_err!("shell", "syntax error: missing command");
+        return;
+    }
+    let done = Arc::new(AtomicBool::new(false));
+    let job_done = done.clone();
+    let job_line = String::from(line);
+    let thread = thread::spawn(move || {
+        with_cmd_io(|io| io.background = true);
+        run_cmd(job_line.as_bytes());
+        let id = thread::current().id();
+        lock(&CMD_IO).retain(|(thread, _)| *thread != id);
+        job_done.store(true, Ordering::Release);
+    });
+    let mut jobs = lock(&JOBS);
+    let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+    println!("[{}] {}", id, line);
+    jobs.push(Job {
+        id,
+        line: line.into(),
+        thread,
+        done,
+    });
+}
+
+/// Returns the index of the job given by its ID, or of the last job if no ID
+/// is given.
+fn find_job(jobs: &[Job], id: &str) -> Option<usize> {
+    if id.is_empty() {
+        return jobs.len().checked_sub(1);
+    }
+    let id = id.strip_prefix('%').unwrap_or(id).parse::<usize>().ok()?;
+    jobs.iter().position(|job| job.id == id)
+}
+
+/// A command of a pipeline, with its redirections.
+struct Command {
+    /// The command and its arguments, without the redirections.
+    line: String,
+    /// The file given with `<`.
+    input: Option<String>,
+    /// The file given with `>`, or with `>>` to append to it.
+    output: Option<(String, bool)>,
+}
+
+impl Command {
+    /// Parses a command of a pipeline. The operators in quotes are not
+    /// parsed, and the quotes are removed from the file names, e.g.,
+    /// `echo '>' > "a b"`. The environment variables are expanded once the
+    /// redirections are found, in the command and in the file names, so the
+    /// operators in their values are not parsed either.
+    fn parse(cmd: &str) -> Result<Self, &'static str> {
+        let mut line = String::new();
+        let mut input = None;
+        let mut output = None;
+        let mut rest = cmd;
+        while let Some(pos) = find_unquoted(rest, |c| c == '<' || c == '>') {
+            line.push_str(&expand_vars(&rest[..pos]));
+            line.push(' ');
+            let (op, after) = match &rest[pos..] {
+                s if s.starts_with(">>") => (">>", &s[2..]),
+                s => (&s[..1], &s[1..]),
+            };
+            let after = after.trim_start();
+            let end = find_unquoted(after, |c| c.is_whitespace() || c == '<' || c == '>')
+                .unwrap_or(after.len());
+            let path = split_quoted(&expand_vars(&after[..end])).concat();
+            if path.is_empty() {
+                return Err("syntax error: missing file name");
+            }
+            match op {
+                "<" => input = Some(path),
+                op => output = Some((path, op == ">>")),
+            }
+            rest = &after[end..];
+        }
+        line.push_str(&expand_vars(rest));
+        Ok(Self {
+            line,
+            input,
+            output,
+        })
+    }
+}
+
+fn write_file(path: &str, append: bool, data: &[u8]) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?
+        .write_all(data)
+}
+
+/// Replaces `$NAME` and `${NAME}` with the values of the environment
+/// variables, or with nothing if they are not set. `\$` is a `$`.
+fn expand_vars(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = line;
+    while let Some(pos) = rest.find(['$', '\\']) {
+        expanded.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if rest[pos..].starts_with('\\') {
+            match after.strip_prefix('$') {
+                Some(after) => {
+                    expanded.push('$');
+                    rest = after;
+                }
+                None => {
+                    expanded.push('\\');
+                    rest = after;
+                }
+            }
+            continue;
+        }
+        let (name, next) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else if let Ok(value) = env::var(name) {
+            expanded.push_str(&value);
+        }
+        rest = next;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Runs a command line: builtin commands connected by `|`, each one getting
+/// the output of the previous one as input, with their input and output
+/// redirected from and to files with `<`, `>` and `>>`. The operators in
+/// quotes are not parsed.
+///
+/// The aliases are expanded first, see [`expand_aliases`], then the
+/// environment variables in each command, see [`Command::parse`].
+///
+/// The output of a command is buffered until it exits, and the errors go
+/// to the console.
+pub fn run_cmd(line: &[u8]) {
+    let line = unsafe { core::str::from_utf8_unchecked(line) };
+    if line.trim().is_empty() {
+        return;
+    }
+    with_cmd_io(|io| io.failed = false);
+    if let Some(line) = line.trim_end().strip_suffix('&') {
+        spawn_job(line.trim());
+        return;
+    }
+    // A command line of a script goes to the output of the script, and has
+    // no input.
+    let (script_input, script_output) = with_cmd_io(|io| (io.input.take(), io.output.take()));
+    let output = run_pipeline(line, script_output);
+    with_cmd_io(|io| {
+        io.input = script_input;
+        io.output = output;
+    });
+}
+
+/// Runs the commands of a command line, the last one writing to `output`,
+/// or to the console if it is `None`. Returns `output`.
+fn run_pipeline(line: &str, mut output: Option<Vec<u8>>) -> Option<Vec<u8>> {
+    let line = expand_aliases(line);
+    let mut commands = Vec::new();
+    for cmd in split_unquoted(&line, '|') {
+        match Command::parse(cmd) {
+            Ok(cmd) if cmd.line.trim().is_empty() => {
+                print_err!("shell", "syntax error: missing command");
+                return output;
+            }
+            Ok(cmd) => commands.push(cmd),
+            Err(e) => {
+                print_err!("shell", e);
+                return output;
+            }
+        }
+    }
+
+    let mut pipe = None;
+    for (i, cmd) in commands.iter().enumerate() {
+        let input = match &cmd.input {
+            Some(path) => match fs::read(path) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    print_err!("shell", path, e);
+                    return output;
+                }
+            },
+            None => pipe.take(),
+        };
+        let last = i + 1 == commands.len();
+        let cmd_output = match cmd.output {
+            None if last => output.take(),
+            _ => Some(Vec::new()),
+        };
+        with_cmd_io(|io| {
+            io.input = input;
+            io.output = cmd_output;
+        });
+        run_one(&cmd.line);
+        let cmd_output = with_cmd_io(|io| {
+            io.input = None;
+            io.output.take()
+        });
+        pipe = match &cmd.output {
+            Some((path, append)) => {
+                if let Err(e) = write_file(path, *append, &cmd_output.unwrap_or_default()) {
+                    print_err!("shell", path, e);
+                    return output;
+                }
+                // The next command has no input.
+                Some(Vec::new())
+            }
+            None if last => {
+                output = cmd_output;
+                None
+            }
+            None => cmd_output,
+        };
+    }
+    output
+}
+
+/// Runs the commands of a script, one per line. A `#` at the start of a word
+/// starts a comment, to the end of the line.
+///
+/// The commands which failed, i.e., printed an error, are reported with their
+/// line numbers, and the script goes on. The script fails if one of them did.
+pub fn run_script(path: &str) {
+    if with_cmd_io(|io| io.script_depth) >= MAX_SCRIPT_DEPTH {
+        print_err!("sh", path, "too many nested scripts");
+        return;
+    }
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            print_err!("sh", path, e);
+            return;
+        }
+    };
+    with_cmd_io(|io| io.script_depth += 1);
+    let mut failed = false;
+    for (i, line) in script.lines().enumerate() {
+        let line = strip_comment(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        if killed() {
+            break;
+        }
+        run_cmd(line.as_bytes());
+        if with_cmd_io(|io| io.failed) {
+            println!("sh: {}:{}: failed: {}", path, i + 1, line.trim());
+            failed = true;
+        }
+    }
+    with_cmd_io(|io| {
+        io.script_depth -= 1;
+        io.failed = failed;
+    });
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev.is_whitespace() {
+            return &line[..i];
+        }
+        prev = c;
+    }
+    line
+}
+
+fn run_one(line: &str) {
+    let (cmd, args) = split_whitespace(line);
+    for (name, func) in CMD_TABLE {
+        if cmd == *name {
+            func(args);
+            return;
+        }
+    }
+    print_err!(cmd, "command not found");
+}
+
+/// Returns the completions of the last word of `line`, sorted: the builtin
+/// commands for the first word, and the entries of the directory being typed
+/// for the arguments, with a `/` after the directories.
+///
+/// Each completion replaces the whole word.
+pub fn complete(line: &str) -> Vec<String> {
+    let word_start = line.rfind(char::is_whitespace).map_or(0, |n| n + 1);
+    let word = &line[word_start..];
+    if line[..word_start].trim().is_empty() {
+        return CMD_TABLE
+            .iter()
+            .filter(|(name, _)| name.starts_with(word))
+            .map(|(name, _)| String::from(*name))
+            .collect();
+    }
+
+    let (dir, prefix) = match word.rfind('/') {
+        Some(n) => (&word[..n + 1], &word[n + 1..]),
+        None => ("", word),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut completions: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name())
+        .filter_map(|name| {
+            let name = path_to_str(&name);
+            // Hidden entries only when asked for.
+            if !name.starts_with(prefix) || (name.starts_with('.') && prefix.is_empty()) {
+                return None;
+            }
+            let path = String::from(dir) + name;
+            let is_dir = fs::metadata(&path).is_ok_and(|m| m.is_dir());
+            Some(if is_dir { path + "/" } else { path })
+        })
+        .collect();
+    completions.sort();
+    completions
+}
+
+/// Makes the builtin commands available to the `guest-exec` command of the
+/// guest agent.
+#[cfg(feature = "guest-agent")]
+pub fn register_agent_commands() {
+    for (name, func) in CMD_TABLE {
+        std::os::arceos::api::sys::ax_register_agent_command(name, *func);
+    }
+}
+
+fn split_whitespace(str: &str) -> (&str, &str) {
+    let str = str.trim();
+    str.find(char::is_whitespace)
+        .map_or((str, ""), |n| (&str[..n], str[n + 1..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_vars() {
+        unsafe {
+            env::set_var("SHELL_TEST_A", "a b");
+            env::remove_var("SHELL_TEST_UNSET");
+        }
+        assert_eq!(expand_vars("echo $SHELL_TEST_A."), "echo a b.");
+        assert_eq!(expand_vars("${SHELL_TEST_A}c"), "a bc");
+        assert_eq!(expand_vars("[$SHELL_TEST_UNSET]"), "[]");
+        assert_eq!(expand_vars(r"\$SHELL_TEST_A \n"), r"$SHELL_TEST_A \n");
+        assert_eq!(expand_vars("$ ${ $"), "$ ${ $");
+    }
+
+    #[test]
+    fn test_parse() {
+        let cmd = Command::parse(" grep a <in >>out ").unwrap();
+        assert_eq!(
+            cmd.line.split_whitespace().collect::<Vec<_>>(),
+            ["grep", "a"]
+        );
+        assert_eq!(cmd.input.as_deref(), Some("in"));
+        assert_eq!(cmd.output, Some((String::from("out"), true)));
+
+        let cmd = Command::parse("cat>out").unwrap();
+        assert_eq!(cmd.line.trim(), "cat");
+        assert_eq!(cmd.output, Some((String::from("out"), false)));
+        assert!(Command::parse("cat >").is_err());
+
+        // The values of the variables are not parsed.
+        unsafe {
+            env::set_var("SHELL_TEST_OP", "a > b | c");
+            env::set_var("SHELL_TEST_FILE", "out");
+        }
+        let cmd = Command::parse("echo $SHELL_TEST_OP > $SHELL_TEST_FILE.txt").unwrap();
+        assert_eq!(cmd.line.trim(), "echo a > b | c");
+        assert_eq!(cmd.input, None);
+        assert_eq!(cmd.output, Some((String::from("out.txt"), false)));
+    }
+
+    #[test]
+    fn test_parse_quoted() {
+        assert_eq!(
+            split_unquoted(r#"echo "a | b" '|' | grep "'" | wc"#, '|'),
+            [r#"echo "a | b" '|' "#, r#" grep "'" "#, " wc"]
+        );
+        assert_eq!(split_unquoted("echo '|", '|'), ["echo '|"]);
+
+        // The quotes are left to the command.
+        let cmd = Command::parse(r#"echo '<a>' ">>" "#).unwrap();
+        assert_eq!(cmd.line, r#"echo '<a>' ">>" "#);
+        assert_eq!((cmd.input, cmd.output), (None, None));
+
+        let cmd = Command::parse(r#"cat <'in file' >"out"'>'.txt"#).unwrap();
+        assert_eq!(cmd.line.trim(), "cat");
+        assert_eq!(cmd.input.as_deref(), Some("in file"));
+        assert_eq!(cmd.output, Some((String::from("out>.txt"), false)));
+        assert!(Command::parse("cat > ''").is_err());
+    }
 }
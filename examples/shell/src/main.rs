@@ -20,70 +20,222 @@ mod cmd;
 #[cfg(feature = "use-ramfs")]
 mod ramfs;
 
+// The decoder and the line editor of axstd only depend on `core`, so they
+// are shared on the host.
+#[cfg(not(feature = "axstd"))]
+#[path = "../../../ulib/axstd/src/io/key.rs"]
+#[allow(dead_code)]
+mod key;
+#[cfg(not(feature = "axstd"))]
+#[path = "../../../ulib/axstd/src/io/line_editor.rs"]
+#[allow(dead_code)]
+mod line_editor;
+
 use std::io::prelude::*;
+use std::{string::String, vec::Vec};
+
+#[cfg(not(feature = "axstd"))]
+use self::{
+    key::{Key, KeyDecoder},
+    line_editor::{History, LineEditor},
+};
+#[cfg(feature = "axstd")]
+use std::io::{History, Key, KeyDecoder, LineEditor};
 
 const LF: u8 = b'\n';
 const CR: u8 = b'\r';
-const DL: u8 = b'\x7f';
-const BS: u8 = b'\x08';
-const SPACE: u8 = b' ';
+const TAB: u8 = b'\t';
 
 const MAX_CMD_LEN: usize = 256;
 
+/// The number of commands recalled with `Up` and `Down`.
+const MAX_HISTORY_LEN: usize = 32;
+
+/// A script run at startup if it exists, e.g., to replay tests stored on the
+/// disk image.
+const STARTUP_SCRIPT: &str = "/autorun.sh";
+
+/// The number of bytes read from stdin at once, so a paste is processed in
+/// large chunks rather than byte by byte.
+const READ_CHUNK_SIZE: usize = 256;
+
 fn print_prompt() {
-    print!(
-        "arceos:{}$ ",
-        path_to_str(&std::env::current_dir().unwrap())
-    );
+    print!("{}", cmd::prompt());
     std::io::stdout().flush().unwrap();
 }
 
-#[cfg_attr(feature = "axstd", unsafe(no_mangle))]
-fn main() {
-    // 调用ulib::axstd::io::stdin()
-    let mut stdin = std::io::stdin();
-    let mut stdout = std::io::stdout();
+/// Turns the bracketed paste mode of the terminal on or off.
+pub fn set_bracketed_paste(enable: bool) {
+    print!("{}", if enable { "\x1b[?2004h" } else { "\x1b[?2004l" });
+    std::io::stdout().flush().unwrap();
+}
 
-    // 此buffer储存从stdin得到的输入信息
-    let mut buf = [0; MAX_CMD_LEN];
-    // 记录光标所在位置, 正常输出是右移, stdin获得特殊字符进行特殊移动
-    let mut cursor = 0;
-    cmd::run_cmd("help".as_bytes());
-    print_prompt();
+/// Switches the console input to the raw mode, as the shell does its own
+/// echo and line editing, or back to the cooked mode.
+pub fn set_raw_mode(enable: bool) {
+    #[cfg(feature = "axstd")]
+    {
+        use std::os::arceos::api::stdio::{AxConsoleMode, ax_console_set_mode};
+        ax_console_set_mode(if enable {
+            AxConsoleMode::Raw
+        } else {
+            AxConsoleMode::Cooked
+        });
+    }
+    // The host terminal is left as it is.
+    #[cfg(not(feature = "axstd"))]
+    let _ = enable;
+}
 
-    loop {
-        // 这里的实现是一个线程阻塞io, 可以考虑修改为非阻塞io, 基于中断
-        // TODO: 但目前先不考虑修改, 而是看到底层究竟是什么硬件或中断来获得用户输入
-        // 一次只读取一个byte, 也就是一个u8 ascii码
-        if stdin.read(&mut buf[cursor..cursor + 1]).ok() != Some(1) {
-            continue;
+/// The command line being edited.
+struct CmdLine {
+    line: LineEditor<MAX_CMD_LEN>,
+    history: History<MAX_CMD_LEN, MAX_HISTORY_LEN>,
+    /// The echo and the redraws not written yet. They are written once per
+    /// chunk read, and once for a whole paste.
+    out: Vec<u8>,
+    /// Decodes the escape sequences in the input.
+    keys: KeyDecoder,
+    /// Whether a bracketed paste is being received.
+    pasting: bool,
+    /// The completions cycled through by repeated Tabs, empty if the last
+    /// key was not a Tab.
+    completions: Vec<String>,
+    /// The index of the completion shown.
+    completion: usize,
+    /// Where the word being completed starts in the line.
+    word_start: usize,
+}
+
+impl CmdLine {
+    const fn new() -> Self {
+        Self {
+            line: LineEditor::new(),
+            history: History::new(),
+            out: Vec::new(),
+            keys: KeyDecoder::new(),
+            pasting: false,
+            completions: Vec::new(),
+            completion: 0,
+            word_start: 0,
         }
+    }
 
-        if buf[cursor] == b'\x1b' {
-            buf[cursor] = b'^';
+    /// Handles a chunk of input.
+    fn input(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            match self.keys.push(c) {
+                Some(Key::PasteStart) => self.pasting = true,
+                Some(Key::PasteEnd) => self.pasting = false,
+                Some(key) => self.input_key(key),
+                None => {}
+            }
+        }
+        if !self.pasting {
+            self.flush();
         }
-        match buf[cursor] {
-            CR | LF => {
+    }
+
+    /// Writes the output not written yet.
+    fn flush(&mut self) {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&self.out).unwrap();
+        stdout.flush().unwrap();
+        self.out.clear();
+    }
+
+    fn input_key(&mut self, key: Key) {
+        if key != Key::Char(TAB) {
+            self.completions.clear();
+        }
+        let mut out = |bytes: &[u8]| self.out.extend_from_slice(bytes);
+        if self.line.edit(key, &mut out) {
+            return;
+        }
+        match key {
+            Key::Char(CR | LF) => {
+                self.flush();
                 println!();
-                if cursor > 0 {
-                    cmd::run_cmd(&buf[..cursor]);
-                    cursor = 0;
+                self.history.push(self.line.line());
+                if !self.line.line().is_empty() {
+                    cmd::run_foreground(self.line.line());
+                    self.line.clear();
                 }
                 print_prompt();
             }
-            BS | DL => {
-                if cursor > 0 {
-                    stdout.write_all(&[BS, SPACE, BS]).unwrap();
-                    cursor -= 1;
+            Key::Char(TAB) if !self.pasting => self.complete(),
+            Key::Up => {
+                if let Some(line) = self.history.older(self.line.line()) {
+                    self.line.set_line(line, &mut out);
                 }
             }
-            0..=31 => {}
-            c => {
-                if cursor < MAX_CMD_LEN - 1 {
-                    stdout.write_all(&[c]).unwrap();
-                    cursor += 1;
+            Key::Down => {
+                if let Some(line) = self.history.newer() {
+                    self.line.set_line(line, &mut out);
                 }
             }
+            // Other keys are not supported yet.
+            _ => {}
+        }
+    }
+
+    /// Completes the word before the cursor, or replaces it with the next
+    /// completion on repeated Tabs.
+    fn complete(&mut self) {
+        let mut out = |bytes: &[u8]| self.out.extend_from_slice(bytes);
+        if self.completions.is_empty() {
+            let line = &self.line.line()[..self.line.cursor()];
+            let Ok(line) = core::str::from_utf8(line) else {
+                return;
+            };
+            self.word_start = line.rfind(char::is_whitespace).map_or(0, |n| n + 1);
+            self.completions = cmd::complete(line);
+            self.completion = 0;
+            // A single completion is final, so the next Tab completes the
+            // next word, or in the directory just completed.
+            if self.completions.len() == 1 {
+                let mut word = self.completions.pop().unwrap();
+                if !word.ends_with('/') {
+                    word.push(' ');
+                }
+                self.line
+                    .replace_before_cursor(self.word_start, word.as_bytes(), &mut out);
+                return;
+            }
+        } else {
+            self.completion = (self.completion + 1) % self.completions.len();
+        }
+        if let Some(word) = self.completions.get(self.completion) {
+            self.line
+                .replace_before_cursor(self.word_start, word.as_bytes(), &mut out);
+        }
+    }
+}
+
+#[cfg_attr(feature = "axstd", unsafe(no_mangle))]
+fn main() {
+    // 调用ulib::axstd::io::stdin()
+    let mut stdin = std::io::stdin();
+
+    let mut chunk = [0; READ_CHUNK_SIZE];
+    let mut cmd_line = CmdLine::new();
+    #[cfg(feature = "guest-agent")]
+    cmd::register_agent_commands();
+    cmd::set_foreground();
+    cmd::run_cmd("help".as_bytes());
+    if std::fs::metadata(STARTUP_SCRIPT).is_ok() {
+        cmd::run_script(STARTUP_SCRIPT);
+    }
+    set_raw_mode(true);
+    set_bracketed_paste(true);
+    print_prompt();
+
+    loop {
+        // 这里的实现是一个线程阻塞io, 可以考虑修改为非阻塞io, 基于中断
+        // 一次读取已收到的所有字节 (最多 READ_CHUNK_SIZE 个)
+        match stdin.read(&mut chunk) {
+            Ok(len) if len > 0 => cmd_line.input(&chunk[..len]),
+            _ => continue,
         }
     }
 }
@@ -0,0 +1,241 @@
+//! Deferred frees, for the memory freed in interrupt context.
+//!
+//! The memory is queued to a per-CPU list, without taking the locks of the
+//! allocator, and freed later by a task calling
+//! [`GlobalAllocator::free_deferred`] on the same CPU.
+//!
+//! The list is threaded through the freed blocks themselves, so queuing never
+//! allocates. The heap blocks too small to hold a list node (3 words) are
+//! queued to a fixed per-CPU array instead. If it is full, they are freed
+//! right away only if the byte allocator is not locked, and leaked otherwise,
+//! which is reported by [`GlobalAllocator::free_deferred`].
+
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::GlobalAllocator;
+
+/// The maximum number of small blocks queued on a CPU.
+const MAX_SMALL_BLOCKS: usize = 256;
+
+/// A freed block, written at its start.
+struct Node {
+    next: *mut Node,
+    /// The size of the heap block, or the number of pages.
+    size: usize,
+    /// The alignment of the heap block, or 0 for pages.
+    align: usize,
+}
+
+/// The blocks queued on a CPU.
+struct DeferredList {
+    head: AtomicPtr<Node>,
+    /// The number of blocks in the list, updated along with it so that it
+    /// is never counted on another CPU. The small blocks are counted by
+    /// their array.
+    len: AtomicUsize,
+}
+
+impl DeferredList {
+    const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, node: NonNull<Node>) {
+        let node = node.as_ptr();
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: the block is freed, so it is owned by the list.
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> *mut Node {
+        self.head.swap(ptr::null_mut(), Ordering::Acquire)
+    }
+}
+
+/// The blocks too small to hold a [`Node`], queued on a CPU.
+struct SmallBlocks {
+    blocks: [(usize, Layout); MAX_SMALL_BLOCKS],
+    len: usize,
+}
+
+impl SmallBlocks {
+    const fn new() -> Self {
+        Self {
+            blocks: [(0, Layout::new::<u8>()); MAX_SMALL_BLOCKS],
+            len: 0,
+        }
+    }
+}
+
+#[percpu::def_percpu]
+static DEFERRED: DeferredList = DeferredList::new();
+
+#[percpu::def_percpu]
+static SMALL_BLOCKS: SpinNoIrq<SmallBlocks> = SpinNoIrq::new(SmallBlocks::new());
+
+/// The number of small blocks leaked as they could be neither queued nor
+/// freed, not reported yet.
+static LEAKED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a block of `layout` at `pos` can hold a [`Node`].
+fn fits_node(pos: NonNull<u8>, layout: Layout) -> bool {
+    layout.size() >= size_of::<Node>() && pos.as_ptr().cast::<Node>().is_aligned()
+}
+
+impl GlobalAllocator {
+    /// Queues an allocated region to be freed by [`free_deferred`], without
+    /// taking the locks of the allocator, e.g., in interrupt context.
+    ///
+    /// The region should be allocated by [`alloc`] with the same `layout`,
+    /// and not be accessed anymore, as for [`dealloc`].
+    ///
+    /// [`alloc`]: GlobalAllocator::alloc
+    /// [`dealloc`]: GlobalAllocator::dealloc
+    /// [`free_deferred`]: GlobalAllocator::free_deferred
+    pub fn dealloc_deferred(&self, pos: NonNull<u8>, layout: Layout) {
+        if !fits_node(pos, layout) {
+            self.queue_small(pos, layout);
+            return;
+        }
+        let node = pos.cast::<Node>();
+        // SAFETY: the block is large and aligned enough, and no longer used.
+        unsafe {
+            node.write(Node {
+                next: ptr::null_mut(),
+                size: layout.size(),
+                align: layout.align(),
+            })
+        };
+        self.queue_deferred(node);
+    }
+
+    /// Queues allocated pages to be freed by [`free_deferred`], without
+    /// taking the locks of the allocator, e.g., in interrupt context.
+    ///
+    /// The pages should be allocated by [`alloc_pages`], and not be
+    /// accessed anymore, as for [`dealloc_pages`].
+    ///
+    /// [`alloc_pages`]: GlobalAllocator::alloc_pages
+    /// [`dealloc_pages`]: GlobalAllocator::dealloc_pages
+    /// [`free_deferred`]: GlobalAllocator::free_deferred
+    pub fn dealloc_pages_deferred(&self, pos: usize, num_pages: usize) {
+        let node = NonNull::new(pos as *mut Node).expect("dealloc null pages");
+        // SAFETY: the pages are no longer used.
+        unsafe {
+            node.write(Node {
+                next: ptr::null_mut(),
+                size: num_pages,
+                align: 0,
+            })
+        };
+        self.queue_deferred(node);
+    }
+
+    fn queue_deferred(&self, node: NonNull<Node>) {
+        // Notified on the same CPU.
+        DEFERRED.with_current(|list| {
+            list.push(node);
+            if let Some(notify) = *self.deferred_notifier.lock() {
+                notify();
+            }
+        });
+    }
+
+    /// Queues a block too small to hold a [`Node`].
+    fn queue_small(&self, pos: NonNull<u8>, layout: Layout) {
+        let queued = SMALL_BLOCKS.with_current(|small| {
+            let mut small = small.lock();
+            if small.len == MAX_SMALL_BLOCKS {
+                return false;
+            }
+            let len = small.len;
+            small.blocks[len] = (pos.as_ptr() as usize, layout);
+            small.len += 1;
+            true
+        });
+        if queued {
+            if let Some(notify) = *self.deferred_notifier.lock() {
+                notify();
+            }
+        } else if let Some(mut balloc) = self.balloc.try_lock() {
+            // Not held by the interrupted context, so it can be freed.
+            balloc.dealloc(pos, layout);
+        } else {
+            LEAKED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of blocks queued on the current CPU, to be freed
+    /// by [`free_deferred`](GlobalAllocator::free_deferred).
+    pub fn deferred_count(&self) -> usize {
+        let len = DEFERRED.with_current(|list| list.len.load(Ordering::Relaxed));
+        len + SMALL_BLOCKS.with_current(|small| small.lock().len)
+    }
+
+    /// Frees the blocks queued on the current CPU with
+    /// [`dealloc_deferred`](GlobalAllocator::dealloc_deferred) and
+    /// [`dealloc_pages_deferred`](GlobalAllocator::dealloc_pages_deferred).
+    /// Returns the number of blocks freed.
+    ///
+    /// It is called in task context, by a task pinned to the CPU.
+    pub fn free_deferred(&self) -> usize {
+        let mut node = DEFERRED.with_current(|list| list.take());
+        let mut count = 0;
+        while let Some(block) = NonNull::new(node) {
+            // SAFETY: the node was written when the block was queued, and
+            // the block is only in this list.
+            let Node { next, size, align } = unsafe { block.read() };
+            if align == 0 {
+                self.dealloc_pages(block.as_ptr() as usize, size);
+            } else {
+                // SAFETY: the layout is the one of the allocation.
+                let layout = unsafe { Layout::from_size_align_unchecked(size, align) };
+                self.dealloc(block.cast(), layout);
+            }
+            node = next;
+            count += 1;
+        }
+        DEFERRED.with_current(|list| list.len.fetch_sub(count, Ordering::Relaxed));
+        // One at a time, not to free them under the lock of the array.
+        while let Some((pos, layout)) = SMALL_BLOCKS.with_current(|small| {
+            let mut small = small.lock();
+            small.len = small.len.checked_sub(1)?;
+            Some(small.blocks[small.len])
+        }) {
+            self.dealloc(NonNull::new(pos as *mut u8).unwrap(), layout);
+            count += 1;
+        }
+        let leaked = LEAKED.swap(0, Ordering::Relaxed);
+        if leaked > 0 {
+            warn!("{} small blocks leaked by deferred frees", leaked);
+        }
+        count
+    }
+
+    /// Sets the function called when a block is queued to be freed, e.g.,
+    /// to wake up the task calling
+    /// [`free_deferred`](GlobalAllocator::free_deferred) on the CPU.
+    ///
+    /// It is called in the context freeing the block, possibly an interrupt
+    /// handler.
+    pub fn set_deferred_notifier(&self, notifier: fn()) {
+        *self.deferred_notifier.lock() = Some(notifier);
+    }
+}
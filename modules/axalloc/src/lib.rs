@@ -4,6 +4,13 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! When the heap is exhausted, the [`OomNotifier`]s are asked to give memory
+//! back, then the [`OomKiller`] to terminate a task, before the allocation
+//! fails and the kernel panics.
+//!
+//! The memory freed in interrupt context can be queued to be freed later by
+//! a task, see [`GlobalAllocator::dealloc_deferred`].
 
 #![no_std]
 
@@ -11,18 +18,107 @@
 extern crate log;
 extern crate alloc;
 
+mod deferred;
 mod page;
 
 use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use kspin::SpinNoIrq;
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
+const MAX_OOM_NOTIFIERS: usize = 8;
 
 pub use page::GlobalPage;
 
+/// Statistics of the global allocator, see [`GlobalAllocator::stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemStats {
+    /// The bytes of the heap, i.e., the memory given to the byte allocator.
+    pub heap_total: usize,
+    /// The bytes allocated from the heap.
+    pub heap_used: usize,
+    /// The highest number of bytes allocated from the heap since boot.
+    pub heap_peak: usize,
+    /// The bytes of the largest block which can be allocated from the heap
+    /// without expanding it, approximately.
+    pub heap_largest_free: usize,
+    /// The number of pages of the page allocator.
+    pub total_pages: usize,
+    /// The number of allocated pages, including the ones of the heap.
+    pub used_pages: usize,
+    /// The highest number of allocated pages since boot.
+    pub peak_pages: usize,
+    /// The number of pages of the largest block of contiguous free pages,
+    /// approximately.
+    pub largest_free_pages: usize,
+}
+
+impl MemStats {
+    /// Returns the fragmentation of the free memory of the heap, in percent:
+    /// 0 if it can be allocated as one block, close to 100 if it is spread
+    /// in many small blocks.
+    pub fn heap_fragmentation(&self) -> f64 {
+        fragmentation(self.heap_largest_free, self.heap_total - self.heap_used)
+    }
+
+    /// Returns the fragmentation of the free pages, in percent, see
+    /// [`heap_fragmentation`](Self::heap_fragmentation).
+    pub fn page_fragmentation(&self) -> f64 {
+        fragmentation(self.largest_free_pages, self.total_pages - self.used_pages)
+    }
+}
+
+fn fragmentation(largest_free: usize, free: usize) -> f64 {
+    if free == 0 {
+        return 0.0;
+    }
+    100.0 * (1.0 - largest_free as f64 / free as f64)
+}
+
+/// Returns the largest `n` from 0 to `max` for which `fits(n)` holds,
+/// assuming that it also holds for all the numbers below such an `n`. It is
+/// not called with 0.
+fn largest_fitting(max: usize, mut fits: impl FnMut(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, max);
+    while low < high {
+        let mid = high - (high - low) / 2;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+/// A function that tries to give back `num_pages` pages to the page
+/// allocator, e.g., by deflating a memory balloon. Returns the number of
+/// pages given back.
+///
+/// It is called with no lock of the allocator held, by
+/// [`GlobalAllocator::reclaim`], or when a page allocation fails, so it must
+/// not allocate memory.
+pub type ReclaimHandler = fn(num_pages: usize) -> usize;
+
+/// A function that tries to give back memory when a heap allocation of
+/// `size` bytes fails, e.g., by shrinking a cache. Returns the number of
+/// bytes given back.
+///
+/// It is called with no lock of the allocator held, so it can free memory,
+/// but it must not allocate memory.
+pub type OomNotifier = fn(size: usize) -> usize;
+
+/// A function that terminates a task to free its memory, when a heap
+/// allocation of `size` bytes fails and the [`OomNotifier`]s give nothing
+/// back. Returns whether a task was terminated, in which case the allocation
+/// is retried.
+///
+/// Like an [`OomNotifier`], it must not allocate memory.
+pub type OomKiller = fn(size: usize) -> bool;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
         /// The default byte allocator.
@@ -50,6 +146,14 @@ cfg_if::cfg_if! {
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    low_watermark: AtomicUsize,
+    reclaim_handler: SpinNoIrq<Option<ReclaimHandler>>,
+    reclaim_pending: AtomicBool,
+    oom_notifiers: SpinNoIrq<[Option<OomNotifier>; MAX_OOM_NOTIFIERS]>,
+    oom_killer: SpinNoIrq<Option<OomKiller>>,
+    deferred_notifier: SpinNoIrq<Option<fn()>>,
+    peak_bytes: AtomicUsize,
+    peak_pages: AtomicUsize,
 }
 
 impl GlobalAllocator {
@@ -58,6 +162,14 @@ impl GlobalAllocator {
         Self {
             balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            low_watermark: AtomicUsize::new(0),
+            reclaim_handler: SpinNoIrq::new(None),
+            reclaim_pending: AtomicBool::new(false),
+            oom_notifiers: SpinNoIrq::new([None; MAX_OOM_NOTIFIERS]),
+            oom_killer: SpinNoIrq::new(None),
+            deferred_notifier: SpinNoIrq::new(None),
+            peak_bytes: AtomicUsize::new(0),
+            peak_pages: AtomicUsize::new(0),
         }
     }
 
@@ -104,24 +216,29 @@ impl GlobalAllocator {
     /// byte allocator.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
-        let mut balloc = self.balloc.lock();
         loop {
-            if let Ok(ptr) = balloc.alloc(layout) {
-                return Ok(ptr);
-            } else {
-                let old_size = balloc.total_bytes();
-                let expand_size = old_size
-                    .max(layout.size())
-                    .next_power_of_two()
-                    .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
-                debug!(
-                    "expand heap memory: [{:#x}, {:#x})",
-                    heap_ptr,
-                    heap_ptr + expand_size
-                );
-                balloc.add_memory(heap_ptr, expand_size)?;
-            }
+            let old_size = {
+                let mut balloc = self.balloc.lock();
+                if let Ok(ptr) = balloc.alloc(layout) {
+                    self.peak_bytes
+                        .fetch_max(balloc.used_bytes(), Ordering::Relaxed);
+                    return Ok(ptr);
+                }
+                balloc.total_bytes()
+            };
+            // The byte allocator is not locked while the pages are allocated, which may
+            // reclaim pages, and logged.
+            let expand_size = old_size
+                .max(layout.size())
+                .next_power_of_two()
+                .max(PAGE_SIZE);
+            let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+            debug!(
+                "expand heap memory: [{:#x}, {:#x})",
+                heap_ptr,
+                heap_ptr + expand_size
+            );
+            self.balloc.lock().add_memory(heap_ptr, expand_size)?;
         }
     }
 
@@ -142,8 +259,39 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// If the allocation fails, the [`ReclaimHandler`] is called to get pages
+    /// back. If the number of available pages drops below the low watermark,
+    /// they are only got back later by [`reclaim`](GlobalAllocator::reclaim).
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        let res = self.palloc_pages(num_pages, align_pow2);
+        let available = self.available_pages();
+        if res.is_ok() {
+            if available < self.low_watermark() {
+                self.reclaim_pending.store(true, Ordering::Relaxed);
+            }
+            return res;
+        }
+        let Some(reclaim) = *self.reclaim_handler.lock() else {
+            return res;
+        };
+        let wanted = self.high_watermark().saturating_sub(available);
+        if reclaim(wanted.max(num_pages)) > 0 {
+            return self.palloc_pages(num_pages, align_pow2);
+        }
+        res
+    }
+
+    /// Allocates pages from the page allocator, keeping track of the peak
+    /// usage.
+    fn palloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let mut palloc = self.palloc.lock();
+        let res = palloc.alloc_pages(num_pages, align_pow2);
+        if res.is_ok() {
+            self.peak_pages
+                .fetch_max(palloc.used_pages(), Ordering::Relaxed);
+        }
+        res
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -176,14 +324,148 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.palloc.lock().available_pages()
     }
+
+    /// Returns the statistics of the allocator.
+    ///
+    /// The allocators do not track their largest free blocks, so they are
+    /// found by trying to allocate blocks, in a binary search. Each try takes
+    /// the lock of the allocator on its own, not to keep the IRQs disabled
+    /// for long, so the largest free blocks are only approximate if memory
+    /// is allocated or freed meanwhile, and it takes a while with large
+    /// heaps.
+    pub fn stats(&self) -> MemStats {
+        let (heap_total, heap_used, heap_free) = {
+            let balloc = self.balloc.lock();
+            let free = balloc.available_bytes();
+            (balloc.total_bytes(), balloc.used_bytes(), free)
+        };
+        let heap_largest_free = largest_fitting(heap_free, |size| {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let mut balloc = self.balloc.lock();
+            match balloc.alloc(layout) {
+                Ok(ptr) => {
+                    balloc.dealloc(ptr, layout);
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        let (total_pages, used_pages, free_pages) = {
+            let palloc = self.palloc.lock();
+            let free = palloc.available_pages();
+            (palloc.total_pages(), palloc.used_pages(), free)
+        };
+        let largest_free_pages = largest_fitting(free_pages, |num_pages| {
+            let mut palloc = self.palloc.lock();
+            match palloc.alloc_pages(num_pages, PAGE_SIZE) {
+                Ok(pos) => {
+                    palloc.dealloc_pages(pos, num_pages);
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        MemStats {
+            heap_total,
+            heap_used,
+            heap_peak: self.peak_bytes.load(Ordering::Relaxed),
+            heap_largest_free,
+            total_pages,
+            used_pages,
+            peak_pages: self.peak_pages.load(Ordering::Relaxed),
+            largest_free_pages,
+        }
+    }
+
+    /// Returns the low watermark of available pages.
+    pub fn low_watermark(&self) -> usize {
+        self.low_watermark.load(Ordering::Relaxed)
+    }
+
+    /// Returns the high watermark of available pages, twice the low one:
+    /// the pages are reclaimed up to it, so the next allocations do not drop
+    /// below the low watermark again right away.
+    pub fn high_watermark(&self) -> usize {
+        self.low_watermark() * 2
+    }
+
+    /// Sets the [`ReclaimHandler`] to be called by
+    /// [`reclaim`](GlobalAllocator::reclaim) once the number of available
+    /// pages dropped below `low_watermark`, or when a page allocation fails.
+    pub fn set_reclaim_handler(&self, low_watermark: usize, handler: ReclaimHandler) {
+        self.low_watermark.store(low_watermark, Ordering::Relaxed);
+        *self.reclaim_handler.lock() = Some(handler);
+    }
+
+    /// Gets pages back with the [`ReclaimHandler`], up to the high watermark,
+    /// if the number of available pages dropped below the low watermark
+    /// since the last call. Returns the number of pages got back.
+    ///
+    /// It is called periodically by a task, which limits the rate of the
+    /// reclaims, instead of in the allocations.
+    pub fn reclaim(&self) -> usize {
+        if !self.reclaim_pending.swap(false, Ordering::Relaxed) {
+            return 0;
+        }
+        let Some(reclaim) = *self.reclaim_handler.lock() else {
+            return 0;
+        };
+        let wanted = self.high_watermark().saturating_sub(self.available_pages());
+        if wanted == 0 {
+            return 0;
+        }
+        let reclaimed = reclaim(wanted);
+        debug!("reclaimed {} of {} pages", reclaimed, wanted);
+        reclaimed
+    }
+
+    /// Registers an [`OomNotifier`] to be called when a heap allocation
+    /// fails. Returns `false` if too many are registered.
+    pub fn register_oom_notifier(&self, notifier: OomNotifier) -> bool {
+        let mut notifiers = self.oom_notifiers.lock();
+        match notifiers.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(notifier);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the [`OomKiller`] to be called when a heap allocation fails and
+    /// the [`OomNotifier`]s give nothing back.
+    pub fn set_oom_killer(&self, killer: OomKiller) {
+        *self.oom_killer.lock() = Some(killer);
+    }
+
+    /// Tries to free memory after a heap allocation of `layout` failed.
+    /// Returns whether the allocation should be retried.
+    fn handle_oom(&self, layout: Layout) -> bool {
+        let notifiers = *self.oom_notifiers.lock();
+        let freed: usize = notifiers
+            .iter()
+            .flatten()
+            .map(|notify| notify(layout.size()))
+            .sum();
+        if freed > 0 {
+            debug!("{} bytes given back on allocation failure", freed);
+            return true;
+        }
+        let killer = *self.oom_killer.lock();
+        killer.is_some_and(|kill| kill(layout.size()))
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
-            ptr.as_ptr()
-        } else {
-            alloc::alloc::handle_alloc_error(layout)
+        loop {
+            if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
+                return ptr.as_ptr();
+            }
+            if !self.handle_oom(layout) {
+                error!("out of memory: failed to allocate {:?}", layout);
+                alloc::alloc::handle_alloc_error(layout)
+            }
         }
     }
 
@@ -0,0 +1,71 @@
+//! AES-GCM authenticated encryption.
+
+use aes_gcm::aead::AeadInPlace;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce, Tag};
+use axerrno::{AxError, AxResult, ax_err};
+
+/// The size of a nonce, which must not be used twice with the same key.
+pub const NONCE_SIZE: usize = 12;
+
+/// The size of an authentication tag.
+pub const TAG_SIZE: usize = 16;
+
+// Not boxed, so no allocator is needed.
+#[allow(clippy::large_enum_variant)]
+enum Cipher {
+    Aes128(Aes128Gcm),
+    Aes256(Aes256Gcm),
+}
+
+/// AES-GCM with a 128 or 256-bit key.
+///
+/// Data is encrypted in place, and authenticated with additional data that
+/// is not encrypted, e.g., a header.
+pub struct AesGcm {
+    cipher: Cipher,
+}
+
+impl AesGcm {
+    /// Creates a cipher with the given key of 16 or 32 bytes.
+    pub fn new(key: &[u8]) -> AxResult<Self> {
+        let cipher = match key.len() {
+            16 => Cipher::Aes128(Aes128Gcm::new_from_slice(key).unwrap()),
+            32 => Cipher::Aes256(Aes256Gcm::new_from_slice(key).unwrap()),
+            _ => return ax_err!(InvalidInput, "the key must be of 16 or 32 bytes"),
+        };
+        Ok(Self { cipher })
+    }
+
+    /// Encrypts `buf` in place, and returns the tag authenticating it with
+    /// `aad`.
+    pub fn encrypt(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], buf: &mut [u8]) -> [u8; TAG_SIZE] {
+        let nonce = Nonce::from_slice(nonce);
+        let tag = match &self.cipher {
+            Cipher::Aes128(c) => c.encrypt_in_place_detached(nonce, aad, buf),
+            Cipher::Aes256(c) => c.encrypt_in_place_detached(nonce, aad, buf),
+        };
+        // It fails only if `buf` is larger than 64 GiB.
+        tag.expect("too much data to encrypt").into()
+    }
+
+    /// Decrypts `buf` in place, after checking that `tag` authenticates it
+    /// with `aad`.
+    ///
+    /// Returns [`AxError::InvalidData`], leaving `buf` unchanged, if it does
+    /// not.
+    pub fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_SIZE],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8; TAG_SIZE],
+    ) -> AxResult {
+        let nonce = Nonce::from_slice(nonce);
+        let tag = Tag::from_slice(tag);
+        match &self.cipher {
+            Cipher::Aes128(c) => c.decrypt_in_place_detached(nonce, aad, buf, tag),
+            Cipher::Aes256(c) => c.decrypt_in_place_detached(nonce, aad, buf, tag),
+        }
+        .map_err(|_| AxError::InvalidData)
+    }
+}
@@ -0,0 +1,60 @@
+//! CRC-32 (IEEE 802.3) checksums.
+
+/// The table of CRC-32 for each byte.
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 of data given in parts.
+#[derive(Clone)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    /// Creates a CRC-32 of no data.
+    pub const fn new() -> Self {
+        Self { crc: !0 }
+    }
+
+    /// Appends the next part of the data.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.crc = bytes.iter().fold(self.crc, |crc, &b| {
+            (crc >> 8) ^ TABLE[((crc ^ b as u32) & 0xff) as usize]
+        });
+    }
+
+    /// Returns the CRC-32 of all the data appended.
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the CRC-32 of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
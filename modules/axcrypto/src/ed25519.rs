@@ -0,0 +1,51 @@
+//! ed25519 signature verification.
+
+use axerrno::{AxError, AxResult};
+use ed25519_compact::{PublicKey, Signature, VerifyingState};
+
+/// The size of a public key.
+pub const PUBLIC_KEY_SIZE: usize = PublicKey::BYTES;
+
+/// The size of a signature.
+pub const SIGNATURE_SIZE: usize = Signature::BYTES;
+
+/// Verifies the signature of `data` made by the given public key.
+///
+/// Returns [`AxError::InvalidInput`] if the key or the signature is
+/// malformed, and [`AxError::InvalidData`] if the signature does not match.
+pub fn verify(key: &[u8], data: &[u8], signature: &[u8]) -> AxResult {
+    let mut verifier = Verifier::new(key, signature)?;
+    verifier.update(data);
+    verifier.finish()
+}
+
+/// Verifies a signature over data given in parts, e.g., an image read from a
+/// block device.
+pub struct Verifier {
+    state: VerifyingState,
+}
+
+impl Verifier {
+    /// Starts verifying a signature made by the given public key.
+    pub fn new(key: &[u8], signature: &[u8]) -> AxResult<Self> {
+        let key = PublicKey::from_slice(key).map_err(|_| AxError::InvalidInput)?;
+        let signature = Signature::from_slice(signature).map_err(|_| AxError::InvalidInput)?;
+        let state = key
+            .verify_incremental(&signature)
+            .map_err(|_| AxError::InvalidData)?;
+        Ok(Self { state })
+    }
+
+    /// Appends the next part of the data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.state.absorb(data);
+    }
+
+    /// Checks the signature over all the data appended.
+    pub fn finish(self) -> AxResult {
+        self.state.verify().map_err(|e| {
+            warn!("signature verification failed: {}", e);
+            AxError::InvalidData
+        })
+    }
+}
@@ -0,0 +1,64 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) cryptographic primitives.
+//!
+//! The primitives used by the modules of ArceOS are all provided here, so
+//! they share one implementation rather than each having their own:
+//!
+//! - [`sha256`]: SHA-256 digests, and HMAC-SHA-256 message authentication.
+//! - [`aead`]: AES-GCM authenticated encryption, with 128 or 256-bit keys.
+//! - [`ed25519`]: ed25519 signature verification, e.g., of images.
+//! - [`crc32`]: CRC-32 checksums, for integrity against torn writes or
+//!   corruption rather than tampering, e.g., of the records of a log.
+//!
+//! The crypto instructions of the CPU are used where available (see
+//! [`hw_features`]): AES-NI, PCLMULQDQ and the SHA extensions on x86_64,
+//! detected at run time, and the ARMv8 crypto extensions on aarch64, if
+//! enabled at build time (e.g., with `-C target-feature=+aes,+sha2`), as they
+//! can not be detected there without an OS. They fall back to constant-time
+//! software implementations otherwise.
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+#[macro_use]
+extern crate log;
+
+pub mod aead;
+pub mod crc32;
+pub mod ed25519;
+pub mod sha256;
+
+/// The crypto instructions used by the primitives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwFeatures {
+    /// AES, and carry-less multiplication for GHASH.
+    pub aes: bool,
+    /// SHA-256.
+    pub sha256: bool,
+}
+
+#[cfg(target_arch = "x86_64")]
+cpufeatures::new!(cpuid_aes, "aes", "pclmulqdq");
+#[cfg(target_arch = "x86_64")]
+cpufeatures::new!(cpuid_sha256, "sha", "sse2", "ssse3", "sse4.1");
+
+#[cfg(target_arch = "aarch64")]
+cpufeatures::new!(cpuid_aes, "aes");
+#[cfg(target_arch = "aarch64")]
+cpufeatures::new!(cpuid_sha256, "sha2");
+
+/// Returns the crypto instructions of the CPU used by the primitives.
+pub fn hw_features() -> HwFeatures {
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    {
+        HwFeatures {
+            aes: cpuid_aes::get(),
+            sha256: cpuid_sha256::get(),
+        }
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        HwFeatures {
+            aes: false,
+            sha256: false,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! SHA-256 digests and HMAC-SHA-256.
+
+use axerrno::{AxError, AxResult};
+use hmac::Mac;
+use sha2::Digest;
+
+/// The size of a SHA-256 digest, and of an HMAC-SHA-256 tag.
+pub const DIGEST_SIZE: usize = 32;
+
+/// Computes the SHA-256 digest of data given in parts.
+#[derive(Clone, Default)]
+pub struct Sha256 {
+    inner: sha2::Sha256,
+}
+
+impl Sha256 {
+    /// Creates a digest of no data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the next part of the data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the digest of all the data appended.
+    pub fn finish(self) -> [u8; DIGEST_SIZE] {
+        self.inner.finalize().into()
+    }
+}
+
+/// Returns the SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Computes the HMAC-SHA-256 tag of data given in parts.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: hmac::Hmac<sha2::Sha256>,
+}
+
+impl HmacSha256 {
+    /// Creates a tag of no data with the given key, of any size.
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            inner: hmac::Hmac::new_from_slice(key).unwrap(),
+        }
+    }
+
+    /// Appends the next part of the data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the tag of all the data appended.
+    pub fn finish(self) -> [u8; DIGEST_SIZE] {
+        self.inner.finalize().into_bytes().into()
+    }
+
+    /// Checks in constant time that `tag` is the tag of all the data
+    /// appended.
+    ///
+    /// Returns [`AxError::InvalidData`] if it is not.
+    pub fn verify(self, tag: &[u8]) -> AxResult {
+        self.inner
+            .verify_slice(tag)
+            .map_err(|_| AxError::InvalidData)
+    }
+}
+
+/// Returns the HMAC-SHA-256 tag of `data` with the given key.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finish()
+}
@@ -0,0 +1,156 @@
+use axcrypto::aead::AesGcm;
+use axcrypto::crc32::{Crc32, crc32};
+use axcrypto::ed25519::{self, Verifier};
+use axcrypto::sha256::{HmacSha256, Sha256, hmac_sha256, sha256};
+use axerrno::AxError;
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_sha256() {
+    assert_eq!(
+        sha256(b"").to_vec(),
+        hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    );
+    let mut hasher = Sha256::new();
+    hasher.update(b"a");
+    hasher.update(b"bc");
+    assert_eq!(
+        hasher.finish().to_vec(),
+        hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+
+    // RFC 4231, test case 2.
+    let tag = hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    assert_eq!(
+        hmac_sha256(b"Jefe", b"what do ya want for nothing?").to_vec(),
+        tag
+    );
+    let mut mac = HmacSha256::new(b"Jefe");
+    mac.update(b"what do ya want ");
+    mac.update(b"for nothing?");
+    assert_eq!(mac.clone().verify(&tag), Ok(()));
+    assert_eq!(mac.verify(&tag[1..]), Err(AxError::InvalidData));
+}
+
+/// Test cases 3 and 15 of the GCM specification.
+#[test]
+fn test_aes_gcm() {
+    let plaintext = hex(
+        "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+         1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255",
+    );
+    let nonce = hex("cafebabefacedbaddecaf888").try_into().unwrap();
+    for (key, ciphertext, tag) in [
+        (
+            "feffe9928665731c6d6a8f9467308308",
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985",
+            "4d5c2af327cd64a62cf35abd2ba6fab4",
+        ),
+        (
+            "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+            "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+             8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662898015ad",
+            "b094dac5d93471bdec1a502270e3cc6c",
+        ),
+    ] {
+        let cipher = AesGcm::new(&hex(key)).unwrap();
+        let mut buf = plaintext.clone();
+        let computed_tag = cipher.encrypt(&nonce, b"", &mut buf);
+        assert_eq!(buf, hex(ciphertext));
+        assert_eq!(computed_tag.to_vec(), hex(tag));
+
+        assert_eq!(cipher.decrypt(&nonce, b"", &mut buf, &computed_tag), Ok(()));
+        assert_eq!(buf, plaintext);
+
+        // Tampered data, or other additional data.
+        let mut buf = hex(ciphertext);
+        buf[0] ^= 1;
+        assert_eq!(
+            cipher.decrypt(&nonce, b"", &mut buf, &computed_tag),
+            Err(AxError::InvalidData)
+        );
+        assert_eq!(buf[1..], hex(ciphertext)[1..]);
+        let mut buf = hex(ciphertext);
+        assert_eq!(
+            cipher.decrypt(&nonce, b"header", &mut buf, &computed_tag),
+            Err(AxError::InvalidData)
+        );
+    }
+    assert!(AesGcm::new(&[0; 24]).is_err());
+}
+
+/// Test vectors from RFC 8032, section 7.1.
+struct Ed25519Vector {
+    key: &'static str,
+    message: &'static str,
+    signature: &'static str,
+}
+
+const ED25519_TEST_2: Ed25519Vector = Ed25519Vector {
+    key: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+    message: "72",
+    signature: "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+};
+
+const ED25519_TEST_3: Ed25519Vector = Ed25519Vector {
+    key: "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+    message: "af82",
+    signature: "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+                18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+};
+
+#[test]
+fn test_ed25519() {
+    for test in [ED25519_TEST_2, ED25519_TEST_3] {
+        let (key, message, signature) = (hex(test.key), hex(test.message), hex(test.signature));
+        assert_eq!(ed25519::verify(&key, &message, &signature), Ok(()));
+
+        // In parts.
+        let mut verifier = Verifier::new(&key, &signature).unwrap();
+        for part in message.chunks(1) {
+            verifier.update(part);
+        }
+        assert_eq!(verifier.finish(), Ok(()));
+    }
+
+    let test = ED25519_TEST_3;
+    let (key, message, signature) = (hex(test.key), hex(test.message), hex(test.signature));
+    let mut tampered = message.clone();
+    tampered[1] ^= 1;
+    assert_eq!(
+        ed25519::verify(&key, &tampered, &signature),
+        Err(AxError::InvalidData)
+    );
+    let mut tampered = signature.clone();
+    tampered[0] ^= 1;
+    assert!(ed25519::verify(&key, &message, &tampered).is_err());
+
+    // Signed by another key.
+    let other_key = hex(ED25519_TEST_2.key);
+    assert_eq!(
+        ed25519::verify(&other_key, &message, &signature),
+        Err(AxError::InvalidData)
+    );
+    assert_eq!(
+        ed25519::verify(&key, &message, &signature[..63]),
+        Err(AxError::InvalidInput)
+    );
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xcbf4_3926);
+}
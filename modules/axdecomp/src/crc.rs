@@ -0,0 +1,28 @@
+//! CRC-64 used by the xz format. Its CRC-32 is that of [`axcrypto::crc32`].
+
+/// The table of CRC-64 (ECMA-182) for each byte.
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xc96c_5795_d787_0f42
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc64(bytes: &[u8]) -> u64 {
+    !bytes.iter().fold(!0, |crc, &b| {
+        (crc >> 8) ^ CRC64_TABLE[((crc ^ b as u64) & 0xff) as usize]
+    })
+}
@@ -0,0 +1,52 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) decompression module.
+//!
+//! It decompresses data in memory, e.g., images embedded in the kernel, so
+//! that they take less space. Currently only the [xz] format is supported.
+//!
+//! The whole output is kept in memory, and serves as the dictionary while
+//! decompressing, so no memory is needed besides the output.
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod crc;
+mod lzma;
+
+pub mod xz;
+
+use alloc::vec::Vec;
+
+use axerrno::{AxResult, ax_err};
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The [xz] format, with the LZMA2 filter only.
+    Xz,
+}
+
+impl Format {
+    /// Detects the format of compressed data by its magic bytes.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(xz::MAGIC) {
+            Some(Self::Xz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decompresses data of a format detected by [`Format::detect`].
+///
+/// Returns [`AxError::Unsupported`](axerrno::AxError::Unsupported) if the
+/// format is unknown, or [`AxError::InvalidData`](axerrno::AxError::InvalidData)
+/// if the data is corrupted.
+pub fn decompress(data: &[u8]) -> AxResult<Vec<u8>> {
+    match Format::detect(data) {
+        Some(Format::Xz) => xz::decompress(data),
+        None => ax_err!(Unsupported, "unknown compression format"),
+    }
+}
@@ -0,0 +1,442 @@
+//! The LZMA2 decoder, writing to a flat output buffer which also serves as
+//! the dictionary.
+//!
+//! See the [LZMA specification](https://www.7-zip.org/a/lzma-specification.7z)
+//! and the LZMA2 part of the [xz format](https://tukaani.org/xz/xz-file-format.txt).
+
+use alloc::vec::Vec;
+
+use axerrno::{AxResult, ax_err};
+
+const NUM_STATES: usize = 12;
+const POS_STATES_MAX: usize = 1 << 4;
+const LITERAL_CODER_SIZE: usize = 0x300;
+/// The maximum of `lc + lp` in LZMA2.
+const LCLP_MAX: u32 = 4;
+
+const LEN_LOW_BITS: u32 = 3;
+const LEN_MID_BITS: u32 = 3;
+const LEN_HIGH_BITS: u32 = 8;
+const MATCH_LEN_MIN: usize = 2;
+
+const DIST_STATES: usize = 4;
+const DIST_SLOT_BITS: u32 = 6;
+const DIST_MODEL_START: u32 = 4;
+const DIST_MODEL_END: u32 = 14;
+const FULL_DISTANCES: usize = 1 << (DIST_MODEL_END / 2);
+const ALIGN_BITS: u32 = 4;
+
+/// The initial value of probabilities, i.e., 0.5.
+const PROB_INIT: u16 = 1 << 10;
+
+/// The range decoder, reading from the compressed data of one chunk.
+struct RangeDecoder<'a> {
+    input: &'a [u8],
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(input: &'a [u8]) -> AxResult<Self> {
+        if input.len() < 5 || input[0] != 0 {
+            return ax_err!(InvalidData, "xz: bad range coder data");
+        }
+        let code = u32::from_be_bytes(input[1..5].try_into().unwrap());
+        Ok(Self {
+            input: &input[5..],
+            range: u32::MAX,
+            code,
+        })
+    }
+
+    fn normalize(&mut self) {
+        if self.range < 1 << 24 {
+            // Reading beyond the chunk yields zeros, and is detected by
+            // `is_finished`.
+            let (&byte, rest) = self.input.split_first().unwrap_or((&0, &[]));
+            self.input = rest;
+            self.range <<= 8;
+            self.code = (self.code << 8) | byte as u32;
+        }
+    }
+
+    /// Whether all the input is consumed, after the last bit is decoded.
+    fn is_finished(&mut self) -> bool {
+        // The encoder flushes as if the range is normalized after the last
+        // bit, while the decoder only normalizes before each bit.
+        self.normalize();
+        self.input.is_empty() && self.code == 0
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> u32 {
+        self.normalize();
+        let bound = (self.range >> 11) * *prob as u32;
+        if self.code < bound {
+            self.range = bound;
+            *prob += ((1 << 11) - *prob) >> 5;
+            0
+        } else {
+            self.range -= bound;
+            self.code -= bound;
+            *prob -= *prob >> 5;
+            1
+        }
+    }
+
+    /// Decodes `num_bits` bits, with the most significant bit first.
+    fn bittree(&mut self, probs: &mut [u16], num_bits: u32) -> u32 {
+        let mut m = 1;
+        for _ in 0..num_bits {
+            m = (m << 1) | self.bit(&mut probs[m as usize]);
+        }
+        m - (1 << num_bits)
+    }
+
+    /// Decodes `num_bits` bits, with the least significant bit first.
+    fn reverse_bittree(&mut self, probs: &mut [u16], num_bits: u32) -> u32 {
+        let mut m = 1;
+        let mut symbol = 0;
+        for i in 0..num_bits {
+            let bit = self.bit(&mut probs[m as usize]);
+            m = (m << 1) | bit;
+            symbol |= bit << i;
+        }
+        symbol
+    }
+
+    /// Decodes `num_bits` bits with the fixed probability of 0.5.
+    fn direct_bits(&mut self, num_bits: u32) -> u32 {
+        let mut result = 0;
+        for _ in 0..num_bits {
+            self.normalize();
+            self.range >>= 1;
+            let bit = (self.code >= self.range) as u32;
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            result = (result << 1) | bit;
+        }
+        result
+    }
+}
+
+/// The probabilities of a length decoder.
+struct LenDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 1 << LEN_LOW_BITS]; POS_STATES_MAX],
+    mid: [[u16; 1 << LEN_MID_BITS]; POS_STATES_MAX],
+    high: [u16; 1 << LEN_HIGH_BITS],
+}
+
+impl LenDecoder {
+    const fn new() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 1 << LEN_LOW_BITS]; POS_STATES_MAX],
+            mid: [[PROB_INIT; 1 << LEN_MID_BITS]; POS_STATES_MAX],
+            high: [PROB_INIT; 1 << LEN_HIGH_BITS],
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> usize {
+        let len = if rc.bit(&mut self.choice) == 0 {
+            rc.bittree(&mut self.low[pos_state], LEN_LOW_BITS)
+        } else if rc.bit(&mut self.choice2) == 0 {
+            (1 << LEN_LOW_BITS) + rc.bittree(&mut self.mid[pos_state], LEN_MID_BITS)
+        } else {
+            (1 << LEN_LOW_BITS) + (1 << LEN_MID_BITS) + rc.bittree(&mut self.high, LEN_HIGH_BITS)
+        };
+        len as usize + MATCH_LEN_MIN
+    }
+}
+
+/// The state of the LZMA decoder, kept across the chunks of LZMA2.
+struct LzmaState {
+    lc: u32,
+    lp: u32,
+    pb: u32,
+    state: usize,
+    reps: [usize; 4],
+    literal: Vec<u16>,
+    is_match: [[u16; POS_STATES_MAX]; NUM_STATES],
+    is_rep: [u16; NUM_STATES],
+    is_rep0: [u16; NUM_STATES],
+    is_rep1: [u16; NUM_STATES],
+    is_rep2: [u16; NUM_STATES],
+    is_rep0_long: [[u16; POS_STATES_MAX]; NUM_STATES],
+    dist_slot: [[u16; 1 << DIST_SLOT_BITS]; DIST_STATES],
+    /// The reverse bit trees of the distances of slots 4 to 13, with one
+    /// more element as they overlap, and bit trees are indexed from 1.
+    dist_special: [u16; FULL_DISTANCES - DIST_MODEL_END as usize + 1],
+    dist_align: [u16; 1 << ALIGN_BITS],
+    match_len: LenDecoder,
+    rep_len: LenDecoder,
+}
+
+impl LzmaState {
+    fn new() -> Self {
+        Self {
+            lc: 0,
+            lp: 0,
+            pb: 0,
+            state: 0,
+            reps: [0; 4],
+            literal: Vec::new(),
+            is_match: [[PROB_INIT; POS_STATES_MAX]; NUM_STATES],
+            is_rep: [PROB_INIT; NUM_STATES],
+            is_rep0: [PROB_INIT; NUM_STATES],
+            is_rep1: [PROB_INIT; NUM_STATES],
+            is_rep2: [PROB_INIT; NUM_STATES],
+            is_rep0_long: [[PROB_INIT; POS_STATES_MAX]; NUM_STATES],
+            dist_slot: [[PROB_INIT; 1 << DIST_SLOT_BITS]; DIST_STATES],
+            dist_special: [PROB_INIT; FULL_DISTANCES - DIST_MODEL_END as usize + 1],
+            dist_align: [PROB_INIT; 1 << ALIGN_BITS],
+            match_len: LenDecoder::new(),
+            rep_len: LenDecoder::new(),
+        }
+    }
+
+    /// Sets `lc`, `lp` and `pb` from the properties byte.
+    fn set_props(&mut self, props: u8) -> AxResult {
+        let props = props as u32;
+        if props >= 9 * 5 * 5 {
+            return ax_err!(InvalidData, "xz: bad LZMA properties");
+        }
+        let (lc, lp, pb) = (props % 9, props / 9 % 5, props / 45);
+        if lc + lp > LCLP_MAX {
+            return ax_err!(InvalidData, "xz: bad LZMA properties");
+        }
+        (self.lc, self.lp, self.pb) = (lc, lp, pb);
+        Ok(())
+    }
+
+    /// Resets the state and the probabilities, keeping the properties.
+    fn reset(&mut self) {
+        let (lc, lp, pb) = (self.lc, self.lp, self.pb);
+        let mut literal = core::mem::take(&mut self.literal);
+        literal.clear();
+        literal.resize(LITERAL_CODER_SIZE << (lc + lp), PROB_INIT);
+        *self = Self {
+            lc,
+            lp,
+            pb,
+            literal,
+            ..Self::new()
+        };
+    }
+
+    fn update_literal(&mut self) {
+        self.state = match self.state {
+            0..=3 => 0,
+            4..=9 => self.state - 3,
+            _ => self.state - 6,
+        };
+    }
+
+    fn is_literal_state(&self) -> bool {
+        self.state < 7
+    }
+}
+
+/// Decodes LZMA2 data, appending the output to `out`.
+///
+/// `size_hint` is the uncompressed size if known, to allocate the output at
+/// once. Returns the number of input bytes consumed.
+pub fn decode_lzma2(input: &[u8], out: &mut Vec<u8>, size_hint: Option<usize>) -> AxResult<usize> {
+    if let Some(size) = size_hint {
+        out.reserve(size);
+    }
+    let mut lzma = LzmaState::new();
+    // The start of the dictionary in `out`, set by a dictionary reset.
+    let mut dict_start = None;
+    let mut has_props = false;
+    let mut pos = 0;
+    loop {
+        let Some(&control) = input.get(pos) else {
+            return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+        };
+        pos += 1;
+        match control {
+            0x00 => return Ok(pos),
+            0x01 | 0x02 => {
+                // An uncompressed chunk, resetting the dictionary if 1.
+                let Some(size) = input.get(pos..pos + 2) else {
+                    return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+                };
+                let size = u16::from_be_bytes([size[0], size[1]]) as usize + 1;
+                pos += 2;
+                let Some(data) = input.get(pos..pos + size) else {
+                    return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+                };
+                if control == 0x01 {
+                    dict_start = Some(out.len());
+                } else if dict_start.is_none() {
+                    return ax_err!(InvalidData, "xz: no dictionary reset");
+                }
+                out.extend_from_slice(data);
+                pos += size;
+            }
+            0x80.. => {
+                let Some(header) = input.get(pos..pos + 4) else {
+                    return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+                };
+                let unpacked = ((control as usize & 0x1f) << 16)
+                    + u16::from_be_bytes([header[0], header[1]]) as usize
+                    + 1;
+                let packed = u16::from_be_bytes([header[2], header[3]]) as usize + 1;
+                pos += 4;
+                let reset = (control >> 5) & 3;
+                if reset == 3 {
+                    dict_start = Some(out.len());
+                } else if dict_start.is_none() {
+                    return ax_err!(InvalidData, "xz: no dictionary reset");
+                }
+                if reset >= 2 {
+                    let Some(&props) = input.get(pos) else {
+                        return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+                    };
+                    lzma.set_props(props)?;
+                    has_props = true;
+                    pos += 1;
+                } else if !has_props {
+                    return ax_err!(InvalidData, "xz: no LZMA properties");
+                }
+                if reset >= 1 {
+                    lzma.reset();
+                }
+                let Some(data) = input.get(pos..pos + packed) else {
+                    return ax_err!(UnexpectedEof, "xz: truncated LZMA2 data");
+                };
+                let mut rc = RangeDecoder::new(data)?;
+                decode_chunk(&mut lzma, &mut rc, out, dict_start.unwrap(), unpacked)?;
+                if !rc.is_finished() {
+                    return ax_err!(InvalidData, "xz: bad LZMA2 chunk size");
+                }
+                pos += packed;
+            }
+            _ => return ax_err!(InvalidData, "xz: bad LZMA2 control byte"),
+        }
+    }
+}
+
+/// Decodes an LZMA chunk of `unpacked` bytes.
+fn decode_chunk(
+    s: &mut LzmaState,
+    rc: &mut RangeDecoder,
+    out: &mut Vec<u8>,
+    dict_start: usize,
+    unpacked: usize,
+) -> AxResult {
+    let end = out.len() + unpacked;
+    let pos_mask = (1 << s.pb) - 1;
+    while out.len() < end {
+        let pos = out.len() - dict_start;
+        let pos_state = pos & pos_mask;
+
+        if rc.bit(&mut s.is_match[s.state][pos_state]) == 0 {
+            let prev = if pos > 0 { out[out.len() - 1] } else { 0 };
+            let lit_state = ((pos & ((1 << s.lp) - 1)) << s.lc) + (prev as usize >> (8 - s.lc));
+            let after_match = !s.is_literal_state();
+            let rep0 = s.reps[0];
+            let probs = &mut s.literal[LITERAL_CODER_SIZE * lit_state..][..LITERAL_CODER_SIZE];
+            let mut symbol = 1;
+            if after_match {
+                // After a match, the byte at the last distance helps to
+                // predict the literal, until they differ.
+                if rep0 >= pos {
+                    return ax_err!(InvalidData, "xz: bad LZMA distance");
+                }
+                let mut match_byte = out[out.len() - rep0 - 1] as usize;
+                while symbol < 0x100 {
+                    let match_bit = (match_byte >> 7) & 1;
+                    match_byte <<= 1;
+                    let bit = rc.bit(&mut probs[0x100 + (match_bit << 8) + symbol]) as usize;
+                    symbol = (symbol << 1) | bit;
+                    if bit != match_bit {
+                        break;
+                    }
+                }
+            }
+            while symbol < 0x100 {
+                symbol = (symbol << 1) | rc.bit(&mut probs[symbol]) as usize;
+            }
+            out.push(symbol as u8);
+            s.update_literal();
+            continue;
+        }
+
+        let len = if rc.bit(&mut s.is_rep[s.state]) == 0 {
+            let len = s.match_len.decode(rc, pos_state);
+            s.state = if s.is_literal_state() { 7 } else { 10 };
+            s.reps = [decode_distance(s, rc, len), s.reps[0], s.reps[1], s.reps[2]];
+            len
+        } else {
+            if rc.bit(&mut s.is_rep0[s.state]) == 0 {
+                if rc.bit(&mut s.is_rep0_long[s.state][pos_state]) == 0 {
+                    // A "short rep" of one byte.
+                    s.state = if s.is_literal_state() { 9 } else { 11 };
+                    copy_match(out, pos, s.reps[0], 1)?;
+                    continue;
+                }
+            } else {
+                let dist = if rc.bit(&mut s.is_rep1[s.state]) == 0 {
+                    s.reps[1]
+                } else if rc.bit(&mut s.is_rep2[s.state]) == 0 {
+                    let dist = s.reps[2];
+                    s.reps[2] = s.reps[1];
+                    dist
+                } else {
+                    let dist = s.reps[3];
+                    s.reps[3] = s.reps[2];
+                    s.reps[2] = s.reps[1];
+                    dist
+                };
+                s.reps[1] = s.reps[0];
+                s.reps[0] = dist;
+            }
+            s.state = if s.is_literal_state() { 8 } else { 11 };
+            s.rep_len.decode(rc, pos_state)
+        };
+        if out.len() + len > end {
+            return ax_err!(InvalidData, "xz: LZMA match beyond the chunk");
+        }
+        copy_match(out, pos, s.reps[0], len)?;
+    }
+    Ok(())
+}
+
+/// Decodes the distance of a match of length `len`, minus one.
+fn decode_distance(s: &mut LzmaState, rc: &mut RangeDecoder, len: usize) -> usize {
+    let dist_state = (len - MATCH_LEN_MIN).min(DIST_STATES - 1);
+    let slot = rc.bittree(&mut s.dist_slot[dist_state], DIST_SLOT_BITS);
+    if slot < DIST_MODEL_START {
+        return slot as usize;
+    }
+    let num_direct = (slot >> 1) - 1;
+    let mut dist = (2 | (slot & 1)) << num_direct;
+    if slot < DIST_MODEL_END {
+        let probs = &mut s.dist_special[(dist - slot) as usize..];
+        dist += rc.reverse_bittree(probs, num_direct);
+    } else {
+        dist += rc.direct_bits(num_direct - ALIGN_BITS) << ALIGN_BITS;
+        dist += rc.reverse_bittree(&mut s.dist_align, ALIGN_BITS);
+    }
+    dist as usize
+}
+
+/// Copies `len` bytes from `dist + 1` bytes before the end of `out`, where
+/// `pos` bytes are in the dictionary.
+fn copy_match(out: &mut Vec<u8>, pos: usize, dist: usize, len: usize) -> AxResult {
+    if dist >= pos {
+        return ax_err!(InvalidData, "xz: bad LZMA distance");
+    }
+    let start = out.len() - dist - 1;
+    // The source and the destination overlap if `len > dist + 1`, so copy
+    // byte by byte.
+    for i in start..start + len {
+        out.push(out[i]);
+    }
+    Ok(())
+}
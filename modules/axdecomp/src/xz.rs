@@ -0,0 +1,217 @@
+//! The [xz format](https://tukaani.org/xz/xz-file-format.txt).
+//!
+//! Only the LZMA2 filter is supported, which is what `xz` uses by default,
+//! i.e., without BCJ or delta filters. Concatenated streams are supported.
+
+use alloc::vec::Vec;
+
+use axcrypto::crc32::crc32;
+use axerrno::{AxResult, ax_err};
+
+use crate::crc::crc64;
+use crate::lzma::decode_lzma2;
+
+/// The magic bytes of the stream header.
+pub const MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+const FOOTER_MAGIC: &[u8] = b"YZ";
+const HEADER_SIZE: usize = 12;
+const FILTER_LZMA2: u64 = 0x21;
+
+/// The integrity check of the blocks, in the stream flags.
+#[derive(Clone, Copy)]
+enum Check {
+    None,
+    Crc32,
+    Crc64,
+    /// Checks not verified, e.g., SHA-256, with the size.
+    Other(usize),
+}
+
+impl Check {
+    fn from_id(id: u8) -> AxResult<Self> {
+        Ok(match id {
+            0x00 => Self::None,
+            0x01 => Self::Crc32,
+            0x04 => Self::Crc64,
+            0x02..=0x0f => Self::Other(4 << ((id - 1) / 3)),
+            _ => return ax_err!(InvalidData, "xz: bad stream flags"),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Crc32 => 4,
+            Self::Crc64 => 8,
+            Self::Other(size) => size,
+        }
+    }
+
+    fn verify(self, data: &[u8], check: &[u8]) -> bool {
+        match self {
+            Self::Crc32 => crc32(data).to_le_bytes() == check,
+            Self::Crc64 => crc64(data).to_le_bytes() == check,
+            Self::None | Self::Other(_) => true,
+        }
+    }
+}
+
+/// A reader of the input, failing if it is too short.
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: usize) -> AxResult<&'a [u8]> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + len) else {
+            return ax_err!(UnexpectedEof, "xz: truncated data");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> AxResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Reads a variable-length integer (7 bits per byte).
+    fn varint(&mut self) -> AxResult<u64> {
+        let mut value = 0;
+        for i in 0..9 {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << (i * 7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        ax_err!(InvalidData, "xz: bad integer")
+    }
+
+    /// Skips to the next multiple of 4 bytes, which must be zeros.
+    fn padding(&mut self) -> AxResult {
+        let len = self.pos.next_multiple_of(4) - self.pos;
+        if self.bytes(len)?.iter().any(|&b| b != 0) {
+            return ax_err!(InvalidData, "xz: bad padding");
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses xz data.
+pub fn decompress(data: &[u8]) -> AxResult<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut input = Input { data, pos: 0 };
+    loop {
+        decode_stream(&mut input, &mut out)?;
+        // Streams may be followed by padding of multiples of 4 bytes, then
+        // by another stream.
+        while input.data[input.pos..].starts_with(&[0; 4]) {
+            input.pos += 4;
+        }
+        if input.pos == input.data.len() {
+            return Ok(out);
+        }
+    }
+}
+
+fn decode_stream(input: &mut Input, out: &mut Vec<u8>) -> AxResult {
+    let header = input.bytes(HEADER_SIZE)?;
+    if &header[..6] != MAGIC {
+        return ax_err!(InvalidData, "xz: bad magic");
+    }
+    let flags = &header[6..8];
+    if header[8..12] != crc32(flags).to_le_bytes() || flags[0] != 0 {
+        return ax_err!(InvalidData, "xz: bad stream header");
+    }
+    let check = Check::from_id(flags[1])?;
+
+    let mut num_blocks = 0;
+    loop {
+        let header_size = input.byte()?;
+        if header_size == 0 {
+            // The index, listing the sizes of the blocks.
+            break;
+        }
+        decode_block(input, (header_size as usize + 1) * 4, check, out)?;
+        num_blocks += 1;
+    }
+
+    // Only check the index is well-formed, as the sizes have been checked
+    // while decoding the blocks.
+    let index_start = input.pos - 1;
+    if input.varint()? != num_blocks {
+        return ax_err!(InvalidData, "xz: bad index");
+    }
+    for _ in 0..num_blocks * 2 {
+        input.varint()?;
+    }
+    input.padding()?;
+    let index = &input.data[index_start..input.pos];
+    if input.bytes(4)? != crc32(index).to_le_bytes() {
+        return ax_err!(InvalidData, "xz: bad index CRC");
+    }
+
+    let index_size = input.pos - index_start;
+    let footer = input.bytes(HEADER_SIZE)?;
+    let backward_size = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+    if footer[0..4] != crc32(&footer[4..10]).to_le_bytes()
+        || (backward_size as usize + 1) * 4 != index_size
+        || footer[8..10] != *flags
+        || &footer[10..] != FOOTER_MAGIC
+    {
+        return ax_err!(InvalidData, "xz: bad stream footer");
+    }
+    Ok(())
+}
+
+fn decode_block(
+    input: &mut Input,
+    header_size: usize,
+    check: Check,
+    out: &mut Vec<u8>,
+) -> AxResult {
+    let start = input.pos - 1;
+    let flags = input.byte()?;
+    if flags & 0x3c != 0 {
+        return ax_err!(Unsupported, "xz: unsupported block flags");
+    }
+    let compressed_size = (flags & 0x40 != 0).then(|| input.varint()).transpose()?;
+    let uncompressed_size = (flags & 0x80 != 0).then(|| input.varint()).transpose()?;
+    let num_filters = (flags & 3) + 1;
+    if num_filters != 1 || input.varint()? != FILTER_LZMA2 {
+        return ax_err!(Unsupported, "xz: only the LZMA2 filter is supported");
+    }
+    // The dictionary size is not needed, as the whole output is kept.
+    if input.varint()? != 1 || input.byte()? > 40 {
+        return ax_err!(InvalidData, "xz: bad LZMA2 properties");
+    }
+    let Some(header_end) = (start + header_size).checked_sub(4) else {
+        return ax_err!(InvalidData, "xz: bad block header");
+    };
+    if input.pos > header_end {
+        return ax_err!(InvalidData, "xz: bad block header");
+    }
+    if input.bytes(header_end - input.pos)?.iter().any(|&b| b != 0) {
+        return ax_err!(InvalidData, "xz: bad block header");
+    }
+    if input.bytes(4)? != crc32(&input.data[start..header_end]).to_le_bytes() {
+        return ax_err!(InvalidData, "xz: bad block header CRC");
+    }
+
+    let out_start = out.len();
+    let size_hint = uncompressed_size.map(|size| size as usize);
+    let data = &input.data[input.pos..];
+    let used = decode_lzma2(data, out, size_hint)?;
+    input.pos += used;
+    if compressed_size.is_some_and(|size| size != used as u64)
+        || uncompressed_size.is_some_and(|size| size != (out.len() - out_start) as u64)
+    {
+        return ax_err!(InvalidData, "xz: bad block size");
+    }
+    input.padding()?;
+    if !check.verify(&out[out_start..], input.bytes(check.size())?) {
+        return ax_err!(InvalidData, "xz: checksum mismatch");
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use axdecomp::Format;
+use axerrno::AxError;
+
+const TEXT: &[u8] = b"Hello, ArceOS! Hello, ArceOS! Hello, ArceOS!\n";
+
+/// `TEXT` compressed by `xz --check=crc32`.
+const TEXT_XZ: &[u8] = &[
+    0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x01, 0x69, 0x22, 0xde, 0x36, //
+    0x04, 0xc0, 0x1f, 0x2d, 0x21, 0x01, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0xfd, 0x96, 0x28, 0x10, 0xe0, 0x00, 0x2c, 0x00, //
+    0x17, 0x5d, 0x00, 0x24, 0x19, 0x49, 0x98, 0x6f, 0x16, 0x02, 0x86, 0x62, //
+    0x20, 0x93, 0x75, 0x19, 0xef, 0x15, 0x3a, 0xde, 0x58, 0x50, 0xce, 0x47, //
+    0x40, 0x00, 0x00, 0x00, 0xc7, 0x2e, 0xa3, 0xcd, 0x00, 0x01, 0x37, 0x2d, //
+    0x6a, 0x49, 0x5e, 0xf5, 0x90, 0x42, 0x99, 0x0d, 0x01, 0x00, 0x00, 0x00, //
+    0x00, 0x01, 0x59, 0x5a,
+];
+
+#[test]
+fn test_xz() {
+    assert_eq!(Format::detect(TEXT_XZ), Some(Format::Xz));
+    assert_eq!(axdecomp::decompress(TEXT_XZ).unwrap(), TEXT);
+
+    // Concatenated streams, with stream padding between.
+    let mut data = TEXT_XZ.to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(TEXT_XZ);
+    assert_eq!(axdecomp::decompress(&data).unwrap(), TEXT.repeat(2));
+}
+
+#[test]
+fn test_xz_corrupted() {
+    assert_eq!(Format::detect(TEXT), None);
+    assert_eq!(axdecomp::decompress(TEXT), Err(AxError::Unsupported));
+
+    let truncated = &TEXT_XZ[..TEXT_XZ.len() - 20];
+    assert_eq!(axdecomp::decompress(truncated), Err(AxError::UnexpectedEof));
+
+    for pos in [8, 20, 45, 70] {
+        let mut data = TEXT_XZ.to_vec();
+        data[pos] ^= 0x10;
+        assert_eq!(axdecomp::decompress(&data), Err(AxError::InvalidData));
+    }
+}
@@ -10,6 +10,29 @@ fn make_cfg_values(str_list: &[&str]) -> String {
         .join(", ")
 }
 
+/// Copies the file at the path in the environment variable `var` to
+/// `OUT_DIR`, to be embedded. An empty file is written if it is not set.
+fn embed_file(var: &str, name: &str) {
+    println!("cargo:rerun-if-env-changed={var}");
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let out_path = std::path::Path::new(&out_dir).join(name);
+    match std::env::var(var) {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={path}");
+            std::fs::copy(path, out_path).unwrap();
+        }
+        _ => std::fs::write(out_path, "").unwrap(),
+    }
+}
+
+/// Copies the image of the initramfs, and its signature, to `OUT_DIR`.
+fn gen_initramfs() {
+    embed_file("AX_INITRAMFS", "initramfs.img");
+    if has_feature("verify") {
+        embed_file("AX_INITRAMFS_SIG", "initramfs.sig");
+    }
+}
+
 fn has_feature(feature: &str) -> bool {
     std::env::var(format!(
         "CARGO_FEATURE_{}",
@@ -23,6 +46,10 @@ fn enable_cfg(key: &str, value: &str) {
 }
 
 fn main() {
+    if has_feature("initramfs") {
+        gen_initramfs();
+    }
+
     if has_feature("bus-mmio") {
         enable_cfg("bus", "mmio");
     } else {
@@ -18,6 +18,14 @@ impl AllDevices {
                     continue; // skip to the next device
                 }
             });
+            #[cfg(feature = "virtio-balloon")]
+            if crate::virtio_balloon::probe_mmio(reg.0) {
+                continue;
+            }
+            #[cfg(feature = "virtio-console")]
+            if crate::virtio_console::probe_mmio(reg.0) {
+                continue;
+            }
         }
     }
 }
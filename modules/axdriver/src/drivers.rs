@@ -57,12 +57,18 @@ cfg_if::cfg_if! {
         register_block_driver!(RamDiskDriver, axdriver_block::ramdisk::RamDisk);
 
         impl DriverProbe for RamDiskDriver {
+            #[cfg(not(feature = "initramfs"))]
             fn probe_global() -> Option<AxDeviceEnum> {
                 // TODO: format RAM disk
                 Some(AxDeviceEnum::from_block(
                     axdriver_block::ramdisk::RamDisk::new(0x100_0000), // 16 MiB
                 ))
             }
+
+            #[cfg(feature = "initramfs")]
+            fn probe_global() -> Option<AxDeviceEnum> {
+                crate::initramfs::load().map(AxDeviceEnum::from_block)
+            }
         }
     }
 }
@@ -87,6 +93,7 @@ cfg_if::cfg_if! {
         use axhal::mem::phys_to_virt;
         pub struct IxgbeDriver;
         register_net_driver!(IxgbeDriver, axdriver_net::ixgbe::IxgbeNic<IxgbeHalImpl, 1024, 1>);
+        impl crate::net_ext::NetDriverExtOps for axdriver_net::ixgbe::IxgbeNic<IxgbeHalImpl, 1024, 1> {}
         impl DriverProbe for IxgbeDriver {
             #[cfg(bus = "pci")]
             fn probe_pci(
@@ -164,6 +171,7 @@ cfg_if::cfg_if! {
         }
 
         register_net_driver!(FXmacDriver, axdriver_net::fxmac::FXmacNic);
+        impl crate::net_ext::NetDriverExtOps for axdriver_net::fxmac::FXmacNic {}
 
         pub struct FXmacDriver;
         impl DriverProbe for FXmacDriver {
@@ -31,6 +31,8 @@ cfg_if! {
             fn receive(&mut self) -> DevResult<NetBufPtr> { Err(DevError::Unsupported) }
             fn alloc_tx_buffer(&mut self, _: usize) -> DevResult<NetBufPtr> { Err(DevError::Unsupported) }
         }
+
+        impl NetDriverExtOps for DummyNetDev {}
     }
 }
 
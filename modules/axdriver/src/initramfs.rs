@@ -0,0 +1,43 @@
+//! The RAM disk initialized with an image embedded at build time.
+
+use axdriver_block::ramdisk::RamDisk;
+
+/// The image at the path in `AX_INITRAMFS` at build time, or empty.
+static IMAGE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.img"));
+
+/// The signature of the image at the path in `AX_INITRAMFS_SIG` at build
+/// time, or empty.
+#[cfg(feature = "verify")]
+static SIGNATURE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.sig"));
+
+/// Creates the RAM disk with the embedded image, decompressing it if needed.
+pub fn load() -> Option<RamDisk> {
+    if IMAGE.is_empty() {
+        error!("no initramfs image embedded, set `AX_INITRAMFS` at build time");
+        return None;
+    }
+    // The image is verified as it is embedded, before it is decompressed.
+    #[cfg(feature = "verify")]
+    if let Err(e) = axverify::verify(IMAGE, SIGNATURE) {
+        panic!("refusing the initramfs image, bad signature: {:?}", e);
+    }
+    let Some(format) = axdecomp::Format::detect(IMAGE) else {
+        info!("initramfs: {} bytes", IMAGE.len());
+        return Some(RamDisk::from(IMAGE));
+    };
+    match axdecomp::decompress(IMAGE) {
+        Ok(data) => {
+            info!(
+                "initramfs: {} bytes, decompressed from {} bytes ({:?})",
+                data.len(),
+                IMAGE.len(),
+                format
+            );
+            Some(RamDisk::from(&data[..]))
+        }
+        Err(e) => {
+            error!("failed to decompress the initramfs image: {:?}", e);
+            None
+        }
+    }
+}
@@ -21,7 +21,7 @@
 //!   model provides the best performance as it avoids dynamic dispatch. But on
 //!   limitation, only one device instance is supported for each device category.
 //! - **Dynamic**: All device instance is using [trait objects] and wrapped in a
-//!   `Box<dyn Trait>`. For example, [`AxNetDevice`] will be [`Box<dyn NetDriverOps>`].
+//!   `Box<dyn Trait>`. For example, [`AxNetDevice`] will be [`Box<dyn NetDriverExtOps>`].
 //!   When call a method provided by the device, it uses [dynamic dispatch][dyn]
 //!   that may introduce a little overhead. But on the other hand, it is more
 //!   flexible, multiple instances of each device category are supported.
@@ -31,9 +31,12 @@
 //! | Device Category | Cargo Feature | Description |
 //! |-|-|-|
 //! | Block | `ramdisk` | A RAM disk that stores data in a vector |
+//! | Block | `initramfs` | A RAM disk with an image embedded at build time, which may be compressed |
 //! | Block | `virtio-blk` | VirtIO block device |
 //! | Network | `virtio-net` | VirtIO network device |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
+//! | Memory | `virtio-balloon` | VirtIO memory balloon device (see [`virtio_balloon`]) |
+//! | Console | `virtio-console` | VirtIO console device (see [`virtio_console`]) |
 //!
 //! # Other Cargo Features
 //!
@@ -42,15 +45,25 @@
 //! - `bus-pci`: use PCI bus to probe all PCI devices. This feature is
 //!   enabled by default.
 //! - `virtio`: use VirtIO devices. This is enabled if any of `virtio-blk`,
-//!   `virtio-net` or `virtio-gpu` is enabled.
+//!   `virtio-net`, `virtio-gpu`, `virtio-balloon` or `virtio-console` is
+//!   enabled.
 //! - `net`: use network devices. This is enabled if any feature of network
 //!   devices is selected. If this feature is enabled without any network device
 //!   features, a dummy struct is used for [`AxNetDevice`].
 //! - `block`: use block storage devices. Similar to the `net` feature.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `initramfs`: initialize the RAM disk with the image at the path in the
+//!   `AX_INITRAMFS` environment variable at build time, instead of leaving it
+//!   empty. The image is decompressed at boot if it is compressed (see
+//!   [`axdecomp`]), so it takes less space in the kernel image.
+//! - `verify`: with `initramfs`, refuse to boot unless the image is signed by
+//!   the trusted key (see [`axverify`]). The signature is embedded from the
+//!   path in `AX_INITRAMFS_SIG` at build time.
+//! - `virtio-mock`: provide a mock VirtIO transport in [`virtio_mock`], so
+//!   VirtIO drivers can be tested on the host.
 //!
 //! [`VirtioNetDev`]: axdriver_virtio::VirtIoNetDev
-//! [`Box<dyn NetDriverOps>`]: axdriver_net::NetDriverOps
+//! [`Box<dyn NetDriverExtOps>`]: net_ext::NetDriverExtOps
 //! [trait objects]: https://doc.rust-lang.org/book/ch17-02-trait-objects.html
 //! [dyn]: https://doc.rust-lang.org/std/keyword.dyn.html
 
@@ -61,7 +74,12 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
+#[cfg(any(
+    feature = "dyn",
+    feature = "initramfs",
+    feature = "virtio-mock",
+    feature = "virtio-balloon"
+))]
 extern crate alloc;
 
 #[macro_use]
@@ -75,9 +93,21 @@ mod structs;
 #[cfg(feature = "virtio")]
 mod virtio;
 
+#[cfg(feature = "virtio-balloon")]
+pub mod virtio_balloon;
+#[cfg(feature = "virtio-console")]
+pub mod virtio_console;
+#[cfg(feature = "virtio-mock")]
+pub mod virtio_mock;
+
+#[cfg(feature = "initramfs")]
+mod initramfs;
 #[cfg(feature = "ixgbe")]
 mod ixgbe;
 
+#[cfg(feature = "net")]
+pub mod net_ext;
+
 pub mod prelude;
 
 #[allow(unused_imports)]
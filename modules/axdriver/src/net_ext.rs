@@ -0,0 +1,48 @@
+//! Extended operations of NIC drivers: jumbo frames and scatter-gather TX.
+
+use axdriver_base::{DevError, DevResult};
+use axdriver_net::NetDriverOps;
+
+/// The standard Ethernet MTU, in bytes.
+pub const STANDARD_MTU: usize = 1500;
+
+/// The minimum MTU required by IPv4, in bytes.
+pub const MIN_MTU: usize = 68;
+
+/// Operations of NIC drivers beyond [`NetDriverOps`].
+///
+/// The default implementations suit devices that only handle standard
+/// frames, with one descriptor per packet.
+pub trait NetDriverExtOps: NetDriverOps {
+    /// The maximum MTU supported by the device, in bytes.
+    ///
+    /// Like all MTUs here, it excludes the Ethernet header.
+    fn max_mtu(&self) -> usize {
+        STANDARD_MTU
+    }
+
+    /// Configures the device to send and receive frames of the given MTU.
+    fn set_mtu(&mut self, mtu: usize) -> DevResult {
+        if (MIN_MTU..=self.max_mtu()).contains(&mtu) {
+            Ok(())
+        } else {
+            Err(DevError::InvalidParam)
+        }
+    }
+
+    /// Transmits a packet made of multiple segments.
+    ///
+    /// Devices supporting multiple descriptors per packet can pass the
+    /// segments to the hardware directly. By default, the segments are
+    /// gathered into one TX buffer.
+    fn transmit_sg(&mut self, segments: &[&[u8]]) -> DevResult {
+        let len = segments.iter().map(|seg| seg.len()).sum();
+        let mut tx_buf = self.alloc_tx_buffer(len)?;
+        let mut offset = 0;
+        for seg in segments {
+            tx_buf.packet_mut()[offset..offset + seg.len()].copy_from_slice(seg);
+            offset += seg.len();
+        }
+        self.transmit(tx_buf)
+    }
+}
@@ -12,9 +12,9 @@ use crate::{AxDeviceEnum, drivers::DriverProbe};
 cfg_if! {
     if #[cfg(bus = "pci")] {
         use axdriver_pci::{PciRoot, DeviceFunction, DeviceFunctionInfo};
-        type VirtIoTransport = axdriver_virtio::PciTransport;
+        pub(crate) type VirtIoTransport = axdriver_virtio::PciTransport;
     } else if #[cfg(bus =  "mmio")] {
-        type VirtIoTransport = axdriver_virtio::MmioTransport;
+        pub(crate) type VirtIoTransport = axdriver_virtio::MmioTransport;
     }
 }
 
@@ -40,6 +40,12 @@ cfg_if! {
                 Ok(AxDeviceEnum::from_net(Self::Device::try_new(transport)?))
             }
         }
+
+        // The RX buffers of the device only fit standard frames.
+        impl crate::net_ext::NetDriverExtOps
+            for axdriver_virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>
+        {
+        }
     }
 }
 
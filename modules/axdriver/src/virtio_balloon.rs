@@ -0,0 +1,416 @@
+//! VirtIO memory balloon device.
+//!
+//! The host sets a target number of pages in the device configuration, and
+//! the driver moves toward it when [`update`] is called: inflating the
+//! balloon takes free pages from the global page allocator and returns them
+//! to the host, deflating gives them back to the allocator.
+//!
+//! Inflation never takes the number of available pages below the high
+//! watermark of the allocator, twice [`LOW_WATERMARK`]. If the
+//! `VIRTIO_BALLOON_F_DEFLATE_ON_OOM` feature is negotiated (e.g.,
+//! `-device virtio-balloon,deflate-on-oom=on` in QEMU), the balloon is also
+//! deflated by the allocator's reclaim handler when memory runs low: when a
+//! page allocation fails, or back to the high watermark by
+//! [`axalloc::GlobalAllocator::reclaim`] once the available pages dropped
+//! below [`LOW_WATERMARK`].
+
+use alloc::vec::Vec;
+use core::ptr::{NonNull, addr_of, addr_of_mut};
+use core::sync::atomic::{Ordering, fence};
+
+use axalloc::{PAGE_SIZE, global_allocator};
+use axdriver_base::{DevError, DevResult};
+use axdriver_virtio::{BufferDirection, Transport, VirtIoHal};
+use axhal::mem::virt_to_phys;
+use kspin::SpinNoIrq;
+use virtio_drivers::transport::{DeviceStatus, DeviceType};
+
+/// The low watermark of available pages set to the allocator.
+pub const LOW_WATERMARK: usize = 256;
+
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u64 = 1 << 2;
+const SUPPORTED_FEATURES: u64 = VIRTIO_F_VERSION_1 | VIRTIO_BALLOON_F_DEFLATE_ON_OOM;
+
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+const QUEUE_SIZE: u16 = 8;
+
+/// Page frame numbers are always in units of 4K, regardless of the page size.
+const PFN_SHIFT: usize = 12;
+/// The maximum number of page frame numbers in one request.
+const MAX_PFNS: usize = 256;
+
+#[repr(C)]
+struct BalloonConfig {
+    num_pages: u32,
+    actual: u32,
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A split virtqueue with only one request in flight.
+///
+/// The queue takes two DMA pages: the descriptor table and the available
+/// ring are in the first, the used ring is in the second, which is also the
+/// layout required by legacy devices.
+struct VirtQueue<H: VirtIoHal> {
+    idx: u16,
+    paddr: usize,
+    vaddr: NonNull<u8>,
+    avail_idx: u16,
+    _hal: core::marker::PhantomData<H>,
+}
+
+impl<H: VirtIoHal> VirtQueue<H> {
+    const PAGES: usize = 2;
+
+    fn new<T: Transport>(transport: &mut T, idx: u16) -> DevResult<Self> {
+        if transport.queue_used(idx) {
+            return Err(DevError::AlreadyExists);
+        }
+        if transport.max_queue_size(idx) < QUEUE_SIZE as u32 {
+            return Err(DevError::Unsupported);
+        }
+        let (paddr, vaddr) = H::dma_alloc(Self::PAGES, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        unsafe { vaddr.as_ptr().write_bytes(0, Self::PAGES * PAGE_SIZE) };
+        let avail_offset = size_of::<Descriptor>() * QUEUE_SIZE as usize;
+        transport.queue_set(
+            idx,
+            QUEUE_SIZE as u32,
+            paddr,
+            paddr + avail_offset,
+            paddr + PAGE_SIZE,
+        );
+        Ok(Self {
+            idx,
+            paddr,
+            vaddr,
+            avail_idx: 0,
+            _hal: core::marker::PhantomData,
+        })
+    }
+
+    fn avail_ring(&self) -> *mut u16 {
+        let offset = size_of::<Descriptor>() * QUEUE_SIZE as usize;
+        unsafe { self.vaddr.as_ptr().add(offset) as *mut u16 }
+    }
+
+    fn used_idx(&self) -> u16 {
+        let used_ring = unsafe { self.vaddr.as_ptr().add(PAGE_SIZE) as *const u16 };
+        unsafe { used_ring.add(1).read_volatile() }
+    }
+
+    /// Sends a device-readable buffer and waits for the device to use it.
+    fn send<T: Transport>(&mut self, transport: &mut T, paddr: usize, len: usize) {
+        let desc = self.vaddr.as_ptr() as *mut Descriptor;
+        let avail = self.avail_ring();
+        unsafe {
+            desc.write_volatile(Descriptor {
+                addr: paddr as u64,
+                len: len as u32,
+                flags: 0,
+                next: 0,
+            });
+            // avail.ring[avail_idx % size] = 0
+            avail
+                .add(2 + (self.avail_idx % QUEUE_SIZE) as usize)
+                .write_volatile(0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            avail.add(1).write_volatile(self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+        transport.notify(self.idx);
+        while self.used_idx() != self.avail_idx {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    fn destroy<T: Transport>(&self, transport: &mut T) {
+        transport.queue_unset(self.idx);
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, Self::PAGES) };
+    }
+}
+
+/// The VirtIO memory balloon device driver.
+pub struct VirtIoBalloonDev<H: VirtIoHal, T: Transport> {
+    transport: T,
+    inflate_queue: VirtQueue<H>,
+    deflate_queue: VirtQueue<H>,
+    config: NonNull<BalloonConfig>,
+    features: u64,
+    pfns_paddr: usize,
+    pfns: NonNull<u32>,
+    pages: Vec<usize>,
+}
+
+// SAFETY: the device registers and DMA buffers are only accessed through
+// `&mut self`.
+unsafe impl<H: VirtIoHal, T: Transport> Send for VirtIoBalloonDev<H, T> {}
+
+impl<H: VirtIoHal, T: Transport> VirtIoBalloonDev<H, T> {
+    /// Creates a new driver instance and initializes the device.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & SUPPORTED_FEATURES;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        if transport.requires_legacy_layout() {
+            transport.set_guest_page_size(PAGE_SIZE as u32);
+        }
+
+        let config = transport
+            .config_space::<BalloonConfig>()
+            .map_err(|_| DevError::Unsupported)?;
+        let inflate_queue = VirtQueue::new(&mut transport, INFLATE_QUEUE)?;
+        let deflate_queue = match VirtQueue::new(&mut transport, DEFLATE_QUEUE) {
+            Ok(queue) => queue,
+            Err(e) => {
+                inflate_queue.destroy(&mut transport);
+                return Err(e);
+            }
+        };
+        let (pfns_paddr, pfns) = H::dma_alloc(1, BufferDirection::DriverToDevice);
+        if pfns_paddr == 0 {
+            inflate_queue.destroy(&mut transport);
+            deflate_queue.destroy(&mut transport);
+            return Err(DevError::NoMemory);
+        }
+
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE
+                | DeviceStatus::DRIVER
+                | DeviceStatus::FEATURES_OK
+                | DeviceStatus::DRIVER_OK,
+        );
+        let mut dev = Self {
+            transport,
+            inflate_queue,
+            deflate_queue,
+            config,
+            features,
+            pfns_paddr,
+            pfns: pfns.cast(),
+            pages: Vec::new(),
+        };
+        dev.write_actual();
+        Ok(dev)
+    }
+
+    /// Returns the number of pages the host wants in the balloon.
+    pub fn target_pages(&self) -> usize {
+        unsafe { addr_of!((*self.config.as_ptr()).num_pages).read_volatile() as usize }
+    }
+
+    /// Returns the number of pages in the balloon.
+    pub fn actual_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Whether the balloon may be deflated when memory runs low.
+    pub fn deflate_on_oom(&self) -> bool {
+        self.features & VIRTIO_BALLOON_F_DEFLATE_ON_OOM != 0
+    }
+
+    fn write_actual(&mut self) {
+        let actual = self.pages.len() as u32;
+        unsafe { addr_of_mut!((*self.config.as_ptr()).actual).write_volatile(actual) };
+    }
+
+    fn set_pfn(&mut self, i: usize, vaddr: usize) {
+        let pfn = virt_to_phys(vaddr.into()).as_usize() >> PFN_SHIFT;
+        unsafe { self.pfns.as_ptr().add(i).write_volatile(pfn as u32) };
+    }
+
+    /// Takes at most `num_pages` free pages from the global allocator and
+    /// gives them to the host.
+    ///
+    /// Returns the number of pages added to the balloon.
+    pub fn inflate(&mut self, num_pages: usize) -> usize {
+        let allocator = global_allocator();
+        let mut inflated = 0;
+        while inflated < num_pages {
+            let batch = (num_pages - inflated).min(MAX_PFNS);
+            let mut count = 0;
+            while count < batch && allocator.available_pages() > allocator.high_watermark() {
+                let Ok(vaddr) = allocator.alloc_pages(1, PAGE_SIZE) else {
+                    break;
+                };
+                self.set_pfn(count, vaddr);
+                self.pages.push(vaddr);
+                count += 1;
+            }
+            if count == 0 {
+                break;
+            }
+            self.inflate_queue.send(
+                &mut self.transport,
+                self.pfns_paddr,
+                count * size_of::<u32>(),
+            );
+            inflated += count;
+            if count < batch {
+                break;
+            }
+        }
+        self.write_actual();
+        inflated
+    }
+
+    /// Takes at most `num_pages` pages back from the host and gives them to
+    /// the global allocator.
+    ///
+    /// Returns the number of pages removed from the balloon. It does not
+    /// allocate memory, so it can be called by the reclaim handler.
+    pub fn deflate(&mut self, num_pages: usize) -> usize {
+        let allocator = global_allocator();
+        let mut deflated = 0;
+        while deflated < num_pages && !self.pages.is_empty() {
+            let count = (num_pages - deflated).min(MAX_PFNS).min(self.pages.len());
+            let start = self.pages.len() - count;
+            for i in 0..count {
+                self.set_pfn(i, self.pages[start + i]);
+            }
+            self.deflate_queue.send(
+                &mut self.transport,
+                self.pfns_paddr,
+                count * size_of::<u32>(),
+            );
+            for vaddr in self.pages.drain(start..) {
+                allocator.dealloc_pages(vaddr, 1);
+            }
+            deflated += count;
+        }
+        self.write_actual();
+        deflated
+    }
+
+    /// Inflates or deflates the balloon toward the host's target.
+    pub fn update(&mut self) {
+        let target = self.target_pages();
+        let actual = self.actual_pages();
+        if target > actual {
+            let n = self.inflate(target - actual);
+            debug!("virtio-balloon: inflated {} pages, target {}", n, target);
+        } else if target < actual {
+            let n = self.deflate(actual - target);
+            debug!("virtio-balloon: deflated {} pages, target {}", n, target);
+        }
+    }
+}
+
+impl<H: VirtIoHal, T: Transport> Drop for VirtIoBalloonDev<H, T> {
+    fn drop(&mut self) {
+        self.deflate(self.pages.len());
+        self.transport.set_status(DeviceStatus::empty());
+        self.inflate_queue.destroy(&mut self.transport);
+        self.deflate_queue.destroy(&mut self.transport);
+        unsafe { H::dma_dealloc(self.pfns_paddr, self.pfns.cast(), 1) };
+    }
+}
+
+type BalloonDevice = VirtIoBalloonDev<crate::virtio::VirtIoHalImpl, crate::virtio::VirtIoTransport>;
+
+static BALLOON: SpinNoIrq<Option<BalloonDevice>> = SpinNoIrq::new(None);
+
+/// The reclaim handler registered to the global allocator.
+fn reclaim(num_pages: usize) -> usize {
+    // Give up if the balloon is being inflated or deflated.
+    let Some(mut balloon) = BALLOON.try_lock() else {
+        return 0;
+    };
+    match balloon.as_mut() {
+        Some(dev) if dev.deflate_on_oom() => dev.deflate(num_pages),
+        _ => 0,
+    }
+}
+
+fn register(dev: BalloonDevice) {
+    info!(
+        "registered a new VirtIO balloon device, target {} pages",
+        dev.target_pages()
+    );
+    *BALLOON.lock() = Some(dev);
+    global_allocator().set_reclaim_handler(LOW_WATERMARK, reclaim);
+}
+
+/// Returns whether a balloon device is present.
+pub fn is_present() -> bool {
+    BALLOON.lock().is_some()
+}
+
+/// Returns the number of pages in the balloon.
+pub fn actual_pages() -> usize {
+    BALLOON.lock().as_ref().map_or(0, |dev| dev.actual_pages())
+}
+
+/// Inflates or deflates the balloon toward the host's target.
+///
+/// It should be called periodically, or when the device configuration is
+/// changed.
+pub fn update() {
+    if let Some(dev) = BALLOON.lock().as_mut() {
+        dev.update();
+    }
+}
+
+#[cfg(bus = "mmio")]
+pub(crate) fn probe_mmio(mmio_base: usize) -> bool {
+    use virtio_drivers::transport::mmio::{MmioTransport, VirtIOHeader};
+
+    let base_vaddr = axhal::mem::phys_to_virt(mmio_base.into());
+    let Some(header) = NonNull::new(base_vaddr.as_mut_ptr() as *mut VirtIOHeader) else {
+        return false;
+    };
+    let transport = match unsafe { MmioTransport::new(header) } {
+        Ok(transport) if transport.device_type() == DeviceType::MemoryBalloon => transport,
+        _ => return false,
+    };
+    match VirtIoBalloonDev::try_new(transport) {
+        Ok(dev) => register(dev),
+        Err(e) => warn!(
+            "failed to initialize VirtIO balloon device at PA:{:#x}: {:?}",
+            mmio_base, e
+        ),
+    }
+    true
+}
+
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    use axdriver_virtio::PciTransport;
+
+    if dev_info.vendor_id != 0x1af4 || !matches!(dev_info.device_id, 0x1002 | 0x1045) {
+        return false;
+    }
+    let transport = match PciTransport::new::<crate::virtio::VirtIoHalImpl>(root, bdf) {
+        Ok(transport) if transport.device_type() == DeviceType::MemoryBalloon => transport,
+        _ => return false,
+    };
+    match VirtIoBalloonDev::try_new(transport) {
+        Ok(dev) => register(dev),
+        Err(e) => warn!(
+            "failed to initialize VirtIO balloon device at {}({}): {:?}",
+            bdf, dev_info, e
+        ),
+    }
+    true
+}
@@ -0,0 +1,194 @@
+//! VirtIO console devices used as byte channels to the host.
+//!
+//! Up to [`MAX_DEVICES`] devices are supported, and only the first port of
+//! each device is used. The functions without a device index use the first
+//! device. In QEMU, a device can be connected to a host socket with:
+//!
+//! ```text
+//! -chardev socket,path=/tmp/arceos.sock,server=on,wait=off,id=vcon0
+//! -device virtio-serial-pci -device virtconsole,chardev=vcon0
+//! ```
+//!
+//! Each device can also serve as a console port of [`axhal::console`], see
+//! [`console_port`].
+
+use axhal::console::ConsoleDriver;
+use kspin::SpinNoIrq;
+use virtio_drivers::device::console::VirtIOConsole;
+use virtio_drivers::transport::{DeviceType, Transport};
+
+type ConsoleDevice = VirtIOConsole<crate::virtio::VirtIoHalImpl, crate::virtio::VirtIoTransport>;
+
+struct Console(ConsoleDevice);
+
+// SAFETY: the device is only accessed with `CONSOLE` locked.
+unsafe impl Send for Console {}
+
+/// The maximum number of console devices.
+pub const MAX_DEVICES: usize = 4;
+
+static CONSOLES: [SpinNoIrq<Option<Console>>; MAX_DEVICES] =
+    [const { SpinNoIrq::new(None) }; MAX_DEVICES];
+
+/// Returns whether a console device is present.
+pub fn is_present() -> bool {
+    num_devices() > 0
+}
+
+/// Returns the number of console devices.
+pub fn num_devices() -> usize {
+    CONSOLES.iter().take_while(|c| c.lock().is_some()).count()
+}
+
+/// Writes bytes to the first console device.
+///
+/// The bytes are dropped if no device is present.
+pub fn write_bytes(bytes: &[u8]) {
+    write_bytes_to(0, bytes);
+}
+
+/// Reads the received bytes of the first console device into `buf` without
+/// blocking.
+///
+/// Returns the number of bytes read.
+pub fn read_bytes(buf: &mut [u8]) -> usize {
+    read_bytes_from(0, buf)
+}
+
+/// Writes bytes to the `idx`-th console device.
+///
+/// The bytes are dropped if the device is not present.
+pub fn write_bytes_to(idx: usize, bytes: &[u8]) {
+    let Some(console) = CONSOLES.get(idx) else {
+        return;
+    };
+    if let Some(Console(dev)) = console.lock().as_mut() {
+        for &b in bytes {
+            if let Err(e) = dev.send(b) {
+                warn!("virtio-console: failed to send: {:?}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Reads the received bytes of the `idx`-th console device into `buf`
+/// without blocking.
+///
+/// Returns the number of bytes read.
+pub fn read_bytes_from(idx: usize, buf: &mut [u8]) -> usize {
+    let Some(console) = CONSOLES.get(idx) else {
+        return 0;
+    };
+    let mut console = console.lock();
+    let Some(Console(dev)) = console.as_mut() else {
+        return 0;
+    };
+    let mut read_len = 0;
+    while read_len < buf.len() {
+        match dev.recv(true) {
+            Ok(Some(b)) => {
+                buf[read_len] = b;
+                read_len += 1;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("virtio-console: failed to receive: {:?}", e);
+                break;
+            }
+        }
+    }
+    read_len
+}
+
+/// A console device as a console port of [`axhal::console`].
+pub struct ConsolePort(usize);
+
+impl ConsoleDriver for ConsolePort {
+    fn write(&self, bytes: &[u8]) {
+        write_bytes_to(self.0, bytes);
+    }
+
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        read_bytes_from(self.0, bytes)
+    }
+}
+
+static CONSOLE_PORTS: [ConsolePort; MAX_DEVICES] = [
+    ConsolePort(0),
+    ConsolePort(1),
+    ConsolePort(2),
+    ConsolePort(3),
+];
+
+/// Returns the `idx`-th console device as a console port, e.g., to be
+/// registered with [`axhal::console::register_port`].
+///
+/// Returns `None` if the device is not present.
+pub fn console_port(idx: usize) -> Option<&'static dyn ConsoleDriver> {
+    if idx < num_devices() {
+        Some(&CONSOLE_PORTS[idx])
+    } else {
+        None
+    }
+}
+
+fn register(dev: ConsoleDevice) {
+    let Some(console) = CONSOLES.iter().find(|c| c.lock().is_none()) else {
+        warn!(
+            "virtio-console: at most {} console devices are supported",
+            MAX_DEVICES
+        );
+        return;
+    };
+    info!("registered a new VirtIO console device");
+    *console.lock() = Some(Console(dev));
+}
+
+#[cfg(bus = "mmio")]
+pub(crate) fn probe_mmio(mmio_base: usize) -> bool {
+    use core::ptr::NonNull;
+    use virtio_drivers::transport::mmio::{MmioTransport, VirtIOHeader};
+
+    let base_vaddr = axhal::mem::phys_to_virt(mmio_base.into());
+    let Some(header) = NonNull::new(base_vaddr.as_mut_ptr() as *mut VirtIOHeader) else {
+        return false;
+    };
+    let transport = match unsafe { MmioTransport::new(header) } {
+        Ok(transport) if transport.device_type() == DeviceType::Console => transport,
+        _ => return false,
+    };
+    match VirtIOConsole::new(transport) {
+        Ok(dev) => register(dev),
+        Err(e) => warn!(
+            "failed to initialize VirtIO console device at PA:{:#x}: {:?}",
+            mmio_base, e
+        ),
+    }
+    true
+}
+
+#[cfg(bus = "pci")]
+pub(crate) fn probe_pci(
+    root: &mut axdriver_pci::PciRoot,
+    bdf: axdriver_pci::DeviceFunction,
+    dev_info: &axdriver_pci::DeviceFunctionInfo,
+) -> bool {
+    use axdriver_virtio::PciTransport;
+
+    if dev_info.vendor_id != 0x1af4 || !matches!(dev_info.device_id, 0x1003 | 0x1043) {
+        return false;
+    }
+    let transport = match PciTransport::new::<crate::virtio::VirtIoHalImpl>(root, bdf) {
+        Ok(transport) if transport.device_type() == DeviceType::Console => transport,
+        _ => return false,
+    };
+    match VirtIOConsole::new(transport) {
+        Ok(dev) => register(dev),
+        Err(e) => warn!(
+            "failed to initialize VirtIO console device at {}({}): {:?}",
+            bdf, dev_info, e
+        ),
+    }
+    true
+}
@@ -0,0 +1,634 @@
+//! A mock VirtIO transport for host-run driver tests.
+//!
+//! [`MockTransport`] implements the VirtIO [`Transport`] trait on top of
+//! ordinary memory. Requests that drivers put into the virtqueues are handed
+//! to a [`MockDevice`], which programs the behavior of the emulated device,
+//! and faults can be injected with [`MockTransport::inject_fault`].
+//!
+//! Drivers must be instantiated with [`MockHal`], which uses identity-mapped
+//! host memory for DMA.
+//!
+//! Mock devices are provided for the VirtIO drivers enabled: a RAM disk
+//! ([`MockBlock`]), a NIC whose frames are captured and injected by the test
+//! (`MockNet`), and a console whose input and output are byte streams
+//! (`MockConsole`).
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let transport = MockTransport::new(MockBlock::new(16));
+//! let mut blk = MockBlkDev::try_new(transport.clone()).unwrap();
+//! blk.write_block(0, &[0xaa; 512]).unwrap();
+//! assert_eq!(transport.with_device(|disk: &mut MockBlock| disk.data()[0]), 0xaa);
+//! ```
+
+use alloc::alloc::{Layout, alloc_zeroed, dealloc};
+use alloc::boxed::Box;
+#[cfg(any(feature = "virtio-net", feature = "virtio-console"))]
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+use core::sync::atomic::{Ordering, fence};
+
+use axdriver_virtio::{BufferDirection, PhysAddr, Transport, VirtIoHal};
+use kspin::SpinNoIrq;
+use virtio_drivers::transport::{DeviceStatus, DeviceType};
+use virtio_drivers::{Error, Result};
+
+/// The VIRTIO_F_VERSION_1 feature bit, which all mock devices offer.
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const PAGE_SIZE: usize = 0x1000;
+const MAX_QUEUES: usize = 4;
+const CONFIG_SPACE_SIZE: usize = 256;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// A VirtIO [`Hal`](VirtIoHal) implementation for the host, where physical
+/// addresses are the same as virtual addresses.
+pub struct MockHal;
+
+unsafe impl VirtIoHal for MockHal {
+    fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
+        let layout = Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
+        match NonNull::new(unsafe { alloc_zeroed(layout) }) {
+            Some(ptr) => (ptr.as_ptr() as PhysAddr, ptr),
+            None => (0, NonNull::dangling()),
+        }
+    }
+
+    unsafe fn dma_dealloc(_paddr: PhysAddr, vaddr: NonNull<u8>, pages: usize) -> i32 {
+        let layout = Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
+        unsafe { dealloc(vaddr.as_ptr(), layout) };
+        0
+    }
+
+    unsafe fn mmio_phys_to_virt(paddr: PhysAddr, _size: usize) -> NonNull<u8> {
+        NonNull::new(paddr as _).unwrap()
+    }
+
+    unsafe fn share(buffer: NonNull<[u8]>, _direction: BufferDirection) -> PhysAddr {
+        buffer.as_ptr() as *mut u8 as PhysAddr
+    }
+
+    unsafe fn unshare(_paddr: PhysAddr, _buffer: NonNull<[u8]>, _direction: BufferDirection) {}
+}
+
+/// The behavior of an emulated VirtIO device.
+pub trait MockDevice: Send + 'static {
+    /// The VirtIO device type.
+    fn device_type(&self) -> DeviceType;
+
+    /// The features offered by the device.
+    fn features(&self) -> u64 {
+        VIRTIO_F_VERSION_1
+    }
+
+    /// The maximum size of each virtqueue.
+    fn max_queue_size(&self) -> u32 {
+        16
+    }
+
+    /// Writes the initial content of the device configuration space.
+    fn init_config(&self, _config: &mut [u8]) {}
+
+    /// Handles a request from the driver.
+    ///
+    /// `input` holds the concatenated device-readable buffers of the request,
+    /// and the device writes its response to `output`, which is the size of
+    /// all device-writable buffers. Returns the number of bytes written, or
+    /// `None` if the device cannot complete the request yet; it is retried
+    /// on the next notification or [`MockTransport::process`].
+    fn handle(&mut self, queue: u16, input: &[u8], output: &mut [u8]) -> Option<usize>;
+}
+
+/// Faults that can be injected into a [`MockTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The next virtqueue setup fails because the device reports a maximum
+    /// queue size of zero.
+    QueueSetup,
+    /// The next request is completed without being passed to the device, and
+    /// with no bytes written.
+    DropRequest,
+    /// The device sets the `DEVICE_NEEDS_RESET` status bit.
+    NeedsReset,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Queue {
+    size: u32,
+    desc: usize,
+    avail: usize,
+    used: usize,
+    last_avail: u16,
+}
+
+struct MockState<D> {
+    device: D,
+    status: DeviceStatus,
+    driver_features: u64,
+    queues: [Option<Queue>; MAX_QUEUES],
+    interrupt: bool,
+    faults: Vec<Fault>,
+    notifications: usize,
+}
+
+impl<D: MockDevice> MockState<D> {
+    fn take_fault(&mut self, fault: Fault) -> bool {
+        match self.faults.iter().position(|&f| f == fault) {
+            Some(i) => {
+                self.faults.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Processes the available requests of the given queue.
+    fn process(&mut self, queue_idx: u16) {
+        let Some(mut queue) = self.queues.get(queue_idx as usize).copied().flatten() else {
+            return;
+        };
+        loop {
+            // SAFETY: the rings are allocated by the driver with `MockHal`.
+            let avail_idx = unsafe { (queue.avail as *const u16).add(1).read_volatile() };
+            if queue.last_avail == avail_idx {
+                break;
+            }
+            fence(Ordering::SeqCst);
+            let slot = (queue.last_avail as u32 % queue.size) as usize;
+            let head = unsafe { (queue.avail as *const u16).add(2 + slot).read_volatile() };
+            let (input, writable) = unsafe { read_chain(&queue, head) };
+            let mut output = vec![0; writable.iter().map(|b| b.1).sum()];
+
+            let written = if self.take_fault(Fault::DropRequest) {
+                0
+            } else {
+                let Some(len) = self.device.handle(queue_idx, &input, &mut output) else {
+                    break;
+                };
+                let mut offset = 0;
+                for &(addr, len) in &writable {
+                    // SAFETY: the buffer is shared by the driver with `MockHal`.
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(output[offset..].as_ptr(), addr as _, len)
+                    };
+                    offset += len;
+                }
+                len.min(output.len())
+            };
+
+            // Append an element to the used ring: { flags, idx, ring: [{ id: u32, len: u32 }] }
+            unsafe {
+                let used_idx = (queue.used as *mut u16).add(1);
+                let idx = used_idx.read_volatile();
+                let elem = (queue.used as *mut u32).add(1 + 2 * (idx as u32 % queue.size) as usize);
+                elem.write_volatile(head as u32);
+                elem.add(1).write_volatile(written as u32);
+                fence(Ordering::SeqCst);
+                used_idx.write_volatile(idx.wrapping_add(1));
+            }
+            queue.last_avail = queue.last_avail.wrapping_add(1);
+            self.interrupt = true;
+        }
+        self.queues[queue_idx as usize] = Some(queue);
+        if self.take_fault(Fault::NeedsReset) {
+            self.status |= DeviceStatus::DEVICE_NEEDS_RESET;
+        }
+    }
+}
+
+/// Reads the descriptor chain starting at `head`. Returns the concatenated
+/// device-readable buffers and the address and length of each
+/// device-writable buffer.
+unsafe fn read_chain(queue: &Queue, head: u16) -> (Vec<u8>, Vec<(usize, usize)>) {
+    let mut input = Vec::new();
+    let mut writable = Vec::new();
+    let mut idx = head;
+    for _ in 0..queue.size {
+        // Descriptor layout: { addr: u64, len: u32, flags: u16, next: u16 }
+        let desc = queue.desc + 16 * idx as usize;
+        let (addr, len, flags, next) = unsafe {
+            (
+                (desc as *const u64).read_volatile() as usize,
+                ((desc + 8) as *const u32).read_volatile() as usize,
+                ((desc + 12) as *const u16).read_volatile(),
+                ((desc + 14) as *const u16).read_volatile(),
+            )
+        };
+        if flags & VIRTQ_DESC_F_WRITE != 0 {
+            writable.push((addr, len));
+        } else {
+            input.extend_from_slice(unsafe { core::slice::from_raw_parts(addr as *const u8, len) });
+        }
+        if flags & VIRTQ_DESC_F_NEXT == 0 {
+            break;
+        }
+        idx = next;
+    }
+    (input, writable)
+}
+
+/// A VirtIO transport backed by memory and a [`MockDevice`].
+///
+/// Clones share the same device, so a test can keep a clone to inspect the
+/// device or inject faults after passing the transport to a driver.
+pub struct MockTransport<D: MockDevice> {
+    state: Arc<SpinNoIrq<MockState<D>>>,
+    config: Arc<SpinNoIrq<Box<[u64; CONFIG_SPACE_SIZE / 8]>>>,
+}
+
+impl<D: MockDevice> Clone for MockTransport<D> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<D: MockDevice> MockTransport<D> {
+    /// Creates a transport for the given device.
+    pub fn new(device: D) -> Self {
+        let mut config = Box::new([0u64; CONFIG_SPACE_SIZE / 8]);
+        // SAFETY: the array is plain memory of `CONFIG_SPACE_SIZE` bytes.
+        device.init_config(unsafe {
+            core::slice::from_raw_parts_mut(config.as_mut_ptr() as *mut u8, CONFIG_SPACE_SIZE)
+        });
+        Self {
+            state: Arc::new(SpinNoIrq::new(MockState {
+                device,
+                status: DeviceStatus::empty(),
+                driver_features: 0,
+                queues: [None; MAX_QUEUES],
+                interrupt: false,
+                faults: Vec::new(),
+                notifications: 0,
+            })),
+            config: Arc::new(SpinNoIrq::new(config)),
+        }
+    }
+
+    /// Calls `f` with the emulated device.
+    pub fn with_device<R>(&self, f: impl FnOnce(&mut D) -> R) -> R {
+        f(&mut self.state.lock().device)
+    }
+
+    /// Injects a fault, which takes effect once.
+    pub fn inject_fault(&self, fault: Fault) {
+        self.state.lock().faults.push(fault);
+    }
+
+    /// Processes pending requests of the given queue, e.g., after the device
+    /// became ready to complete a request it previously deferred.
+    pub fn process(&self, queue: u16) {
+        self.state.lock().process(queue);
+    }
+
+    /// Returns the features accepted by the driver.
+    pub fn driver_features(&self) -> u64 {
+        self.state.lock().driver_features
+    }
+
+    /// Returns the number of queue notifications received from the driver.
+    pub fn notifications(&self) -> usize {
+        self.state.lock().notifications
+    }
+}
+
+impl<D: MockDevice> Transport for MockTransport<D> {
+    fn device_type(&self) -> DeviceType {
+        self.state.lock().device.device_type()
+    }
+
+    fn read_device_features(&mut self) -> u64 {
+        self.state.lock().device.features()
+    }
+
+    fn write_driver_features(&mut self, driver_features: u64) {
+        self.state.lock().driver_features = driver_features;
+    }
+
+    fn max_queue_size(&mut self, _queue: u16) -> u32 {
+        let mut state = self.state.lock();
+        if state.take_fault(Fault::QueueSetup) {
+            return 0;
+        }
+        state.device.max_queue_size()
+    }
+
+    fn notify(&mut self, queue: u16) {
+        let mut state = self.state.lock();
+        state.notifications += 1;
+        state.process(queue);
+    }
+
+    fn get_status(&self) -> DeviceStatus {
+        self.state.lock().status
+    }
+
+    fn set_status(&mut self, status: DeviceStatus) {
+        let mut state = self.state.lock();
+        state.status = status;
+        if status.is_empty() {
+            // Device reset.
+            state.queues = [None; MAX_QUEUES];
+            state.interrupt = false;
+        }
+    }
+
+    fn set_guest_page_size(&mut self, _guest_page_size: u32) {}
+
+    fn requires_legacy_layout(&self) -> bool {
+        false
+    }
+
+    fn queue_set(
+        &mut self,
+        queue: u16,
+        size: u32,
+        descriptors: PhysAddr,
+        driver_area: PhysAddr,
+        device_area: PhysAddr,
+    ) {
+        self.state.lock().queues[queue as usize] = Some(Queue {
+            size,
+            desc: descriptors,
+            avail: driver_area,
+            used: device_area,
+            last_avail: 0,
+        });
+    }
+
+    fn queue_unset(&mut self, queue: u16) {
+        self.state.lock().queues[queue as usize] = None;
+    }
+
+    fn queue_used(&mut self, queue: u16) -> bool {
+        self.state.lock().queues[queue as usize].is_some()
+    }
+
+    fn ack_interrupt(&mut self) -> bool {
+        core::mem::take(&mut self.state.lock().interrupt)
+    }
+
+    fn config_space<T: 'static>(&self) -> Result<NonNull<T>> {
+        if size_of::<T>() > CONFIG_SPACE_SIZE {
+            return Err(Error::ConfigSpaceTooSmall);
+        }
+        // The config space is boxed, so it stays in place while the lock is
+        // released.
+        Ok(NonNull::new(self.config.lock().as_mut_ptr() as *mut T).unwrap())
+    }
+}
+
+/// A RAM disk that behaves as a VirtIO block device.
+#[cfg(feature = "virtio-blk")]
+pub struct MockBlock {
+    data: Vec<u8>,
+    requests: usize,
+}
+
+#[cfg(feature = "virtio-blk")]
+impl MockBlock {
+    /// The size of a sector in bytes.
+    pub const SECTOR_SIZE: usize = 512;
+
+    /// Creates a zero-filled disk with the given number of sectors.
+    pub fn new(num_sectors: usize) -> Self {
+        Self {
+            data: vec![0; num_sectors * Self::SECTOR_SIZE],
+            requests: 0,
+        }
+    }
+
+    /// Returns the content of the disk.
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Returns the number of requests handled.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+}
+
+#[cfg(feature = "virtio-blk")]
+impl MockDevice for MockBlock {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn init_config(&self, config: &mut [u8]) {
+        // `capacity` in sectors, as a little-endian 64-bit integer.
+        let capacity = (self.data.len() / Self::SECTOR_SIZE) as u64;
+        config[..8].copy_from_slice(&capacity.to_le_bytes());
+    }
+
+    fn handle(&mut self, _queue: u16, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        const VIRTIO_BLK_T_IN: u32 = 0;
+        const VIRTIO_BLK_T_OUT: u32 = 1;
+        const VIRTIO_BLK_T_FLUSH: u32 = 4;
+        const VIRTIO_BLK_S_OK: u8 = 0;
+        const VIRTIO_BLK_S_IOERR: u8 = 1;
+        const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+        self.requests += 1;
+        // Request header: { type: u32, reserved: u32, sector: u64 }, followed
+        // by the data for writes. The response is the data for reads, followed
+        // by a status byte.
+        let (status, rest) = output.split_last_mut()?;
+        let ty = u32::from_le_bytes(input.get(0..4)?.try_into().unwrap());
+        let sector = u64::from_le_bytes(input.get(8..16)?.try_into().unwrap()) as usize;
+        let start = sector * Self::SECTOR_SIZE;
+        let (result, written) = match ty {
+            VIRTIO_BLK_T_IN => match self.data.get(start..start + rest.len()) {
+                Some(data) => {
+                    rest.copy_from_slice(data);
+                    (VIRTIO_BLK_S_OK, rest.len())
+                }
+                None => (VIRTIO_BLK_S_IOERR, 0),
+            },
+            VIRTIO_BLK_T_OUT => {
+                let data = &input[16..];
+                match self.data.get_mut(start..start + data.len()) {
+                    Some(dst) => {
+                        dst.copy_from_slice(data);
+                        (VIRTIO_BLK_S_OK, 0)
+                    }
+                    None => (VIRTIO_BLK_S_IOERR, 0),
+                }
+            }
+            VIRTIO_BLK_T_FLUSH => (VIRTIO_BLK_S_OK, 0),
+            _ => (VIRTIO_BLK_S_UNSUPP, 0),
+        };
+        *status = result;
+        Some(written + 1)
+    }
+}
+
+/// The VirtIO block driver instantiated with the mock transport.
+#[cfg(feature = "virtio-blk")]
+pub type MockBlkDev = axdriver_virtio::VirtIoBlkDev<MockHal, MockTransport<MockBlock>>;
+
+/// A NIC that behaves as a VirtIO network device: the frames sent by the
+/// driver are captured, and the frames injected with
+/// [`push_rx`](Self::push_rx) are received by it.
+///
+/// It offers neither `VIRTIO_F_VERSION_1` nor `VIRTIO_NET_F_MRG_RXBUF`, so
+/// the frames have the legacy header of [`NET_HDR_SIZE`](Self::NET_HDR_SIZE)
+/// bytes.
+#[cfg(feature = "virtio-net")]
+pub struct MockNet {
+    mac: [u8; 6],
+    rx: VecDeque<Vec<u8>>,
+    tx: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "virtio-net")]
+impl MockNet {
+    /// The size of the header before each frame.
+    pub const NET_HDR_SIZE: usize = 10;
+
+    const QUEUE_RECEIVE: u16 = 0;
+    const QUEUE_TRANSMIT: u16 = 1;
+
+    /// Creates a NIC with the given MAC address.
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            rx: VecDeque::new(),
+            tx: Vec::new(),
+        }
+    }
+
+    /// Queues a frame to be received by the driver. It is delivered on the
+    /// next notification of the receive queue or
+    /// [`MockTransport::process`] of queue 0, if the driver has a buffer.
+    pub fn push_rx(&mut self, frame: &[u8]) {
+        self.rx.push_back(frame.to_vec());
+    }
+
+    /// Returns the number of frames not received by the driver yet.
+    pub fn rx_pending(&self) -> usize {
+        self.rx.len()
+    }
+
+    /// Takes the frames sent by the driver, without their headers, the
+    /// oldest first.
+    pub fn take_tx(&mut self) -> Vec<Vec<u8>> {
+        core::mem::take(&mut self.tx)
+    }
+}
+
+#[cfg(feature = "virtio-net")]
+impl MockDevice for MockNet {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Network
+    }
+
+    fn features(&self) -> u64 {
+        const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+        VIRTIO_NET_F_MAC
+    }
+
+    fn init_config(&self, config: &mut [u8]) {
+        // `mac`, then `status`, which is not offered.
+        config[..6].copy_from_slice(&self.mac);
+    }
+
+    fn handle(&mut self, queue: u16, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        match queue {
+            Self::QUEUE_RECEIVE => {
+                let frame = self.rx.front()?;
+                let len = frame
+                    .len()
+                    .min(output.len().saturating_sub(Self::NET_HDR_SIZE));
+                // A zeroed header: no checksum offload, no segmentation.
+                output[..Self::NET_HDR_SIZE].fill(0);
+                output[Self::NET_HDR_SIZE..][..len].copy_from_slice(&frame[..len]);
+                self.rx.pop_front();
+                Some(Self::NET_HDR_SIZE + len)
+            }
+            Self::QUEUE_TRANSMIT => {
+                self.tx
+                    .push(input.get(Self::NET_HDR_SIZE..).unwrap_or_default().to_vec());
+                Some(0)
+            }
+            _ => Some(0),
+        }
+    }
+}
+
+/// The VirtIO network driver instantiated with the mock transport.
+#[cfg(feature = "virtio-net")]
+pub type MockNetDev = axdriver_virtio::VirtIoNetDev<MockHal, MockTransport<MockNet>, 16>;
+
+/// A console that behaves as a VirtIO console device, with a single port:
+/// the bytes sent by the driver are captured, and the bytes injected with
+/// [`push_input`](Self::push_input) are received by it.
+#[cfg(feature = "virtio-console")]
+#[derive(Default)]
+pub struct MockConsole {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "virtio-console")]
+impl MockConsole {
+    const QUEUE_RECEIVE: u16 = 0;
+    const QUEUE_TRANSMIT: u16 = 1;
+
+    /// Creates a console with no input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues bytes to be received by the driver. They are delivered on the
+    /// next notification of the receive queue or
+    /// [`MockTransport::process`] of queue 0, if the driver has a buffer.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Takes the bytes sent by the driver.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+}
+
+#[cfg(feature = "virtio-console")]
+impl MockDevice for MockConsole {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Console
+    }
+
+    fn handle(&mut self, queue: u16, input: &[u8], output: &mut [u8]) -> Option<usize> {
+        match queue {
+            Self::QUEUE_RECEIVE => {
+                if self.input.is_empty() {
+                    return None;
+                }
+                let len = self.input.len().min(output.len());
+                for (dst, src) in output.iter_mut().zip(self.input.drain(..len)) {
+                    *dst = src;
+                }
+                Some(len)
+            }
+            Self::QUEUE_TRANSMIT => {
+                self.output.extend_from_slice(input);
+                Some(0)
+            }
+            _ => Some(0),
+        }
+    }
+}
+
+/// The VirtIO console driver instantiated with the mock transport.
+#[cfg(feature = "virtio-console")]
+pub type MockConsoleDev =
+    virtio_drivers::device::console::VirtIOConsole<MockHal, MockTransport<MockConsole>>;
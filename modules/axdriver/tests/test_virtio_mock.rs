@@ -0,0 +1,132 @@
+#![cfg(feature = "virtio-mock")]
+
+use axdriver::prelude::*;
+use axdriver::virtio_mock::*;
+
+#[cfg(feature = "virtio-blk")]
+#[test]
+fn test_virtio_blk() {
+    let transport = MockTransport::new(MockBlock::new(16));
+    let mut blk = MockBlkDev::try_new(transport.clone()).unwrap();
+    assert_eq!(blk.device_type(), DeviceType::Block);
+    assert_eq!(blk.num_blocks(), 16);
+    assert_eq!(blk.block_size(), MockBlock::SECTOR_SIZE);
+
+    let mut buf = [0u8; 512];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    blk.write_block(3, &buf).unwrap();
+    transport.with_device(|disk| assert_eq!(disk.data()[3 * 512..4 * 512], buf));
+
+    transport.with_device(|disk| disk.data()[5 * 512] = 0xaa);
+    blk.read_block(5, &mut buf).unwrap();
+    assert_eq!(buf[0], 0xaa);
+    assert_eq!(buf[1], 0);
+
+    assert!(blk.read_block(16, &mut buf).is_err());
+    assert_eq!(transport.with_device(|disk| disk.requests()), 3);
+    assert!(transport.notifications() >= 3);
+    blk.flush().unwrap();
+}
+
+#[cfg(feature = "virtio-blk")]
+#[test]
+fn test_virtio_blk_faults() {
+    let transport = MockTransport::new(MockBlock::new(4));
+    transport.inject_fault(Fault::QueueSetup);
+    assert!(MockBlkDev::try_new(transport.clone()).is_err());
+
+    let mut blk = MockBlkDev::try_new(transport.clone()).unwrap();
+    let mut buf = [0u8; 512];
+    transport.inject_fault(Fault::DropRequest);
+    assert!(blk.read_block(0, &mut buf).is_err());
+    assert_eq!(transport.with_device(|disk| disk.requests()), 0);
+
+    // The driver recovers once the fault is consumed.
+    blk.read_block(0, &mut buf).unwrap();
+    assert_eq!(transport.with_device(|disk| disk.requests()), 1);
+}
+
+#[cfg(feature = "virtio-net")]
+#[test]
+fn test_virtio_net() {
+    const MAC: [u8; 6] = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+
+    let transport = MockTransport::new(MockNet::new(MAC));
+    let mut net = MockNetDev::try_new(transport.clone()).unwrap();
+    assert_eq!(net.device_type(), DeviceType::Net);
+    assert_eq!(net.mac_address().0, MAC);
+
+    // Transmit: the device sees the frame without its header.
+    let frame: Vec<u8> = (0..60).collect();
+    let mut tx_buf = net.alloc_tx_buffer(frame.len()).unwrap();
+    tx_buf.packet_mut().copy_from_slice(&frame);
+    net.transmit(tx_buf).unwrap();
+    net.recycle_tx_buffers().unwrap();
+    assert_eq!(transport.with_device(|dev| dev.take_tx()), [frame.clone()]);
+
+    // Receive: nothing until the device has a frame.
+    assert!(net.receive().is_err());
+    transport.with_device(|dev| dev.push_rx(&frame[..42]));
+    transport.process(0);
+    assert_eq!(transport.with_device(|dev| dev.rx_pending()), 0);
+    let rx_buf = net.receive().unwrap();
+    assert_eq!(rx_buf.packet(), &frame[..42]);
+    net.recycle_rx_buffer(rx_buf).unwrap();
+
+    // The recycled buffers keep receiving.
+    for i in 0..40u8 {
+        transport.with_device(|dev| dev.push_rx(&[i; 64]));
+        transport.process(0);
+        let rx_buf = net.receive().unwrap();
+        assert_eq!(rx_buf.packet(), [i; 64]);
+        net.recycle_rx_buffer(rx_buf).unwrap();
+    }
+}
+
+#[cfg(feature = "virtio-net")]
+#[test]
+fn test_virtio_net_faults() {
+    let transport = MockTransport::new(MockNet::new([2, 0, 0, 0, 0, 1]));
+    transport.inject_fault(Fault::QueueSetup);
+    assert!(MockNetDev::try_new(transport.clone()).is_err());
+
+    let mut net = MockNetDev::try_new(transport.clone()).unwrap();
+    // The dropped frame never reaches the device.
+    transport.inject_fault(Fault::DropRequest);
+    let tx_buf = net.alloc_tx_buffer(60).unwrap();
+    net.transmit(tx_buf).unwrap();
+    net.recycle_tx_buffers().unwrap();
+    assert!(transport.with_device(|dev| dev.take_tx()).is_empty());
+
+    let tx_buf = net.alloc_tx_buffer(60).unwrap();
+    net.transmit(tx_buf).unwrap();
+    assert_eq!(transport.with_device(|dev| dev.take_tx()).len(), 1);
+}
+
+#[cfg(feature = "virtio-console")]
+#[test]
+fn test_virtio_console() {
+    let transport = MockTransport::new(MockConsole::new());
+    let mut console = MockConsoleDev::new(transport.clone()).unwrap();
+
+    for &b in b"hello\n" {
+        console.send(b).unwrap();
+    }
+    assert_eq!(transport.with_device(|dev| dev.take_output()), b"hello\n");
+
+    // Nothing is received until the host sends input.
+    assert_eq!(console.recv(true).unwrap(), None);
+    transport.with_device(|dev| dev.push_input(b"ls"));
+    transport.process(0);
+    assert_eq!(console.recv(false).unwrap(), Some(b'l'));
+    assert_eq!(console.recv(true).unwrap(), Some(b'l'));
+    assert_eq!(console.recv(true).unwrap(), Some(b's'));
+    assert_eq!(console.recv(true).unwrap(), None);
+
+    // The receive buffer is given back for the next input.
+    transport.with_device(|dev| dev.push_input(b"x"));
+    transport.process(0);
+    assert_eq!(console.recv(true).unwrap(), Some(b'x'));
+}
@@ -0,0 +1,116 @@
+//! Asynchronous file operations.
+//!
+//! The operations are run by [`axtask::future::spawn_blocking`] on a pool of
+//! tasks, so that the task polling their futures, e.g., an executor serving
+//! network connections, is not blocked while the disk is accessed.
+//!
+//! The data is copied between the buffers of the callers and the ones of the
+//! pool, so that a future can be dropped before it completes.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use axerrno::AxResult;
+use axio::SeekFrom;
+use axsync::Mutex;
+use axtask::future::spawn_blocking;
+
+use crate::fops::{self, FileAttr, OpenOptions};
+
+/// An opened file object, whose operations are asynchronous.
+///
+/// The file can be shared by cloning it, with one cursor.
+#[derive(Clone)]
+pub struct File {
+    inner: Arc<Mutex<fops::File>>,
+}
+
+impl File {
+    /// Opens a file at the path relative to the current directory.
+    pub async fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
+        // The tasks of the pool may have other current directories.
+        let path = crate::root::absolute_path(path)?;
+        let opts = opts.clone();
+        let file = spawn_blocking(move || fops::File::open(&path, &opts)).await?;
+        Ok(Self::from(file))
+    }
+
+    /// Reads the file at the current position. Returns the number of bytes
+    /// read.
+    ///
+    /// After the read, the cursor will be advanced by the number of bytes read.
+    pub async fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let inner = self.inner.clone();
+        let mut data = vec![0; buf.len()];
+        let (data, read_len) = spawn_blocking(move || {
+            let read_len = inner.lock().read(&mut data);
+            (data, read_len)
+        })
+        .await;
+        let read_len = read_len?;
+        buf[..read_len].copy_from_slice(&data[..read_len]);
+        Ok(read_len)
+    }
+
+    /// Reads the file at the given position. Returns the number of bytes read.
+    ///
+    /// It does not update the file cursor.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let inner = self.inner.clone();
+        let mut data = vec![0; buf.len()];
+        let (data, read_len) = spawn_blocking(move || {
+            let read_len = inner.lock().read_at(offset, &mut data);
+            (data, read_len)
+        })
+        .await;
+        let read_len = read_len?;
+        buf[..read_len].copy_from_slice(&data[..read_len]);
+        Ok(read_len)
+    }
+
+    /// Writes the file at the current position. Returns the number of bytes
+    /// written.
+    ///
+    /// After the write, the cursor will be advanced by the number of bytes
+    /// written.
+    pub async fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        let inner = self.inner.clone();
+        let data = Vec::from(buf);
+        spawn_blocking(move || inner.lock().write(&data)).await
+    }
+
+    /// Writes the file at the given position. Returns the number of bytes
+    /// written.
+    ///
+    /// It does not update the file cursor.
+    pub async fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let inner = self.inner.clone();
+        let data = Vec::from(buf);
+        spawn_blocking(move || inner.lock().write_at(offset, &data)).await
+    }
+
+    /// Flushes the file, writes all buffered data to the underlying device.
+    pub async fn flush(&self) -> AxResult {
+        let inner = self.inner.clone();
+        spawn_blocking(move || inner.lock().flush()).await
+    }
+
+    /// Sets the cursor of the file to the specified offset. Returns the new
+    /// position after the seek.
+    pub async fn seek(&self, pos: SeekFrom) -> AxResult<u64> {
+        let inner = self.inner.clone();
+        spawn_blocking(move || inner.lock().seek(pos)).await
+    }
+
+    /// Gets the file attributes.
+    pub async fn get_attr(&self) -> AxResult<FileAttr> {
+        let inner = self.inner.clone();
+        spawn_blocking(move || inner.lock().get_attr()).await
+    }
+}
+
+impl From<fops::File> for File {
+    fn from(file: fops::File) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(file)),
+        }
+    }
+}
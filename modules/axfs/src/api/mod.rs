@@ -3,7 +3,7 @@
 mod dir;
 mod file;
 
-pub use self::dir::{DirBuilder, DirEntry, ReadDir};
+pub use self::dir::{DirBuilder, DirEntry, ReadDir, WalkDir, WalkEntry};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
 use alloc::{string::String, vec::Vec};
@@ -14,10 +14,16 @@ pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
 }
 
+/// Returns an iterator over the entries within a directory and,
+/// recursively, within its subdirectories, e.g., to search for files.
+pub fn walk_dir(path: &str) -> WalkDir {
+    WalkDir::new(path)
+}
+
 /// Returns the canonical, absolute form of a path with all intermediate
 /// components normalized.
 pub fn canonicalize(path: &str) -> io::Result<String> {
-    crate::root::absolute_path(path)
+    crate::root::absolute_view_path(path)
 }
 
 /// Returns the current working directory as a [`String`].
@@ -30,6 +36,30 @@ pub fn set_current_dir(path: &str) -> io::Result<()> {
     crate::root::set_current_dir(path)
 }
 
+/// Makes the current directory the root directory of the current task, and
+/// of the tasks it creates afterwards, like `chroot`.
+///
+/// The paths are then resolved from it, and `..` does not go above it, so
+/// these tasks cannot reach the other files. `/dev`, `/proc` and `/sys` are
+/// still seen at the same paths.
+pub fn unshare_root() -> io::Result<()> {
+    crate::root::unshare_root()
+}
+
+/// Returns the file mode creation mask of the current task.
+pub fn umask() -> u32 {
+    crate::root::umask()
+}
+
+/// Sets the file mode creation mask of the current task, returning the
+/// previous one.
+///
+/// It is only recorded for now, as the filesystems do not keep the
+/// permissions of their files.
+pub fn set_umask(umask: u32) -> u32 {
+    crate::root::set_umask(umask)
+}
+
 /// Read the entire contents of a file into a bytes vector.
 pub fn read(path: &str) -> io::Result<Vec<u8>> {
     let mut file = File::open(path)?;
@@ -87,3 +117,31 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
+
+/// Creates a new hard link on the filesystem.
+///
+/// The `link` path will be a link pointing to the `original` path. Hard links
+/// are only supported between the files of a same RAM filesystem, e.g., in
+/// `/tmp`.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    crate::root::hard_link(original, link)
+}
+
+/// Creates a new symbolic link on the filesystem.
+///
+/// The `link` path will be a symbolic link pointing to the `original` path,
+/// which does not need to exist.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    crate::root::symlink(original, link)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(path)
+}
+
+/// Sets the maximum number of symbolic links followed when resolving a path,
+/// returning the previous one. It is 40 by default.
+pub fn set_max_symlink_follows(max: usize) -> usize {
+    crate::root::set_max_symlink_follows(max)
+}
@@ -1,28 +1,105 @@
+use alloc::sync::Arc;
 use axdriver::prelude::*;
+use axsync::Mutex;
 
 const BLOCK_SIZE: usize = 512;
 
 /// A disk device with a cursor.
+///
+/// It covers either a whole block device, or a range of its blocks, e.g., a
+/// partition, with the device shared by the disks over it.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: Arc<Mutex<AxBlockDevice>>,
+    /// The first block of the disk on the device.
+    start_block: u64,
+    num_blocks: u64,
 }
 
 impl Disk {
     /// Create a new disk.
     pub fn new(dev: AxBlockDevice) -> Self {
         assert_eq!(BLOCK_SIZE, dev.block_size());
+        let num_blocks = dev.num_blocks();
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            dev: Arc::new(Mutex::new(dev)),
+            start_block: 0,
+            num_blocks,
+        }
+    }
+
+    /// Creates a disk over `num_blocks` blocks of this one from
+    /// `start_block`, e.g., a partition, sharing the device.
+    ///
+    /// The range is truncated to the end of this disk.
+    pub fn slice(&self, start_block: u64, num_blocks: u64) -> Self {
+        let start_block = start_block.min(self.num_blocks);
+        Self {
+            block_id: 0,
+            offset: 0,
+            dev: self.dev.clone(),
+            start_block: self.start_block + start_block,
+            num_blocks: num_blocks.min(self.num_blocks - start_block),
         }
     }
 
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
-        self.dev.num_blocks() * BLOCK_SIZE as u64
+        self.num_blocks * BLOCK_SIZE as u64
+    }
+
+    /// Get the number of blocks of the disk.
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Reads a block of the disk.
+    pub fn read_block(&self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        if block_id >= self.num_blocks {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev.lock().read_block(self.start_block + block_id, buf)
+    }
+
+    /// Writes a block of the disk.
+    pub fn write_block(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        if block_id >= self.num_blocks {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev
+            .lock()
+            .write_block(self.start_block + block_id, buf)
+    }
+
+    /// Reads consecutive blocks of the disk from `block_id`, as many as fit
+    /// in `buf`, with one request to the device.
+    ///
+    /// The length of `buf` must be a multiple of the block size.
+    pub fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.check_blocks(block_id, buf.len())?;
+        self.dev.lock().read_block(self.start_block + block_id, buf)
+    }
+
+    /// Writes consecutive blocks of the disk from `block_id`, as many as fit
+    /// in `buf`, with one request to the device.
+    ///
+    /// The length of `buf` must be a multiple of the block size.
+    pub fn write_blocks(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.check_blocks(block_id, buf.len())?;
+        self.dev
+            .lock()
+            .write_block(self.start_block + block_id, buf)
+    }
+
+    fn check_blocks(&self, block_id: u64, len: usize) -> DevResult {
+        let num_blocks = (len / BLOCK_SIZE) as u64;
+        if len % BLOCK_SIZE != 0 || block_id.saturating_add(num_blocks) > self.num_blocks {
+            return Err(DevError::InvalidParam);
+        }
+        Ok(())
     }
 
     /// Get the position of the cursor.
@@ -40,8 +117,7 @@ impl Disk {
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
+            self.read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -50,7 +126,7 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.read_block(self.block_id, &mut data)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -67,7 +143,7 @@ impl Disk {
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
+            self.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -76,9 +152,9 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.read_block(self.block_id, &mut data)?;
             data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.write_block(self.block_id, &data)?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
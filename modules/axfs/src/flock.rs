@@ -0,0 +1,173 @@
+//! Advisory whole-file locks, like `flock`.
+//!
+//! A lock is held by an opened file, which can hold either an exclusive lock,
+//! or a shared one along with other opened files. Taking a lock replaces the
+//! one held by the opened file, releasing it even if the new one can not be
+//! taken, as on Linux.
+//!
+//! The files are identified by their nodes on the filesystems keeping them,
+//! e.g., the RAM filesystems, so that the hard links of a file share its
+//! locks, and by their paths, with the symbolic links resolved, on the other
+//! ones, which have no hard links. A lock is released when its file is unlocked or closed, or when
+//! the task which took it exits. The locks are advisory: the reads and
+//! writes of the files do not check them.
+//!
+//! Without the `multitask` feature, no other task can release a lock, so
+//! waiting for one fails with [`WouldBlock`](axerrno::AxError::WouldBlock).
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use axerrno::{AxResult, ax_err};
+use axfs_vfs::VfsNodeRef;
+use axsync::spin::SpinNoIrq;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The identity of a file, for its locks.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum FileKey {
+    /// The address of the node of the file, which is the same for all its
+    /// hard links while it is opened.
+    Node(usize),
+    /// The real path of the file, on the filesystems creating a node each
+    /// time a file is opened.
+    Path(String),
+}
+
+impl FileKey {
+    /// Returns the identity of the file at the real path `path`, opened with
+    /// `node`.
+    pub(crate) fn new(path: String, node: &VfsNodeRef) -> Self {
+        if crate::root::keeps_nodes(&path) {
+            Self::Node(alloc::sync::Arc::as_ptr(node) as *const () as usize)
+        } else {
+            Self::Path(path)
+        }
+    }
+}
+
+/// An opened file holding a lock, and the task which took it.
+struct Holder {
+    file: u64,
+    task: u64,
+}
+
+/// The locks held on a file: an exclusive one, or shared ones.
+#[derive(Default)]
+struct FileLocks {
+    exclusive: bool,
+    holders: Vec<Holder>,
+}
+
+/// The locks of the files, in a spinlock so that they can be taken by the
+/// condition of a wait queue.
+static LOCKS: SpinNoIrq<BTreeMap<FileKey, FileLocks>> = SpinNoIrq::new(BTreeMap::new());
+
+/// The tasks waiting for a lock.
+#[cfg(feature = "multitask")]
+static WAIT_QUEUE: axtask::WaitQueue = axtask::WaitQueue::new();
+
+static NEXT_FILE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the ID of a newly opened file.
+pub(crate) fn new_file_id() -> u64 {
+    NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn current_task() -> u64 {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.id().as_u64();
+    }
+    0
+}
+
+/// Wakes up the tasks waiting for a lock, once some are released.
+fn notify_waiters() {
+    #[cfg(feature = "multitask")]
+    WAIT_QUEUE.notify_all(true);
+}
+
+/// Releases the locks matching `f` on the files, forgetting the files without
+/// locks. Returns whether any is released.
+fn release(
+    locks: &mut BTreeMap<FileKey, FileLocks>,
+    key: Option<&FileKey>,
+    f: impl Fn(&Holder) -> bool,
+) -> bool {
+    let mut released = false;
+    locks.retain(|lock_key, file_locks| {
+        if key.is_some_and(|key| key != lock_key) {
+            return true;
+        }
+        let len = file_locks.holders.len();
+        file_locks.holders.retain(|holder| !f(holder));
+        released |= file_locks.holders.len() != len;
+        !file_locks.holders.is_empty()
+    });
+    released
+}
+
+/// Takes a lock for the opened file if no other one holds a conflicting lock,
+/// after releasing the one it holds. Returns whether it is taken, and whether
+/// a lock is released.
+fn try_lock(key: &FileKey, file: u64, exclusive: bool) -> (bool, bool) {
+    let mut locks = LOCKS.lock();
+    let released = release(&mut locks, Some(key), |holder| holder.file == file);
+    let holder = Holder {
+        file,
+        task: current_task(),
+    };
+    match locks.get_mut(key) {
+        Some(file_locks) if exclusive || file_locks.exclusive => (false, released),
+        Some(file_locks) => {
+            file_locks.holders.push(holder);
+            (true, released)
+        }
+        None => {
+            let file_locks = FileLocks {
+                exclusive,
+                holders: alloc::vec![holder],
+            };
+            locks.insert(key.clone(), file_locks);
+            (true, released)
+        }
+    }
+}
+
+/// Takes a lock on the file `key` for the opened file `file`, waiting for
+/// the conflicting locks to be released if `wait` is set.
+pub(crate) fn lock(key: &FileKey, file: u64, exclusive: bool, wait: bool) -> AxResult {
+    let (locked, released) = try_lock(key, file, exclusive);
+    if released {
+        notify_waiters();
+    }
+    if locked {
+        return Ok(());
+    } else if !wait {
+        return ax_err!(WouldBlock);
+    }
+    #[cfg(feature = "multitask")]
+    {
+        WAIT_QUEUE.wait_until(|| try_lock(key, file, exclusive).0);
+        Ok(())
+    }
+    #[cfg(not(feature = "multitask"))]
+    ax_err!(WouldBlock, "no other task can release the lock")
+}
+
+/// Releases the lock held by the opened file `file` on the file `key`.
+pub(crate) fn unlock(key: &FileKey, file: u64) {
+    let released = release(&mut LOCKS.lock(), Some(key), |holder| holder.file == file);
+    if released {
+        notify_waiters();
+    }
+}
+
+/// Releases the locks taken by a task, once it exits.
+#[cfg(feature = "multitask")]
+pub(crate) fn release_task_locks(task: axtask::TaskId) {
+    let task = task.as_u64();
+    let released = release(&mut LOCKS.lock(), None, |holder| holder.task == task);
+    if released {
+        notify_waiters();
+    }
+}
@@ -1,11 +1,14 @@
 //! Low-level filesystem operations.
 
+use alloc::string::String;
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
 
+use crate::flock::FileKey;
+
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
@@ -20,11 +23,20 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// The alignment of the offsets and lengths of direct I/O, i.e., the size of
+/// a block.
+pub const DIRECT_IO_ALIGN: usize = 512;
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
+    is_direct: bool,
     offset: u64,
+    /// The identity of the file, for the locks.
+    lock_key: FileKey,
+    /// The ID of this opened file, holding its locks.
+    id: u64,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +44,8 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    /// The real path of the directory, which the paths are relative to.
+    path: String,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -44,6 +58,7 @@ pub struct OpenOptions {
     truncate: bool,
     create: bool,
     create_new: bool,
+    direct: bool,
     // system-specific
     _custom_flags: i32,
     _mode: u32,
@@ -66,6 +81,7 @@ impl OpenOptions {
             truncate: false,
             create: false,
             create_new: false,
+            direct: false,
             // system-specific
             _custom_flags: 0,
             _mode: 0o666,
@@ -95,6 +111,15 @@ impl OpenOptions {
     pub fn create_new(&mut self, create_new: bool) {
         self.create_new = create_new;
     }
+    /// Sets the option for direct I/O, like `O_DIRECT`.
+    ///
+    /// Only block device files can be opened for direct I/O. Their reads and
+    /// writes must then be at offsets and of lengths that are multiples of
+    /// [`DIRECT_IO_ALIGN`], and go straight between the buffer and the device,
+    /// with one request to the driver.
+    pub fn direct(&mut self, direct: bool) {
+        self.direct = direct;
+    }
 
     const fn is_valid(&self) -> bool {
         if !self.read && !self.write && !self.append {
@@ -122,11 +147,27 @@ impl File {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    /// Checks the alignment of a transfer if the file is opened for direct
+    /// I/O.
+    fn check_direct(&self, offset: u64, len: usize) -> AxResult {
+        let align = DIRECT_IO_ALIGN as u64;
+        if self.is_direct && (offset % align != 0 || len as u64 % align != 0) {
+            return ax_err!(InvalidInput, "unaligned direct I/O");
+        }
+        Ok(())
+    }
+
+    fn _open_at(
+        dir: Option<&VfsNodeRef>,
+        dir_path: Option<&str>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
         }
+        let real_path = crate::root::real_path(dir_path, path)?;
 
         let node_option = crate::root::lookup(dir, path);
         let node = if opts.create || opts.create_new {
@@ -153,6 +194,9 @@ impl File {
         {
             return ax_err!(IsADirectory);
         }
+        if opts.direct && attr.file_type() != FileType::BlockDevice {
+            return ax_err!(InvalidInput);
+        }
         let access_cap = opts.into();
         if !perm_to_cap(attr.perm()).contains(access_cap) {
             return ax_err!(PermissionDenied);
@@ -162,17 +206,21 @@ impl File {
         if opts.truncate {
             node.truncate(0)?;
         }
+        let lock_key = FileKey::new(real_path, &node);
         Ok(Self {
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
+            is_direct: opts.direct,
             offset: 0,
+            lock_key,
+            id: crate::flock::new_file_id(),
         })
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
     /// [`File`] object.
     pub fn open(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_at(None, path, opts)
+        Self::_open_at(None, None, path, opts)
     }
 
     /// Truncates the file to the specified size.
@@ -186,6 +234,7 @@ impl File {
     ///
     /// After the read, the cursor will be advanced by the number of bytes read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        self.check_direct(self.offset, buf.len())?;
         let node = self.access_node(Cap::READ)?;
         let read_len = node.read_at(self.offset, buf)?;
         self.offset += read_len as u64;
@@ -196,6 +245,7 @@ impl File {
     ///
     /// It does not update the file cursor.
     pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.check_direct(offset, buf.len())?;
         let node = self.access_node(Cap::READ)?;
         let read_len = node.read_at(offset, buf)?;
         Ok(read_len)
@@ -212,6 +262,7 @@ impl File {
         } else {
             self.offset
         };
+        self.check_direct(offset, buf.len())?;
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
         self.offset = offset + write_len as u64;
@@ -223,6 +274,7 @@ impl File {
     ///
     /// It does not update the file cursor.
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.check_direct(offset, buf.len())?;
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
         Ok(write_len)
@@ -252,6 +304,43 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Takes an exclusive advisory lock on the file, waiting until no other
+    /// opened file holds a lock on it, like `flock(LOCK_EX)`.
+    ///
+    /// A lock held by this opened file is replaced, and released even if the
+    /// new one can not be taken. The locks are advisory, and are released
+    /// when the file is unlocked or closed, or when the task which took them
+    /// exits.
+    pub fn lock(&self) -> AxResult {
+        crate::flock::lock(&self.lock_key, self.id, true, true)
+    }
+
+    /// Takes a shared advisory lock on the file, waiting until no other
+    /// opened file holds an exclusive lock on it, like `flock(LOCK_SH)`.
+    pub fn lock_shared(&self) -> AxResult {
+        crate::flock::lock(&self.lock_key, self.id, false, true)
+    }
+
+    /// Takes an exclusive advisory lock on the file, or returns
+    /// [`WouldBlock`](AxError::WouldBlock) if another opened file holds a
+    /// lock on it.
+    pub fn try_lock(&self) -> AxResult {
+        crate::flock::lock(&self.lock_key, self.id, true, false)
+    }
+
+    /// Takes a shared advisory lock on the file, or returns
+    /// [`WouldBlock`](AxError::WouldBlock) if another opened file holds an
+    /// exclusive lock on it.
+    pub fn try_lock_shared(&self) -> AxResult {
+        crate::flock::lock(&self.lock_key, self.id, false, false)
+    }
+
+    /// Releases the lock held by this opened file, if any.
+    pub fn unlock(&self) -> AxResult {
+        crate::flock::unlock(&self.lock_key, self.id);
+        Ok(())
+    }
 }
 
 impl Directory {
@@ -259,7 +348,12 @@ impl Directory {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(
+        dir: Option<&VfsNodeRef>,
+        dir_path: Option<&str>,
+        path: &str,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
             return ax_err!(InvalidInput);
@@ -268,6 +362,7 @@ impl Directory {
             return ax_err!(InvalidInput);
         }
 
+        let real_path = crate::root::real_path(dir_path, path)?;
         let node = crate::root::lookup(dir, path)?;
         let attr = node.get_attr()?;
         if !attr.is_dir() {
@@ -282,6 +377,7 @@ impl Directory {
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            path: real_path,
         })
     }
 
@@ -296,19 +392,19 @@ impl Directory {
     /// Opens a directory at the path relative to the current directory.
     /// Returns a [`Directory`] object.
     pub fn open_dir(path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(None, path, opts)
+        Self::_open_dir_at(None, None, path, opts)
     }
 
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        Self::_open_dir_at(self.access_at(path)?, Some(&self.path), path, opts)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        File::_open_at(self.access_at(path)?, Some(&self.path), path, opts)
     }
 
     /// Creates an empty file at the path relative to this directory.
@@ -355,6 +451,7 @@ impl Directory {
 
 impl Drop for File {
     fn drop(&mut self) {
+        crate::flock::unlock(&self.lock_key, self.id);
         unsafe { self.node.access_unchecked().release().ok() };
     }
 }
@@ -386,6 +483,7 @@ impl fmt::Debug for OpenOptions {
         fmt_opt!(truncate, "TRUNC");
         fmt_opt!(create, "CREATE");
         fmt_opt!(create_new, "CREATE_NEW");
+        fmt_opt!(direct, "DIRECT");
         Ok(())
     }
 }
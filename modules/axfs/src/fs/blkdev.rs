@@ -0,0 +1,84 @@
+//! Block device files in `/dev`.
+
+use axdriver::prelude::DevError;
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::Mutex;
+
+use crate::dev::Disk;
+
+const BLOCK_SIZE: usize = 512;
+
+/// A block device file, e.g., `/dev/sda1`, to read and write a disk.
+pub struct BlockDevNode(Mutex<Disk>);
+
+impl BlockDevNode {
+    /// Creates a block device file of the disk.
+    pub fn new(disk: Disk) -> Self {
+        Self(Mutex::new(disk))
+    }
+}
+
+impl VfsNodeOps for BlockDevNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let disk = self.0.lock();
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::BlockDevice,
+            disk.size(),
+            disk.num_blocks(),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut disk = self.0.lock();
+        let len = buf.len().min(disk.size().saturating_sub(offset) as usize);
+        let mut read_len = direct_len(offset, len);
+        if read_len > 0 {
+            let block_id = offset / BLOCK_SIZE as u64;
+            disk.read_blocks(block_id, &mut buf[..read_len])
+                .map_err(as_vfs_err)?;
+        }
+        disk.set_position(offset + read_len as u64);
+        while read_len < len {
+            read_len += disk.read_one(&mut buf[read_len..len]).map_err(as_vfs_err)?;
+        }
+        Ok(read_len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut disk = self.0.lock();
+        let len = buf.len().min(disk.size().saturating_sub(offset) as usize);
+        let mut write_len = direct_len(offset, len);
+        if write_len > 0 {
+            let block_id = offset / BLOCK_SIZE as u64;
+            disk.write_blocks(block_id, &buf[..write_len])
+                .map_err(as_vfs_err)?;
+        }
+        disk.set_position(offset + write_len as u64);
+        while write_len < len {
+            write_len += disk.write_one(&buf[write_len..len]).map_err(as_vfs_err)?;
+        }
+        Ok(write_len)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The length of the whole blocks from `offset` in `len` bytes, transferred
+/// directly between the buffer and the device, or 0 if `offset` is not at the
+/// start of a block. The rest goes through a buffer of one block.
+const fn direct_len(offset: u64, len: usize) -> usize {
+    if offset % BLOCK_SIZE as u64 == 0 {
+        len / BLOCK_SIZE * BLOCK_SIZE
+    } else {
+        0
+    }
+}
+
+const fn as_vfs_err(err: DevError) -> VfsError {
+    match err {
+        DevError::InvalidParam => VfsError::InvalidInput,
+        DevError::Unsupported => VfsError::Unsupported,
+        _ => VfsError::Io,
+    }
+}
@@ -6,6 +6,8 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(feature = "devfs")]
+pub mod blkdev;
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
@@ -0,0 +1,778 @@
+//! Checking and repairing FAT filesystems (`fsck`).
+//!
+//! [`check`] validates the cluster chains of all files and directories, the
+//! sizes of the files, the copies of the FAT, and the free cluster count of
+//! FAT32. It repairs the inconsistencies a power loss usually leaves, like
+//! `fsck.vfat -a`:
+//!
+//! - a chain going to an invalid, free or bad cluster, or to a cluster of
+//!   another chain (cross-linked), is truncated before it;
+//! - a file whose chain is longer than its size has the extra clusters
+//!   freed, and one whose chain is shorter has its size reduced;
+//! - a directory without a valid first cluster is removed;
+//! - the clusters allocated but in no chain (lost) are freed;
+//! - the copies of the FAT are overwritten by the first one;
+//! - the free cluster count of FAT32 is recomputed.
+//!
+//! The filesystem must not be in use while it is repaired, so the mounted
+//! main filesystem can only be checked with [`check_device`]. With the `fsck`
+//! feature, it is checked and repaired at boot, before being mounted.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt;
+
+use axdriver::prelude::{DevError, DevResult};
+use axerrno::{AxError, AxResult, ax_err};
+use axfs_vfs::VfsNodeType;
+use lazyinit::LazyInit;
+
+const BLOCK_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+/// The attributes of a long file name entry.
+const ATTR_LFN: u8 = 0x0f;
+/// The first byte of the name of a deleted directory entry.
+const DELETED: u8 = 0xe5;
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+/// The free cluster count, or next free cluster, when it is unknown.
+const FSINFO_UNKNOWN: u32 = 0xffff_ffff;
+
+/// The offsets of the 13 UTF-16 characters in a long file name entry.
+const LFN_CHAR_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// The maximum number of entries of a long file name.
+const LFN_MAX_ENTRIES: usize = 20;
+
+/// The device of the main filesystem, e.g., `/dev/sda1`, which is mounted.
+pub(crate) static MAIN_DEVICE: LazyInit<String> = LazyInit::new();
+
+/// The type of a FAT filesystem, given by its number of clusters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// The value of the FAT entry of a bad cluster.
+    const fn bad(self) -> u32 {
+        match self {
+            Self::Fat12 => 0xff7,
+            Self::Fat16 => 0xfff7,
+            Self::Fat32 => 0x0fff_fff7,
+        }
+    }
+
+    /// The value written to the FAT entry of the last cluster of a chain.
+    /// Any value above [`bad`](Self::bad) ends a chain.
+    const fn end_of_chain(self) -> u32 {
+        match self {
+            Self::Fat12 => 0xfff,
+            Self::Fat16 => 0xffff,
+            Self::Fat32 => 0x0fff_ffff,
+        }
+    }
+}
+
+/// An inconsistency found by [`check`], with the way it is repaired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The chain of a file or directory goes to a cluster out of the data
+    /// area, free or bad. It is truncated before it, or the file is emptied
+    /// if it is its first cluster.
+    InvalidCluster { path: String, cluster: u32 },
+    /// The chain of a file or directory goes to a cluster already in a
+    /// chain, maybe its own. It is truncated before it, or the file is
+    /// emptied if it is its first cluster.
+    CrossLinked { path: String, cluster: u32 },
+    /// The size of a file does not match the number of clusters of its
+    /// chain. The extra clusters are freed, or the size is reduced to that
+    /// of the clusters.
+    SizeMismatch {
+        path: String,
+        size: u32,
+        clusters: u32,
+    },
+    /// A directory has no valid first cluster. It is removed.
+    InvalidDirectory { path: String },
+    /// Clusters are allocated but in no chain. They are freed.
+    LostClusters(u32),
+    /// A copy of the FAT, numbered from 0, differs from the first one. It is
+    /// overwritten.
+    FatMismatch(usize),
+    /// The free cluster count of FAT32 is wrong. It is recomputed.
+    FreeCount { recorded: u32, actual: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCluster { path, cluster } => {
+                write!(f, "{}: invalid cluster {} in the chain", path, cluster)
+            }
+            Self::CrossLinked { path, cluster } => {
+                write!(f, "{}: cross-linked at cluster {}", path, cluster)
+            }
+            Self::SizeMismatch {
+                path,
+                size,
+                clusters,
+            } => write!(f, "{}: size {} but {} clusters", path, size, clusters),
+            Self::InvalidDirectory { path } => {
+                write!(f, "{}: directory without a valid cluster", path)
+            }
+            Self::LostClusters(n) => write!(f, "{} lost clusters", n),
+            Self::FatMismatch(n) => write!(f, "FAT copy {} differs from the first one", n),
+            Self::FreeCount { recorded, actual } => {
+                write!(
+                    f,
+                    "free cluster count is {}, should be {}",
+                    recorded, actual
+                )
+            }
+        }
+    }
+}
+
+/// The result of [`check`].
+#[derive(Debug, Clone)]
+pub struct FsckReport {
+    pub fat_type: FatType,
+    /// The size of a cluster in bytes.
+    pub cluster_size: u32,
+    /// The number of clusters of the data area.
+    pub num_clusters: u32,
+    /// The number of free clusters, after the repairs if any.
+    pub free_clusters: u32,
+    /// The number of files, not counting directories.
+    pub files: u32,
+    /// The number of directories, not counting the root one.
+    pub dirs: u32,
+    /// The inconsistencies found.
+    pub problems: Vec<Problem>,
+    /// Whether the problems were repaired.
+    pub repaired: bool,
+}
+
+/// A FAT filesystem being checked.
+struct Volume<R, W> {
+    read_block: R,
+    write_block: W,
+    repair: bool,
+    fat_type: FatType,
+    sectors_per_cluster: u64,
+    /// The first sector of the first FAT.
+    fat_start: u64,
+    fat_sectors: u64,
+    num_fats: u64,
+    /// The sectors of the fixed root directory of FAT12 and FAT16.
+    root_sectors: core::ops::Range<u64>,
+    /// The first cluster of the root directory of FAT32.
+    root_cluster: u32,
+    /// The sector of the FSInfo structure of FAT32.
+    fsinfo_sector: u64,
+    /// The first sector of cluster 2.
+    data_start: u64,
+    num_clusters: u32,
+    /// The first FAT, repaired in memory, then written to all copies.
+    fat: Vec<u8>,
+    /// The sectors of the FAT changed.
+    fat_dirty: Vec<bool>,
+    /// The clusters in a chain, by cluster number.
+    used: Vec<bool>,
+    /// The directories to check, with their path and sectors.
+    pending_dirs: Vec<(String, Vec<u64>)>,
+    report: FsckReport,
+}
+
+fn le16(bytes: &[u8]) -> u32 {
+    u16::from_le_bytes([bytes[0], bytes[1]]) as u32
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// Checks the FAT filesystem on a device of 512-byte blocks, with
+/// `read_block` and `write_block` reading and writing a block, and repairs
+/// it if `repair` is set. Nothing is written otherwise.
+///
+/// Returns [`DevError::InvalidParam`] if the device does not hold a FAT
+/// filesystem with 512-byte sectors, or if its root directory is invalid.
+pub fn check(
+    read_block: impl FnMut(u64, &mut [u8]) -> DevResult,
+    write_block: impl FnMut(u64, &[u8]) -> DevResult,
+    repair: bool,
+) -> DevResult<FsckReport> {
+    let mut volume = Volume::open(read_block, write_block, repair)?;
+    volume.check_fat_copies()?;
+    volume.check_root()?;
+    while let Some((path, sectors)) = volume.pending_dirs.pop() {
+        volume.check_dir(&path, &sectors)?;
+    }
+    volume.free_lost_clusters();
+    volume.check_free_count()?;
+    volume.write_fat()?;
+    let mut report = volume.report;
+    report.repaired = repair && !report.problems.is_empty();
+    Ok(report)
+}
+
+impl<R, W> Volume<R, W>
+where
+    R: FnMut(u64, &mut [u8]) -> DevResult,
+    W: FnMut(u64, &[u8]) -> DevResult,
+{
+    /// Reads the boot sector and the first FAT.
+    fn open(mut read_block: R, write_block: W, repair: bool) -> DevResult<Self> {
+        let mut bs = [0; BLOCK_SIZE];
+        read_block(0, &mut bs)?;
+        let bytes_per_sector = le16(&bs[11..]);
+        let sectors_per_cluster = bs[13] as u64;
+        let reserved = le16(&bs[14..]) as u64;
+        let num_fats = bs[16] as u64;
+        let root_entries = le16(&bs[17..]) as u64;
+        let total_sectors = match le16(&bs[19..]) {
+            0 => le32(&bs[32..]),
+            n => n,
+        } as u64;
+        let fat_sectors = match le16(&bs[22..]) {
+            0 => le32(&bs[36..]),
+            n => n,
+        } as u64;
+        if !matches!(bs[0], 0xeb | 0xe9)
+            || bs[510..] != [0x55, 0xaa]
+            || bytes_per_sector as usize != BLOCK_SIZE
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || num_fats == 0
+            || fat_sectors == 0
+        {
+            return Err(DevError::InvalidParam);
+        }
+
+        let root_start = reserved + num_fats * fat_sectors;
+        let data_start =
+            root_start + (root_entries * DIR_ENTRY_SIZE as u64).div_ceil(BLOCK_SIZE as u64);
+        if total_sectors <= data_start {
+            return Err(DevError::InvalidParam);
+        }
+        let num_clusters = ((total_sectors - data_start) / sectors_per_cluster) as u32;
+        let fat_type = match num_clusters {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let entry_bits = match fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        if (num_clusters as u64 + 2) * entry_bits > fat_sectors * BLOCK_SIZE as u64 * 8
+            || (fat_type == FatType::Fat32) != (root_entries == 0)
+        {
+            return Err(DevError::InvalidParam);
+        }
+
+        let mut fat = vec![0; fat_sectors as usize * BLOCK_SIZE];
+        for (i, sector) in fat.chunks_mut(BLOCK_SIZE).enumerate() {
+            read_block(reserved + i as u64, sector)?;
+        }
+        Ok(Self {
+            read_block,
+            write_block,
+            repair,
+            fat_type,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_sectors,
+            num_fats,
+            root_sectors: root_start..data_start,
+            root_cluster: le32(&bs[44..]),
+            fsinfo_sector: le16(&bs[48..]) as u64,
+            data_start,
+            num_clusters,
+            fat,
+            fat_dirty: vec![false; fat_sectors as usize],
+            used: vec![false; num_clusters as usize + 2],
+            pending_dirs: Vec::new(),
+            report: FsckReport {
+                fat_type,
+                cluster_size: (sectors_per_cluster as usize * BLOCK_SIZE) as u32,
+                num_clusters,
+                free_clusters: 0,
+                files: 0,
+                dirs: 0,
+                problems: Vec::new(),
+                repaired: false,
+            },
+        })
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> DevResult {
+        if self.repair {
+            (self.write_block)(sector, buf)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn problem(&mut self, problem: Problem) {
+        warn!("fsck: {}", problem);
+        self.report.problems.push(problem);
+    }
+
+    /// Returns the FAT entry of a cluster, i.e., the next cluster of its
+    /// chain.
+    fn get(&self, cluster: u32) -> u32 {
+        let n = cluster as usize;
+        match self.fat_type {
+            FatType::Fat12 => {
+                let entry = le16(&self.fat[n + n / 2..]);
+                if n % 2 == 1 {
+                    entry >> 4
+                } else {
+                    entry & 0xfff
+                }
+            }
+            FatType::Fat16 => le16(&self.fat[2 * n..]),
+            FatType::Fat32 => le32(&self.fat[4 * n..]) & 0x0fff_ffff,
+        }
+    }
+
+    /// Sets the FAT entry of a cluster, keeping the reserved bits.
+    fn set(&mut self, cluster: u32, value: u32) {
+        let n = cluster as usize;
+        let (offset, len, entry) = match self.fat_type {
+            FatType::Fat12 => {
+                let old = le16(&self.fat[n + n / 2..]);
+                let entry = if n % 2 == 1 {
+                    (old & 0x000f) | (value << 4)
+                } else {
+                    (old & 0xf000) | value
+                };
+                (n + n / 2, 2, entry)
+            }
+            FatType::Fat16 => (2 * n, 2, value),
+            FatType::Fat32 => (4 * n, 4, (le32(&self.fat[4 * n..]) & 0xf000_0000) | value),
+        };
+        self.fat[offset..offset + len].copy_from_slice(&entry.to_le_bytes()[..len]);
+        self.fat_dirty[offset / BLOCK_SIZE] = true;
+        self.fat_dirty[(offset + len - 1) / BLOCK_SIZE] = true;
+    }
+
+    /// Whether a cluster is in the data area and allocated, i.e., can be in
+    /// a chain.
+    fn is_allocated(&self, cluster: u32) -> bool {
+        (2..self.num_clusters + 2).contains(&cluster)
+            && !matches!(self.get(cluster), 0 | 1)
+            && self.get(cluster) != self.fat_type.bad()
+    }
+
+    /// Compares the other copies of the FAT with the first one, before it
+    /// is repaired.
+    fn check_fat_copies(&mut self) -> DevResult {
+        let mut buf = [0; BLOCK_SIZE];
+        for copy in 1..self.num_fats {
+            let mut differs = false;
+            for i in 0..self.fat_sectors as usize {
+                (self.read_block)(
+                    self.fat_start + copy * self.fat_sectors + i as u64,
+                    &mut buf,
+                )?;
+                if buf != self.fat[i * BLOCK_SIZE..][..BLOCK_SIZE] {
+                    self.fat_dirty[i] = true;
+                    differs = true;
+                }
+            }
+            if differs {
+                self.problem(Problem::FatMismatch(copy as usize));
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows the chain from `start`, marking its clusters as used, and
+    /// truncates it before an invalid or cross-linked cluster. Returns its
+    /// clusters, or the problem of its first cluster, for the caller to
+    /// handle.
+    fn follow_chain(&mut self, path: &str, start: u32) -> Result<Vec<u32>, Problem> {
+        let path = String::from(path);
+        if !self.is_allocated(start) {
+            return Err(Problem::InvalidCluster {
+                path,
+                cluster: start,
+            });
+        }
+        if self.used[start as usize] {
+            return Err(Problem::CrossLinked {
+                path,
+                cluster: start,
+            });
+        }
+        let mut chain = vec![start];
+        self.used[start as usize] = true;
+        let mut cluster = start;
+        loop {
+            let next = self.get(cluster);
+            if next > self.fat_type.bad() {
+                break;
+            }
+            let problem = if !self.is_allocated(next) {
+                Problem::InvalidCluster {
+                    path,
+                    cluster: next,
+                }
+            } else if self.used[next as usize] {
+                Problem::CrossLinked {
+                    path,
+                    cluster: next,
+                }
+            } else {
+                chain.push(next);
+                self.used[next as usize] = true;
+                cluster = next;
+                continue;
+            };
+            self.problem(problem);
+            self.set(cluster, self.fat_type.end_of_chain());
+            break;
+        }
+        Ok(chain)
+    }
+
+    /// Returns the sectors of the clusters of a chain.
+    fn chain_sectors(&self, chain: &[u32]) -> Vec<u64> {
+        chain
+            .iter()
+            .flat_map(|&cluster| {
+                let first = self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster;
+                first..first + self.sectors_per_cluster
+            })
+            .collect()
+    }
+
+    fn check_root(&mut self) -> DevResult {
+        let sectors = if self.fat_type == FatType::Fat32 {
+            match self.follow_chain("/", self.root_cluster) {
+                Ok(chain) => self.chain_sectors(&chain),
+                Err(problem) => {
+                    warn!("fsck: invalid root directory: {}", problem);
+                    return Err(DevError::InvalidParam);
+                }
+            }
+        } else {
+            self.root_sectors.clone().collect()
+        };
+        self.check_dir("", &sectors)
+    }
+
+    /// Checks the entries of the directory in the given sectors, and queues
+    /// its subdirectories.
+    fn check_dir(&mut self, path: &str, sectors: &[u64]) -> DevResult {
+        let mut buf = [0; BLOCK_SIZE];
+        let mut long_name = LongName::new();
+        for &sector in sectors {
+            (self.read_block)(sector, &mut buf)?;
+            let mut dirty = false;
+            for entry in buf.chunks_mut(DIR_ENTRY_SIZE) {
+                match entry[0] {
+                    0 => break,
+                    DELETED => {
+                        long_name.reset();
+                        continue;
+                    }
+                    _ => {}
+                }
+                let attr = entry[11];
+                if attr & 0x3f == ATTR_LFN {
+                    long_name.push(entry);
+                    continue;
+                }
+                let name = long_name.take(entry);
+                if attr & ATTR_VOLUME_ID != 0 || entry[0] == b'.' {
+                    continue;
+                }
+                let path = format!("{}/{}", path, name);
+                dirty |= self.check_entry(path, entry);
+            }
+            if dirty {
+                self.write(sector, &buf)?;
+            }
+            // The end of the directory.
+            if buf.chunks(DIR_ENTRY_SIZE).any(|entry| entry[0] == 0) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a directory entry, other than `.` and `..`. Returns whether it
+    /// was changed.
+    fn check_entry(&mut self, path: String, entry: &mut [u8]) -> bool {
+        let mut start = le16(&entry[26..]);
+        if self.fat_type == FatType::Fat32 {
+            start |= le16(&entry[20..]) << 16;
+        }
+        let size = le32(&entry[28..]);
+
+        if entry[11] & ATTR_DIRECTORY != 0 {
+            self.report.dirs += 1;
+            return match self.follow_chain(&path, start) {
+                Ok(chain) => {
+                    let sectors = self.chain_sectors(&chain);
+                    self.pending_dirs.push((path, sectors));
+                    false
+                }
+                Err(_) => {
+                    self.problem(Problem::InvalidDirectory { path });
+                    entry[0] = DELETED;
+                    true
+                }
+            };
+        }
+
+        self.report.files += 1;
+        let chain = match start {
+            0 => Ok(Vec::new()),
+            _ => self.follow_chain(&path, start),
+        };
+        let new_size = match chain {
+            Ok(chain) => self.check_size(path, &chain, size),
+            Err(problem) => {
+                self.problem(problem);
+                0
+            }
+        };
+        // An empty file has no cluster.
+        let new_start = if new_size == 0 { 0 } else { start };
+        if (new_start, new_size) == (start, size) {
+            return false;
+        }
+        entry[26..28].copy_from_slice(&(new_start as u16).to_le_bytes());
+        if self.fat_type == FatType::Fat32 {
+            entry[20..22].copy_from_slice(&((new_start >> 16) as u16).to_le_bytes());
+        }
+        entry[28..32].copy_from_slice(&new_size.to_le_bytes());
+        true
+    }
+
+    /// Checks the size of a file against the number of clusters of its
+    /// chain, freeing the extra clusters. Returns the size, reduced to that
+    /// of the chain if it is shorter.
+    fn check_size(&mut self, path: String, chain: &[u32], size: u32) -> u32 {
+        let cluster_size = self.report.cluster_size;
+        let needed = size.div_ceil(cluster_size) as usize;
+        if chain.len() == needed {
+            return size;
+        }
+        self.problem(Problem::SizeMismatch {
+            path,
+            size,
+            clusters: chain.len() as u32,
+        });
+        if chain.len() < needed {
+            return chain.len() as u32 * cluster_size;
+        }
+        if needed > 0 {
+            self.set(chain[needed - 1], self.fat_type.end_of_chain());
+        }
+        for &cluster in &chain[needed..] {
+            self.set(cluster, 0);
+            self.used[cluster as usize] = false;
+        }
+        size
+    }
+
+    /// Frees the clusters allocated but in no chain, and counts the free
+    /// clusters.
+    fn free_lost_clusters(&mut self) {
+        let mut lost = 0;
+        let mut free = 0;
+        for cluster in 2..self.num_clusters + 2 {
+            match self.get(cluster) {
+                0 => free += 1,
+                entry if entry == self.fat_type.bad() => {}
+                _ if !self.used[cluster as usize] => {
+                    self.set(cluster, 0);
+                    lost += 1;
+                    free += 1;
+                }
+                _ => {}
+            }
+        }
+        if lost > 0 {
+            self.problem(Problem::LostClusters(lost));
+        }
+        self.report.free_clusters = free;
+    }
+
+    /// Checks the free cluster count in the FSInfo structure of FAT32, and
+    /// updates it with the next free cluster.
+    fn check_free_count(&mut self) -> DevResult {
+        if self.fat_type != FatType::Fat32 || self.fsinfo_sector == 0 {
+            return Ok(());
+        }
+        let mut fsinfo = [0; BLOCK_SIZE];
+        (self.read_block)(self.fsinfo_sector, &mut fsinfo)?;
+        if le32(&fsinfo[0..]) != FSINFO_LEAD_SIG || le32(&fsinfo[484..]) != FSINFO_STRUCT_SIG {
+            warn!("fsck: invalid FSInfo structure");
+            return Ok(());
+        }
+        let recorded = le32(&fsinfo[488..]);
+        let actual = self.report.free_clusters;
+        if recorded == actual || recorded == FSINFO_UNKNOWN {
+            return Ok(());
+        }
+        self.problem(Problem::FreeCount { recorded, actual });
+        let next_free = (2..self.num_clusters + 2)
+            .find(|&cluster| self.get(cluster) == 0)
+            .unwrap_or(FSINFO_UNKNOWN);
+        fsinfo[488..492].copy_from_slice(&actual.to_le_bytes());
+        fsinfo[492..496].copy_from_slice(&next_free.to_le_bytes());
+        self.write(self.fsinfo_sector, &fsinfo)
+    }
+
+    /// Writes the changed sectors of the FAT to all its copies.
+    fn write_fat(&mut self) -> DevResult {
+        for i in 0..self.fat_sectors as usize {
+            if !self.fat_dirty[i] {
+                continue;
+            }
+            let sector: [u8; BLOCK_SIZE] =
+                self.fat[i * BLOCK_SIZE..][..BLOCK_SIZE].try_into().unwrap();
+            for copy in 0..self.num_fats {
+                self.write(self.fat_start + copy * self.fat_sectors + i as u64, &sector)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The long file name being assembled from the entries before a short one.
+struct LongName {
+    chars: [u16; 13 * LFN_MAX_ENTRIES],
+    /// The checksum of the short name, in all the entries.
+    checksum: u8,
+    valid: bool,
+}
+
+impl LongName {
+    const fn new() -> Self {
+        Self {
+            chars: [0; 13 * LFN_MAX_ENTRIES],
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.valid = false;
+    }
+
+    /// Adds a long file name entry. They come in reverse order, the last one
+    /// first.
+    fn push(&mut self, entry: &[u8]) {
+        let order = (entry[0] & 0x1f) as usize;
+        if entry[0] & 0x40 != 0 {
+            self.chars.fill(0xffff);
+            self.checksum = entry[13];
+            self.valid = true;
+        }
+        if order == 0 || order > LFN_MAX_ENTRIES || entry[13] != self.checksum {
+            self.valid = false;
+        }
+        if !self.valid {
+            return;
+        }
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.chars[(order - 1) * 13 + i] = le16(&entry[offset..]) as u16;
+        }
+    }
+
+    /// Returns the name of the short entry, which is the long one if it
+    /// belongs to it.
+    fn take(&mut self, entry: &[u8]) -> String {
+        let checksum = entry[..11]
+            .iter()
+            .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b));
+        if core::mem::take(&mut self.valid) && checksum == self.checksum {
+            let len = self.chars.iter().position(|&c| c == 0 || c == 0xffff);
+            let chars = &self.chars[..len.unwrap_or(self.chars.len())];
+            return char::decode_utf16(chars.iter().copied())
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect();
+        }
+        // The 8.3 name, whose bytes are in an OEM code page.
+        let to_str = |bytes: &[u8]| -> String {
+            let bytes = bytes.trim_ascii_end();
+            bytes
+                .iter()
+                .map(|&b| if b.is_ascii() { b as char } else { '?' })
+                .collect()
+        };
+        let mut name = to_str(&entry[..8]);
+        // The first byte 0x05 stands for 0xe5.
+        if entry[0] == 0x05 {
+            name.replace_range(..1, "?");
+        }
+        let ext = to_str(&entry[8..11]);
+        if !ext.is_empty() {
+            name.push('.');
+            name.push_str(&ext);
+        }
+        name
+    }
+}
+
+/// Checks the FAT filesystem on a block device file, e.g., `/dev/sda1`, and
+/// repairs it if `repair` is set. See [`check`].
+///
+/// The device of the main filesystem can not be repaired, as it is mounted.
+pub fn check_device(path: &str, repair: bool) -> AxResult<FsckReport> {
+    let abs_path = crate::root::absolute_path(path)?;
+    if repair && MAIN_DEVICE.get() == Some(&abs_path) {
+        return ax_err!(ResourceBusy, "the filesystem is mounted");
+    }
+    let node = crate::root::lookup(None, path)?;
+    if node.get_attr()?.file_type() != VfsNodeType::BlockDevice {
+        return ax_err!(InvalidInput, "not a block device");
+    }
+    let read_block = |id: u64, buf: &mut [u8]| match node.read_at(id * BLOCK_SIZE as u64, buf) {
+        Ok(BLOCK_SIZE) => Ok(()),
+        _ => Err(DevError::Io),
+    };
+    let write_block = |id: u64, buf: &[u8]| match node.write_at(id * BLOCK_SIZE as u64, buf) {
+        Ok(BLOCK_SIZE) => Ok(()),
+        _ => Err(DevError::Io),
+    };
+    check(read_block, write_block, repair).map_err(|e| match e {
+        DevError::InvalidParam => AxError::InvalidData,
+        _ => AxError::Io,
+    })
+}
+
+/// Checks and repairs the main filesystem before it is mounted.
+#[cfg(feature = "fsck")]
+pub(crate) fn check_main_disk(disk: &crate::dev::Disk) {
+    let read_block = |id, buf: &mut [u8]| disk.read_block(id, buf);
+    let write_block = |id, buf: &[u8]| disk.write_block(id, buf);
+    match check(read_block, write_block, true) {
+        Ok(report) if report.problems.is_empty() => info!(
+            "  fsck: clean, {} files, {}/{} clusters free",
+            report.files, report.free_clusters, report.num_clusters
+        ),
+        Ok(report) => warn!(
+            "  fsck: repaired {} problems, {} files, {}/{} clusters free",
+            report.problems.len(),
+            report.files,
+            report.free_clusters,
+            report.num_clusters
+        ),
+        Err(e) => warn!("  fsck: failed to check the main filesystem: {:?}", e),
+    }
+}
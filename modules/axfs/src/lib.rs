@@ -15,6 +15,21 @@
 //!   to create and initialize other filesystems. This feature is **disabled** by
 //!   by default, but it will override other filesystem selection features if
 //!   both are enabled.
+//! - `fsck`: Check the main FAT filesystem and repair it at boot, before
+//!   mounting it (see [`fsck`]). This feature is **disabled** by default.
+//! - `multitask`: Give each task its own current directory, file mode
+//!   creation mask and root directory (see [`api::unshare_root`]), inherited
+//!   from the task spawning it. Otherwise, they are shared by all the code.
+//!   This feature is **disabled** by default.
+//! - `aio`: Provide asynchronous file operations in [`aio`], run on a pool of
+//!   tasks. This feature is **disabled** by default.
+//! - `swap`: Swap out user pages to the first Linux swap partition of the
+//!   block device, if any, under memory pressure. This feature is
+//!   **disabled** by default.
+//!
+//! The main filesystem is on the first FAT partition of the block device, or on
+//! the whole device if it has no partition table (see [`partition`]). The
+//! device and its partitions are exposed in `/dev` as `sda`, `sda1`, etc.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -27,13 +42,22 @@ extern crate log;
 extern crate alloc;
 
 mod dev;
+mod flock;
 mod fs;
+mod link;
 mod mounts;
 mod root;
+#[cfg(feature = "swap")]
+mod swap;
 
+#[cfg(feature = "aio")]
+pub mod aio;
 pub mod api;
 pub mod fops;
+pub mod fsck;
+pub mod partition;
 
+use alloc::{format, vec, vec::Vec};
 use axdriver::{AxDeviceContainer, prelude::*};
 
 /// Initializes filesystems by block devices.
@@ -42,5 +66,40 @@ pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
 
     let dev = blk_devs.take_one().expect("No block device found!");
     info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let disk = self::dev::Disk::new(dev);
+    let partitions = partition::scan(|id, buf| disk.read_block(id, buf), disk.num_blocks())
+        .unwrap_or_else(|e| {
+            warn!("  failed to read the partition table: {:?}", e);
+            Vec::new()
+        });
+
+    let mut block_devs = vec![("sda", disk.slice(0, disk.num_blocks()))];
+    for part in &partitions {
+        let name: &'static str = format!("sda{}", part.number).leak();
+        info!(
+            "  {}: {} blocks from block {}, {:?}",
+            name, part.num_blocks, part.start_block, part.ty
+        );
+        block_devs.push((name, disk.slice(part.start_block, part.num_blocks)));
+    }
+    #[cfg(feature = "swap")]
+    if let Some(part) = partitions.iter().find(|part| part.ty.is_swap()) {
+        info!("  swap on sda{}", part.number);
+        swap::init(disk.slice(part.start_block, part.num_blocks));
+    }
+    let (main_device, main_disk) = match partitions.iter().find(|part| part.ty.is_fat()) {
+        Some(part) => {
+            info!("  main filesystem on sda{}", part.number);
+            let disk = disk.slice(part.start_block, part.num_blocks);
+            (format!("/dev/sda{}", part.number), disk)
+        }
+        None => ("/dev/sda".into(), disk),
+    };
+    fsck::MAIN_DEVICE.init_once(main_device);
+    // A RAM disk is formatted when mounted.
+    #[cfg(all(feature = "fsck", not(feature = "myfs"), not(feature = "use-ramdisk")))]
+    fsck::check_main_disk(&main_disk);
+    self::root::init_rootfs(main_disk, block_devs);
+    #[cfg(feature = "multitask")]
+    axtask::on_exit(flock::release_task_locks);
 }
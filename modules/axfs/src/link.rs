@@ -0,0 +1,140 @@
+//! Symbolic and hard links.
+//!
+//! The VFS interface and the filesystems have no links, so they are kept here
+//! by absolute path, above the mounted filesystems. Each link is backed by an
+//! empty file at its path, so that it is listed in its directory and that its
+//! name can not be taken by another file. The links are not saved on the
+//! disk: after a reboot, only these empty files are left.
+//!
+//! A hard link keeps the node of its file, so it is only allowed on the
+//! filesystems keeping the data of their files while a node is referenced,
+//! e.g., the RAM filesystems.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use axerrno::{AxResult, ax_err};
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default maximum number of symbolic links followed in a path, as on
+/// Linux.
+const DEFAULT_MAX_FOLLOWS: usize = 40;
+
+#[derive(Clone)]
+pub(crate) enum Link {
+    /// A symbolic link, with the path it points to.
+    Symbolic(String),
+    /// A hard link, with the node of the file.
+    Hard(VfsNodeRef),
+}
+
+static LINKS: Mutex<BTreeMap<String, Link>> = Mutex::new(BTreeMap::new());
+
+static MAX_FOLLOWS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_FOLLOWS);
+
+/// Sets the maximum number of symbolic links followed in a path, returning
+/// the previous one.
+pub(crate) fn set_max_follows(max: usize) -> usize {
+    MAX_FOLLOWS.swap(max, Ordering::Relaxed)
+}
+
+/// Whether there is any link.
+pub(crate) fn has_links() -> bool {
+    !LINKS.lock().is_empty()
+}
+
+/// Replaces the symbolic links in an absolute and canonical path by the paths
+/// they point to, the last component only if `follow_last` is set.
+///
+/// The absolute paths pointed to, and `..`, do not go above `root`, the root
+/// directory of the task (see [`unshare_root`]), or the empty string.
+///
+/// Returns an error if more than the maximum number of links are followed,
+/// e.g., if they make a loop.
+///
+/// [`unshare_root`]: crate::root::unshare_root
+pub(crate) fn resolve(path: &str, follow_last: bool, root: &str) -> AxResult<String> {
+    let links = LINKS.lock();
+    let max_follows = MAX_FOLLOWS.load(Ordering::Relaxed);
+
+    // The components left to resolve, the next one last.
+    let mut pending: Vec<String> = components(path).rev().map(String::from).collect();
+    // The path resolved so far, without the trailing `/`.
+    let mut resolved = String::new();
+    let mut follows = 0;
+    while let Some(name) = pending.pop() {
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                let in_root = resolved
+                    .strip_prefix(root)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+                let floor = if in_root { root.len() } else { 0 };
+                resolved.truncate(resolved.rfind('/').unwrap_or(0).max(floor));
+                continue;
+            }
+            _ => {}
+        }
+        let parent_len = resolved.len();
+        resolved.push('/');
+        resolved.push_str(&name);
+        if !pending.is_empty() || follow_last {
+            if let Some(Link::Symbolic(target)) = links.get(&resolved) {
+                follows += 1;
+                if follows > max_follows {
+                    return ax_err!(InvalidInput, "too many levels of symbolic links");
+                }
+                if target.starts_with('/') {
+                    resolved.clear();
+                    if !crate::root::is_shared(target) {
+                        resolved.push_str(root);
+                    }
+                } else {
+                    resolved.truncate(parent_len);
+                }
+                pending.extend(components(target).rev().map(String::from));
+            }
+        }
+    }
+    if resolved.is_empty() {
+        resolved.push('/');
+    }
+    Ok(resolved)
+}
+
+fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+/// Returns the path a symbolic link points to, or the node of a hard link.
+pub(crate) fn get(path: &str) -> Option<Link> {
+    LINKS.lock().get(path).cloned()
+}
+
+/// Adds a link, whose backing file was created.
+pub(crate) fn add(path: String, link: Link) {
+    LINKS.lock().insert(path, link);
+}
+
+/// Removes a link, if there is one at the path.
+pub(crate) fn remove(path: &str) {
+    LINKS.lock().remove(path);
+}
+
+/// Moves the links at `old` or under it, once it has been renamed to `new`.
+pub(crate) fn rename(old: &str, new: &str) {
+    let mut links = LINKS.lock();
+    links.remove(new);
+    let moved: Vec<String> = links
+        .keys()
+        .filter(|path| {
+            path.strip_prefix(old)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .cloned()
+        .collect();
+    for path in moved {
+        let link = links.remove(&path).unwrap();
+        links.insert(String::from(new) + &path[old.len()..], link);
+    }
+}
@@ -4,7 +4,9 @@ use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
 use crate::fs;
 
 #[cfg(feature = "devfs")]
-pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
+pub(crate) fn devfs(
+    block_devs: alloc::vec::Vec<(&'static str, crate::dev::Disk)>,
+) -> Arc<fs::devfs::DeviceFileSystem> {
     let null = fs::devfs::NullDev;
     let zero = fs::devfs::ZeroDev;
     let bar = fs::devfs::ZeroDev;
@@ -13,6 +15,9 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     foo_dir.add("bar", Arc::new(bar));
+    for (name, disk) in block_devs {
+        devfs.add(name, Arc::new(fs::blkdev::BlockDevNode::new(disk)));
+    }
     Arc::new(devfs)
 }
 
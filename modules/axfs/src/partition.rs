@@ -0,0 +1,290 @@
+//! Partition tables of block devices (MBR and GPT).
+//!
+//! [`scan`] finds the partitions of a device, which are then exposed as
+//! block devices of their own, e.g., `/dev/sda1`. A GPT is recognized by the
+//! protective MBR before it. The logical partitions of an extended MBR
+//! partition are numbered from 5, like on Linux.
+//!
+//! A device without a partition table, i.e., with a filesystem on the whole
+//! device (e.g., a FAT "superfloppy"), has no partitions.
+
+use alloc::vec::Vec;
+
+use axcrypto::crc32::{Crc32, crc32};
+use axdriver::prelude::DevResult;
+
+const BLOCK_SIZE: usize = 512;
+
+/// The signature at the end of an MBR (and of a boot sector).
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES_OFFSET: usize = 446;
+
+/// The MBR partition type of a protective MBR, before a GPT.
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// The MBR partition types of extended partitions.
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// The MBR partition types of FAT filesystems, including the EFI system
+/// partition.
+const MBR_TYPES_FAT: [u8; 7] = [0x01, 0x04, 0x06, 0x0b, 0x0c, 0x0e, 0xef];
+
+/// The MBR partition type of Linux swap partitions.
+const MBR_TYPE_SWAP: u8 = 0x82;
+
+/// The maximum number of logical partitions, to bound a loop in the chain.
+const MAX_LOGICAL: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// The maximum number of GPT entries, usually 128.
+const MAX_GPT_ENTRIES: usize = 1024;
+
+/// The GPT partition types of FAT filesystems: the EFI system partition and
+/// basic data partitions.
+const GPT_TYPES_FAT: [[u8; 16]; 2] = [
+    guid(0xc12a_7328, 0xf81f, 0x11d2, 0xba4b_00a0_c93e_c93b),
+    guid(0xebd0_a0a2, 0xb9e5, 0x4433, 0x87c0_68b6_b726_99c7),
+];
+
+/// The GPT partition type of Linux swap partitions.
+const GPT_TYPE_SWAP: [u8; 16] = guid(0x0657_fd6d, 0xa4ab, 0x43c4, 0x84e5_0933_c84b_4f4f);
+
+/// Returns the GUID `a-b-c-d` in the byte order it is stored on disk, with
+/// its first three fields little-endian.
+const fn guid(a: u32, b: u16, c: u16, d: u64) -> [u8; 16] {
+    let a = a.to_le_bytes();
+    let b = b.to_le_bytes();
+    let c = c.to_le_bytes();
+    let d = d.to_be_bytes();
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+        d[7],
+    ]
+}
+
+/// The type of a partition, as given by the partition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The system ID of an MBR partition.
+    Mbr(u8),
+    /// The type GUID of a GPT partition, in the byte order it is stored on
+    /// disk.
+    Gpt([u8; 16]),
+}
+
+impl PartitionType {
+    /// Whether the partition is of a type holding a FAT filesystem.
+    pub fn is_fat(&self) -> bool {
+        match self {
+            Self::Mbr(ty) => MBR_TYPES_FAT.contains(ty),
+            Self::Gpt(ty) => GPT_TYPES_FAT.contains(ty),
+        }
+    }
+
+    /// Whether the partition is a Linux swap partition.
+    pub fn is_swap(&self) -> bool {
+        match self {
+            Self::Mbr(ty) => *ty == MBR_TYPE_SWAP,
+            Self::Gpt(ty) => *ty == GPT_TYPE_SWAP,
+        }
+    }
+}
+
+/// A partition of a block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    /// The number of the partition, from 1, as in its name (e.g., `sda1`).
+    pub number: usize,
+    /// The first block of the partition on the device.
+    pub start_block: u64,
+    /// The number of blocks of the partition.
+    pub num_blocks: u64,
+    /// The type of the partition.
+    pub ty: PartitionType,
+}
+
+/// An entry of the MBR, or of an extended boot record.
+struct MbrEntry {
+    status: u8,
+    ty: u8,
+    start: u64,
+    len: u64,
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn le64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn mbr_entries(block: &[u8; BLOCK_SIZE]) -> [MbrEntry; 4] {
+    core::array::from_fn(|i| {
+        let entry = &block[MBR_ENTRIES_OFFSET + 16 * i..][..16];
+        MbrEntry {
+            status: entry[0],
+            ty: entry[4],
+            start: le32(&entry[8..]) as u64,
+            len: le32(&entry[12..]) as u64,
+        }
+    })
+}
+
+/// Whether the first block is the boot sector of a FAT filesystem on the
+/// whole device, which has the same signature as an MBR.
+fn is_fat_boot_sector(block: &[u8; BLOCK_SIZE]) -> bool {
+    matches!(block[0], 0xeb | 0xe9) && (&block[54..57] == b"FAT" || &block[82..85] == b"FAT")
+}
+
+/// Adds a partition if it lies within the device.
+fn add_partition(
+    parts: &mut Vec<Partition>,
+    device_blocks: u64,
+    number: usize,
+    start_block: u64,
+    num_blocks: u64,
+    ty: PartitionType,
+) {
+    if num_blocks == 0 || start_block.saturating_add(num_blocks) > device_blocks {
+        warn!(
+            "partition {} (blocks {}+{}) is beyond the end of the device",
+            number, start_block, num_blocks
+        );
+        return;
+    }
+    parts.push(Partition {
+        number,
+        start_block,
+        num_blocks,
+        ty,
+    });
+}
+
+/// Finds the partitions of a device of `num_blocks` blocks of 512 bytes,
+/// with `read_block` reading a block.
+///
+/// Returns no partitions if the device has no valid partition table, and an
+/// error only if a block can not be read.
+pub fn scan(
+    mut read_block: impl FnMut(u64, &mut [u8]) -> DevResult,
+    num_blocks: u64,
+) -> DevResult<Vec<Partition>> {
+    let mut mbr = [0; BLOCK_SIZE];
+    read_block(0, &mut mbr)?;
+    if mbr[510..] != MBR_SIGNATURE || is_fat_boot_sector(&mbr) {
+        return Ok(Vec::new());
+    }
+    let entries = mbr_entries(&mbr);
+    // The boot indicator is either 0x00 or 0x80, which tells an MBR from
+    // other boot sectors.
+    if entries.iter().any(|e| e.status & 0x7f != 0) {
+        return Ok(Vec::new());
+    }
+    if entries.iter().any(|e| e.ty == MBR_TYPE_GPT_PROTECTIVE) {
+        return scan_gpt(read_block, num_blocks);
+    }
+
+    let mut parts = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.ty == 0 {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&entry.ty) {
+            scan_logical(&mut read_block, entry.start, num_blocks, &mut parts)?;
+        } else {
+            let ty = PartitionType::Mbr(entry.ty);
+            add_partition(&mut parts, num_blocks, i + 1, entry.start, entry.len, ty);
+        }
+    }
+    Ok(parts)
+}
+
+/// Finds the logical partitions in the chain of extended boot records of
+/// the extended partition starting at `ext_start`.
+fn scan_logical(
+    read_block: &mut impl FnMut(u64, &mut [u8]) -> DevResult,
+    ext_start: u64,
+    num_blocks: u64,
+    parts: &mut Vec<Partition>,
+) -> DevResult {
+    let mut ebr = [0; BLOCK_SIZE];
+    let mut ebr_block = ext_start;
+    for number in 5..5 + MAX_LOGICAL {
+        if ebr_block >= num_blocks {
+            break;
+        }
+        read_block(ebr_block, &mut ebr)?;
+        if ebr[510..] != MBR_SIGNATURE {
+            break;
+        }
+        // The first entry is the logical partition, relative to its EBR, and
+        // the second one the next EBR, relative to the extended partition.
+        let [part, next, ..] = mbr_entries(&ebr);
+        if part.ty != 0 {
+            let start = ebr_block + part.start;
+            let ty = PartitionType::Mbr(part.ty);
+            add_partition(parts, num_blocks, number, start, part.len, ty);
+        }
+        if next.ty == 0 || next.start == 0 {
+            break;
+        }
+        ebr_block = ext_start + next.start;
+    }
+    Ok(())
+}
+
+/// Finds the partitions of the GPT, whose header is in block 1.
+fn scan_gpt(
+    mut read_block: impl FnMut(u64, &mut [u8]) -> DevResult,
+    num_blocks: u64,
+) -> DevResult<Vec<Partition>> {
+    let mut header = [0; BLOCK_SIZE];
+    read_block(1, &mut header)?;
+    let header_size = le32(&header[12..]) as usize;
+    if &header[..8] != GPT_SIGNATURE || !(92..=BLOCK_SIZE).contains(&header_size) {
+        warn!("invalid GPT header");
+        return Ok(Vec::new());
+    }
+    // The CRC is computed with its own field zeroed.
+    let header_crc = le32(&header[16..]);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        warn!("GPT header checksum mismatch");
+        return Ok(Vec::new());
+    }
+    let entries_block = le64(&header[72..]);
+    let num_entries = le32(&header[80..]) as usize;
+    let entry_size = le32(&header[84..]) as usize;
+    let entries_crc = le32(&header[88..]);
+    if entry_size < 128 || !BLOCK_SIZE.is_multiple_of(entry_size) || num_entries > MAX_GPT_ENTRIES {
+        warn!(
+            "unsupported GPT entries: {} of {} bytes",
+            num_entries, entry_size
+        );
+        return Ok(Vec::new());
+    }
+
+    let per_block = BLOCK_SIZE / entry_size;
+    let mut block = [0; BLOCK_SIZE];
+    let mut crc = Crc32::new();
+    let mut parts = Vec::new();
+    for i in 0..num_entries {
+        if i % per_block == 0 {
+            read_block(entries_block + (i / per_block) as u64, &mut block)?;
+        }
+        let entry = &block[i % per_block * entry_size..][..entry_size];
+        crc.update(entry);
+        let ty: [u8; 16] = entry[..16].try_into().unwrap();
+        let first = le64(&entry[32..]);
+        let last = le64(&entry[40..]);
+        if ty == [0; 16] || last < first {
+            continue;
+        }
+        let ty = PartitionType::Gpt(ty);
+        add_partition(&mut parts, num_blocks, i + 1, first, last - first + 1, ty);
+    }
+    if crc.finish() != entries_crc {
+        warn!("GPT entries checksum mismatch");
+        return Ok(Vec::new());
+    }
+    Ok(parts)
+}
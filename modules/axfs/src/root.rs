@@ -2,23 +2,103 @@
 //!
 //! TODO: it doesn't work very well if the mount points have containment relationships.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{borrow::Cow, format, string::String, sync::Arc, vec::Vec};
 use axerrno::{AxError, AxResult, ax_err};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axns::{ResArc, def_resource};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::link::{self, Link};
+use crate::{api::FileType, dev::Disk, fs, mounts};
+
+/// The default file mode creation mask.
+const DEFAULT_UMASK: u32 = 0o022;
+
+/// The mount points seen at the same paths from all the root directories
+/// (see [`unshare_root`]), as they hold no files of the applications.
+const SHARED_MOUNTS: &[&str] = &["/dev", "/proc", "/sys"];
+
+/// The current directory, the file mode creation mask and the root
+/// directory of a task.
+///
+/// With `multitask`, each task has its own, carried as a task-local value,
+/// and starts with the one of the task spawning it. The tasks which never
+/// changed theirs, or their parents', share the default one.
+#[derive(Clone)]
+struct FsContext {
+    dir: VfsNodeRef,
+    /// The absolute path of `dir`, ending with `/`, as seen from `root`.
+    dir_path: String,
+    umask: u32,
+    /// The absolute path of the root directory, without the trailing `/`,
+    /// empty for the root of the mounted filesystems.
+    root: String,
+}
+
+impl FsContext {
+    /// Converts an absolute and canonical path seen from the root directory
+    /// to the path from the root of the mounted filesystems.
+    fn real_path(&self, path: &str) -> String {
+        if self.root.is_empty() || is_shared(path) {
+            path.into()
+        } else if path == "/" {
+            self.root.clone()
+        } else {
+            self.root.clone() + path
+        }
+    }
+
+    /// Converts a path from the root of the mounted filesystems to the path
+    /// seen from the root directory, see [`real_path`](Self::real_path).
+    fn view_path(&self, path: &str) -> String {
+        match path.strip_prefix(self.root.as_str()) {
+            _ if self.root.is_empty() || is_shared(path) => path.into(),
+            Some("") => "/".into(),
+            Some(rest) if rest.starts_with('/') => rest.into(),
+            _ => path.into(),
+        }
+    }
+}
+
+/// Whether the absolute path is in one of the [`SHARED_MOUNTS`].
+pub(crate) fn is_shared(path: &str) -> bool {
+    SHARED_MOUNTS.iter().any(|mp| {
+        path.strip_prefix(mp)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
 
 def_resource! {
-    static CURRENT_DIR_PATH: ResArc<Mutex<String>> = ResArc::new();
-    static CURRENT_DIR: ResArc<Mutex<VfsNodeRef>> = ResArc::new();
+    /// The default context.
+    static FS_CONTEXT: ResArc<Mutex<Arc<FsContext>>> = ResArc::new();
+}
+
+/// Returns the context of the current task.
+fn context() -> Arc<FsContext> {
+    #[cfg(feature = "multitask")]
+    if let Some(ctx) = axtask::current_may_uninit().and_then(|curr| curr.local()) {
+        return ctx;
+    }
+    FS_CONTEXT.lock().clone()
+}
+
+/// Sets the context of the current task.
+fn set_context(ctx: FsContext) {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        curr.set_local(Arc::new(ctx));
+        return;
+    }
+    *FS_CONTEXT.lock() = Arc::new(ctx);
 }
 
 struct MountPoint {
     path: &'static str,
     fs: Arc<dyn VfsOps>,
+    /// Whether the filesystem keeps the data of its files while their nodes
+    /// are referenced, so that they can be hard linked.
+    hard_links: bool,
 }
 
 struct RootDirectory {
@@ -29,8 +109,12 @@ struct RootDirectory {
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>, hard_links: bool) -> Self {
+        Self {
+            path,
+            fs,
+            hard_links,
+        }
     }
 }
 
@@ -48,7 +132,7 @@ impl RootDirectory {
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>, hard_links: bool) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
@@ -61,7 +145,7 @@ impl RootDirectory {
         // create the mount point in the main filesystem if it does not exist
         self.main_fs.root_dir().create(path, FileType::Dir)?;
         fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        self.mounts.push(MountPoint::new(path, fs, hard_links));
         Ok(())
     }
 
@@ -73,6 +157,30 @@ impl RootDirectory {
         self.mounts.iter().any(|mp| mp.path == path)
     }
 
+    /// Returns the index of the mount point of the filesystem having the
+    /// absolute path, if it supports hard links.
+    pub fn hard_link_mount(&self, path: &str) -> Option<usize> {
+        self.find_mount(path.trim_matches('/'))
+            .filter(|&idx| self.mounts[idx].hard_links)
+    }
+
+    /// Returns the index of the mount point having the longest path matching
+    /// the path, without its leading `/`, or `None` for the main filesystem.
+    fn find_mount(&self, path: &str) -> Option<usize> {
+        let mut idx = None;
+        let mut max_len = 0;
+
+        // TODO: more efficient, e.g. trie
+        for (i, mp) in self.mounts.iter().enumerate() {
+            // skip the first '/'
+            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
+                max_len = mp.path.len() - 1;
+                idx = Some(i);
+            }
+        }
+        idx
+    }
+
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
@@ -83,24 +191,14 @@ impl RootDirectory {
             return self.lookup_mounted_fs(rest, f);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
         // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
+        match self.find_mount(path) {
+            None => f(self.main_fs.clone(), path), // not matched any mount point
+            Some(idx) => {
+                let mp = &self.mounts[idx];
+                f(mp.fs.clone(), &path[mp.path.len() - 1..]) // matched at `idx`
             }
         }
-
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
-        }
     }
 }
 
@@ -146,7 +244,10 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+/// Mounts the main filesystem on `disk`, and the others, with `block_devs`
+/// in `/dev`.
+#[cfg_attr(not(feature = "devfs"), allow(unused_variables))]
+pub(crate) fn init_rootfs(disk: Disk, block_devs: Vec<(&'static str, Disk)>) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
@@ -162,53 +263,114 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", mounts::devfs(block_devs), false)
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", mounts::ramfs(), true)
         .expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc", mounts::procfs().unwrap(), true)
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", mounts::sysfs().unwrap(), true)
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
-    CURRENT_DIR.init_new(Mutex::new(ROOT_DIR.clone()));
-    CURRENT_DIR_PATH.init_new(Mutex::new("/".into()));
+    FS_CONTEXT.init_new(Mutex::new(Arc::new(FsContext {
+        dir: ROOT_DIR.clone(),
+        dir_path: "/".into(),
+        umask: DEFAULT_UMASK,
+        root: String::new(),
+    })));
 }
 
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        dir.cloned().unwrap_or_else(|| context().dir.clone())
     }
 }
 
-pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
+/// Returns the absolute and canonical form of a path, as seen from the root
+/// directory of the current task.
+pub(crate) fn absolute_view_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
+        let path = context().dir_path.clone() + path;
         Ok(axfs_vfs::path::canonicalize(&path))
     }
 }
 
+/// Returns the absolute and canonical form of a path, from the root of the
+/// mounted filesystems.
+pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
+    Ok(context().real_path(&absolute_view_path(path)?))
+}
+
+/// Returns the absolute form of a path returned by [`resolve`].
+fn resolved_absolute_path(path: &str) -> AxResult<String> {
+    if path.starts_with('/') {
+        Ok(axfs_vfs::path::canonicalize(path))
+    } else {
+        absolute_path(path)
+    }
+}
+
+/// Resolves the symbolic links in a path (see [`link::resolve`]), making it
+/// absolute from the root of the mounted filesystems, unless it is relative
+/// to an opened directory.
+fn resolve<'a>(
+    dir: Option<&VfsNodeRef>,
+    path: &'a str,
+    follow_last: bool,
+) -> AxResult<Cow<'a, str>> {
+    let root = context().root.clone();
+    if (!link::has_links() && root.is_empty()) || (dir.is_some() && !path.starts_with('/')) {
+        return Ok(Cow::Borrowed(path));
+    }
+    let follow_last = follow_last || path.ends_with('/');
+    let mut resolved = link::resolve(&absolute_path(path)?, follow_last, &root)?;
+    if path.ends_with('/') && !resolved.ends_with('/') {
+        resolved.push('/');
+    }
+    Ok(Cow::Owned(resolved))
+}
+
+/// Returns the absolute path of a file, relative to the directory at
+/// `dir_path` or to the current one, with its symbolic links resolved.
+pub(crate) fn real_path(dir_path: Option<&str>, path: &str) -> AxResult<String> {
+    let path = match dir_path {
+        Some(dir_path) if !path.starts_with('/') => {
+            axfs_vfs::path::canonicalize(&format!("{dir_path}/{path}"))
+        }
+        _ => absolute_path(path)?,
+    };
+    link::resolve(&path, true, &context().root)
+}
+
 pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    lookup_resolved(dir, &resolve(dir, path, true)?)
+}
+
+/// Looks up a path returned by [`resolve`].
+fn lookup_resolved(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    let node = match link::get(path) {
+        Some(Link::Hard(node)) => node,
+        _ => parent_node_of(dir, path).lookup(path)?,
+    };
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
@@ -222,21 +384,32 @@ pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsN
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let path = resolve(dir, path, true)?;
+    let parent = parent_node_of(dir, &path);
+    parent.create(&path, VfsNodeType::File)?;
+    parent.lookup(&path)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     match lookup(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let path = resolve(dir, path, false)?;
+            parent_node_of(dir, &path).create(&path, VfsNodeType::Dir)
+        }
         Err(e) => Err(e),
     }
 }
 
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+    let path = resolve(dir, path, false)?;
+    let path = path.as_ref();
+    if link::get(path).is_some() {
+        parent_node_of(dir, path).remove(path)?;
+        link::remove(path);
+        return Ok(());
+    }
+    let node = lookup_resolved(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
@@ -264,8 +437,13 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     if ROOT_DIR.contains(&absolute_path(path)?) {
         return ax_err!(PermissionDenied);
     }
+    let path = resolve(dir, path, false)?;
+    let path = path.as_ref();
+    if link::get(path).is_some() {
+        return ax_err!(NotADirectory);
+    }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_resolved(dir, path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
@@ -277,37 +455,141 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    Ok(context().dir_path.clone())
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
-    let mut abs_path = absolute_path(path)?;
+    let mut abs_path = resolved_absolute_path(&resolve(None, path, true)?)?;
     if !abs_path.ends_with('/') {
         abs_path += "/";
     }
+    let ctx = context();
+    let dir_path = ctx.view_path(&abs_path);
     if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
+        set_context(FsContext {
+            dir: ROOT_DIR.clone(),
+            dir_path,
+            ..FsContext::clone(&ctx)
+        });
         return Ok(());
     }
 
-    let node = lookup(None, &abs_path)?;
+    let node = lookup_resolved(None, &abs_path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        set_context(FsContext {
+            dir: node,
+            dir_path,
+            ..FsContext::clone(&ctx)
+        });
         Ok(())
     }
 }
 
+/// Makes the current directory the root directory of the current task, and
+/// of the tasks it creates afterwards, like `chroot`.
+///
+/// The paths are then resolved from it, except the ones in
+/// [`SHARED_MOUNTS`], and `..` does not go above it, so the tasks cannot
+/// reach the files of the others.
+pub(crate) fn unshare_root() -> AxResult {
+    let ctx = context();
+    let root = ctx.real_path(&ctx.dir_path);
+    if is_shared(&root) {
+        return ax_err!(InvalidInput, "cannot change the root to a shared mount");
+    }
+    set_context(FsContext {
+        dir_path: "/".into(),
+        root: String::from(root.trim_end_matches('/')),
+        ..FsContext::clone(&ctx)
+    });
+    Ok(())
+}
+
+pub(crate) fn umask() -> u32 {
+    context().umask
+}
+
+pub(crate) fn set_umask(umask: u32) -> u32 {
+    let ctx = context();
+    set_context(FsContext {
+        umask: umask & 0o777,
+        ..FsContext::clone(&ctx)
+    });
+    ctx.umask
+}
+
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    let (src, dst) = (resolve(None, old, false)?, resolve(None, new, false)?);
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if link::get(dst).is_some() || parent_node_of(None, dst).lookup(dst).is_ok() {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    parent_node_of(None, src).rename(src, dst)?;
+    if link::has_links() {
+        link::rename(&resolved_absolute_path(src)?, &resolved_absolute_path(dst)?);
+    }
+    Ok(())
+}
+
+/// Creates the backing file of a link at a path returned by [`resolve`], and
+/// adds it.
+fn create_link(path: &str, link: Link) -> AxResult {
+    let path = resolved_absolute_path(path)?;
+    if link::get(&path).is_some() || ROOT_DIR.clone().lookup(&path).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    ROOT_DIR.create(&path, VfsNodeType::File)?;
+    link::add(path, link);
+    Ok(())
+}
+
+pub(crate) fn symlink(target: &str, path: &str) -> AxResult {
+    if target.is_empty() {
+        return ax_err!(NotFound);
+    }
+    create_link(&resolve(None, path, false)?, Link::Symbolic(target.into()))
+}
+
+/// Whether the filesystem of the absolute path keeps the nodes of its files,
+/// so that a file has the same node each time it is opened.
+pub(crate) fn keeps_nodes(path: &str) -> bool {
+    ROOT_DIR.hard_link_mount(path).is_some()
+}
+
+pub(crate) fn hard_link(old: &str, new: &str) -> AxResult {
+    let node = lookup(None, old)?;
+    if node.get_attr()?.is_dir() {
+        return ax_err!(PermissionDenied, "cannot hard link a directory");
+    }
+    let old = resolved_absolute_path(&resolve(None, old, true)?)?;
+    let new = resolved_absolute_path(&resolve(None, new, false)?)?;
+    let mount = ROOT_DIR.hard_link_mount(&old);
+    if mount.is_none() || mount != ROOT_DIR.hard_link_mount(&new) {
+        return ax_err!(Unsupported, "hard links are not supported here");
+    }
+    create_link(&new, Link::Hard(node))
+}
+
+pub(crate) fn read_link(path: &str) -> AxResult<String> {
+    match link::get(&resolved_absolute_path(&resolve(None, path, false)?)?) {
+        Some(Link::Symbolic(target)) => Ok(target),
+        _ => ax_err!(InvalidInput, "not a symbolic link"),
+    }
+}
+
+pub(crate) fn is_symlink(path: &str) -> bool {
+    link::has_links()
+        && resolve(None, path, false)
+            .and_then(|path| resolved_absolute_path(&path))
+            .is_ok_and(|path| matches!(link::get(&path), Some(Link::Symbolic(_))))
+}
+
+pub(crate) fn set_max_symlink_follows(max: usize) -> usize {
+    link::set_max_follows(max)
 }
@@ -0,0 +1,48 @@
+//! Swapping to a Linux swap partition.
+//!
+//! The pages are stored one after another, after the first one, which holds
+//! the header written by `mkswap` and is left as it is. The content of the
+//! partition does not survive a reboot.
+
+use alloc::sync::Arc;
+use axdriver::prelude::DevError;
+use axerrno::{AxError, AxResult};
+use axmm::swap::SwapDevice;
+
+use crate::dev::Disk;
+
+const BLOCK_SIZE: usize = 512;
+const PAGE_SIZE: usize = 0x1000;
+const PAGE_BLOCKS: u64 = (PAGE_SIZE / BLOCK_SIZE) as u64;
+
+struct SwapPartition(Disk);
+
+impl SwapDevice for SwapPartition {
+    fn num_pages(&self) -> usize {
+        (self.0.num_blocks() / PAGE_BLOCKS).saturating_sub(1) as usize
+    }
+
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult {
+        self.0
+            .read_blocks((index as u64 + 1) * PAGE_BLOCKS, buf)
+            .map_err(dev_err)
+    }
+
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult {
+        self.0
+            .write_blocks((index as u64 + 1) * PAGE_BLOCKS, buf)
+            .map_err(dev_err)
+    }
+}
+
+fn dev_err(err: DevError) -> AxError {
+    warn!("swap device error: {:?}", err);
+    AxError::Io
+}
+
+/// Starts swapping to the partition.
+pub(crate) fn init(disk: Disk) {
+    if let Err(e) = axmm::swap::swap_on(Arc::new(SwapPartition(disk))) {
+        warn!("  failed to swap on: {:?}", e);
+    }
+}
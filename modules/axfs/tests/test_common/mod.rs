@@ -2,7 +2,7 @@ use axfs::api as fs;
 use axio as io;
 
 use fs::{File, FileType, OpenOptions};
-use io::{Error, Result, prelude::*};
+use io::{Error, Result, SeekFrom, prelude::*};
 
 macro_rules! assert_err {
     ($expr: expr) => {
@@ -198,6 +198,7 @@ fn test_devfs_ramfs() -> Result<()> {
         .collect::<Vec<_>>();
     assert!(dirents.contains(&"null".into()));
     assert!(dirents.contains(&"zero".into()));
+    assert!(dirents.contains(&"sda".into()));
 
     // stat /dev
     let dname = "/dev";
@@ -252,6 +253,198 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_direct_io() -> Result<()> {
+    println!("test direct I/O ...");
+
+    const N: usize = 1024;
+    let mut buf = [0; N];
+    let mut cached = [0; N];
+    File::open("/dev/sda")?.read_exact(&mut cached)?;
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .direct(true)
+        .open("/dev/sda")?;
+    assert_eq!(file.read(&mut buf)?, N);
+    assert_eq!(buf, cached);
+    assert_eq!(file.seek(SeekFrom::Start(0))?, 0);
+    assert_eq!(file.write(&buf)?, N);
+
+    // unaligned offsets and lengths
+    assert_err!(file.read(&mut buf[..100]), InvalidInput);
+    assert_eq!(file.seek(SeekFrom::Start(1))?, 1);
+    assert_err!(file.read(&mut buf), InvalidInput);
+    assert_err!(file.write(&buf), InvalidInput);
+
+    // unaligned reads without direct I/O
+    let mut file = File::open("/dev/sda")?;
+    assert_eq!(file.seek(SeekFrom::Start(100))?, 100);
+    assert_eq!(file.read(&mut buf[..N - 100])?, N - 100);
+    assert_eq!(buf[..N - 100], cached[100..]);
+
+    // only block devices
+    assert_err!(
+        OpenOptions::new().read(true).direct(true).open("/dev/zero"),
+        InvalidInput
+    );
+    assert_err!(
+        OpenOptions::new()
+            .read(true)
+            .direct(true)
+            .open("/short.txt"),
+        InvalidInput
+    );
+
+    println!("test_direct_io() OK!");
+    Ok(())
+}
+
+fn test_links() -> Result<()> {
+    println!("test links ...");
+
+    // symbolic links, relative and absolute
+    fs::create_dir("/link-dir")?;
+    fs::write("/link-dir/target.txt", "Rust is cool!\n")?;
+    fs::symlink("target.txt", "/link-dir/rel")?;
+    fs::symlink("/link-dir", "/link-abs")?;
+    assert_eq!(fs::read_link("/link-dir/rel")?, "target.txt");
+    assert_eq!(fs::read_to_string("/link-dir/rel")?, "Rust is cool!\n");
+    assert_eq!(fs::read_to_string("/link-abs/rel")?, "Rust is cool!\n");
+    assert!(fs::metadata("/link-abs")?.is_dir());
+    assert_err!(fs::read_link("/link-dir/target.txt"), InvalidInput);
+    assert_err!(fs::symlink("target.txt", "/link-dir/rel"), AlreadyExists);
+
+    let mut entries = fs::read_dir("/link-abs")?
+        .map(|e| e.map(|e| (e.file_name(), e.file_type())))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        entries,
+        [
+            ("rel".into(), FileType::SymLink),
+            ("target.txt".into(), FileType::File)
+        ]
+    );
+
+    // dangling links and loops
+    fs::symlink("missing", "/link-dir/dangling")?;
+    fs::symlink("loop", "/link-dir/loop")?;
+    assert_err!(fs::metadata("/link-dir/dangling"), NotFound);
+    assert_err!(fs::metadata("/link-dir/loop"), InvalidInput);
+    let max = fs::set_max_symlink_follows(1);
+    assert_err!(fs::metadata("/link-abs/rel"), InvalidInput);
+    fs::set_max_symlink_follows(max);
+
+    // removing a link keeps its target
+    for link in ["/link-dir/rel", "/link-dir/dangling", "/link-dir/loop"] {
+        fs::remove_file(link)?;
+    }
+    assert_err!(fs::remove_dir("/link-abs"), NotADirectory);
+    fs::remove_file("/link-abs")?;
+    assert_eq!(
+        fs::read_to_string("/link-dir/target.txt")?,
+        "Rust is cool!\n"
+    );
+    fs::remove_file("/link-dir/target.txt")?;
+    fs::remove_dir("/link-dir")?;
+
+    // hard links, only in a RAM filesystem
+    fs::write("/tmp/hard.txt", "Rust is cool!\n")?;
+    fs::hard_link("/tmp/hard.txt", "/tmp/hard-link.txt")?;
+    fs::remove_file("/tmp/hard.txt")?;
+    assert_eq!(fs::read_to_string("/tmp/hard-link.txt")?, "Rust is cool!\n");
+    assert_err!(fs::hard_link("/tmp", "/tmp/dir-link"), PermissionDenied);
+    assert_err!(fs::hard_link("/short.txt", "/hard.txt"), Unsupported);
+    assert_err!(
+        fs::hard_link("/tmp/hard-link.txt", "/hard.txt"),
+        Unsupported
+    );
+    fs::remove_file("/tmp/hard-link.txt")?;
+
+    println!("test_links() OK!");
+    Ok(())
+}
+
+fn test_file_lock() -> Result<()> {
+    println!("test file locks ...");
+
+    let file1 = File::open("/short.txt")?;
+    let file2 = File::open("short.txt")?;
+    file1.lock()?;
+    file1.lock()?;
+    assert_err!(file2.try_lock(), WouldBlock);
+    assert_err!(file2.try_lock_shared(), WouldBlock);
+    // no other task can release it
+    assert_err!(file2.lock(), WouldBlock);
+
+    // shared locks
+    file1.unlock()?;
+    file2.lock_shared()?;
+    file1.try_lock_shared()?;
+    // converting a lock releases it, even if it fails
+    assert_err!(file1.try_lock(), WouldBlock);
+    file2.try_lock()?;
+
+    // closing a file releases its lock
+    drop(file2);
+    file1.try_lock()?;
+    // through a symbolic link
+    fs::symlink("/short.txt", "/tmp/short-link")?;
+    let file3 = File::open("/tmp/short-link")?;
+    assert_err!(file3.try_lock_shared(), WouldBlock);
+    drop(file1);
+    file3.try_lock_shared()?;
+    fs::remove_file("/tmp/short-link")?;
+
+    // through a hard link, sharing the node of the file
+    fs::write("/tmp/lock.txt", "")?;
+    fs::hard_link("/tmp/lock.txt", "/tmp/lock-link.txt")?;
+    let file4 = File::open("/tmp/lock.txt")?;
+    let file5 = File::open("/tmp/lock-link.txt")?;
+    file4.lock()?;
+    assert_err!(file5.try_lock_shared(), WouldBlock);
+    drop(file4);
+    file5.try_lock()?;
+    drop(file5);
+    fs::remove_file("/tmp/lock.txt")?;
+    fs::remove_file("/tmp/lock-link.txt")?;
+
+    println!("test_file_lock() OK!");
+    Ok(())
+}
+
+fn test_walk_dir() -> Result<()> {
+    println!("test walk_dir ...");
+
+    fs::create_dir("/tmp/walk")?;
+    fs::create_dir("/tmp/walk/sub")?;
+    fs::write("/tmp/walk/a.txt", "a")?;
+    fs::write("/tmp/walk/sub/b.txt", "b")?;
+
+    let entries = fs::walk_dir("/tmp/walk")
+        .map(|e| e.map(|e| (String::from(e.path()), e.depth())))
+        .collect::<Result<Vec<_>>>()?;
+    println!("entries = {:?}", entries);
+    assert_eq!(entries.len(), 3);
+    assert!(entries.contains(&("/tmp/walk/a.txt".into(), 1)));
+    // the entries of a directory come right after it
+    let sub = entries.iter().position(|(path, _)| path == "/tmp/walk/sub");
+    assert_eq!(entries[sub.unwrap() + 1], ("/tmp/walk/sub/b.txt".into(), 2));
+
+    let mut walk = fs::walk_dir("/not/exist");
+    assert_err!(walk.next().unwrap(), NotFound);
+    assert!(walk.next().is_none());
+
+    fs::remove_file("/tmp/walk/sub/b.txt")?;
+    fs::remove_dir("/tmp/walk/sub")?;
+    fs::remove_file("/tmp/walk/a.txt")?;
+    fs::remove_dir("/tmp/walk")?;
+
+    println!("test_walk_dir() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +452,8 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_direct_io().expect("test_direct_io() failed");
+    test_links().expect("test_links() failed");
+    test_file_lock().expect("test_file_lock() failed");
+    test_walk_dir().expect("test_walk_dir() failed");
 }
@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+
+use axfs::fsck::{FatType, FsckReport, Problem, check};
+
+const IMG_PATH: &str = "resources/fat16.img";
+const BLOCK_SIZE: usize = 512;
+
+// The layout of the FAT16 image: 1 reserved sector, 2 FATs of 20 sectors,
+// 512 root entries, and 1 sector per cluster.
+const FAT_OFFSET: usize = BLOCK_SIZE;
+const FAT_SIZE: usize = 20 * BLOCK_SIZE;
+const ROOT_OFFSET: usize = 41 * BLOCK_SIZE;
+const DATA_OFFSET: usize = 73 * BLOCK_SIZE;
+
+/// The indexes of the root entries of `long.txt`, 14000 bytes from cluster 3,
+/// and of `short.txt`, 14 bytes in cluster 31.
+const LONG_TXT: usize = 2;
+const SHORT_TXT: usize = 4;
+
+fn load_image() -> Vec<u8> {
+    let path = std::env::current_dir().unwrap().join(IMG_PATH);
+    std::fs::read(path).expect("failed to load disk image")
+}
+
+fn fsck(image: &mut Vec<u8>, repair: bool) -> FsckReport {
+    let image = RefCell::new(image);
+    let read_block = |id: u64, buf: &mut [u8]| {
+        buf.copy_from_slice(&image.borrow()[id as usize * BLOCK_SIZE..][..BLOCK_SIZE]);
+        Ok(())
+    };
+    let write_block = |id: u64, buf: &[u8]| {
+        image.borrow_mut()[id as usize * BLOCK_SIZE..][..BLOCK_SIZE].copy_from_slice(buf);
+        Ok(())
+    };
+    check(read_block, write_block, repair).unwrap()
+}
+
+fn root_entry(image: &mut [u8], index: usize) -> &mut [u8] {
+    &mut image[ROOT_OFFSET + 32 * index..][..32]
+}
+
+/// Sets a FAT entry in both copies of the FAT.
+fn set_fat_entry(image: &mut [u8], cluster: usize, value: u16) {
+    for fat in [FAT_OFFSET, FAT_OFFSET + FAT_SIZE] {
+        image[fat + 2 * cluster..][..2].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Checks, repairs, then checks again that the image is clean.
+fn check_repair(image: &mut Vec<u8>, problems: &[Problem]) -> FsckReport {
+    let original = image.clone();
+    let report = fsck(image, false);
+    assert_eq!(report.problems, problems);
+    assert!(!report.repaired);
+    assert_eq!(*image, original, "the image is written without repair");
+
+    let report = fsck(image, true);
+    assert_eq!(report.problems, problems);
+    assert!(report.repaired);
+    let report = fsck(image, false);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    report
+}
+
+#[test]
+fn test_clean() {
+    let mut image = load_image();
+    let report = fsck(&mut image, true);
+    assert_eq!(report.fat_type, FatType::Fat16);
+    assert_eq!(report.cluster_size, 512);
+    assert_eq!((report.files, report.dirs), (4, 4));
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    assert!(!report.repaired);
+    assert_eq!(image, load_image());
+}
+
+#[test]
+fn test_not_fat() {
+    let mut image = vec![0; 64 * BLOCK_SIZE];
+    let image = RefCell::new(&mut image);
+    let read_block = |id: u64, buf: &mut [u8]| {
+        buf.copy_from_slice(&image.borrow()[id as usize * BLOCK_SIZE..][..BLOCK_SIZE]);
+        Ok(())
+    };
+    assert!(check(read_block, |_, _| Ok(()), true).is_err());
+}
+
+#[test]
+fn test_size_mismatch() {
+    let mut image = load_image();
+    let free = fsck(&mut image, false).free_clusters;
+    // A size shorter than the chain of 28 clusters.
+    root_entry(&mut image, LONG_TXT)[28..32].copy_from_slice(&1000u32.to_le_bytes());
+    let report = check_repair(
+        &mut image,
+        &[Problem::SizeMismatch {
+            path: "/long.txt".into(),
+            size: 1000,
+            clusters: 28,
+        }],
+    );
+    assert_eq!(report.free_clusters, free + 26);
+
+    // A size longer than the chain.
+    root_entry(&mut image, LONG_TXT)[28..32].copy_from_slice(&5000u32.to_le_bytes());
+    check_repair(
+        &mut image,
+        &[Problem::SizeMismatch {
+            path: "/long.txt".into(),
+            size: 5000,
+            clusters: 2,
+        }],
+    );
+    assert_eq!(
+        root_entry(&mut image, LONG_TXT)[28..32],
+        1024u32.to_le_bytes()
+    );
+}
+
+#[test]
+fn test_broken_chain() {
+    let mut image = load_image();
+    // The 10th cluster of `long.txt` points to a free cluster.
+    set_fat_entry(&mut image, 12, 4000);
+    check_repair(
+        &mut image,
+        &[
+            Problem::InvalidCluster {
+                path: "/long.txt".into(),
+                cluster: 4000,
+            },
+            Problem::SizeMismatch {
+                path: "/long.txt".into(),
+                size: 14000,
+                clusters: 10,
+            },
+            Problem::LostClusters(18),
+        ],
+    );
+    let long_txt = root_entry(&mut image, LONG_TXT);
+    assert_eq!(long_txt[28..32], 5120u32.to_le_bytes());
+    // The data of the remaining clusters is kept.
+    assert_eq!(&image[DATA_OFFSET + 512..][..14], b"Rust is cool!\n");
+}
+
+#[test]
+fn test_cross_linked() {
+    let mut image = load_image();
+    // `short.txt` starts in the first cluster of `long.txt`.
+    root_entry(&mut image, SHORT_TXT)[26..28].copy_from_slice(&3u16.to_le_bytes());
+    check_repair(
+        &mut image,
+        &[
+            Problem::CrossLinked {
+                path: "/short.txt".into(),
+                cluster: 3,
+            },
+            Problem::LostClusters(1),
+        ],
+    );
+    let short_txt = root_entry(&mut image, SHORT_TXT);
+    assert_eq!(short_txt[26..28], [0, 0]);
+    assert_eq!(short_txt[28..32], [0; 4]);
+}
+
+#[test]
+fn test_lost_clusters() {
+    let mut image = load_image();
+    set_fat_entry(&mut image, 4000, 4001);
+    set_fat_entry(&mut image, 4001, 0xffff);
+    check_repair(&mut image, &[Problem::LostClusters(2)]);
+}
+
+#[test]
+fn test_invalid_directory() {
+    let mut image = load_image();
+    // The first cluster of `very` is free.
+    set_fat_entry(&mut image, 32, 0);
+    // Its subdirectories and file are lost.
+    check_repair(
+        &mut image,
+        &[
+            Problem::InvalidDirectory {
+                path: "/very".into(),
+            },
+            Problem::LostClusters(3),
+        ],
+    );
+    assert_eq!(root_entry(&mut image, 6)[0], 0xe5);
+}
+
+#[test]
+fn test_fat_mismatch() {
+    let mut image = load_image();
+    image[FAT_OFFSET + FAT_SIZE + 100] ^= 0xff;
+    check_repair(&mut image, &[Problem::FatMismatch(1)]);
+    assert_eq!(
+        image[FAT_OFFSET..][..FAT_SIZE],
+        image[FAT_OFFSET + FAT_SIZE..][..FAT_SIZE]
+    );
+}
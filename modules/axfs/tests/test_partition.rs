@@ -0,0 +1,155 @@
+use axcrypto::crc32::crc32;
+use axfs::partition::{Partition, PartitionType, scan};
+
+const BLOCK_SIZE: usize = 512;
+const NUM_BLOCKS: u64 = 4096;
+
+fn new_image() -> Vec<u8> {
+    vec![0; NUM_BLOCKS as usize * BLOCK_SIZE]
+}
+
+fn scan_image(image: &[u8]) -> Vec<Partition> {
+    let read_block = |id: u64, buf: &mut [u8]| {
+        let start = id as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&image[start..start + BLOCK_SIZE]);
+        Ok(())
+    };
+    scan(read_block, NUM_BLOCKS).unwrap()
+}
+
+/// Writes an MBR, or an EBR, entry into the given block.
+fn write_mbr_entry(image: &mut [u8], block: u64, index: usize, ty: u8, start: u32, len: u32) {
+    let block = &mut image[block as usize * BLOCK_SIZE..][..BLOCK_SIZE];
+    let entry = &mut block[446 + 16 * index..][..16];
+    entry[4] = ty;
+    entry[8..12].copy_from_slice(&start.to_le_bytes());
+    entry[12..16].copy_from_slice(&len.to_le_bytes());
+    block[510..].copy_from_slice(&[0x55, 0xaa]);
+}
+
+/// The type GUID of the EFI system partition, as stored on disk.
+const EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b,
+];
+/// The type GUID of a Linux filesystem, as stored on disk.
+const LINUX_FS: [u8; 16] = [
+    0xaf, 0x3d, 0xc6, 0x0f, 0x83, 0x84, 0x72, 0x47, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4,
+];
+
+/// Writes a protective MBR and a GPT with the given entries (type, first
+/// and last block), with 128 entries of 128 bytes from block 2.
+fn write_gpt(image: &mut [u8], entries: &[([u8; 16], u64, u64)]) {
+    write_mbr_entry(image, 0, 0, 0xee, 1, NUM_BLOCKS as u32 - 1);
+    let mut table = vec![0; 128 * 128];
+    for (i, &(ty, first, last)) in entries.iter().enumerate() {
+        let entry = &mut table[128 * i..][..128];
+        entry[..16].copy_from_slice(&ty);
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+    }
+    image[2 * BLOCK_SIZE..][..table.len()].copy_from_slice(&table);
+
+    let header = &mut image[BLOCK_SIZE..][..92];
+    header[..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&crc32(&table).to_le_bytes());
+    let crc = crc32(header);
+    header[16..20].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[test]
+fn test_no_partition_table() {
+    assert!(scan_image(&new_image()).is_empty());
+
+    // A FAT boot sector on the whole device is not an MBR.
+    let mut image = new_image();
+    image[0] = 0xeb;
+    image[54..57].copy_from_slice(b"FAT");
+    image[510..512].copy_from_slice(&[0x55, 0xaa]);
+    assert!(scan_image(&image).is_empty());
+}
+
+#[test]
+fn test_mbr() {
+    let mut image = new_image();
+    write_mbr_entry(&mut image, 0, 0, 0x0c, 2048, 1024);
+    write_mbr_entry(&mut image, 0, 1, 0x83, 3072, 1024);
+    // Beyond the end of the device.
+    write_mbr_entry(&mut image, 0, 3, 0x83, 4000, 1024);
+
+    let parts = scan_image(&image);
+    assert_eq!(parts.len(), 2);
+    assert_eq!(
+        parts[0],
+        Partition {
+            number: 1,
+            start_block: 2048,
+            num_blocks: 1024,
+            ty: PartitionType::Mbr(0x0c),
+        }
+    );
+    assert!(parts[0].ty.is_fat());
+    assert_eq!(parts[1].number, 2);
+    assert_eq!(parts[1].start_block, 3072);
+    assert!(!parts[1].ty.is_fat());
+}
+
+#[test]
+fn test_mbr_logical() {
+    let mut image = new_image();
+    write_mbr_entry(&mut image, 0, 0, 0x0b, 64, 960);
+    write_mbr_entry(&mut image, 0, 1, 0x05, 1024, 2048);
+    // Two logical partitions, after their EBRs at 1024 and 2048.
+    write_mbr_entry(&mut image, 1024, 0, 0x83, 1, 511);
+    write_mbr_entry(&mut image, 1024, 1, 0x05, 1024, 1024);
+    write_mbr_entry(&mut image, 2048, 0, 0x83, 1, 1023);
+
+    let parts = scan_image(&image);
+    let layout: Vec<_> = parts
+        .iter()
+        .map(|p| (p.number, p.start_block, p.num_blocks))
+        .collect();
+    assert_eq!(layout, [(1, 64, 960), (5, 1025, 511), (6, 2049, 1023)]);
+}
+
+#[test]
+fn test_gpt() {
+    let mut image = new_image();
+    write_gpt(
+        &mut image,
+        &[
+            (EFI_SYSTEM, 2048, 2559),
+            ([0; 16], 0, 0),
+            (LINUX_FS, 2560, 4000),
+        ],
+    );
+
+    let parts = scan_image(&image);
+    assert_eq!(parts.len(), 2);
+    assert_eq!(
+        parts[0],
+        Partition {
+            number: 1,
+            start_block: 2048,
+            num_blocks: 512,
+            ty: PartitionType::Gpt(EFI_SYSTEM),
+        }
+    );
+    assert!(parts[0].ty.is_fat());
+    assert_eq!(parts[1].number, 3);
+    assert_eq!(parts[1].num_blocks, 1441);
+    assert!(!parts[1].ty.is_fat());
+}
+
+#[test]
+fn test_gpt_corrupted() {
+    let mut image = new_image();
+    write_gpt(&mut image, &[(EFI_SYSTEM, 2048, 2559)]);
+    // An entry changed after the checksum of the table.
+    image[2 * BLOCK_SIZE + 32] ^= 1;
+    assert!(scan_image(&image).is_empty());
+}
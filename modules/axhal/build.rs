@@ -5,6 +5,7 @@ const BUILTIN_PLATFORMS: &[&str] = &[
     "aarch64-bsta1000b",
     "aarch64-qemu-virt",
     "aarch64-raspi4",
+    "dummy",
     "loongarch64-qemu-virt",
     "riscv64-qemu-virt",
     "x86_64-pc-oslab",
@@ -16,6 +17,7 @@ const BUILTIN_PLATFORM_FAMILIES: &[&str] = &[
     "aarch64-phytium-pi",
     "aarch64-qemu-virt",
     "aarch64-raspi",
+    "dummy",
     "loongarch64-qemu-virt",
     "riscv64-qemu-virt",
     "x86-pc",
@@ -49,6 +51,24 @@ fn main() {
         "cargo::rustc-check-cfg=cfg(platform_family, values({}))",
         make_cfg_values(BUILTIN_PLATFORM_FAMILIES)
     );
+
+    if std::env::var("CARGO_FEATURE_CONSOLE_REPLAY").is_ok() {
+        gen_replay_script().unwrap();
+    }
+}
+
+fn gen_replay_script() -> Result<()> {
+    println!("cargo:rerun-if-env-changed=AX_CONSOLE_REPLAY");
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let out_path = Path::new(&out_dir).join("console_replay.txt");
+    match std::env::var("AX_CONSOLE_REPLAY") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={path}");
+            std::fs::copy(path, out_path)?;
+        }
+        _ => std::fs::write(out_path, "")?,
+    }
+    Ok(())
 }
 
 fn gen_linker_script(arch: &str, platform: &str) -> Result<()> {
@@ -0,0 +1,70 @@
+//! Backtraces following the frame pointers.
+//!
+//! They are only complete if the kernel is built with the frame pointers
+//! (`FRAME_POINTERS=y`); otherwise they stop at the first frame without one,
+//! and are usually empty.
+
+/// The maximum number of return addresses in a backtrace.
+const MAX_BACKTRACE: usize = 32;
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))] {
+        /// The offsets from the frame pointer of the saved frame pointer and
+        /// return address, in words: they are at the bottom of the frame.
+        const FRAME_OFFSETS: (isize, isize) = (0, 1);
+    } else {
+        /// The offsets from the frame pointer of the saved frame pointer and
+        /// return address, in words: they are at the top of the frame.
+        const FRAME_OFFSETS: (isize, isize) = (-2, -1);
+    }
+}
+
+/// Returns the frame pointer and the stack pointer of the caller.
+#[cfg(feature = "irq")]
+#[inline(always)]
+pub(crate) fn current_frame() -> (usize, usize) {
+    let (fp, sp): (usize, usize);
+    // SAFETY: only reads registers.
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::asm!("mov {}, rbp", "mov {}, rsp", out(reg) fp, out(reg) sp) };
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe { core::arch::asm!("mov {}, x29", "mov {}, sp", out(reg) fp, out(reg) sp) };
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe { core::arch::asm!("mv {}, s0", "mv {}, sp", out(reg) fp, out(reg) sp) };
+        } else if #[cfg(target_arch = "loongarch64")] {
+            unsafe { core::arch::asm!("move {}, $fp", "move {}, $sp", out(reg) fp, out(reg) sp) };
+        } else {
+            (fp, sp) = (0, 0);
+        }
+    }
+    (fp, sp)
+}
+
+/// Returns the return addresses of the frames from the one of `fp`,
+/// innermost first, `sp` being the stack pointer in that frame.
+///
+/// It stops at the first frame pointer which is not on the stack, so the
+/// frames must still be there when it is iterated.
+pub(crate) fn walk(fp: usize, sp: usize) -> impl Iterator<Item = usize> {
+    let mut fp = fp;
+    core::iter::from_fn(move || {
+        // The stack is at most as large as that of a task.
+        let on_stack = |fp: usize| fp >= sp && fp - sp < axconfig::TASK_STACK_SIZE;
+        if fp % core::mem::size_of::<usize>() != 0 || !on_stack(fp) {
+            return None;
+        }
+        let frame = fp as *const usize;
+        // SAFETY: the frame is on the stack, which is mapped.
+        let (next, ret) = unsafe {
+            (
+                *frame.offset(FRAME_OFFSETS.0),
+                *frame.offset(FRAME_OFFSETS.1),
+            )
+        };
+        // Frames are pushed downwards, so the callers are above.
+        fp = if next > fp { next } else { 0 };
+        Some(ret)
+    })
+    .take(MAX_BACKTRACE)
+}
@@ -0,0 +1,65 @@
+//! The boot command line, e.g., to select options at boot rather than when
+//! building the kernel.
+//!
+//! It is given by the bootloader: in `/chosen/bootargs` of the device tree
+//! on the QEMU `virt` machines, and in the multiboot information on x86-pc,
+//! i.e., what QEMU is given with `-append`. Otherwise, it is the one set at
+//! build time by `AX_CMDLINE`.
+//!
+//! It is a list of options separated by spaces, each being `key=value` or a
+//! bare `key`, see [`get`].
+
+use core::cell::SyncUnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The maximum length of the command line kept, longer ones are truncated.
+pub const MAX_CMDLINE_LEN: usize = 512;
+
+static BUF: SyncUnsafeCell<[u8; MAX_CMDLINE_LEN]> = SyncUnsafeCell::new([0; MAX_CMDLINE_LEN]);
+/// The length of the command line in [`BUF`], plus one once it is set.
+static LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the command line given by the bootloader, once, at early boot.
+#[allow(dead_code)]
+pub(crate) fn set(cmdline: &[u8]) {
+    if LEN.load(Ordering::Acquire) != 0 {
+        return;
+    }
+    // Up to the first NUL, and to a character boundary.
+    let len = cmdline
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(cmdline.len());
+    let mut len = len.min(MAX_CMDLINE_LEN);
+    while core::str::from_utf8(&cmdline[..len]).is_err() {
+        len -= 1;
+    }
+    // SAFETY: it is only written here, before it is read.
+    unsafe { (*BUF.get())[..len].copy_from_slice(&cmdline[..len]) };
+    LEN.store(len + 1, Ordering::Release);
+}
+
+/// Returns the boot command line, or the one set at build time if the
+/// bootloader gave none.
+pub fn cmdline() -> &'static str {
+    match LEN.load(Ordering::Acquire) {
+        0 | 1 => option_env!("AX_CMDLINE").unwrap_or(""),
+        len => {
+            // SAFETY: it is not written anymore, and is valid UTF-8.
+            unsafe { core::str::from_utf8_unchecked(&(*BUF.get())[..len - 1]) }
+        }
+    }
+}
+
+/// Returns the value of an option of the command line, the last one if it is
+/// given several times: `value` for `key=value`, or an empty string for a
+/// bare `key`. Returns `None` if it is not given.
+pub fn get(key: &str) -> Option<&'static str> {
+    cmdline()
+        .split_ascii_whitespace()
+        .filter_map(|option| match option.split_once('=') {
+            Some((k, value)) => (k == key).then_some(value),
+            None => (option == key).then_some(""),
+        })
+        .last()
+}
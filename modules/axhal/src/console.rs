@@ -0,0 +1,767 @@
+//! Console input and output.
+//!
+//! Each platform provides a console device implementing [`ConsoleDriver`],
+//! which only moves raw bytes. The functions of this module add the common
+//! semantics on top of it: line feeds are written as `\r\n`, and carriage
+//! returns sent by serial terminals are read as line feeds.
+//!
+//! With the `irq` feature, devices supporting it buffer the bytes written by
+//! [`write_bytes_nonblocking`] and send them from their transmit interrupt
+//! handler, so heavy logging does not stall the kernel. The buffered bytes
+//! are flushed at shutdown, including after a panic.
+//!
+//! Platforms with several serial ports expose all of them through
+//! [`Console::port`], port 0 being the console device itself. The other
+//! ports can be used, for example, to send the logs to a separate line.
+//! Devices probed later, such as VirtIO consoles, can be added as ports with
+//! [`register_port`], and any port can replace the console device with
+//! [`set_console`].
+//!
+//! The output can also be copied to other devices, e.g., a framebuffer
+//! console or a [`ConsoleBuffer`] in memory, by adding them as sinks with
+//! [`add_sink`]. Each sink, and the console device itself (see
+//! [`set_console_sources`]), only receives the output of the selected
+//! [`ConsoleSource`]s, so for example the logs can stay on the serial port
+//! while the output of applications is shown on the screen. Sinks can also
+//! provide input, e.g., a network console, which is read after that of the
+//! console device.
+//!
+//! The input is echoed and edited line by line by default, like the
+//! canonical mode of a terminal, see [`set_mode`]. The input lost, as a
+//! buffer or the FIFO of a UART was full, is counted in [`stats`]. Like the
+//! signal characters of a terminal, `Ctrl-C` can call a handler instead, see
+//! [`set_interrupt_handler`].
+//!
+//! With the `vt` feature, the console device is shared by virtual terminals,
+//! switched with `Ctrl-A` and a number, see [`switch_vt`].
+//!
+//! With the `sysrq` feature, `Ctrl-\` and a key run diagnostics even when
+//! the application reading the input is stuck, see [`register_sysrq`].
+//!
+//! With the `earlycon` feature, all output is written to the early console
+//! instead until it is disabled, see [`earlycon`](crate::earlycon).
+
+pub use super::console_buffer::ConsoleBuffer;
+pub use super::console_ldisc::{ConsoleMode, mode, set_mode};
+#[cfg(feature = "sysrq")]
+pub use super::console_sysrq::{MAX_SYSRQ_ACTIONS, register_sysrq};
+#[cfg(feature = "vt")]
+pub use super::console_vt::{
+    NUM_VTS, active_vt, read_scrollback, read_vt_input, set_source_vt, source_vt, switch_vt,
+};
+pub use super::platform::console::*;
+
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use kspin::SpinNoIrq;
+
+use super::platform::console::{CONSOLE, PORTS};
+use super::rcu::{self, RcuCell};
+
+/// The maximum number of ports added by [`register_port`].
+pub const MAX_EXTRA_PORTS: usize = 4;
+
+/// The console device set by [`set_console`].
+static ACTIVE_CONSOLE: SpinNoIrq<Option<&'static dyn ConsoleDriver>> = SpinNoIrq::new(None);
+
+/// Ports added by [`register_port`], after the ports of the platform.
+static EXTRA_PORTS: SpinNoIrq<[Option<&'static dyn ConsoleDriver>; MAX_EXTRA_PORTS]> =
+    SpinNoIrq::new([None; MAX_EXTRA_PORTS]);
+
+/// The maximum number of sinks added by [`add_sink`].
+pub const MAX_SINKS: usize = 4;
+
+/// Sinks added by [`add_sink`], with the sources they receive.
+static SINKS: SpinNoIrq<[Option<(&'static dyn ConsoleDriver, ConsoleSource)>; MAX_SINKS]> =
+    SpinNoIrq::new([None; MAX_SINKS]);
+
+/// The sources written to the console device, see [`set_console_sources`].
+static CONSOLE_SOURCES: AtomicU8 = AtomicU8::new(ConsoleSource::all().bits());
+
+bitflags::bitflags! {
+    /// The sources of console output.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ConsoleSource: u8 {
+        /// Log records and other messages of the kernel.
+        const LOG = 1 << 0;
+        /// Output of applications, i.e., their standard output and error.
+        const STDOUT = 1 << 1;
+    }
+}
+
+/// A console device.
+pub trait ConsoleDriver: Sync {
+    /// Initializes the device.
+    fn init(&self) {}
+
+    /// Writes bytes to the device, blocking until all of them are written.
+    fn write(&self, bytes: &[u8]);
+
+    /// Reads the received bytes into `bytes` without blocking.
+    ///
+    /// Returns the number of bytes read.
+    fn try_read(&self, bytes: &mut [u8]) -> usize;
+
+    /// Enables the receive interrupt, so received bytes are buffered by the
+    /// IRQ handler instead of being polled from the device.
+    ///
+    /// Returns `false` if the device does not support it.
+    fn enable_rx_irq(&self) -> bool {
+        false
+    }
+
+    /// Writes bytes to the device without waiting for them to be sent.
+    ///
+    /// Returns the number of bytes accepted, which is less than
+    /// `bytes.len()` if the transmit buffer is full. By default, the bytes
+    /// are written with [`ConsoleDriver::write`].
+    fn write_nonblocking(&self, bytes: &[u8]) -> usize {
+        self.write(bytes);
+        bytes.len()
+    }
+
+    /// Enables the transmit interrupt, so bytes written by
+    /// [`ConsoleDriver::write_nonblocking`] are buffered and sent by the IRQ
+    /// handler.
+    ///
+    /// Returns `false` if the device does not support it.
+    fn enable_tx_irq(&self) -> bool {
+        false
+    }
+
+    /// Sends all buffered bytes, blocking until they are sent.
+    ///
+    /// It is called at shutdown, including after a panic, so it should not
+    /// wait for locks.
+    fn flush(&self) {}
+}
+
+/// Adds a device that receives a copy of the console output from the given
+/// sources, e.g., a framebuffer console.
+///
+/// The device receives the bytes as they are written, without line feeds
+/// translated. The bytes it returns from [`ConsoleDriver::try_read`] are
+/// read as console input. Returns the ID of the sink, or `None` if there are already
+/// [`MAX_SINKS`] sinks added.
+pub fn add_sink(dev: &'static dyn ConsoleDriver, sources: ConsoleSource) -> Option<usize> {
+    let mut sinks = SINKS.lock();
+    let id = sinks.iter().position(Option::is_none)?;
+    sinks[id] = Some((dev, sources));
+    Some(id)
+}
+
+/// Changes the sources whose output is copied to the sink with the given
+/// ID. Returns `false` if there is no such sink.
+pub fn set_sink_sources(id: usize, sources: ConsoleSource) -> bool {
+    match SINKS.lock().get_mut(id) {
+        Some(Some((_, old))) => {
+            *old = sources;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Removes the sink with the given ID, added by [`add_sink`].
+pub fn remove_sink(id: usize) {
+    if let Some(sink) = SINKS.lock().get_mut(id) {
+        *sink = None;
+    }
+}
+
+/// Selects the sources whose output is written to the console device. All
+/// sources are written by default.
+pub fn set_console_sources(sources: ConsoleSource) {
+    CONSOLE_SOURCES.store(sources.bits(), Ordering::Release);
+}
+
+/// Returns the sources whose output is written to the console device.
+pub fn console_sources() -> ConsoleSource {
+    ConsoleSource::from_bits_truncate(CONSOLE_SOURCES.load(Ordering::Acquire))
+}
+
+/// Reads the bytes received by the sinks, in the order they were added.
+fn read_sinks(bytes: &mut [u8]) -> usize {
+    let sinks = *SINKS.lock();
+    let mut len = 0;
+    for (dev, _) in sinks.iter().flatten() {
+        if len == bytes.len() {
+            break;
+        }
+        len += dev.try_read(&mut bytes[len..]);
+    }
+    len
+}
+
+fn write_sinks(source: ConsoleSource, bytes: &[u8]) {
+    let sinks = *SINKS.lock();
+    for (dev, sources) in sinks.iter().flatten() {
+        if sources.contains(source) {
+            dev.write(bytes);
+        }
+    }
+}
+
+/// Returns the console device, which is that of the platform unless it is
+/// replaced by [`set_console`].
+pub fn driver() -> &'static dyn ConsoleDriver {
+    ACTIVE_CONSOLE.lock().unwrap_or(&CONSOLE)
+}
+
+/// Replaces the console device with the given port.
+///
+/// All console input and output goes through the port afterwards, e.g., a
+/// VirtIO console, which is faster than an emulated UART.
+pub fn set_console(port: Console) {
+    *ACTIVE_CONSOLE.lock() = Some(port.0);
+}
+
+/// Adds a console device as a new port, e.g., a device probed by a driver.
+///
+/// Returns the number of the port, or `None` if there are already
+/// [`MAX_EXTRA_PORTS`] ports added.
+pub fn register_port(dev: &'static dyn ConsoleDriver) -> Option<usize> {
+    let mut extra_ports = EXTRA_PORTS.lock();
+    let idx = extra_ports.iter().position(Option::is_none)?;
+    extra_ports[idx] = Some(dev);
+    Some(PORTS.len() + idx)
+}
+
+/// A serial port of the platform.
+#[derive(Clone, Copy)]
+pub struct Console(&'static dyn ConsoleDriver);
+
+impl Console {
+    /// Returns the `n`-th port, or `None` if there is no such port.
+    ///
+    /// The serial ports of the platform come first, followed by the ports
+    /// added by [`register_port`]. Port 0 is the console device of the
+    /// platform, which is initialized at boot. Other serial ports must be
+    /// initialized with [`Console::init`] before use.
+    pub fn port(n: usize) -> Option<Self> {
+        match PORTS.get(n) {
+            Some(&dev) => Some(Self(dev)),
+            None => EXTRA_PORTS.lock().get(n - PORTS.len())?.map(Self),
+        }
+    }
+
+    /// Returns the number of ports.
+    pub fn num_ports() -> usize {
+        PORTS.len() + EXTRA_PORTS.lock().iter().flatten().count()
+    }
+
+    /// Returns the device of the port.
+    pub fn driver(&self) -> &'static dyn ConsoleDriver {
+        self.0
+    }
+
+    /// Initializes the port.
+    pub fn init(&self) {
+        self.0.init();
+    }
+
+    /// Enables the receive interrupt of the port, see
+    /// [`ConsoleDriver::enable_rx_irq`].
+    pub fn enable_rx_irq(&self) -> bool {
+        self.0.enable_rx_irq()
+    }
+
+    /// Enables the transmit interrupt of the port, see
+    /// [`ConsoleDriver::enable_tx_irq`].
+    pub fn enable_tx_irq(&self) -> bool {
+        self.0.enable_tx_irq()
+    }
+
+    /// Write a slice of bytes to the port, see [`write_bytes`].
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        write_translated(self.0, bytes);
+    }
+
+    /// Writes bytes to the port without blocking, see
+    /// [`write_bytes_nonblocking`].
+    pub fn write_bytes_nonblocking(&self, bytes: &[u8]) -> usize {
+        write_translated_nonblocking(self.0, bytes)
+    }
+
+    /// Sends all buffered bytes of the port.
+    pub fn flush(&self) {
+        self.0.flush();
+    }
+
+    /// Reads bytes from the port without blocking, see [`read_bytes`].
+    ///
+    /// Unlike [`read_bytes`], the bytes are returned as received, without
+    /// the line discipline, and nothing is replayed with the
+    /// `console-replay` feature.
+    pub fn read_bytes(&self, bytes: &mut [u8]) -> usize {
+        let len = self.0.try_read(bytes);
+        translate_cr(&mut bytes[..len]);
+        len
+    }
+}
+
+/// The size of the chunks the output is translated in, so a device is locked
+/// once per chunk rather than once per line.
+const WRITE_CHUNK_SIZE: usize = 256;
+
+/// Translates bytes into `buf`, with line feeds as `\r\n`, until it is full.
+///
+/// Returns the number of bytes of `bytes` translated, and that of `buf`
+/// filled.
+fn translate_lf(bytes: &[u8], buf: &mut [u8]) -> (usize, usize) {
+    let mut len = 0;
+    for (i, &c) in bytes.iter().enumerate() {
+        let translated: &[u8] = if c == b'\n' { b"\r\n" } else { &[c] };
+        let Some(dst) = buf.get_mut(len..len + translated.len()) else {
+            return (i, len);
+        };
+        dst.copy_from_slice(translated);
+        len += translated.len();
+    }
+    (bytes.len(), len)
+}
+
+/// Writes bytes to `dev`, with line feeds written as `\r\n`.
+pub(crate) fn write_translated(dev: &dyn ConsoleDriver, bytes: &[u8]) {
+    if !bytes.contains(&b'\n') {
+        dev.write(bytes);
+        return;
+    }
+    let mut buf = [0; WRITE_CHUNK_SIZE];
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (consumed, len) = translate_lf(rest, &mut buf);
+        dev.write(&buf[..len]);
+        rest = &rest[consumed..];
+    }
+}
+
+/// Writes bytes to `dev` without blocking, with line feeds written as
+/// `\r\n`.
+///
+/// Returns the number of bytes of `bytes` accepted.
+fn write_translated_nonblocking(dev: &dyn ConsoleDriver, bytes: &[u8]) -> usize {
+    if !bytes.contains(&b'\n') {
+        return dev.write_nonblocking(bytes);
+    }
+    let mut buf = [0; WRITE_CHUNK_SIZE];
+    let mut written = 0;
+    while written < bytes.len() {
+        let (consumed, len) = translate_lf(&bytes[written..], &mut buf);
+        let accepted = dev.write_nonblocking(&buf[..len]);
+        if accepted < len {
+            // Count the bytes whose translation is accepted in whole. A
+            // partially written `\r\n` is written again on retry, as an
+            // extra `\r` is harmless.
+            let (consumed, _) = translate_lf(&bytes[written..], &mut buf[..accepted]);
+            return written + consumed;
+        }
+        written += consumed;
+    }
+    written
+}
+
+/// Replaces carriage returns in received bytes with line feeds.
+fn translate_cr(bytes: &mut [u8]) {
+    for c in bytes {
+        if *c == b'\r' {
+            *c = b'\n';
+        }
+    }
+}
+
+/// The interrupt character, `Ctrl-C`.
+const INTERRUPT_CHAR: u8 = 0x03;
+
+static INTERRUPT_HANDLER: RcuCell<Option<fn() -> bool>> = RcuCell::new(None);
+
+/// Sets the function called when the interrupt character (`Ctrl-C`) is
+/// received, e.g., to cancel the foreground task.
+///
+/// It returns whether the character is handled, and so not returned as
+/// input. It may be called in interrupt context, when the character is
+/// received by the IRQ handler of a console port, or else when the input is
+/// read. The old function is no longer running when it returns.
+pub fn set_interrupt_handler(handler: fn() -> bool) {
+    INTERRUPT_HANDLER.replace(Some(handler));
+}
+
+/// Calls the function set by [`set_interrupt_handler`] if `c` is the
+/// interrupt character, and returns whether it is handled.
+///
+/// With the `sysrq` feature, the magic keys are handled first.
+pub(crate) fn handle_interrupt_char(c: u8) -> bool {
+    #[cfg(feature = "sysrq")]
+    if super::console_sysrq::handle_char(c) {
+        return true;
+    }
+    if c != INTERRUPT_CHAR {
+        return false;
+    }
+    let guard = rcu::read_lock();
+    INTERRUPT_HANDLER
+        .read(&guard)
+        .is_some_and(|handler| handler())
+}
+
+/// Removes the interrupt characters handled from received bytes, returns the
+/// number of bytes left.
+fn filter_interrupt_chars(bytes: &mut [u8]) -> usize {
+    let mut len = 0;
+    for i in 0..bytes.len() {
+        if !handle_interrupt_char(bytes[i]) {
+            bytes[len] = bytes[i];
+            len += 1;
+        }
+    }
+    len
+}
+
+/// Write a slice of bytes to the console, as the output of applications
+/// ([`ConsoleSource::STDOUT`]).
+pub fn write_bytes(bytes: &[u8]) {
+    write_bytes_from(ConsoleSource::STDOUT, bytes);
+}
+
+/// Writes a slice of bytes from the given source to the console device and
+/// the sinks that receive the source.
+pub fn write_bytes_from(source: ConsoleSource, bytes: &[u8]) {
+    #[cfg(feature = "earlycon")]
+    if super::earlycon::is_enabled() {
+        super::earlycon::write_bytes(bytes);
+        return;
+    }
+    if console_sources().contains(source) {
+        #[cfg(feature = "vt")]
+        super::console_vt::write(source, bytes, |bytes| {
+            write_translated(driver(), bytes);
+            bytes.len()
+        });
+        #[cfg(not(feature = "vt"))]
+        write_translated(driver(), bytes);
+    }
+    write_sinks(source, bytes);
+}
+
+/// Writes a slice of bytes to the console without waiting for them to be
+/// sent, if the transmit interrupt of the console is enabled.
+///
+/// Returns the number of bytes accepted, which is less than `bytes.len()` if
+/// the transmit buffer is full. The buffered bytes are sent before any bytes
+/// written later by [`write_bytes`], and at shutdown.
+pub fn write_bytes_nonblocking(bytes: &[u8]) -> usize {
+    write_bytes_nonblocking_from(ConsoleSource::STDOUT, bytes)
+}
+
+/// Writes a slice of bytes from the given source without waiting for them to
+/// be sent, see [`write_bytes_nonblocking`] and [`write_bytes_from`].
+pub fn write_bytes_nonblocking_from(source: ConsoleSource, bytes: &[u8]) -> usize {
+    #[cfg(feature = "earlycon")]
+    if super::earlycon::is_enabled() {
+        super::earlycon::write_bytes(bytes);
+        return bytes.len();
+    }
+    let written = if console_sources().contains(source) {
+        let write_device = |bytes: &[u8]| write_translated_nonblocking(driver(), bytes);
+        #[cfg(feature = "vt")]
+        let write_device = |bytes: &[u8]| super::console_vt::write(source, bytes, write_device);
+        write_device(bytes)
+    } else {
+        bytes.len()
+    };
+    write_sinks(source, &bytes[..written]);
+    written
+}
+
+/// Sends all bytes buffered by [`write_bytes_nonblocking`].
+pub fn flush() {
+    driver().flush();
+}
+
+/// Sends the buffered bytes of all ports at shutdown, so the last logs are
+/// not lost after a panic.
+#[linkme::distributed_slice(crate::misc::SHUTDOWN_HOOKS)]
+fn flush_ports() {
+    for port in &PORTS {
+        port.flush();
+    }
+    if let Some(extra_ports) = EXTRA_PORTS.try_lock() {
+        for port in extra_ports.iter().flatten() {
+            port.flush();
+        }
+    }
+}
+
+static RX_DROPPED: AtomicU64 = AtomicU64::new(0);
+static RX_OVERRUNS: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the console input lost, see [`stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConsoleStats {
+    /// Bytes received and then dropped as a buffer was full, e.g., in excess
+    /// of the longest line in the cooked mode.
+    pub rx_dropped: u64,
+    /// Overrun errors reported by the UARTs, each for at least one byte lost
+    /// as the receive FIFO was full.
+    pub rx_overruns: u64,
+}
+
+/// Returns the counters of the console input lost since boot.
+pub fn stats() -> ConsoleStats {
+    ConsoleStats {
+        rx_dropped: RX_DROPPED.load(Ordering::Relaxed),
+        rx_overruns: RX_OVERRUNS.load(Ordering::Relaxed),
+    }
+}
+
+/// Counts bytes received and then dropped as a buffer was full.
+///
+/// It is called by console devices, and can be called by sinks receiving
+/// input elsewhere, e.g., a network console.
+pub fn count_rx_dropped(n: usize) {
+    if n > 0 {
+        RX_DROPPED.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counts an overrun error reported by a UART.
+pub(crate) fn count_rx_overrun() {
+    RX_OVERRUNS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Reads bytes from the console into the given mutable slice.
+/// Returns the number of bytes read.
+///
+/// The input is processed by the line discipline according to [`mode`], so
+/// nothing is returned in the cooked mode until a line is ended.
+pub fn read_bytes(bytes: &mut [u8]) -> usize {
+    super::console_ldisc::read_bytes(bytes, read_raw)
+}
+
+/// Reads the bytes received by the console, and then by the sinks.
+///
+/// With the `console-replay` feature, the bytes are replayed from the
+/// embedded script first. With the `vt` feature, they go to the active
+/// terminal, and the input of the terminal of the applications is returned.
+fn read_raw(bytes: &mut [u8]) -> usize {
+    #[cfg(feature = "console-replay")]
+    let mut len = super::console_replay::read_bytes(bytes);
+    #[cfg(not(feature = "console-replay"))]
+    let mut len = driver().try_read(bytes);
+    len += read_sinks(&mut bytes[len..]);
+    translate_cr(&mut bytes[..len]);
+    let len = filter_interrupt_chars(&mut bytes[..len]);
+    #[cfg(feature = "vt")]
+    let len = {
+        super::console_vt::receive(&bytes[..len]);
+        read_vt_input(source_vt(ConsoleSource::STDOUT), bytes)
+    };
+    len
+}
+
+#[cfg(feature = "irq")]
+pub use self::rx_notify::{notify_rx, rx_events, set_rx_notifier};
+
+#[cfg(feature = "irq")]
+pub(crate) use self::queue::{RxQueue, TxQueue};
+
+/// Notification of input received by the IRQ handlers of console ports.
+#[cfg(feature = "irq")]
+mod rx_notify {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::rcu::{self, RcuCell};
+
+    static RX_EVENTS: AtomicUsize = AtomicUsize::new(0);
+    static RX_NOTIFIER: RcuCell<Option<fn()>> = RcuCell::new(None);
+
+    /// Sets the function called when bytes are received by the IRQ handler
+    /// of a console port, e.g., to wake up the tasks waiting for input.
+    ///
+    /// It is called in interrupt context. The old function is no longer
+    /// running when it returns.
+    pub fn set_rx_notifier(notifier: fn()) {
+        RX_NOTIFIER.replace(Some(notifier));
+    }
+
+    /// Returns a counter of the input events of console ports, which changes
+    /// when new input may be available.
+    ///
+    /// Consoles without receive interrupts do not update it, so their input
+    /// must still be polled.
+    pub fn rx_events() -> usize {
+        RX_EVENTS.load(Ordering::Acquire)
+    }
+
+    /// Reports an input event, calling the function set by
+    /// [`set_rx_notifier`].
+    ///
+    /// It is called by the IRQ handlers of console ports, and can be called
+    /// by sinks receiving input elsewhere, e.g., a network console.
+    pub fn notify_rx() {
+        RX_EVENTS.fetch_add(1, Ordering::AcqRel);
+        let guard = rcu::read_lock();
+        if let Some(notifier) = RX_NOTIFIER.read(&guard) {
+            notifier();
+        }
+    }
+}
+
+#[cfg(feature = "irq")]
+mod queue {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use axring::SpscRing;
+    use kspin::SpinNoIrq;
+
+    /// The capacity of [`RxQueue`], which should be large enough for a
+    /// pasted script.
+    const RX_QUEUE_SIZE: usize = axconfig::CONSOLE_RX_QUEUE_SIZE;
+
+    /// The capacity of [`TxQueue`].
+    const TX_QUEUE_SIZE: usize = axconfig::CONSOLE_TX_QUEUE_SIZE;
+
+    /// Bytes received by the IRQ handler of a console device and not read
+    /// yet.
+    ///
+    /// When the queue is full, the device is throttled: the received bytes
+    /// are left in the device, whose receive interrupt should be disabled
+    /// (and RTS deasserted, if supported) until [`RxQueue::should_resume`]
+    /// returns `true`, so no bytes are dropped as long as the sender obeys
+    /// the flow control.
+    pub(crate) struct RxQueue {
+        buf: SpscRing<u8, RX_QUEUE_SIZE>,
+        /// Serializes the IRQ handler and the readers filling the queue, the
+        /// producers of `buf`.
+        fill_lock: SpinNoIrq<()>,
+        /// Serializes the readers, the consumers of `buf`.
+        read_lock: SpinNoIrq<()>,
+        enabled: AtomicBool,
+        throttled: AtomicBool,
+    }
+
+    impl RxQueue {
+        pub const fn new() -> Self {
+            Self {
+                buf: SpscRing::new(),
+                fill_lock: SpinNoIrq::new(()),
+                read_lock: SpinNoIrq::new(()),
+                enabled: AtomicBool::new(false),
+                throttled: AtomicBool::new(false),
+            }
+        }
+
+        /// Whether the received bytes are read from the queue rather than
+        /// from the device directly.
+        pub fn is_enabled(&self) -> bool {
+            self.enabled.load(Ordering::Acquire)
+        }
+
+        pub fn enable(&self) {
+            self.enabled.store(true, Ordering::Release);
+        }
+
+        /// Moves bytes returned by `getchar` to the queue, until there are
+        /// no more or the queue is full.
+        ///
+        /// Returns `true` if the queue is full, i.e., the device should be
+        /// throttled.
+        pub fn fill(&self, mut getchar: impl FnMut() -> Option<u8>) -> bool {
+            let guard = self.fill_lock.lock();
+            let mut full = true;
+            let mut received = false;
+            let mut interrupted = false;
+            // Only the producer fills the queue, so it stays not full.
+            while !self.buf.is_full() {
+                match getchar() {
+                    Some(c) if super::handle_interrupt_char(c) => interrupted = true,
+                    Some(c) => {
+                        // SAFETY: the producers are serialized by `fill_lock`.
+                        received |= unsafe { self.buf.push(c) }.is_ok();
+                    }
+                    None => {
+                        full = false;
+                        break;
+                    }
+                };
+            }
+            drop(guard);
+            // Not logged, as it is called with the device locked, which the
+            // logs may be written to.
+            if full {
+                self.throttled.store(true, Ordering::Release);
+            }
+            // The readers are woken up on interrupts too, to check whether
+            // they are cancelled.
+            if received || interrupted {
+                super::rx_notify::notify_rx();
+            }
+            full
+        }
+
+        /// Returns `true` once if the device is throttled and at least half
+        /// of the queue is free, i.e., the device should be filled from again
+        /// and its receive interrupt enabled.
+        pub fn should_resume(&self) -> bool {
+            self.throttled.load(Ordering::Acquire)
+                && self.buf.len() <= RX_QUEUE_SIZE / 2
+                && self.throttled.swap(false, Ordering::AcqRel)
+        }
+
+        pub fn read(&self, bytes: &mut [u8]) -> usize {
+            let _guard = self.read_lock.lock();
+            // SAFETY: the consumers are serialized by `read_lock`.
+            unsafe { self.buf.read(bytes) }
+        }
+    }
+
+    /// Bytes written to a console device and not sent yet, sent by the IRQ
+    /// handler when the device is ready to transmit.
+    pub(crate) struct TxQueue {
+        buf: SpscRing<u8, TX_QUEUE_SIZE>,
+        /// Serializes the writers, the producers of `buf`.
+        push_lock: SpinNoIrq<()>,
+        /// Serializes the IRQ handler and the flushes sending the bytes, the
+        /// consumers of `buf`.
+        pop_lock: SpinNoIrq<()>,
+        enabled: AtomicBool,
+    }
+
+    impl TxQueue {
+        pub const fn new() -> Self {
+            Self {
+                buf: SpscRing::new(),
+                push_lock: SpinNoIrq::new(()),
+                pop_lock: SpinNoIrq::new(()),
+                enabled: AtomicBool::new(false),
+            }
+        }
+
+        /// Whether written bytes are buffered rather than sent to the device
+        /// directly.
+        pub fn is_enabled(&self) -> bool {
+            self.enabled.load(Ordering::Acquire)
+        }
+
+        pub fn enable(&self) {
+            self.enabled.store(true, Ordering::Release);
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.buf.is_empty()
+        }
+
+        /// Appends bytes to the queue, returns the number of bytes appended.
+        pub fn push(&self, bytes: &[u8]) -> usize {
+            let _guard = self.push_lock.lock();
+            // SAFETY: the producers are serialized by `push_lock`.
+            unsafe { self.buf.push_slice(bytes) }
+        }
+
+        pub fn pop(&self) -> Option<u8> {
+            let _guard = self.pop_lock.lock();
+            // SAFETY: the consumers are serialized by `pop_lock`.
+            unsafe { self.buf.pop() }
+        }
+    }
+}
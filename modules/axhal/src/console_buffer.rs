@@ -0,0 +1,102 @@
+//! Console output kept in memory.
+
+use kspin::SpinNoIrq;
+
+use crate::console::ConsoleDriver;
+
+/// A console sink keeping the last `N` bytes of the output in memory, e.g.,
+/// to show the boot logs later.
+///
+/// Add it with [`add_sink`](crate::console::add_sink) to receive the output.
+pub struct ConsoleBuffer<const N: usize> {
+    inner: SpinNoIrq<Ring<N>>,
+}
+
+struct Ring<const N: usize> {
+    buf: [u8; N],
+    /// The position of the next byte written.
+    head: usize,
+    /// Whether the buffer is full, i.e., the oldest byte is at `head`.
+    wrapped: bool,
+}
+
+impl<const N: usize> ConsoleBuffer<N> {
+    /// Creates an empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            inner: SpinNoIrq::new(Ring {
+                buf: [0; N],
+                head: 0,
+                wrapped: false,
+            }),
+        }
+    }
+
+    /// Returns the number of bytes kept.
+    pub fn len(&self) -> usize {
+        let ring = self.inner.lock();
+        if ring.wrapped { N } else { ring.head }
+    }
+
+    /// Whether nothing is kept.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the bytes kept into `buf`, from the oldest, returns the number
+    /// of bytes copied.
+    ///
+    /// If `buf` is shorter than the bytes kept, the newest bytes that fit are
+    /// copied.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let ring = self.inner.lock();
+        let (old, new) = if ring.wrapped {
+            (&ring.buf[ring.head..], &ring.buf[..ring.head])
+        } else {
+            (&[][..], &ring.buf[..ring.head])
+        };
+        let skip = (old.len() + new.len()).saturating_sub(buf.len());
+        let mut len = 0;
+        for (dst, &c) in buf.iter_mut().zip(old.iter().chain(new).skip(skip)) {
+            *dst = c;
+            len += 1;
+        }
+        len
+    }
+
+    /// Discards all bytes kept.
+    pub fn clear(&self) {
+        let mut ring = self.inner.lock();
+        ring.head = 0;
+        ring.wrapped = false;
+    }
+}
+
+impl<const N: usize> Default for ConsoleBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ConsoleDriver for ConsoleBuffer<N> {
+    fn write(&self, bytes: &[u8]) {
+        if N == 0 {
+            return;
+        }
+        let mut ring = self.inner.lock();
+        // Only the last `N` bytes would be kept.
+        let bytes = &bytes[bytes.len().saturating_sub(N)..];
+        for &c in bytes {
+            let head = ring.head;
+            ring.buf[head] = c;
+            ring.head = (head + 1) % N;
+            if ring.head == 0 {
+                ring.wrapped = true;
+            }
+        }
+    }
+
+    fn try_read(&self, _bytes: &mut [u8]) -> usize {
+        0
+    }
+}
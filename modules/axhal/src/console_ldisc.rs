@@ -0,0 +1,163 @@
+//! Line discipline of the console input.
+//!
+//! Like the canonical mode of a terminal, the input returned by
+//! [`read_bytes`] is processed line by line by default
+//! ([`ConsoleMode::Cooked`]): received characters are echoed and collected
+//! into a line, which can be edited with backspace (`^H` or `DEL`) and
+//! erased with `^U`. A line is only returned after it is ended by a line
+//! feed, and a read returns at most one line. Control characters are echoed
+//! as `^X`.
+//!
+//! Programs doing their own echo and line editing, e.g., the shell, switch
+//! to [`ConsoleMode::Raw`], in which the bytes are returned as soon as they
+//! are received, without echo.
+//!
+//! [`read_bytes`]: crate::console::read_bytes
+
+use kspin::SpinNoIrq;
+
+use crate::console::write_bytes;
+
+/// The maximum length of a line, including the line feed. Characters after
+/// it are discarded until the line is ended.
+const MAX_LINE: usize = 256;
+
+/// The number of bytes read from the console device at a time in the cooked
+/// mode.
+const READ_CHUNK: usize = 64;
+
+const ERASE_CHAR: u8 = 0x7f;
+const ERASE_CHAR_ALT: u8 = 0x08;
+const KILL_LINE: u8 = 0x15;
+
+/// The mode of the console input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleMode {
+    /// Bytes are returned as soon as they are received, without echo.
+    Raw,
+    /// Input is echoed and edited in the kernel, and returned line by line.
+    Cooked,
+}
+
+static LDISC: SpinNoIrq<LineDiscipline> = SpinNoIrq::new(LineDiscipline::new());
+
+struct LineDiscipline {
+    mode: ConsoleMode,
+    buf: [u8; MAX_LINE],
+    /// The number of bytes in `buf`.
+    len: usize,
+    /// The number of bytes of the ended lines at the start of `buf`, which
+    /// are ready to be read. The rest is the line being edited.
+    ready: usize,
+}
+
+impl LineDiscipline {
+    const fn new() -> Self {
+        Self {
+            mode: ConsoleMode::Cooked,
+            buf: [0; MAX_LINE],
+            len: 0,
+            ready: 0,
+        }
+    }
+
+    /// Moves at most `max` bytes from the start of `buf` into `bytes`,
+    /// returns the number of bytes moved.
+    fn take(&mut self, bytes: &mut [u8], max: usize) -> usize {
+        let len = max.min(bytes.len());
+        bytes[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.copy_within(len..self.len, 0);
+        self.len -= len;
+        self.ready = self.ready.saturating_sub(len);
+        len
+    }
+
+    /// Erases the last character of the line being edited, along with its
+    /// echo.
+    fn erase(&mut self) {
+        if self.len == self.ready {
+            return;
+        }
+        self.len -= 1;
+        write_bytes(b"\x08 \x08");
+        if is_control(self.buf[self.len]) {
+            write_bytes(b"\x08 \x08");
+        }
+    }
+
+    fn input(&mut self, c: u8) {
+        match c {
+            b'\n' => {
+                self.buf[self.len] = c;
+                self.len += 1;
+                self.ready = self.len;
+                write_bytes(b"\n");
+            }
+            ERASE_CHAR | ERASE_CHAR_ALT => self.erase(),
+            KILL_LINE => {
+                while self.len > self.ready {
+                    self.erase();
+                }
+            }
+            // Keep the last byte for the line feed.
+            _ if self.len + 1 >= MAX_LINE => super::console::count_rx_dropped(1),
+            _ => {
+                self.buf[self.len] = c;
+                self.len += 1;
+                if is_control(c) {
+                    write_bytes(&[b'^', c ^ 0x40]);
+                } else {
+                    write_bytes(&[c]);
+                }
+            }
+        }
+    }
+}
+
+/// Whether the character is echoed as `^X` in the cooked mode.
+fn is_control(c: u8) -> bool {
+    c < b' ' && c != b'\t'
+}
+
+/// Returns the current mode of the console input.
+pub fn mode() -> ConsoleMode {
+    LDISC.lock().mode
+}
+
+/// Sets the mode of the console input.
+///
+/// The input already received in the cooked mode is not discarded, and is
+/// returned first after switching to the raw mode.
+pub fn set_mode(mode: ConsoleMode) {
+    LDISC.lock().mode = mode;
+}
+
+/// Reads the input processed according to the mode, with `read_raw` reading
+/// the bytes received by the console device.
+pub(crate) fn read_bytes(bytes: &mut [u8], read_raw: fn(&mut [u8]) -> usize) -> usize {
+    let mut ldisc = LDISC.lock();
+    if ldisc.mode == ConsoleMode::Raw {
+        let pending = ldisc.len;
+        let len = ldisc.take(bytes, pending);
+        return len + read_raw(&mut bytes[len..]);
+    }
+    // Once the buffer is full of ended lines, the bytes are left in the
+    // device until they are read. A line too long is still read, with the
+    // excess characters discarded, so it can be ended.
+    let room = if ldisc.ready > 0 {
+        (MAX_LINE - 1).saturating_sub(ldisc.len)
+    } else {
+        READ_CHUNK
+    };
+    let mut chunk = [0; READ_CHUNK];
+    let len = read_raw(&mut chunk[..room.min(READ_CHUNK)]);
+    for &c in &chunk[..len] {
+        ldisc.input(c);
+    }
+    // Like a terminal, at most one line is returned at a time.
+    let line_len = ldisc.buf[..ldisc.ready]
+        .iter()
+        .position(|&c| c == b'\n')
+        .map_or(0, |pos| pos + 1);
+    ldisc.take(bytes, line_len)
+}
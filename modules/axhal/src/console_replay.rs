@@ -0,0 +1,192 @@
+//! Deterministic replay of console input.
+//!
+//! When the `console-replay` feature is enabled, [`read_bytes`] first returns
+//! the input from a script embedded at build time (specified by the
+//! `AX_CONSOLE_REPLAY` environment variable, or `REPLAY=<path>` in `make`),
+//! and only reads from the real console after the script is exhausted. Every
+//! byte returned, whether replayed or not, is logged with a timestamp, so a
+//! run can be reproduced by turning the log back into a script.
+//!
+//! # Script format
+//!
+//! The script is processed line by line:
+//!
+//! - `# ...`: a comment, ignored.
+//! - `@delay <ms>`: waits `<ms>` milliseconds before the following input.
+//! - `@at <ms>`: waits until `<ms>` milliseconds after boot.
+//! - `@live`: stops replaying and switches to the real console.
+//! - Any other line (including an empty one) is sent as input, followed by a
+//!   newline. A trailing `\` suppresses the newline, and the escapes `\\`,
+//!   `\n`, `\r`, `\t`, `\e` and `\xNN` can be used for control keys.
+//!
+//! ```text
+//! # wait for the shell prompt
+//! @delay 500
+//! ls /
+//! @delay 100
+//! \x03\
+//! ```
+
+use kspin::SpinNoIrq;
+
+use crate::time::{Duration, monotonic_time};
+
+static SCRIPT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/console_replay.txt"));
+
+static REPLAY: SpinNoIrq<Replay> = SpinNoIrq::new(Replay::new(SCRIPT));
+
+struct Replay {
+    script: &'static [u8],
+    pos: usize,
+    line_start: bool,
+    ready_at: Duration,
+}
+
+impl Replay {
+    const fn new(script: &'static [u8]) -> Self {
+        Self {
+            script,
+            pos: 0,
+            line_start: true,
+            ready_at: Duration::ZERO,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pos >= self.script.len()
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.script.get(self.pos + offset).copied()
+    }
+
+    /// Returns the rest of the current line and moves to the next line.
+    fn take_line(&mut self) -> &'static [u8] {
+        let rest = &self.script[self.pos..];
+        let len = rest.iter().position(|&c| c == b'\n');
+        self.pos += len.map_or(rest.len(), |len| len + 1);
+        self.line_start = true;
+        let line = &rest[..len.unwrap_or(rest.len())];
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+
+    fn directive(&mut self, now: Duration) {
+        let line = self.take_line();
+        let line = core::str::from_utf8(line).unwrap_or_default();
+        let mut args = line[1..].split_whitespace();
+        let cmd = args.next().unwrap_or_default();
+        let ms = args.next().and_then(|s| s.parse::<u64>().ok());
+        match (cmd, ms) {
+            ("delay", Some(ms)) => self.ready_at = now + Duration::from_millis(ms),
+            ("at", Some(ms)) => self.ready_at = Duration::from_millis(ms),
+            ("live", None) => self.pos = self.script.len(),
+            _ => warn!("console replay: invalid directive {:?}", line),
+        }
+    }
+
+    fn escape(&mut self) -> Option<u8> {
+        let c = match self.peek(1) {
+            None | Some(b'\n') => {
+                // Line continuation: no newline.
+                self.take_line();
+                return None;
+            }
+            Some(b'\r') if self.peek(2) == Some(b'\n') => {
+                self.take_line();
+                return None;
+            }
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'e') => 0x1b,
+            Some(b'x') => {
+                let hex = self.script.get(self.pos + 2..self.pos + 4);
+                let value = hex
+                    .and_then(|hex| core::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(value) = value {
+                    self.pos += 4;
+                    return Some(value);
+                }
+                b'x'
+            }
+            Some(c) => c,
+        };
+        self.pos += 2;
+        Some(c)
+    }
+
+    /// Returns the next byte to be replayed, or `None` if the script is
+    /// finished or the next byte is not due yet.
+    fn next_byte(&mut self, now: Duration) -> Option<u8> {
+        loop {
+            if self.is_finished() || now < self.ready_at {
+                return None;
+            }
+            if self.line_start {
+                match self.peek(0) {
+                    Some(b'#') => {
+                        self.take_line();
+                        continue;
+                    }
+                    Some(b'@') => {
+                        self.directive(now);
+                        continue;
+                    }
+                    _ => self.line_start = false,
+                }
+            }
+            match self.peek(0)? {
+                b'\\' => match self.escape() {
+                    Some(c) => return Some(c),
+                    None => continue,
+                },
+                b'\r' if self.peek(1) == Some(b'\n') => self.pos += 1,
+                b'\n' => {
+                    self.pos += 1;
+                    self.line_start = true;
+                    return Some(b'\n');
+                }
+                c => {
+                    self.pos += 1;
+                    return Some(c);
+                }
+            }
+        }
+    }
+}
+
+/// Reads bytes from the replay script, or from the console once the script
+/// is finished. Returns the number of bytes read.
+///
+/// All bytes read are logged.
+pub fn read_bytes(bytes: &mut [u8]) -> usize {
+    let now = monotonic_time();
+    let mut replay = REPLAY.lock();
+    let (len, source) = if replay.is_finished() {
+        drop(replay);
+        (crate::console::driver().try_read(bytes), "live")
+    } else {
+        let mut len = 0;
+        while len < bytes.len() {
+            match replay.next_byte(now) {
+                Some(c) => bytes[len] = c,
+                None => break,
+            }
+            len += 1;
+        }
+        if replay.is_finished() {
+            info!("console replay: script finished");
+        }
+        (len, "replay")
+    };
+    if len > 0 {
+        info!(
+            "console input ({}) @{}ms: \"{}\"",
+            source,
+            now.as_millis(),
+            bytes[..len].escape_ascii()
+        );
+    }
+    len
+}
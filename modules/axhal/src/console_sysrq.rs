@@ -0,0 +1,133 @@
+//! Magic keys on the console, like the SysRq key of Linux, running
+//! diagnostics without the cooperation of the application reading the
+//! input, e.g., when the shell is stuck.
+//!
+//! `Ctrl-\` followed by a key runs the action registered for the key with
+//! [`register_sysrq`]. The keys are handled before the line discipline and
+//! the virtual terminals, when they are received by the IRQ handler of a
+//! console port, or else when the input is read. `Ctrl-\` twice sends a
+//! literal `Ctrl-\`, and any other key lists the registered keys. The prefix
+//! is not `Ctrl-A`, which switches the virtual terminals, and is the escape
+//! key of QEMU.
+//!
+//! The keys of this module are `b`, which resets the system at once,
+//! without the shutdown hooks, and `i`, which shows the IRQ counts, with
+//! the `irq` feature.
+//!
+//! The actions may run in interrupt context, interrupting code which holds
+//! locks, so they should take as few as possible.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+/// The prefix of the magic keys (`Ctrl-\`).
+const PREFIX: u8 = 0x1c;
+
+/// The maximum number of actions registered with [`register_sysrq`].
+pub const MAX_SYSRQ_ACTIONS: usize = 16;
+
+/// An action run by a magic key.
+#[derive(Clone, Copy)]
+struct Action {
+    key: u8,
+    help: &'static str,
+    run: fn(),
+}
+
+/// The actions of this module.
+const BUILTIN_ACTIONS: &[Action] = &[
+    Action {
+        key: b'b',
+        help: "reset the system at once",
+        run: reset,
+    },
+    #[cfg(feature = "irq")]
+    Action {
+        key: b'i',
+        help: "show the IRQ counts",
+        run: show_irq_counts,
+    },
+];
+
+static ACTIONS: SpinNoIrq<[Option<Action>; MAX_SYSRQ_ACTIONS]> =
+    SpinNoIrq::new([None; MAX_SYSRQ_ACTIONS]);
+
+/// Whether the last byte received was [`PREFIX`], so the next one is a key.
+static PREFIX_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Runs `action` when `Ctrl-\` and then `key` are received, `help`
+/// describing it in the list of the keys.
+///
+/// Returns `false` if `key` already has an action, or
+/// [`MAX_SYSRQ_ACTIONS`] are registered.
+pub fn register_sysrq(key: u8, help: &'static str, action: fn()) -> bool {
+    if key == PREFIX || find(key).is_some() {
+        return false;
+    }
+    let mut actions = ACTIONS.lock();
+    let Some(slot) = actions.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+    *slot = Some(Action {
+        key,
+        help,
+        run: action,
+    });
+    true
+}
+
+/// Handles a received byte, and returns whether it is consumed, i.e., it is
+/// the prefix or the key following it.
+pub(crate) fn handle_char(c: u8) -> bool {
+    if !PREFIX_PENDING.swap(false, Ordering::AcqRel) {
+        if c == PREFIX {
+            PREFIX_PENDING.store(true, Ordering::Release);
+            return true;
+        }
+        return false;
+    }
+    if c == PREFIX {
+        // A literal prefix.
+        return false;
+    }
+    match find(c) {
+        Some(action) => (action.run)(),
+        None => show_help(),
+    }
+    true
+}
+
+/// Returns the action of a key.
+fn find(key: u8) -> Option<Action> {
+    actions().find(|action| action.key == key)
+}
+
+/// Returns the actions, copied so they do not run with the lock held.
+fn actions() -> impl Iterator<Item = Action> {
+    let registered = *ACTIONS.lock();
+    BUILTIN_ACTIONS
+        .iter()
+        .copied()
+        .chain(registered.into_iter().flatten())
+}
+
+fn show_help() {
+    axlog::ax_println!("SysRq: Ctrl-\\ followed by");
+    for action in actions() {
+        axlog::ax_println!("  {}  {}", action.key as char, action.help);
+    }
+}
+
+fn reset() {
+    axlog::ax_println!("SysRq: resetting");
+    crate::misc::reset();
+}
+
+#[cfg(feature = "irq")]
+fn show_irq_counts() {
+    axlog::ax_println!("SysRq: IRQ counts");
+    for (irq_num, count) in crate::irq::irq_counts() {
+        axlog::ax_println!("  IRQ {:>4}: {}", irq_num, count);
+    }
+}
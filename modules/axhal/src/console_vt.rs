@@ -0,0 +1,200 @@
+//! Virtual terminals multiplexed over the console device.
+//!
+//! The output of each [`ConsoleSource`] goes to one of [`NUM_VTS`] virtual
+//! terminals, see [`set_source_vt`]: by default, the output of applications
+//! to the first one, and the logs to the second one, so they do not
+//! interleave. Only the active terminal is shown on the console device. The
+//! last output of each terminal is kept in its scrollback, and shown again
+//! when switching to it.
+//!
+//! The input goes to the active terminal, each having its own input buffer.
+//! `Ctrl-A` followed by a digit switches to the terminal of that number
+//! (from `1`), followed by `n` or `p` to the next or previous one, and
+//! followed by `Ctrl-A` sends a literal `Ctrl-A`.
+//! [`read_bytes`](crate::console::read_bytes) reads the input of the
+//! terminal of the applications, and [`read_vt_input`] that of any one.
+//!
+//! The terminals are numbered from 0 in the API, but from 1 for the user.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::console::{ConsoleBuffer, ConsoleDriver, ConsoleSource};
+
+/// The number of virtual terminals.
+pub const NUM_VTS: usize = 4;
+
+/// The size of the scrollback of each terminal.
+const SCROLLBACK_SIZE: usize = 4096;
+
+/// The size of the input buffer of each terminal.
+const INPUT_SIZE: usize = 256;
+
+/// The prefix of the commands switching terminals.
+const HOTKEY: u8 = 0x01;
+
+/// Clears the screen and moves the cursor home.
+const CLEAR_SCREEN: &[u8] = b"\x1b[2J\x1b[H";
+
+/// The active terminal. It is locked while writing to the console device, so
+/// a switch does not happen in the middle of a write.
+static ACTIVE: SpinNoIrq<usize> = SpinNoIrq::new(0);
+
+/// The terminal of each source, by the index of its bit.
+static SOURCE_VTS: [AtomicUsize; 2] = [AtomicUsize::new(1), AtomicUsize::new(0)];
+
+/// Whether the last byte received was [`HOTKEY`], so the next one is a
+/// command.
+static HOTKEY_PENDING: AtomicBool = AtomicBool::new(false);
+
+static SCROLLBACKS: [ConsoleBuffer<SCROLLBACK_SIZE>; NUM_VTS] =
+    [const { ConsoleBuffer::new() }; NUM_VTS];
+
+static INPUTS: [SpinNoIrq<Input>; NUM_VTS] = [const { SpinNoIrq::new(Input::new()) }; NUM_VTS];
+
+/// A queue of received bytes, dropping the new bytes when it is full.
+struct Input {
+    buf: [u8; INPUT_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl Input {
+    const fn new() -> Self {
+        Self {
+            buf: [0; INPUT_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, c: u8) {
+        if self.len == INPUT_SIZE {
+            crate::console::count_rx_dropped(1);
+            return;
+        }
+        self.buf[(self.head + self.len) % INPUT_SIZE] = c;
+        self.len += 1;
+    }
+
+    fn pop(&mut self, bytes: &mut [u8]) -> usize {
+        let len = bytes.len().min(self.len);
+        for b in &mut bytes[..len] {
+            *b = self.buf[self.head];
+            self.head = (self.head + 1) % INPUT_SIZE;
+        }
+        self.len -= len;
+        len
+    }
+}
+
+fn source_index(source: ConsoleSource) -> usize {
+    source.bits().trailing_zeros() as usize
+}
+
+/// Returns the active terminal.
+pub fn active_vt() -> usize {
+    *ACTIVE.lock()
+}
+
+/// Switches to the given terminal, clearing the screen and showing its
+/// scrollback. Returns `false` if there is no such terminal.
+pub fn switch_vt(vt: usize) -> bool {
+    if vt >= NUM_VTS {
+        return false;
+    }
+    let mut active = ACTIVE.lock();
+    if *active != vt {
+        *active = vt;
+        let mut buf = [0; SCROLLBACK_SIZE];
+        let len = SCROLLBACKS[vt].read(&mut buf);
+        let dev = crate::console::driver();
+        dev.write(CLEAR_SCREEN);
+        super::console::write_translated(dev, &buf[..len]);
+    }
+    true
+}
+
+/// Returns the terminal the output of the given source goes to.
+pub fn source_vt(source: ConsoleSource) -> usize {
+    SOURCE_VTS[source_index(source)].load(Ordering::Relaxed)
+}
+
+/// Sends the output of the given source to the given terminal. Returns
+/// `false` if there is no such terminal.
+pub fn set_source_vt(source: ConsoleSource, vt: usize) -> bool {
+    if vt >= NUM_VTS {
+        return false;
+    }
+    for flag in source.iter() {
+        SOURCE_VTS[source_index(flag)].store(vt, Ordering::Relaxed);
+    }
+    true
+}
+
+/// Reads the raw input received by the given terminal, without blocking.
+///
+/// Returns the number of bytes read.
+pub fn read_vt_input(vt: usize, bytes: &mut [u8]) -> usize {
+    match INPUTS.get(vt) {
+        Some(input) => input.lock().pop(bytes),
+        None => 0,
+    }
+}
+
+/// Copies the scrollback of the given terminal into `buf`, see
+/// [`ConsoleBuffer::read`]. Returns the number of bytes copied.
+pub fn read_scrollback(vt: usize, buf: &mut [u8]) -> usize {
+    SCROLLBACKS.get(vt).map_or(0, |sb| sb.read(buf))
+}
+
+/// Writes output from the given source to its terminal, with
+/// `write_device` writing to the console device if the terminal is active.
+///
+/// Returns the number of bytes written, which are those accepted by
+/// `write_device` if it is called.
+pub(crate) fn write(
+    source: ConsoleSource,
+    bytes: &[u8],
+    write_device: impl FnOnce(&[u8]) -> usize,
+) -> usize {
+    let vt = source_vt(source);
+    let active = ACTIVE.lock();
+    let written = if *active == vt {
+        write_device(bytes)
+    } else {
+        bytes.len()
+    };
+    SCROLLBACKS[vt].write(&bytes[..written]);
+    written
+}
+
+/// Passes the received bytes to the active terminal, switching terminals on
+/// the hotkey.
+pub(crate) fn receive(bytes: &[u8]) {
+    for &c in bytes {
+        if !HOTKEY_PENDING.swap(false, Ordering::Relaxed) {
+            if c == HOTKEY {
+                HOTKEY_PENDING.store(true, Ordering::Relaxed);
+            } else {
+                INPUTS[active_vt()].lock().push(c);
+            }
+            continue;
+        }
+        let active = active_vt();
+        match c {
+            HOTKEY => INPUTS[active].lock().push(c),
+            b'1'..=b'9' => {
+                switch_vt((c - b'1') as usize);
+            }
+            b'n' => {
+                switch_vt((active + 1) % NUM_VTS);
+            }
+            b'p' => {
+                switch_vt((active + NUM_VTS - 1) % NUM_VTS);
+            }
+            _ => {}
+        }
+    }
+}
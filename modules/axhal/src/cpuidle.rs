@@ -0,0 +1,220 @@
+//! CPU idle states, with a timer broadcast for the ones stopping the timer.
+//!
+//! The idle task waits for IRQs with [`enter_idle`], in the deepest idle
+//! state of the CPU (on x86_64, the deepest C-state of `MWAIT`), to save
+//! power. But on many CPUs, the local timer stops in the deep idle states
+//! (on x86_64, without ARAT, the "always running APIC timer"), so the CPU
+//! would miss its timer events and its scheduler ticks.
+//!
+//! So a CPU entering such a state hands its deadline to the broadcast CPU,
+//! [`BROADCAST_CPU`], which never enters them: its timer also fires at the
+//! earliest deadline of the CPUs in deep idle states, and it then sends them
+//! the timer IRQ as an IPI, so they handle it as if their own timer fired.
+//! The timers are programmed with [`set_oneshot_timer`], which keeps track of
+//! their deadlines.
+//!
+//! Without the broadcast CPU, e.g., on a single CPU, the CPUs whose timer
+//! stops in the deep idle states only enter the shallow one.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cpu::this_cpu_id;
+
+/// The CPU keeping the deadlines of the CPUs in deep idle states, which
+/// never enters them itself.
+pub const BROADCAST_CPU: usize = 0;
+
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// The deadline the timer of each CPU is set to.
+static TIMER_DEADLINES: [AtomicU64; axconfig::SMP] =
+    [const { AtomicU64::new(NO_DEADLINE) }; axconfig::SMP];
+/// The deadline of each CPU in a deep idle state, or [`NO_DEADLINE`].
+static BROADCAST_DEADLINES: [AtomicU64; axconfig::SMP] =
+    [const { AtomicU64::new(NO_DEADLINE) }; axconfig::SMP];
+/// The deadline the timer of [`BROADCAST_CPU`] is programmed at.
+static BROADCAST_PROGRAMMED: AtomicU64 = AtomicU64::new(NO_DEADLINE);
+
+/// Sets the timer of the current CPU to fire at `deadline_ns` (in monotonic
+/// nanoseconds), and keeps track of it.
+///
+/// On [`BROADCAST_CPU`], it fires at the earliest deadline of the CPUs in
+/// deep idle states if it is earlier.
+pub fn set_oneshot_timer(deadline_ns: u64) {
+    let cpu = this_cpu_id();
+    TIMER_DEADLINES[cpu].store(deadline_ns, Ordering::Relaxed);
+    if cpu == BROADCAST_CPU {
+        program_broadcast_timer();
+    } else {
+        crate::platform::time::set_oneshot_timer(deadline_ns);
+    }
+}
+
+/// Programs the timer of [`BROADCAST_CPU`], on it, with the IRQs disabled.
+fn program_broadcast_timer() {
+    let deadline = BROADCAST_DEADLINES
+        .iter()
+        .chain(core::iter::once(&TIMER_DEADLINES[BROADCAST_CPU]))
+        .map(|deadline| deadline.load(Ordering::Acquire))
+        .min()
+        .unwrap_or(NO_DEADLINE);
+    BROADCAST_PROGRAMMED.store(deadline, Ordering::Release);
+    if deadline != NO_DEADLINE {
+        crate::platform::time::set_oneshot_timer(deadline);
+    }
+}
+
+/// Sends the timer IRQ to the CPUs in deep idle states whose deadline has
+/// passed. It is called on the timer IRQs, before the handler.
+pub(crate) fn handle_timer_irq() {
+    if this_cpu_id() != BROADCAST_CPU {
+        return;
+    }
+    let now = crate::time::monotonic_time_nanos();
+    for (cpu, deadline) in BROADCAST_DEADLINES.iter().enumerate() {
+        if deadline.load(Ordering::Acquire) <= now
+            && deadline.swap(NO_DEADLINE, Ordering::AcqRel) != NO_DEADLINE
+        {
+            arch::send_timer_ipi(cpu);
+        }
+    }
+    program_broadcast_timer();
+}
+
+/// Waits for IRQs in the deepest idle state of the CPU. It is called by the
+/// idle task, with the IRQs enabled.
+///
+/// Like [`crate::asm::wait_for_irqs`], it returns after an IRQ is handled.
+pub fn enter_idle() {
+    if !arch::has_deep_idle() {
+        crate::asm::wait_for_irqs();
+        return;
+    }
+    let cpu = this_cpu_id();
+    if !arch::timer_stops_in_deep_idle() {
+        crate::asm::disable_irqs();
+        arch::enter_deep_idle();
+        crate::asm::enable_irqs();
+        return;
+    }
+    if cpu == BROADCAST_CPU || axconfig::SMP == 1 {
+        crate::asm::wait_for_irqs();
+        return;
+    }
+
+    // The IRQs are disabled until the deadline is taken back, and the deep
+    // idle state is left on IRQs even so.
+    crate::asm::disable_irqs();
+    let deadline = TIMER_DEADLINES[cpu].load(Ordering::Relaxed);
+    BROADCAST_DEADLINES[cpu].store(deadline, Ordering::Release);
+    if deadline < BROADCAST_PROGRAMMED.load(Ordering::Acquire) {
+        // Its timer handler programs its timer again.
+        arch::send_timer_ipi(BROADCAST_CPU);
+    }
+    arch::enter_deep_idle();
+    BROADCAST_DEADLINES[cpu].store(NO_DEADLINE, Ordering::Release);
+    // The timer stopped, so it is set again, firing right away if the
+    // deadline passed.
+    if deadline != NO_DEADLINE {
+        crate::platform::time::set_oneshot_timer(deadline);
+    }
+    crate::asm::enable_irqs();
+}
+
+#[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))]
+mod arch {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use raw_cpuid::CpuId;
+
+    /// The hint of `MWAIT` is not probed yet.
+    const HINT_UNKNOWN: u32 = u32::MAX;
+    /// There is no deep C-state.
+    const HINT_NONE: u32 = u32::MAX - 1;
+
+    /// The hint of `MWAIT` for the deepest C-state.
+    static MWAIT_HINT: AtomicU32 = AtomicU32::new(HINT_UNKNOWN);
+    /// The address monitored by `MONITOR`, which is never written.
+    static MONITORED: AtomicU32 = AtomicU32::new(0);
+
+    /// Finds the deepest C-state beyond C1 which `MWAIT` can enter, and be
+    /// woken up from by the IRQs even if they are disabled.
+    fn probe_mwait_hint() -> Option<u32> {
+        let cpuid = CpuId::new();
+        if !cpuid.get_feature_info()?.has_monitor_mwait() {
+            return None;
+        }
+        let info = cpuid.get_monitor_mwait_info()?;
+        if !info.extensions_supported() || !info.interrupts_as_break_event() {
+            return None;
+        }
+        let substates = [
+            info.supported_c2_states(),
+            info.supported_c3_states(),
+            info.supported_c4_states(),
+            info.supported_c5_states(),
+            info.supported_c6_states(),
+            info.supported_c7_states(),
+        ];
+        // The C-state minus one in bits 4 to 7, and the sub-state (0) in
+        // bits 0 to 3.
+        let cstate = substates.iter().rposition(|&n| n > 0)? as u32 + 2;
+        Some((cstate - 1) << 4)
+    }
+
+    fn mwait_hint() -> Option<u32> {
+        let mut hint = MWAIT_HINT.load(Ordering::Relaxed);
+        if hint == HINT_UNKNOWN {
+            hint = probe_mwait_hint().unwrap_or(HINT_NONE);
+            MWAIT_HINT.store(hint, Ordering::Relaxed);
+        }
+        (hint != HINT_NONE).then_some(hint)
+    }
+
+    pub fn has_deep_idle() -> bool {
+        mwait_hint().is_some()
+    }
+
+    pub fn timer_stops_in_deep_idle() -> bool {
+        !CpuId::new()
+            .get_thermal_power_info()
+            .is_some_and(|info| info.has_arat())
+    }
+
+    /// Enters the deepest C-state, with the IRQs disabled, until an IRQ.
+    pub fn enter_deep_idle() {
+        let Some(hint) = mwait_hint() else {
+            return;
+        };
+        unsafe {
+            core::arch::asm!(
+                "monitor",
+                in("rax") MONITORED.as_ptr(),
+                in("ecx") 0,
+                in("edx") 0,
+                options(nostack),
+            );
+            // Woken up by the IRQs even if they are disabled (ECX bit 0).
+            core::arch::asm!("mwait", in("eax") hint, in("ecx") 1, options(nostack));
+        }
+    }
+
+    pub fn send_timer_ipi(cpu: usize) {
+        crate::platform::irq::send_ipi(cpu, crate::time::TIMER_IRQ_NUM);
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", platform_family = "x86-pc")))]
+mod arch {
+    pub fn has_deep_idle() -> bool {
+        false
+    }
+
+    pub fn timer_stops_in_deep_idle() -> bool {
+        false
+    }
+
+    pub fn enter_deep_idle() {}
+
+    pub fn send_timer_ipi(_cpu: usize) {}
+}
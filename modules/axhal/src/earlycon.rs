@@ -0,0 +1,66 @@
+//! Early console, which works from the very first instructions of the
+//! kernel.
+//!
+//! The console device of the platform may need locks, per-CPU data, or
+//! initialization at boot before it works. The early console instead polls
+//! the boot UART at its physical address, which is identity-mapped (or
+//! statically mapped) until the kernel page table is set up, without locks
+//! and without anything in `.bss` to be initialized.
+//!
+//! It is enabled at boot, and all console output is written to it, until it
+//! is disabled with [`disable`] once the console device and the logger are
+//! up. Panics that happen before that are then not silent.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::platform::console::early_putchar;
+
+/// Whether the early console is disabled. It is in `.bss`, so it is still
+/// `false` after the `.bss` is cleared at boot.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether the early console is enabled, i.e., [`disable`] is not
+/// called yet.
+pub fn is_enabled() -> bool {
+    !DISABLED.load(Ordering::Acquire)
+}
+
+/// Disables the early console, switching the console output to the console
+/// device of the platform.
+///
+/// It must be called before the identity mapping of the boot page table is
+/// removed, i.e., before the kernel page table is set up.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Release);
+}
+
+/// Writes bytes to the early console, with line feeds written as `\r\n`.
+///
+/// Nothing is written if the early console is disabled.
+pub fn write_bytes(bytes: &[u8]) {
+    if !is_enabled() {
+        return;
+    }
+    for &c in bytes {
+        if c == b'\n' {
+            early_putchar(b'\r');
+        }
+        early_putchar(c);
+    }
+}
+
+struct EarlyConsole;
+
+impl Write for EarlyConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Writes formatted text to the early console, e.g., a panic message when
+/// the logger is not initialized yet.
+pub fn write_fmt(args: fmt::Arguments) {
+    let _ = EarlyConsole.write_fmt(args);
+}
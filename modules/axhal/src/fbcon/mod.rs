@@ -0,0 +1,257 @@
+//! Text console drawn into a linear framebuffer.
+//!
+//! Once initialized by [`init`], it receives a copy of the console output
+//! from the selected sources (see [`console::add_sink`]), so the output is
+//! also visible on the screen, e.g., the graphical window of QEMU.
+//!
+//! The framebuffer must have 32 bits per pixel, in the XRGB8888 format.
+//! Characters are drawn in cells of 8x16 pixels, and the screen scrolls up
+//! when the last line is full. The colors set by the SGR escape sequences
+//! (`ESC [ ... m`) are supported, other escape sequences are ignored.
+//!
+//! Some devices only show the framebuffer after it is flushed, which is done
+//! by [`flush`].
+
+mod font;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::console::{self, ConsoleDriver, ConsoleSource};
+
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 16;
+const TAB_WIDTH: usize = 8;
+
+const BG_COLOR: u32 = 0x00_00_00;
+const FG_COLOR: u32 = 0xaa_aa_aa;
+
+/// The colors of SGR 30-37, and of SGR 90-97 (bright).
+const PALETTE: [[u32; 8]; 2] = [
+    [
+        0x00_00_00, 0xaa_00_00, 0x00_aa_00, 0xaa_55_00, 0x00_00_aa, 0xaa_00_aa, 0x00_aa_aa,
+        0xaa_aa_aa,
+    ],
+    [
+        0x55_55_55, 0xff_55_55, 0x55_ff_55, 0xff_ff_55, 0x55_55_ff, 0xff_55_ff, 0x55_ff_ff,
+        0xff_ff_ff,
+    ],
+];
+
+/// A linear framebuffer with 32 bits per pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    /// The virtual address of the framebuffer.
+    pub base_vaddr: usize,
+    /// The width in pixels.
+    pub width: usize,
+    /// The height in pixels.
+    pub height: usize,
+    /// The number of pixels from the start of a line to that of the next.
+    pub stride: usize,
+}
+
+/// The state of parsing escape sequences.
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    /// After `ESC`.
+    Start,
+    /// In a control sequence, with the parameter being parsed.
+    Csi(u32),
+}
+
+struct FbConsole {
+    fb: Framebuffer,
+    flush_fn: fn(),
+    cols: usize,
+    rows: usize,
+    col: usize,
+    row: usize,
+    fg_color: u32,
+    escape: Escape,
+}
+
+impl FbConsole {
+    fn new(fb: Framebuffer, flush_fn: fn()) -> Self {
+        Self {
+            fb,
+            flush_fn,
+            cols: fb.width / CELL_WIDTH,
+            rows: fb.height / CELL_HEIGHT,
+            col: 0,
+            row: 0,
+            fg_color: FG_COLOR,
+            escape: Escape::None,
+        }
+    }
+
+    fn pixels(&mut self) -> &mut [u32] {
+        // SAFETY: the framebuffer is valid as `init` requires, and only
+        // accessed with `FBCON` locked.
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.fb.base_vaddr as *mut u32,
+                self.fb.stride * self.fb.height,
+            )
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pixels().fill(BG_COLOR);
+        self.col = 0;
+        self.row = 0;
+    }
+
+    fn draw_char(&mut self, c: u8) {
+        let idx = c.wrapping_sub(font::FIRST_CHAR) as usize;
+        let unknown = &font::GLYPHS[(b'?' - font::FIRST_CHAR) as usize];
+        let glyph = font::GLYPHS.get(idx).unwrap_or(unknown);
+        let (stride, fg_color) = (self.fb.stride, self.fg_color);
+        let start = self.row * CELL_HEIGHT * stride + self.col * CELL_WIDTH;
+        let pixels = &mut self.pixels()[start..];
+        for y in 0..CELL_HEIGHT {
+            // Each row of the 8x8 glyph is drawn twice.
+            let bits = glyph[y / 2];
+            let line = &mut pixels[y * stride..y * stride + CELL_WIDTH];
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = if bits & (1 << x) != 0 {
+                    fg_color
+                } else {
+                    BG_COLOR
+                };
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        let line_size = self.fb.stride * CELL_HEIGHT;
+        let end = self.rows * line_size;
+        let pixels = self.pixels();
+        pixels.copy_within(line_size..end, 0);
+        pixels[end - line_size..end].fill(BG_COLOR);
+    }
+
+    fn put_char(&mut self, c: u8) {
+        // A full line is wrapped when the next character comes, so a line
+        // feed right after it does not leave an empty line.
+        if self.col == self.cols {
+            self.new_line();
+        }
+        self.draw_char(c);
+        self.col += 1;
+    }
+
+    fn set_color(&mut self, param: u32) {
+        self.fg_color = match param {
+            0 | 39 => FG_COLOR,
+            30..=37 => PALETTE[0][param as usize - 30],
+            90..=97 => PALETTE[1][param as usize - 90],
+            _ => return,
+        };
+    }
+
+    fn write_byte(&mut self, c: u8) {
+        match (self.escape, c) {
+            (Escape::None, 0x1b) => self.escape = Escape::Start,
+            (Escape::None, b'\n') => self.new_line(),
+            (Escape::None, b'\r') => self.col = 0,
+            (Escape::None, 0x08) => self.col = self.col.saturating_sub(1),
+            (Escape::None, b'\t') => {
+                let next = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.col < next.min(self.cols) {
+                    self.put_char(b' ');
+                }
+            }
+            (Escape::None, c) if c < b' ' => {}
+            (Escape::None, c) => self.put_char(c),
+            (Escape::Start, b'[') => self.escape = Escape::Csi(0),
+            (Escape::Start, _) => self.escape = Escape::None,
+            (Escape::Csi(param), b'0'..=b'9') => {
+                let param = param.saturating_mul(10).saturating_add((c - b'0') as u32);
+                self.escape = Escape::Csi(param);
+            }
+            (Escape::Csi(param), b';') => {
+                self.set_color(param);
+                self.escape = Escape::Csi(0);
+            }
+            (Escape::Csi(param), b'm') => {
+                self.set_color(param);
+                self.escape = Escape::None;
+            }
+            (Escape::Csi(_), 0x40..=0x7e) => self.escape = Escape::None,
+            (Escape::Csi(_), _) => {}
+        }
+    }
+}
+
+static FBCON: SpinNoIrq<Option<FbConsole>> = SpinNoIrq::new(None);
+
+/// Whether anything is drawn since the last [`flush`].
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// The framebuffer console as a console device.
+struct FbConsoleDriver;
+
+impl ConsoleDriver for FbConsoleDriver {
+    fn write(&self, bytes: &[u8]) {
+        if let Some(con) = FBCON.lock().as_mut() {
+            for &c in bytes {
+                con.write_byte(c);
+            }
+            DIRTY.store(true, Ordering::Release);
+        }
+    }
+
+    fn try_read(&self, _bytes: &mut [u8]) -> usize {
+        0
+    }
+}
+
+/// Initializes the framebuffer console, and starts copying the console
+/// output from `sources` to it.
+///
+/// `flush_fn` is called by [`flush`] to show the framebuffer on the screen.
+///
+/// # Safety
+///
+/// The framebuffer must be mapped at `fb.base_vaddr`, and must not be
+/// written by others afterwards.
+pub unsafe fn init(fb: Framebuffer, flush_fn: fn(), sources: ConsoleSource) {
+    let mut con = FbConsole::new(fb, flush_fn);
+    if con.cols == 0 || con.rows == 0 {
+        warn!("fbcon: framebuffer too small: {}x{}", fb.width, fb.height);
+        return;
+    }
+    con.clear();
+    info!("fbcon: {}x{} characters", con.cols, con.rows);
+    *FBCON.lock() = Some(con);
+    DIRTY.store(true, Ordering::Release);
+    if console::add_sink(&FbConsoleDriver, sources).is_none() {
+        warn!("fbcon: too many console sinks");
+    }
+    flush();
+}
+
+/// Shows the framebuffer on the screen, if anything is drawn since the last
+/// call.
+///
+/// The output is not shown on some devices until it is called, so it should
+/// be called periodically, e.g., from a background task. It must not be
+/// called in interrupt context.
+pub fn flush() {
+    if !DIRTY.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let flush_fn = match FBCON.lock().as_ref() {
+        Some(con) => con.flush_fn,
+        None => return,
+    };
+    flush_fn();
+}
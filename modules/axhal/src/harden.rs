@@ -0,0 +1,231 @@
+//! Run-time support of the hardening options the kernel is built with
+//! (`HARDEN=y`).
+//!
+//! - Stack protectors (`-Z stack-protector=strong`): functions with buffers
+//!   on the stack check on return that a canary put below the return address
+//!   is intact, and call [`__stack_chk_fail`] if it is not, which panics. The
+//!   canary is [`__stack_chk_guard`], seeded at boot from [`random_u64`].
+//!   Without `smp`, each task has its own canary, switched to with
+//!   [`set_stack_canary`] on context switches, so a canary leaked by one task
+//!   does not help to overflow the stack of another.
+//! - Pointer authentication on aarch64 (`-Z branch-protection=pac-ret`):
+//!   return addresses are signed on the stack with a key generated at boot,
+//!   if the CPU has FEAT_PAuth.
+//! - BTI landing pads on aarch64 and ENDBR ones on x86_64 (`-Z
+//!   cf-protection=full`) are put in the code, but not enforced yet: that
+//!   needs the guarded page attribute in the page tables, and landing pads in
+//!   the trap vectors, respectively. Shadow stacks are not supported.
+//!
+//! The stack canary and the keys are only changed from functions that never
+//! return, i.e., the entries of the CPUs, as the functions returning after
+//! would fail their checks otherwise.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The stack canary checked by the code built with stack protectors.
+///
+/// It is not `0` before it is seeded, so a zeroing overflow is still caught.
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static mut __stack_chk_guard: usize = 0x595e_9fbd_94fd_a766_u64 as usize;
+
+/// Called by the code built with stack protectors when the canary of its
+/// stack frame is overwritten.
+#[unsafe(no_mangle)]
+pub extern "C" fn __stack_chk_fail() -> ! {
+    panic!("stack smashing detected");
+}
+
+/// Returns the current stack canary.
+#[inline]
+pub fn stack_canary() -> usize {
+    unsafe { (&raw const __stack_chk_guard).read_volatile() }
+}
+
+/// Sets the stack canary, e.g., to that of the next task on context
+/// switches.
+///
+/// # Safety
+///
+/// The functions in progress that return after must have been entered with
+/// this canary.
+#[inline]
+pub unsafe fn set_stack_canary(canary: usize) {
+    unsafe { (&raw mut __stack_chk_guard).write_volatile(canary) };
+}
+
+/// Returns a new stack canary, e.g., for a new task.
+pub fn new_stack_canary() -> usize {
+    // The lowest byte is zero, so it can not be leaked by reading a string.
+    random_u64() as usize & !0xff
+}
+
+/// Returns a random number, from the random number generator of the CPU if
+/// it has one (RDRAND on x86_64, RNDR on aarch64), and mixed with the time
+/// otherwise.
+///
+/// It is not fit for making keys from if the CPU has no random number
+/// generator, as the time at boot is quite predictable.
+pub fn random_u64() -> u64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+
+    let seed = arch::hw_random().unwrap_or_else(crate::time::current_ticks);
+    // Mix with a counter, so numbers taken at the same tick still differ.
+    let state = STATE.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed);
+    splitmix64(seed ^ state)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Seeds the stack canary and enables pointer authentication on the
+/// primary CPU.
+///
+/// It must be inlined into, and called first by, the entry of the CPU, which
+/// never returns.
+#[inline(always)]
+pub fn init_primary() {
+    unsafe { set_stack_canary(new_stack_canary()) };
+    #[cfg(target_arch = "aarch64")]
+    arch::init_pac_primary();
+}
+
+/// Enables pointer authentication on a secondary CPU, with the key of the
+/// primary CPU, as tasks migrate between CPUs.
+///
+/// It must be inlined into, and called first by, the entry of the CPU, which
+/// never returns.
+#[inline(always)]
+pub fn init_secondary() {
+    #[cfg(target_arch = "aarch64")]
+    arch::init_pac_secondary();
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use core::arch::x86_64::_rdrand64_step;
+
+    use raw_cpuid::CpuId;
+
+    pub fn hw_random() -> Option<u64> {
+        let has_rdrand = CpuId::new()
+            .get_feature_info()
+            .is_some_and(|f| f.has_rdrand());
+        if !has_rdrand {
+            return None;
+        }
+        unsafe { rdrand() }
+    }
+
+    #[target_feature(enable = "rdrand")]
+    unsafe fn rdrand() -> Option<u64> {
+        let mut value = 0;
+        // It may fail transiently if the entropy is drained.
+        for _ in 0..10 {
+            if _rdrand64_step(&mut value) == 1 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use core::arch::asm;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    /// The instruction key A, which signs the return addresses.
+    static APIA_KEY: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+    /// SCTLR_EL1.EnIA: enables the instruction key A.
+    const SCTLR_ENIA: u64 = 1 << 31;
+
+    pub fn hw_random() -> Option<u64> {
+        let isar0: u64;
+        unsafe { asm!("mrs {}, ID_AA64ISAR0_EL1", out(reg) isar0) };
+        // ID_AA64ISAR0_EL1.RNDR[63:60]
+        if isar0 >> 60 == 0 {
+            return None;
+        }
+        for _ in 0..10 {
+            let (value, ok): (u64, u64);
+            // RNDR, which clears NZCV.Z on success.
+            unsafe {
+                asm!(
+                    "mrs {0}, S3_3_C2_C4_0",
+                    "cset {1}, ne",
+                    out(reg) value,
+                    out(reg) ok,
+                    options(nomem, nostack),
+                )
+            };
+            if ok != 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn has_pauth() -> bool {
+        let (isar1, isar2): (u64, u64);
+        unsafe {
+            asm!("mrs {}, ID_AA64ISAR1_EL1", out(reg) isar1);
+            // ID_AA64ISAR2_EL1
+            asm!("mrs {}, S3_0_C0_C6_2", out(reg) isar2);
+        }
+        // ID_AA64ISAR1_EL1.{APA[7:4], API[11:8]}, ID_AA64ISAR2_EL1.APA3[15:12]
+        (isar1 >> 4) & 0xff != 0 || (isar2 >> 12) & 0xf != 0
+    }
+
+    #[inline(always)]
+    pub fn init_pac_primary() {
+        if !has_pauth() {
+            return;
+        }
+        APIA_KEY[0].store(super::random_u64(), Ordering::Relaxed);
+        APIA_KEY[1].store(super::random_u64(), Ordering::Relaxed);
+        enable_pac();
+    }
+
+    #[inline(always)]
+    pub fn init_pac_secondary() {
+        if has_pauth() {
+            enable_pac();
+        }
+    }
+
+    /// Sets the key and enables it. Returning addresses signed before would
+    /// fail, so it is inlined into the entry of the CPU.
+    #[inline(always)]
+    fn enable_pac() {
+        let lo = APIA_KEY[0].load(Ordering::Relaxed);
+        let hi = APIA_KEY[1].load(Ordering::Relaxed);
+        unsafe {
+            asm!(
+                // APIAKeyLo_EL1, APIAKeyHi_EL1
+                "msr S3_0_C2_C1_0, {lo}",
+                "msr S3_0_C2_C1_1, {hi}",
+                "mrs {tmp}, SCTLR_EL1",
+                "orr {tmp}, {tmp}, {enia}",
+                "msr SCTLR_EL1, {tmp}",
+                "isb",
+                lo = in(reg) lo,
+                hi = in(reg) hi,
+                enia = in(reg) SCTLR_ENIA,
+                tmp = out(reg) _,
+            )
+        };
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    pub fn hw_random() -> Option<u64> {
+        None
+    }
+}
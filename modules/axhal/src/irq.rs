@@ -1,23 +1,321 @@
 //! Interrupt management.
+//!
+//! IRQ handlers are closures given the IRQ number, which can capture the
+//! state of their device, e.g., `&|irq| DEVICE.handle_irq(irq)`.
+//!
+//! The IRQ handlers run in RCU read-side critical sections (see
+//! [`rcu`](crate::rcu)), so they can be replaced while the system is live,
+//! e.g., when a driver is reloaded, see [`replace_handler`], or removed, see
+//! [`unregister_handler`].
+//!
+//! The run time of the handlers can be limited, to find the ones delaying
+//! the others, e.g., making a console drop characters, see [`set_budget`].
+//!
+//! An IRQ line can be shared by several devices, e.g., legacy PCI INTx, each
+//! with its handler telling whether its device raised the IRQ, see
+//! [`register_shared_handler`].
+//!
+//! The handlers doing heavy work can run it in a task instead, with the
+//! threaded IRQs of `axtask` (`request_threaded_irq`), or after the IRQ
+//! handlers, see [`set_exit_hook`].
+//!
+//! PCI devices can signal interrupts with messages (MSI or MSI-X) rather
+//! than pins, see [`request_msi`].
+//!
+//! On SMP, IRQs are routed to the primary CPU until they are spread over the
+//! CPUs, see [`spread_irqs`], or routed to chosen ones, see
+//! [`set_affinity`].
+//!
+//! The lines are programmed as edge- or level-triggered, active high or low,
+//! as the device tree or the ACPI tables describe them, see [`set_trigger`].
+//!
+//! Where the controller supports it (the GIC, the PLIC), the IRQs have
+//! priorities, see [`set_priority`], and each CPU a threshold below which
+//! they are masked, see [`set_priority_threshold`].
+//!
+//! With the `nested-irq` feature, the handlers of an IRQ can be interrupted
+//! by the IRQs with a higher priority, e.g., the timer IRQ, so a slow handler
+//! does not delay them. The priority mask of the CPU (the task priority of
+//! the LAPIC, the priority mask of the GIC CPU interface, or the threshold of
+//! the PLIC) is raised to the priority of the IRQ, and the IRQs are enabled
+//! while its handlers run, at most `MAX_NESTING_DEPTH` levels deep, as the
+//! nested handlers run on the stack of the interrupted task. The locks the
+//! handlers share with other handlers must then be taken with the IRQs
+//! disabled (`SpinNoIrq`).
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
 
 use axcpu::trap::{IRQ, register_trap_handler};
-use handler_table::HandlerTable;
 
-use crate::platform::irq::{MAX_IRQ_COUNT, dispatch_irq};
+use crate::platform::irq::{MAX_IRQ_COUNT, dispatch_irq, msi_irqs, setup_msi};
+use crate::rcu::{self, RcuCell, RcuReadGuard, RcuSlot};
+use crate::time::{TIMER_IRQ_NUM, monotonic_time_nanos};
 
 pub use crate::platform::irq::{register_handler, set_enable};
 
-/// The type if an IRQ handler.
-pub type IrqHandler = handler_table::Handler;
+/// The type if an IRQ handler, called with the IRQ number.
+pub type IrqHandler = &'static (dyn Fn(usize) + Send + Sync);
+
+/// The type of a handler of a shared IRQ, called with the IRQ number, which
+/// returns whether its device raised the IRQ, i.e., whether it handled it.
+pub type SharedIrqHandler = &'static (dyn Fn(usize) -> bool + Send + Sync);
+
+/// The maximum number of handlers of a shared IRQ.
+pub const MAX_SHARED_HANDLERS: usize = 4;
+
+/// The number of consecutive times an IRQ is not handled by any handler
+/// before it is disabled, as it would otherwise keep firing if it is
+/// level-triggered.
+const MAX_UNHANDLED: u32 = 100_000;
+
+static IRQ_HANDLER_TABLE: [RcuSlot<Option<IrqHandler>>; MAX_IRQ_COUNT] =
+    [const { RcuSlot::new(None) }; MAX_IRQ_COUNT];
+/// The handlers of the shared IRQs, which have no handler in
+/// [`IRQ_HANDLER_TABLE`].
+static SHARED_HANDLERS: [[RcuSlot<Option<SharedIrqHandler>>; MAX_SHARED_HANDLERS]; MAX_IRQ_COUNT] =
+    [const { [const { RcuSlot::new(None) }; MAX_SHARED_HANDLERS] }; MAX_IRQ_COUNT];
+/// The number of consecutive times each shared IRQ was not handled.
+static UNHANDLED: [AtomicU32; MAX_IRQ_COUNT] = [const { AtomicU32::new(0) }; MAX_IRQ_COUNT];
+
+/// The maximum run time of the IRQ handlers, in nanoseconds, or 0 if it is
+/// not enforced, see [`set_budget`].
+static BUDGET_NS: AtomicU64 = AtomicU64::new(0);
+/// Whether the IRQs whose handlers overrun the budget are deferred.
+static DEFER_OVERRUNS: AtomicBool = AtomicBool::new(false);
+/// Whether each IRQ is handled in task context.
+static DEFERRED: [AtomicBool; MAX_IRQ_COUNT] = [const { AtomicBool::new(false) }; MAX_IRQ_COUNT];
+/// Whether each deferred IRQ fired and was not handled yet.
+static PENDING: [AtomicBool; MAX_IRQ_COUNT] = [const { AtomicBool::new(false) }; MAX_IRQ_COUNT];
+static DEFER_NOTIFIER: RcuCell<Option<fn()>> = RcuCell::new(None);
+/// Called after the handlers of each IRQ, see [`set_exit_hook`].
+static EXIT_HOOK: RcuCell<Option<fn()>> = RcuCell::new(None);
+
+/// Whether the affinity of each IRQ is set with [`set_affinity`], so it is
+/// not changed by [`spread_irqs`].
+static AFFINITY_SET: [AtomicBool; MAX_IRQ_COUNT] =
+    [const { AtomicBool::new(false) }; MAX_IRQ_COUNT];
+/// The number of CPUs the IRQs are spread over, see [`spread_irqs`].
+static SPREAD_CPUS: AtomicUsize = AtomicUsize::new(0);
+/// The CPU the next IRQ spread is routed to.
+static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximum number of IRQ handlers a CPU runs with the IRQs enabled, one
+/// interrupting the other, with the `nested-irq` feature.
+#[cfg(feature = "nested-irq")]
+pub const MAX_NESTING_DEPTH: usize = 2;
+
+/// The number of IRQ handlers the current CPU runs with the IRQs enabled.
+#[cfg(feature = "nested-irq")]
+#[percpu::def_percpu]
+static NESTING_DEPTH: usize = 0;
 
-static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
+/// The number of times each IRQ fired since boot.
+static IRQ_COUNTS: [AtomicU64; MAX_IRQ_COUNT] = [const { AtomicU64::new(0) }; MAX_IRQ_COUNT];
 
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
     trace!("IRQ {}", irq_num);
-    if !IRQ_HANDLER_TABLE.handle(irq_num) {
+    if let Some(count) = IRQ_COUNTS.get(irq_num) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+    // The CPUs in deep idle states are woken up at their deadlines.
+    #[cfg(feature = "cpuidle")]
+    if irq_num == TIMER_IRQ_NUM {
+        crate::cpuidle::handle_timer_irq();
+    }
+    let guard = rcu::read_lock();
+    if !has_handlers(irq_num, &guard) {
         warn!("Unhandled IRQ {}", irq_num);
+        return;
+    }
+    if DEFERRED[irq_num].load(Ordering::Acquire) {
+        if let Some(notifier) = DEFER_NOTIFIER.read(&guard) {
+            // Disabled until it is handled, as it may be level-triggered.
+            set_enable(irq_num, false);
+            PENDING[irq_num].store(true, Ordering::Release);
+            notifier();
+            return;
+        }
+    }
+    #[cfg(feature = "nested-irq")]
+    let _nesting = Nesting::enter(irq_num);
+    run_handlers(irq_num, &guard);
+}
+
+/// The handlers of an IRQ running with the IRQs enabled, and the IRQs with
+/// a lower or the same priority masked.
+#[cfg(feature = "nested-irq")]
+struct Nesting {
+    /// The priority mask of the CPU before, restored when it is dropped.
+    saved_mask: usize,
+}
+
+#[cfg(feature = "nested-irq")]
+impl Nesting {
+    /// Lets the IRQs with a higher priority interrupt the handlers of
+    /// `irq_num`, unless it is the timer IRQ, which has the highest, the
+    /// nesting is already [`MAX_NESTING_DEPTH`] deep, or the interrupt
+    /// controller has no priority mask.
+    fn enter(irq_num: usize) -> Option<Self> {
+        let depth = NESTING_DEPTH.read_current();
+        if irq_num == TIMER_IRQ_NUM || depth >= MAX_NESTING_DEPTH {
+            return None;
+        }
+        let saved_mask = crate::platform::irq::raise_priority_mask(irq_num)?;
+        NESTING_DEPTH.write_current(depth + 1);
+        crate::asm::enable_irqs();
+        Some(Self { saved_mask })
+    }
+}
+
+#[cfg(feature = "nested-irq")]
+impl Drop for Nesting {
+    fn drop(&mut self) {
+        crate::asm::disable_irqs();
+        NESTING_DEPTH.write_current(NESTING_DEPTH.read_current() - 1);
+        crate::platform::irq::restore_priority_mask(self.saved_mask);
+    }
+}
+
+/// Whether an IRQ has a handler, or shared handlers.
+fn has_handlers(irq_num: usize, guard: &RcuReadGuard) -> bool {
+    irq_num < MAX_IRQ_COUNT
+        && (IRQ_HANDLER_TABLE[irq_num].read(guard).is_some()
+            || SHARED_HANDLERS[irq_num]
+                .iter()
+                .any(|slot| slot.read(guard).is_some()))
+}
+
+/// Runs the handler of an IRQ, or all its shared handlers, and reports it if
+/// none of them handled it.
+fn run_handlers(irq_num: usize, guard: &RcuReadGuard) {
+    if let Some(handler) = IRQ_HANDLER_TABLE[irq_num].read(guard) {
+        run_timed(irq_num, 0, || handler(irq_num));
+        return;
+    }
+    let mut handled = false;
+    for (index, slot) in SHARED_HANDLERS[irq_num].iter().enumerate() {
+        if let Some(handler) = slot.read(guard) {
+            handled |= run_timed(irq_num, index, || handler(irq_num));
+        }
+    }
+    if handled {
+        UNHANDLED[irq_num].store(0, Ordering::Relaxed);
+    } else {
+        report_unhandled(irq_num);
+    }
+}
+
+/// Runs a handler, the `index`-th of the IRQ, reporting it if it overruns the
+/// budget.
+fn run_timed<R>(irq_num: usize, index: usize, handler: impl FnOnce() -> R) -> R {
+    let budget = BUDGET_NS.load(Ordering::Relaxed);
+    if budget == 0 {
+        return handler();
+    }
+    let start = monotonic_time_nanos();
+    let ret = handler();
+    let elapsed = monotonic_time_nanos().saturating_sub(start);
+    if elapsed > budget {
+        report_overrun(irq_num, index, elapsed, budget);
+    }
+    ret
+}
+
+/// Reports a shared IRQ none of whose handlers handled it, and disables it
+/// after [`MAX_UNHANDLED`] times in a row.
+fn report_unhandled(irq_num: usize) {
+    let count = UNHANDLED[irq_num].fetch_add(1, Ordering::Relaxed) + 1;
+    if count.is_power_of_two() {
+        warn!(
+            "IRQ {}: not handled by any of its handlers ({} times in a row)",
+            irq_num, count
+        );
+    }
+    if count == MAX_UNHANDLED {
+        error!("IRQ {}: nobody cared, disabling it", irq_num);
+        set_enable(irq_num, false);
+    }
+}
+
+fn report_overrun(irq_num: usize, index: usize, elapsed: u64, budget: u64) {
+    warn!(
+        "IRQ {}: handler {} ran for {}us, over the budget of {}us",
+        irq_num,
+        index,
+        elapsed / 1000,
+        budget / 1000
+    );
+    // The handler returned, but the frames from here into the interrupted
+    // code are still on the stack.
+    let (fp, sp) = crate::backtrace::current_frame();
+    for (depth, addr) in crate::backtrace::walk(fp, sp).enumerate() {
+        warn!("  #{} {:#x}", depth, addr);
+    }
+    if DEFER_OVERRUNS.load(Ordering::Relaxed)
+        && irq_num != TIMER_IRQ_NUM
+        && !DEFERRED[irq_num].swap(true, Ordering::AcqRel)
+    {
+        warn!("IRQ {}: deferred to task context", irq_num);
+    }
+}
+
+/// Sets the maximum time an IRQ handler may run, or `None` not to enforce
+/// one, which is the default.
+///
+/// A handler running longer is reported with a warning, giving its run time,
+/// its index among the handlers of a shared IRQ, and the backtrace from the
+/// IRQ handling into the interrupted code, if the kernel is built with the
+/// frame pointers (`FRAME_POINTERS=y`). If `defer`, its IRQ is then
+/// deferred, once a notifier is set with [`set_defer_notifier`]: it is
+/// disabled when it fires, and handled in task context by
+/// [`handle_deferred`]. The timer IRQ is never deferred.
+pub fn set_budget(budget: Option<Duration>, defer: bool) {
+    let budget_ns = budget.map_or(0, |budget| (budget.as_nanos() as u64).max(1));
+    BUDGET_NS.store(budget_ns, Ordering::Relaxed);
+    DEFER_OVERRUNS.store(defer, Ordering::Relaxed);
+}
+
+/// Sets the function called in interrupt context when a deferred IRQ fires,
+/// e.g., to wake up a task calling [`handle_deferred`].
+pub fn set_defer_notifier(notifier: fn()) {
+    DEFER_NOTIFIER.replace(Some(notifier));
+}
+
+/// Sets the function called after the handlers of each IRQ, e.g., to run
+/// the work they deferred (the softirqs of `axruntime`).
+///
+/// It is called with the IRQs still disabled, and before the current task is
+/// preempted, if it must be.
+pub fn set_exit_hook(hook: fn()) {
+    EXIT_HOOK.replace(Some(hook));
+}
+
+/// Whether a deferred IRQ fired and is not handled yet, see
+/// [`handle_deferred`].
+pub fn deferred_pending() -> bool {
+    PENDING
+        .iter()
+        .any(|pending| pending.load(Ordering::Acquire))
+}
+
+/// Handles the deferred IRQs which fired, and enables them again.
+///
+/// It is called in task context, where the other interrupts are enabled,
+/// after the notifier set with [`set_defer_notifier`] is called.
+pub fn handle_deferred() {
+    for (irq_num, pending) in PENDING.iter().enumerate() {
+        if !pending.swap(false, Ordering::AcqRel) {
+            continue;
+        }
+        let guard = rcu::read_lock();
+        // Not enabled again if it is unregistered meanwhile.
+        if has_handlers(irq_num, &guard) {
+            run_handlers(irq_num, &guard);
+            set_enable(irq_num, true);
+        }
     }
 }
 
@@ -28,9 +326,16 @@ pub(crate) fn dispatch_irq_common(irq_num: usize) {
 #[allow(dead_code)]
 pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bool {
     // axlog::ax_println!("--------------------external irq register here----------------------------");
-    if irq_num < MAX_IRQ_COUNT && IRQ_HANDLER_TABLE.register_handler(irq_num, handler) {
+    // Not for shared IRQs, see `register_shared_handler`.
+    if irq_num < MAX_IRQ_COUNT
+        && !has_handlers(irq_num, &rcu::read_lock())
+        && IRQ_HANDLER_TABLE[irq_num]
+            .update(|old| old.is_none().then_some(Some(handler)))
+            .is_some()
+    {
         // 看看都注册了哪些handler
         axlog::ax_println!("irq number: {irq_num}");
+        route_default(irq_num);
         set_enable(irq_num, true);
         return true;
     }
@@ -38,10 +343,306 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
     false
 }
 
+/// Replaces the handler of a registered IRQ, and returns the old one, or
+/// `None` if no handler is registered, in which case nothing is done.
+///
+/// When it returns, the old handler is no longer running on any CPU, so
+/// what it uses can be released. It must not be called by an IRQ handler.
+pub fn replace_handler(irq_num: usize, handler: IrqHandler) -> Option<IrqHandler> {
+    let entry = IRQ_HANDLER_TABLE.get(irq_num)?;
+    entry.update(|old| old.map(|_| Some(handler))).flatten()
+}
+
+/// Disables an IRQ and removes its handler, and returns it, or `None` if no
+/// handler is registered.
+///
+/// When it returns, the handler is no longer running on any CPU, and the IRQ
+/// is no longer deferred, see [`set_budget`]. It must not be called by an IRQ
+/// handler.
+pub fn unregister_handler(irq_num: usize) -> Option<IrqHandler> {
+    let entry = IRQ_HANDLER_TABLE.get(irq_num)?;
+    entry.read(&rcu::read_lock())?;
+    set_enable(irq_num, false);
+    let old = entry.update(|old| old.map(|_| None)).flatten();
+    // `handle_deferred` may have enabled it again before the grace period
+    // ended, but finds no handler from now on.
+    set_enable(irq_num, false);
+    DEFERRED[irq_num].store(false, Ordering::Release);
+    PENDING[irq_num].store(false, Ordering::Release);
+    old
+}
+
+/// Adds a handler to an IRQ shared by several devices, and enables it, or
+/// returns `false` if the IRQ has a handler registered with
+/// [`register_handler`] or already [`MAX_SHARED_HANDLERS`] shared handlers.
+///
+/// All the shared handlers are run when the IRQ fires, each telling whether
+/// its device raised it. If none of them did [`MAX_UNHANDLED`] times in a
+/// row, the IRQ is disabled.
+pub fn register_shared_handler(irq_num: usize, handler: SharedIrqHandler) -> bool {
+    if irq_num < MAX_IRQ_COUNT && IRQ_HANDLER_TABLE[irq_num].read(&rcu::read_lock()).is_none() {
+        for slot in &SHARED_HANDLERS[irq_num] {
+            if slot
+                .update(|old| old.is_none().then_some(Some(handler)))
+                .is_some()
+            {
+                UNHANDLED[irq_num].store(0, Ordering::Relaxed);
+                route_default(irq_num);
+                set_enable(irq_num, true);
+                return true;
+            }
+        }
+    }
+    warn!("register shared handler for IRQ {} failed", irq_num);
+    false
+}
+
+/// Removes a handler added with [`register_shared_handler`], given the same
+/// reference, and disables the IRQ if it was the last one. Returns `false`
+/// if it is not found.
+///
+/// When it returns, the handler is no longer running on any CPU. It must not
+/// be called by an IRQ handler.
+pub fn unregister_shared_handler(irq_num: usize, handler: SharedIrqHandler) -> bool {
+    let Some(slots) = SHARED_HANDLERS.get(irq_num) else {
+        return false;
+    };
+    // The vtables are compared too, as closures without captures all have
+    // the same address.
+    let is_handler =
+        |old: Option<SharedIrqHandler>| old.is_some_and(|old| core::ptr::eq(old, handler));
+    if !slots
+        .iter()
+        .any(|slot| slot.update(|old| is_handler(old).then_some(None)).is_some())
+    {
+        return false;
+    }
+    if !has_handlers(irq_num, &rcu::read_lock()) {
+        set_enable(irq_num, false);
+        DEFERRED[irq_num].store(false, Ordering::Release);
+        PENDING[irq_num].store(false, Ordering::Release);
+    }
+    true
+}
+
+/// Routes an IRQ to the CPUs in `cpu_mask`, bit `n` being CPU `n`, or to
+/// the lowest of them where an IRQ is routed to a single CPU (the IO APIC,
+/// the APLIC). Returns `false` if `cpu_mask` has no CPU, or the IRQ can not
+/// be routed, e.g., the per-CPU timer IRQ, or the MSIs on x86_64.
+///
+/// The IRQ is then no longer moved by [`spread_irqs`].
+pub fn set_affinity(irq_num: usize, cpu_mask: usize) -> bool {
+    let all_cpus = usize::MAX >> (usize::BITS as usize - axconfig::SMP.min(usize::BITS as usize));
+    let cpu_mask = cpu_mask & all_cpus;
+    if irq_num >= MAX_IRQ_COUNT
+        || cpu_mask == 0
+        || !crate::platform::irq::set_affinity(irq_num, cpu_mask)
+    {
+        return false;
+    }
+    AFFINITY_SET[irq_num].store(true, Ordering::Relaxed);
+    true
+}
+
+/// Spreads the IRQs over the first `num_cpus` CPUs, round robin, so one
+/// CPU does not handle them all. It is called once the CPUs are up, and the
+/// IRQs registered afterwards are spread too.
+///
+/// The timer IRQ and the IRQs whose affinity is set with [`set_affinity`]
+/// are not moved.
+pub fn spread_irqs(num_cpus: usize) {
+    SPREAD_CPUS.store(num_cpus, Ordering::Release);
+    let guard = rcu::read_lock();
+    for irq_num in 0..MAX_IRQ_COUNT {
+        if has_handlers(irq_num, &guard) {
+            route_default(irq_num);
+        }
+    }
+}
+
+/// Routes an IRQ to the next CPU, once [`spread_irqs`] is called.
+fn route_default(irq_num: usize) {
+    let num_cpus = SPREAD_CPUS.load(Ordering::Acquire);
+    if num_cpus <= 1 || irq_num == TIMER_IRQ_NUM || AFFINITY_SET[irq_num].load(Ordering::Relaxed) {
+        return;
+    }
+    let cpu = NEXT_CPU.fetch_add(1, Ordering::Relaxed) % num_cpus;
+    if crate::platform::irq::set_affinity(irq_num, 1 << cpu) {
+        debug!("IRQ {} routed to CPU {}", irq_num, cpu);
+    }
+}
+
+/// Returns the number of times each IRQ fired since boot, on all the CPUs,
+/// as `(irq_num, count)` for the IRQs which fired.
+pub fn irq_counts() -> impl Iterator<Item = (usize, u64)> {
+    IRQ_COUNTS
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .enumerate()
+        .filter(|&(_, count)| count != 0)
+}
+
+/// The trigger mode and polarity of an IRQ line, as described by the device
+/// tree or the ACPI tables, see [`set_trigger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// Triggered on the rising edge.
+    EdgeRising,
+    /// Triggered on the falling edge.
+    EdgeFalling,
+    /// Triggered while the line is high.
+    LevelHigh,
+    /// Triggered while the line is low.
+    LevelLow,
+}
+
+impl TriggerMode {
+    /// Converts the flags of an `interrupts` cell of the device tree
+    /// (`IRQ_TYPE_*`), or returns `None` if they have no single mode.
+    pub const fn from_dt_flags(flags: u32) -> Option<Self> {
+        match flags & 0xf {
+            1 => Some(Self::EdgeRising),
+            2 => Some(Self::EdgeFalling),
+            4 => Some(Self::LevelHigh),
+            8 => Some(Self::LevelLow),
+            _ => None,
+        }
+    }
+
+    /// Whether the line is level-triggered.
+    pub const fn is_level(self) -> bool {
+        matches!(self, Self::LevelHigh | Self::LevelLow)
+    }
+
+    /// Whether the line is active low, or triggered on the falling edge.
+    pub const fn is_active_low(self) -> bool {
+        matches!(self, Self::EdgeFalling | Self::LevelLow)
+    }
+}
+
+/// Sets the trigger mode and polarity of an IRQ line, in the interrupt
+/// controller: the redirection entry of an IO APIC pin, the `GICD_ICFGR`
+/// bits of an SPI, or the source mode of an APLIC source.
+///
+/// It is called before the IRQ is enabled. It returns `false` if the
+/// controller does not support `mode` for the IRQ, e.g., the active-low modes
+/// on the GIC, whose SPIs are either level-high or rising-edge, or the
+/// per-CPU IRQs.
+pub fn set_trigger(irq_num: usize, mode: TriggerMode) -> bool {
+    if irq_num >= MAX_IRQ_COUNT || !crate::platform::irq::set_trigger(irq_num, mode) {
+        return false;
+    }
+    debug!("IRQ {} set to {:?}", irq_num, mode);
+    true
+}
+
+/// The highest priority of an IRQ, see [`set_priority`].
+pub const MAX_PRIORITY: u8 = 7;
+
+/// The priority of the IRQs whose priority is not set.
+pub const DEFAULT_PRIORITY: u8 = 1;
+
+/// Sets the priority of an IRQ, from 1 to [`MAX_PRIORITY`], in the
+/// interrupt controller, [`DEFAULT_PRIORITY`] by default.
+///
+/// While an IRQ is handled, only the IRQs with a higher priority can be
+/// delivered to the CPU. It returns `false` if the controller has no
+/// priorities for the IRQ (only the SPIs of the GIC and the PLIC sources
+/// have), or if `priority` is out of range.
+pub fn set_priority(irq_num: usize, priority: u8) -> bool {
+    if irq_num >= MAX_IRQ_COUNT || !crate::platform::irq::set_priority(irq_num, priority) {
+        return false;
+    }
+    debug!("IRQ {} set to priority {}", irq_num, priority);
+    true
+}
+
+/// Sets the priority threshold of the current CPU: only the IRQs with a
+/// higher priority are delivered to it, 0 letting all of them through,
+/// which is the default.
+///
+/// It returns `false` if the controller has no priorities.
+pub fn set_priority_threshold(threshold: u8) -> bool {
+    crate::platform::irq::set_priority_threshold(threshold)
+}
+
+/// A message signaled interrupt (MSI): a device raises it by writing `data`
+/// to `address`, which are programmed into its MSI capability or MSI-X
+/// table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    /// The physical address written.
+    pub address: u64,
+    /// The value written.
+    pub data: u32,
+}
+
+/// An MSI allocated with [`request_msi`].
+#[derive(Debug)]
+pub struct MsiVector {
+    irq_num: usize,
+    message: MsiMessage,
+}
+
+impl MsiVector {
+    /// The IRQ number the MSI is dispatched as.
+    pub fn irq_num(&self) -> usize {
+        self.irq_num
+    }
+
+    /// The message the device must write to raise the MSI.
+    pub fn message(&self) -> MsiMessage {
+        self.message
+    }
+}
+
+/// Allocates an MSI for the device `dev`, with `handler` as its handler, or
+/// returns `None` if the platform has no free one.
+///
+/// `dev` identifies the device on platforms where MSIs are translated per
+/// device, i.e., its PCI requester ID (bus, device and function numbers).
+///
+/// The MSI is enabled when it is returned. Its message is then programmed
+/// into the device, which masks it itself, e.g., with the mask bits of MSI-X.
+pub fn request_msi(dev: u32, handler: IrqHandler) -> Option<MsiVector> {
+    for irq_num in msi_irqs() {
+        let entry = &IRQ_HANDLER_TABLE[irq_num];
+        if entry
+            .update(|old| old.is_none().then_some(Some(handler)))
+            .is_some()
+        {
+            let message = setup_msi(dev, irq_num);
+            debug!("MSI {} for device {:#x}: {:x?}", irq_num, dev, message);
+            set_enable(irq_num, true);
+            return Some(MsiVector { irq_num, message });
+        }
+    }
+    warn!("no free MSI for device {:#x}", dev);
+    None
+}
+
+/// Releases an MSI allocated with [`request_msi`], once the device no longer
+/// raises it.
+///
+/// When it returns, its handler is no longer running on any CPU. It must not
+/// be called by an IRQ handler.
+pub fn release_msi(msi: MsiVector) {
+    unregister_handler(msi.irq_num);
+}
+
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
-    let guard = kernel_guard::NoPreempt::new();
+    // Preemption is disabled in the critical section.
+    let guard = rcu::read_lock();
     dispatch_irq(irq_num);
+    // The hook runs once, after the outermost handlers.
+    #[cfg(feature = "nested-irq")]
+    if NESTING_DEPTH.read_current() != 0 {
+        return true;
+    }
+    if let Some(hook) = EXIT_HOOK.read(&guard) {
+        hook();
+    }
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
 }
@@ -11,8 +11,9 @@
 //! - `aarch64-qemu-virt`: QEMU virt machine with AArch64 ISA.
 //! - `aarch64-raspi`: Raspberry Pi with AArch64 ISA.
 //! - `dummy`: If none of the above platform is selected, the dummy platform
-//!   will be used. In this platform, most of the operations are no-op or
-//!   `unimplemented!()`. This platform is mainly used for [cargo test].
+//!   will be used. In this platform, the console, the clock and the interrupt
+//!   controller are emulated in memory, and driven by the tests through
+//!   [`dummy`]. This platform is mainly used for [cargo test].
 //!
 //! # Cargo Features
 //!
@@ -23,6 +24,30 @@
 //! - `tls`: Enable kernel space thread-local storage support.
 //! - `rtc`: Enable real-time clock support.
 //! - `uspace`: Enable user space support.
+//! - `selftest`: Run HAL self-tests after platform initialization.
+//! - `qemu-exit`: Report the exit code to QEMU on shutdown (see [`misc::exit`]).
+//! - `console-replay`: Replay console input from a script embedded at build
+//!   time (see [`console::read_bytes`]).
+//! - `vt`: Multiplex virtual terminals over the console device (see
+//!   [`console::switch_vt`]).
+//! - `sysrq`: Run diagnostics with magic keys on the console (see
+//!   [`console::register_sysrq`]).
+//! - `fbcon`: Enable the text console drawn into a framebuffer (see
+//!   [`fbcon`]).
+//! - `earlycon`: Write the console output to the boot UART by polling until
+//!   the console is up, so early panics are not silent (see [`earlycon`]).
+//! - `uart-pio`: Receive from the UART by programmed I/O rather than by DMA,
+//!   where DMA is supported (the PL011 of the Raspberry Pi).
+//! - `harden`: Run-time support of stack protectors and pointer authentication,
+//!   for kernels built with them (see [`harden`]).
+//! - `pm`: Runtime power management of devices (see [`pm`]).
+//! - `usb-acm`: Add a USB serial console (CDC-ACM) on the OTG port of the
+//!   Raspberry Pi 4 as a console sink.
+//! - `watch`: Hardware watchpoints on memory writes (see [`watch`]).
+//! - `cpuidle`: Enter the deepest idle state of the CPUs when idle, with a
+//!   timer broadcast for the states stopping their timer (see [`cpuidle`]).
+//! - `nested-irq`: Let the IRQs with a higher priority interrupt the IRQ
+//!   handlers, e.g., the timer IRQ (see [`irq`]).
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -41,8 +66,13 @@ extern crate memory_addr;
 
 mod platform;
 
+#[cfg(any(feature = "irq", feature = "watch"))]
+mod backtrace;
+
+pub mod cmdline;
 pub mod cpu;
 pub mod mem;
+pub mod rcu;
 pub mod time;
 
 #[cfg(feature = "tls")]
@@ -54,14 +84,131 @@ pub mod irq;
 #[cfg(feature = "paging")]
 pub mod paging;
 
-/// Console input and output.
-pub mod console {
-    pub use super::platform::console::*;
+#[cfg(feature = "selftest")]
+pub mod selftest;
+
+#[cfg(feature = "console-replay")]
+mod console_replay;
+
+mod console_buffer;
+mod console_ldisc;
+#[cfg(feature = "sysrq")]
+mod console_sysrq;
+#[cfg(feature = "vt")]
+mod console_vt;
+
+pub mod console;
+
+#[cfg(feature = "fbcon")]
+pub mod fbcon;
+
+#[cfg(feature = "earlycon")]
+pub mod earlycon;
+
+#[cfg(feature = "harden")]
+pub mod harden;
+
+#[cfg(feature = "pm")]
+pub mod pm;
+
+#[cfg(feature = "watch")]
+pub mod watch;
+
+#[cfg(feature = "cpuidle")]
+pub mod cpuidle;
+
+/// Devices emulated by the dummy platform, for the tests to feed the console
+/// input, advance the clock and raise IRQs.
+#[cfg(platform_family = "dummy")]
+pub mod dummy {
+    pub use super::platform::console::{push_input, take_output};
+    #[cfg(feature = "irq")]
+    pub use super::platform::irq::{is_pending, raise};
+    pub use super::platform::time::advance;
 }
 
 /// Miscellaneous operation, e.g. terminate the system.
 pub mod misc {
     pub use super::platform::misc::*;
+
+    /// Shutdown the whole system, as there is no way to report the exit code
+    /// to the host on this platform.
+    #[cfg(not(any(
+        platform_family = "x86-pc",
+        platform_family = "riscv64-qemu-virt",
+        platform_family = "aarch64-qemu-virt"
+    )))]
+    pub fn qemu_exit(_code: i32) -> ! {
+        terminate()
+    }
+
+    /// Shutdown the whole system, as there is no way to reset it on this
+    /// platform.
+    #[cfg(not(any(
+        platform_family = "x86-pc",
+        platform_family = "riscv64-qemu-virt",
+        platform_family = "aarch64-qemu-virt",
+        platform_family = "aarch64-phytium-pi",
+        platform_family = "aarch64-bsta1000b",
+        platform_family = "loongarch64-qemu-virt"
+    )))]
+    pub fn reset() -> ! {
+        warn!("reboot is not supported on this platform");
+        terminate()
+    }
+
+    /// Functions called by [`exit`] and [`reboot`] before the system shuts
+    /// down, e.g., to flush buffered data.
+    ///
+    /// Register a hook with `#[linkme::distributed_slice(SHUTDOWN_HOOKS)]`.
+    /// Hooks may run after a panic, so they should not wait for locks.
+    #[linkme::distributed_slice]
+    pub static SHUTDOWN_HOOKS: [fn()];
+
+    /// Reboots the whole system, e.g., to boot into an updated image.
+    ///
+    /// The [`SHUTDOWN_HOOKS`] are called first. If the platform does not
+    /// support it, the system is shut down instead.
+    pub fn reboot() -> ! {
+        for hook in SHUTDOWN_HOOKS {
+            hook();
+        }
+        reset()
+    }
+
+    /// Halts the system without powering it off, e.g., for the host to
+    /// inspect it before turning it off.
+    ///
+    /// The [`SHUTDOWN_HOOKS`] are called first, then this CPU stops with the
+    /// IRQs disabled. The other CPUs are not stopped.
+    pub fn halt() -> ! {
+        for hook in SHUTDOWN_HOOKS {
+            hook();
+        }
+        info!("System halted.");
+        crate::asm::disable_irqs();
+        loop {
+            crate::asm::halt();
+        }
+    }
+
+    /// Shutdown the whole system with the given exit code.
+    ///
+    /// The [`SHUTDOWN_HOOKS`] are called first. If the `qemu-exit` feature is
+    /// enabled, the exit code is reported to QEMU via [`qemu_exit`].
+    /// Otherwise, the exit code is ignored.
+    pub fn exit(code: i32) -> ! {
+        for hook in SHUTDOWN_HOOKS {
+            hook();
+        }
+        #[cfg(feature = "qemu-exit")]
+        qemu_exit(code);
+        #[cfg(not(feature = "qemu-exit"))]
+        {
+            debug!("exit code {} is ignored", code);
+            terminate()
+        }
+    }
 }
 
 /// Multi-core operations.
@@ -1,5 +1,6 @@
 //! snps,dw-apb-uart serial driver
 
+use crate::console::ConsoleDriver;
 use crate::mem::phys_to_virt;
 use dw_apb_uart::DW8250;
 use kspin::SpinNoIrq;
@@ -9,58 +10,115 @@ const UART_BASE: PhysAddr = pa!(axconfig::devices::UART_PADDR);
 
 static UART: SpinNoIrq<DW8250> = SpinNoIrq::new(DW8250::new(phys_to_virt(UART_BASE).as_usize()));
 
-/// Writes a byte to the console.
-pub fn putchar(c: u8) {
-    let mut uart = UART.lock();
-    match c {
-        b'\r' | b'\n' => {
-            uart.putchar(b'\r');
-            uart.putchar(b'\n');
-        }
-        c => uart.putchar(c),
+/// Bytes received by the UART IRQ handler and not read yet.
+#[cfg(feature = "irq")]
+static RX_QUEUE: crate::console::RxQueue = crate::console::RxQueue::new();
+
+/// The console device backed by the DW APB UART.
+pub(crate) struct DwApbConsole;
+
+/// The console device of the platform.
+pub(crate) static CONSOLE: DwApbConsole = DwApbConsole;
+
+/// All console ports, indexed by the port number. There is only one.
+pub(crate) static PORTS: [&dyn ConsoleDriver; 1] = [&CONSOLE];
+
+impl ConsoleDriver for DwApbConsole {
+    /// UART simply initialize
+    fn init(&self) {
+        UART.lock().init();
     }
-}
 
-/// Reads a byte from the console, or returns [`None`] if no input is available.
-fn getchar() -> Option<u8> {
-    UART.lock().getchar()
-}
+    fn write(&self, bytes: &[u8]) {
+        let mut uart = UART.lock();
+        for &c in bytes {
+            uart.putchar(c);
+        }
+    }
 
-/// Write a slice of bytes to the console.
-pub fn write_bytes(bytes: &[u8]) {
-    for c in bytes {
-        putchar(*c);
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        #[cfg(feature = "irq")]
+        if RX_QUEUE.is_enabled() {
+            let len = RX_QUEUE.read(bytes);
+            if RX_QUEUE.should_resume() {
+                fill_rx_queue(&mut UART.lock());
+            }
+            return len;
+        }
+        let mut uart = UART.lock();
+        check_overrun();
+        let mut read_len = 0;
+        while read_len < bytes.len() {
+            match uart.getchar() {
+                Some(c) => bytes[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        read_len
     }
-}
 
-/// Reads bytes from the console into the given mutable slice.
-/// Returns the number of bytes read.
-pub fn read_bytes(bytes: &mut [u8]) -> usize {
-    let mut read_len = 0;
-    while read_len < bytes.len() {
-        if let Some(c) = getchar() {
-            bytes[read_len] = c;
-        } else {
-            break;
+    /// Set UART IRQ Enable
+    #[cfg(feature = "irq")]
+    fn enable_rx_irq(&self) -> bool {
+        if !crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, &|_| handle()) {
+            return false;
         }
-        read_len += 1;
+        // Move the bytes received before to the queue.
+        fill_rx_queue(&mut UART.lock());
+        RX_QUEUE.enable();
+        true
     }
-    read_len
 }
 
-/// UART simply initialize
-pub fn init_early() {
-    UART.lock().init();
+/// Moves received bytes to [`RX_QUEUE`], disabling the receive interrupt
+/// while it is full.
+#[cfg(feature = "irq")]
+fn fill_rx_queue(uart: &mut DW8250) {
+    check_overrun();
+    let full = RX_QUEUE.fill(|| uart.getchar());
+    uart.set_ier(!full);
 }
 
-/// Set UART IRQ Enable
-#[cfg(feature = "irq")]
-pub fn init_irq() {
-    UART.lock().set_ier(true);
-    crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
+/// Counts the overrun error reported by the UART. Must be called with
+/// [`UART`] locked.
+///
+/// Reading the LSR clears it, so the overruns reported when [`DW8250`] reads
+/// it are missed.
+fn check_overrun() {
+    // The registers are 4 bytes apart.
+    const LSR: usize = 0x14;
+    const LSR_OE: u32 = 1 << 1;
+
+    let lsr = phys_to_virt(UART_BASE).as_usize() + LSR;
+    if unsafe { core::ptr::read_volatile(lsr as *const u32) } & LSR_OE != 0 {
+        crate::console::count_rx_overrun();
+    }
 }
 
-/// UART IRQ Handler
-pub fn handle() {
+/// UART IRQ Handler, moves all received bytes to [`RX_QUEUE`].
+#[cfg(feature = "irq")]
+fn handle() {
     trace!("Uart IRQ Handler");
+    fill_rx_queue(&mut UART.lock());
+}
+
+/// Writes a byte to the UART at its physical address by polling, without
+/// locks, see [`crate::earlycon`].
+#[cfg(feature = "earlycon")]
+pub(crate) fn early_putchar(c: u8) {
+    use core::ptr::{read_volatile, write_volatile};
+
+    // The registers are 4 bytes apart.
+    const THR: usize = 0x00;
+    const LSR: usize = 0x14;
+    const LSR_THRE: u32 = 1 << 5;
+
+    let base = UART_BASE.as_usize();
+    unsafe {
+        while read_volatile((base + LSR) as *const u32) & LSR_THRE == 0 {
+            core::hint::spin_loop();
+        }
+        write_volatile((base + THR) as *mut u32, c as u32);
+    }
 }
@@ -35,6 +35,13 @@ pub fn reset_cpu() {
     loop {}
 }
 
+/// Resets the whole system, including all CPUs.
+pub fn reset() -> ! {
+    info!("Rebooting...");
+    reset_cpu();
+    unreachable!()
+}
+
 /// reboot system
 #[allow(dead_code)]
 pub fn do_reset() {
@@ -19,6 +19,8 @@ pub mod time {
     pub use crate::platform::aarch64_common::generic_timer::*;
 }
 
+use crate::console::ConsoleDriver;
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize);
     #[cfg(feature = "smp")]
@@ -29,7 +31,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     axcpu::init::init_trap();
     crate::cpu::init_primary(cpu_id);
-    dw_apb_uart::init_early();
+    dw_apb_uart::CONSOLE.init();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
 }
@@ -49,7 +51,7 @@ pub fn platform_init() {
     super::aarch64_common::gic::init_primary();
     super::aarch64_common::generic_timer::init_percpu();
     #[cfg(feature = "irq")]
-    dw_apb_uart::init_irq();
+    dw_apb_uart::CONSOLE.enable_rx_irq();
 }
 
 /// Initializes the platform devices for secondary CPUs.
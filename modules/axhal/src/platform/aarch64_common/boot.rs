@@ -71,6 +71,7 @@ unsafe extern "C" fn _start_primary() -> ! {
         add     x8, x8, {boot_stack_size}
         mov     sp, x8
 
+        bl      {init_el2}              // configure EL2 if entered at EL2
         bl      {switch_to_el1}         // switch to EL1
         bl      {enable_fp}             // enable fp/neon
         bl      {init_boot_page_table}
@@ -85,6 +86,7 @@ unsafe extern "C" fn _start_primary() -> ! {
         ldr     x8, ={entry}
         blr     x8
         b      .",
+        init_el2 = sym super::el2::init_el2,
         switch_to_el1 = sym axcpu::init::switch_to_el1,
         init_mmu = sym axcpu::init::init_mmu,
         init_boot_page_table = sym init_boot_page_table,
@@ -108,6 +110,7 @@ unsafe extern "C" fn _start_secondary() -> ! {
         and     x19, x19, #0xffffff     // get current CPU id
 
         mov     sp, x0
+        bl      {init_el2}
         bl      {switch_to_el1}
         bl      {enable_fp}
         adrp    x0, {boot_pt}
@@ -120,6 +123,7 @@ unsafe extern "C" fn _start_secondary() -> ! {
         ldr     x8, ={entry}
         blr     x8
         b      .",
+        init_el2 = sym super::el2::init_el2,
         switch_to_el1 = sym axcpu::init::switch_to_el1,
         init_mmu = sym axcpu::init::init_mmu,
         enable_fp = sym enable_fp,
@@ -0,0 +1,165 @@
+//! Handling of CPUs entered at EL2.
+//!
+//! Firmware and QEMU (`-machine virt,virtualization=on`) may start the kernel
+//! at EL2. Before dropping to EL1, [`init_el2`] puts EL2 into a known state,
+//! since the firmware may leave traps enabled (e.g., of FP/SIMD accesses) or
+//! the VHE mode on, which would otherwise stop EL1 silently at its first
+//! trapped instruction.
+//!
+//! A minimal vector table is installed at EL2, like the hypervisor stub of
+//! Linux. It serves `hvc #0` from EL1, with the function in `x0`:
+//!
+//! - [`HVC_GET_VECTORS`]: returns `VBAR_EL2` in `x0`.
+//! - [`HVC_SET_VECTORS`]: sets `VBAR_EL2` to the physical address in `x1`,
+//!   so a hypervisor can take over EL2 later.
+//!
+//! Other exceptions taken to EL2 from EL1 are reflected back to the
+//! synchronous exception vector of EL1, so the kernel reports them instead of
+//! hanging.
+
+/// The function of `hvc #0` returning the EL2 vector table.
+pub const HVC_GET_VECTORS: usize = 0;
+/// The function of `hvc #0` replacing the EL2 vector table.
+pub const HVC_SET_VECTORS: usize = 1;
+
+/// `SCTLR_EL2` with MMU and caches off, little-endian (only RES1 bits).
+const SCTLR_EL2_INIT: u64 = 0x30c5_0830;
+/// `SCTLR_EL1` with MMU and caches off, little-endian (only RES1 bits).
+const SCTLR_EL1_INIT: u64 = 0x30d0_0800;
+/// `HCR_EL2` with EL1 in AArch64, and nothing else (no VHE, no traps).
+const HCR_EL2_INIT: u64 = 1 << 31;
+/// `CPTR_EL2` without traps of FP/SIMD and CPACR accesses (only RES1 bits).
+const CPTR_EL2_INIT: u64 = 0x33ff;
+
+core::arch::global_asm!("
+    .macro PARK
+        .balign 0x80
+    1:  wfe
+        b       1b
+    .endm
+
+    .pushsection .text
+    .balign 0x800
+    .global el2_vectors
+el2_vectors:
+    // Current EL with SP0, and with SPx: EL2 itself never traps.
+    .rept 8
+    PARK
+    .endr
+
+    // Lower EL using AArch64.
+    .balign 0x80
+    b       el2_sync_lower
+    .rept 3
+    PARK
+    .endr
+
+    // Lower EL using AArch32.
+    .rept 4
+    PARK
+    .endr
+
+el2_sync_lower:
+    msr     tpidr_el2, x9           // x9 is the only register used
+    mrs     x9, esr_el2
+    lsr     x9, x9, #26
+    cmp     x9, #0x16               // EC: HVC from AArch64
+    b.ne    el2_reflect
+
+    cmp     x0, #{get_vectors}
+    b.ne    1f
+    mrs     x0, vbar_el2
+    b       3f
+1:  cmp     x0, #{set_vectors}
+    b.ne    2f
+    msr     vbar_el2, x1
+    mov     x0, #0
+    b       3f
+2:  mov     x0, #-1                 // unknown function
+3:  mrs     x9, tpidr_el2
+    eret
+
+// Returns to the synchronous exception vector of EL1, as if the exception
+// was taken to EL1.
+el2_reflect:
+    mrs     x9, esr_el2
+    msr     esr_el1, x9
+    mrs     x9, elr_el2
+    msr     elr_el1, x9
+    mrs     x9, far_el2
+    msr     far_el1, x9
+    mrs     x9, spsr_el2
+    msr     spsr_el1, x9
+    tst     x9, #0xf                // from EL0?
+    mrs     x9, vbar_el1
+    b.eq    1f
+    add     x9, x9, #0x200          // current EL with SPx
+    b       2f
+1:  add     x9, x9, #0x400          // lower EL using AArch64
+2:  msr     elr_el2, x9
+    mov     x9, #0x3c5              // EL1h, with DAIF masked
+    msr     spsr_el2, x9
+    mrs     x9, tpidr_el2
+    eret
+
+    .popsection
+",
+    get_vectors = const HVC_GET_VECTORS,
+    set_vectors = const HVC_SET_VECTORS,
+);
+
+unsafe extern "C" {
+    fn el2_vectors();
+}
+
+/// Configures EL2 for running the kernel at EL1, if the current EL is EL2.
+///
+/// It must be called with the MMU off, before switching to EL1. Only `x9` is
+/// clobbered.
+#[unsafe(naked)]
+pub(super) unsafe extern "C" fn init_el2() {
+    core::arch::naked_asm!("
+        mrs     x9, CurrentEL
+        cmp     x9, #(2 << 2)
+        b.ne    1f
+
+        // Clear E2H and TGE first, since they redirect the accesses to EL1
+        // registers, and change the layout of EL2 registers.
+        ldr     x9, ={hcr_el2}
+        msr     hcr_el2, x9
+        isb
+
+        adrp    x9, {vectors}
+        add     x9, x9, :lo12:{vectors}
+        msr     vbar_el2, x9
+        ldr     x9, ={sctlr_el2}
+        msr     sctlr_el2, x9
+        ldr     x9, ={cptr_el2}
+        msr     cptr_el2, x9
+        msr     hstr_el2, xzr
+        mrs     x9, mdcr_el2            // keep only HPMN, no debug traps
+        and     x9, x9, #0x1f
+        msr     mdcr_el2, x9
+        msr     vttbr_el2, xzr
+
+        mrs     x9, midr_el1            // EL1 sees the real IDs
+        msr     vpidr_el2, x9
+        mrs     x9, mpidr_el1
+        msr     vmpidr_el2, x9
+
+        mrs     x9, cnthctl_el2         // EL1 can access the physical timer
+        orr     x9, x9, #3
+        msr     cnthctl_el2, x9
+        msr     cntvoff_el2, xzr
+
+        ldr     x9, ={sctlr_el1}
+        msr     sctlr_el1, x9
+        isb
+    1:  ret",
+        vectors = sym el2_vectors,
+        sctlr_el2 = const SCTLR_EL2_INIT,
+        hcr_el2 = const HCR_EL2_INIT,
+        cptr_el2 = const CPTR_EL2_INIT,
+        sctlr_el1 = const SCTLR_EL1_INIT,
+    )
+}
@@ -1,6 +1,7 @@
-use crate::{irq::IrqHandler, mem::phys_to_virt};
-use arm_gicv2::{GicCpuInterface, GicDistributor, InterruptType, translate_irq};
-use axconfig::devices::{GICC_PADDR, GICD_PADDR, UART_IRQ};
+use crate::irq::{DEFAULT_PRIORITY, IrqHandler, MAX_PRIORITY};
+use crate::mem::phys_to_virt;
+use arm_gicv2::{GicCpuInterface, GicDistributor, InterruptType, TriggerMode, translate_irq};
+use axconfig::devices::{GICC_PADDR, GICD_PADDR, GICV2M_PADDR, UART_IRQ};
 use kspin::SpinNoIrq;
 use memory_addr::PhysAddr;
 
@@ -16,6 +17,27 @@ pub const UART_IRQ_NUM: usize = translate_irq(UART_IRQ, InterruptType::SPI).unwr
 const GICD_BASE: PhysAddr = pa!(GICD_PADDR);
 const GICC_BASE: PhysAddr = pa!(GICC_PADDR);
 
+/// The MSI frame of GICv2m, or 0 if there is none.
+const GICV2M_BASE: PhysAddr = pa!(GICV2M_PADDR);
+
+/// The byte of the first SPI in the `GICD_ITARGETSR` registers, which
+/// route each SPI to CPU interfaces.
+const GICD_ITARGETSR_SPI: usize = 0x800 + SPI_BASE;
+/// The first SPI; the SGIs and PPIs before are private to each CPU.
+const SPI_BASE: usize = 32;
+
+// Distributor and CPU interface registers.
+const GICD_TYPER: usize = 0x004;
+const GICD_IPRIORITYR: usize = 0x400;
+const GICC_PMR: usize = 0x004;
+
+/// The priority mask letting all the IRQs through.
+const PMR_ALL: u8 = 0xff;
+
+// Registers of the GICv2m MSI frame.
+const V2M_MSI_TYPER: usize = 0x008;
+const V2M_MSI_SETSPI_NS: usize = 0x040;
+
 static GICD: SpinNoIrq<GicDistributor> =
     SpinNoIrq::new(GicDistributor::new(phys_to_virt(GICD_BASE).as_mut_ptr()));
 
@@ -28,6 +50,109 @@ pub fn set_enable(irq_num: usize, enabled: bool) {
     GICD.lock().set_enable(irq_num as _, enabled);
 }
 
+/// Routes an SPI to the CPUs in `cpu_mask`, whose CPU interface numbers are
+/// the CPU IDs, in its `GICD_ITARGETSR` byte. It is then handled by the
+/// first of them to acknowledge it.
+///
+/// It returns `false` for the SGIs and PPIs, which are private to each CPU.
+pub fn set_affinity(irq_num: usize, cpu_mask: usize) -> bool {
+    // GICv2 supports up to 8 CPU interfaces.
+    let targets = cpu_mask as u8;
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) || targets == 0 {
+        return false;
+    }
+    let _gicd = GICD.lock();
+    let reg = phys_to_virt(GICD_BASE + GICD_ITARGETSR_SPI + (irq_num - SPI_BASE)).as_mut_ptr();
+    // The registers are byte-accessible.
+    unsafe { reg.write_volatile(targets) };
+    true
+}
+
+/// Sets an SPI level-sensitive or edge-triggered, in its `GICD_ICFGR` bits.
+///
+/// It returns `false` for the active-low modes, as the GIC only takes
+/// level-high or rising-edge lines, and for the SGIs and PPIs, whose modes
+/// are fixed.
+pub fn set_trigger(irq_num: usize, mode: crate::irq::TriggerMode) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) || mode.is_active_low() {
+        return false;
+    }
+    let trigger = if mode.is_level() {
+        TriggerMode::Level
+    } else {
+        TriggerMode::Edge
+    };
+    GICD.lock().configure_interrupt(irq_num, trigger);
+    true
+}
+
+/// Returns the `GICD_IPRIORITYR` byte of a priority, from 1 to
+/// [`MAX_PRIORITY`]: the lower the byte, the higher the priority. Only
+/// the upper 3 bits are used, as a GICv2 implements at least 16 levels.
+const fn priority_byte(priority: u8) -> u8 {
+    (MAX_PRIORITY - priority) << 5
+}
+
+#[cfg(feature = "nested-irq")]
+fn read_priority(irq_num: usize) -> u8 {
+    let reg = phys_to_virt(GICD_BASE + GICD_IPRIORITYR + irq_num).as_ptr();
+    unsafe { reg.read_volatile() }
+}
+
+fn write_priority(irq_num: usize, priority: u8) {
+    let reg = phys_to_virt(GICD_BASE + GICD_IPRIORITYR + irq_num).as_mut_ptr();
+    // The registers are byte-accessible.
+    unsafe { reg.write_volatile(priority_byte(priority)) };
+}
+
+fn read_pmr() -> u8 {
+    let reg = phys_to_virt(GICC_BASE + GICC_PMR).as_ptr() as *const u32;
+    unsafe { reg.read_volatile() as u8 }
+}
+
+fn write_pmr(pmr: u8) {
+    let reg = phys_to_virt(GICC_BASE + GICC_PMR).as_mut_ptr() as *mut u32;
+    unsafe { reg.write_volatile(pmr as u32) };
+}
+
+/// Sets the priority of an SPI, in its `GICD_IPRIORITYR` byte.
+///
+/// It returns `false` for the SGIs and PPIs, whose priorities are banked
+/// per CPU, or if `priority` is out of range.
+pub fn set_priority(irq_num: usize, priority: u8) -> bool {
+    if !(SPI_BASE..MAX_IRQ_COUNT).contains(&irq_num) || !(1..=MAX_PRIORITY).contains(&priority) {
+        return false;
+    }
+    let _gicd = GICD.lock();
+    write_priority(irq_num, priority);
+    true
+}
+
+/// Sets the priority threshold of the current CPU, in the priority mask of
+/// its CPU interface (`GICC_PMR`).
+pub fn set_priority_threshold(threshold: u8) -> bool {
+    match threshold.min(MAX_PRIORITY) {
+        0 => write_pmr(PMR_ALL),
+        threshold => write_pmr(priority_byte(threshold)),
+    }
+    true
+}
+
+/// Masks the IRQs whose priority is not higher than the one of `irq_num`,
+/// in the priority mask of the CPU interface, and returns the old mask.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn raise_priority_mask(irq_num: usize) -> Option<usize> {
+    let pmr = read_pmr();
+    write_pmr(pmr.min(read_priority(irq_num)));
+    Some(pmr as usize)
+}
+
+/// Restores the priority mask returned by [`raise_priority_mask`].
+#[cfg(feature = "nested-irq")]
+pub(crate) fn restore_priority_mask(saved: usize) {
+    write_pmr(saved as u8);
+}
+
 /// Registers an IRQ handler for the given IRQ.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
@@ -37,6 +162,29 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Returns the SPIs which can be allocated to MSIs, those of the GICv2m
+/// frame, if any.
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    if GICV2M_PADDR == 0 {
+        return 0..0;
+    }
+    let typer = phys_to_virt(GICV2M_BASE + V2M_MSI_TYPER).as_ptr() as *const u32;
+    let typer = unsafe { typer.read_volatile() } as usize;
+    let (base, count) = ((typer >> 16) & 0x3ff, typer & 0x3ff);
+    base..(base + count).min(MAX_IRQ_COUNT)
+}
+
+/// Configures the SPI of an MSI as edge-triggered, and returns its message:
+/// the SPI number written to the `MSI_SETSPI_NS` register of the GICv2m
+/// frame.
+pub(crate) fn setup_msi(_dev: u32, irq_num: usize) -> crate::irq::MsiMessage {
+    GICD.lock().configure_interrupt(irq_num, TriggerMode::Edge);
+    crate::irq::MsiMessage {
+        address: (GICV2M_PADDR + V2M_MSI_SETSPI_NS) as u64,
+        data: irq_num as u32,
+    }
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -49,12 +197,30 @@ pub fn dispatch_irq(_unused: usize) {
 /// Initializes GICD, GICC on the primary CPU.
 pub(crate) fn init_primary() {
     info!("Initialize GICv2...");
-    GICD.lock().init();
+    let mut gicd = GICD.lock();
+    gicd.init();
+    let typer = phys_to_virt(GICD_BASE + GICD_TYPER).as_ptr() as *const u32;
+    let num_irqs = ((unsafe { typer.read_volatile() } as usize & 0x1f) + 1) * 32;
+    for irq_num in SPI_BASE..num_irqs.min(MAX_IRQ_COUNT) {
+        write_priority(irq_num, DEFAULT_PRIORITY);
+    }
+    drop(gicd);
+    init_percpu_priorities();
     GICC.init();
 }
 
+/// Sets the priorities of the SGIs and PPIs of the current CPU: the timer
+/// IRQ has the highest, so it can interrupt the handlers of the others.
+fn init_percpu_priorities() {
+    for irq_num in 0..SPI_BASE {
+        write_priority(irq_num, DEFAULT_PRIORITY);
+    }
+    write_priority(TIMER_IRQ_NUM, MAX_PRIORITY);
+}
+
 /// Initializes GICC on secondary CPUs.
 #[cfg(feature = "smp")]
 pub(crate) fn init_secondary() {
+    init_percpu_priorities();
     GICC.init();
 }
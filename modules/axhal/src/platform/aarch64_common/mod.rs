@@ -1,9 +1,13 @@
 mod boot;
+mod el2;
 
 pub mod generic_timer;
 #[cfg(not(platform_family = "aarch64-raspi"))]
 pub mod psci;
 
+#[cfg(platform_family = "aarch64-qemu-virt")]
+pub mod semihosting;
+
 #[cfg(feature = "irq")]
 pub mod gic;
 
@@ -4,6 +4,7 @@ use arm_pl011::Pl011Uart;
 use kspin::SpinNoIrq;
 use memory_addr::PhysAddr;
 
+use crate::console::ConsoleDriver;
 use crate::mem::phys_to_virt;
 
 const UART_BASE: PhysAddr = pa!(axconfig::devices::UART_PADDR);
@@ -11,63 +12,552 @@ const UART_BASE: PhysAddr = pa!(axconfig::devices::UART_PADDR);
 static UART: SpinNoIrq<Pl011Uart> =
     SpinNoIrq::new(Pl011Uart::new(phys_to_virt(UART_BASE).as_mut_ptr()));
 
-/// Writes a byte to the console.
-pub fn putchar(c: u8) {
-    let mut uart = UART.lock();
-    match c {
-        b'\n' => {
-            uart.putchar(b'\r');
-            uart.putchar(b'\n');
+/// Bytes received by the UART IRQ handler and not read yet.
+#[cfg(feature = "irq")]
+static RX_QUEUE: crate::console::RxQueue = crate::console::RxQueue::new();
+
+/// Bytes written and not sent yet, sent by the UART IRQ handler.
+#[cfg(feature = "irq")]
+static TX_QUEUE: crate::console::TxQueue = crate::console::TxQueue::new();
+
+/// Access to the registers for burst writes, transmit interrupts and receive
+/// flow control, which [`Pl011Uart`] does not cover. Must be used with
+/// [`UART`] locked.
+mod regs {
+    use core::ptr::{read_volatile, write_volatile};
+
+    use super::{UART_BASE, phys_to_virt};
+
+    /// Data register.
+    const DR: usize = 0x00;
+    /// Flag register.
+    const FR: usize = 0x18;
+    /// Interrupt mask set/clear register.
+    #[cfg(feature = "irq")]
+    const IMSC: usize = 0x38;
+    /// DMA control register.
+    #[cfg(feature = "irq")]
+    const DMACR: usize = 0x48;
+
+    /// Transmit FIFO full.
+    const FR_TXFF: u32 = 1 << 5;
+    /// Receive interrupt mask.
+    #[cfg(feature = "irq")]
+    const IMSC_RXIM: u32 = 1 << 4;
+    /// Transmit interrupt mask.
+    #[cfg(feature = "irq")]
+    const IMSC_TXIM: u32 = 1 << 5;
+    /// Receive timeout interrupt mask.
+    #[cfg(feature = "irq")]
+    const IMSC_RTIM: u32 = 1 << 6;
+    /// Receive DMA enable.
+    #[cfg(feature = "irq")]
+    const DMACR_RXDMAE: u32 = 1 << 0;
+
+    fn reg(offset: usize) -> *mut u32 {
+        (phys_to_virt(UART_BASE).as_usize() + offset) as *mut u32
+    }
+
+    /// Writes bytes returned by `next` until the transmit FIFO is full.
+    pub fn fill_tx_fifo(mut next: impl FnMut() -> Option<u8>) {
+        unsafe {
+            while read_volatile(reg(FR)) & FR_TXFF == 0 {
+                match next() {
+                    Some(c) => write_volatile(reg(DR), c as u32),
+                    None => break,
+                }
+            }
         }
-        c => uart.putchar(c),
     }
-}
 
-/// Reads a byte from the console, or returns [`None`] if no input is available.
-fn getchar() -> Option<u8> {
-    UART.lock().getchar()
+    /// Writes all the bytes, as many at a time as the transmit FIFO can
+    /// hold.
+    pub fn write_bytes(bytes: &[u8]) {
+        let mut rest = bytes;
+        loop {
+            fill_tx_fifo(|| {
+                let (&c, tail) = rest.split_first()?;
+                rest = tail;
+                Some(c)
+            });
+            if rest.is_empty() {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    #[cfg(feature = "irq")]
+    fn set_interrupt_mask(mask: u32, enable: bool) {
+        unsafe {
+            let imsc = read_volatile(reg(IMSC));
+            let imsc = if enable { imsc | mask } else { imsc & !mask };
+            write_volatile(reg(IMSC), imsc);
+        }
+    }
+
+    /// Enables or disables the transmit interrupt.
+    #[cfg(feature = "irq")]
+    pub fn set_tx_interrupt(enable: bool) {
+        set_interrupt_mask(IMSC_TXIM, enable);
+    }
+
+    /// Enables or disables the receive interrupts.
+    #[cfg(feature = "irq")]
+    pub fn set_rx_interrupt(enable: bool) {
+        set_interrupt_mask(IMSC_RXIM | IMSC_RTIM, enable);
+    }
+
+    /// Enables DMA requests for the receive FIFO.
+    #[cfg(feature = "irq")]
+    #[allow(dead_code)]
+    pub fn enable_rx_dma() {
+        unsafe { write_volatile(reg(DMACR), read_volatile(reg(DMACR)) | DMACR_RXDMAE) };
+    }
 }
 
-/// Write a slice of bytes to the console.
-pub fn write_bytes(bytes: &[u8]) {
-    for c in bytes {
-        putchar(*c);
+/// Counts the overrun error reported by the UART, and clears it. Must be
+/// called with [`UART`] locked.
+fn check_overrun() {
+    use core::ptr::{read_volatile, write_volatile};
+
+    /// Receive status register (read), error clear register (write).
+    const RSR_ECR: usize = 0x04;
+    /// Overrun error.
+    const RSR_OE: u32 = 1 << 3;
+
+    let reg = (phys_to_virt(UART_BASE).as_usize() + RSR_ECR) as *mut u32;
+    unsafe {
+        if read_volatile(reg) & RSR_OE != 0 {
+            crate::console::count_rx_overrun();
+            write_volatile(reg, 0);
+        }
     }
 }
 
-/// Reads bytes from the console into the given mutable slice.
-/// Returns the number of bytes read.
-pub fn read_bytes(bytes: &mut [u8]) -> usize {
-    let mut read_len = 0;
-    while read_len < bytes.len() {
-        if let Some(c) = getchar() {
-            bytes[read_len] = c;
+/// Receiving by DMA on the Raspberry Pi, so there is an interrupt for each
+/// half of a ring buffer rather than for each few bytes.
+///
+/// Two control blocks of the DMA controller, chained in a loop, move the
+/// received bytes to each half of [`RING`](dma::RING) in turn, and interrupt
+/// when it is full. The bytes received since are picked up by reading the
+/// position of the channel, when the console is read.
+#[cfg(all(
+    feature = "irq",
+    platform_family = "aarch64-raspi",
+    not(feature = "uart-pio")
+))]
+mod dma {
+    use core::mem::size_of;
+    use core::ptr::{read_volatile, write_volatile};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use arm_gicv2::{InterruptType, translate_irq};
+    use axconfig::devices::{UART_DMA_CHANNEL, UART_DMA_IRQ, UART_DMA_PADDR, UART_PADDR};
+
+    use super::{RX_QUEUE, UART};
+    use crate::mem::{phys_to_virt, virt_to_phys};
+
+    /// The number of bytes received in each half of the ring buffer.
+    const HALF_LEN: usize = 1024;
+    const RING_LEN: usize = 2 * HALF_LEN;
+
+    /// The IRQ number of the DMA channel.
+    const IRQ_NUM: usize = translate_irq(UART_DMA_IRQ, InterruptType::SPI).unwrap();
+
+    /// The peripheral number of the receive FIFO of the PL011 (UART0).
+    const DREQ_UART_RX: u32 = 14;
+
+    /// Registers of the channel.
+    const CS: usize = 0x00;
+    const CONBLK_AD: usize = 0x04;
+    const DEST_AD: usize = 0x10;
+    /// The register enabling each channel, after those of the channels.
+    const ENABLE: usize = 0xff0;
+
+    const CS_ACTIVE: u32 = 1 << 0;
+    const CS_END: u32 = 1 << 1;
+    const CS_INT: u32 = 1 << 2;
+    const CS_WAIT_FOR_OUTSTANDING_WRITES: u32 = 1 << 28;
+    const CS_RESET: u32 = 1 << 31;
+
+    /// The overrun error flag in the words of the data register.
+    const DR_OE: u32 = 1 << 11;
+
+    const TI_INTEN: u32 = 1 << 0;
+    const TI_WAIT_RESP: u32 = 1 << 3;
+    const TI_DEST_INC: u32 = 1 << 4;
+    const TI_SRC_DREQ: u32 = 1 << 10;
+    const TI_PERMAP_SHIFT: u32 = 16;
+
+    /// A control block of the DMA controller, describing a transfer.
+    #[repr(C, align(32))]
+    struct ControlBlock {
+        ti: u32,
+        source_ad: u32,
+        dest_ad: u32,
+        txfr_len: u32,
+        stride: u32,
+        nextconbk: u32,
+        _reserved: [u32; 2],
+    }
+
+    /// The ring buffer, with each received byte in the lowest byte of a word
+    /// (with the error flags of the data register above), as the controller
+    /// only reads the UART by words.
+    #[repr(C, align(64))]
+    struct Ring([u32; RING_LEN]);
+
+    static mut CONTROL_BLOCKS: [ControlBlock; 2] = [const {
+        ControlBlock {
+            ti: 0,
+            source_ad: 0,
+            dest_ad: 0,
+            txfr_len: 0,
+            stride: 0,
+            nextconbk: 0,
+            _reserved: [0; 2],
+        }
+    }; 2];
+
+    static mut RING: Ring = Ring([0; RING_LEN]);
+
+    /// The index in [`RING`] of the next byte to read. Must be used with
+    /// [`UART`] locked.
+    static READ_POS: AtomicUsize = AtomicUsize::new(0);
+
+    static ENABLED: AtomicBool = AtomicBool::new(false);
+
+    const CACHE_LINE_SIZE: usize = 64;
+
+    fn reg(offset: usize) -> *mut u32 {
+        let base = phys_to_virt(pa!(UART_DMA_PADDR)).as_usize();
+        (base + UART_DMA_CHANNEL * 0x100 + offset) as *mut u32
+    }
+
+    /// The address of memory for the DMA controller.
+    fn mem_bus_addr(vaddr: usize) -> u32 {
+        (virt_to_phys(va!(vaddr)).as_usize() + axconfig::plat::PHYS_BUS_OFFSET) as u32
+    }
+
+    /// The address of a peripheral for the DMA controller, which sees them
+    /// at `0x7E00_0000` rather than `0xFE00_0000`.
+    fn peripheral_bus_addr(paddr: usize) -> u32 {
+        (paddr - 0xFE00_0000 + 0x7E00_0000) as u32
+    }
+
+    /// Cleans and invalidates the data cache lines of the given range, as the
+    /// DMA controller is not coherent with the caches.
+    fn flush_dcache(start: usize, len: usize) {
+        let mut addr = start & !(CACHE_LINE_SIZE - 1);
+        while addr < start + len {
+            axcpu::asm::flush_dcache_line(va!(addr));
+            addr += CACHE_LINE_SIZE;
+        }
+    }
+
+    /// Whether the received bytes are moved by DMA.
+    pub fn is_enabled() -> bool {
+        ENABLED.load(Ordering::Acquire)
+    }
+
+    /// Starts moving the received bytes to the ring buffer. Must be called
+    /// with [`UART`] locked.
+    ///
+    /// Returns `false` if the IRQ handler of the channel can not be
+    /// registered.
+    pub fn start() -> bool {
+        if !crate::irq::register_handler(IRQ_NUM, &|_| handle()) {
+            return false;
+        }
+        let ring = (&raw const RING) as usize;
+        let blocks = &raw mut CONTROL_BLOCKS;
+        for i in 0..2 {
+            let next = (i + 1) % 2;
+            let block = ControlBlock {
+                ti: TI_INTEN
+                    | TI_WAIT_RESP
+                    | TI_DEST_INC
+                    | TI_SRC_DREQ
+                    | (DREQ_UART_RX << TI_PERMAP_SHIFT),
+                // The data register is at offset 0.
+                source_ad: peripheral_bus_addr(UART_PADDR),
+                dest_ad: mem_bus_addr(ring + i * HALF_LEN * 4),
+                txfr_len: (HALF_LEN * 4) as u32,
+                stride: 0,
+                nextconbk: mem_bus_addr(blocks as usize + next * size_of::<ControlBlock>()),
+                _reserved: [0; 2],
+            };
+            unsafe { (*blocks)[i] = block };
+        }
+        flush_dcache(blocks as usize, size_of::<[ControlBlock; 2]>());
+        flush_dcache(ring, size_of::<Ring>());
+        unsafe {
+            let enable = (phys_to_virt(pa!(UART_DMA_PADDR)).as_usize() + ENABLE) as *mut u32;
+            write_volatile(enable, read_volatile(enable) | 1 << UART_DMA_CHANNEL);
+            write_volatile(reg(CS), CS_RESET);
+            write_volatile(reg(CONBLK_AD), mem_bus_addr(blocks as usize));
+            write_volatile(reg(CS), CS_ACTIVE | CS_WAIT_FOR_OUTSTANDING_WRITES);
+        }
+        super::regs::enable_rx_dma();
+        ENABLED.store(true, Ordering::Release);
+        true
+    }
+
+    /// The index in [`RING`] of the next byte to be received.
+    fn write_pos() -> Option<usize> {
+        let dest = unsafe { read_volatile(reg(DEST_AD)) };
+        let offset = dest.wrapping_sub(mem_bus_addr((&raw const RING) as usize)) as usize / 4;
+        // It is at the end of the ring until the first block is loaded again.
+        (offset <= RING_LEN).then_some(offset % RING_LEN)
+    }
+
+    /// Pauses or resumes the channel, leaving the bytes in the UART while the
+    /// receive queue is full.
+    fn set_active(active: bool) {
+        unsafe {
+            let cs = read_volatile(reg(CS)) & CS_WAIT_FOR_OUTSTANDING_WRITES;
+            write_volatile(reg(CS), if active { cs | CS_ACTIVE } else { cs });
+        }
+    }
+
+    /// Moves the bytes in the ring buffer to [`RX_QUEUE`], pausing the
+    /// channel while it is full. Must be called with [`UART`] locked.
+    pub fn fill_rx_queue() {
+        let Some(end) = write_pos() else {
+            return;
+        };
+        let mut pos = READ_POS.load(Ordering::Relaxed);
+        let ring = (&raw const RING) as usize;
+        if pos <= end {
+            flush_dcache(ring + pos * 4, (end - pos) * 4);
         } else {
-            break;
+            flush_dcache(ring + pos * 4, (RING_LEN - pos) * 4);
+            flush_dcache(ring, end * 4);
         }
-        read_len += 1;
+        let full = RX_QUEUE.fill(|| {
+            if pos == end {
+                return None;
+            }
+            let word = unsafe { read_volatile(&raw const RING.0[pos]) };
+            pos = (pos + 1) % RING_LEN;
+            if word & DR_OE != 0 {
+                crate::console::count_rx_overrun();
+            }
+            Some(word as u8)
+        });
+        READ_POS.store(pos, Ordering::Relaxed);
+        set_active(!full);
+    }
+
+    /// IRQ handler of the channel, called when a half of the ring buffer is
+    /// full.
+    fn handle() {
+        let _uart = UART.lock();
+        unsafe {
+            let cs = read_volatile(reg(CS)) & (CS_ACTIVE | CS_WAIT_FOR_OUTSTANDING_WRITES);
+            write_volatile(reg(CS), cs | CS_INT | CS_END);
+        }
+        fill_rx_queue();
     }
-    read_len
 }
 
-/// Initialize the UART
-pub fn init_early() {
-    UART.lock().init();
+/// No DMA controller is supported for the UART on this platform.
+#[cfg(all(
+    feature = "irq",
+    not(all(platform_family = "aarch64-raspi", not(feature = "uart-pio")))
+))]
+mod dma {
+    pub fn is_enabled() -> bool {
+        false
+    }
+
+    pub fn start() -> bool {
+        false
+    }
+
+    pub fn fill_rx_queue() {}
 }
 
-/// Set UART IRQ Enable
-pub fn init() {
+/// The console device backed by the PL011 UART.
+pub(crate) struct Pl011Console;
+
+/// The console device of the platform.
+pub(crate) static CONSOLE: Pl011Console = Pl011Console;
+
+/// All console ports, indexed by the port number. There is only one.
+pub(crate) static PORTS: [&dyn ConsoleDriver; 1] = [&CONSOLE];
+
+impl ConsoleDriver for Pl011Console {
+    fn init(&self) {
+        UART.lock().init();
+    }
+
+    fn write(&self, bytes: &[u8]) {
+        let _uart = UART.lock();
+        // Keep the order with bytes written before without blocking.
+        #[cfg(feature = "irq")]
+        if TX_QUEUE.is_enabled() {
+            send_queued(true);
+        }
+        regs::write_bytes(bytes);
+    }
+
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        #[cfg(feature = "irq")]
+        if RX_QUEUE.is_enabled() {
+            // Pick up the bytes received by DMA since the last interrupt.
+            if dma::is_enabled() {
+                fill_rx_queue(&mut UART.lock());
+            }
+            let len = RX_QUEUE.read(bytes);
+            if RX_QUEUE.should_resume() {
+                fill_rx_queue(&mut UART.lock());
+            }
+            return len;
+        }
+        let mut uart = UART.lock();
+        check_overrun();
+        let mut read_len = 0;
+        while read_len < bytes.len() {
+            match uart.getchar() {
+                Some(c) => bytes[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        read_len
+    }
+
+    #[cfg(feature = "irq")]
+    fn enable_rx_irq(&self) -> bool {
+        if !register_irq_handler() {
+            return false;
+        }
+        let mut uart = UART.lock();
+        if dma::start() {
+            regs::set_rx_interrupt(false);
+            RX_QUEUE.enable();
+            info!("UART receiving by DMA");
+            return true;
+        }
+        // Move the bytes received before to the queue.
+        fill_rx_queue(&mut uart);
+        RX_QUEUE.enable();
+        true
+    }
+
     #[cfg(feature = "irq")]
-    crate::irq::set_enable(crate::platform::irq::UART_IRQ_NUM, true);
+    fn write_nonblocking(&self, bytes: &[u8]) -> usize {
+        if TX_QUEUE.is_enabled() {
+            let len = TX_QUEUE.push(bytes);
+            let _uart = UART.lock();
+            send_queued(false);
+            return len;
+        }
+        self.write(bytes);
+        bytes.len()
+    }
+
+    #[cfg(feature = "irq")]
+    fn enable_tx_irq(&self) -> bool {
+        if !register_irq_handler() {
+            return false;
+        }
+        TX_QUEUE.enable();
+        true
+    }
+
+    /// Sends all queued bytes, or none if the UART is locked, e.g., by the
+    /// panicked code.
+    #[cfg(feature = "irq")]
+    fn flush(&self) {
+        if !TX_QUEUE.is_enabled() {
+            return;
+        }
+        if let Some(_uart) = UART.try_lock() {
+            send_queued(true);
+        }
+    }
+}
+
+/// Moves received bytes to [`RX_QUEUE`], disabling the receive interrupts
+/// while it is full. Must be called with [`UART`] locked.
+#[cfg(feature = "irq")]
+fn fill_rx_queue(uart: &mut Pl011Uart) {
+    if dma::is_enabled() {
+        dma::fill_rx_queue();
+        return;
+    }
+    check_overrun();
+    let full = RX_QUEUE.fill(|| uart.getchar());
+    regs::set_rx_interrupt(!full);
+}
+
+/// Sends bytes from [`TX_QUEUE`], until it is empty if `block` is `true`,
+/// otherwise as many as the transmit FIFO can hold. Must be called with
+/// [`UART`] locked.
+///
+/// The transmit interrupt is enabled as long as the queue is not empty.
+#[cfg(feature = "irq")]
+fn send_queued(block: bool) {
+    loop {
+        regs::fill_tx_fifo(|| TX_QUEUE.pop());
+        if !block || TX_QUEUE.is_empty() {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    regs::set_tx_interrupt(!TX_QUEUE.is_empty());
+}
+
+/// Registers the UART IRQ handler, if not yet.
+#[cfg(feature = "irq")]
+fn register_irq_handler() -> bool {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+    if REGISTERED.swap(true, Ordering::AcqRel) {
+        return true;
+    }
+    if !crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, &|_| handle()) {
+        REGISTERED.store(false, Ordering::Release);
+        return false;
+    }
+    true
 }
 
-/// UART IRQ Handler
-pub fn handle() {
-    let is_receive_interrupt = UART.lock().is_receive_interrupt();
-    UART.lock().ack_interrupts();
+/// UART IRQ Handler, moves all received bytes to [`RX_QUEUE`], and sends
+/// bytes from [`TX_QUEUE`].
+#[cfg(feature = "irq")]
+fn handle() {
+    let mut uart = UART.lock();
+    let is_receive_interrupt = uart.is_receive_interrupt();
+    uart.ack_interrupts();
     if is_receive_interrupt {
-        while let Some(c) = getchar() {
-            putchar(c);
+        fill_rx_queue(&mut uart);
+    }
+    if TX_QUEUE.is_enabled() {
+        send_queued(false);
+    }
+}
+
+/// Writes a byte to the UART at its physical address by polling, without
+/// locks, see [`crate::earlycon`].
+#[cfg(feature = "earlycon")]
+pub(crate) fn early_putchar(c: u8) {
+    use core::ptr::{read_volatile, write_volatile};
+
+    const DR: usize = 0x00;
+    const FR: usize = 0x18;
+    const FR_TXFF: u32 = 1 << 5;
+
+    let base = UART_BASE.as_usize();
+    unsafe {
+        while read_volatile((base + FR) as *const u32) & FR_TXFF != 0 {
+            core::hint::spin_loop();
         }
+        write_volatile((base + DR) as *mut u32, c as u32);
     }
 }
@@ -0,0 +1,26 @@
+//! ARM semihosting calls, used to report the exit code to QEMU.
+//!
+//! QEMU must be started with `-semihosting`, otherwise the `hlt` instruction
+//! causes an undefined instruction exception.
+
+/// `SYS_EXIT` operation number.
+const SYS_EXIT: usize = 0x18;
+/// `ADP_Stopped_ApplicationExit` reason code.
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x20026;
+
+/// Exits QEMU with the given exit code.
+pub fn qemu_exit(code: i32) -> ! {
+    info!("Exiting QEMU with code {}...", code);
+    // On AArch64, the parameter of `SYS_EXIT` is a pointer to a two-field
+    // block containing the reason code and the exit code.
+    let block = [ADP_STOPPED_APPLICATION_EXIT, code as usize];
+    unsafe {
+        core::arch::asm!(
+            "hlt #0xf000",
+            in("x0") SYS_EXIT,
+            in("x1") block.as_ptr(),
+        )
+    };
+    warn!("It should exit QEMU!");
+    super::psci::system_off()
+}
@@ -17,6 +17,8 @@ pub mod time {
 }
 
 pub mod misc {
+    pub use crate::platform::aarch64_common::psci::system_reset as reset;
+
     pub fn terminate() -> ! {
         info!("Shutting down...");
         loop {
@@ -25,6 +27,8 @@ pub mod misc {
     }
 }
 
+use crate::console::ConsoleDriver;
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize);
     #[cfg(feature = "smp")]
@@ -36,7 +40,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     let cpu_id = cpu_hard_id_to_logic_id(cpu_id);
     axcpu::init::init_trap();
     crate::cpu::init_primary(cpu_id);
-    super::aarch64_common::pl011::init_early();
+    super::aarch64_common::pl011::CONSOLE.init();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
 }
@@ -56,7 +60,11 @@ pub fn platform_init() {
     #[cfg(feature = "irq")]
     super::aarch64_common::gic::init_primary();
     super::aarch64_common::generic_timer::init_percpu();
-    super::aarch64_common::pl011::init();
+    #[cfg(feature = "irq")]
+    {
+        super::aarch64_common::pl011::CONSOLE.enable_rx_irq();
+        super::aarch64_common::pl011::CONSOLE.enable_tx_irq();
+    }
 }
 
 /// Initializes the platform devices for secondary CPUs.
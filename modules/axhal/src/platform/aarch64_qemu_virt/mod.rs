@@ -18,8 +18,12 @@ pub mod time {
 
 pub mod misc {
     pub use crate::platform::aarch64_common::psci::system_off as terminate;
+    pub use crate::platform::aarch64_common::psci::system_reset as reset;
+    pub use crate::platform::aarch64_common::semihosting::qemu_exit;
 }
 
+use crate::console::ConsoleDriver;
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize);
     #[cfg(feature = "smp")]
@@ -30,8 +34,9 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     axcpu::init::init_trap();
     crate::cpu::init_primary(cpu_id);
-    super::aarch64_common::pl011::init_early();
+    super::aarch64_common::pl011::CONSOLE.init();
     super::aarch64_common::generic_timer::init_early();
+    super::fdt::init_cmdline(dtb);
     rust_main(cpu_id, dtb);
 }
 
@@ -49,7 +54,11 @@ pub fn platform_init() {
     #[cfg(feature = "irq")]
     super::aarch64_common::gic::init_primary();
     super::aarch64_common::generic_timer::init_percpu();
-    super::aarch64_common::pl011::init();
+    #[cfg(feature = "irq")]
+    {
+        super::aarch64_common::pl011::CONSOLE.enable_rx_irq();
+        super::aarch64_common::pl011::CONSOLE.enable_tx_irq();
+    }
 }
 
 /// Initializes the platform devices for secondary CPUs.
@@ -3,6 +3,9 @@ pub mod mem;
 #[cfg(feature = "smp")]
 pub mod mp;
 
+#[cfg(feature = "usb-acm")]
+mod usb_acm;
+
 #[cfg(feature = "irq")]
 pub mod irq {
     pub use crate::platform::aarch64_common::gic::*;
@@ -25,6 +28,8 @@ pub mod misc {
     }
 }
 
+use crate::console::ConsoleDriver;
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize);
     #[cfg(feature = "smp")]
@@ -35,7 +40,7 @@ pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
     axcpu::init::init_trap();
     crate::cpu::init_primary(cpu_id);
-    super::aarch64_common::pl011::init_early();
+    super::aarch64_common::pl011::CONSOLE.init();
     super::aarch64_common::generic_timer::init_early();
     rust_main(cpu_id, dtb);
 }
@@ -54,7 +59,13 @@ pub fn platform_init() {
     #[cfg(feature = "irq")]
     super::aarch64_common::gic::init_primary();
     super::aarch64_common::generic_timer::init_percpu();
-    super::aarch64_common::pl011::init();
+    #[cfg(feature = "irq")]
+    {
+        super::aarch64_common::pl011::CONSOLE.enable_rx_irq();
+        super::aarch64_common::pl011::CONSOLE.enable_tx_irq();
+    }
+    #[cfg(feature = "usb-acm")]
+    usb_acm::init();
 }
 
 /// Initializes the platform devices for secondary CPUs.
@@ -0,0 +1,671 @@
+//! USB serial console: a CDC-ACM function on the USB OTG controller (DWC2)
+//! of the Raspberry Pi 4, i.e., its USB-C port, in device mode.
+//!
+//! It is added as a console sink, so the host sees a serial port (e.g.,
+//! `/dev/ttyACM0` on Linux) carrying the console output and input, without
+//! a USB-to-serial adapter. The output is dropped until the host opens the
+//! port (sets DTR), and while it does not read it, so the kernel does not
+//! block without a host.
+//!
+//! The CPU moves the packets through the FIFOs of the controller (its slave
+//! mode), from its interrupt handler with the `irq` feature, so the host is
+//! answered, e.g., enumerates the device, and the input is received while
+//! the console is not used. Without the `irq` feature, the controller is
+//! polled from the reads and writes of the console instead.
+//!
+//! QEMU emulates this controller in host mode only (on `raspi4b`, as on
+//! `raspi3b`), where it is detected and the function is not added. Running
+//! with `USB=y` checks the kernel still boots on the UART console then.
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+#[cfg(feature = "irq")]
+use axconfig::devices::USB_OTG_IRQ;
+use axconfig::devices::USB_OTG_PADDR;
+use kspin::SpinNoIrq;
+
+use crate::console::{ConsoleDriver, ConsoleSource};
+use crate::mem::phys_to_virt;
+use crate::time::{busy_wait, monotonic_time_nanos};
+
+/// Registers of the core.
+const GAHBCFG: usize = 0x008;
+const GUSBCFG: usize = 0x00c;
+const GRSTCTL: usize = 0x010;
+const GINTSTS: usize = 0x014;
+const GINTMSK: usize = 0x018;
+const GRXSTSP: usize = 0x020;
+const GRXFSIZ: usize = 0x024;
+const GNPTXFSIZ: usize = 0x028;
+const DIEPTXF1: usize = 0x104;
+/// Registers of the device mode.
+const DCFG: usize = 0x800;
+const DCTL: usize = 0x804;
+const PCGCCTL: usize = 0xe00;
+
+const fn diepctl(ep: usize) -> usize {
+    0x900 + 0x20 * ep
+}
+const fn dieptsiz(ep: usize) -> usize {
+    0x910 + 0x20 * ep
+}
+const fn doepctl(ep: usize) -> usize {
+    0xb00 + 0x20 * ep
+}
+const fn doeptsiz(ep: usize) -> usize {
+    0xb10 + 0x20 * ep
+}
+const fn fifo(ep: usize) -> usize {
+    0x1000 * (ep + 1)
+}
+
+const GUSBCFG_TRDT_SHIFT: u32 = 10;
+const GUSBCFG_TRDT_MASK: u32 = 0xf << GUSBCFG_TRDT_SHIFT;
+const GUSBCFG_FORCE_HOST: u32 = 1 << 29;
+const GUSBCFG_FORCE_DEVICE: u32 = 1 << 30;
+
+const GRSTCTL_CSFTRST: u32 = 1 << 0;
+const GRSTCTL_RXFFLSH: u32 = 1 << 4;
+const GRSTCTL_TXFFLSH: u32 = 1 << 5;
+/// All the transmit FIFOs, in GRSTCTL.TxFNum.
+const GRSTCTL_TXFNUM_ALL: u32 = 0x10 << 6;
+const GRSTCTL_AHB_IDLE: u32 = 1 << 31;
+
+#[cfg(feature = "irq")]
+const GAHBCFG_GLBL_INTR_EN: u32 = 1 << 0;
+
+/// The controller is in host mode.
+const GINTSTS_CURMOD_HOST: u32 = 1 << 0;
+const GINTSTS_RXFLVL: u32 = 1 << 4;
+const GINTSTS_USBRST: u32 = 1 << 12;
+const GINTSTS_ENUMDONE: u32 = 1 << 13;
+
+/// Packet status in GRXSTSP.
+const PKTSTS_OUT_DATA: u32 = 2;
+const PKTSTS_OUT_DONE: u32 = 3;
+const PKTSTS_SETUP_DONE: u32 = 4;
+const PKTSTS_SETUP_DATA: u32 = 6;
+
+/// Full speed with the UTMI+ PHY, so the bulk packets are of 64 bytes.
+const DCFG_DEVSPD_FS: u32 = 1;
+const DCFG_DEVADDR_SHIFT: u32 = 4;
+const DCFG_DEVADDR_MASK: u32 = 0x7f << DCFG_DEVADDR_SHIFT;
+
+const DCTL_SFT_DISCON: u32 = 1 << 1;
+
+const EPCTL_MPS_MASK: u32 = 0x7ff;
+const EPCTL_USBAEP: u32 = 1 << 15;
+const EPCTL_TYPE_SHIFT: u32 = 18;
+const EPCTL_STALL: u32 = 1 << 21;
+const EPCTL_TXFNUM_SHIFT: u32 = 22;
+const EPCTL_CNAK: u32 = 1 << 26;
+const EPCTL_SNAK: u32 = 1 << 27;
+const EPCTL_SD0PID: u32 = 1 << 28;
+const EPCTL_EPENA: u32 = 1 << 31;
+
+const EPTYPE_BULK: u32 = 2;
+const EPTYPE_INTERRUPT: u32 = 3;
+
+const EPTSIZ_PKTCNT_SHIFT: u32 = 19;
+/// The number of SETUP packets EP0 can receive back to back.
+const EPTSIZ_SUPCNT_3: u32 = 3 << 29;
+
+/// The sizes of the FIFOs, in words.
+const RX_FIFO_WORDS: u32 = 256;
+const EP0_TX_FIFO_WORDS: u32 = 64;
+const DATA_TX_FIFO_WORDS: u32 = 128;
+const NOTIFY_TX_FIFO_WORDS: u32 = 16;
+
+/// The bulk IN endpoint, which has the transmit FIFO of the same number.
+const DATA_IN_EP: usize = 1;
+const DATA_OUT_EP: usize = 2;
+/// The interrupt IN endpoint of the notifications, which are never sent.
+const NOTIFY_EP: usize = 3;
+const NUM_EPS: usize = 4;
+
+const MAX_PACKET: usize = 64;
+const NOTIFY_MAX_PACKET: usize = 16;
+
+/// How long the host is waited for to take a packet, before the output is
+/// dropped until it does.
+const TX_TIMEOUT_NANOS: u64 = 10_000_000;
+/// How long the controller is waited for on reset.
+const RESET_TIMEOUT_NANOS: u64 = 100_000_000;
+
+/// Standard requests.
+const GET_STATUS: u8 = 0;
+const CLEAR_FEATURE: u8 = 1;
+const SET_ADDRESS: u8 = 5;
+const GET_DESCRIPTOR: u8 = 6;
+const GET_CONFIGURATION: u8 = 8;
+const SET_CONFIGURATION: u8 = 9;
+const GET_INTERFACE: u8 = 10;
+const SET_INTERFACE: u8 = 11;
+/// Requests of the CDC-ACM class.
+const SET_LINE_CODING: u8 = 0x20;
+const GET_LINE_CODING: u8 = 0x21;
+const SET_CONTROL_LINE_STATE: u8 = 0x22;
+const SEND_BREAK: u8 = 0x23;
+
+const DESC_DEVICE: u16 = 1;
+const DESC_CONFIGURATION: u16 = 2;
+const DESC_STRING: u16 = 3;
+
+/// The vendor ID of pid.codes, with their test product ID.
+const VENDOR_ID: u16 = 0x1209;
+const PRODUCT_ID: u16 = 0x0001;
+
+#[rustfmt::skip]
+const DEVICE_DESC: [u8; 18] = [
+    18, DESC_DEVICE as u8,
+    0x00, 0x02,                 // USB 2.0
+    0x02, 0, 0,                 // Communications device class
+    MAX_PACKET as u8,
+    VENDOR_ID as u8, (VENDOR_ID >> 8) as u8,
+    PRODUCT_ID as u8, (PRODUCT_ID >> 8) as u8,
+    0x00, 0x01,                 // Device release 1.0
+    1, 2, 0,                    // Manufacturer, product, no serial number
+    1,                          // Configurations
+];
+
+#[rustfmt::skip]
+const CONFIG_DESC: [u8; 67] = [
+    // Configuration: 2 interfaces, bus-powered, 100 mA.
+    9, DESC_CONFIGURATION as u8, 67, 0, 2, 1, 0, 0x80, 50,
+    // Interface 0: communications, abstract control model.
+    9, 4, 0, 0, 1, 0x02, 0x02, 0x00, 0,
+    // Header functional descriptor: CDC 1.10.
+    5, 0x24, 0x00, 0x10, 0x01,
+    // Call management: none, data interface 1.
+    5, 0x24, 0x01, 0x00, 1,
+    // Abstract control management: line coding and control line state.
+    4, 0x24, 0x02, 0x02,
+    // Union: interface 0 controls interface 1.
+    5, 0x24, 0x06, 0, 1,
+    // Notification endpoint: interrupt IN.
+    7, 5, 0x80 | NOTIFY_EP as u8, 0x03, NOTIFY_MAX_PACKET as u8, 0, 255,
+    // Interface 1: data.
+    9, 4, 1, 0, 2, 0x0a, 0, 0, 0,
+    // Data endpoints: bulk IN and OUT.
+    7, 5, 0x80 | DATA_IN_EP as u8, 0x02, MAX_PACKET as u8, 0, 0,
+    7, 5, DATA_OUT_EP as u8, 0x02, MAX_PACKET as u8, 0, 0,
+];
+
+/// The supported languages of the strings: US English.
+const LANGUAGES_DESC: [u8; 4] = [4, 3, 0x09, 0x04];
+const STRINGS: [&str; 2] = ["ArceOS", "ArceOS Console"];
+
+const RX_BUFFER_SIZE: usize = 512;
+
+fn reg(offset: usize) -> *mut u32 {
+    (phys_to_virt(pa!(USB_OTG_PADDR)).as_usize() + offset) as *mut u32
+}
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { read_volatile(reg(offset)) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { write_volatile(reg(offset), value) }
+}
+
+fn modify_reg(offset: usize, clear: u32, set: u32) {
+    write_reg(offset, (read_reg(offset) & !clear) | set);
+}
+
+/// Waits until `cond` holds, for at most `timeout_nanos`.
+fn wait_until(timeout_nanos: u64, cond: impl Fn() -> bool) -> bool {
+    let deadline = monotonic_time_nanos() + timeout_nanos;
+    while !cond() {
+        if monotonic_time_nanos() > deadline {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    true
+}
+
+/// Why the controller could not be set up.
+enum InitError {
+    Timeout,
+    /// The controller stays in host mode, e.g., under QEMU.
+    HostMode,
+}
+
+/// Resets the core and sets it up in device mode, then connects to the host.
+fn init_controller() -> Result<(), InitError> {
+    write_reg(PCGCCTL, 0);
+    let idle = || read_reg(GRSTCTL) & GRSTCTL_AHB_IDLE != 0;
+    if !wait_until(RESET_TIMEOUT_NANOS, idle) {
+        return Err(InitError::Timeout);
+    }
+    write_reg(GRSTCTL, GRSTCTL_CSFTRST);
+    if !wait_until(RESET_TIMEOUT_NANOS, || {
+        read_reg(GRSTCTL) & GRSTCTL_CSFTRST == 0
+    }) || !wait_until(RESET_TIMEOUT_NANOS, idle)
+    {
+        return Err(InitError::Timeout);
+    }
+
+    // The turnaround time of the 8-bit UTMI+ PHY. Forcing the mode takes
+    // effect after 25 ms.
+    modify_reg(
+        GUSBCFG,
+        GUSBCFG_FORCE_HOST | GUSBCFG_TRDT_MASK,
+        GUSBCFG_FORCE_DEVICE | 9 << GUSBCFG_TRDT_SHIFT,
+    );
+    busy_wait(Duration::from_millis(25));
+    if read_reg(GINTSTS) & GINTSTS_CURMOD_HOST != 0 {
+        return Err(InitError::HostMode);
+    }
+    modify_reg(DCTL, 0, DCTL_SFT_DISCON);
+    write_reg(DCFG, DCFG_DEVSPD_FS);
+
+    // The receive FIFO, then the transmit FIFOs of EP0, of the data and of
+    // the notifications, given by their start and depth.
+    let mut start = RX_FIFO_WORDS;
+    write_reg(GRXFSIZ, RX_FIFO_WORDS);
+    write_reg(GNPTXFSIZ, EP0_TX_FIFO_WORDS << 16 | start);
+    start += EP0_TX_FIFO_WORDS;
+    write_reg(DIEPTXF1, DATA_TX_FIFO_WORDS << 16 | start);
+    start += DATA_TX_FIFO_WORDS;
+    write_reg(DIEPTXF1 + 4, NOTIFY_TX_FIFO_WORDS << 16 | start);
+
+    write_reg(GRSTCTL, GRSTCTL_TXFFLSH | GRSTCTL_TXFNUM_ALL);
+    if !wait_until(RESET_TIMEOUT_NANOS, || {
+        read_reg(GRSTCTL) & GRSTCTL_TXFFLSH == 0
+    }) {
+        return Err(InitError::Timeout);
+    }
+    write_reg(GRSTCTL, GRSTCTL_RXFFLSH);
+    if !wait_until(RESET_TIMEOUT_NANOS, || {
+        read_reg(GRSTCTL) & GRSTCTL_RXFFLSH == 0
+    }) {
+        return Err(InitError::Timeout);
+    }
+
+    // Slave mode, i.e., no DMA, and the interrupt is enabled once its
+    // handler is registered, see `enable_irq`.
+    write_reg(GAHBCFG, 0);
+    write_reg(GINTMSK, GINTSTS_RXFLVL | GINTSTS_USBRST | GINTSTS_ENUMDONE);
+    write_reg(GINTSTS, !0);
+    modify_reg(DCTL, DCTL_SFT_DISCON, 0);
+    Ok(())
+}
+
+/// Reads a packet of `buf.len()` bytes from the receive FIFO.
+fn read_fifo(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(4) {
+        let word = read_reg(fifo(0)).to_le_bytes();
+        chunk.copy_from_slice(&word[..chunk.len()]);
+    }
+}
+
+/// Writes a packet to the transmit FIFO of the endpoint.
+fn write_fifo(ep: usize, data: &[u8]) {
+    for chunk in data.chunks(4) {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        write_reg(fifo(ep), u32::from_le_bytes(word));
+    }
+}
+
+/// Starts an IN transfer of `data` on the endpoint.
+fn start_in(ep: usize, data: &[u8]) {
+    let packets = data.len().div_ceil(MAX_PACKET).max(1) as u32;
+    write_reg(
+        dieptsiz(ep),
+        packets << EPTSIZ_PKTCNT_SHIFT | data.len() as u32,
+    );
+    modify_reg(diepctl(ep), 0, EPCTL_EPENA | EPCTL_CNAK);
+    write_fifo(ep, data);
+}
+
+/// Makes EP0 ready to receive the next SETUP or OUT packet.
+fn arm_ep0_out() {
+    write_reg(
+        doeptsiz(0),
+        EPTSIZ_SUPCNT_3 | 1 << EPTSIZ_PKTCNT_SHIFT | MAX_PACKET as u32,
+    );
+    modify_reg(doepctl(0), 0, EPCTL_EPENA | EPCTL_CNAK);
+}
+
+/// Makes the bulk OUT endpoint ready to receive a packet.
+fn arm_data_out() {
+    write_reg(
+        doeptsiz(DATA_OUT_EP),
+        1 << EPTSIZ_PKTCNT_SHIFT | MAX_PACKET as u32,
+    );
+    modify_reg(doepctl(DATA_OUT_EP), 0, EPCTL_EPENA | EPCTL_CNAK);
+}
+
+/// The state of the CDC-ACM function.
+struct UsbAcm {
+    /// Whether the host set the configuration, enabling the data endpoints.
+    configured: bool,
+    /// Whether the host opened the port, i.e., set DTR.
+    dtr: bool,
+    /// Whether the host did not take a packet in time, so the output is
+    /// dropped until it does.
+    tx_stalled: bool,
+    /// The last SETUP packet received.
+    setup: [u8; 8],
+    /// The line coding set by the host, which is only reported back.
+    line_coding: [u8; 7],
+    /// Whether the data stage of SET_LINE_CODING is expected on EP0.
+    expect_line_coding: bool,
+    /// Whether the bulk OUT endpoint is left NAKing, as `rx` is full.
+    rx_paused: bool,
+    rx: [u8; RX_BUFFER_SIZE],
+    rx_head: usize,
+    rx_len: usize,
+}
+
+impl UsbAcm {
+    const fn new() -> Self {
+        Self {
+            configured: false,
+            dtr: false,
+            tx_stalled: false,
+            setup: [0; 8],
+            // 115200 bauds, 1 stop bit, no parity, 8 data bits.
+            line_coding: [0x00, 0xc2, 0x01, 0x00, 0, 0, 8],
+            expect_line_coding: false,
+            rx_paused: false,
+            rx: [0; RX_BUFFER_SIZE],
+            rx_head: 0,
+            rx_len: 0,
+        }
+    }
+
+    /// Processes the events of the controller and the received packets.
+    fn poll(&mut self) {
+        let status = read_reg(GINTSTS);
+        if status & GINTSTS_USBRST != 0 {
+            write_reg(GINTSTS, GINTSTS_USBRST);
+            self.reset();
+        }
+        if status & GINTSTS_ENUMDONE != 0 {
+            write_reg(GINTSTS, GINTSTS_ENUMDONE);
+            // The maximum packet size of EP0 is encoded as 0 for 64 bytes.
+            modify_reg(diepctl(0), EPCTL_MPS_MASK, 0);
+            arm_ep0_out();
+        }
+        while read_reg(GINTSTS) & GINTSTS_RXFLVL != 0 {
+            self.receive();
+        }
+        self.resume_rx();
+    }
+
+    /// Makes the bulk OUT endpoint receive again, if it was paused and `rx`
+    /// has room for a packet.
+    fn resume_rx(&mut self) {
+        if self.rx_paused && RX_BUFFER_SIZE - self.rx_len >= MAX_PACKET {
+            self.rx_paused = false;
+            arm_data_out();
+        }
+    }
+
+    fn reset(&mut self) {
+        for ep in 0..NUM_EPS {
+            modify_reg(doepctl(ep), 0, EPCTL_SNAK);
+        }
+        modify_reg(DCFG, DCFG_DEVADDR_MASK, 0);
+        self.configured = false;
+        self.dtr = false;
+        self.tx_stalled = false;
+        self.expect_line_coding = false;
+        self.rx_paused = false;
+    }
+
+    /// Pops a packet from the receive FIFO.
+    fn receive(&mut self) {
+        let status = read_reg(GRXSTSP);
+        let ep = (status & 0xf) as usize;
+        let len = ((status >> 4) & 0x7ff) as usize;
+        let mut buf = [0; MAX_PACKET];
+        let buf = &mut buf[..len.min(MAX_PACKET)];
+        match (status >> 17) & 0xf {
+            PKTSTS_SETUP_DATA => {
+                read_fifo(buf);
+                if buf.len() == self.setup.len() {
+                    self.setup.copy_from_slice(buf);
+                }
+            }
+            PKTSTS_SETUP_DONE => {
+                self.handle_setup();
+                arm_ep0_out();
+            }
+            PKTSTS_OUT_DATA => {
+                read_fifo(buf);
+                if ep == 0 && self.expect_line_coding && buf.len() == self.line_coding.len() {
+                    self.expect_line_coding = false;
+                    self.line_coding.copy_from_slice(buf);
+                    start_in(0, &[]);
+                } else if ep == DATA_OUT_EP {
+                    self.push_rx(buf);
+                }
+            }
+            PKTSTS_OUT_DONE if ep == 0 => arm_ep0_out(),
+            PKTSTS_OUT_DONE if ep == DATA_OUT_EP => {
+                if RX_BUFFER_SIZE - self.rx_len >= MAX_PACKET {
+                    arm_data_out();
+                } else {
+                    self.rx_paused = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_setup(&mut self) {
+        let setup = self.setup;
+        let (request_type, request) = (setup[0], setup[1]);
+        let value = u16::from_le_bytes([setup[2], setup[3]]);
+        let length = u16::from_le_bytes([setup[6], setup[7]]) as usize;
+        let reply = |data: &[u8]| start_in(0, &data[..data.len().min(length)]);
+        match (request_type, request) {
+            (0x80, GET_STATUS) => reply(&[0, 0]),
+            (0x80, GET_DESCRIPTOR) => match (value >> 8, value as u8) {
+                (DESC_DEVICE, _) => reply(&DEVICE_DESC),
+                (DESC_CONFIGURATION, _) => reply(&CONFIG_DESC),
+                (DESC_STRING, 0) => reply(&LANGUAGES_DESC),
+                (DESC_STRING, index @ 1..=2) => {
+                    let s = STRINGS[index as usize - 1];
+                    let mut desc = [0; 2 + 2 * 16];
+                    desc[0] = (2 + 2 * s.len()) as u8;
+                    desc[1] = DESC_STRING as u8;
+                    for (i, c) in s.bytes().enumerate() {
+                        desc[2 + 2 * i] = c;
+                    }
+                    reply(&desc[..desc[0] as usize]);
+                }
+                _ => stall_ep0(),
+            },
+            (0x00, SET_ADDRESS) => {
+                modify_reg(
+                    DCFG,
+                    DCFG_DEVADDR_MASK,
+                    (value as u32) << DCFG_DEVADDR_SHIFT & DCFG_DEVADDR_MASK,
+                );
+                reply(&[]);
+            }
+            (0x00, SET_CONFIGURATION) => {
+                self.configured = value == 1;
+                if self.configured {
+                    configure_endpoints();
+                }
+                reply(&[]);
+            }
+            (0x80, GET_CONFIGURATION) => reply(&[self.configured as u8]),
+            (0x81, GET_INTERFACE) => reply(&[0]),
+            (0x00..=0x02, CLEAR_FEATURE) | (0x01, SET_INTERFACE) => reply(&[]),
+            (0x21, SET_LINE_CODING) => self.expect_line_coding = true,
+            (0xa1, GET_LINE_CODING) => reply(&self.line_coding),
+            (0x21, SET_CONTROL_LINE_STATE) => {
+                self.dtr = value & 1 != 0;
+                self.tx_stalled = false;
+                reply(&[]);
+            }
+            (0x21, SEND_BREAK) => reply(&[]),
+            _ => stall_ep0(),
+        }
+    }
+
+    fn push_rx(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.rx_len == RX_BUFFER_SIZE {
+                crate::console::count_rx_dropped(1);
+                continue;
+            }
+            self.rx[(self.rx_head + self.rx_len) % RX_BUFFER_SIZE] = b;
+            self.rx_len += 1;
+        }
+    }
+
+    fn pop_rx(&mut self, bytes: &mut [u8]) -> usize {
+        let len = bytes.len().min(self.rx_len);
+        for b in &mut bytes[..len] {
+            *b = self.rx[self.rx_head];
+            self.rx_head = (self.rx_head + 1) % RX_BUFFER_SIZE;
+        }
+        self.rx_len -= len;
+        len
+    }
+}
+
+/// Rejects the request, until the next SETUP packet.
+fn stall_ep0() {
+    modify_reg(diepctl(0), 0, EPCTL_STALL);
+    modify_reg(doepctl(0), 0, EPCTL_STALL);
+}
+
+fn configure_endpoints() {
+    write_reg(
+        diepctl(DATA_IN_EP),
+        EPCTL_USBAEP
+            | EPCTL_SD0PID
+            | EPTYPE_BULK << EPCTL_TYPE_SHIFT
+            | (DATA_IN_EP as u32) << EPCTL_TXFNUM_SHIFT
+            | MAX_PACKET as u32,
+    );
+    write_reg(
+        diepctl(NOTIFY_EP),
+        EPCTL_USBAEP
+            | EPCTL_SD0PID
+            | EPTYPE_INTERRUPT << EPCTL_TYPE_SHIFT
+            | 2 << EPCTL_TXFNUM_SHIFT
+            | NOTIFY_MAX_PACKET as u32,
+    );
+    write_reg(
+        doepctl(DATA_OUT_EP),
+        EPCTL_USBAEP | EPCTL_SD0PID | EPTYPE_BULK << EPCTL_TYPE_SHIFT | MAX_PACKET as u32,
+    );
+    arm_data_out();
+}
+
+struct UsbAcmConsole(SpinNoIrq<UsbAcm>);
+
+/// Whether the controller is serviced by its interrupt handler, rather than
+/// polled by the console.
+static IRQ_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Polls the controller, unless its interrupt handler does.
+fn poll_if_needed(acm: &mut UsbAcm) {
+    if !IRQ_ENABLED.load(Ordering::Acquire) {
+        acm.poll();
+    }
+}
+
+impl ConsoleDriver for UsbAcmConsole {
+    fn write(&self, bytes: &[u8]) {
+        for packet in bytes.chunks(MAX_PACKET) {
+            let deadline = monotonic_time_nanos() + TX_TIMEOUT_NANOS;
+            loop {
+                let mut acm = self.0.lock();
+                poll_if_needed(&mut acm);
+                if !acm.configured || !acm.dtr {
+                    return;
+                }
+                if read_reg(diepctl(DATA_IN_EP)) & EPCTL_EPENA == 0 {
+                    acm.tx_stalled = false;
+                    start_in(DATA_IN_EP, packet);
+                    break;
+                }
+                if acm.tx_stalled || monotonic_time_nanos() > deadline {
+                    acm.tx_stalled = true;
+                    return;
+                }
+                drop(acm);
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        let mut acm = self.0.lock();
+        poll_if_needed(&mut acm);
+        let len = acm.pop_rx(bytes);
+        acm.resume_rx();
+        len
+    }
+}
+
+static CONSOLE: UsbAcmConsole = UsbAcmConsole(SpinNoIrq::new(UsbAcm::new()));
+
+/// Handles the interrupt of the controller: answers the host, and receives
+/// the input, waking up the tasks waiting for it.
+#[cfg(feature = "irq")]
+fn handle_irq() {
+    let mut acm = CONSOLE.0.lock();
+    let rx_len = acm.rx_len;
+    acm.poll();
+    let received = acm.rx_len > rx_len;
+    drop(acm);
+    if received {
+        crate::console::notify_rx();
+    }
+}
+
+/// Registers the interrupt handler of the controller, and enables its
+/// interrupt, returns whether it is registered.
+#[cfg(feature = "irq")]
+fn enable_irq() -> bool {
+    use arm_gicv2::{InterruptType, translate_irq};
+
+    const IRQ_NUM: usize = translate_irq(USB_OTG_IRQ, InterruptType::SPI).unwrap();
+
+    if !crate::irq::register_handler(IRQ_NUM, &|_| handle_irq()) {
+        return false;
+    }
+    IRQ_ENABLED.store(true, Ordering::Release);
+    modify_reg(GAHBCFG, 0, GAHBCFG_GLBL_INTR_EN);
+    true
+}
+
+/// Sets up the OTG controller in device mode and adds the CDC-ACM function
+/// as a console sink.
+pub fn init() {
+    match init_controller() {
+        Ok(()) => {}
+        Err(InitError::Timeout) => {
+            warn!("USB OTG controller timed out on reset");
+            return;
+        }
+        Err(InitError::HostMode) => {
+            warn!("USB OTG controller can not switch to device mode");
+            return;
+        }
+    }
+    #[cfg(feature = "irq")]
+    if !enable_irq() {
+        warn!("USB serial console: polled, the IRQ handler can not be registered");
+    }
+    if crate::console::add_sink(&CONSOLE, ConsoleSource::all()).is_none() {
+        warn!("USB serial console: too many console sinks");
+        return;
+    }
+    info!("USB serial console on the OTG port");
+}
@@ -0,0 +1,110 @@
+//! Console backed by a pair of in-memory byte channels.
+//!
+//! Bytes written by the kernel are stored in the output channel and can be
+//! drained by tests with [`take_output`]. Bytes pushed with [`push_input`]
+//! are returned by [`read_bytes`](crate::console::read_bytes).
+
+use kspin::SpinNoIrq;
+
+use crate::console::ConsoleDriver;
+
+const CHANNEL_SIZE: usize = 4096;
+
+struct Channel {
+    buf: [u8; CHANNEL_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl Channel {
+    const fn new() -> Self {
+        Self {
+            buf: [0; CHANNEL_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends a byte, dropping the oldest one if the channel is full.
+    /// Returns `true` if a byte is dropped.
+    fn push(&mut self, c: u8) -> bool {
+        let full = self.len == CHANNEL_SIZE;
+        if full {
+            self.head = (self.head + 1) % CHANNEL_SIZE;
+            self.len -= 1;
+        }
+        self.buf[(self.head + self.len) % CHANNEL_SIZE] = c;
+        self.len += 1;
+        full
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % CHANNEL_SIZE;
+        self.len -= 1;
+        Some(c)
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> usize {
+        let mut read_len = 0;
+        while read_len < bytes.len() {
+            match self.pop() {
+                Some(c) => bytes[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        read_len
+    }
+}
+
+static INPUT: SpinNoIrq<Channel> = SpinNoIrq::new(Channel::new());
+static OUTPUT: SpinNoIrq<Channel> = SpinNoIrq::new(Channel::new());
+
+/// The console device backed by the in-memory channels.
+pub(crate) struct DummyConsole;
+
+/// The console device of the platform.
+pub(crate) static CONSOLE: DummyConsole = DummyConsole;
+
+/// All console ports, indexed by the port number. There is only one.
+pub(crate) static PORTS: [&dyn ConsoleDriver; 1] = [&CONSOLE];
+
+impl ConsoleDriver for DummyConsole {
+    fn write(&self, bytes: &[u8]) {
+        let mut output = OUTPUT.lock();
+        for &c in bytes {
+            output.push(c);
+        }
+    }
+
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        INPUT.lock().read(bytes)
+    }
+}
+
+/// Feeds bytes to the console input, as if they were typed by the user.
+///
+/// If the input channel is full, the oldest bytes are dropped.
+pub fn push_input(bytes: &[u8]) {
+    let mut input = INPUT.lock();
+    let dropped = bytes.iter().filter(|&&c| input.push(c)).count();
+    crate::console::count_rx_dropped(dropped);
+}
+
+/// Drains the bytes written to the console into the given mutable slice.
+/// Returns the number of bytes read.
+///
+/// Only the last 4096 bytes written are kept.
+pub fn take_output(bytes: &mut [u8]) -> usize {
+    OUTPUT.lock().read(bytes)
+}
+
+/// Writes a byte to the output channel, see [`crate::earlycon`].
+#[cfg(feature = "earlycon")]
+pub(crate) fn early_putchar(c: u8) {
+    OUTPUT.lock().push(c);
+}
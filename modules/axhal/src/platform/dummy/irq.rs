@@ -0,0 +1,121 @@
+//! A software interrupt controller.
+//!
+//! IRQs are raised by [`raise`]. An enabled IRQ is dispatched immediately on
+//! the calling thread; a disabled one is kept pending until it is enabled.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::irq::IrqHandler;
+
+/// The maximum number of IRQs.
+pub const MAX_IRQ_COUNT: usize = 256;
+
+/// The timer IRQ number.
+pub const TIMER_IRQ_NUM: usize = 0;
+
+const WORDS: usize = MAX_IRQ_COUNT / 64;
+
+static ENABLED: [AtomicU64; WORDS] = [const { AtomicU64::new(0) }; WORDS];
+static PENDING: [AtomicU64; WORDS] = [const { AtomicU64::new(0) }; WORDS];
+
+fn bit(irq_num: usize) -> (usize, u64) {
+    (irq_num / 64, 1 << (irq_num % 64))
+}
+
+/// Enables or disables the given IRQ.
+///
+/// Enabling an IRQ dispatches it if it is pending.
+pub fn set_enable(irq_num: usize, enabled: bool) {
+    if irq_num >= MAX_IRQ_COUNT {
+        return;
+    }
+    let (word, mask) = bit(irq_num);
+    if enabled {
+        ENABLED[word].fetch_or(mask, Ordering::AcqRel);
+        if PENDING[word].fetch_and(!mask, Ordering::AcqRel) & mask != 0 {
+            dispatch_irq(irq_num);
+        }
+    } else {
+        ENABLED[word].fetch_and(!mask, Ordering::AcqRel);
+    }
+}
+
+/// Registers an IRQ handler for the given IRQ.
+pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
+    crate::irq::register_handler_common(irq_num, handler)
+}
+
+/// Routes an IRQ to the CPUs in `cpu_mask`, which is not supported on this
+/// platform.
+pub fn set_affinity(_irq_num: usize, _cpu_mask: usize) -> bool {
+    false
+}
+
+/// Sets the trigger mode and polarity of an IRQ, which is not supported on
+/// this platform.
+pub fn set_trigger(_irq_num: usize, _mode: crate::irq::TriggerMode) -> bool {
+    false
+}
+
+/// Sets the priority of an IRQ, which is not supported on this platform.
+pub fn set_priority(_irq_num: usize, _priority: u8) -> bool {
+    false
+}
+
+/// Sets the priority threshold of the current CPU, which is not supported
+/// on this platform.
+pub fn set_priority_threshold(_threshold: u8) -> bool {
+    false
+}
+
+/// Masks the IRQs with a lower priority than `irq_num`, which is not
+/// supported on this platform, so the IRQ handlers are not nested.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn raise_priority_mask(_irq_num: usize) -> Option<usize> {
+    None
+}
+
+/// Restores the priority mask, never called as it is not raised.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn restore_priority_mask(_saved: usize) {}
+
+/// Returns the IRQs which can be allocated to MSIs, none on this platform.
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    0..0
+}
+
+/// Returns the message of an MSI, never called as there are none.
+pub(crate) fn setup_msi(_dev: u32, _irq_num: usize) -> crate::irq::MsiMessage {
+    unreachable!("no MSIs on this platform")
+}
+
+/// Dispatches the IRQ.
+///
+/// This function is called by the common interrupt handler. It looks
+/// up in the IRQ handler table and calls the corresponding handler. If
+/// necessary, it also acknowledges the interrupt controller after handling.
+pub fn dispatch_irq(irq_num: usize) {
+    crate::irq::dispatch_irq_common(irq_num);
+}
+
+/// Raises the given IRQ, as if it were triggered by a device.
+///
+/// Returns `true` if the IRQ is dispatched, or `false` if it is left pending
+/// because it is disabled.
+pub fn raise(irq_num: usize) -> bool {
+    assert!(irq_num < MAX_IRQ_COUNT, "invalid IRQ number {}", irq_num);
+    let (word, mask) = bit(irq_num);
+    if ENABLED[word].load(Ordering::Acquire) & mask != 0 {
+        dispatch_irq(irq_num);
+        true
+    } else {
+        PENDING[word].fetch_or(mask, Ordering::AcqRel);
+        false
+    }
+}
+
+/// Returns whether the given IRQ is pending.
+pub fn is_pending(irq_num: usize) -> bool {
+    let (word, mask) = bit(irq_num);
+    irq_num < MAX_IRQ_COUNT && PENDING[word].load(Ordering::Acquire) & mask != 0
+}
@@ -1,18 +1,18 @@
+//! A platform that runs on the host, used for unit tests.
+//!
+//! Devices are emulated in memory: the console is backed by a pair of byte
+//! channels, the clock only advances when [`time::advance`] is called, and
+//! the software interrupt controller dispatches IRQs raised by
+//! [`irq::raise`].
+
 #![allow(unused_variables)]
 #![allow(dead_code)]
 
-pub mod console {
-    /// Writes bytes to the console from input u8 slice.
-    pub fn write_bytes(_bytes: &[u8]) {
-        unimplemented!()
-    }
+pub mod console;
+pub mod time;
 
-    /// Reads bytes from the console into the given mutable slice.
-    /// Returns the number of bytes read.
-    pub fn read_bytes(_bytes: &mut [u8]) -> usize {
-        unimplemented!()
-    }
-}
+#[cfg(feature = "irq")]
+pub mod irq;
 
 pub mod misc {
     /// Shutdown the whole system, including all CPUs.
@@ -34,57 +34,6 @@ pub mod mem {
     }
 }
 
-pub mod time {
-    /// Returns the current clock time in hardware ticks.
-    pub fn current_ticks() -> u64 {
-        0
-    }
-
-    /// Converts hardware ticks to nanoseconds.
-    pub fn ticks_to_nanos(ticks: u64) -> u64 {
-        ticks
-    }
-
-    /// Converts nanoseconds to hardware ticks.
-    pub fn nanos_to_ticks(nanos: u64) -> u64 {
-        nanos
-    }
-
-    /// Set a one-shot timer.
-    ///
-    /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
-    pub fn set_oneshot_timer(deadline_ns: u64) {}
-
-    /// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
-    pub fn epochoffset_nanos() -> u64 {
-        0
-    }
-}
-
-#[cfg(feature = "irq")]
-pub mod irq {
-    /// The maximum number of IRQs.
-    pub const MAX_IRQ_COUNT: usize = 256;
-
-    /// The timer IRQ number.
-    pub const TIMER_IRQ_NUM: usize = 0;
-
-    /// Enables or disables the given IRQ.
-    pub fn set_enable(irq_num: usize, enabled: bool) {}
-
-    /// Registers an IRQ handler for the given IRQ.
-    pub fn register_handler(irq_num: usize, handler: crate::irq::IrqHandler) -> bool {
-        false
-    }
-
-    /// Dispatches the IRQ.
-    ///
-    /// This function is called by the common interrupt handler. It looks
-    /// up in the IRQ handler table and calls the corresponding handler. If
-    /// necessary, it also acknowledges the interrupt controller after handling.
-    pub fn dispatch_irq(irq_num: usize) {}
-}
-
 /// Initializes the platform devices for the primary CPU.
 pub fn platform_init() {}
 
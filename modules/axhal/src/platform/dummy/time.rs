@@ -0,0 +1,60 @@
+//! A software clock that only advances when told to.
+//!
+//! One tick is one nanosecond. When the `irq` feature is enabled, advancing
+//! the clock past the deadline set by [`set_oneshot_timer`] raises the timer
+//! IRQ.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static NOW_NANOS: AtomicU64 = AtomicU64::new(0);
+static TIMER_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Returns the current clock time in hardware ticks.
+pub fn current_ticks() -> u64 {
+    NOW_NANOS.load(Ordering::Acquire)
+}
+
+/// Converts hardware ticks to nanoseconds.
+pub fn ticks_to_nanos(ticks: u64) -> u64 {
+    ticks
+}
+
+/// Converts nanoseconds to hardware ticks.
+pub fn nanos_to_ticks(nanos: u64) -> u64 {
+    nanos
+}
+
+/// Set a one-shot timer.
+///
+/// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
+pub fn set_oneshot_timer(deadline_ns: u64) {
+    TIMER_DEADLINE.store(deadline_ns, Ordering::Release);
+    #[cfg(feature = "irq")]
+    check_timer();
+}
+
+/// Return epoch offset in nanoseconds (wall time offset to monotonic clock start).
+pub fn epochoffset_nanos() -> u64 {
+    0
+}
+
+/// Advances the clock by the given number of nanoseconds.
+///
+/// If the one-shot timer expires, the timer IRQ is raised.
+pub fn advance(nanos: u64) {
+    NOW_NANOS.fetch_add(nanos, Ordering::AcqRel);
+    #[cfg(feature = "irq")]
+    check_timer();
+}
+
+#[cfg(feature = "irq")]
+fn check_timer() {
+    let deadline = TIMER_DEADLINE.load(Ordering::Acquire);
+    if current_ticks() >= deadline
+        && TIMER_DEADLINE
+            .compare_exchange(deadline, u64::MAX, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    {
+        super::irq::raise(super::irq::TIMER_IRQ_NUM);
+    }
+}
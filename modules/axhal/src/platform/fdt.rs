@@ -0,0 +1,159 @@
+//! A minimal reader of the flattened device tree (DTB) passed by the
+//! firmware, enough to find the interrupt controllers and the boot command
+//! line.
+//!
+//! See the [Devicetree Specification](https://devicetree-specification.readthedocs.io/en/stable/flattened-format.html)
+//! for the format.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// Returns the NUL-terminated string at the start of `bytes`.
+fn c_str(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+    &bytes[..len]
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// A flattened device tree.
+pub(super) struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl Fdt<'_> {
+    /// Reads the device tree at the given virtual address, returns `None` if
+    /// there is no valid device tree.
+    ///
+    /// # Safety
+    ///
+    /// `vaddr` must be mapped and readable, and the device tree must not be
+    /// modified while it is read.
+    pub unsafe fn from_ptr(vaddr: usize) -> Option<Self> {
+        if vaddr == 0 || vaddr % 4 != 0 {
+            return None;
+        }
+        let header = unsafe { core::slice::from_raw_parts(vaddr as *const u8, 40) };
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total_size = be32(header, 4)? as usize;
+        let blob = unsafe { core::slice::from_raw_parts(vaddr as *const u8, total_size) };
+        let field = |offset| be32(header, offset).map(|v| v as usize);
+        let (struct_off, strings_off) = (field(8)?, field(12)?);
+        let (strings_size, struct_size) = (field(32)?, field(36)?);
+        Some(Self {
+            structs: blob.get(struct_off..struct_off + struct_size)?,
+            strings: blob.get(strings_off..strings_off + strings_size)?,
+        })
+    }
+
+    /// Calls `f` on each node, in the order they appear in the tree.
+    pub fn for_each_node(&self, mut f: impl FnMut(&Node)) {
+        let mut offset = 0;
+        while let Some(token) = be32(self.structs, offset) {
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name_len = c_str(&self.structs[offset..]).len();
+                    offset = align4(offset + name_len + 1);
+                    f(&Node { fdt: self, offset });
+                }
+                FDT_PROP => {
+                    let Some(len) = be32(self.structs, offset) else {
+                        return;
+                    };
+                    offset = align4(offset + 8 + len as usize);
+                }
+                FDT_END_NODE | FDT_NOP => {}
+                _ => return,
+            }
+        }
+    }
+}
+
+/// A node of a device tree.
+pub(super) struct Node<'a> {
+    fdt: &'a Fdt<'a>,
+    /// The offset of the first property of the node in the structure block.
+    offset: usize,
+}
+
+impl Node<'_> {
+    /// Returns the value of the given property.
+    pub fn prop(&self, name: &str) -> Option<&[u8]> {
+        let structs = self.fdt.structs;
+        let mut offset = self.offset;
+        // The properties of a node come before its children.
+        loop {
+            match be32(structs, offset)? {
+                FDT_PROP => {
+                    let len = be32(structs, offset + 4)? as usize;
+                    let name_off = be32(structs, offset + 8)? as usize;
+                    let value = structs.get(offset + 12..offset + 12 + len)?;
+                    if c_str(self.fdt.strings.get(name_off..)?) == name.as_bytes() {
+                        return Some(value);
+                    }
+                    offset = align4(offset + 12 + len);
+                }
+                FDT_NOP => offset += 4,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the cells (big-endian `u32`s) of the given property.
+    pub fn cells(&self, name: &str) -> impl Iterator<Item = u32> + '_ {
+        self.prop(name)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+    }
+
+    /// Whether the node is compatible with the given model.
+    pub fn is_compatible(&self, model: &str) -> bool {
+        self.prop("compatible")
+            .unwrap_or_default()
+            .split(|&c| c == 0)
+            .any(|s| s == model.as_bytes())
+    }
+
+    /// Returns the address and size of the first region in the `reg`
+    /// property, assuming both are two cells as on QEMU `virt`.
+    pub fn reg(&self) -> Option<(usize, usize)> {
+        let mut cells = self.cells("reg").map(|cell| cell as usize);
+        let mut next = || Some((cells.next()? << 32) | cells.next()?);
+        Some((next()?, next()?))
+    }
+}
+
+/// Sets the boot command line to `bootargs` of the `/chosen` node of the
+/// device tree at the physical address `dtb`, if any.
+pub(super) fn init_cmdline(dtb: usize) {
+    if dtb == 0 {
+        return;
+    }
+    // SAFETY: the device tree is mapped at boot, and never modified.
+    let Some(fdt) = (unsafe { Fdt::from_ptr(crate::mem::phys_to_virt(pa!(dtb)).as_usize()) })
+    else {
+        return;
+    };
+    // Only `/chosen` has it.
+    fdt.for_each_node(|node| {
+        if let Some(bootargs) = node.prop("bootargs") {
+            crate::cmdline::set(bootargs);
+        }
+    });
+}
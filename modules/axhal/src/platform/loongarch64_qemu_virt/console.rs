@@ -1,43 +1,43 @@
+use crate::console::ConsoleDriver;
 use crate::mem::phys_to_virt;
-use kspin::SpinNoIrq;
-use lazyinit::LazyInit;
+use crate::platform::uart16550::{MmioIo, SerialPort};
 use memory_addr::PhysAddr;
-use ns16550a::Uart;
 
 const UART_BASE: PhysAddr = pa!(axconfig::devices::UART_PADDR);
 
-static UART: LazyInit<SpinNoIrq<Uart>> = LazyInit::new();
+/// The console device backed by the NS16550A UART, with its registers 1 byte
+/// apart.
+///
+/// The baud rate set by the firmware is kept, and there is no interrupt
+/// controller driver for its IRQ.
+pub(crate) static CONSOLE: SerialPort<MmioIo<1>> =
+    SerialPort::new(MmioIo::new(phys_to_virt(UART_BASE).as_usize()), 0, no_irq);
 
-/// Writes bytes to the console from input u8 slice.
-pub fn write_bytes(bytes: &[u8]) {
-    for &c in bytes {
-        let uart = UART.lock();
-        match c {
-            b'\n' => {
-                let _ = uart.put(b'\r');
-                let _ = uart.put(b'\n');
-            }
-            c => {
-                let _ = uart.put(c);
-            }
-        }
-    }
+fn no_irq() -> bool {
+    false
 }
 
-/// Reads bytes from the console into the given mutable slice.
-/// Returns the number of bytes read.
-pub fn read_bytes(bytes: &mut [u8]) -> usize {
-    for (i, byte) in bytes.iter_mut().enumerate() {
-        match UART.lock().get() {
-            Some(c) => *byte = c,
-            None => return i,
+/// All console ports, indexed by the port number. There is only one.
+pub(crate) static PORTS: [&dyn ConsoleDriver; 1] = [&CONSOLE];
+
+/// Writes a byte to the UART by polling, without locks, see
+/// [`crate::earlycon`].
+///
+/// The UART is accessed through the direct mapping window set up at boot,
+/// before [`CONSOLE`] is initialized.
+#[cfg(feature = "earlycon")]
+pub(crate) fn early_putchar(c: u8) {
+    use core::ptr::{read_volatile, write_volatile};
+
+    const THR: usize = 0;
+    const LSR: usize = 5;
+    const LSR_THRE: u8 = 1 << 5;
+
+    let base = phys_to_virt(UART_BASE).as_usize();
+    unsafe {
+        while read_volatile((base + LSR) as *const u8) & LSR_THRE == 0 {
+            core::hint::spin_loop();
         }
+        write_volatile((base + THR) as *mut u8, c);
     }
-    bytes.len()
-}
-
-/// Early stage initialization for ns16550a
-pub(super) fn init_early() {
-    let vaddr = phys_to_virt(UART_BASE);
-    UART.init_once(SpinNoIrq::new(Uart::new(vaddr.as_usize())));
 }
@@ -26,6 +26,50 @@ pub fn register_handler(irq_num: usize, handler: crate::irq::IrqHandler) -> bool
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Routes an IRQ to the CPUs in `cpu_mask`, which is not supported on this
+/// platform.
+pub fn set_affinity(_irq_num: usize, _cpu_mask: usize) -> bool {
+    false
+}
+
+/// Sets the trigger mode and polarity of an IRQ, which is not supported on
+/// this platform.
+pub fn set_trigger(_irq_num: usize, _mode: crate::irq::TriggerMode) -> bool {
+    false
+}
+
+/// Sets the priority of an IRQ, which is not supported on this platform.
+pub fn set_priority(_irq_num: usize, _priority: u8) -> bool {
+    false
+}
+
+/// Sets the priority threshold of the current CPU, which is not supported
+/// on this platform.
+pub fn set_priority_threshold(_threshold: u8) -> bool {
+    false
+}
+
+/// Masks the IRQs with a lower priority than `irq_num`, which is not
+/// supported on this platform, so the IRQ handlers are not nested.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn raise_priority_mask(_irq_num: usize) -> Option<usize> {
+    None
+}
+
+/// Restores the priority mask, never called as it is not raised.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn restore_priority_mask(_saved: usize) {}
+
+/// Returns the IRQs which can be allocated to MSIs, none on this platform.
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    0..0
+}
+
+/// Returns the message of an MSI, never called as there are none.
+pub(crate) fn setup_msi(_dev: u32, _irq_num: usize) -> crate::irq::MsiMessage {
+    unreachable!("no MSIs on this platform")
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -2,6 +2,10 @@ use crate::mem::phys_to_virt;
 use memory_addr::pa;
 
 const HALT_ADDR: *mut u8 = phys_to_virt(pa!(axconfig::devices::GED_PADDR)).as_mut_ptr();
+/// The reset register of the GED, next to the sleep control register.
+const RESET_ADDR: *mut u8 = HALT_ADDR.wrapping_add(2);
+/// The value to write to [`RESET_ADDR`] to reset the system.
+const RESET_VALUE: u8 = 0x42;
 
 /// Shutdown the whole system, including all CPUs.
 pub fn terminate() -> ! {
@@ -13,3 +17,14 @@ pub fn terminate() -> ! {
         axcpu::asm::halt();
     }
 }
+
+/// Resets the whole system, including all CPUs.
+pub fn reset() -> ! {
+    info!("Rebooting...");
+    unsafe { RESET_ADDR.write_volatile(RESET_VALUE) };
+    axcpu::asm::halt();
+    warn!("It should reboot!");
+    loop {
+        axcpu::asm::halt();
+    }
+}
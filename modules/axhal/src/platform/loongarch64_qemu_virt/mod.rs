@@ -9,8 +9,13 @@ pub mod misc;
 pub mod mp;
 pub mod time;
 
+use crate::console::ConsoleDriver;
+
 /// Initializes the platform devices for the primary CPU.
-pub fn platform_init() {}
+pub fn platform_init() {
+    #[cfg(feature = "pm")]
+    self::console::CONSOLE.register_pm("uart0");
+}
 
 /// Initializes the platform devices for secondary CPUs.
 #[cfg(feature = "smp")]
@@ -28,7 +33,7 @@ unsafe extern "C" {
 unsafe extern "C" fn rust_entry(cpu_id: usize) {
     crate::mem::clear_bss();
     axcpu::init::init_trap();
-    super::console::init_early();
+    self::console::CONSOLE.init();
     crate::cpu::init_primary(cpu_id);
     super::time::init_primary();
     super::time::init_percpu();
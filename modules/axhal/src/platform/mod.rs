@@ -6,6 +6,20 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(any(
+    all(target_arch = "x86_64", platform_family = "x86-pc"),
+    all(target_arch = "loongarch64", platform_family = "loongarch64-qemu-virt")
+))]
+mod uart16550;
+
+// Not all of it is used on every platform.
+#[allow(dead_code)]
+#[cfg(any(
+    all(target_arch = "riscv64", platform_family = "riscv64-qemu-virt"),
+    all(target_arch = "aarch64", platform_family = "aarch64-qemu-virt")
+))]
+mod fdt;
+
 cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))] {
         mod x86_pc;
@@ -0,0 +1,352 @@
+//! RISC-V Advanced Interrupt Architecture (AIA).
+//!
+//! Wired interrupts are handled by the APLIC of the supervisor domain, which
+//! either delivers them directly to the harts through its interrupt delivery
+//! controllers (IDCs), or forwards them as MSIs to the IMSICs of the harts.
+//! The MSI mode is used when the APLIC has a `msi-parent` in the device tree.
+//!
+//! Interrupts are numbered by their APLIC source numbers. In the MSI mode,
+//! source `n` is forwarded as the MSI with identity `n`, so the numbers are
+//! the same in both modes. Interrupts are sent to the primary hart, unless
+//! routed to another one with [`set_target`].
+//!
+//! In the MSI mode, the identities after the APLIC sources are allocated to
+//! the MSIs of PCI devices, which write them to the interrupt file of the
+//! primary hart.
+//!
+//! See the [RISC-V AIA specification](https://github.com/riscv/riscv-aia).
+
+use core::ops::Range;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazyinit::LazyInit;
+use memory_addr::PhysAddr;
+
+use crate::mem::phys_to_virt;
+use crate::platform::fdt::Fdt;
+
+// APLIC registers.
+const APLIC_DOMAINCFG: usize = 0x0000;
+const APLIC_SOURCECFG: usize = 0x0004;
+const APLIC_SETIENUM: usize = 0x1edc;
+const APLIC_CLRIENUM: usize = 0x1fdc;
+const APLIC_SETIPNUM_LE: usize = 0x2000;
+const APLIC_TARGET: usize = 0x3004;
+const APLIC_IDC: usize = 0x4000;
+const APLIC_IDC_SIZE: usize = 32;
+
+// Registers of the IDCs.
+const IDC_IDELIVERY: usize = 0x00;
+const IDC_ITHRESHOLD: usize = 0x08;
+const IDC_CLAIMI: usize = 0x1c;
+
+const DOMAINCFG_IE: u32 = 1 << 8;
+const DOMAINCFG_DM_MSI: u32 = 1 << 2;
+
+// The source modes, in `sourcecfg`.
+const SOURCECFG_EDGE_RISE: u32 = 4;
+const SOURCECFG_EDGE_FALL: u32 = 5;
+/// The source mode of level-sensitive interrupts, active high, which is
+/// what the devices of QEMU `virt` use.
+const SOURCECFG_LEVEL_HIGH: u32 = 6;
+const SOURCECFG_LEVEL_LOW: u32 = 7;
+
+const TARGET_HART_SHIFT: u32 = 18;
+/// The priority of interrupts in the direct mode. All are the same.
+const TARGET_IPRIO: u32 = 1;
+
+// Supervisor CSRs of the IMSIC, accessed indirectly through `siselect` and
+// `sireg`, except `stopei`.
+const IMSIC_EIDELIVERY: usize = 0x70;
+const IMSIC_EITHRESHOLD: usize = 0x72;
+const IMSIC_EIE0: usize = 0xc0;
+
+/// The size of the supervisor interrupt file of each hart, without guest
+/// interrupt files.
+const IMSIC_HART_SIZE: usize = 0x1000;
+
+/// How the APLIC delivers interrupts.
+#[derive(Debug, Clone, Copy)]
+enum DeliveryMode {
+    /// To the IDCs of the APLIC.
+    Direct,
+    /// As MSIs to the IMSICs, which support identities up to `num_ids`, and
+    /// whose supervisor interrupt files start at `imsic_base`.
+    Msi {
+        num_ids: usize,
+        imsic_base: PhysAddr,
+    },
+}
+
+struct Aplic {
+    base: PhysAddr,
+    num_sources: usize,
+    mode: DeliveryMode,
+}
+
+static APLIC: LazyInit<Aplic> = LazyInit::new();
+
+/// The hart all interrupts are sent to.
+static PRIMARY_HART: AtomicUsize = AtomicUsize::new(0);
+
+impl Aplic {
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (phys_to_virt(self.base).as_usize() + offset) as *mut u32
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile(self.reg(offset)) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile(self.reg(offset), value) }
+    }
+
+    fn idc(&self, hart_id: usize, offset: usize) -> usize {
+        APLIC_IDC + hart_id * APLIC_IDC_SIZE + offset
+    }
+
+    /// Sends an interrupt source to a hart.
+    fn set_target(&self, irq: usize, hart_id: usize) {
+        let hart = (hart_id as u32) << TARGET_HART_SHIFT;
+        let target = match self.mode {
+            DeliveryMode::Direct => hart | TARGET_IPRIO,
+            DeliveryMode::Msi { .. } => hart | irq as u32,
+        };
+        self.write(APLIC_TARGET + (irq - 1) * 4, target);
+    }
+}
+
+/// Accesses the IMSIC CSRs of the current hart.
+mod imsic {
+    use core::arch::asm;
+
+    fn select(reg: usize) {
+        unsafe { asm!("csrw 0x150, {}", in(reg) reg) }; // siselect
+    }
+
+    pub fn write(reg: usize, value: usize) {
+        select(reg);
+        unsafe { asm!("csrw 0x151, {}", in(reg) value) }; // sireg
+    }
+
+    /// Claims the pending interrupt with the highest priority, returns its
+    /// identity, or 0 if there is none.
+    pub fn claim() -> usize {
+        let topei: usize;
+        unsafe { asm!("csrrw {}, 0x15c, zero", out(reg) topei) }; // stopei
+        (topei >> 16) & 0x7ff
+    }
+}
+
+/// Finds the supervisor-level APLIC in the device tree, returns whether it
+/// is found.
+///
+/// The device tree must be readable at `dtb_vaddr`. It is only read here,
+/// so it can be overwritten afterwards.
+pub(super) fn probe(dtb_vaddr: usize) -> bool {
+    let Some(fdt) = (unsafe { Fdt::from_ptr(dtb_vaddr) }) else {
+        return false;
+    };
+    let mut aplic = None;
+    let mut imsic = None;
+    fdt.for_each_node(|node| {
+        if node.is_compatible("riscv,aplic") {
+            // The machine-level domain delegates interrupts to its children.
+            if node.prop("riscv,children").is_some() {
+                return;
+            }
+            let num_sources = node.cells("riscv,num-sources").next().unwrap_or(0) as usize;
+            let msi = node.prop("msi-parent").is_some();
+            aplic = node.reg().map(|(base, _)| (base, num_sources, msi));
+        } else if node.is_compatible("riscv,imsics") {
+            // `interrupts-extended` lists pairs of the parent and the local
+            // interrupt of each hart, 9 being the supervisor external one.
+            if node.cells("interrupts-extended").nth(1) == Some(9) {
+                let num_ids = node.cells("riscv,num-ids").next();
+                imsic = num_ids.zip(node.reg().map(|(base, _)| base));
+            }
+        }
+    });
+
+    let Some((base, num_sources, msi)) = aplic else {
+        return false;
+    };
+    let mut max_irq = super::irq::MAX_IRQ_COUNT - 1;
+    let mode = match (msi, imsic) {
+        (false, _) => DeliveryMode::Direct,
+        (true, Some((num_ids, imsic_base))) => {
+            // Sources beyond the identities of the IMSICs cannot be used.
+            max_irq = max_irq.min(num_ids as usize);
+            DeliveryMode::Msi {
+                num_ids: num_ids as usize,
+                imsic_base: pa!(imsic_base),
+            }
+        }
+        (true, None) => return false,
+    };
+    APLIC.init_once(Aplic {
+        base: pa!(base),
+        num_sources: num_sources.min(max_irq),
+        mode,
+    });
+    true
+}
+
+/// Initializes the APLIC, and the interrupt delivery of the primary hart.
+pub(super) fn init_primary() {
+    let Some(aplic) = APLIC.get() else {
+        return;
+    };
+    info!(
+        "Initialize APLIC at {:#x} ({} sources, {:?} mode)...",
+        aplic.base, aplic.num_sources, aplic.mode
+    );
+    let domaincfg = match aplic.mode {
+        DeliveryMode::Direct => 0,
+        DeliveryMode::Msi { .. } => DOMAINCFG_DM_MSI,
+    };
+    aplic.write(APLIC_DOMAINCFG, domaincfg);
+    PRIMARY_HART.store(crate::cpu::this_cpu_id(), Ordering::Relaxed);
+    for irq in 1..=aplic.num_sources {
+        aplic.write(APLIC_SOURCECFG + (irq - 1) * 4, SOURCECFG_LEVEL_HIGH);
+        aplic.set_target(irq, crate::cpu::this_cpu_id());
+    }
+    aplic.write(APLIC_DOMAINCFG, domaincfg | DOMAINCFG_IE);
+    init_percpu();
+}
+
+/// Initializes the interrupt delivery of the current hart.
+pub(super) fn init_percpu() {
+    let Some(aplic) = APLIC.get() else {
+        return;
+    };
+    match aplic.mode {
+        DeliveryMode::Direct => {
+            let hart_id = crate::cpu::this_cpu_id();
+            aplic.write(aplic.idc(hart_id, IDC_ITHRESHOLD), 0);
+            aplic.write(aplic.idc(hart_id, IDC_IDELIVERY), 1);
+        }
+        DeliveryMode::Msi { num_ids, .. } => {
+            // Interrupts are enabled or disabled in the APLIC, so enable all
+            // the identities here, which could only be done on each hart.
+            for reg in 0..num_ids.div_ceil(64) {
+                // On RV64, only the even-numbered `eie` registers exist.
+                imsic::write(IMSIC_EIE0 + reg * 2, usize::MAX);
+            }
+            imsic::write(IMSIC_EITHRESHOLD, 0);
+            imsic::write(IMSIC_EIDELIVERY, 1);
+        }
+    }
+}
+
+/// Whether the AIA is found by [`probe`].
+pub(super) fn is_present() -> bool {
+    APLIC.is_inited()
+}
+
+/// Enables or disables the given interrupt source.
+pub(super) fn set_enable(irq: usize, enabled: bool) {
+    let Some(aplic) = APLIC.get() else {
+        return;
+    };
+    if irq == 0 || irq > aplic.num_sources {
+        return;
+    }
+    let reg = if enabled {
+        APLIC_SETIENUM
+    } else {
+        APLIC_CLRIENUM
+    };
+    aplic.write(reg, irq as u32);
+}
+
+/// Sends the given interrupt source to the hart `hart_id`, returns whether
+/// the source exists.
+pub(super) fn set_target(irq: usize, hart_id: usize) -> bool {
+    let Some(aplic) = APLIC.get() else {
+        return false;
+    };
+    if irq == 0 || irq > aplic.num_sources {
+        return false;
+    }
+    aplic.set_target(irq, hart_id);
+    true
+}
+
+/// Sets the source mode of the given interrupt source, returns whether the
+/// source exists.
+pub(super) fn set_source_mode(irq: usize, mode: crate::irq::TriggerMode) -> bool {
+    use crate::irq::TriggerMode;
+
+    let Some(aplic) = APLIC.get() else {
+        return false;
+    };
+    if irq == 0 || irq > aplic.num_sources {
+        return false;
+    }
+    let sourcecfg = match mode {
+        TriggerMode::EdgeRising => SOURCECFG_EDGE_RISE,
+        TriggerMode::EdgeFalling => SOURCECFG_EDGE_FALL,
+        TriggerMode::LevelHigh => SOURCECFG_LEVEL_HIGH,
+        TriggerMode::LevelLow => SOURCECFG_LEVEL_LOW,
+    };
+    aplic.write(APLIC_SOURCECFG + (irq - 1) * 4, sourcecfg);
+    true
+}
+
+/// Returns the identities which can be allocated to MSIs, those after the
+/// APLIC sources in the MSI mode.
+pub(super) fn msi_irqs() -> Range<usize> {
+    match APLIC.get() {
+        Some(Aplic {
+            num_sources,
+            mode: DeliveryMode::Msi { num_ids, .. },
+            ..
+        }) => num_sources + 1..(num_ids + 1).min(super::irq::MAX_IRQ_COUNT),
+        _ => 0..0,
+    }
+}
+
+/// Returns the address of the supervisor interrupt file of the primary hart,
+/// which MSIs are written to.
+pub(super) fn msi_address() -> usize {
+    match APLIC.get().map(|aplic| aplic.mode) {
+        Some(DeliveryMode::Msi { imsic_base, .. }) => {
+            imsic_base.as_usize() + PRIMARY_HART.load(Ordering::Relaxed) * IMSIC_HART_SIZE
+        }
+        _ => 0,
+    }
+}
+
+/// Claims and handles all pending interrupts of the current hart, calling
+/// `f` with the number of each.
+pub(super) fn handle_irq(mut f: impl FnMut(usize)) {
+    let Some(aplic) = APLIC.get() else {
+        return;
+    };
+    match aplic.mode {
+        DeliveryMode::Direct => {
+            let claimi = aplic.idc(crate::cpu::this_cpu_id(), IDC_CLAIMI);
+            loop {
+                let irq = (aplic.read(claimi) >> 16) as usize & 0x3ff;
+                if irq == 0 {
+                    break;
+                }
+                f(irq);
+            }
+        }
+        DeliveryMode::Msi { .. } => loop {
+            let irq = imsic::claim();
+            if irq == 0 {
+                break;
+            }
+            f(irq);
+            // A level-sensitive source only sends an MSI when its input
+            // becomes high, so make it pending again if it is still high
+            // (see section 4.9.2 of the specification).
+            aplic.write(APLIC_SETIPNUM_LE, irq as u32);
+        },
+    }
+}
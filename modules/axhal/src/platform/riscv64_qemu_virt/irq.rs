@@ -1,5 +1,13 @@
-//! TODO: PLIC
+//! Interrupts of the supervisor mode.
+//!
+//! External interrupts are handled by the AIA (see [`super::aia`]) if the
+//! device tree has it, and are numbered by their APLIC source numbers, or
+//! else by the PLIC (see [`super::plic`]), and numbered by its source numbers.
+//!
+//! The priorities of the interrupts and the threshold of each hart are only
+//! supported by the PLIC.
 
+use super::{aia, plic};
 use crate::irq::IrqHandler;
 use lazyinit::LazyInit;
 use riscv::register::sie;
@@ -36,9 +44,75 @@ macro_rules! with_cause {
 }
 
 /// Enables or disables the given IRQ.
-pub fn set_enable(scause: usize, _enabled: bool) {
-    if scause == S_EXT {
-        // TODO: set enable in PLIC
+pub fn set_enable(irq_num: usize, enabled: bool) {
+    if irq_num >= MAX_IRQ_COUNT {
+        return;
+    }
+    if aia::is_present() {
+        aia::set_enable(irq_num, enabled);
+    } else {
+        plic::set_enable(irq_num, enabled);
+    }
+}
+
+/// Routes an external IRQ to the lowest hart in `cpu_mask`, in its APLIC
+/// target register, or to all the harts in `cpu_mask`, in their PLIC enable
+/// bits.
+///
+/// It returns `false` for the local interrupts.
+pub fn set_affinity(irq_num: usize, cpu_mask: usize) -> bool {
+    if irq_num >= MAX_IRQ_COUNT {
+        return false;
+    }
+    if aia::is_present() {
+        aia::set_target(irq_num, cpu_mask.trailing_zeros() as usize)
+    } else {
+        plic::set_affinity(irq_num, cpu_mask)
+    }
+}
+
+/// Sets the trigger mode and polarity of an external IRQ, in its APLIC
+/// source mode. A PLIC has no such setting.
+///
+/// It returns `false` if there is no AIA, or for the local interrupts.
+pub fn set_trigger(irq_num: usize, mode: crate::irq::TriggerMode) -> bool {
+    irq_num < MAX_IRQ_COUNT && aia::set_source_mode(irq_num, mode)
+}
+
+/// Sets the priority of an external IRQ, in its PLIC priority register.
+///
+/// It returns `false` if there is no PLIC, for the local interrupts, or if
+/// `priority` is out of range.
+pub fn set_priority(irq_num: usize, priority: u8) -> bool {
+    irq_num < MAX_IRQ_COUNT && plic::set_priority(irq_num, priority)
+}
+
+/// Sets the priority threshold of the current hart, in its PLIC context.
+///
+/// It returns `false` if there is no PLIC.
+pub fn set_priority_threshold(threshold: u8) -> bool {
+    plic::set_threshold(threshold)
+}
+
+/// Masks the external IRQs which cannot interrupt the handlers of `irq_num`.
+///
+/// The PLIC already masks the sources without a higher priority while one is
+/// handled (see [`plic::handle_irq`]). The AIA sources all have the same
+/// priority, so they are all masked: only the local interrupts (the timer)
+/// can interrupt their handlers.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn raise_priority_mask(_irq_num: usize) -> Option<usize> {
+    if aia::is_present() {
+        unsafe { sie::clear_sext() };
+    }
+    Some(0)
+}
+
+/// Unmasks the external IRQs masked by [`raise_priority_mask`].
+#[cfg(feature = "nested-irq")]
+pub(crate) fn restore_priority_mask(_saved: usize) {
+    if aia::is_present() {
+        unsafe { sie::set_sext() };
     }
 }
 
@@ -46,8 +120,8 @@ pub fn set_enable(scause: usize, _enabled: bool) {
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
-pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
-    match scause {
+pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
+    match irq_num {
         S_TIMER => {
             axlog::ax_println!("--------------riscv irq register timer handler here-----------------");
             if !TIMER_HANDLER.is_inited() {
@@ -59,8 +133,23 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
                 false
             }
         }
-        S_EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
-        _ => panic!("invalid trap cause: {:#x}", scause),
+        _ if irq_num < MAX_IRQ_COUNT => crate::irq::register_handler_common(irq_num, handler),
+        _ => panic!("invalid IRQ number: {:#x}", irq_num),
+    }
+}
+
+/// Returns the IRQs which can be allocated to MSIs, none unless the AIA is
+/// in the MSI mode.
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    aia::msi_irqs()
+}
+
+/// Returns the message of an MSI: its identity, written to the interrupt
+/// file of the primary hart.
+pub(crate) fn setup_msi(_dev: u32, irq_num: usize) -> crate::irq::MsiMessage {
+    crate::irq::MsiMessage {
+        address: aia::msi_address() as u64,
+        data: irq_num as u32,
     }
 }
 
@@ -74,13 +163,41 @@ pub fn dispatch_irq(scause: usize) {
         scause,
         @TIMER => {
             trace!("IRQ: timer");
-            TIMER_HANDLER();
+            TIMER_HANDLER(S_TIMER);
+        },
+        @EXT => if aia::is_present() {
+            aia::handle_irq(crate::irq::dispatch_irq_common);
+        } else {
+            plic::handle_irq(crate::irq::dispatch_irq_common);
         },
-        @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
     );
 }
 
-pub(super) fn init_percpu() {
+/// Finds the interrupt controllers in the device tree at `dtb_vaddr`.
+///
+/// It must be called before the memory of the device tree is reused.
+pub(super) fn init_early(dtb_vaddr: usize) {
+    if !aia::probe(dtb_vaddr) {
+        plic::probe(dtb_vaddr);
+    }
+}
+
+/// Initializes the interrupt controllers on the primary CPU.
+pub(super) fn init_primary() {
+    aia::init_primary();
+    plic::init_primary();
+    enable_local_irqs();
+}
+
+/// Initializes the interrupt delivery on secondary CPUs.
+#[cfg(feature = "smp")]
+pub(super) fn init_secondary() {
+    aia::init_percpu();
+    plic::init_percpu();
+    enable_local_irqs();
+}
+
+fn enable_local_irqs() {
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
         sie::set_ssoft();
@@ -1,3 +1,11 @@
+use crate::mem::phys_to_virt;
+
+const SIFIVE_TEST: *mut u32 =
+    phys_to_virt(pa!(axconfig::devices::SIFIVE_TEST_PADDR)).as_mut_ptr() as _;
+
+const FINISHER_FAIL: u32 = 0x3333;
+const FINISHER_PASS: u32 = 0x5555;
+
 /// Shutdown the whole system, including all CPUs.
 pub fn terminate() -> ! {
     info!("Shutting down...");
@@ -7,3 +15,25 @@ pub fn terminate() -> ! {
         axcpu::asm::halt();
     }
 }
+
+/// Resets the whole system, including all CPUs.
+pub fn reset() -> ! {
+    info!("Rebooting...");
+    sbi_rt::system_reset(sbi_rt::ColdReboot, sbi_rt::NoReason);
+    warn!("It should reboot!");
+    loop {
+        axcpu::asm::halt();
+    }
+}
+
+/// Exits QEMU with the given exit code, using the `sifive_test` device.
+pub fn qemu_exit(code: i32) -> ! {
+    info!("Exiting QEMU with code {}...", code);
+    let value = if code == 0 {
+        FINISHER_PASS
+    } else {
+        ((code as u32) << 16) | FINISHER_FAIL
+    };
+    unsafe { SIFIVE_TEST.write_volatile(value) };
+    terminate()
+}
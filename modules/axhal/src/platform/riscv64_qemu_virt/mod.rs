@@ -1,5 +1,10 @@
 mod boot;
 
+#[cfg(feature = "irq")]
+mod aia;
+#[cfg(feature = "irq")]
+mod plic;
+
 pub mod console;
 pub mod mem;
 pub mod misc;
@@ -22,6 +27,9 @@ unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     axcpu::init::init_trap();
     crate::cpu::init_primary(cpu_id);
     self::time::init_early();
+    super::fdt::init_cmdline(dtb);
+    #[cfg(feature = "irq")]
+    self::irq::init_early(crate::mem::phys_to_virt(pa!(dtb)).as_usize());
     rust_main(cpu_id, dtb);
 }
 
@@ -37,7 +45,7 @@ unsafe extern "C" fn rust_entry_secondary(cpu_id: usize) {
 /// For example, the interrupt controller and the timer.
 pub fn platform_init() {
     #[cfg(feature = "irq")]
-    self::irq::init_percpu();
+    self::irq::init_primary();
     self::time::init_percpu();
 }
 
@@ -45,6 +53,6 @@ pub fn platform_init() {
 #[cfg(feature = "smp")]
 pub fn platform_init_secondary() {
     #[cfg(feature = "irq")]
-    self::irq::init_percpu();
+    self::irq::init_secondary();
     self::time::init_percpu();
 }
@@ -0,0 +1,307 @@
+//! RISC-V Platform-Level Interrupt Controller (PLIC).
+//!
+//! It is used when the device tree has no AIA (see [`super::aia`]), which is
+//! the default of QEMU `virt`. Interrupts are numbered by their PLIC source
+//! numbers, and are sent to the primary hart, unless routed to other harts
+//! with [`set_affinity`]: each is enabled in the supervisor contexts of the
+//! harts it is routed to, and the first of them to claim it handles it.
+//!
+//! Each source has a priority, from 1 to [`MAX_PRIORITY`], and each context
+//! a threshold: only the sources with a priority above it are delivered to
+//! the hart. While an interrupt is handled, the threshold of the hart is
+//! raised to its priority, see [`handle_irq`], so only the interrupts with a
+//! higher priority can nest, if the handler enables the interrupts.
+//!
+//! See the [RISC-V PLIC specification](https://github.com/riscv/riscv-plic-spec).
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::PhysAddr;
+
+use super::irq::MAX_IRQ_COUNT;
+use crate::irq::{DEFAULT_PRIORITY, MAX_PRIORITY};
+use crate::mem::phys_to_virt;
+use crate::platform::fdt::Fdt;
+
+// PLIC registers.
+const PLIC_PRIORITY: usize = 0x00_0000;
+const PLIC_ENABLE: usize = 0x00_2000;
+const PLIC_ENABLE_SIZE: usize = 0x80;
+const PLIC_CONTEXT: usize = 0x20_0000;
+const PLIC_CONTEXT_SIZE: usize = 0x1000;
+
+// Registers of the contexts.
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+/// The local interrupt of the contexts of the supervisor mode, in
+/// `interrupts-extended` (the supervisor external interrupt).
+const S_EXT_LOCAL_IRQ: u32 = 9;
+
+struct Plic {
+    base: PhysAddr,
+    num_sources: usize,
+    /// The supervisor context of each hart.
+    contexts: [Option<usize>; axconfig::SMP],
+    /// The routing of the sources. Its lock also serializes the updates of
+    /// the enable bits, which are shared by the sources in the same register.
+    routing: SpinNoIrq<Routing>,
+}
+
+/// Which sources are enabled, and the harts they are sent to.
+struct Routing {
+    enabled: [bool; MAX_IRQ_COUNT],
+    /// The harts each source is sent to, bit `n` being hart `n`, or 0 for
+    /// the primary hart.
+    affinity: [usize; MAX_IRQ_COUNT],
+}
+
+static PLIC: LazyInit<Plic> = LazyInit::new();
+
+/// The hart all interrupts are sent to.
+static PRIMARY_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the PLIC, and the supervisor context of the current hart.
+fn this_context() -> Option<(&'static Plic, usize)> {
+    let plic = PLIC.get()?;
+    Some((plic, plic.this_context()?))
+}
+
+impl Plic {
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (phys_to_virt(self.base).as_usize() + offset) as *mut u32
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile(self.reg(offset)) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile(self.reg(offset), value) }
+    }
+
+    fn context_reg(&self, context: usize, offset: usize) -> usize {
+        PLIC_CONTEXT + context * PLIC_CONTEXT_SIZE + offset
+    }
+
+    fn has_source(&self, irq: usize) -> bool {
+        irq != 0 && irq <= self.num_sources
+    }
+
+    /// Returns the supervisor context of the current hart.
+    fn this_context(&self) -> Option<usize> {
+        *self.contexts.get(crate::cpu::this_cpu_id())?
+    }
+
+    /// Enables or disables a source in a context. The lock of the routing
+    /// must be held.
+    fn set_enable(&self, context: usize, irq: usize, enabled: bool) {
+        let reg = PLIC_ENABLE + context * PLIC_ENABLE_SIZE + irq / 32 * 4;
+        let mask = 1 << (irq % 32);
+        let value = self.read(reg);
+        let value = if enabled { value | mask } else { value & !mask };
+        self.write(reg, value);
+    }
+
+    /// Enables a source in the contexts of the harts it is sent to, if it is
+    /// enabled, and disables it in the others.
+    fn apply_routing(&self, routing: &Routing, irq: usize) {
+        let harts = match routing.affinity[irq] {
+            0 => 1 << PRIMARY_HART.load(Ordering::Relaxed),
+            harts => harts,
+        };
+        for (hart, context) in self.contexts.iter().enumerate() {
+            if let Some(context) = *context {
+                let selected = harts.checked_shr(hart as u32).is_some_and(|h| h & 1 != 0);
+                self.set_enable(context, irq, routing.enabled[irq] && selected);
+            }
+        }
+    }
+}
+
+/// Finds the PLIC in the device tree, returns whether it is found.
+///
+/// The device tree must be readable at `dtb_vaddr`. It is only read here,
+/// so it can be overwritten afterwards.
+pub(super) fn probe(dtb_vaddr: usize) -> bool {
+    let Some(fdt) = (unsafe { Fdt::from_ptr(dtb_vaddr) }) else {
+        return false;
+    };
+    let mut plic = None;
+    fdt.for_each_node(|node| {
+        if !node.is_compatible("sifive,plic-1.0.0") && !node.is_compatible("riscv,plic0") {
+            return;
+        }
+        let num_sources = node.cells("riscv,ndev").next().unwrap_or(0) as usize;
+        // `interrupts-extended` lists pairs of the parent and the local
+        // interrupt of each context. The supervisor contexts are those of
+        // the harts, in order.
+        let mut contexts = [None; axconfig::SMP];
+        let s_contexts = node
+            .cells("interrupts-extended")
+            .skip(1)
+            .step_by(2)
+            .enumerate()
+            .filter(|&(_, irq)| irq == S_EXT_LOCAL_IRQ)
+            .map(|(context, _)| context);
+        for (slot, context) in contexts.iter_mut().zip(s_contexts) {
+            *slot = Some(context);
+        }
+        plic = node.reg().map(|(base, _)| Plic {
+            base: pa!(base),
+            num_sources: num_sources.min(super::irq::MAX_IRQ_COUNT - 1),
+            contexts,
+            routing: SpinNoIrq::new(Routing {
+                enabled: [false; MAX_IRQ_COUNT],
+                affinity: [0; MAX_IRQ_COUNT],
+            }),
+        });
+    });
+
+    let Some(plic) = plic else {
+        return false;
+    };
+    PLIC.init_once(plic);
+    true
+}
+
+/// Initializes the PLIC, and the interrupt delivery of the primary hart.
+pub(super) fn init_primary() {
+    let Some(plic) = PLIC.get() else {
+        return;
+    };
+    info!(
+        "Initialize PLIC at {:#x} ({} sources)...",
+        plic.base, plic.num_sources
+    );
+    PRIMARY_HART.store(crate::cpu::this_cpu_id(), Ordering::Relaxed);
+    for irq in 1..=plic.num_sources {
+        plic.write(PLIC_PRIORITY + irq * 4, DEFAULT_PRIORITY as u32);
+    }
+    init_percpu();
+}
+
+/// Initializes the interrupt delivery of the current hart, which takes all
+/// the interrupts enabled in its context.
+pub(super) fn init_percpu() {
+    if let Some((plic, context)) = this_context() {
+        plic.write(plic.context_reg(context, CONTEXT_THRESHOLD), 0);
+    }
+}
+
+/// Whether the PLIC is found by [`probe`].
+pub(super) fn is_present() -> bool {
+    PLIC.is_inited()
+}
+
+/// Enables or disables the given interrupt source, in the contexts of the
+/// harts it is sent to.
+pub(super) fn set_enable(irq: usize, enabled: bool) {
+    let Some(plic) = PLIC.get() else {
+        return;
+    };
+    if !plic.has_source(irq) {
+        return;
+    }
+    let mut routing = plic.routing.lock();
+    routing.enabled[irq] = enabled;
+    plic.apply_routing(&routing, irq);
+}
+
+/// Sends the given interrupt source to the harts in `hart_mask`, bit `n`
+/// being hart `n`, returns whether the source exists.
+pub(super) fn set_affinity(irq: usize, hart_mask: usize) -> bool {
+    let Some(plic) = PLIC.get() else {
+        return false;
+    };
+    if !plic.has_source(irq) {
+        return false;
+    }
+    let mut routing = plic.routing.lock();
+    routing.affinity[irq] = hart_mask;
+    plic.apply_routing(&routing, irq);
+    true
+}
+
+/// Sets the priority of the given interrupt source, from 1 to
+/// [`MAX_PRIORITY`], returns whether the source exists.
+pub(super) fn set_priority(irq: usize, priority: u8) -> bool {
+    let Some(plic) = PLIC.get() else {
+        return false;
+    };
+    if !plic.has_source(irq) || !(1..=MAX_PRIORITY).contains(&priority) {
+        return false;
+    }
+    plic.write(PLIC_PRIORITY + irq * 4, priority as u32);
+    true
+}
+
+/// Returns the priority of the given interrupt source.
+pub(super) fn priority(irq: usize) -> u8 {
+    match PLIC.get() {
+        Some(plic) if plic.has_source(irq) => plic.read(PLIC_PRIORITY + irq * 4) as u8,
+        _ => 0,
+    }
+}
+
+/// Sets the threshold of the current hart: only the sources with a higher
+/// priority are delivered to it. Returns whether the hart has a context.
+pub(super) fn set_threshold(threshold: u8) -> bool {
+    let Some((plic, context)) = this_context() else {
+        return false;
+    };
+    plic.write(
+        plic.context_reg(context, CONTEXT_THRESHOLD),
+        threshold.min(MAX_PRIORITY) as u32,
+    );
+    true
+}
+
+/// Returns the threshold of the current hart.
+pub(super) fn threshold() -> u8 {
+    match this_context() {
+        Some((plic, context)) => plic.read(plic.context_reg(context, CONTEXT_THRESHOLD)) as u8,
+        None => 0,
+    }
+}
+
+/// Claims the pending interrupt of the current hart with the highest
+/// priority, returns its number, or 0 if there is none.
+///
+/// It is not delivered again until it is completed with [`complete`].
+pub(super) fn claim() -> usize {
+    match this_context() {
+        Some((plic, context)) => plic.read(plic.context_reg(context, CONTEXT_CLAIM)) as usize,
+        None => 0,
+    }
+}
+
+/// Completes an interrupt claimed with [`claim`] on the current hart.
+pub(super) fn complete(irq: usize) {
+    if let Some((plic, context)) = this_context() {
+        plic.write(plic.context_reg(context, CONTEXT_CLAIM), irq as u32);
+    }
+}
+
+/// Claims and handles all pending interrupts of the current hart, calling
+/// `f` with the number of each.
+///
+/// While `f` runs, the threshold of the hart is raised to the priority of
+/// the interrupt, so it can only be interrupted by a source with a higher
+/// priority.
+pub(super) fn handle_irq(mut f: impl FnMut(usize)) {
+    loop {
+        let irq = claim();
+        if irq == 0 {
+            break;
+        }
+        let threshold = threshold();
+        set_threshold(priority(irq).max(threshold));
+        f(irq);
+        set_threshold(threshold);
+        complete(irq);
+    }
+}
@@ -0,0 +1,557 @@
+//! Generic driver of 16550-compatible UARTs.
+//!
+//! The registers are accessed through a [`UartIo`], so the same driver is
+//! used with the I/O ports of the PC serial ports ([`PortIo`]) and with
+//! memory-mapped UARTs with their registers `STRIDE` bytes apart
+//! ([`MmioIo`]).
+//!
+//! With the `pm` feature, a port can be registered for runtime power
+//! management ([`SerialPort::register_pm`]): when it is idle, it is put in the
+//! sleep mode of 16750-compatible UARTs, which stops their clocks. Plain
+//! 16550s ignore it.
+
+#[cfg(feature = "pm")]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::console::ConsoleDriver;
+
+const UART_CLOCK_FACTOR: usize = 16;
+
+/// Receive buffer (read), transmit holding (write), divisor latch low
+/// (with DLAB).
+const DATA: usize = 0;
+/// Interrupt enable, divisor latch high (with DLAB).
+const INT_EN: usize = 1;
+/// Interrupt identification (read), FIFO control (write).
+const FIFO_CTRL: usize = 2;
+const LINE_CTRL: usize = 3;
+const MODEM_CTRL: usize = 4;
+const LINE_STS: usize = 5;
+/// Scratch, to detect whether the chip is present.
+const SCRATCH: usize = 7;
+
+bitflags::bitflags! {
+    /// Line status flags
+    struct LineStsFlags: u8 {
+        const INPUT_FULL = 1;
+        const OVERRUN_ERROR = 1 << 1;
+        // 2 to 4 unknown
+        const OUTPUT_EMPTY = 1 << 5;
+        /// The transmit FIFO and shift register are both empty.
+        const TRANSMITTER_EMPTY = 1 << 6;
+        // 7 unknown
+    }
+}
+
+/// Access to the registers of a UART, by their index.
+pub(crate) trait UartIo {
+    fn read(&self, reg: usize) -> u8;
+    fn write(&self, reg: usize, value: u8);
+}
+
+/// Registers at consecutive I/O ports.
+#[cfg(target_arch = "x86_64")]
+pub(crate) struct PortIo {
+    base: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl PortIo {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl UartIo for PortIo {
+    fn read(&self, reg: usize) -> u8 {
+        use x86_64::instructions::port::PortReadOnly;
+        unsafe { PortReadOnly::new(self.base + reg as u16).read() }
+    }
+
+    fn write(&self, reg: usize, value: u8) {
+        use x86_64::instructions::port::PortWriteOnly;
+        unsafe { PortWriteOnly::new(self.base + reg as u16).write(value) }
+    }
+}
+
+/// Memory-mapped registers, `STRIDE` bytes apart, and accessed by bytes.
+#[allow(dead_code)]
+pub(crate) struct MmioIo<const STRIDE: usize> {
+    base: usize,
+}
+
+#[allow(dead_code)]
+impl<const STRIDE: usize> MmioIo<STRIDE> {
+    /// Creates the access to the registers at the virtual address `base`.
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+}
+
+impl<const STRIDE: usize> UartIo for MmioIo<STRIDE> {
+    fn read(&self, reg: usize) -> u8 {
+        unsafe { core::ptr::read_volatile((self.base + reg * STRIDE) as *const u8) }
+    }
+
+    fn write(&self, reg: usize, value: u8) {
+        unsafe { core::ptr::write_volatile((self.base + reg * STRIDE) as *mut u8, value) }
+    }
+}
+
+struct Uart16550<IO> {
+    io: IO,
+    /// The frequency of the input clock, or `0` to keep the baud rate set by
+    /// the firmware.
+    clock_freq: usize,
+    /// The value of the write-only IER.
+    int_flags: u8,
+    /// Whether the UART is in sleep mode.
+    #[cfg(feature = "pm")]
+    asleep: bool,
+}
+
+impl<IO: UartIo> Uart16550<IO> {
+    const fn new(io: IO, clock_freq: usize) -> Self {
+        Self {
+            io,
+            clock_freq,
+            int_flags: 0,
+            #[cfg(feature = "pm")]
+            asleep: false,
+        }
+    }
+
+    /// 串口设置
+    ///
+    /// - baud_rate波特率用来协调串口通信
+    fn init(&mut self, baud_rate: usize) {
+        // Disable interrupts, 禁用中断
+        self.io.write(INT_EN, 0x00);
+        self.int_flags = 0;
+
+        if self.clock_freq != 0 {
+            // Enable DLAB
+            self.io.write(LINE_CTRL, 0x80);
+
+            // Set maximum speed according the input baud rate by configuring DLL and DLM
+            let divisor = self.clock_freq / (baud_rate * UART_CLOCK_FACTOR);
+            self.io.write(DATA, (divisor & 0xff) as u8);
+            self.io.write(INT_EN, (divisor >> 8) as u8);
+        }
+
+        // Disable DLAB and set data word length to 8 bits
+        // 设定数据长度为1个字节
+        self.io.write(LINE_CTRL, 0x03);
+
+        // Enable FIFO, clear TX/RX queues and
+        // set interrupt watermark at 14 bytes
+        self.io.write(FIFO_CTRL, 0xC7);
+
+        // Mark data terminal ready, signal request to send
+        // and enable auxilliary output #2 (used as interrupt line for CPU)
+        self.io.write(MODEM_CTRL, 0x0B);
+    }
+
+    /// Checks whether the chip is present, by writing to its scratch
+    /// register and reading it back.
+    fn is_present(&mut self) -> bool {
+        const TEST_BYTE: u8 = 0x5A;
+        self.io.write(SCRATCH, TEST_BYTE);
+        self.io.read(SCRATCH) == TEST_BYTE
+    }
+
+    /// Throttles or resumes receiving: disables the "received data
+    /// available" interrupt and deasserts RTS when throttled, so the sender
+    /// pauses if it supports hardware flow control.
+    #[cfg(feature = "irq")]
+    fn throttle_rx(&mut self, throttle: bool) {
+        self.set_int_flag(0x01, !throttle);
+        self.io
+            .write(MODEM_CTRL, if throttle { 0x09 } else { 0x0B });
+    }
+
+    /// Whether the UART has an interrupt pending, in the IIR.
+    #[cfg(feature = "irq")]
+    fn irq_pending(&mut self) -> bool {
+        const NO_INT_PENDING: u8 = 1;
+        self.io.read(FIFO_CTRL) & NO_INT_PENDING == 0
+    }
+
+    /// Enables or disables the "transmitter holding register empty"
+    /// interrupt.
+    #[cfg(feature = "irq")]
+    fn set_tx_interrupt(&mut self, enable: bool) {
+        self.set_int_flag(0x02, enable);
+    }
+
+    #[cfg(feature = "irq")]
+    fn set_int_flag(&mut self, flag: u8, enable: bool) {
+        let int_flags = if enable {
+            self.int_flags | flag
+        } else {
+            self.int_flags & !flag
+        };
+        if int_flags != self.int_flags {
+            self.int_flags = int_flags;
+            self.write_ier();
+        }
+    }
+
+    /// Writes the IER, keeping the UART in sleep mode if it is.
+    #[cfg(any(feature = "irq", feature = "pm"))]
+    fn write_ier(&mut self) {
+        #[cfg(feature = "pm")]
+        if self.asleep {
+            const SLEEP_MODE: u8 = 1 << 4;
+            self.io.write(INT_EN, self.int_flags | SLEEP_MODE);
+            return;
+        }
+        self.io.write(INT_EN, self.int_flags);
+    }
+
+    /// Enters or leaves the sleep mode (IER bit 4) of 16750-compatible UARTs.
+    #[cfg(feature = "pm")]
+    fn set_sleep(&mut self, sleep: bool) {
+        self.asleep = sleep;
+        self.write_ier();
+    }
+
+    /// Reads the LSR, counting the overrun error it reports, as reading
+    /// clears it.
+    fn line_sts(&mut self) -> LineStsFlags {
+        let sts = LineStsFlags::from_bits_truncate(self.io.read(LINE_STS));
+        if sts.contains(LineStsFlags::OVERRUN_ERROR) {
+            crate::console::count_rx_overrun();
+        }
+        sts
+    }
+
+    /// 向串口发送字节, 每次 THR 为空时写满发送 FIFO
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            while !self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY) {}
+            self.fill_tx_fifo(|| {
+                let (&c, tail) = rest.split_first()?;
+                rest = tail;
+                Some(c)
+            });
+        }
+    }
+
+    /// Fills the transmit FIFO with bytes returned by `next`, if it is empty.
+    fn fill_tx_fifo(&mut self, mut next: impl FnMut() -> Option<u8>) {
+        // 16550 的发送 FIFO 为 16 字节, THR 为空时可以一次写满
+        const TX_FIFO_SIZE: usize = 16;
+        if !self.line_sts().contains(LineStsFlags::OUTPUT_EMPTY) {
+            return;
+        }
+        for _ in 0..TX_FIFO_SIZE {
+            match next() {
+                Some(c) => self.io.write(DATA, c),
+                None => break,
+            }
+        }
+    }
+
+    /// 从串口读取一个字节
+    fn getchar(&mut self) -> Option<u8> {
+        // 如果数据就绪(线路状态寄存器LSR的0位为1表示就绪)
+        if self.line_sts().contains(LineStsFlags::INPUT_FULL) {
+            // 则从接收缓存寄存器data读取一个字节
+            Some(self.io.read(DATA))
+        } else {
+            None
+        }
+    }
+
+    /// Checks the serial chip with its internal loopback mode.
+    ///
+    /// Returns `true` if a byte sent in loopback mode is read back unchanged.
+    /// The normal operation mode is restored before returning.
+    fn loopback_test(&mut self) -> bool {
+        const TEST_BYTE: u8 = 0xAE;
+        // Set in loopback mode, keep OUT1/OUT2/RTS set
+        self.io.write(MODEM_CTRL, 0x1E);
+        while self.getchar().is_some() {}
+        self.io.write(DATA, TEST_BYTE);
+        let mut ok = false;
+        for _ in 0..0x10000 {
+            if let Some(c) = self.getchar() {
+                ok = c == TEST_BYTE;
+                break;
+            }
+        }
+        // Back to normal operation mode
+        self.io.write(MODEM_CTRL, 0x0B);
+        ok
+    }
+}
+
+/// A serial port, and the bytes received from it and to be sent to it.
+///
+/// SerialPort.uart.lock()时同时禁用内核抢占和中断
+pub(crate) struct SerialPort<IO> {
+    uart: SpinNoIrq<Uart16550<IO>>,
+    /// Registers the IRQ handler of the port, which calls
+    /// [`SerialPort::handle_irq`]. Returns `false` if the port has no IRQ.
+    #[cfg_attr(not(feature = "irq"), allow(dead_code))]
+    register_irq: fn() -> bool,
+    /// Whether the port is initialized and its chip is present.
+    ready: AtomicBool,
+    /// Bytes received by the IRQ handler and not read yet.
+    #[cfg(feature = "irq")]
+    rx_queue: crate::console::RxQueue,
+    /// Bytes written and not sent yet, sent by the IRQ handler.
+    #[cfg(feature = "irq")]
+    tx_queue: crate::console::TxQueue,
+    /// The ID of the port for runtime power management, if registered.
+    #[cfg(feature = "pm")]
+    pm_id: AtomicUsize,
+}
+
+impl<IO: UartIo> SerialPort<IO> {
+    /// Creates a serial port with the UART accessed by `io`, and its input
+    /// clock frequency (see [`Uart16550::clock_freq`]).
+    pub const fn new(io: IO, clock_freq: usize, register_irq: fn() -> bool) -> Self {
+        Self {
+            uart: SpinNoIrq::new(Uart16550::new(io, clock_freq)),
+            register_irq,
+            ready: AtomicBool::new(false),
+            #[cfg(feature = "irq")]
+            rx_queue: crate::console::RxQueue::new(),
+            #[cfg(feature = "irq")]
+            tx_queue: crate::console::TxQueue::new(),
+            #[cfg(feature = "pm")]
+            pm_id: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Sends bytes from the transmit queue, until it is empty if `block` is
+    /// `true`, otherwise as many as the transmit FIFO can hold.
+    ///
+    /// The transmit interrupt is enabled as long as the queue is not empty.
+    #[cfg(feature = "irq")]
+    fn send_queued(&self, uart: &mut Uart16550<IO>, block: bool) {
+        loop {
+            uart.fill_tx_fifo(|| self.tx_queue.pop());
+            if !block || self.tx_queue.is_empty() {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        uart.set_tx_interrupt(!self.tx_queue.is_empty());
+    }
+
+    /// Moves received bytes to the receive queue, throttling receiving if it
+    /// is full.
+    #[cfg(feature = "irq")]
+    fn fill_rx_queue(&self, uart: &mut Uart16550<IO>) {
+        let full = self.rx_queue.fill(|| uart.getchar());
+        uart.throttle_rx(full);
+    }
+
+    /// Moves all received bytes to the receive queue, and sends queued bytes.
+    #[cfg(feature = "irq")]
+    pub fn handle_irq(&self) {
+        if self.rx_queue.is_enabled() || self.tx_queue.is_enabled() {
+            let mut uart = self.uart.lock();
+            if self.rx_queue.is_enabled() {
+                self.fill_rx_queue(&mut uart);
+            }
+            if self.tx_queue.is_enabled() {
+                self.send_queued(&mut uart, false);
+            }
+        }
+    }
+
+    /// Whether the port has an interrupt pending, e.g., bytes received after
+    /// [`SerialPort::handle_irq`] read them.
+    #[cfg(feature = "irq")]
+    pub fn irq_pending(&self) -> bool {
+        self.is_ready() && self.uart.lock().irq_pending()
+    }
+
+    /// Registers the port for runtime power management, if it is present.
+    #[cfg(feature = "pm")]
+    pub fn register_pm(&'static self, name: &'static str)
+    where
+        IO: Send,
+    {
+        if !self.is_ready() {
+            return;
+        }
+        if let Some(id) = crate::pm::register(name, self, None) {
+            self.pm_id.store(id, Ordering::Release);
+        }
+    }
+
+    /// Records that the port is used, see [`crate::pm::mark_busy`]. It must
+    /// be called before locking the UART.
+    fn mark_busy(&self) {
+        #[cfg(feature = "pm")]
+        {
+            let id = self.pm_id.load(Ordering::Acquire);
+            if id != usize::MAX {
+                crate::pm::mark_busy(id);
+            }
+        }
+    }
+
+    /// Locks the UART, waking it up if it is asleep, as it may be suspended
+    /// after [`SerialPort::mark_busy`].
+    fn lock_awake(&self) -> kspin::SpinNoIrqGuard<'_, Uart16550<IO>> {
+        #[allow(unused_mut)]
+        let mut uart = self.uart.lock();
+        #[cfg(feature = "pm")]
+        if uart.asleep {
+            uart.set_sleep(false);
+        }
+        uart
+    }
+
+    /// Runs the loopback test of the UART, see [`Uart16550::loopback_test`].
+    #[allow(dead_code)]
+    pub fn loopback_test(&self) -> bool {
+        self.uart.lock().loopback_test()
+    }
+}
+
+impl<IO: UartIo + Send> ConsoleDriver for SerialPort<IO> {
+    /// 设置波特率为115200
+    ///
+    /// The port is left unused if its chip is not present.
+    fn init(&self) {
+        let mut uart = self.uart.lock();
+        if uart.is_present() {
+            uart.init(115200);
+            self.ready.store(true, Ordering::Release);
+        }
+    }
+
+    fn write(&self, bytes: &[u8]) {
+        if !self.is_ready() {
+            return;
+        }
+        self.mark_busy();
+        let mut uart = self.lock_awake();
+        // Keep the order with bytes written before without blocking.
+        #[cfg(feature = "irq")]
+        if self.tx_queue.is_enabled() {
+            self.send_queued(&mut uart, true);
+        }
+        uart.write_bytes(bytes);
+    }
+
+    #[cfg(feature = "irq")]
+    fn write_nonblocking(&self, bytes: &[u8]) -> usize {
+        if self.is_ready() && self.tx_queue.is_enabled() {
+            self.mark_busy();
+            let len = self.tx_queue.push(bytes);
+            self.send_queued(&mut self.lock_awake(), false);
+            return len;
+        }
+        self.write(bytes);
+        bytes.len()
+    }
+
+    /// - from crate::console::read_bytes(bytes)
+    /// - 启用中断后从 `rx_queue` 读取, 由中断处理函数填充
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        if !self.is_ready() {
+            return 0;
+        }
+        #[cfg(feature = "irq")]
+        if self.rx_queue.is_enabled() {
+            let len = self.rx_queue.read(bytes);
+            if self.rx_queue.should_resume() {
+                self.fill_rx_queue(&mut self.uart.lock());
+            }
+            return len;
+        }
+        let mut uart = self.uart.lock();
+        let mut read_len = 0;
+        // 每次读1个字节, 直到把buf填满为止
+        while read_len < bytes.len() {
+            match uart.getchar() {
+                Some(c) => bytes[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        read_len
+    }
+
+    /// Enables receive interrupts of the port, must be called after the
+    /// interrupt controller is initialized.
+    #[cfg(feature = "irq")]
+    fn enable_rx_irq(&self) -> bool {
+        if !self.is_ready() || !(self.register_irq)() {
+            return false;
+        }
+        let mut uart = self.uart.lock();
+        // Move the bytes received before to the queue.
+        self.fill_rx_queue(&mut uart);
+        self.rx_queue.enable();
+        true
+    }
+
+    /// Enables transmit interrupts of the port, must be called after the
+    /// interrupt controller is initialized.
+    #[cfg(feature = "irq")]
+    fn enable_tx_irq(&self) -> bool {
+        if !self.is_ready() || !(self.register_irq)() {
+            return false;
+        }
+        self.tx_queue.enable();
+        true
+    }
+
+    /// Sends all queued bytes, or none if the port is locked, e.g., by the
+    /// panicked code.
+    #[cfg(feature = "irq")]
+    fn flush(&self) {
+        if !self.is_ready() || !self.tx_queue.is_enabled() {
+            return;
+        }
+        if let Some(mut uart) = self.uart.try_lock() {
+            #[cfg(feature = "pm")]
+            if uart.asleep {
+                uart.set_sleep(false);
+            }
+            self.send_queued(&mut uart, true);
+        }
+    }
+}
+
+#[cfg(feature = "pm")]
+impl<IO: UartIo + Send> crate::pm::DevicePm for SerialPort<IO> {
+    /// The port is idle once all the bytes written are sent.
+    fn is_idle(&self) -> bool {
+        #[cfg(feature = "irq")]
+        if !self.tx_queue.is_empty() {
+            return false;
+        }
+        // Not waiting for the lock, as it is called with the device table
+        // locked.
+        self.uart
+            .try_lock()
+            .is_some_and(|mut uart| uart.line_sts().contains(LineStsFlags::TRANSMITTER_EMPTY))
+    }
+
+    fn suspend(&self) {
+        self.uart.lock().set_sleep(true);
+    }
+
+    fn resume(&self) {
+        self.uart.lock().set_sleep(false);
+    }
+}
@@ -0,0 +1,222 @@
+//! Power off and reset with ACPI, on real machines as well as in QEMU.
+//!
+//! The tables are parsed at boot, while the low 4 GiB of the physical memory
+//! are mapped: the FADT gives the PM1 control registers and the reset
+//! register, and the `\_S5_` object of the DSDT the sleep types of the
+//! soft-off state (S5). Only the registers in the I/O space are supported.
+//!
+//! See <https://wiki.osdev.org/Shutdown> and the ACPI specification.
+
+use lazyinit::LazyInit;
+use x86_64::instructions::port::{Port, PortWriteOnly};
+
+use crate::mem::phys_to_virt;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// The size of the header of the system description tables.
+const SDT_HEADER_SIZE: usize = 36;
+
+/// The ACPI mode is enabled (in PM1 control).
+const SCI_EN: u16 = 1 << 0;
+/// Enters the sleep state of `SLP_TYP` (in PM1 control).
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+/// The reset register is supported (in the flags of the FADT).
+const RESET_REG_SUP: u32 = 1 << 10;
+/// The address space of the I/O ports, in a generic address structure.
+const ADDRESS_SPACE_IO: u8 = 1;
+
+/// The registers and values to power off and reset the system.
+#[derive(Debug)]
+struct AcpiPower {
+    smi_cmd: u16,
+    acpi_enable: u8,
+    pm1a_cnt: u16,
+    pm1b_cnt: u16,
+    /// The sleep types of S5, for PM1a and PM1b control, if the DSDT has
+    /// them.
+    slp_typ: Option<(u16, u16)>,
+    /// The port of the reset register and the value to write, if the FADT
+    /// has one.
+    reset: Option<(u16, u8)>,
+}
+
+static ACPI_POWER: LazyInit<AcpiPower> = LazyInit::new();
+
+/// Returns the bytes of a physical memory range mapped at boot, or `None` if
+/// it is not in the low 4 GiB.
+fn phys_bytes(paddr: usize, len: usize) -> Option<&'static [u8]> {
+    if paddr.checked_add(len)? > 1 << 32 {
+        return None;
+    }
+    let vaddr = phys_to_virt(pa!(paddr)).as_usize();
+    // SAFETY: the low 4 GiB are mapped at boot.
+    Some(unsafe { core::slice::from_raw_parts(vaddr as *const u8, len) })
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// Finds the RSDP in the first KiB of the EBDA, or in the BIOS area.
+fn find_rsdp() -> Option<usize> {
+    // The segment of the EBDA is in the BIOS data area.
+    let ebda = (u16::from_le_bytes(phys_bytes(0x40e, 2)?.try_into().unwrap()) as usize) << 4;
+    let areas = [(ebda, 0x400), (0xe0000, 0x20000)];
+    areas
+        .into_iter()
+        .filter(|&(start, _)| start != 0)
+        .flat_map(|(start, len)| (start..start + len).step_by(16))
+        .find(|&paddr| {
+            phys_bytes(paddr, 20)
+                .is_some_and(|rsdp| rsdp.starts_with(RSDP_SIGNATURE) && checksum_ok(rsdp))
+        })
+}
+
+/// Returns a system description table, if its checksum is valid.
+fn table(paddr: usize) -> Option<&'static [u8]> {
+    let header = phys_bytes(paddr, SDT_HEADER_SIZE)?;
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    let table = phys_bytes(paddr, len.max(SDT_HEADER_SIZE))?;
+    checksum_ok(table).then_some(table)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Finds the table with `signature` in the RSDT, or the XSDT.
+fn find_table(rsdp: usize, signature: &[u8; 4]) -> Option<&'static [u8]> {
+    let rsdp = phys_bytes(rsdp, 36)?;
+    // The XSDT is only in the RSDP of ACPI 2.0 and later.
+    let xsdt = read_u64(rsdp, 24)
+        .filter(|&xsdt| rsdp[15] >= 2 && xsdt != 0)
+        .and_then(|xsdt| table(usize::try_from(xsdt).ok()?));
+    let (sdt, entry_size) = match xsdt {
+        Some(xsdt) => (xsdt, 8),
+        None => (table(read_u32(rsdp, 16)? as usize)?, 4),
+    };
+    sdt[SDT_HEADER_SIZE..]
+        .chunks_exact(entry_size)
+        .filter_map(|entry| match entry_size {
+            8 => usize::try_from(read_u64(entry, 0)?).ok(),
+            _ => Some(read_u32(entry, 0)? as usize),
+        })
+        .filter_map(table)
+        .find(|table| table.starts_with(signature))
+}
+
+/// Reads an integer constant of AML: `ZeroOp`, `OneOp`, or `BytePrefix`
+/// followed by the byte. Returns it and the bytes after it.
+fn aml_byte(aml: &[u8]) -> Option<(u16, &[u8])> {
+    match aml {
+        [0x00, rest @ ..] => Some((0, rest)),
+        [0x01, rest @ ..] => Some((1, rest)),
+        [0x0a, value, rest @ ..] => Some((*value as u16, rest)),
+        _ => None,
+    }
+}
+
+/// Finds the sleep types of S5 in the `\_S5_` package of the DSDT, e.g.,
+/// `Name (\_S5, Package () { 5, 5, 0, 0 })`.
+fn s5_sleep_types(dsdt: &[u8]) -> Option<(u16, u16)> {
+    let aml = dsdt.get(SDT_HEADER_SIZE..)?;
+    // NameOp, with the root prefix or not, the name, then PackageOp.
+    let pos = (0..aml.len().saturating_sub(4)).find(|&i| {
+        &aml[i..i + 4] == b"_S5_"
+            && (aml[..i].ends_with(&[0x08]) || aml[..i].ends_with(b"\x08\\"))
+            && aml.get(i + 4) == Some(&0x12)
+    })?;
+    // PkgLength (its first byte tells the number of bytes after it), and
+    // NumElements.
+    let pkg = &aml[pos + 5..];
+    let pkg = pkg.get(((*pkg.first()? >> 6) as usize) + 2..)?;
+    let (slp_typa, pkg) = aml_byte(pkg)?;
+    let (slp_typb, _) = aml_byte(pkg).unwrap_or((0, &[]));
+    Some((slp_typa, slp_typb))
+}
+
+fn parse_fadt(fadt: &[u8]) -> Option<AcpiPower> {
+    let dsdt = read_u64(fadt, 140)
+        .filter(|&dsdt| dsdt != 0)
+        .and_then(|dsdt| usize::try_from(dsdt).ok())
+        .or_else(|| Some(read_u32(fadt, 40)? as usize))
+        .and_then(table);
+    let flags = read_u32(fadt, 112).unwrap_or(0);
+    let reset = fadt
+        .get(116..129)
+        .filter(|reg| flags & RESET_REG_SUP != 0 && reg[0] == ADDRESS_SPACE_IO)
+        .and_then(|reg| Some((u16::try_from(read_u64(reg, 4)?).ok()?, reg[12])));
+    Some(AcpiPower {
+        smi_cmd: read_u32(fadt, 48)? as u16,
+        acpi_enable: *fadt.get(52)?,
+        pm1a_cnt: read_u32(fadt, 64)? as u16,
+        pm1b_cnt: read_u32(fadt, 68)? as u16,
+        slp_typ: dsdt.and_then(s5_sleep_types),
+        reset,
+    })
+}
+
+/// Parses the ACPI tables, at boot, while the low 4 GiB are mapped.
+pub(super) fn init() {
+    let Some(rsdp) = find_rsdp() else {
+        return;
+    };
+    if let Some(power) = find_table(rsdp, b"FACP").and_then(parse_fadt) {
+        ACPI_POWER.init_once(power);
+    }
+}
+
+/// Logs what the ACPI tables give, once the logger is initialized.
+pub(super) fn log_info() {
+    match ACPI_POWER.get() {
+        Some(power) => debug!("ACPI power management: {:x?}", power),
+        None => warn!("no ACPI tables, falling back to legacy shutdown and reset"),
+    }
+}
+
+/// Powers off the system by entering S5. Returns if it is not supported.
+pub(super) fn power_off() {
+    let Some(power) = ACPI_POWER.get() else {
+        return;
+    };
+    let Some((slp_typa, slp_typb)) = power.slp_typ else {
+        return;
+    };
+    if power.pm1a_cnt == 0 {
+        return;
+    }
+    let mut pm1a_cnt = Port::<u16>::new(power.pm1a_cnt);
+    unsafe {
+        // The firmware switches to the ACPI mode if it is not yet.
+        if pm1a_cnt.read() & SCI_EN == 0 && power.smi_cmd != 0 && power.acpi_enable != 0 {
+            PortWriteOnly::<u8>::new(power.smi_cmd).write(power.acpi_enable);
+            for _ in 0..1_000_000 {
+                if pm1a_cnt.read() & SCI_EN != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        pm1a_cnt.write((slp_typa << SLP_TYP_SHIFT) | SLP_EN);
+        if power.pm1b_cnt != 0 {
+            PortWriteOnly::<u16>::new(power.pm1b_cnt).write((slp_typb << SLP_TYP_SHIFT) | SLP_EN);
+        }
+    }
+}
+
+/// Resets the system with the reset register. Returns if it is not
+/// supported.
+pub(super) fn reset() {
+    if let Some((port, value)) = ACPI_POWER.get().and_then(|power| power.reset) {
+        unsafe { PortWriteOnly::<u8>::new(port).write(value) };
+    }
+}
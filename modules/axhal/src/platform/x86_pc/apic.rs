@@ -13,6 +13,10 @@ use self::vectors::*;
 use crate::mem::phys_to_virt;
 
 pub(super) mod vectors {
+    /// The vector of IO APIC pin 0. Pin `n` is mapped to vector `0x20 + n`.
+    pub const IO_APIC_VECTOR_BASE: u8 = 0x20;
+    /// The first vector allocated to MSIs, after those of the IO APIC pins.
+    pub const MSI_VECTOR_BASE: u8 = 0x40;
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
@@ -24,8 +28,14 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The UART (COM1) IRQ number, i.e., the vector of ISA IRQ 4.
+pub const UART_IRQ_NUM: usize = IO_APIC_VECTOR_BASE as usize + 4;
+
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
+/// The base of the MSI addresses, which the LAPICs receive writes to.
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
 static LOCAL_APIC: SyncUnsafeCell<MaybeUninit<LocalApic>> =
     SyncUnsafeCell::new(MaybeUninit::uninit());
 static mut IS_X2APIC: bool = false;
@@ -35,15 +45,105 @@ static IO_APIC: LazyInit<SpinNoIrq<IoApic>> = LazyInit::new();
 #[cfg(feature = "irq")]
 pub fn set_enable(vector: usize, enabled: bool) {
     // should not affect LAPIC interrupts
-    if vector < APIC_TIMER_VECTOR as _ {
-        unsafe {
-            if enabled {
-                IO_APIC.lock().enable_irq(vector as u8);
-            } else {
-                IO_APIC.lock().disable_irq(vector as u8);
-            }
+    let Some(pin) = vector.checked_sub(IO_APIC_VECTOR_BASE as usize) else {
+        return;
+    };
+    let mut io_apic = IO_APIC.lock();
+    unsafe {
+        if pin > io_apic.max_table_entry() as usize {
+            return;
+        }
+        if enabled {
+            io_apic.enable_irq(pin as u8);
+        } else {
+            io_apic.disable_irq(pin as u8);
+        }
+    }
+}
+
+/// Routes the IRQ of an IO APIC pin to the lowest CPU in `cpu_mask`, whose
+/// APIC ID is the CPU ID, in the destination field of its redirection entry.
+///
+/// It returns `false` for the other vectors, including the MSIs, whose
+/// destination is programmed into their devices.
+#[cfg(feature = "irq")]
+pub fn set_affinity(vector: usize, cpu_mask: usize) -> bool {
+    let Some(pin) = vector.checked_sub(IO_APIC_VECTOR_BASE as usize) else {
+        return false;
+    };
+    let mut io_apic = IO_APIC.lock();
+    unsafe {
+        if pin > io_apic.max_table_entry() as usize {
+            return false;
         }
+        // Physical destination mode: a single APIC ID.
+        let mut entry = io_apic.table_entry(pin as u8);
+        entry.set_dest(cpu_mask.trailing_zeros() as u8);
+        io_apic.set_table_entry(pin as u8, entry);
+    }
+    true
+}
+
+/// Sets the trigger mode and polarity of an IO APIC pin, in the flags of its
+/// redirection entry.
+///
+/// It returns `false` for the other vectors: the LAPIC interrupts, and the
+/// MSIs, which are always edge-triggered.
+#[cfg(feature = "irq")]
+pub fn set_trigger(vector: usize, mode: crate::irq::TriggerMode) -> bool {
+    use x2apic::ioapic::IrqFlags;
+
+    let Some(pin) = vector.checked_sub(IO_APIC_VECTOR_BASE as usize) else {
+        return false;
+    };
+    let mut io_apic = IO_APIC.lock();
+    unsafe {
+        if pin > io_apic.max_table_entry() as usize {
+            return false;
+        }
+        let mut entry = io_apic.table_entry(pin as u8);
+        let mut flags = entry.flags();
+        flags.set(IrqFlags::LEVEL_TRIGGERED, mode.is_level());
+        flags.set(IrqFlags::LOW_ACTIVE, mode.is_active_low());
+        entry.set_flags(flags);
+        io_apic.set_table_entry(pin as u8, entry);
+    }
+    true
+}
+
+/// Sets the priority of an IRQ, which is not supported: the priority of an
+/// interrupt is the class of its vector.
+#[cfg(feature = "irq")]
+pub fn set_priority(_vector: usize, _priority: u8) -> bool {
+    false
+}
+
+/// Sets the priority threshold of the current CPU, which is not supported.
+#[cfg(feature = "irq")]
+pub fn set_priority_threshold(_threshold: u8) -> bool {
+    false
+}
+
+/// Masks the interrupts whose priority class (the upper 4 bits of the
+/// vector) is not higher than the one of `vector`, in the task priority
+/// register of the LAPIC, through `CR8`, and returns the old one.
+///
+/// The timer has the highest class, so it interrupts the handlers of the
+/// others.
+#[cfg(feature = "nested-irq")]
+pub(crate) fn raise_priority_mask(vector: usize) -> Option<usize> {
+    let tpr: usize;
+    unsafe {
+        core::arch::asm!("mov {}, cr8", out(reg) tpr);
+        core::arch::asm!("mov cr8, {}", in(reg) tpr.max(vector >> 4));
     }
+    Some(tpr)
+}
+
+/// Restores the task priority returned by [`raise_priority_mask`].
+#[cfg(feature = "nested-irq")]
+pub(crate) fn restore_priority_mask(saved: usize) {
+    unsafe { core::arch::asm!("mov cr8, {}", in(reg) saved) };
 }
 
 /// Registers an IRQ handler for the given IRQ.
@@ -68,6 +168,37 @@ pub fn dispatch_irq(vector: usize) {
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Returns the vectors which can be allocated to MSIs.
+#[cfg(feature = "irq")]
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    MSI_VECTOR_BASE as usize..APIC_TIMER_VECTOR as usize
+}
+
+/// Returns the message of the MSI with the given vector.
+///
+/// It is delivered to the BSP (APIC ID 0), like the IO APIC pins by default,
+/// in fixed mode and edge-triggered, i.e., with the vector as the data.
+#[cfg(feature = "irq")]
+pub(crate) fn setup_msi(_dev: u32, vector: usize) -> crate::irq::MsiMessage {
+    crate::irq::MsiMessage {
+        address: MSI_ADDRESS_BASE,
+        data: vector as u32,
+    }
+}
+
+/// Sends an inter-processor interrupt with the given vector to the current CPU.
+#[cfg(feature = "irq")]
+pub fn send_ipi_self(vector: usize) {
+    unsafe { local_apic().send_ipi_self(vector as u8) };
+}
+
+/// Sends an inter-processor interrupt with the given vector to the CPU
+/// `cpu_id`.
+#[cfg(feature = "irq")]
+pub fn send_ipi(cpu_id: usize, vector: usize) {
+    unsafe { local_apic().send_ipi(vector as u8, raw_apic_id(cpu_id as u8)) };
+}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as `LOCAL_APIC` is initialized in `init_primary`.
     unsafe { LOCAL_APIC.get().as_mut().unwrap().assume_init_mut() }
@@ -122,7 +253,10 @@ pub(super) fn init_primary() {
     }
 
     info!("Initialize IO APIC...");
-    let io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    let mut io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    // Map all pins to vectors starting from `IO_APIC_VECTOR_BASE`, masked and
+    // delivered to the BSP.
+    unsafe { io_apic.init(IO_APIC_VECTOR_BASE) };
     IO_APIC.init_once(SpinNoIrq::new(io_apic));
 }
 
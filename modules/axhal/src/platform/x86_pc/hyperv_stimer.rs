@@ -0,0 +1,101 @@
+//! The synthetic timers of Hyper-V.
+//!
+//! The synthetic timer 0 of each CPU is used as the timer instead of the
+//! local APIC timer, if available, in the direct mode: it raises the timer
+//! vector on the local APIC, with no message of the synthetic interrupt
+//! controller to handle. Its deadlines are in the units of the partition
+//! reference counter (100 ns), which keeps counting while the VM is
+//! descheduled, and needs no calibration.
+//!
+//! See the [Hyper-V TLFS](https://learn.microsoft.com/en-us/virtualization/hyper-v-on-windows/tlfs/tlfs).
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86::msr::{rdmsr, wrmsr};
+
+// Features in EAX of the Hyper-V features leaf of CPUID.
+const HV_ACCESS_PARTITION_REFERENCE_COUNTER: u32 = 1 << 1;
+const HV_ACCESS_SYNIC_REGS: u32 = 1 << 2;
+const HV_ACCESS_SYNTHETIC_TIMER_REGS: u32 = 1 << 3;
+// Features in EDX of the Hyper-V features leaf of CPUID.
+const HV_STIMER_DIRECT_MODE_AVAILABLE: u32 = 1 << 19;
+
+const HV_X64_MSR_GUEST_OS_ID: u32 = 0x4000_0000;
+const HV_X64_MSR_TIME_REF_COUNT: u32 = 0x4000_0020;
+const HV_X64_MSR_SCONTROL: u32 = 0x4000_0080;
+const HV_X64_MSR_STIMER0_CONFIG: u32 = 0x4000_00b0;
+const HV_X64_MSR_STIMER0_COUNT: u32 = 0x4000_00b1;
+
+/// The guest OS ID of an open source OS of no registered type. It must be
+/// set before the synthetic MSRs are used.
+const GUEST_OS_ID: u64 = 1 << 63;
+
+/// Enables the synthetic interrupt controller (in `HV_X64_MSR_SCONTROL`).
+const SCONTROL_ENABLE: u64 = 1 << 0;
+
+// Bits of `HV_X64_MSR_STIMER0_CONFIG`.
+/// The timer is enabled when its count is written.
+const STIMER_AUTO_ENABLE: u64 = 1 << 3;
+const STIMER_APIC_VECTOR_SHIFT: u64 = 4;
+/// The timer raises its vector on the local APIC, instead of sending a
+/// message to a synthetic interrupt source.
+const STIMER_DIRECT_MODE: u64 = 1 << 12;
+
+/// The period of the partition reference counter.
+const NANOS_PER_REFERENCE_TICK: u64 = 100;
+
+/// Whether the synthetic timers are used, decided on the primary CPU.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the synthetic timer 0 of the current CPU in the direct mode, to
+/// raise `vector`, returns whether it is used as the timer.
+///
+/// Whether it is available is checked on the primary CPU only, and the
+/// secondary CPUs follow it.
+pub(super) fn init(vector: u8, primary: bool) -> bool {
+    if primary {
+        let required = HV_ACCESS_PARTITION_REFERENCE_COUNTER
+            | HV_ACCESS_SYNIC_REGS
+            | HV_ACCESS_SYNTHETIC_TIMER_REGS;
+        let available = match super::hypervisor::hyperv_features() {
+            Some((eax, edx)) => {
+                eax & required == required && edx & HV_STIMER_DIRECT_MODE_AVAILABLE != 0
+            }
+            None => false,
+        };
+        ENABLED.store(available, Ordering::Relaxed);
+    }
+    if !is_enabled() {
+        return false;
+    }
+    unsafe {
+        if rdmsr(HV_X64_MSR_GUEST_OS_ID) == 0 {
+            wrmsr(HV_X64_MSR_GUEST_OS_ID, GUEST_OS_ID);
+        }
+        wrmsr(
+            HV_X64_MSR_SCONTROL,
+            rdmsr(HV_X64_MSR_SCONTROL) | SCONTROL_ENABLE,
+        );
+        // One-shot, and disabled until the first deadline is written.
+        wrmsr(
+            HV_X64_MSR_STIMER0_CONFIG,
+            STIMER_AUTO_ENABLE | STIMER_DIRECT_MODE | (vector as u64) << STIMER_APIC_VECTOR_SHIFT,
+        );
+    }
+    true
+}
+
+/// Whether the synthetic timers are set up by [`init`].
+pub(super) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Arms the synthetic timer 0 of the current CPU to expire in `nanos`.
+pub(super) fn set_oneshot(nanos: u64) {
+    unsafe {
+        let now = rdmsr(HV_X64_MSR_TIME_REF_COUNT);
+        // The count is absolute, and 0 would disable the timer.
+        let count = now + (nanos / NANOS_PER_REFERENCE_TICK).max(1);
+        wrmsr(HV_X64_MSR_STIMER0_COUNT, count);
+    }
+}
@@ -0,0 +1,68 @@
+//! Detection of the hypervisor, and of the timer frequencies it reports.
+//!
+//! The frequencies of the TSC and of the local APIC timer can not be measured
+//! reliably in a virtual machine, so they are taken from the hypervisor if it
+//! reports them: by the timing leaf (`0x4000_0010`) of CPUID on KVM, VMware
+//! and others, or by the frequency MSRs of Hyper-V.
+//!
+//! On Hyper-V, the synthetic timers are used instead of the local APIC timer
+//! if available, see the `hyperv_stimer` module.
+
+use raw_cpuid::{CpuId, Hypervisor};
+
+/// The CPUID leaf of the features available to Hyper-V guests.
+const HV_CPUID_FEATURES: u32 = 0x4000_0003;
+/// The frequency MSRs are available (in EAX of [`HV_CPUID_FEATURES`]).
+const HV_ACCESS_FREQUENCY_MSRS: u32 = 1 << 11;
+
+const HV_X64_MSR_TSC_FREQUENCY: u32 = 0x4000_0022;
+const HV_X64_MSR_APIC_FREQUENCY: u32 = 0x4000_0023;
+
+/// Returns the hypervisor the system runs on, or `None` on bare metal.
+pub(super) fn hypervisor() -> Option<Hypervisor> {
+    CpuId::new().get_hypervisor_info().map(|hv| hv.identify())
+}
+
+/// Returns the features available to Hyper-V guests, in EAX and EDX of
+/// [`HV_CPUID_FEATURES`], or `None` if not running on Hyper-V.
+pub(super) fn hyperv_features() -> Option<(u32, u32)> {
+    if !matches!(hypervisor(), Some(Hypervisor::HyperV)) {
+        return None;
+    }
+    let features = raw_cpuid::cpuid!(HV_CPUID_FEATURES);
+    Some((features.eax, features.edx))
+}
+
+/// Reads a frequency MSR of Hyper-V, if available.
+fn hyperv_frequency(msr: u32) -> Option<u64> {
+    let (eax, _) = hyperv_features()?;
+    if eax & HV_ACCESS_FREQUENCY_MSRS == 0 {
+        return None;
+    }
+    Some(unsafe { x86::msr::rdmsr(msr) }).filter(|&freq| freq > 0)
+}
+
+/// Returns the TSC frequency in Hz reported by the hypervisor.
+pub(super) fn tsc_frequency() -> Option<u64> {
+    let khz = CpuId::new()
+        .get_hypervisor_info()
+        .and_then(|hv| hv.tsc_frequency())
+        .filter(|&khz| khz > 0);
+    match khz {
+        Some(khz) => Some(khz as u64 * 1000),
+        None => hyperv_frequency(HV_X64_MSR_TSC_FREQUENCY),
+    }
+}
+
+/// Returns the frequency of the local APIC timer in Hz reported by the
+/// hypervisor.
+pub(super) fn apic_timer_frequency() -> Option<u64> {
+    let khz = CpuId::new()
+        .get_hypervisor_info()
+        .and_then(|hv| hv.apic_frequency())
+        .filter(|&khz| khz > 0);
+    match khz {
+        Some(khz) => Some(khz as u64 * 1000),
+        None => hyperv_frequency(HV_X64_MSR_APIC_FREQUENCY),
+    }
+}
@@ -1,6 +1,12 @@
-use x86_64::instructions::port::PortWriteOnly;
+use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
 
-/// Shutdown the whole system (in QEMU), including all CPUs.
+/// I/O port of the QEMU `isa-debug-exit` device.
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Shutdown the whole system, including all CPUs.
+///
+/// The system enters the ACPI soft-off state (S5), falling back to the
+/// QEMU-specific methods if the ACPI tables do not give it.
 ///
 /// See <https://wiki.osdev.org/Shutdown> for more information.
 pub fn terminate() -> ! {
@@ -9,12 +15,15 @@ pub fn terminate() -> ! {
     #[cfg(platform = "x86_64-pc-oslab")]
     {
         axlog::ax_println!("System will reboot, press any key to continue ...");
+        crate::console::set_mode(crate::console::ConsoleMode::Raw);
         let mut buffer = [0u8; 1];
-        while super::console::read_bytes(&mut buffer) == 0 {}
+        while crate::console::read_bytes(&mut buffer) == 0 {}
         axlog::ax_println!("Rebooting ...");
         unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
     }
 
+    super::acpi::power_off();
+
     #[cfg(platform = "x86_64-qemu-q35")]
     unsafe {
         PortWriteOnly::new(0x604).write(0x2000u16)
@@ -26,3 +35,65 @@ pub fn terminate() -> ! {
         axcpu::asm::halt();
     }
 }
+
+/// Resets the whole system, including all CPUs.
+///
+/// The reset register of ACPI is used, falling back to the reset control
+/// register of the chipset, to the keyboard controller, and at last to a
+/// triple fault.
+pub fn reset() -> ! {
+    info!("Rebooting...");
+    super::acpi::reset();
+    unsafe {
+        // Full reset: system reset (bit 1) and reset CPU (bit 2).
+        PortWriteOnly::<u8>::new(0xcf9).write(0x06);
+    }
+    keyboard_controller_reset();
+    warn!("It should reboot!");
+    triple_fault()
+}
+
+/// Pulses the reset line of the CPU with the keyboard controller.
+fn keyboard_controller_reset() {
+    const KBC_STATUS_INPUT_FULL: u8 = 1 << 1;
+
+    let mut status = PortReadOnly::<u8>::new(0x64);
+    unsafe {
+        for _ in 0..100_000 {
+            if status.read() & KBC_STATUS_INPUT_FULL == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        PortWriteOnly::<u8>::new(0x64).write(0xfe);
+    }
+    // The reset is not immediate.
+    for _ in 0..1_000_000 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Resets the CPU with a triple fault: an exception with an empty IDT.
+fn triple_fault() -> ! {
+    use x86_64::structures::DescriptorTablePointer;
+
+    let idt = DescriptorTablePointer {
+        limit: 0,
+        base: x86_64::VirtAddr::zero(),
+    };
+    unsafe {
+        x86_64::instructions::tables::lidt(&idt);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
+
+/// Exits QEMU with the given exit code, using the `isa-debug-exit` device.
+///
+/// QEMU must be started with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`,
+/// and its exit status will be `(code << 1) | 1`. Falls back to [`terminate`]
+/// if the device is not present.
+pub fn qemu_exit(code: i32) -> ! {
+    info!("Exiting QEMU with code {}...", code);
+    unsafe { PortWriteOnly::<u32>::new(DEBUG_EXIT_PORT).write(code as u32) };
+    terminate()
+}
@@ -1,6 +1,10 @@
+mod acpi;
 mod apic;
 mod boot;
-mod uart16550;
+#[cfg(feature = "irq")]
+mod hyperv_stimer;
+mod hypervisor;
+mod serial;
 
 pub mod mem;
 pub mod misc;
@@ -15,10 +19,12 @@ pub mod irq {
 }
 
 pub mod console {
-    // 将uart16550的东西重导出为console::*
-    pub use super::uart16550::*;
+    // 将串口的东西重导出为console::*
+    pub use super::serial::*;
 }
 
+use crate::console::ConsoleDriver;
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize) -> !;
     #[cfg(feature = "smp")]
@@ -32,14 +38,35 @@ fn current_cpu_id() -> usize {
     }
 }
 
-unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
-    // TODO: handle multiboot info
+/// Sets the boot command line from the multiboot information at the
+/// physical address `mbi`, if it has one.
+fn init_cmdline(mbi: usize) {
+    /// The `cmdline` field is valid.
+    const MULTIBOOT_INFO_CMDLINE: u32 = 1 << 2;
+
+    let info = crate::mem::phys_to_virt(pa!(mbi)).as_usize() as *const u32;
+    // SAFETY: the multiboot information and the command line are in the low
+    // memory mapped at boot.
+    unsafe {
+        if info.read() & MULTIBOOT_INFO_CMDLINE == 0 {
+            return;
+        }
+        let cmdline = crate::mem::phys_to_virt(pa!(info.add(4).read() as usize));
+        let cmdline = core::ffi::CStr::from_ptr(cmdline.as_ptr() as *const core::ffi::c_char);
+        crate::cmdline::set(cmdline.to_bytes());
+    }
+}
+
+unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
+    // TODO: handle the memory map of the multiboot info
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::mem::clear_bss();
+        init_cmdline(mbi);
+        self::acpi::init();
         let cpu_id = current_cpu_id();
         crate::cpu::init_primary(cpu_id);
         axcpu::init::init_trap();
-        self::uart16550::init();
+        self::serial::CONSOLE.init();
         self::time::init_early();
         rust_main(cpu_id, 0);
     }
@@ -58,8 +85,16 @@ unsafe extern "C" fn rust_entry_secondary(magic: usize) {
 
 /// Initializes the platform devices for the primary CPU.
 pub fn platform_init() {
+    self::acpi::log_info();
     self::apic::init_primary();
     self::time::init_primary();
+    #[cfg(feature = "irq")]
+    {
+        self::serial::CONSOLE.enable_rx_irq();
+        self::serial::CONSOLE.enable_tx_irq();
+    }
+    #[cfg(feature = "pm")]
+    self::serial::CONSOLE.register_pm("com1");
 }
 
 /// Initializes the platform devices for secondary CPUs.
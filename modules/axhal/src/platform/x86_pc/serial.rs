@@ -0,0 +1,124 @@
+//! The PC serial ports, driven by the generic 16550 driver.
+
+#[cfg(feature = "irq")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::console::ConsoleDriver;
+use crate::platform::uart16550::{PortIo, SerialPort};
+
+/// The frequency of the input clock of the PC serial ports.
+const OSC_FREQ: usize = 1_843_200;
+
+type PcSerialPort = SerialPort<PortIo>;
+
+/// 创建静态实例, 通过此实例执行字符读取和输出功能
+/// 类似rust_os的WRITER(只输出), rust_os的输入获取由中断实现(异步和非异步都是如此)
+/// - 0x3f8 is the standard I/O port address for the first serial port, also known as COM1, on a PC.
+///   This port is used for communication with serial devices like modems and printers.
+///   Specifically, the port range 0x3f8 through 0x3ff is associated with COM1.
+/// - The standard addresses for the first four COM ports are:
+///   COM1: 0x3F8
+///   COM2: 0x2F8
+///   COM3: 0x3E8
+///   COM4: 0x2E8
+/// - COM1 and COM3 use IRQ4, COM2 and COM4 use IRQ3.
+pub(crate) static COM1: PcSerialPort = SerialPort::new(PortIo::new(0x3f8), OSC_FREQ, register_irq4);
+pub(crate) static COM2: PcSerialPort = SerialPort::new(PortIo::new(0x2f8), OSC_FREQ, register_irq3);
+pub(crate) static COM3: PcSerialPort = SerialPort::new(PortIo::new(0x3e8), OSC_FREQ, register_irq4);
+pub(crate) static COM4: PcSerialPort = SerialPort::new(PortIo::new(0x2e8), OSC_FREQ, register_irq3);
+
+/// The console device of the platform.
+pub(crate) use self::COM1 as CONSOLE;
+
+/// All serial ports, indexed by the port number.
+pub(crate) static PORTS: [&dyn ConsoleDriver; 4] = [&COM1, &COM2, &COM3, &COM4];
+
+/// Writes a byte to COM1 by polling, without locks, see
+/// [`crate::earlycon`].
+#[cfg(feature = "earlycon")]
+pub(crate) fn early_putchar(c: u8) {
+    use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
+
+    const LSR_THRE: u8 = 1 << 5;
+
+    let mut line_sts = PortReadOnly::<u8>::new(0x3f8 + 5);
+    let mut data = PortWriteOnly::<u8>::new(0x3f8);
+    unsafe {
+        while line_sts.read() & LSR_THRE == 0 {
+            core::hint::spin_loop();
+        }
+        data.write(c);
+    }
+}
+
+fn register_irq3() -> bool {
+    register_irq_line(3)
+}
+
+fn register_irq4() -> bool {
+    register_irq_line(4)
+}
+
+/// Registers the handler of an ISA IRQ line of serial ports, if not yet.
+#[cfg(feature = "irq")]
+fn register_irq_line(irq_line: u8) -> bool {
+    use super::apic::vectors::IO_APIC_VECTOR_BASE;
+
+    static REGISTERED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+    let (registered, handler): (_, crate::irq::IrqHandler) = match irq_line {
+        3 => (&REGISTERED[0], &|_| irq3_handler()),
+        4 => (&REGISTERED[1], &|_| irq4_handler()),
+        _ => return false,
+    };
+    if registered.swap(true, Ordering::AcqRel) {
+        return true;
+    }
+    let vector = IO_APIC_VECTOR_BASE as usize + irq_line as usize;
+    if !crate::irq::register_handler(vector, handler) {
+        registered.store(false, Ordering::Release);
+        return false;
+    }
+    true
+}
+
+#[cfg(not(feature = "irq"))]
+fn register_irq_line(_irq_line: u8) -> bool {
+    false
+}
+
+/// Handles the interrupts of the ports sharing an ISA IRQ line, until none
+/// of them has one pending.
+///
+/// The line is edge-triggered, and stays asserted as long as one of the
+/// ports has an interrupt pending, so returning before all are handled
+/// would leave the line asserted with no more edges: no more interrupts.
+#[cfg(feature = "irq")]
+fn handle_irq_line(ports: [&PcSerialPort; 2]) {
+    loop {
+        for port in ports {
+            port.handle_irq();
+        }
+        if !ports.iter().any(|port| port.irq_pending()) {
+            break;
+        }
+    }
+}
+
+/// IRQ3 handler, shared by COM2 and COM4.
+#[cfg(feature = "irq")]
+fn irq3_handler() {
+    handle_irq_line([&COM2, &COM4]);
+}
+
+/// IRQ4 handler, shared by COM1 and COM3.
+#[cfg(feature = "irq")]
+fn irq4_handler() {
+    handle_irq_line([&COM1, &COM3]);
+}
+
+/// Runs the loopback test of COM1, see [`SerialPort::loopback_test`].
+#[allow(dead_code)]
+pub(crate) fn loopback_test() -> bool {
+    COM1.loopback_test()
+}
@@ -3,6 +3,7 @@ use raw_cpuid::CpuId;
 #[cfg(feature = "irq")]
 use int_ratio::Ratio;
 
+/// The frequency of the local APIC timer, if not reported by the hypervisor.
 #[cfg(feature = "irq")]
 const LAPIC_TICKS_PER_SEC: u64 = 1_000_000_000; // TODO: need to calibrate
 
@@ -40,8 +41,12 @@ pub fn epochoffset_nanos() -> u64 {
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
 #[cfg(feature = "irq")]
 pub fn set_oneshot_timer(deadline_ns: u64) {
-    let lapic = super::apic::local_apic();
     let now_ns = crate::time::monotonic_time_nanos();
+    if super::hyperv_stimer::is_enabled() {
+        super::hyperv_stimer::set_oneshot(deadline_ns.saturating_sub(now_ns));
+        return;
+    }
+    let lapic = super::apic::local_apic();
     unsafe {
         if now_ns < deadline_ns {
             let apic_ticks = NANOS_TO_LAPIC_TICKS_RATIO.mul_trunc(deadline_ns - now_ns);
@@ -63,6 +68,14 @@ pub(super) fn init_early() {
         unsafe { CPU_FREQ_MHZ = freq as u64 }
     }
 
+    if let Some(hv) = super::hypervisor::hypervisor() {
+        axlog::ax_println!("Running on hypervisor: {:?}", hv);
+        if let Some(freq) = super::hypervisor::tsc_frequency() {
+            axlog::ax_println!("Got TSC frequency from the hypervisor: {} Hz", freq);
+            unsafe { CPU_FREQ_MHZ = freq / 1_000_000 }
+        }
+    }
+
     unsafe {
         INIT_TICK = core::arch::x86_64::_rdtsc();
     }
@@ -84,14 +97,20 @@ pub(super) fn init_primary() {
     #[cfg(feature = "irq")]
     unsafe {
         use x2apic::lapic::{TimerDivide, TimerMode};
+        if super::hyperv_stimer::init(super::apic::vectors::APIC_TIMER_VECTOR, true) {
+            axlog::ax_println!("Using the Hyper-V synthetic timers");
+            return;
+        }
         let lapic = super::apic::local_apic();
         lapic.set_timer_mode(TimerMode::OneShot);
         lapic.set_timer_divide(TimerDivide::Div256); // indeed it is Div1, the name is confusing.
         lapic.enable_timer();
 
-        // TODO: calibrate with HPET
+        // TODO: calibrate with HPET on bare metal
+        let lapic_ticks_per_sec =
+            super::hypervisor::apic_timer_frequency().unwrap_or(LAPIC_TICKS_PER_SEC);
         NANOS_TO_LAPIC_TICKS_RATIO = Ratio::new(
-            LAPIC_TICKS_PER_SEC as u32,
+            lapic_ticks_per_sec as u32,
             crate::time::NANOS_PER_SEC as u32,
         );
     }
@@ -101,6 +120,9 @@ pub(super) fn init_primary() {
 pub(super) fn init_secondary() {
     #[cfg(feature = "irq")]
     unsafe {
+        if super::hyperv_stimer::init(super::apic::vectors::APIC_TIMER_VECTOR, false) {
+            return;
+        }
         super::apic::local_apic().enable_timer();
     }
 }
@@ -0,0 +1,196 @@
+//! Runtime power management of devices.
+//!
+//! Drivers register their devices with [`register`], under a parent device
+//! if any (e.g., a bus controller), which forms a tree. A device is either
+//! [`Active`](PowerState::Active) or [`Suspended`](PowerState::Suspended):
+//!
+//! - The idle detector, [`suspend_idle`], called periodically, suspends the
+//!   devices not used for a while and reporting that they are idle, once all
+//!   their children are suspended.
+//! - Drivers call [`mark_busy`] when the device is used, which resumes it,
+//!   and its parents first, if it is suspended.
+//! - A system suspend suspends all the devices with [`suspend_all`], children
+//!   first, and resumes them with [`resume_all`], parents first.
+//!
+//! The drivers must still be correct if their device is used while it is
+//! suspended, as [`mark_busy`] may race with [`suspend_idle`], e.g., by
+//! resuming it themselves under their lock.
+
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::time::monotonic_time_nanos;
+
+/// The maximum number of devices registered.
+pub const MAX_DEVICES: usize = 32;
+
+/// The runtime power management callbacks of a device.
+///
+/// They are called with the device table locked, so they must not wait for
+/// locks, log, or mark devices busy.
+pub trait DevicePm: Sync {
+    /// Returns whether the device is idle, e.g., has no transfers in
+    /// progress, so it can be suspended.
+    fn is_idle(&self) -> bool {
+        true
+    }
+
+    /// Puts the device in a low-power state. It is only called if
+    /// [`DevicePm::is_idle`] returned `true`.
+    fn suspend(&self);
+
+    /// Brings the device back from the low-power state.
+    fn resume(&self);
+}
+
+/// The power state of a device.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// Running.
+    Active = 0,
+    /// In a low-power state.
+    Suspended = 1,
+}
+
+/// The ID of a registered device.
+pub type PmId = usize;
+
+struct Device {
+    name: &'static str,
+    ops: &'static dyn DevicePm,
+    parent: Option<PmId>,
+}
+
+/// The devices, in the order of registration, so parents come before their
+/// children.
+static DEVICES: SpinNoIrq<[Option<Device>; MAX_DEVICES]> =
+    SpinNoIrq::new([const { None }; MAX_DEVICES]);
+
+static STATES: [AtomicU8; MAX_DEVICES] = [const { AtomicU8::new(0) }; MAX_DEVICES];
+static LAST_BUSY: [AtomicU64; MAX_DEVICES] = [const { AtomicU64::new(0) }; MAX_DEVICES];
+
+/// Registers a device, active, under `parent` if it is given.
+///
+/// Returns the ID of the device, or `None` if there are already
+/// [`MAX_DEVICES`] devices, or `parent` is not registered.
+pub fn register(
+    name: &'static str,
+    ops: &'static dyn DevicePm,
+    parent: Option<PmId>,
+) -> Option<PmId> {
+    let mut devices = DEVICES.lock();
+    if parent.is_some_and(|p| devices.get(p).is_none_or(Option::is_none)) {
+        return None;
+    }
+    let id = devices.iter().position(Option::is_none)?;
+    STATES[id].store(PowerState::Active as u8, Ordering::Relaxed);
+    LAST_BUSY[id].store(monotonic_time_nanos(), Ordering::Relaxed);
+    devices[id] = Some(Device { name, ops, parent });
+    Some(id)
+}
+
+/// Returns the power state of a device.
+pub fn state(id: PmId) -> PowerState {
+    match STATES[id].load(Ordering::Acquire) {
+        0 => PowerState::Active,
+        _ => PowerState::Suspended,
+    }
+}
+
+/// Records that a device is used, resuming it, and its parents first, if it
+/// is suspended.
+///
+/// It is cheap if the device is active, so it can be called on each use.
+pub fn mark_busy(id: PmId) {
+    LAST_BUSY[id].store(monotonic_time_nanos(), Ordering::Relaxed);
+    if state(id) == PowerState::Suspended {
+        resume(&DEVICES.lock(), id);
+    }
+}
+
+fn resume(devices: &[Option<Device>; MAX_DEVICES], id: PmId) {
+    let Some(dev) = &devices[id] else {
+        return;
+    };
+    if let Some(parent) = dev.parent {
+        LAST_BUSY[parent].store(monotonic_time_nanos(), Ordering::Relaxed);
+        resume(devices, parent);
+    }
+    if state(id) == PowerState::Suspended {
+        dev.ops.resume();
+        STATES[id].store(PowerState::Active as u8, Ordering::Release);
+    }
+}
+
+/// Suspends the device if it is active and all its children are suspended.
+fn try_suspend(devices: &[Option<Device>; MAX_DEVICES], id: PmId) -> bool {
+    let Some(dev) = &devices[id] else {
+        return false;
+    };
+    if state(id) == PowerState::Suspended {
+        return true;
+    }
+    let children_suspended = devices.iter().enumerate().all(|(child, d)| {
+        d.as_ref().is_none_or(|d| d.parent != Some(id)) || state(child) == PowerState::Suspended
+    });
+    if !children_suspended || !dev.ops.is_idle() {
+        return false;
+    }
+    dev.ops.suspend();
+    STATES[id].store(PowerState::Suspended as u8, Ordering::Release);
+    true
+}
+
+/// Suspends the devices not used for `delay_nanos` that are idle, children
+/// first.
+///
+/// Returns the number of devices suspended.
+pub fn suspend_idle(delay_nanos: u64) -> usize {
+    let devices = DEVICES.lock();
+    let now = monotonic_time_nanos();
+    let mut count = 0;
+    for id in (0..MAX_DEVICES).rev() {
+        if devices[id].is_none() || state(id) == PowerState::Suspended {
+            continue;
+        }
+        let idle_for = now.saturating_sub(LAST_BUSY[id].load(Ordering::Relaxed));
+        if idle_for >= delay_nanos && try_suspend(&devices, id) {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Suspends all the devices, children first, for a system suspend.
+///
+/// Returns `false`, with the devices suspended so far left suspended, if a
+/// device is not idle.
+pub fn suspend_all() -> bool {
+    let devices = DEVICES.lock();
+    (0..MAX_DEVICES)
+        .rev()
+        .filter(|&id| devices[id].is_some())
+        .all(|id| try_suspend(&devices, id))
+}
+
+/// Resumes all the devices, parents first, after a system suspend.
+pub fn resume_all() {
+    let devices = DEVICES.lock();
+    for id in 0..MAX_DEVICES {
+        LAST_BUSY[id].store(monotonic_time_nanos(), Ordering::Relaxed);
+        resume(&devices, id);
+    }
+}
+
+/// Calls `f` with the ID, the name and the power state of each device, in
+/// the order of registration.
+pub fn for_each_device(mut f: impl FnMut(PmId, &'static str, PowerState)) {
+    let devices = DEVICES.lock();
+    for (id, dev) in devices.iter().enumerate() {
+        if let Some(dev) = dev {
+            f(id, dev.name, state(id));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Read-copy-update (RCU) protection of functions replaced at run time.
+//!
+//! Code calling a function which may be replaced, e.g., the dispatcher of
+//! IRQ handlers, runs it in a read-side critical section ([`read_lock`]),
+//! which is cheap and never waits. Code replacing it swaps the pointer and
+//! then calls [`synchronize`], which waits until every CPU has left the
+//! critical sections it was in, so the old function is no longer running
+//! anywhere when it returns, and can be released, e.g., with the driver it
+//! belongs to.
+//!
+//! Critical sections can be nested, e.g., an IRQ handler interrupting a
+//! reader, and run with preemption disabled, so they must not block.
+//! [`RcuCell`] combines both sides for a function pointer, and [`RcuSlot`]
+//! for larger values, e.g., a reference to a closure.
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel_guard::NoPreempt;
+use kspin::SpinNoPreempt;
+
+use crate::cpu::this_cpu_id;
+
+/// The read-side state of a CPU.
+struct RcuCpu {
+    /// The nesting depth of the critical sections the CPU is in.
+    depth: AtomicUsize,
+    /// The number of times the CPU left its outermost critical section.
+    quiescent: AtomicUsize,
+}
+
+static CPUS: [RcuCpu; axconfig::SMP] = [const {
+    RcuCpu {
+        depth: AtomicUsize::new(0),
+        quiescent: AtomicUsize::new(0),
+    }
+}; axconfig::SMP];
+
+/// A read-side critical section, left when it is dropped.
+pub struct RcuReadGuard {
+    cpu: usize,
+    /// Preemption is enabled again after the depth is decreased.
+    _guard: NoPreempt,
+    /// The guard must be dropped on the CPU it was created on.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Enters a read-side critical section, in which the functions read from
+/// [`RcuCell`]s are not released.
+pub fn read_lock() -> RcuReadGuard {
+    let guard = NoPreempt::new();
+    let cpu = this_cpu_id();
+    CPUS[cpu].depth.fetch_add(1, Ordering::SeqCst);
+    RcuReadGuard {
+        cpu,
+        _guard: guard,
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for RcuReadGuard {
+    fn drop(&mut self) {
+        let rcu = &CPUS[self.cpu];
+        if rcu.depth.fetch_sub(1, Ordering::SeqCst) == 1 {
+            rcu.quiescent.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Waits for a grace period, i.e., until every critical section entered
+/// before the call is left.
+///
+/// # Panics
+///
+/// Panics if it is called in a critical section, as it would wait for
+/// itself.
+pub fn synchronize() {
+    let snapshot: [usize; axconfig::SMP] =
+        core::array::from_fn(|cpu| CPUS[cpu].quiescent.load(Ordering::SeqCst));
+    for (cpu, rcu) in CPUS.iter().enumerate() {
+        if rcu.depth.load(Ordering::SeqCst) > 0 {
+            assert_ne!(
+                cpu,
+                this_cpu_id(),
+                "rcu::synchronize called in a read-side critical section"
+            );
+        }
+        while rcu.depth.load(Ordering::SeqCst) > 0
+            && rcu.quiescent.load(Ordering::SeqCst) == snapshot[cpu]
+        {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A value of the size of a pointer, e.g., an optional function pointer,
+/// which is read in read-side critical sections and can be replaced at run
+/// time.
+pub struct RcuCell<T: Copy> {
+    value: AtomicUsize,
+    _marker: PhantomData<T>,
+}
+
+/// Converts between a value of `T` and its bits.
+union Bits<T: Copy> {
+    value: T,
+    bits: usize,
+}
+
+impl<T: Copy> RcuCell<T> {
+    /// Creates a new cell with the given value.
+    ///
+    /// It fails to build if `T` does not have the size of a pointer.
+    pub const fn new(value: T) -> Self {
+        const { assert!(core::mem::size_of::<T>() == core::mem::size_of::<usize>()) };
+        // SAFETY: `T` has the size of `usize`.
+        let bits = unsafe { Bits { value }.bits };
+        Self {
+            value: AtomicUsize::new(bits),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the current value, which is not released while `_guard` is
+    /// held.
+    pub fn read(&self, _guard: &RcuReadGuard) -> T {
+        let bits = self.value.load(Ordering::Acquire);
+        // SAFETY: the bits are those of a value of `T`.
+        unsafe { Bits { bits }.value }
+    }
+
+    /// Replaces the value, and returns the old one once no CPU can be using
+    /// it anymore (see [`synchronize`]).
+    pub fn replace(&self, value: T) -> T {
+        // SAFETY: `T` has the size of `usize`.
+        let bits = unsafe { Bits { value }.bits };
+        let old = self.value.swap(bits, Ordering::SeqCst);
+        synchronize();
+        // SAFETY: the bits are those of a value of `T`.
+        unsafe { Bits { bits: old }.value }
+    }
+
+    /// Replaces the value if it is `current`, see [`replace`](Self::replace),
+    /// and returns whether it is replaced.
+    pub fn compare_replace(&self, current: T, value: T) -> bool {
+        // SAFETY: `T` has the size of `usize`.
+        let (current, bits) = unsafe { (Bits { value: current }.bits, Bits { value }.bits) };
+        let replaced = self
+            .value
+            .compare_exchange(current, bits, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if replaced {
+            synchronize();
+        }
+        replaced
+    }
+}
+
+/// Serializes the writers of all the [`RcuSlot`]s, which are rarely
+/// replaced.
+static SLOT_WRITER: SpinNoPreempt<()> = SpinNoPreempt::new(());
+
+/// A value of any size, e.g., a reference to a closure, which is read in
+/// read-side critical sections and can be replaced at run time.
+///
+/// It keeps two copies of the value, and the index of the current one in an
+/// [`RcuCell`]. A writer writes the other copy, which no reader uses since
+/// the grace period of the previous replacement, then switches to it.
+pub struct RcuSlot<T: Copy> {
+    copies: [UnsafeCell<T>; 2],
+    current: RcuCell<usize>,
+}
+
+// SAFETY: a copy is only written by the writer holding `SLOT_WRITER`, while
+// no reader uses it.
+unsafe impl<T: Copy + Send + Sync> Sync for RcuSlot<T> {}
+
+impl<T: Copy> RcuSlot<T> {
+    /// Creates a new slot with the given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            copies: [UnsafeCell::new(value), UnsafeCell::new(value)],
+            current: RcuCell::new(0),
+        }
+    }
+
+    /// Returns the current value, which is not released while `guard` is
+    /// held.
+    pub fn read(&self, guard: &RcuReadGuard) -> T {
+        let index = self.current.read(guard);
+        // SAFETY: the current copy is not written until a grace period after
+        // it is switched away from, which the guard delays.
+        unsafe { *self.copies[index].get() }
+    }
+
+    /// Replaces the value with the one returned by `f`, which is given the
+    /// current value, unless it returns `None`. Returns the old value if it
+    /// is replaced, once no CPU can be using it anymore (see [`synchronize`]).
+    ///
+    /// `f` runs with the writers of all the slots blocked, so it must be
+    /// short.
+    pub fn update(&self, f: impl FnOnce(T) -> Option<T>) -> Option<T> {
+        let _writer = SLOT_WRITER.lock();
+        let index = self.current.read(&read_lock());
+        // SAFETY: the writers are serialized, so the current copy is not
+        // written meanwhile.
+        let old = unsafe { *self.copies[index].get() };
+        let value = f(old)?;
+        // SAFETY: no reader uses the other copy since the previous
+        // replacement synchronized.
+        unsafe { *self.copies[1 - index].get() = value };
+        self.current.replace(1 - index);
+        Some(old)
+    }
+}
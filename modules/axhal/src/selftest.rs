@@ -0,0 +1,215 @@
+//! Boot-time self-tests of the hardware abstraction layer.
+//!
+//! [`run`] is called right after [`platform_init`](crate::platform_init) when
+//! the `selftest` feature is enabled. Each test prints one line in the form
+//! `[selftest] <name>: PASS|FAIL|SKIP (<detail>)`, followed by a summary line,
+//! so the report can be checked by scripts watching the serial output.
+
+use core::fmt;
+
+use crate::time::{Duration, busy_wait, monotonic_time, nanos_to_ticks, ticks_to_nanos};
+
+/// The result of a single self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The test passed.
+    Pass,
+    /// The test failed.
+    Fail,
+    /// The test is not supported on this platform or configuration.
+    Skip,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Fail => "FAIL",
+            Self::Skip => "SKIP",
+        })
+    }
+}
+
+/// The summary of all self-tests.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Report {
+    /// Number of passed tests.
+    pub passed: usize,
+    /// Number of failed tests.
+    pub failed: usize,
+    /// Number of skipped tests.
+    pub skipped: usize,
+}
+
+impl Report {
+    fn record(&mut self, name: &str, outcome: Outcome, detail: fmt::Arguments) {
+        axlog::ax_println!("[selftest] {}: {} ({})", name, outcome, detail);
+        match outcome {
+            Outcome::Pass => self.passed += 1,
+            Outcome::Fail => self.failed += 1,
+            Outcome::Skip => self.skipped += 1,
+        }
+    }
+}
+
+/// Checks that the monotonic clock advances at the expected rate while
+/// busy-waiting, and that tick/nanosecond conversions are consistent.
+fn test_timer(report: &mut Report) {
+    const WAIT: Duration = Duration::from_millis(10);
+    const TOLERANCE: Duration = Duration::from_millis(5);
+
+    let start = monotonic_time();
+    busy_wait(WAIT);
+    let elapsed = monotonic_time() - start;
+    let outcome = if elapsed >= WAIT && elapsed <= WAIT + TOLERANCE {
+        Outcome::Pass
+    } else {
+        Outcome::Fail
+    };
+    report.record(
+        "timer.busy_wait",
+        outcome,
+        format_args!("expected {:?}, got {:?}", WAIT, elapsed),
+    );
+
+    let nanos = 1_000_000_000;
+    let round_trip = ticks_to_nanos(nanos_to_ticks(nanos));
+    let outcome = if round_trip.abs_diff(nanos) <= 1_000 {
+        Outcome::Pass
+    } else {
+        Outcome::Fail
+    };
+    report.record(
+        "timer.conversion",
+        outcome,
+        format_args!("{} ns -> {} ns", nanos, round_trip),
+    );
+}
+
+/// Registers a handler for a spare vector and triggers it with a self-IPI,
+/// then unregisters it.
+#[cfg(feature = "irq")]
+fn test_irq(report: &mut Report) {
+    cfg_if::cfg_if! {
+        if #[cfg(platform_family = "x86-pc")] {
+            use core::sync::atomic::{AtomicUsize, Ordering};
+
+            const SELFTEST_VECTOR: usize = 0xf3;
+            static HITS: AtomicUsize = AtomicUsize::new(0);
+
+            HITS.store(0, Ordering::Relaxed);
+            if !crate::irq::register_handler(SELFTEST_VECTOR, &|_| {
+                HITS.fetch_add(1, Ordering::Relaxed);
+            }) {
+                report.record("irq.self_ipi", Outcome::Fail, format_args!("register failed"));
+                return;
+            }
+
+            let irqs_enabled = crate::asm::irqs_enabled();
+            crate::asm::enable_irqs();
+            crate::platform::irq::send_ipi_self(SELFTEST_VECTOR);
+            let deadline = monotonic_time() + Duration::from_millis(10);
+            while HITS.load(Ordering::Relaxed) == 0 && monotonic_time() < deadline {
+                core::hint::spin_loop();
+            }
+            if !irqs_enabled {
+                crate::asm::disable_irqs();
+            }
+            crate::irq::unregister_handler(SELFTEST_VECTOR);
+
+            let hits = HITS.load(Ordering::Relaxed);
+            let outcome = if hits == 1 { Outcome::Pass } else { Outcome::Fail };
+            report.record("irq.self_ipi", outcome, format_args!("{} interrupt(s) received", hits));
+        } else {
+            report.record("irq.self_ipi", Outcome::Skip, format_args!("no self-IPI support"));
+        }
+    }
+}
+
+/// Sends a byte through the UART in its internal loopback mode.
+fn test_console(report: &mut Report) {
+    cfg_if::cfg_if! {
+        if #[cfg(platform_family = "x86-pc")] {
+            let outcome = if crate::console::loopback_test() {
+                Outcome::Pass
+            } else {
+                Outcome::Fail
+            };
+            report.record("console.loopback", outcome, format_args!("16550 loopback mode"));
+        } else {
+            report.record("console.loopback", Outcome::Skip, format_args!("no loopback mode"));
+        }
+    }
+}
+
+/// Maps, queries, protects and unmaps a page in a scratch page table.
+#[cfg(feature = "paging")]
+fn test_paging(report: &mut Report) {
+    use crate::mem::{PAGE_SIZE_4K, VirtAddr, virt_to_phys};
+    use crate::paging::{MappingFlags, PageSize, PageTable, PagingError, PagingResult};
+
+    fn map_unmap() -> PagingResult<&'static str> {
+        const TEST_VADDR: usize = 0x1000_0000;
+        let vaddr = VirtAddr::from(TEST_VADDR);
+        let frame = axalloc::global_allocator()
+            .alloc_pages(1, PAGE_SIZE_4K)
+            .map_err(|_| PagingError::NoMemory)?;
+        let paddr = virt_to_phys(frame.into());
+
+        let mut pt = PageTable::try_new()?;
+        let result = (|| {
+            let rw = MappingFlags::READ | MappingFlags::WRITE;
+            pt.map(vaddr, paddr, PageSize::Size4K, rw)?.ignore();
+            let (pa, flags, _) = pt.query(vaddr)?;
+            if pa != paddr || !flags.contains(rw) {
+                return Ok("query after map mismatch");
+            }
+            pt.protect(vaddr, MappingFlags::READ)?.1.ignore();
+            let (_, flags, _) = pt.query(vaddr)?;
+            if flags.contains(MappingFlags::WRITE) {
+                return Ok("page still writable after protect");
+            }
+            let (pa, _, tlb) = pt.unmap(vaddr)?;
+            tlb.ignore();
+            if pa != paddr || pt.query(vaddr).is_ok() {
+                return Ok("page still mapped after unmap");
+            }
+            Ok("")
+        })();
+        axalloc::global_allocator().dealloc_pages(frame, 1);
+        result
+    }
+
+    match map_unmap() {
+        Ok("") => report.record(
+            "paging.map_unmap",
+            Outcome::Pass,
+            format_args!("map/query/protect/unmap"),
+        ),
+        Ok(msg) => report.record("paging.map_unmap", Outcome::Fail, format_args!("{}", msg)),
+        Err(e) => report.record("paging.map_unmap", Outcome::Fail, format_args!("{:?}", e)),
+    }
+}
+
+/// Runs all self-tests and prints a report.
+///
+/// Returns the summary of the results.
+pub fn run() -> Report {
+    axlog::ax_println!("[selftest] running HAL self-tests...");
+    let mut report = Report::default();
+
+    test_timer(&mut report);
+    #[cfg(feature = "irq")]
+    test_irq(&mut report);
+    test_console(&mut report);
+    #[cfg(feature = "paging")]
+    test_paging(&mut report);
+
+    axlog::ax_println!(
+        "[selftest] summary: {} passed, {} failed, {} skipped",
+        report.passed,
+        report.failed,
+        report.skipped
+    );
+    report
+}
@@ -8,9 +8,11 @@ pub use core::time::Duration;
 /// represent a duration, but a clock time.
 pub type TimeValue = Duration;
 
+#[cfg(feature = "cpuidle")]
+pub use crate::cpuidle::set_oneshot_timer;
 #[cfg(feature = "irq")]
 pub use crate::platform::irq::TIMER_IRQ_NUM;
-#[cfg(feature = "irq")]
+#[cfg(all(feature = "irq", not(feature = "cpuidle")))]
 pub use crate::platform::time::set_oneshot_timer;
 pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
 
@@ -0,0 +1,279 @@
+//! Hardware watchpoints (data breakpoints), which report the writes to a
+//! range of memory, e.g., to find what corrupts it without an external
+//! debugger.
+//!
+//! They are set in the debug registers of the CPU, which trap after the
+//! write, and a handler set with [`set_hit_handler`] is called in exception
+//! context with the [`WatchHit`]. Only x86_64 is supported, with up to
+//! [`MAX_WATCHPOINTS`] watchpoints of 1, 2, 4 or 8 bytes, aligned to their
+//! length.
+//!
+//! The debug registers are per CPU: a watchpoint is loaded into those of the
+//! CPU setting it at once, and into those of the others when they call
+//! [`sync_this_cpu`], e.g., on timer ticks.
+//!
+//! The backtraces of the hits follow the frame pointers, so they are only
+//! complete if the kernel is built with them (`FRAME_POINTERS=y`).
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::cpu::this_cpu_id;
+
+/// The maximum number of watchpoints set at the same time.
+pub const MAX_WATCHPOINTS: usize = 4;
+
+/// A watched range of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The virtual address of the range.
+    pub addr: usize,
+    /// The length of the range in bytes.
+    pub len: usize,
+}
+
+/// A write to a watched range.
+#[derive(Debug, Clone, Copy)]
+pub struct WatchHit {
+    /// The index of the watchpoint, as returned by [`set`].
+    pub index: usize,
+    /// The watched range.
+    pub watchpoint: Watchpoint,
+    /// The address of the instruction after the one which wrote.
+    pub pc: usize,
+    /// The frame pointer of the code which wrote.
+    fp: usize,
+    /// The stack pointer of the code which wrote.
+    sp: usize,
+}
+
+impl WatchHit {
+    /// Returns the return addresses of the functions the write was made in,
+    /// innermost first, found by following the frame pointers.
+    ///
+    /// It stops at the first frame pointer which is not on the stack of the
+    /// code which wrote, so it is empty if the kernel is built without frame
+    /// pointers.
+    pub fn backtrace(&self) -> impl Iterator<Item = usize> {
+        crate::backtrace::walk(self.fp, self.sp)
+    }
+}
+
+static WATCHPOINTS: SpinNoIrq<[Option<Watchpoint>; MAX_WATCHPOINTS]> =
+    SpinNoIrq::new([None; MAX_WATCHPOINTS]);
+/// Incremented each time the watchpoints change.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// The generation of the watchpoints in the debug registers of each CPU.
+static LOADED: [AtomicUsize; axconfig::SMP] = [const { AtomicUsize::new(0) }; axconfig::SMP];
+static HIT_HANDLER: SpinNoIrq<Option<fn(&WatchHit)>> = SpinNoIrq::new(None);
+
+/// Whether the CPU has hardware watchpoints supported here.
+pub fn is_supported() -> bool {
+    cfg!(target_arch = "x86_64")
+}
+
+/// Watches the writes to `len` bytes at `addr`, and returns the index of the
+/// watchpoint.
+///
+/// Returns `None` if `len` is not 1, 2, 4 or 8, `addr` is not aligned to it,
+/// all the watchpoints are in use, or they are not supported.
+pub fn set(addr: usize, len: usize) -> Option<usize> {
+    if !is_supported() || !matches!(len, 1 | 2 | 4 | 8) || addr % len != 0 {
+        return None;
+    }
+    let mut watchpoints = WATCHPOINTS.lock();
+    let index = watchpoints.iter().position(Option::is_none)?;
+    watchpoints[index] = Some(Watchpoint { addr, len });
+    GENERATION.fetch_add(1, Ordering::Release);
+    arch::install_trap_handler();
+    load(&watchpoints);
+    Some(index)
+}
+
+/// Removes a watchpoint, and returns it, or `None` if it is not set.
+pub fn clear(index: usize) -> Option<Watchpoint> {
+    let mut watchpoints = WATCHPOINTS.lock();
+    let old = watchpoints.get_mut(index)?.take()?;
+    GENERATION.fetch_add(1, Ordering::Release);
+    load(&watchpoints);
+    Some(old)
+}
+
+/// Returns the watchpoints, indexed as returned by [`set`].
+pub fn list() -> [Option<Watchpoint>; MAX_WATCHPOINTS] {
+    *WATCHPOINTS.lock()
+}
+
+/// Sets the function called when a watched range is written, e.g., to print
+/// the task which wrote it.
+///
+/// It is called in exception context, after the write, and must not take the
+/// locks the code which wrote may hold.
+pub fn set_hit_handler(handler: fn(&WatchHit)) {
+    *HIT_HANDLER.lock() = Some(handler);
+}
+
+/// Loads the watchpoints into the debug registers of the calling CPU if they
+/// changed since it last did.
+pub fn sync_this_cpu() {
+    if LOADED[this_cpu_id()].load(Ordering::Relaxed) != GENERATION.load(Ordering::Acquire) {
+        load(&WATCHPOINTS.lock());
+    }
+}
+
+/// Loads the watchpoints into the debug registers of the calling CPU, with
+/// the lock held.
+fn load(watchpoints: &[Option<Watchpoint>; MAX_WATCHPOINTS]) {
+    arch::load(watchpoints);
+    LOADED[this_cpu_id()].store(GENERATION.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Reports the hits of the watchpoints whose bits are set in `hits`.
+#[allow(dead_code)]
+fn report(hits: usize, pc: usize, fp: usize, sp: usize) {
+    let handler = *HIT_HANDLER.lock();
+    for (index, watchpoint) in list().into_iter().enumerate() {
+        let Some(watchpoint) = watchpoint.filter(|_| hits & (1 << index) != 0) else {
+            continue;
+        };
+        let hit = WatchHit {
+            index,
+            watchpoint,
+            pc,
+            fp,
+            sp,
+        };
+        match handler {
+            Some(handler) => handler(&hit),
+            None => warn!("watchpoint {} written at {:#x}", index, pc),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use core::arch::{asm, global_asm};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{MAX_WATCHPOINTS, Watchpoint};
+
+    /// The vector of the debug exception (#DB).
+    const DEBUG_VECTOR: usize = 1;
+    /// The value of DR6 with no condition detected, i.e., its reserved bits.
+    const DR6_CLEAR: usize = 0xffff_0ff0;
+
+    // The debug exception has no error code. The CPU aligns the stack to 16
+    // bytes before pushing the 5 words of the interrupt frame, so it is
+    // aligned again after 9 registers are saved.
+    global_asm!(
+        ".global watch_debug_entry",
+        "watch_debug_entry:",
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "mov rdi, [rsp + 9 * 8]", // RIP
+        "mov rsi, rbp",
+        "mov rdx, [rsp + 12 * 8]", // RSP
+        "cld",
+        "call {handler}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        "iretq",
+        handler = sym handle_debug_exception,
+    );
+
+    unsafe extern "C" {
+        fn watch_debug_entry();
+    }
+
+    extern "C" fn handle_debug_exception(pc: usize, fp: usize, sp: usize) {
+        let dr6: usize;
+        unsafe {
+            asm!("mov {}, dr6", out(reg) dr6);
+            // It is not cleared by the CPU.
+            asm!("mov dr6, {}", in(reg) DR6_CLEAR);
+        }
+        super::report(dr6 & 0xf, pc, fp, sp);
+    }
+
+    /// Points the debug exception at [`watch_debug_entry`] in the IDT set up
+    /// by `axcpu`, which panics on it, if not yet.
+    ///
+    /// The IDT is shared by all the CPUs.
+    pub fn install_trap_handler() {
+        static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+        if INSTALLED.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let mut idtr = [0u8; 10];
+        unsafe { asm!("sidt [{}]", in(reg) idtr.as_mut_ptr(), options(nostack, preserves_flags)) };
+        let base = u64::from_le_bytes(idtr[2..].try_into().unwrap()) as usize;
+        let entry = (base + DEBUG_VECTOR * 16) as *mut u8;
+        let offset = watch_debug_entry as usize;
+        // Only the offset changes: the gate stays an interrupt gate of the
+        // kernel code segment.
+        unsafe {
+            (entry as *mut u16).write_volatile(offset as u16);
+            (entry.add(6) as *mut u16).write_volatile((offset >> 16) as u16);
+            (entry.add(8) as *mut u32).write_volatile((offset >> 32) as u32);
+        }
+    }
+
+    /// Writes the watchpoints into DR0-DR3, enabled in DR7 to break on data
+    /// writes.
+    pub fn load(watchpoints: &[Option<Watchpoint>; MAX_WATCHPOINTS]) {
+        let mut dr7 = 0;
+        for (index, watchpoint) in watchpoints.iter().enumerate() {
+            let Some(Watchpoint { addr, len }) = *watchpoint else {
+                continue;
+            };
+            // R/W = 01 (data writes), LEN = 00, 01, 11 or 10 for 1, 2, 4 or 8
+            // bytes.
+            let len_bits = match len {
+                1 => 0b00,
+                2 => 0b01,
+                4 => 0b11,
+                _ => 0b10,
+            };
+            dr7 |= 1 << (index * 2 + 1); // Gn
+            dr7 |= (0b01 | len_bits << 2) << (16 + index * 4);
+            unsafe { write_dr(index, addr) };
+        }
+        unsafe { asm!("mov dr7, {}", in(reg) dr7) };
+    }
+
+    unsafe fn write_dr(index: usize, addr: usize) {
+        unsafe {
+            match index {
+                0 => asm!("mov dr0, {}", in(reg) addr),
+                1 => asm!("mov dr1, {}", in(reg) addr),
+                2 => asm!("mov dr2, {}", in(reg) addr),
+                _ => asm!("mov dr3, {}", in(reg) addr),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod arch {
+    use super::{MAX_WATCHPOINTS, Watchpoint};
+
+    pub fn install_trap_handler() {}
+
+    pub fn load(_watchpoints: &[Option<Watchpoint>; MAX_WATCHPOINTS]) {}
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use axhal::console::{self, ConsoleMode};
+use axhal::dummy;
+use axhal::irq;
+use axhal::time::{self, TIMER_IRQ_NUM};
+
+const TEST_IRQ: usize = 10;
+
+static TIMER_IRQS: AtomicUsize = AtomicUsize::new(0);
+static TEST_IRQS: AtomicUsize = AtomicUsize::new(0);
+
+fn take_output() -> Vec<u8> {
+    let mut buf = [0; 4096];
+    let len = dummy::take_output(&mut buf);
+    buf[..len].to_vec()
+}
+
+#[test]
+fn test_console() {
+    console::write_bytes(b"hello\n");
+    assert_eq!(take_output(), b"hello\r\n");
+
+    // A line is echoed, and only returned once it is ended.
+    let mut buf = [0; 16];
+    dummy::push_input(b"hi");
+    assert_eq!(console::read_bytes(&mut buf), 0);
+    dummy::push_input(b"\r");
+    let len = console::read_bytes(&mut buf);
+    assert_eq!(&buf[..len], b"hi\n");
+    assert_eq!(take_output(), b"hi\r\n");
+
+    console::set_mode(ConsoleMode::Raw);
+    dummy::push_input(b"ab\x7f");
+    let len = console::read_bytes(&mut buf);
+    assert_eq!(&buf[..len], b"ab\x7f");
+    assert!(take_output().is_empty());
+    assert_eq!(console::read_bytes(&mut buf), 0);
+    console::set_mode(ConsoleMode::Cooked);
+}
+
+#[test]
+fn test_irq() {
+    assert!(!dummy::raise(TEST_IRQ));
+    assert!(dummy::is_pending(TEST_IRQ));
+
+    // Registering the handler enables the IRQ, which dispatches it.
+    assert!(irq::register_handler(TEST_IRQ, &|irq_num| {
+        assert_eq!(irq_num, TEST_IRQ);
+        TEST_IRQS.fetch_add(1, Ordering::Relaxed);
+    }));
+    assert!(!dummy::is_pending(TEST_IRQ));
+    assert_eq!(TEST_IRQS.load(Ordering::Relaxed), 1);
+
+    assert!(dummy::raise(TEST_IRQ));
+    assert_eq!(TEST_IRQS.load(Ordering::Relaxed), 2);
+
+    irq::set_enable(TEST_IRQ, false);
+    assert!(!dummy::raise(TEST_IRQ));
+    assert_eq!(TEST_IRQS.load(Ordering::Relaxed), 2);
+    irq::set_enable(TEST_IRQ, true);
+    assert_eq!(TEST_IRQS.load(Ordering::Relaxed), 3);
+
+    let count = irq::irq_counts().find(|&(irq_num, _)| irq_num == TEST_IRQ);
+    assert_eq!(count, Some((TEST_IRQ, 3)));
+}
+
+#[test]
+fn test_timer() {
+    assert!(irq::register_handler(TIMER_IRQ_NUM, &|_| {
+        TIMER_IRQS.fetch_add(1, Ordering::Relaxed);
+    }));
+
+    let start = time::current_ticks();
+    dummy::advance(1000);
+    assert_eq!(time::current_ticks(), start + 1000);
+    assert_eq!(time::ticks_to_nanos(time::current_ticks()), start + 1000);
+    assert_eq!(TIMER_IRQS.load(Ordering::Relaxed), 0);
+
+    time::set_oneshot_timer(start + 3000);
+    dummy::advance(1999);
+    assert_eq!(TIMER_IRQS.load(Ordering::Relaxed), 0);
+    dummy::advance(1);
+    assert_eq!(TIMER_IRQS.load(Ordering::Relaxed), 1);
+
+    // The timer is one-shot.
+    dummy::advance(10_000);
+    assert_eq!(TIMER_IRQS.load(Ordering::Relaxed), 1);
+
+    // A deadline already passed fires at once.
+    time::set_oneshot_timer(start);
+    assert_eq!(TIMER_IRQS.load(Ordering::Relaxed), 2);
+}
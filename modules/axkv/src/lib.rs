@@ -0,0 +1,82 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) key-value store module.
+//!
+//! It keeps small key-value pairs, e.g., configurations and counters, on a
+//! block device across reboots, without a filesystem. Updates are appended to
+//! a log with a CRC each, so they survive crashes (see [`KvStore`]).
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod store;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use axdriver::{AxDeviceContainer, prelude::*};
+use axerrno::{AxError, AxResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+
+pub use self::store::{KvStore, MAX_KEY_LEN};
+
+static KV_STORE: LazyInit<Mutex<KvStore<AxBlockDevice>>> = LazyInit::new();
+
+/// Initializes the key-value store on the last block device.
+///
+/// The last one is used, so the first one is left to the filesystem if there
+/// are more than one. If there is no block device, the store is not available
+/// and all operations fail.
+pub fn init_kv(blk_devs: &mut AxDeviceContainer<AxBlockDevice>) {
+    info!("Initialize key-value store...");
+
+    let Some(dev) = blk_devs.take_last() else {
+        warn!("  no block device found for the key-value store");
+        return;
+    };
+    info!("  use block device: {:?}", dev.device_name());
+    match KvStore::open(dev) {
+        Ok(store) => {
+            info!("  {} keys found", store.len());
+            KV_STORE.init_once(Mutex::new(store));
+        }
+        Err(e) => warn!("  failed to open the key-value store: {:?}", e),
+    }
+}
+
+fn with_store<T>(f: impl FnOnce(&mut KvStore<AxBlockDevice>) -> AxResult<T>) -> AxResult<T> {
+    let store = KV_STORE.get().ok_or(AxError::BadState)?;
+    f(&mut store.lock())
+}
+
+/// Returns the value of the given key.
+pub fn get(key: &str) -> AxResult<Option<Vec<u8>>> {
+    with_store(|store| Ok(store.get(key).map(Vec::from)))
+}
+
+/// Sets the value of the given key, and writes it to the device before
+/// returning.
+pub fn put(key: &str, value: &[u8]) -> AxResult {
+    with_store(|store| store.put(key, value))
+}
+
+/// Removes the given key, and writes the removal to the device before
+/// returning.
+pub fn remove(key: &str) -> AxResult {
+    with_store(|store| store.remove(key))
+}
+
+/// Returns all keys, in order.
+pub fn keys() -> AxResult<Vec<String>> {
+    with_store(|store| Ok(store.keys().map(String::from).collect()))
+}
+
+/// Rewrites the log of the store with the live entries only.
+///
+/// It is done when the log is full, so there is no need to call it, unless
+/// to do it at a convenient time.
+pub fn compact() -> AxResult {
+    with_store(|store| store.compact())
+}
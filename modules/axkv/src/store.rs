@@ -0,0 +1,356 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axcrypto::crc32::crc32;
+use axdriver::prelude::*;
+use axerrno::{AxError, AxResult, ax_err};
+
+const BLOCK_SIZE: usize = 512;
+
+/// `"AXKV"` in little-endian.
+const MAGIC: u32 = 0x564b_5841;
+
+/// The size of the header of each record.
+const RECORD_HEADER_SIZE: usize = 16;
+/// The record removes the key.
+const FLAG_REMOVED: u16 = 1;
+
+/// The maximum length of keys, in bytes.
+pub const MAX_KEY_LEN: usize = 255;
+
+/// A key-value store kept as a log of updates on a block device.
+///
+/// The device is split into two regions of the same size. One of them holds
+/// the current log, and the other is where the live entries are written to
+/// when the log is compacted, i.e., when it is full.
+///
+/// The first block of each region is its header, with a magic number, the
+/// generation of its log and a CRC. Records are appended after it, each
+/// with the layout below (little-endian):
+///
+/// | Offset | Size      | Field                                          |
+/// |--------|-----------|------------------------------------------------|
+/// | 0      | 4         | CRC-32 of the rest of the record               |
+/// | 4      | 4         | Generation of the log                          |
+/// | 8      | 2         | Length of the key                              |
+/// | 10     | 2         | Flags, 1 if the key is removed                 |
+/// | 12     | 4         | Length of the value                            |
+/// | 16     | key len   | Key (UTF-8)                                    |
+/// | ...    | value len | Value                                          |
+///
+/// On opening, the log of the valid header with the highest generation is
+/// replayed up to the first record with a bad CRC or another generation,
+/// which is where the next record is appended. So a record torn by a crash
+/// is discarded, along with the update it records. A compaction writes the
+/// header of the new log last, so the old log stays current until the new
+/// one is complete.
+pub struct KvStore<D> {
+    dev: D,
+    /// The number of blocks of each region.
+    region_blocks: u64,
+    /// The region holding the current log, 0 or 1.
+    active: u64,
+    /// The generation of the current log, increased by each compaction.
+    generation: u32,
+    /// The end of the current log, in bytes from the start of its region.
+    end: usize,
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl<D: BlockDriverOps> KvStore<D> {
+    /// Opens the store on the given device, and formats the device if it
+    /// does not hold a store.
+    pub fn open(dev: D) -> AxResult<Self> {
+        if dev.block_size() != BLOCK_SIZE {
+            return ax_err!(InvalidInput, "unsupported block size");
+        }
+        let region_blocks = dev.num_blocks() / 2;
+        if region_blocks < 2 {
+            return ax_err!(StorageFull, "block device too small");
+        }
+        let mut store = Self {
+            dev,
+            region_blocks,
+            active: 0,
+            generation: 0,
+            end: BLOCK_SIZE,
+            entries: BTreeMap::new(),
+        };
+
+        let headers = [store.read_header(0)?, store.read_header(1)?];
+        match headers {
+            [None, None] => store.format()?,
+            [h0, h1] => {
+                store.active = (h1 > h0) as u64;
+                store.generation = h0.max(h1).unwrap();
+                store.replay()?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Returns the underlying device.
+    pub fn into_device(self) -> D {
+        self.dev
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the value of the given key.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Returns an iterator over the keys, in order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Sets the value of the given key, and writes it to the device.
+    ///
+    /// Returns [`AxError::InvalidInput`] if the key is empty or longer than
+    /// [`MAX_KEY_LEN`], or [`AxError::StorageFull`] if the entries would not
+    /// fit in a region.
+    pub fn put(&mut self, key: &str, value: &[u8]) -> AxResult {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return ax_err!(InvalidInput, "invalid key length");
+        }
+        if value.len() > u32::MAX as usize {
+            return Err(AxError::StorageFull);
+        }
+        if self.get(key) == Some(value) {
+            return Ok(());
+        }
+        self.update(key, Some(value))
+    }
+
+    /// Removes the given key, and writes the removal to the device.
+    ///
+    /// Returns [`AxError::NotFound`] if the key does not exist.
+    pub fn remove(&mut self, key: &str) -> AxResult {
+        if !self.entries.contains_key(key) {
+            return Err(AxError::NotFound);
+        }
+        self.update(key, None)
+    }
+
+    /// Rewrites the log with the live entries only.
+    pub fn compact(&mut self) -> AxResult {
+        let target = 1 - self.active;
+        let generation = self.generation + 1;
+        let mut log = Vec::new();
+        for (key, value) in &self.entries {
+            log.extend_from_slice(&encode_record(generation, key, Some(value)));
+        }
+        if BLOCK_SIZE + log.len() > self.region_size() {
+            return Err(AxError::StorageFull);
+        }
+
+        self.write_at(target, BLOCK_SIZE, &log)?;
+        self.flush()?;
+        self.write_header(target, generation)?;
+        self.active = target;
+        self.generation = generation;
+        self.end = BLOCK_SIZE + log.len();
+        debug!(
+            "compacted key-value store: {} keys, {} bytes",
+            self.entries.len(),
+            log.len()
+        );
+        Ok(())
+    }
+
+    fn update(&mut self, key: &str, value: Option<&[u8]>) -> AxResult {
+        let record = encode_record(self.generation, key, value);
+        let old = match value {
+            Some(value) => self.entries.insert(key.into(), value.into()),
+            None => self.entries.remove(key),
+        };
+        let res = if self.end + record.len() <= self.region_size() {
+            self.append(&record)
+        } else {
+            // The compacted log holds the update already.
+            self.compact()
+        };
+        if res.is_err() {
+            match old {
+                Some(old) => self.entries.insert(key.into(), old),
+                None => self.entries.remove(key),
+            };
+        }
+        res
+    }
+
+    fn append(&mut self, record: &[u8]) -> AxResult {
+        self.write_at(self.active, self.end, record)?;
+        self.flush()?;
+        self.end += record.len();
+        Ok(())
+    }
+
+    fn format(&mut self) -> AxResult {
+        info!("  no key-value store found, formatting...");
+        // A stale record right after the header may look valid otherwise.
+        self.write_at(0, BLOCK_SIZE, &[0; BLOCK_SIZE])?;
+        self.flush()?;
+        self.write_header(0, 1)?;
+        self.active = 0;
+        self.generation = 1;
+        self.end = BLOCK_SIZE;
+        Ok(())
+    }
+
+    /// Reads the records of the current log into the entries, and finds its
+    /// end.
+    fn replay(&mut self) -> AxResult {
+        let mut offset = BLOCK_SIZE;
+        let mut header = [0; RECORD_HEADER_SIZE];
+        while offset + RECORD_HEADER_SIZE <= self.region_size() {
+            self.read_at(self.active, offset, &mut header)?;
+            let generation = le32(&header[4..]);
+            let key_len = le16(&header[8..]) as usize;
+            let value_len = le32(&header[12..]) as usize;
+            let len = RECORD_HEADER_SIZE + key_len + value_len;
+            if generation != self.generation
+                || key_len == 0
+                || key_len > MAX_KEY_LEN
+                || value_len > self.region_size()
+                || offset + len > self.region_size()
+            {
+                break;
+            }
+
+            let mut record = vec![0; len];
+            self.read_at(self.active, offset, &mut record)?;
+            if le32(&record) != crc32(&record[4..]) {
+                break;
+            }
+            let Ok(key) = core::str::from_utf8(&record[RECORD_HEADER_SIZE..][..key_len]) else {
+                break;
+            };
+            if le16(&header[10..]) & FLAG_REMOVED != 0 {
+                self.entries.remove(key);
+            } else {
+                let value = record[RECORD_HEADER_SIZE + key_len..].to_vec();
+                self.entries.insert(key.into(), value);
+            }
+            offset += len;
+        }
+        self.end = offset;
+        debug!(
+            "replayed key-value store: generation {}, {} keys, {} bytes",
+            self.generation,
+            self.entries.len(),
+            offset - BLOCK_SIZE
+        );
+        Ok(())
+    }
+
+    fn region_size(&self) -> usize {
+        self.region_blocks as usize * BLOCK_SIZE
+    }
+
+    /// Returns the generation of the given region, or `None` if its header is
+    /// not valid.
+    fn read_header(&mut self, region: u64) -> AxResult<Option<u32>> {
+        let mut block = [0; BLOCK_SIZE];
+        self.read_at(region, 0, &mut block)?;
+        let valid = le32(&block) == MAGIC && le32(&block[8..]) == crc32(&block[..8]);
+        Ok(valid.then(|| le32(&block[4..])))
+    }
+
+    fn write_header(&mut self, region: u64, generation: u32) -> AxResult {
+        let mut block = [0; BLOCK_SIZE];
+        block[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        block[4..8].copy_from_slice(&generation.to_le_bytes());
+        let crc = crc32(&block[..8]);
+        block[8..12].copy_from_slice(&crc.to_le_bytes());
+        self.write_at(region, 0, &block)?;
+        self.flush()
+    }
+
+    fn read_at(&mut self, region: u64, offset: usize, buf: &mut [u8]) -> AxResult {
+        let mut block = [0; BLOCK_SIZE];
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let start = pos % BLOCK_SIZE;
+            let count = (buf.len() - done).min(BLOCK_SIZE - start);
+            self.dev
+                .read_block(self.block_id(region, pos), &mut block)
+                .map_err(dev_err)?;
+            buf[done..done + count].copy_from_slice(&block[start..start + count]);
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, region: u64, offset: usize, buf: &[u8]) -> AxResult {
+        let mut block = [0; BLOCK_SIZE];
+        let mut pos = offset;
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let block_id = self.block_id(region, pos);
+            let start = pos % BLOCK_SIZE;
+            let count = rest.len().min(BLOCK_SIZE - start);
+            if count < BLOCK_SIZE {
+                // Keep the rest of the block, e.g., the records before.
+                self.dev.read_block(block_id, &mut block).map_err(dev_err)?;
+            }
+            block[start..start + count].copy_from_slice(&rest[..count]);
+            self.dev.write_block(block_id, &block).map_err(dev_err)?;
+            rest = &rest[count..];
+            pos += count;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> AxResult {
+        self.dev.flush().map_err(dev_err)
+    }
+
+    fn block_id(&self, region: u64, offset: usize) -> u64 {
+        region * self.region_blocks + (offset / BLOCK_SIZE) as u64
+    }
+}
+
+fn dev_err(err: DevError) -> AxError {
+    warn!("key-value store device error: {:?}", err);
+    AxError::Io
+}
+
+/// Encodes a record setting `key` to `value`, or removing it if `value` is
+/// `None`.
+fn encode_record(generation: u32, key: &str, value: Option<&[u8]>) -> Vec<u8> {
+    let flags = if value.is_none() { FLAG_REMOVED } else { 0 };
+    let value = value.unwrap_or_default();
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + key.len() + value.len());
+    record.extend_from_slice(&[0; 4]);
+    record.extend_from_slice(&generation.to_le_bytes());
+    record.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    record.extend_from_slice(&flags.to_le_bytes());
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    let crc = crc32(&record[4..]);
+    record[..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+fn le16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
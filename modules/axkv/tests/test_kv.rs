@@ -0,0 +1,78 @@
+use axdriver_block::BlockDriverOps;
+use axdriver_block::ramdisk::RamDisk;
+use axerrno::AxError;
+use axkv::KvStore;
+
+const BLOCK_SIZE: usize = 512;
+
+fn reopen(store: KvStore<RamDisk>) -> KvStore<RamDisk> {
+    KvStore::open(store.into_device()).expect("failed to reopen")
+}
+
+#[test]
+fn test_persistence() {
+    let mut store = KvStore::open(RamDisk::new(64 * BLOCK_SIZE)).unwrap();
+    assert!(store.is_empty());
+
+    store.put("boot_count", &1u32.to_le_bytes()).unwrap();
+    store.put("hostname", b"arceos").unwrap();
+    store.put("boot_count", &2u32.to_le_bytes()).unwrap();
+    store.put("tmp", b"x").unwrap();
+    store.remove("tmp").unwrap();
+    assert_eq!(store.remove("tmp"), Err(AxError::NotFound));
+    assert_eq!(store.put("", b"x"), Err(AxError::InvalidInput));
+
+    let store = reopen(store);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get("boot_count"), Some(&2u32.to_le_bytes()[..]));
+    assert_eq!(store.get("hostname"), Some(&b"arceos"[..]));
+    assert_eq!(store.get("tmp"), None);
+    assert_eq!(store.keys().collect::<Vec<_>>(), ["boot_count", "hostname"]);
+}
+
+#[test]
+fn test_compaction() {
+    // Each region has 3 blocks for records.
+    let mut store = KvStore::open(RamDisk::new(8 * BLOCK_SIZE)).unwrap();
+    let value = [0xa5; 100];
+    for i in 0..100u32 {
+        store.put("counter", &i.to_le_bytes()).unwrap();
+        store.put("data", &value[..(i % 100) as usize]).unwrap();
+    }
+    store.put("big", &[0; 1024]).unwrap();
+    assert_eq!(store.put("bigger", &[0; 1024]), Err(AxError::StorageFull));
+    assert_eq!(store.get("bigger"), None);
+
+    // Replacing a value must not need space for both.
+    store.put("big", &[1; 1024]).unwrap();
+
+    let store = reopen(store);
+    assert_eq!(store.get("counter"), Some(&99u32.to_le_bytes()[..]));
+    assert_eq!(store.get("data"), Some(&value[..99]));
+    assert_eq!(store.get("big"), Some(&[1; 1024][..]));
+    assert_eq!(store.get("bigger"), None);
+}
+
+#[test]
+fn test_torn_write() {
+    let mut store = KvStore::open(RamDisk::new(64 * BLOCK_SIZE)).unwrap();
+    store.put("a", b"1").unwrap();
+    store.put("b", b"2").unwrap();
+
+    // Corrupt the last record, as if the write was interrupted.
+    let mut disk = store.into_device();
+    let mut block = [0; BLOCK_SIZE];
+    disk.read_block(1, &mut block).unwrap();
+    let last = block.iter().rposition(|&b| b == b'2').unwrap();
+    block[last] = b'3';
+    disk.write_block(1, &block).unwrap();
+
+    let mut store = KvStore::open(disk).unwrap();
+    assert_eq!(store.get("a"), Some(&b"1"[..]));
+    assert_eq!(store.get("b"), None);
+
+    // The next record replaces the torn one.
+    store.put("c", b"4").unwrap();
+    let store = reopen(store);
+    assert_eq!(store.keys().collect::<Vec<_>>(), ["a", "c"]);
+}
@@ -14,6 +14,10 @@
 //! axlog = { version = "0.1", features = ["std"] }
 //! ```
 //!
+//! The maximum level can also be overridden at run time for the records of a
+//! module and its submodules, with [`set_target_level`], e.g., to debug a
+//! subsystem on a live system.
+//!
 //! # Cargo features:
 //!
 //! - `std`: Use in the `std` environment. If it is enabled, you can use console
@@ -52,6 +56,7 @@ extern crate log;
 
 use core::fmt::{self, Write};
 use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use log::{Level, LevelFilter, Log, Metadata, Record};
 
@@ -107,6 +112,111 @@ enum ColorCode {
     BrightWhite = 97,
 }
 
+/// A secondary destination of log records, such as a file.
+///
+/// See [`set_sink`].
+pub trait LogSink: Sync {
+    /// Writes a log record, formatted without colors and terminated by a
+    /// newline.
+    ///
+    /// It may be called in any context, including interrupt handlers, and
+    /// recursively if the sink itself emits logs, so it should not block.
+    fn write_record(&self, record: fmt::Arguments);
+
+    /// Flushes buffered records.
+    fn flush(&self);
+}
+
+static SINK: kspin::SpinNoIrq<Option<&'static dyn LogSink>> = kspin::SpinNoIrq::new(None);
+
+/// Sets the secondary destination of log records, in addition to the console.
+///
+/// Only records enabled by the maximum log level are written to the sink.
+pub fn set_sink(sink: &'static dyn LogSink) {
+    *SINK.lock() = Some(sink);
+}
+
+fn sink() -> Option<&'static dyn LogSink> {
+    *SINK.lock()
+}
+
+/// The maximum number of targets with their own maximum log level.
+const MAX_TARGETS: usize = 16;
+
+/// The maximum length of a target with its own maximum log level.
+const MAX_TARGET_LEN: usize = 48;
+
+#[derive(Clone, Copy)]
+struct TargetLevel {
+    target: [u8; MAX_TARGET_LEN],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl TargetLevel {
+    fn target(&self) -> &str {
+        // Copied from a `&str` in whole.
+        core::str::from_utf8(&self.target[..self.len]).unwrap()
+    }
+
+    /// Whether a record of `target` is of this target or of one of its
+    /// submodules.
+    fn matches(&self, target: &str) -> bool {
+        let prefix = self.target();
+        target
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+/// The maximum log level of the targets without their own.
+static GLOBAL_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Warn as usize);
+
+static TARGET_LEVELS: kspin::SpinNoIrq<[Option<TargetLevel>; MAX_TARGETS]> =
+    kspin::SpinNoIrq::new([None; MAX_TARGETS]);
+
+/// Whether [`TARGET_LEVELS`] is not empty, to skip looking it up otherwise.
+static HAS_TARGET_LEVELS: AtomicBool = AtomicBool::new(false);
+
+fn level_from_usize(level: usize) -> LevelFilter {
+    match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+fn global_level() -> LevelFilter {
+    level_from_usize(GLOBAL_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns the level of the longest target matching `target`, if any.
+fn target_level(target: &str) -> Option<LevelFilter> {
+    TARGET_LEVELS
+        .lock()
+        .iter()
+        .flatten()
+        .filter(|t| t.matches(target))
+        .max_by_key(|t| t.len)
+        .map(|t| t.level)
+}
+
+/// Sets the maximum level checked by the log macros to the highest of the
+/// levels, so no record enabled by one is filtered out before [`Logger`]
+/// checks it against the level of its target.
+fn update_max_level(targets: &[Option<TargetLevel>; MAX_TARGETS]) {
+    let max = targets
+        .iter()
+        .flatten()
+        .map(|t| t.level)
+        .fold(global_level(), Ord::max);
+    log::set_max_level(max);
+    HAS_TARGET_LEVELS.store(targets.iter().any(Option::is_some), Ordering::Relaxed);
+}
+
 /// Extern interfaces that must be implemented in other crates.
 #[crate_interface::def_interface]
 pub trait LogIf {
@@ -144,8 +254,13 @@ impl Write for Logger {
 
 impl Log for Logger {
     #[inline]
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let max_level = if HAS_TARGET_LEVELS.load(Ordering::Relaxed) {
+            target_level(metadata.target())
+        } else {
+            None
+        };
+        metadata.level() <= max_level.unwrap_or_else(global_level)
     }
 
     fn log(&self, record: &Record) {
@@ -217,20 +332,40 @@ impl Log for Logger {
                         args = with_color!(args_color, "{}", record.args()),
                     ));
                 }
+                if let Some(sink) = sink() {
+                    sink.write_record(format_args!(
+                        "[{:>3}.{:06} {path}:{line}] {level:<5} {args}\n",
+                        now.as_secs(),
+                        now.subsec_micros(),
+                        path = path,
+                        line = line,
+                        level = level,
+                        args = record.args(),
+                    ));
+                }
             }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(sink) = sink() {
+            sink.flush();
+        }
+    }
 }
 
+static PRINT_LOCK: kspin::SpinNoIrq<()> = kspin::SpinNoIrq::new(()); // TODO: more efficient
+
 /// Prints the formatted string to the console.
 pub fn print_fmt(args: fmt::Arguments) -> fmt::Result {
-    use kspin::SpinNoIrq; // TODO: more efficient
-    static LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+    with_print_lock(|| Logger.write_fmt(args))
+}
 
-    let _guard = LOCK.lock();
-    Logger.write_fmt(args)
+/// Runs `f` with the lock of [`print_fmt`] held, so the output written by
+/// `f` is not interleaved with that of [`print_fmt`], such as log records.
+pub fn with_print_lock<T>(f: impl FnOnce() -> T) -> T {
+    let _guard = PRINT_LOCK.lock();
+    f()
 }
 
 #[doc(hidden)]
@@ -244,7 +379,7 @@ pub fn __print_impl(args: fmt::Arguments) {
 /// nothing will be printed.
 pub fn init() {
     log::set_logger(&Logger).unwrap();
-    log::set_max_level(LevelFilter::Warn);
+    set_max_level("warn");
 }
 
 /// Set the maximum log level.
@@ -258,5 +393,62 @@ pub fn set_max_level(level: &str) {
     let lf = LevelFilter::from_str(level)
         .ok()
         .unwrap_or(LevelFilter::Off);
-    log::set_max_level(lf);
+    let targets = TARGET_LEVELS.lock();
+    GLOBAL_LEVEL.store(lf as usize, Ordering::Relaxed);
+    update_max_level(&targets);
+}
+
+/// Returns the maximum log level set by [`set_max_level`], as one of `off`,
+/// `error`, `warn`, `info`, `debug`, `trace`.
+pub fn max_level() -> &'static str {
+    match global_level() {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
+
+/// Sets the maximum log level of the records of `target` (a module path,
+/// e.g., `axnet` or `axfs::fs`) and its submodules, overriding the one set by
+/// [`set_max_level`], or removes the override if `level` is `None`.
+///
+/// If several targets match a record, the longest one is used. Returns
+/// `false` if `level` is not a valid level, or if there are already too many
+/// targets with their own level, or the target is too long.
+pub fn set_target_level(target: &str, level: Option<&str>) -> bool {
+    let target = target.trim_end_matches("::");
+    let mut targets = TARGET_LEVELS.lock();
+    let index = targets
+        .iter()
+        .position(|t| t.is_some_and(|t| t.target() == target));
+    match level {
+        Some(level) => {
+            let Ok(level) = LevelFilter::from_str(level) else {
+                return false;
+            };
+            let Some(index) = index.or_else(|| targets.iter().position(Option::is_none)) else {
+                return false;
+            };
+            if target.len() > MAX_TARGET_LEN {
+                return false;
+            }
+            let mut entry = TargetLevel {
+                target: [0; MAX_TARGET_LEN],
+                len: target.len(),
+                level,
+            };
+            entry.target[..target.len()].copy_from_slice(target.as_bytes());
+            targets[index] = Some(entry);
+        }
+        None => {
+            if let Some(index) = index {
+                targets[index] = None;
+            }
+        }
+    }
+    update_max_level(&targets);
+    true
 }
@@ -1,4 +1,8 @@
+#[cfg(feature = "swap")]
+use alloc::collections::BTreeMap;
 use core::fmt;
+#[cfg(feature = "swap")]
+use core::sync::atomic::{Ordering, fence};
 
 use axerrno::{AxError, AxResult, ax_err};
 use axhal::mem::phys_to_virt;
@@ -9,6 +13,8 @@ use memory_addr::{
 use memory_set::{MemoryArea, MemorySet};
 
 use crate::backend::Backend;
+#[cfg(feature = "swap")]
+use crate::backend::{alloc_frame, dealloc_frame};
 use crate::mapping_err_to_ax_err;
 
 /// The virtual memory address space.
@@ -16,6 +22,12 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// The swap slots of the pages swapped out, by address.
+    #[cfg(feature = "swap")]
+    swapped: BTreeMap<VirtAddr, usize>,
+    /// Where the next swap out starts, see [`AddrSpace::swap_out`].
+    #[cfg(feature = "swap")]
+    swap_hand: VirtAddr,
 }
 
 impl AddrSpace {
@@ -44,6 +56,23 @@ impl AddrSpace {
         self.pt.root_paddr()
     }
 
+    /// Returns the total size of the memory regions mapped in the address
+    /// space, including the pages not populated yet.
+    pub fn mapped_size(&self) -> usize {
+        self.areas.iter().map(|area| area.size()).sum()
+    }
+
+    /// Returns the number of populated pages of the allocation mappings, i.e.,
+    /// of the frames owned by the address space.
+    pub fn resident_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| matches!(area.backend(), Backend::Alloc { .. }))
+            .flat_map(|area| PageIter4K::new(area.start(), area.end()).unwrap())
+            .filter(|&vaddr| matches!(self.pt.query(vaddr), Ok((_, flags, _)) if !flags.is_empty()))
+            .count()
+    }
+
     /// Checks if the address space contains the given address range.
     pub fn contains_range(&self, start: VirtAddr, size: usize) -> bool {
         self.va_range
@@ -56,6 +85,10 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            #[cfg(feature = "swap")]
+            swapped: BTreeMap::new(),
+            #[cfg(feature = "swap")]
+            swap_hand: base,
         })
     }
 
@@ -74,6 +107,74 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Copies the memory regions of another address space, with their data.
+    ///
+    /// Each region is mapped with the same backend and flags, and the data of
+    /// all pages present in `other` is copied to newly allocated frames, e.g.,
+    /// to fork a user process. The pages swapped out in `other` are read back
+    /// into the frames. Pages not populated yet in `other` are left
+    /// unpopulated, and linear regions are mapped to the same physical memory.
+    pub fn clone_areas_from(&mut self, other: &AddrSpace) -> AxResult {
+        for area in other.areas.iter() {
+            let (start, size, flags) = (area.start(), area.size(), area.flags());
+            match *area.backend() {
+                Backend::Linear { pa_va_offset } => {
+                    let start_paddr = PhysAddr::from(start.as_usize() - pa_va_offset);
+                    self.map_linear(start, start_paddr, size, flags)?;
+                    continue;
+                }
+                Backend::Alloc { populate } => self.map_alloc(start, size, flags, populate)?,
+            }
+            for vaddr in PageIter4K::new(start, start + size).unwrap() {
+                if other.query_present(vaddr).is_none() && !other.is_swapped(vaddr) {
+                    continue;
+                }
+                if self.query_present(vaddr).is_none() && !self.handle_page_fault(vaddr, flags) {
+                    return ax_err!(NoMemory);
+                }
+                let dst = self.query_present(vaddr).ok_or(AxError::BadState)?;
+                other.read_page(vaddr, unsafe { frame_data(dst) })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the physical address of the page mapped at `vaddr`, if it is
+    /// populated.
+    fn query_present(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        match self.pt.query(vaddr) {
+            Ok((paddr, flags, _)) if !flags.is_empty() => Some(paddr),
+            _ => None,
+        }
+    }
+
+    /// Whether the page at `vaddr` is swapped out.
+    fn is_swapped(&self, vaddr: VirtAddr) -> bool {
+        #[cfg(feature = "swap")]
+        {
+            self.swapped.contains_key(&vaddr)
+        }
+        #[cfg(not(feature = "swap"))]
+        {
+            let _ = vaddr;
+            false
+        }
+    }
+
+    /// Copies the data of the page at `vaddr`, populated or swapped out, to
+    /// `buf`.
+    fn read_page(&self, vaddr: VirtAddr, buf: &mut [u8]) -> AxResult {
+        if let Some(paddr) = self.query_present(vaddr) {
+            buf.copy_from_slice(unsafe { frame_data(paddr) });
+            return Ok(());
+        }
+        #[cfg(feature = "swap")]
+        if let Some(&slot) = self.swapped.get(&vaddr) {
+            return crate::swap::read_slot(slot, buf);
+        }
+        ax_err!(BadAddress)
+    }
+
     /// Finds a free area that can accommodate the given size.
     ///
     /// The search starts from the given hint address, and the area should be within the given limit range.
@@ -162,6 +263,8 @@ impl AddrSpace {
         self.areas
             .unmap(start, size, &mut self.pt)
             .map_err(mapping_err_to_ax_err)?;
+        #[cfg(feature = "swap")]
+        self.free_swapped(start, start + size);
         Ok(())
     }
 
@@ -246,6 +349,8 @@ impl AddrSpace {
     /// Removes all mappings in the address space.
     pub fn clear(&mut self) {
         self.areas.clear(&mut self.pt).unwrap();
+        #[cfg(feature = "swap")]
+        self.free_swapped(self.base(), self.end());
     }
 
     /// Handles a page fault at the given address.
@@ -261,6 +366,10 @@ impl AddrSpace {
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
+                #[cfg(feature = "swap")]
+                if self.swapped.contains_key(&vaddr.align_down_4k()) {
+                    return self.swap_in(vaddr.align_down_4k(), orig_flags);
+                }
                 return area
                     .backend()
                     .handle_page_fault(vaddr, orig_flags, &mut self.pt);
@@ -270,6 +379,141 @@ impl AddrSpace {
     }
 }
 
+#[cfg(feature = "swap")]
+impl AddrSpace {
+    /// Swaps out up to `max_pages` populated pages of the allocation
+    /// mappings. Returns the number of pages swapped out, which is less if
+    /// the swap device is full, or if the address space is in use.
+    ///
+    /// The pages are taken from where the last swap out stopped, going round
+    /// the address space.
+    ///
+    /// Only the TLB of the current CPU is flushed when a page is unmapped, so
+    /// the address space must not be in use on another CPU, where writes
+    /// through a stale TLB entry would be lost once the page is saved.
+    /// `in_use` tells whether it is, e.g., whether the task of its process is
+    /// running. It is checked after each page is unmapped: if it is in use,
+    /// the page is mapped back and the swap out stops. A CPU starting to use
+    /// the address space afterwards flushes its TLB when it switches to it,
+    /// so it finds the page unmapped.
+    pub fn swap_out(&mut self, max_pages: usize, in_use: impl Fn() -> bool) -> usize {
+        let hand = self.swap_hand;
+        let areas = &self.areas;
+        let pages = || {
+            areas
+                .iter()
+                .filter(|area| matches!(area.backend(), Backend::Alloc { .. }))
+                .flat_map(|area| {
+                    PageIter4K::new(area.start(), area.end())
+                        .unwrap()
+                        .map(move |vaddr| (vaddr, area.flags()))
+                })
+        };
+        let pages = pages()
+            .filter(|(vaddr, _)| *vaddr >= hand)
+            .chain(pages().filter(|(vaddr, _)| *vaddr < hand));
+
+        let mut count = 0;
+        for (vaddr, flags) in pages {
+            if count == max_pages {
+                break;
+            }
+            let frame = match self.pt.query(vaddr) {
+                Ok((frame, page_flags, _)) if !page_flags.is_empty() => frame,
+                _ => continue,
+            };
+            let Some(slot) = crate::swap::alloc_slot() else {
+                break;
+            };
+            // Unmapped first, so that the page is not written while it is
+            // being saved.
+            match self
+                .pt
+                .remap(vaddr, PhysAddr::from(0), MappingFlags::empty())
+            {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => {
+                    crate::swap::free_slot(slot);
+                    continue;
+                }
+            }
+            // Ordered after the unmapping, against a CPU switching to the
+            // address space.
+            fence(Ordering::SeqCst);
+            if in_use() {
+                crate::swap::free_slot(slot);
+                if let Ok((_, tlb)) = self.pt.remap(vaddr, frame, flags) {
+                    tlb.flush();
+                }
+                break;
+            }
+            if let Err(e) = crate::swap::write_slot(slot, unsafe { frame_data(frame) }) {
+                warn!("failed to swap out page {:#x}: {:?}", vaddr, e);
+                crate::swap::free_slot(slot);
+                if let Ok((_, tlb)) = self.pt.remap(vaddr, frame, flags) {
+                    tlb.flush();
+                }
+                break;
+            }
+            dealloc_frame(frame);
+            self.swapped.insert(vaddr, slot);
+            self.swap_hand = vaddr + PAGE_SIZE_4K;
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the number of pages swapped out.
+    pub fn swapped_pages(&self) -> usize {
+        self.swapped.len()
+    }
+
+    /// Reads a swapped out page back into a new frame, and maps it.
+    fn swap_in(&mut self, vaddr: VirtAddr, flags: MappingFlags) -> bool {
+        let slot = self.swapped[&vaddr];
+        let Some(frame) = alloc_frame(false) else {
+            return false;
+        };
+        if let Err(e) = crate::swap::read_slot(slot, unsafe { frame_data(frame) }) {
+            warn!("failed to swap in page {:#x}: {:?}", vaddr, e);
+            dealloc_frame(frame);
+            return false;
+        }
+        match self.pt.remap(vaddr, frame, flags) {
+            Ok((_, tlb)) => tlb.flush(),
+            Err(_) => {
+                dealloc_frame(frame);
+                return false;
+            }
+        }
+        self.swapped.remove(&vaddr);
+        crate::swap::free_slot(slot);
+        true
+    }
+
+    /// Frees the swap slots of the pages in `[start, end)`, once unmapped.
+    fn free_swapped(&mut self, start: VirtAddr, end: VirtAddr) {
+        let freed: alloc::vec::Vec<VirtAddr> = self
+            .swapped
+            .range(start..end)
+            .map(|(&vaddr, _)| vaddr)
+            .collect();
+        for vaddr in freed {
+            crate::swap::free_slot(self.swapped.remove(&vaddr).unwrap());
+        }
+    }
+}
+
+/// Returns the data of a frame.
+///
+/// # Safety
+///
+/// The frame must be allocated, and not accessed by others during the
+/// lifetime of the slice.
+unsafe fn frame_data<'a>(frame: PhysAddr) -> &'a mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) }
+}
+
 impl fmt::Debug for AddrSpace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AddrSpace")
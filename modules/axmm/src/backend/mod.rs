@@ -7,6 +7,8 @@ use memory_set::MappingBackend;
 mod alloc;
 mod linear;
 
+pub(crate) use self::alloc::{alloc_frame, dealloc_frame};
+
 /// A unified enum type for different memory mapping backends.
 ///
 /// Currently, two backends are implemented:
@@ -1,4 +1,9 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
+//!
+//! # Cargo Features
+//!
+//! - `swap`: Swap out pages of user address spaces to a block device under
+//!   memory pressure.
 
 #![no_std]
 
@@ -8,6 +13,8 @@ extern crate alloc;
 
 mod aspace;
 mod backend;
+#[cfg(feature = "swap")]
+pub mod swap;
 
 pub use self::aspace::AddrSpace;
 pub use self::backend::Backend;
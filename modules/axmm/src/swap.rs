@@ -0,0 +1,187 @@
+//! Swapping of user memory to a block device, under memory pressure.
+//!
+//! Once a swap device is set by [`swap_on`], [`AddrSpace::swap_out`] writes
+//! pages of allocation mappings to free slots of the device, and frees their
+//! frames. A swapped out page is left unmapped, as if it were not populated
+//! yet, and is read back into a new frame on its next page fault.
+//!
+//! The page tables do not report here which pages were accessed, so the
+//! pages are picked by a clock hand sweeping each address space, rather than
+//! by their last access.
+//!
+//! Clean caches, e.g., of file pages, whose pages can be dropped without
+//! being written back, are registered with [`register_clean_cache`], and
+//! evicted first, see [`evict_clean_caches`].
+//!
+//! [`AddrSpace::swap_out`]: crate::AddrSpace::swap_out
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axalloc::global_allocator;
+use axerrno::{AxResult, ax_err};
+use kspin::SpinNoIrq;
+
+/// The default number of available pages below which the memory is under
+/// pressure (4 MB).
+const DEFAULT_LOW_WATERMARK: usize = 1024;
+
+/// A block device, or a part of one, holding swapped out pages.
+pub trait SwapDevice: Send + Sync {
+    /// Returns the number of pages the device can hold.
+    fn num_pages(&self) -> usize;
+    /// Reads the page at `index` into `buf`, of
+    /// [`PAGE_SIZE_4K`](memory_addr::PAGE_SIZE_4K) bytes.
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult;
+    /// Writes the page at `index` from `buf`, of
+    /// [`PAGE_SIZE_4K`](memory_addr::PAGE_SIZE_4K) bytes.
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult;
+}
+
+/// The swap device, with the slots in use.
+struct Swap {
+    dev: Arc<dyn SwapDevice>,
+    /// A bitmap of the slots in use.
+    used: Vec<u64>,
+    num_used: usize,
+    num_slots: usize,
+}
+
+/// The swap device, in a spinlock as it is used with address spaces locked.
+/// It is never held during the I/O.
+static SWAP: SpinNoIrq<Option<Swap>> = SpinNoIrq::new(None);
+
+static LOW_WATERMARK: AtomicUsize = AtomicUsize::new(DEFAULT_LOW_WATERMARK);
+
+/// The maximum number of clean caches.
+const MAX_CLEAN_CACHES: usize = 4;
+
+/// A function dropping up to `num_pages` clean pages of a cache, and
+/// returning the number of pages freed.
+///
+/// It is called with no address space locked.
+pub type CleanCacheEvictor = fn(num_pages: usize) -> usize;
+
+static CLEAN_CACHES: SpinNoIrq<[Option<CleanCacheEvictor>; MAX_CLEAN_CACHES]> =
+    SpinNoIrq::new([None; MAX_CLEAN_CACHES]);
+
+/// Starts swapping to the given device.
+///
+/// Returns [`AxError::AlreadyExists`](axerrno::AxError::AlreadyExists) if a
+/// swap device is already set.
+pub fn swap_on(dev: Arc<dyn SwapDevice>) -> AxResult {
+    let mut swap = SWAP.lock();
+    if swap.is_some() {
+        return ax_err!(AlreadyExists, "swap device already set");
+    }
+    let num_slots = dev.num_pages();
+    info!("swap on: {} pages", num_slots);
+    *swap = Some(Swap {
+        dev,
+        used: vec![0; num_slots.div_ceil(64)],
+        num_used: 0,
+        num_slots,
+    });
+    Ok(())
+}
+
+/// Whether a swap device is set.
+pub fn is_on() -> bool {
+    SWAP.lock().is_some()
+}
+
+/// Returns the number of pages swapped out, and the number of pages the swap
+/// device can hold, or `(0, 0)` without a swap device.
+pub fn usage() -> (usize, usize) {
+    SWAP.lock()
+        .as_ref()
+        .map_or((0, 0), |swap| (swap.num_used, swap.num_slots))
+}
+
+/// Sets the number of available pages below which the memory is under
+/// pressure, returning the previous one.
+pub fn set_low_watermark(pages: usize) -> usize {
+    LOW_WATERMARK.swap(pages, Ordering::Relaxed)
+}
+
+/// Whether the memory is under pressure, i.e., the number of available pages
+/// is below the low watermark, and pages can be swapped out.
+pub fn under_pressure() -> bool {
+    is_on() && below_low_watermark()
+}
+
+/// Registers a clean cache, evicted before pages are swapped out. Returns
+/// `false` if too many are registered.
+pub fn register_clean_cache(evict: CleanCacheEvictor) -> bool {
+    let mut caches = CLEAN_CACHES.lock();
+    match caches.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(evict);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Evicts up to `num_pages` pages of the clean caches, in the order they are
+/// registered, and returns the number of pages freed.
+pub fn evict_clean_caches(num_pages: usize) -> usize {
+    let caches = *CLEAN_CACHES.lock();
+    let mut freed = 0;
+    for evict in caches.iter().flatten() {
+        if freed >= num_pages {
+            break;
+        }
+        freed += evict(num_pages - freed);
+    }
+    freed
+}
+
+/// Whether the number of available pages is below the low watermark, with or
+/// without a swap device.
+pub fn below_low_watermark() -> bool {
+    global_allocator().available_pages() < LOW_WATERMARK.load(Ordering::Relaxed)
+}
+
+/// Takes a free slot of the swap device, or returns `None` if it is full.
+pub(crate) fn alloc_slot() -> Option<usize> {
+    let mut swap = SWAP.lock();
+    let swap = swap.as_mut()?;
+    let (i, word) = swap
+        .used
+        .iter_mut()
+        .enumerate()
+        .find(|(_, word)| **word != u64::MAX)?;
+    let slot = i * 64 + word.trailing_ones() as usize;
+    if slot >= swap.num_slots {
+        return None;
+    }
+    *word |= 1 << (slot % 64);
+    swap.num_used += 1;
+    Some(slot)
+}
+
+/// Gives back a slot taken by [`alloc_slot`].
+pub(crate) fn free_slot(slot: usize) {
+    if let Some(swap) = SWAP.lock().as_mut() {
+        swap.used[slot / 64] &= !(1 << (slot % 64));
+        swap.num_used -= 1;
+    }
+}
+
+fn device() -> AxResult<Arc<dyn SwapDevice>> {
+    match SWAP.lock().as_ref() {
+        Some(swap) => Ok(swap.dev.clone()),
+        None => ax_err!(BadState, "no swap device"),
+    }
+}
+
+/// Reads the page in a slot.
+pub(crate) fn read_slot(slot: usize, buf: &mut [u8]) -> AxResult {
+    device()?.read_page(slot, buf)
+}
+
+/// Writes a page to a slot.
+pub(crate) fn write_slot(slot: usize, buf: &[u8]) -> AxResult {
+    device()?.write_page(slot, buf)
+}
@@ -4,25 +4,50 @@
 //! using various underlying network stacks. Currently, only [smoltcp] is
 //! supported.
 //!
+//! The network is dual-stack: the sockets take IPv4 and IPv6 addresses, and
+//! those bound to an unspecified address accept both. The IPv4 address is set
+//! at build time (`AX_IP` and `AX_GW`). The IPv6 address is set by `AX_IP6`
+//! and `AX_GW6`, or else configured from the router advertisements (SLAAC),
+//! besides the link-local one.
+//!
 //! # Organization
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
 //! - [`dns_query`]: Function for DNS query.
+//! - [`ping`]: Function to send an ICMP echo request and wait for the reply.
+//! - [`interface_info`]: Function to get the addresses and the statistics of
+//!   the network interface.
+//! - [`unshare_net`]: Function to move the current task into a network
+//!   namespace, with its own loopback interface, sockets and ports.
+//! - [`set_mtu`]: Function to set the MTU, including jumbo frames if the NIC
+//!   supports them.
 //!
 //! # Cargo Features
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `rss`: Steer received packets to per-CPU backlogs by their flow hash, and
+//!   process them in a `net-rx` task on each CPU, woken up when packets are
+//!   steered to it. The interrupt of the NIC (given by `AX_NET_IRQ`) is
+//!   routed to the CPU of the first queue.
+//! - `napi`: Mask the interrupt of the NIC (given by `AX_NET_IRQ`) under load,
+//!   and poll it with a budget from a `net-napi` task until it is drained, so
+//!   a packet flood can not starve the other tasks. See [`napi_stats`].
+//! - `pm`: Register the NIC for runtime power management (see [`axhal::pm`]),
+//!   idle when there are no sockets.
+//! - `multitask`: Enable network namespaces (see [`unshare_net`]).
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+mod ns;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "smoltcp")] {
         mod smoltcp_impl;
@@ -32,8 +57,14 @@ cfg_if::cfg_if! {
 
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
+pub use self::net_impl::{InterfaceInfo, interface_info};
+#[cfg(feature = "napi")]
+pub use self::net_impl::{NapiStats, napi_stats};
 pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{dns_query, ping, poll_interfaces};
+pub use self::net_impl::{max_mtu, mtu, set_mtu};
+#[cfg(feature = "multitask")]
+pub use self::ns::unshare_net;
 
 use axdriver::{AxDeviceContainer, prelude::*};
 
@@ -0,0 +1,39 @@
+//! Network namespaces.
+//!
+//! A network namespace, created by [`unshare_net`], has a network stack of
+//! its own: a loopback interface, with `127.0.0.1/8` and `::1/128`, its own
+//! sockets and its own port space. The sockets created in it can only reach
+//! the other sockets of the namespace, and can bind the ports bound in the
+//! other namespaces. The namespace is inherited by the tasks it creates.
+//!
+//! The NIC is left to the root namespace, as well as [`dns_query`], and the
+//! configuration of the interface (e.g., [`interface_info`]).
+//!
+//! Namespaces need the `multitask` feature; without it, all the code runs in
+//! the root namespace.
+//!
+//! [`dns_query`]: crate::dns_query
+//! [`interface_info`]: crate::interface_info
+
+#[cfg(feature = "multitask")]
+use alloc::sync::Arc;
+
+#[cfg(feature = "multitask")]
+use crate::net_impl::NamespaceStack;
+
+/// Moves the current task into a new network namespace, with only a loopback
+/// interface.
+///
+/// The sockets created before are left in the previous namespace. The tasks
+/// created by the current task afterwards are in the new namespace too.
+#[cfg(feature = "multitask")]
+pub fn unshare_net() {
+    axtask::current().set_local(Arc::new(NamespaceStack::new()));
+}
+
+/// Returns the network stack of the namespace of the current task, or `None`
+/// in the root namespace.
+#[cfg(feature = "multitask")]
+pub(crate) fn current_stack() -> Option<Arc<NamespaceStack>> {
+    axtask::current().local::<NamespaceStack>()
+}
@@ -1,5 +1,5 @@
 use alloc::vec::Vec;
-use axerrno::{AxError, AxResult, ax_err_type};
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use core::net::IpAddr;
 
 use smoltcp::iface::SocketHandle;
@@ -7,11 +7,12 @@ use smoltcp::socket::dns::{self, GetQueryResultError, StartQueryError};
 use smoltcp::wire::DnsQueryType;
 
 use super::addr::into_core_ipaddr;
-use super::{ETH0, SOCKET_SET, SocketSetWrapper};
+use super::{NetStack, SocketSetWrapper};
 
 /// A DNS socket.
 struct DnsSocket {
     handle: Option<SocketHandle>,
+    stack: NetStack,
 }
 
 impl DnsSocket {
@@ -19,24 +20,29 @@ impl DnsSocket {
     /// Creates a new DNS socket.
     pub fn new() -> Self {
         let socket = SocketSetWrapper::new_dns_socket();
-        let handle = Some(SOCKET_SET.add(socket));
-        Self { handle }
+        let stack = NetStack::current();
+        let handle = Some(stack.sockets().add(socket));
+        Self { handle, stack }
     }
 
     #[allow(dead_code)]
     /// Update the list of DNS servers, will replace all existing servers.
     pub fn update_servers(self, servers: &[smoltcp::wire::IpAddress]) {
-        SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(self.handle.unwrap(), |socket| {
-            socket.update_servers(servers)
-        });
+        self.stack
+            .sockets()
+            .with_socket_mut::<dns::Socket, _, _>(self.handle.unwrap(), |socket| {
+                socket.update_servers(servers)
+            });
     }
 
     /// Query a address with given DNS query type.
     pub fn query(&self, name: &str, query_type: DnsQueryType) -> AxResult<Vec<IpAddr>> {
         // let local_addr = self.local_addr.unwrap_or_else(f);
         let handle = self.handle.ok_or_else(|| ax_err_type!(InvalidInput))?;
-        let iface = &ETH0.iface;
-        let query_handle = SOCKET_SET
+        let iface = self.stack.iface();
+        let query_handle = self
+            .stack
+            .sockets()
             .with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
                 socket.start_query(iface.lock().context(), name, query_type)
             })
@@ -52,15 +58,18 @@ impl DnsSocket {
                 }
             })?;
         loop {
-            SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.get_query_result(query_handle).map_err(|e| match e {
-                    GetQueryResultError::Pending => AxError::WouldBlock,
-                    GetQueryResultError::Failed => {
-                        ax_err_type!(ConnectionRefused, "socket query() failed")
-                    }
-                })
-            }) {
+            self.stack.poll_interfaces();
+            match self
+                .stack
+                .sockets()
+                .with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
+                    socket.get_query_result(query_handle).map_err(|e| match e {
+                        GetQueryResultError::Pending => AxError::WouldBlock,
+                        GetQueryResultError::Failed => {
+                            ax_err_type!(ConnectionRefused, "socket query() failed")
+                        }
+                    })
+                }) {
                 Ok(n) => {
                     let mut res = Vec::with_capacity(n.capacity());
                     for ip in n {
@@ -78,13 +87,26 @@ impl DnsSocket {
 impl Drop for DnsSocket {
     fn drop(&mut self) {
         if let Some(handle) = self.handle {
-            SOCKET_SET.remove(handle);
+            self.stack.sockets().remove(handle);
         }
     }
 }
 
 /// Public function for DNS query.
+///
+/// It returns the IPv4 addresses (`A` records) first, then the IPv6 ones
+/// (`AAAA` records), and fails only if both queries fail. It is not supported
+/// in a network namespace.
 pub fn dns_query(name: &str) -> AxResult<alloc::vec::Vec<IpAddr>> {
+    // The DNS servers are out of reach of the loopback interface.
+    if !matches!(NetStack::current(), NetStack::Root) {
+        return ax_err!(Unsupported, "no DNS server in the network namespace");
+    }
     let socket = DnsSocket::new();
-    socket.query(name, DnsQueryType::A)
+    let v4 = socket.query(name, DnsQueryType::A);
+    let v6 = socket.query(name, DnsQueryType::Aaaa);
+    match (v4, v6) {
+        (Err(e), Err(_)) => Err(e),
+        (v4, v6) => Ok(v4.into_iter().chain(v6).flatten().collect()),
+    }
 }
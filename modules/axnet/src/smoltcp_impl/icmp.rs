@@ -0,0 +1,130 @@
+use core::net::IpAddr;
+use core::sync::atomic::{AtomicU16, Ordering};
+use core::time::Duration;
+
+use axerrno::{AxResult, ax_err, ax_err_type};
+use axhal::time::monotonic_time;
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::icmp;
+use smoltcp::wire::{Icmpv4Packet, Icmpv4Repr, Icmpv6Packet, Icmpv6Repr, IpAddress, Ipv6Address};
+
+use super::addr::from_core_ipaddr;
+use super::{NetStack, SocketSetWrapper};
+
+/// The length of the data of the echo requests, as sent by `ping` by default.
+const ECHO_DATA_LEN: usize = 56;
+
+const UNSPECIFIED_IPV6: IpAddress = IpAddress::Ipv6(Ipv6Address::UNSPECIFIED);
+
+/// The identifier of the next echo requests, so that concurrent pings do not
+/// receive the replies of each other.
+static NEXT_IDENT: AtomicU16 = AtomicU16::new(1);
+
+/// Sends an ICMP (or ICMPv6) echo request to `addr` with the sequence number
+/// `seq`, and waits for the reply. Returns the round-trip time.
+///
+/// Returns [`AxError::TimedOut`](axerrno::AxError::TimedOut) if there is no
+/// reply within `timeout`.
+pub fn ping(addr: IpAddr, seq: u16, timeout: Duration) -> AxResult<Duration> {
+    let ident = NEXT_IDENT.fetch_add(1, Ordering::Relaxed);
+    let mut socket = SocketSetWrapper::new_icmp_socket();
+    socket
+        .bind(icmp::Endpoint::Ident(ident))
+        .map_err(|_| ax_err_type!(InvalidInput, "socket bind() failed"))?;
+    let stack = NetStack::current();
+    let handle = stack.sockets().add(socket);
+    let res = echo(&stack, handle, from_core_ipaddr(addr), ident, seq, timeout);
+    stack.sockets().remove(handle);
+    res
+}
+
+fn echo(
+    stack: &NetStack,
+    handle: SocketHandle,
+    addr: IpAddress,
+    ident: u16,
+    seq: u16,
+    timeout: Duration,
+) -> AxResult<Duration> {
+    let data: [u8; ECHO_DATA_LEN] = core::array::from_fn(|i| i as u8);
+    let caps = ChecksumCapabilities::default();
+    let start = monotonic_time();
+    stack
+        .sockets()
+        .with_socket_mut::<icmp::Socket, _, _>(handle, |socket| {
+            match addr {
+                IpAddress::Ipv4(_) => {
+                    let request = Icmpv4Repr::EchoRequest {
+                        ident,
+                        seq_no: seq,
+                        data: &data,
+                    };
+                    let buf = socket
+                        .send(request.buffer_len(), addr)
+                        .map_err(|_| ax_err_type!(InvalidInput, "socket send() failed"))?;
+                    request.emit(&mut Icmpv4Packet::new_unchecked(buf), &caps);
+                }
+                IpAddress::Ipv6(_) => {
+                    let request = Icmpv6Repr::EchoRequest {
+                        ident,
+                        seq_no: seq,
+                        data: &data,
+                    };
+                    let buf = socket
+                        .send(request.buffer_len(), addr)
+                        .map_err(|_| ax_err_type!(InvalidInput, "socket send() failed"))?;
+                    // The checksum covers the source address, and is computed
+                    // again by the socket once it is chosen.
+                    request.emit(
+                        &UNSPECIFIED_IPV6,
+                        &addr,
+                        &mut Icmpv6Packet::new_unchecked(buf),
+                        &caps,
+                    );
+                }
+            }
+            AxResult::Ok(())
+        })?;
+    loop {
+        stack.poll_interfaces();
+        let replied = stack
+            .sockets()
+            .with_socket_mut::<icmp::Socket, _, _>(handle, |socket| {
+                while let Ok((payload, src_addr)) = socket.recv() {
+                    if is_echo_reply(payload, src_addr, ident, seq) {
+                        return true;
+                    }
+                }
+                false
+            });
+        let elapsed = monotonic_time() - start;
+        if replied {
+            return Ok(elapsed);
+        } else if elapsed >= timeout {
+            return ax_err!(TimedOut, "no echo reply");
+        }
+        axtask::yield_now();
+    }
+}
+
+/// Whether an ICMP packet received from `src_addr` is the echo reply with
+/// `ident` and `seq`.
+fn is_echo_reply(payload: &[u8], src_addr: IpAddress, ident: u16, seq: u16) -> bool {
+    // The checksums are verified by the interface.
+    let caps = ChecksumCapabilities::ignored();
+    match src_addr {
+        IpAddress::Ipv4(_) => Icmpv4Packet::new_checked(payload)
+            .and_then(|packet| Icmpv4Repr::parse(&packet, &caps))
+            .is_ok_and(|repr| {
+                matches!(repr, Icmpv4Repr::EchoReply { ident: i, seq_no, .. }
+                    if i == ident && seq_no == seq)
+            }),
+        IpAddress::Ipv6(_) => Icmpv6Packet::new_checked(payload)
+            .and_then(|packet| Icmpv6Repr::parse(&src_addr, &UNSPECIFIED_IPV6, &packet, &caps))
+            .is_ok_and(|repr| {
+                matches!(repr, Icmpv6Repr::EchoReply { ident: i, seq_no, .. }
+                    if i == ident && seq_no == seq)
+            }),
+    }
+}
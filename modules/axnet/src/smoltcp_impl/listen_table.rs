@@ -7,7 +7,7 @@ use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
-use super::{LISTEN_QUEUE_SIZE, SOCKET_SET, SocketSetWrapper};
+use super::{LISTEN_QUEUE_SIZE, SocketSetWrapper};
 
 const PORT_NUM: usize = 65536;
 
@@ -33,14 +33,8 @@ impl ListenTableEntry {
     }
 }
 
-impl Drop for ListenTableEntry {
-    fn drop(&mut self) {
-        for &handle in &self.syn_queue {
-            SOCKET_SET.remove(handle);
-        }
-    }
-}
-
+/// The TCP ports listened on in a network stack, with the connections not
+/// accepted yet, whose sockets are in the socket set of the stack.
 pub struct ListenTable {
     tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
 }
@@ -73,27 +67,39 @@ impl ListenTable {
         }
     }
 
-    pub fn unlisten(&self, port: u16) {
+    pub fn unlisten(&self, port: u16, sockets: &SocketSetWrapper) {
         debug!("TCP socket unlisten on {}", port);
-        *self.tcp[port as usize].lock() = None;
+        let entry = self.tcp[port as usize].lock().take();
+        if let Some(entry) = entry {
+            for &handle in &entry.syn_queue {
+                sockets.remove(handle);
+            }
+        }
     }
 
-    pub fn can_accept(&self, port: u16) -> AxResult<bool> {
+    pub fn can_accept(&self, port: u16, sockets: &SocketSetWrapper) -> AxResult<bool> {
         if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
+            Ok(entry
+                .syn_queue
+                .iter()
+                .any(|&handle| is_connected(handle, sockets)))
         } else {
             ax_err!(InvalidInput, "socket accept() failed: not listen")
         }
     }
 
-    pub fn accept(&self, port: u16) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+    pub fn accept(
+        &self,
+        port: u16,
+        sockets: &SocketSetWrapper,
+    ) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
         if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
             let syn_queue = &mut entry.syn_queue;
             let (idx, addr_tuple) = syn_queue
                 .iter()
                 .enumerate()
                 .find_map(|(idx, &handle)| {
-                    is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
+                    is_connected(handle, sockets).then(|| (idx, get_addr_tuple(handle, sockets)))
                 })
                 .ok_or(AxError::WouldBlock)?; // wait for connection
             if idx > 0 {
@@ -139,14 +145,14 @@ impl ListenTable {
     }
 }
 
-fn is_connected(handle: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+fn is_connected(handle: SocketHandle, sockets: &SocketSetWrapper) -> bool {
+    sockets.with_socket::<tcp::Socket, _, _>(handle, |socket| {
         !matches!(socket.state(), State::Listen | State::SynReceived)
     })
 }
 
-fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+fn get_addr_tuple(handle: SocketHandle, sockets: &SocketSetWrapper) -> (IpEndpoint, IpEndpoint) {
+    sockets.with_socket::<tcp::Socket, _, _>(handle, |socket| {
         (
             socket.local_endpoint().unwrap(),
             socket.remote_endpoint().unwrap(),
@@ -0,0 +1,146 @@
+//! The network stacks of the network namespaces, each with a loopback
+//! interface.
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use axsync::Mutex;
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+
+use super::listen_table::ListenTable;
+use super::{ETHERNET_HEADER_LEN, InterfaceWrapper, RANDOM_SEED, STANDARD_MTU};
+use super::{SocketSetWrapper, snoop_tcp_packet};
+
+/// The MAC address of the loopback interfaces. Their frames never leave
+/// them, so it is the same for all of them.
+const LOOPBACK_MAC: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x01]);
+
+/// The network stack of a network namespace: its sockets, the TCP ports they
+/// listen on, and a loopback interface with `127.0.0.1/8` and `::1/128`.
+///
+/// The sockets of the namespace are only sent through its loopback
+/// interface, so they can not reach those of the other namespaces, nor the
+/// NIC, and they can bind the same ports.
+pub(crate) struct NamespaceStack {
+    pub(super) sockets: SocketSetWrapper<'static>,
+    pub(super) listen_table: ListenTable,
+    pub(super) iface: Mutex<Interface>,
+    /// The frames transmitted on the loopback interface, not received yet.
+    queue: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl NamespaceStack {
+    pub fn new() -> Self {
+        let listen_table = ListenTable::new();
+        let mut queue = VecDeque::new();
+        let mut dev = LoopbackDevice {
+            queue: &mut queue,
+            listen_table: &listen_table,
+        };
+        let mut config = Config::new(HardwareAddress::Ethernet(LOOPBACK_MAC));
+        config.random_seed = RANDOM_SEED;
+        let mut iface = Interface::new(config, &mut dev, InterfaceWrapper::current_time());
+        iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs
+                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+                .unwrap();
+            ip_addrs
+                .push(IpCidr::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 1), 128))
+                .unwrap();
+        });
+        Self {
+            sockets: SocketSetWrapper::new(),
+            listen_table,
+            iface: Mutex::new(iface),
+            queue: Mutex::new(queue),
+        }
+    }
+
+    /// Polls the loopback interface: the frames transmitted on it are
+    /// received by the sockets of the namespace.
+    pub fn poll(&self) {
+        let mut queue = self.queue.lock();
+        let mut iface = self.iface.lock();
+        let mut sockets = self.sockets.0.lock();
+        let mut dev = LoopbackDevice {
+            queue: &mut queue,
+            listen_table: &self.listen_table,
+        };
+        iface.poll(InterfaceWrapper::current_time(), &mut dev, &mut sockets);
+    }
+}
+
+/// The loopback device, while the interface is polled.
+struct LoopbackDevice<'a> {
+    queue: &'a mut VecDeque<Vec<u8>>,
+    /// The listen table the TCP SYNs are snooped into, see
+    /// [`snoop_tcp_packet`].
+    listen_table: &'a ListenTable,
+}
+
+impl Device for LoopbackDevice<'_> {
+    type RxToken<'a>
+        = LoopbackRxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = LoopbackTxToken<'a>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let buf = self.queue.pop_front()?;
+        let rx_token = LoopbackRxToken {
+            buf,
+            listen_table: self.listen_table,
+        };
+        Some((rx_token, LoopbackTxToken(&mut *self.queue)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(LoopbackTxToken(&mut *self.queue))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = STANDARD_MTU + ETHERNET_HEADER_LEN;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+struct LoopbackRxToken<'a> {
+    buf: Vec<u8>,
+    listen_table: &'a ListenTable,
+}
+
+struct LoopbackTxToken<'a>(&'a mut VecDeque<Vec<u8>>);
+
+impl RxToken for LoopbackRxToken<'_> {
+    fn preprocess(&self, sockets: &mut SocketSet<'_>) {
+        snoop_tcp_packet(&self.buf, self.listen_table, sockets).ok();
+    }
+
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        trace!("LOOPBACK RECV {} bytes", self.buf.len());
+        f(&mut self.buf)
+    }
+}
+
+impl TxToken for LoopbackTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0; len];
+        let ret = f(&mut buf);
+        self.0.push_back(buf);
+        ret
+    }
+}
@@ -1,16 +1,32 @@
 mod addr;
 mod bench;
 mod dns;
+mod icmp;
 mod listen_table;
+#[cfg(feature = "multitask")]
+mod loopback;
+#[cfg(feature = "napi")]
+mod napi;
+#[cfg(feature = "pm")]
+mod pm;
+#[cfg(feature = "rss")]
+mod rss;
+mod slaac;
 mod tcp;
 mod udp;
 
+#[cfg(feature = "multitask")]
+use alloc::sync::Arc;
 use alloc::vec;
+use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::net::{IpAddr, Ipv6Addr};
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
+use axerrno::{AxResult, ax_err};
 use axhal::time::{NANOS_PER_MICROS, wall_time_nanos};
 use axsync::Mutex;
 use lazyinit::LazyInit;
@@ -18,11 +34,18 @@ use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, Ipv6Address};
 
+use self::addr::{into_core_ipaddr, into_core_ipv6addr};
 use self::listen_table::ListenTable;
 
+#[cfg(feature = "multitask")]
+pub(crate) use self::loopback::NamespaceStack;
+
 pub use self::dns::dns_query;
+pub use self::icmp::ping;
+#[cfg(feature = "napi")]
+pub use self::napi::{NapiStats, napi_stats};
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
 
@@ -37,10 +60,15 @@ macro_rules! env_or_default {
 
 const IP: &str = env_or_default!("AX_IP");
 const GATEWAY: &str = env_or_default!("AX_GW");
+const IP6: &str = env_or_default!("AX_IP6");
+const GATEWAY6: &str = env_or_default!("AX_GW6");
 const DNS_SEVER: &str = "8.8.8.8";
+const DNS_SERVER6: &str = "2001:4860:4860::8888";
 const IP_PREFIX: u8 = 24;
+const IP6_PREFIX: u8 = 64;
 
 const STANDARD_MTU: usize = 1500;
+const ETHERNET_HEADER_LEN: usize = 14;
 
 const RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 
@@ -48,25 +76,94 @@ const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+const ICMP_BUF_LEN: usize = 4 * 1024;
 const LISTEN_QUEUE_SIZE: usize = 512;
 
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
 static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
+static COUNTERS: Counters = Counters::new();
 
 struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
+/// The network stack of a socket: the root one, with the NIC, or that of a
+/// network namespace, with a loopback interface of its own.
+#[derive(Clone)]
+enum NetStack {
+    Root,
+    #[cfg(feature = "multitask")]
+    Namespace(Arc<NamespaceStack>),
+}
+
 struct DeviceWrapper {
     inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    mtu: usize,
+    #[cfg(feature = "rss")]
+    steering: rss::RxSteering,
+    /// The packets received in the current poll, and the maximum of them.
+    #[cfg(feature = "napi")]
+    rx_count: usize,
+    #[cfg(feature = "napi")]
+    rx_budget: usize,
 }
 
 struct InterfaceWrapper {
     name: &'static str,
     ether_addr: EthernetAddress,
+    gateway: Mutex<Option<IpAddress>>,
+    gateway6: Mutex<Option<Ipv6Address>>,
     dev: Mutex<DeviceWrapper>,
     iface: Mutex<Interface>,
 }
 
+/// The packets and bytes received and transmitted by the interface.
+struct Counters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+}
+
+/// The configuration and the statistics of the network interface, see
+/// [`interface_info`].
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    /// The name of the interface.
+    pub name: &'static str,
+    /// The MAC address of the NIC.
+    pub mac: [u8; 6],
+    /// The IPv4 address, with the length of the network prefix.
+    pub ip: Option<(IpAddr, u8)>,
+    /// The default IPv4 gateway.
+    pub gateway: Option<IpAddr>,
+    /// The IPv6 addresses, link-local first, with the lengths of their
+    /// network prefixes.
+    pub ipv6: Vec<(Ipv6Addr, u8)>,
+    /// The default IPv6 gateway, e.g., learned from router advertisements.
+    pub gateway6: Option<Ipv6Addr>,
+    /// The MTU, in bytes.
+    pub mtu: usize,
+    /// The number of packets received.
+    pub rx_packets: u64,
+    /// The number of bytes received.
+    pub rx_bytes: u64,
+    /// The number of packets transmitted.
+    pub tx_packets: u64,
+    /// The number of bytes transmitted.
+    pub tx_bytes: u64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            rx_packets: AtomicU64::new(0),
+            rx_bytes: AtomicU64::new(0),
+            tx_packets: AtomicU64::new(0),
+            tx_bytes: AtomicU64::new(0),
+        }
+    }
+}
+
 impl<'a> SocketSetWrapper<'a> {
     fn new() -> Self {
         Self(Mutex::new(SocketSet::new(vec![])))
@@ -90,9 +187,23 @@ impl<'a> SocketSetWrapper<'a> {
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
 
+    pub fn new_icmp_socket() -> socket::icmp::Socket<'a> {
+        let icmp_rx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_BUF_LEN],
+        );
+        let icmp_tx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_BUF_LEN],
+        );
+        socket::icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
+    }
+
     pub fn new_dns_socket() -> socket::dns::Socket<'a> {
-        let server_addr = DNS_SEVER.parse().expect("invalid DNS server address");
-        socket::dns::Socket::new(&[server_addr], vec![])
+        // The IPv6 server is tried once the IPv4 one does not answer.
+        let servers = [DNS_SEVER, DNS_SERVER6]
+            .map(|server| server.parse().expect("invalid DNS server address"));
+        socket::dns::Socket::new(&servers, vec![])
     }
 
     pub fn add<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
@@ -120,7 +231,10 @@ impl<'a> SocketSetWrapper<'a> {
     }
 
     pub fn poll_interfaces(&self) {
+        #[cfg(feature = "pm")]
+        pm::mark_busy();
         ETH0.poll(&self.0);
+        slaac::poll();
     }
 
     pub fn remove(&self, handle: SocketHandle) {
@@ -129,21 +243,70 @@ impl<'a> SocketSetWrapper<'a> {
     }
 }
 
+impl NetStack {
+    /// Returns the network stack of the namespace of the current task.
+    fn current() -> Self {
+        #[cfg(feature = "multitask")]
+        if let Some(stack) = crate::ns::current_stack() {
+            return Self::Namespace(stack);
+        }
+        Self::Root
+    }
+
+    fn sockets(&self) -> &SocketSetWrapper<'static> {
+        match self {
+            Self::Root => &SOCKET_SET,
+            #[cfg(feature = "multitask")]
+            Self::Namespace(stack) => &stack.sockets,
+        }
+    }
+
+    fn listen_table(&self) -> &ListenTable {
+        match self {
+            Self::Root => &LISTEN_TABLE,
+            #[cfg(feature = "multitask")]
+            Self::Namespace(stack) => &stack.listen_table,
+        }
+    }
+
+    /// Returns the interface the sockets of the stack are sent through.
+    fn iface(&self) -> &Mutex<Interface> {
+        match self {
+            Self::Root => &ETH0.iface,
+            #[cfg(feature = "multitask")]
+            Self::Namespace(stack) => &stack.iface,
+        }
+    }
+
+    fn poll_interfaces(&self) {
+        match self {
+            Self::Root => SOCKET_SET.poll_interfaces(),
+            #[cfg(feature = "multitask")]
+            Self::Namespace(stack) => stack.poll(),
+        }
+    }
+}
+
 impl InterfaceWrapper {
     fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
-        let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
-        config.random_seed = RANDOM_SEED;
-
         let mut dev = DeviceWrapper::new(dev);
-        let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
+        let iface = Mutex::new(Self::new_iface(ether_addr, &mut dev));
         Self {
             name,
             ether_addr,
+            gateway: Mutex::new(None),
+            gateway6: Mutex::new(None),
             dev: Mutex::new(dev),
             iface,
         }
     }
 
+    fn new_iface(ether_addr: EthernetAddress, dev: &mut DeviceWrapper) -> Interface {
+        let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
+        config.random_seed = RANDOM_SEED;
+        Interface::new(config, dev, Self::current_time())
+    }
+
     fn current_time() -> Instant {
         Instant::from_micros_const((wall_time_nanos() / NANOS_PER_MICROS) as i64)
     }
@@ -156,6 +319,36 @@ impl InterfaceWrapper {
         self.ether_addr
     }
 
+    pub fn mtu(&self) -> usize {
+        self.dev.lock().mtu
+    }
+
+    pub fn max_mtu(&self) -> usize {
+        self.dev.lock().inner.borrow().max_mtu()
+    }
+
+    pub fn set_mtu(&self, mtu: usize) -> AxResult {
+        let mut dev = self.dev.lock();
+        if let Err(e) = dev.inner.get_mut().set_mtu(mtu) {
+            warn!("failed to set MTU to {}: {:?}", mtu, e);
+            return ax_err!(InvalidInput, "unsupported MTU");
+        }
+        dev.mtu = mtu;
+
+        // The interface keeps the device capabilities it was created with, so
+        // recreate it with the same addresses and routes.
+        let mut iface = self.iface.lock();
+        let mut new_iface = Self::new_iface(self.ether_addr, &mut dev);
+        new_iface.update_ip_addrs(|ip_addrs| {
+            for cidr in iface.ip_addrs() {
+                ip_addrs.push(*cidr).unwrap();
+            }
+        });
+        core::mem::swap(new_iface.routes_mut(), iface.routes_mut());
+        *iface = new_iface;
+        Ok(())
+    }
+
     pub fn setup_ip_addr(&self, ip: IpAddress, prefix_len: u8) {
         let mut iface = self.iface.lock();
         iface.update_ip_addrs(|ip_addrs| {
@@ -163,13 +356,70 @@ impl InterfaceWrapper {
         });
     }
 
+    /// Adds an address, unless the interface has it or has no room for it.
+    /// Returns whether it is added.
+    pub fn add_ip_addr(&self, ip: IpAddress, prefix_len: u8) -> bool {
+        let mut iface = self.iface.lock();
+        if iface.has_ip_addr(ip) {
+            return false;
+        }
+        let mut added = false;
+        iface.update_ip_addrs(|ip_addrs| {
+            added = ip_addrs.push(IpCidr::new(ip, prefix_len)).is_ok();
+        });
+        if !added {
+            warn!("no room for address {}/{}", ip, prefix_len);
+        }
+        added
+    }
+
     pub fn setup_gateway(&self, gateway: IpAddress) {
         let mut iface = self.iface.lock();
         match gateway {
-            IpAddress::Ipv4(v4) => iface.routes_mut().add_default_ipv4_route(v4).unwrap(),
+            IpAddress::Ipv4(v4) => {
+                iface.routes_mut().add_default_ipv4_route(v4).unwrap();
+                *self.gateway.lock() = Some(gateway);
+            }
+            IpAddress::Ipv6(v6) => {
+                iface.routes_mut().add_default_ipv6_route(v6).unwrap();
+                *self.gateway6.lock() = Some(v6);
+            }
         };
     }
 
+    pub fn gateway6(&self) -> Option<Ipv6Address> {
+        *self.gateway6.lock()
+    }
+
+    pub fn info(&self) -> InterfaceInfo {
+        let iface = self.iface.lock();
+        let ip = iface
+            .ip_addrs()
+            .iter()
+            .find(|cidr| matches!(cidr, IpCidr::Ipv4(_)));
+        let ipv6 = iface
+            .ip_addrs()
+            .iter()
+            .filter_map(|cidr| match cidr {
+                IpCidr::Ipv6(v6) => Some((into_core_ipv6addr(v6.address()), v6.prefix_len())),
+                _ => None,
+            })
+            .collect();
+        InterfaceInfo {
+            name: self.name,
+            mac: self.ether_addr.0,
+            ip: ip.map(|cidr| (into_core_ipaddr(cidr.address()), cidr.prefix_len())),
+            gateway: self.gateway.lock().map(into_core_ipaddr),
+            ipv6,
+            gateway6: self.gateway6().map(into_core_ipv6addr),
+            mtu: self.mtu(),
+            rx_packets: COUNTERS.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: COUNTERS.rx_bytes.load(Ordering::Relaxed),
+            tx_packets: COUNTERS.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: COUNTERS.tx_bytes.load(Ordering::Relaxed),
+        }
+    }
+
     pub fn poll(&self, sockets: &Mutex<SocketSet>) {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
@@ -177,12 +427,44 @@ impl InterfaceWrapper {
         let timestamp = Self::current_time();
         iface.poll(timestamp, dev.deref_mut(), &mut sockets);
     }
+
+    /// Returns how long the sockets can wait before the interface is polled,
+    /// for their timers, or `None` if they have none.
+    #[cfg(feature = "rss")]
+    pub fn poll_delay(&self, sockets: &Mutex<SocketSet>) -> Option<core::time::Duration> {
+        let mut iface = self.iface.lock();
+        let sockets = sockets.lock();
+        let delay = iface.poll_delay(Self::current_time(), &sockets)?;
+        Some(core::time::Duration::from_micros(delay.total_micros()))
+    }
+
+    /// Polls the interface, receiving at most `budget` packets, and returns
+    /// the number of packets received.
+    #[cfg(feature = "napi")]
+    pub fn poll_budget(&self, sockets: &Mutex<SocketSet>, budget: usize) -> usize {
+        let mut dev = self.dev.lock();
+        let mut iface = self.iface.lock();
+        let mut sockets = sockets.lock();
+        let timestamp = Self::current_time();
+        dev.rx_count = 0;
+        dev.rx_budget = budget;
+        iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        dev.rx_budget = usize::MAX;
+        dev.rx_count
+    }
 }
 
 impl DeviceWrapper {
     fn new(inner: AxNetDevice) -> Self {
         Self {
             inner: RefCell::new(inner),
+            mtu: STANDARD_MTU,
+            #[cfg(feature = "rss")]
+            steering: rss::RxSteering::new(axconfig::SMP),
+            #[cfg(feature = "napi")]
+            rx_count: 0,
+            #[cfg(feature = "napi")]
+            rx_budget: usize::MAX,
         }
     }
 }
@@ -198,6 +480,10 @@ impl Device for DeviceWrapper {
         Self: 'a;
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        #[cfg(feature = "napi")]
+        if self.rx_count >= self.rx_budget {
+            return None;
+        }
         let mut dev = self.inner.borrow_mut();
         if let Err(e) = dev.recycle_tx_buffers() {
             warn!("recycle_tx_buffers failed: {:?}", e);
@@ -207,7 +493,11 @@ impl Device for DeviceWrapper {
         if !dev.can_transmit() {
             return None;
         }
-        let rx_buf = match dev.receive() {
+        #[cfg(feature = "rss")]
+        let rx_buf = self.steering.receive(&mut dev);
+        #[cfg(not(feature = "rss"))]
+        let rx_buf = dev.receive();
+        let rx_buf = match rx_buf {
             Ok(buf) => buf,
             Err(err) => {
                 if !matches!(err, DevError::Again) {
@@ -216,6 +506,10 @@ impl Device for DeviceWrapper {
                 return None;
             }
         };
+        #[cfg(feature = "napi")]
+        {
+            self.rx_count += 1;
+        }
         Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
     }
 
@@ -234,7 +528,7 @@ impl Device for DeviceWrapper {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1514;
+        caps.max_transmission_unit = self.mtu + ETHERNET_HEADER_LEN;
         caps.max_burst_size = None;
         caps.medium = Medium::Ethernet;
         caps
@@ -246,7 +540,7 @@ struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
 
 impl RxToken for AxNetRxToken<'_> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
-        snoop_tcp_packet(self.1.packet(), sockets).ok();
+        snoop_tcp_packet(self.1.packet(), &LISTEN_TABLE, sockets).ok();
     }
 
     fn consume<R, F>(self, f: F) -> R
@@ -259,6 +553,10 @@ impl RxToken for AxNetRxToken<'_> {
             rx_buf.packet_len(),
             rx_buf.packet()
         );
+        COUNTERS.rx_packets.fetch_add(1, Ordering::Relaxed);
+        COUNTERS
+            .rx_bytes
+            .fetch_add(rx_buf.packet_len() as u64, Ordering::Relaxed);
         let result = f(rx_buf.packet_mut());
         self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
         result
@@ -275,35 +573,96 @@ impl TxToken for AxNetTxToken<'_> {
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
         dev.transmit(tx_buf).unwrap();
+        COUNTERS.tx_packets.fetch_add(1, Ordering::Relaxed);
+        COUNTERS.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
         ret
     }
 }
 
-fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smoltcp::wire::Error> {
-    use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
+fn snoop_tcp_packet(
+    buf: &[u8],
+    listen_table: &ListenTable,
+    sockets: &mut SocketSet<'_>,
+) -> Result<(), smoltcp::wire::Error> {
+    use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, Ipv6Packet};
+    use smoltcp::wire::{IpEndpoint, TcpPacket};
 
     let ether_frame = EthernetFrame::new_checked(buf)?;
-    let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
+    let (src_ip, dst_ip, protocol, payload): (IpAddress, IpAddress, _, _) =
+        match ether_frame.ethertype() {
+            EthernetProtocol::Ipv4 => {
+                let packet = Ipv4Packet::new_checked(ether_frame.payload())?;
+                let payload = &ether_frame.payload()[packet.header_len() as usize..];
+                (
+                    packet.src_addr().into(),
+                    packet.dst_addr().into(),
+                    packet.next_header(),
+                    payload,
+                )
+            }
+            EthernetProtocol::Ipv6 => {
+                let packet = Ipv6Packet::new_checked(ether_frame.payload())?;
+                let payload = &ether_frame.payload()[packet.header_len()..];
+                (
+                    packet.src_addr().into(),
+                    packet.dst_addr().into(),
+                    packet.next_header(),
+                    payload,
+                )
+            }
+            _ => return Ok(()),
+        };
 
-    if ipv4_packet.next_header() == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
-        let src_addr = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
-        let dst_addr = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
+    if protocol == IpProtocol::Tcp {
+        let tcp_packet = TcpPacket::new_checked(payload)?;
+        let src_addr = IpEndpoint::new(src_ip, tcp_packet.src_port());
+        let dst_addr = IpEndpoint::new(dst_ip, tcp_packet.dst_port());
         let is_first = tcp_packet.syn() && !tcp_packet.ack();
         if is_first {
             // create a socket for the first incoming TCP packet, as the later accept() returns.
-            LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+            listen_table.incoming_tcp_packet(src_addr, dst_addr, sockets);
         }
     }
     Ok(())
 }
 
-/// Poll the network stack.
+/// Returns how long the network stack can wait before it is polled again, or
+/// `None` if it has no timer.
+#[cfg(feature = "rss")]
+fn poll_delay() -> Option<core::time::Duration> {
+    ETH0.poll_delay(&SOCKET_SET.0)
+}
+
+/// Poll the network stack of the current task.
 ///
 /// It may receive packets from the NIC and process them, and transmit queued
-/// packets to the NIC.
+/// packets to the NIC. In a network namespace, it delivers the packets sent on
+/// its loopback interface instead.
 pub fn poll_interfaces() {
-    SOCKET_SET.poll_interfaces();
+    NetStack::current().poll_interfaces();
+}
+
+/// Returns the configuration and the statistics of the network interface.
+pub fn interface_info() -> InterfaceInfo {
+    ETH0.info()
+}
+
+/// Returns the MTU of the network interface, in bytes.
+pub fn mtu() -> usize {
+    ETH0.mtu()
+}
+
+/// Returns the maximum MTU supported by the NIC, in bytes.
+pub fn max_mtu() -> usize {
+    ETH0.max_mtu()
+}
+
+/// Sets the MTU of the network interface, in bytes.
+///
+/// MTUs above the standard 1500 bytes (jumbo frames) are only accepted if the
+/// NIC supports them.
+pub fn set_mtu(mtu: usize) -> AxResult {
+    ETH0.set_mtu(mtu)
 }
 
 /// Benchmark raw socket transmit bandwidth.
@@ -324,13 +683,39 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     let gateway = GATEWAY.parse().expect("invalid gateway IP address");
     eth0.setup_ip_addr(ip, IP_PREFIX);
     eth0.setup_gateway(gateway);
+    // The link-local address, for the neighbor discovery.
+    let link_local = slaac::link_local_addr(ether_addr);
+    eth0.setup_ip_addr(IpAddress::Ipv6(link_local), IP6_PREFIX);
+    let ip6 = (!IP6.is_empty()).then(|| IP6.parse::<IpAddress>().expect("invalid IPv6 address"));
+    if let Some(ip6) = ip6 {
+        eth0.setup_ip_addr(ip6, IP6_PREFIX);
+    }
+    if !GATEWAY6.is_empty() {
+        eth0.setup_gateway(GATEWAY6.parse().expect("invalid IPv6 gateway address"));
+    }
 
     ETH0.init_once(eth0);
     SOCKET_SET.init_once(SocketSetWrapper::new());
     LISTEN_TABLE.init_once(ListenTable::new());
+    if ip6.is_none() {
+        slaac::init();
+    }
 
     info!("created net interface {:?}:", ETH0.name());
     info!("  ether:    {}", ETH0.ethernet_address());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
+    info!("  ip6:      {}/{}", link_local, IP6_PREFIX);
+    match ip6 {
+        Some(ip6) => info!("  ip6:      {}/{}", ip6, IP6_PREFIX),
+        None => info!("  ip6:      (SLAAC)"),
+    }
+    info!("  mtu:      {} (max {})", ETH0.mtu(), ETH0.max_mtu());
+
+    #[cfg(feature = "rss")]
+    rss::init();
+    #[cfg(feature = "napi")]
+    napi::init();
+    #[cfg(feature = "pm")]
+    pm::init();
 }
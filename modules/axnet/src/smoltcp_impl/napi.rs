@@ -0,0 +1,106 @@
+//! NAPI-like hybrid of interrupts and polling for the NIC.
+//!
+//! Taking the packets in the interrupt handler does not hold under a packet
+//! flood: the CPU spends all its time in the handler, and the tasks, e.g.,
+//! the console, starve (interrupt livelock). Instead, the interrupt of the
+//! NIC masks itself and wakes the `net-napi` task, which processes at most
+//! [`NAPI_BUDGET`] packets per poll, yielding the CPU between two polls, as
+//! long as the NIC has more. The interrupt is unmasked once the NIC is
+//! drained. So the NIC is polled under load, and interrupts only when idle.
+//!
+//! The NIC drivers do not expose their interrupt, so its number is given by
+//! `AX_NET_IRQ` at build time. Nor do they acknowledge it, so it must be
+//! edge-triggered (e.g., MSI), or deasserted by the NIC once its RX queue is
+//! drained.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use axtask::WaitQueue;
+
+use super::{ETH0, SOCKET_SET};
+
+/// The maximum number of packets processed in one poll.
+const NAPI_BUDGET: usize = 64;
+
+/// The interrupt of the NIC.
+static NET_IRQ: AtomicUsize = AtomicUsize::new(0);
+/// Whether the interrupt fired and is masked until the NIC is drained.
+static SCHEDULED: AtomicBool = AtomicBool::new(false);
+static NAPI_WQ: WaitQueue = WaitQueue::new();
+
+static INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static POLLS: AtomicU64 = AtomicU64::new(0);
+static BUDGET_EXHAUSTED: AtomicU64 = AtomicU64::new(0);
+static PACKETS: AtomicU64 = AtomicU64::new(0);
+
+/// Statistics of the NIC interrupt and of the polls, to monitor its load.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NapiStats {
+    /// The number of interrupts taken.
+    pub interrupts: u64,
+    /// The number of polls of the NIC.
+    pub polls: u64,
+    /// The number of polls that used up their budget, i.e., with the NIC
+    /// still having packets, so it went on being polled with the interrupt
+    /// masked.
+    pub budget_exhausted: u64,
+    /// The number of packets received by the polls.
+    pub packets: u64,
+}
+
+/// Returns the statistics of the NIC interrupt and of the polls.
+pub fn napi_stats() -> NapiStats {
+    NapiStats {
+        interrupts: INTERRUPTS.load(Ordering::Relaxed),
+        polls: POLLS.load(Ordering::Relaxed),
+        budget_exhausted: BUDGET_EXHAUSTED.load(Ordering::Relaxed),
+        packets: PACKETS.load(Ordering::Relaxed),
+    }
+}
+
+fn napi_irq_handler(irq: usize) {
+    axhal::irq::set_enable(irq, false);
+    INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+    SCHEDULED.store(true, Ordering::Release);
+    NAPI_WQ.notify_one(true);
+}
+
+fn napi_poll_task() {
+    loop {
+        NAPI_WQ.wait_until(|| SCHEDULED.load(Ordering::Acquire));
+        #[cfg(feature = "pm")]
+        super::pm::mark_busy();
+        loop {
+            let packets = ETH0.poll_budget(&SOCKET_SET.0, NAPI_BUDGET);
+            POLLS.fetch_add(1, Ordering::Relaxed);
+            PACKETS.fetch_add(packets as u64, Ordering::Relaxed);
+            if packets < NAPI_BUDGET {
+                break;
+            }
+            BUDGET_EXHAUSTED.fetch_add(1, Ordering::Relaxed);
+            axtask::yield_now();
+        }
+        SCHEDULED.store(false, Ordering::Release);
+        axhal::irq::set_enable(NET_IRQ.load(Ordering::Relaxed), true);
+    }
+}
+
+/// Spawns the `net-napi` task and registers the interrupt of the NIC, if it
+/// is given by `AX_NET_IRQ`.
+pub(super) fn init() {
+    let irq = match option_env!("AX_NET_IRQ") {
+        Some(irq) if !irq.is_empty() => irq,
+        _ => {
+            warn!("AX_NET_IRQ is not set, the NIC is only polled by socket operations");
+            return;
+        }
+    };
+    let irq = irq.parse().expect("invalid AX_NET_IRQ");
+    NET_IRQ.store(irq, Ordering::Relaxed);
+    axtask::spawn_raw(napi_poll_task, "net-napi".into(), axconfig::TASK_STACK_SIZE);
+    if !axhal::irq::register_handler(irq, &napi_irq_handler) {
+        warn!("failed to register the NIC interrupt {}", irq);
+        return;
+    }
+    info!("  NAPI on IRQ {}, budget {}", irq, NAPI_BUDGET);
+}
@@ -0,0 +1,51 @@
+//! Runtime power management of the NIC.
+//!
+//! The NIC is idle, and suspended by the idle detector of
+//! [`axhal::pm`], when there are no sockets. The NIC drivers have no
+//! low-power state yet, so suspending it only reclaims the buffers of the
+//! packets sent; it is the hook for them to build on.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axhal::pm::DevicePm;
+
+use super::{ETH0, SOCKET_SET};
+
+/// The ID of the NIC for runtime power management, if registered.
+static PM_ID: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+struct NicPm;
+
+impl DevicePm for NicPm {
+    fn is_idle(&self) -> bool {
+        // Not waiting for the lock, as it is called with the device table
+        // locked.
+        SOCKET_SET
+            .0
+            .try_lock()
+            .is_some_and(|sockets| sockets.iter().next().is_none())
+    }
+
+    fn suspend(&self) {
+        if let Some(dev) = ETH0.dev.try_lock() {
+            dev.inner.borrow_mut().recycle_tx_buffers().ok();
+        }
+    }
+
+    fn resume(&self) {}
+}
+
+/// Records that the NIC is used, see [`axhal::pm::mark_busy`].
+pub(super) fn mark_busy() {
+    let id = PM_ID.load(Ordering::Acquire);
+    if id != usize::MAX {
+        axhal::pm::mark_busy(id);
+    }
+}
+
+/// Registers the NIC for runtime power management.
+pub(super) fn init() {
+    if let Some(id) = axhal::pm::register(ETH0.name, &NicPm, None) {
+        PM_ID.store(id, Ordering::Release);
+    }
+}
@@ -0,0 +1,419 @@
+//! Receive-side scaling (RSS) in software.
+//!
+//! Received packets are hashed by their flow (IPv4 or IPv6 addresses and
+//! TCP/UDP ports) with the Toeplitz hash, and steered to a per-CPU RX backlog
+//! through an indirection table, so all packets of a flow are processed on
+//! the same CPU. Each CPU runs a `net-rx` task bound to it, the software
+//! interrupt of its queue: it sleeps until packets are steered to its
+//! backlog, and then processes them.
+//!
+//! The NIC drivers only have a single RX queue, so packets are steered after
+//! they are received from the NIC, rather than by the NIC itself. The
+//! interrupt of the NIC, given by `AX_NET_IRQ`, is routed to the CPU of
+//! [`IRQ_QUEUE`], whose task masks it while it drains the NIC, steering the
+//! packets of the other queues to their tasks. Without it, or with the
+//! `napi` feature, which takes the interrupt, the NIC is drained by the
+//! polls of the sockets and of the `net-napi` task.
+//!
+//! The backlogs are lock-free rings, but smoltcp has a single interface
+//! for all the sockets, so the packets are still processed, and the NIC
+//! drained, under the lock of the interface, one CPU at a time.
+
+use alloc::format;
+#[cfg(not(feature = "napi"))]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axdriver::prelude::*;
+use axdriver_net::{DevError, DevResult, NetBufPtr};
+use axring::MpscRing;
+use axtask::WaitQueue;
+
+/// The default Toeplitz key from the Microsoft RSS specification.
+const RSS_KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// The number of entries in the indirection table.
+const TABLE_SIZE: usize = 128;
+
+/// The maximum number of packets in a backlog. More packets are dropped.
+const MAX_BACKLOG_LEN: usize = 256;
+
+/// The maximum number of packets taken from the NIC in one receive call.
+const RX_BUDGET: usize = 64;
+
+/// The queue whose CPU takes the interrupt of the NIC.
+const IRQ_QUEUE: usize = 0;
+
+/// The longest the task of [`IRQ_QUEUE`] sleeps without packets, to run the
+/// timers of the sockets, e.g., TCP retransmissions.
+const MAX_POLL_DELAY: Duration = Duration::from_millis(100);
+
+/// Computes the Toeplitz hash of `input`.
+fn toeplitz_hash(key: &[u8; 40], input: &[u8]) -> u32 {
+    let mut hash = 0;
+    let mut window = u32::from_be_bytes([key[0], key[1], key[2], key[3]]);
+    for (i, &byte) in input.iter().enumerate() {
+        let next = key.get(i + 4).copied().unwrap_or(0);
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                hash ^= window;
+            }
+            window = window << 1 | ((next >> (7 - bit)) & 1) as u32;
+        }
+    }
+    hash
+}
+
+/// Computes the RSS hash of an Ethernet frame.
+///
+/// Returns `None` if it is not an IP packet.
+fn flow_hash(frame: &[u8]) -> Option<u32> {
+    use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, Ipv6Packet};
+
+    let ether_frame = EthernetFrame::new_checked(frame).ok()?;
+    // The source and destination addresses, then the ports.
+    let mut input = [0; 36];
+    let (addrs_len, protocol, payload) = match ether_frame.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let packet = Ipv4Packet::new_checked(ether_frame.payload()).ok()?;
+            input[..4].copy_from_slice(packet.src_addr().as_bytes());
+            input[4..8].copy_from_slice(packet.dst_addr().as_bytes());
+            // Fragments other than the first one have no ports, so only the
+            // addresses are hashed for fragmented packets.
+            let protocol = if packet.more_frags() || packet.frag_offset() != 0 {
+                None
+            } else {
+                Some(packet.next_header())
+            };
+            (8, protocol, packet.payload())
+        }
+        EthernetProtocol::Ipv6 => {
+            let packet = Ipv6Packet::new_checked(ether_frame.payload()).ok()?;
+            input[..16].copy_from_slice(packet.src_addr().as_bytes());
+            input[16..32].copy_from_slice(packet.dst_addr().as_bytes());
+            (32, Some(packet.next_header()), packet.payload())
+        }
+        _ => return None,
+    };
+    let len = match protocol {
+        Some(IpProtocol::Tcp | IpProtocol::Udp) if payload.len() >= 4 => {
+            input[addrs_len..addrs_len + 4].copy_from_slice(&payload[..4]);
+            addrs_len + 4
+        }
+        _ => addrs_len,
+    };
+    Some(toeplitz_hash(&RSS_KEY, &input[..len]))
+}
+
+struct RxPacket(NetBufPtr);
+
+// SAFETY: the buffer is owned by the packet, and only accessed with the
+// device locked.
+unsafe impl Send for RxPacket {}
+
+/// The packets steered to a CPU, and its `net-rx` task.
+struct Backlog {
+    /// Pushed by the CPU receiving from the NIC, popped by that of the
+    /// backlog.
+    packets: MpscRing<RxPacket, MAX_BACKLOG_LEN>,
+    /// Whether the task has work: packets were steered to it, or the NIC
+    /// interrupt fired.
+    kicked: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Backlog {
+    const fn new() -> Self {
+        Self {
+            packets: MpscRing::new(),
+            kicked: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Wakes up the task of the backlog.
+    fn kick(&self) {
+        self.kicked.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+}
+
+static BACKLOGS: [Backlog; axconfig::SMP] = [const { Backlog::new() }; axconfig::SMP];
+
+/// Whether the last packets were received until the NIC had no more.
+static NIC_DRAINED: AtomicBool = AtomicBool::new(false);
+
+/// The interrupt of the NIC, or `usize::MAX` if it has none.
+#[cfg(not(feature = "napi"))]
+static NET_IRQ: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// Whether the interrupt of the NIC fired and is masked until the NIC is
+/// drained.
+#[cfg(not(feature = "napi"))]
+static IRQ_MASKED: AtomicBool = AtomicBool::new(false);
+
+/// Steers received packets to per-CPU backlogs.
+pub(super) struct RxSteering {
+    table: [u8; TABLE_SIZE],
+    dropped: usize,
+}
+
+impl RxSteering {
+    pub fn new(num_queues: usize) -> Self {
+        assert!(num_queues > 0 && num_queues <= axconfig::SMP);
+        let mut table = [0; TABLE_SIZE];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (i % num_queues) as u8;
+        }
+        Self { table, dropped: 0 }
+    }
+
+    /// Returns the queue for the given Ethernet frame.
+    ///
+    /// Non-IP packets go to queue 0.
+    pub fn queue_of(&self, frame: &[u8]) -> usize {
+        match flow_hash(frame) {
+            Some(hash) => self.table[hash as usize % TABLE_SIZE] as usize,
+            None => 0,
+        }
+    }
+
+    /// Receives a packet to be processed on the current CPU.
+    ///
+    /// The packet is taken from the backlog of the current CPU if it is not
+    /// empty, otherwise packets are received from the NIC until one is
+    /// steered to the current CPU. Packets steered to other CPUs are put in
+    /// their backlogs, and their tasks woken up, or dropped if the backlog is
+    /// full. Gives up after [`RX_BUDGET`] packets.
+    pub fn receive(&mut self, dev: &mut AxNetDevice) -> DevResult<NetBufPtr> {
+        let queue = axhal::cpu::this_cpu_id() % axconfig::SMP;
+        if let Some(packet) = BACKLOGS[queue].packets.pop() {
+            return Ok(packet.0);
+        }
+        for _ in 0..RX_BUDGET {
+            let buf = match dev.receive() {
+                Ok(buf) => buf,
+                Err(DevError::Again) => {
+                    NIC_DRAINED.store(true, Ordering::Release);
+                    return Err(DevError::Again);
+                }
+                Err(e) => return Err(e),
+            };
+            let target = self.queue_of(buf.packet());
+            if target == queue {
+                return Ok(buf);
+            }
+            let backlog = &BACKLOGS[target];
+            match backlog.packets.push(RxPacket(buf)) {
+                Ok(()) => backlog.kick(),
+                Err(RxPacket(buf)) => {
+                    self.dropped += 1;
+                    debug!(
+                        "RX backlog {} is full, {} packets dropped",
+                        target, self.dropped
+                    );
+                    dev.recycle_rx_buffer(buf)?;
+                }
+            }
+        }
+        Err(DevError::Again)
+    }
+}
+
+/// The `net-rx` task of a queue, bound to its CPU.
+fn rx_task(queue: usize) {
+    axtask::set_current_affinity(axtask::AxCpuMask::one_shot(queue));
+    let backlog = &BACKLOGS[queue];
+    loop {
+        let kicked = || backlog.kicked.load(Ordering::Acquire);
+        if queue == IRQ_QUEUE {
+            let delay = super::poll_delay().map_or(MAX_POLL_DELAY, |d| d.min(MAX_POLL_DELAY));
+            backlog.wq.wait_timeout_until(delay, kicked);
+        } else {
+            backlog.wq.wait_until(kicked);
+        }
+        backlog.kicked.store(false, Ordering::Release);
+
+        #[cfg(not(feature = "napi"))]
+        let irq = queue == IRQ_QUEUE && IRQ_MASKED.load(Ordering::Acquire);
+        #[cfg(feature = "napi")]
+        let irq = false;
+        loop {
+            NIC_DRAINED.store(false, Ordering::Release);
+            super::SOCKET_SET.poll_interfaces();
+            // Until the NIC is drained, as its interrupt is masked.
+            if !irq || NIC_DRAINED.load(Ordering::Acquire) {
+                break;
+            }
+            axtask::yield_now();
+        }
+        #[cfg(not(feature = "napi"))]
+        if irq {
+            IRQ_MASKED.store(false, Ordering::Release);
+            axhal::irq::set_enable(NET_IRQ.load(Ordering::Relaxed), true);
+        }
+    }
+}
+
+#[cfg(not(feature = "napi"))]
+fn rss_irq_handler(irq: usize) {
+    axhal::irq::set_enable(irq, false);
+    IRQ_MASKED.store(true, Ordering::Release);
+    BACKLOGS[IRQ_QUEUE].kick();
+}
+
+/// Routes the interrupt of the NIC given by `AX_NET_IRQ`, if any, to the CPU
+/// of [`IRQ_QUEUE`].
+#[cfg(not(feature = "napi"))]
+fn init_irq() {
+    let irq: usize = match option_env!("AX_NET_IRQ") {
+        Some(irq) if !irq.is_empty() => irq.parse().expect("invalid AX_NET_IRQ"),
+        _ => {
+            warn!("AX_NET_IRQ is not set, the NIC is only drained by the polls");
+            return;
+        }
+    };
+    NET_IRQ.store(irq, Ordering::Relaxed);
+    if !axhal::irq::register_handler(irq, &rss_irq_handler) {
+        warn!("failed to register the NIC interrupt {}", irq);
+        return;
+    }
+    if !axhal::irq::set_affinity(irq, 1 << IRQ_QUEUE) {
+        warn!(
+            "failed to route the NIC interrupt {} to CPU {}",
+            irq, IRQ_QUEUE
+        );
+    }
+    info!("  RSS on {} queues, NIC interrupt {}", axconfig::SMP, irq);
+}
+
+/// Spawns a `net-rx` task on each CPU to process its backlog, and takes the
+/// interrupt of the NIC.
+pub(super) fn init() {
+    for queue in 0..axconfig::SMP {
+        axtask::spawn_raw(
+            move || rx_task(queue),
+            format!("net-rx/{}", queue),
+            axconfig::TASK_STACK_SIZE,
+        );
+    }
+    #[cfg(not(feature = "napi"))]
+    init_irq();
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::{IpAddr, SocketAddr};
+
+    use super::{RSS_KEY, flow_hash, toeplitz_hash};
+
+    /// The verification suite of the Microsoft RSS specification: the
+    /// destination and the source, the hash of their addresses, and the hash
+    /// with their ports.
+    const VECTORS: [(&str, &str, u32, u32); 8] = [
+        (
+            "161.142.100.80:1766",
+            "66.9.149.187:2794",
+            0x323e8fc2,
+            0x51ccc178,
+        ),
+        (
+            "65.69.140.83:4739",
+            "199.92.111.2:14230",
+            0xd718262a,
+            0xc626b0ea,
+        ),
+        (
+            "12.22.207.184:38024",
+            "24.19.198.95:12898",
+            0xd2d0a5de,
+            0x5c2b394a,
+        ),
+        (
+            "209.142.163.6:2217",
+            "38.27.205.30:48228",
+            0x82989176,
+            0xafc7327f,
+        ),
+        (
+            "202.188.127.2:1303",
+            "153.39.163.191:44251",
+            0x5d1809c5,
+            0x10e828a2,
+        ),
+        (
+            "[3ffe:2501:200:3::1]:1766",
+            "[3ffe:2501:200:1fff::7]:2794",
+            0x2cc18cd5,
+            0x40207d3d,
+        ),
+        (
+            "[ff02::1]:4739",
+            "[3ffe:501:8::260:97ff:fe40:efab]:14230",
+            0x0f0c461c,
+            0xdde51bbf,
+        ),
+        (
+            "[fe80::200:f8ff:fe21:67cf]:38024",
+            "[3ffe:1900:4545:3:200:f8ff:fe21:67cf]:44251",
+            0x4b61e985,
+            0x02d1feef,
+        ),
+    ];
+
+    fn octets(addr: IpAddr) -> Vec<u8> {
+        match addr {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_toeplitz() {
+        for (dst, src, hash, hash_ports) in VECTORS {
+            let dst: SocketAddr = dst.parse().unwrap();
+            let src: SocketAddr = src.parse().unwrap();
+            let addrs = [octets(src.ip()), octets(dst.ip())].concat();
+            let ports = [src.port().to_be_bytes(), dst.port().to_be_bytes()].concat();
+            assert_eq!(toeplitz_hash(&RSS_KEY, &addrs), hash, "{}", dst);
+            let input = [addrs, ports].concat();
+            assert_eq!(toeplitz_hash(&RSS_KEY, &input), hash_ports, "{}", dst);
+        }
+    }
+
+    /// Builds an Ethernet frame with an IPv4 header, and the ports of a TCP
+    /// header.
+    fn ipv4_frame(src: SocketAddr, dst: SocketAddr, frag: u16) -> Vec<u8> {
+        let mut frame = vec![0; 14 + 20 + 20];
+        frame[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+        let ip = &mut frame[14..];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&40u16.to_be_bytes());
+        ip[6..8].copy_from_slice(&frag.to_be_bytes());
+        ip[8] = 64;
+        ip[9] = 6; // TCP
+        ip[12..16].copy_from_slice(&octets(src.ip()));
+        ip[16..20].copy_from_slice(&octets(dst.ip()));
+        ip[20..22].copy_from_slice(&src.port().to_be_bytes());
+        ip[22..24].copy_from_slice(&dst.port().to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_flow_hash() {
+        let (dst, src, hash, hash_ports) = VECTORS[0];
+        let (dst, src) = (dst.parse().unwrap(), src.parse().unwrap());
+        assert_eq!(flow_hash(&ipv4_frame(src, dst, 0)), Some(hash_ports));
+        // A fragment is hashed by its addresses only.
+        const MORE_FRAGS: u16 = 0x2000;
+        assert_eq!(flow_hash(&ipv4_frame(src, dst, MORE_FRAGS)), Some(hash));
+        // Not an IP packet.
+        let mut frame = ipv4_frame(src, dst, 0);
+        frame[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+        assert_eq!(flow_hash(&frame), None);
+    }
+}
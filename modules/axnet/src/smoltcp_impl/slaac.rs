@@ -0,0 +1,205 @@
+//! IPv6 stateless address autoconfiguration (SLAAC, RFC 4862).
+//!
+//! Unless an IPv6 address is set at build time (`AX_IP6`), the interface
+//! solicits the routers of the link, and takes an address in each prefix of
+//! their advertisements flagged for autoconfiguration, with the interface
+//! identifier of its link-local address (derived from the MAC address). The
+//! first router advertising a non-zero lifetime becomes the default gateway.
+//!
+//! The neighbor discovery itself (NDP) is done by smoltcp. The lifetimes of
+//! the addresses are not tracked: they are kept until the next reboot.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axhal::time::monotonic_time_nanos;
+use lazyinit::LazyInit;
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+use smoltcp::wire::{
+    EthernetAddress, Icmpv6Packet, Icmpv6Repr, IpAddress, IpProtocol, IpVersion, Ipv6Address,
+    Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags, NdiscRepr, RawHardwareAddress,
+};
+use spin::Mutex;
+
+use super::{ETH0, SOCKET_SET};
+
+/// The maximum number of router solicitations sent (`MAX_RTR_SOLICITATIONS`).
+const MAX_SOLICITATIONS: u32 = 3;
+/// The interval between the router solicitations, in nanoseconds
+/// (`RTR_SOLICITATION_INTERVAL`).
+const SOLICITATION_INTERVAL_NS: u64 = 4_000_000_000;
+/// The length of the prefixes addresses are configured in, as the interface
+/// identifiers are 64 bits long.
+const SLAAC_PREFIX_LEN: u8 = 64;
+/// The hop limit of the neighbor discovery messages, so they are known to be
+/// from the link.
+const NDISC_HOP_LIMIT: u8 = 255;
+
+const RAW_BUF_LEN: usize = 4 * 1024;
+
+/// The raw ICMPv6 socket receiving the router advertisements.
+static SOCKET: LazyInit<SocketHandle> = LazyInit::new();
+
+/// What a router advertisement configures.
+struct Advert {
+    /// The address taken in the advertised prefix.
+    addr: Option<Ipv6Address>,
+    /// The router, if it can be the default gateway.
+    router: Option<Ipv6Address>,
+}
+
+/// The router solicitations sent, and when the last one was.
+struct SolicitState {
+    sent: u32,
+    last_ns: u64,
+    advertised: bool,
+}
+
+static STATE: Mutex<SolicitState> = Mutex::new(SolicitState {
+    sent: 0,
+    last_ns: 0,
+    advertised: false,
+});
+
+/// Returns the link-local address of the interface, whose interface
+/// identifier is the modified EUI-64 of the MAC address.
+pub fn link_local_addr(mac: EthernetAddress) -> Ipv6Address {
+    let mut bytes = [0; 16];
+    bytes[..2].copy_from_slice(&[0xfe, 0x80]);
+    bytes[8..].copy_from_slice(&interface_id(mac));
+    Ipv6Address(bytes)
+}
+
+fn interface_id(mac: EthernetAddress) -> [u8; 8] {
+    let m = mac.0;
+    // The universal/local bit is inverted.
+    [m[0] ^ 0x02, m[1], m[2], 0xff, 0xfe, m[3], m[4], m[5]]
+}
+
+/// Starts soliciting the routers.
+pub fn init() {
+    let rx_buffer =
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 8], vec![0; RAW_BUF_LEN]);
+    let tx_buffer =
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 2], vec![0; RAW_BUF_LEN]);
+    let socket = raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer);
+    SOCKET.init_once(SOCKET_SET.add(socket));
+    poll();
+}
+
+/// Handles the router advertisements received, and solicits the routers
+/// again if none answered. It is called after the interface is polled.
+pub fn poll() {
+    let Some(&handle) = SOCKET.get() else {
+        return;
+    };
+    let mac = ETH0.ethernet_address();
+    // Configured once the socket set is unlocked, as the interface is locked
+    // before it when polled.
+    let adverts = SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(handle, |socket| {
+        let mut adverts = Vec::new();
+        while let Ok(packet) = socket.recv() {
+            adverts.extend(parse_advert(packet, mac));
+        }
+        let mut state = STATE.lock();
+        state.advertised |= !adverts.is_empty();
+        let now = monotonic_time_nanos();
+        if !state.advertised
+            && state.sent < MAX_SOLICITATIONS
+            && (state.sent == 0 || now - state.last_ns >= SOLICITATION_INTERVAL_NS)
+            && send_solicitation(socket, mac)
+        {
+            state.sent += 1;
+            state.last_ns = now;
+        }
+        adverts
+    });
+    for advert in adverts {
+        match advert.addr {
+            Some(addr) if ETH0.add_ip_addr(IpAddress::Ipv6(addr), SLAAC_PREFIX_LEN) => {
+                info!("SLAAC: address {}/{} configured", addr, SLAAC_PREFIX_LEN);
+            }
+            _ => {}
+        }
+        match advert.router {
+            Some(router) if ETH0.gateway6().is_none() => {
+                ETH0.setup_gateway(IpAddress::Ipv6(router));
+                info!("SLAAC: default gateway {}", router);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends a router solicitation to all the routers of the link.
+fn send_solicitation(socket: &mut raw::Socket, mac: EthernetAddress) -> bool {
+    let src_addr = link_local_addr(mac);
+    let dst_addr = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+    let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+        lladdr: Some(RawHardwareAddress::from_bytes(&mac.0)),
+    });
+    let ip_repr = Ipv6Repr {
+        src_addr,
+        dst_addr,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: NDISC_HOP_LIMIT,
+    };
+    let Ok(buf) = socket.send(ip_repr.buffer_len() + icmp_repr.buffer_len()) else {
+        return false;
+    };
+    let mut packet = Ipv6Packet::new_unchecked(buf);
+    ip_repr.emit(&mut packet);
+    icmp_repr.emit(
+        &src_addr.into(),
+        &dst_addr.into(),
+        &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+        &ChecksumCapabilities::default(),
+    );
+    debug!("SLAAC: router solicitation sent");
+    true
+}
+
+/// Parses a router advertisement, or returns `None` if the packet is not
+/// one.
+fn parse_advert(packet: &[u8], mac: EthernetAddress) -> Option<Advert> {
+    let ip_packet = Ipv6Packet::new_checked(packet).ok()?;
+    let ip_repr = Ipv6Repr::parse(&ip_packet).ok()?;
+    let icmp_packet = Icmpv6Packet::new_checked(ip_packet.payload()).ok()?;
+    // The checksum is verified by the interface.
+    let icmp_repr = Icmpv6Repr::parse(
+        &ip_repr.src_addr.into(),
+        &ip_repr.dst_addr.into(),
+        &icmp_packet,
+        &ChecksumCapabilities::ignored(),
+    )
+    .ok()?;
+    let Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+        router_lifetime,
+        prefix_info,
+        ..
+    }) = icmp_repr
+    else {
+        return None;
+    };
+    if ip_repr.hop_limit != NDISC_HOP_LIMIT || !ip_repr.src_addr.is_link_local() {
+        return None;
+    }
+
+    let addr = prefix_info
+        .filter(|info| {
+            info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+                && info.prefix_len == SLAAC_PREFIX_LEN
+                && !info.prefix.is_link_local()
+                && info.valid_lifetime.total_millis() > 0
+        })
+        .map(|info| {
+            let mut bytes = info.prefix.0;
+            bytes[8..].copy_from_slice(&interface_id(mac));
+            Ipv6Address(bytes)
+        });
+    let router = (router_lifetime.total_millis() > 0).then_some(ip_repr.src_addr);
+    Some(Advert { addr, router })
+}
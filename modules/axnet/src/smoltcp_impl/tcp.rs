@@ -11,7 +11,8 @@ use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
-use super::{ETH0, LISTEN_TABLE, SOCKET_SET, SocketSetWrapper};
+use super::listen_table::ListenTable;
+use super::{NetStack, SocketSetWrapper};
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -41,27 +42,32 @@ pub struct TcpSocket {
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    /// The network stack of the namespace the socket is created in.
+    stack: NetStack,
 }
 
 unsafe impl Sync for TcpSocket {}
 
 impl TcpSocket {
-    /// Creates a new TCP socket.
-    pub const fn new() -> Self {
+    /// Creates a new TCP socket, in the network namespace of the current task.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
         Self {
             state: AtomicU8::new(STATE_CLOSED),
             handle: UnsafeCell::new(None),
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            stack: NetStack::current(),
         }
     }
 
     /// Creates a new TCP socket that is already connected.
-    const fn new_connected(
+    fn new_connected(
         handle: SocketHandle,
         local_addr: IpEndpoint,
         peer_addr: IpEndpoint,
+        stack: NetStack,
     ) -> Self {
         Self {
             state: AtomicU8::new(STATE_CONNECTED),
@@ -69,6 +75,7 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            stack,
         }
     }
 
@@ -122,13 +129,15 @@ impl TcpSocket {
         self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
             // SAFETY: no other threads can read or write these fields.
             let handle = unsafe { self.handle.get().read() }
-                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
+                .unwrap_or_else(|| self.stack.sockets().add(SocketSetWrapper::new_tcp_socket()));
 
             // TODO: check remote addr unreachable
             let remote_endpoint = from_core_sockaddr(remote_addr);
             let bound_endpoint = self.bound_endpoint()?;
-            let iface = &ETH0.iface;
-            let (local_endpoint, remote_endpoint) = SOCKET_SET
+            let iface = self.stack.iface();
+            let (local_endpoint, remote_endpoint) = self
+                .stack
+                .sockets()
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                     socket
                         .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
@@ -183,7 +192,7 @@ impl TcpSocket {
         self.update_state(STATE_CLOSED, STATE_CLOSED, || {
             // TODO: check addr is available
             if local_addr.port() == 0 {
-                local_addr.set_port(get_ephemeral_port()?);
+                local_addr.set_port(get_ephemeral_port(self.stack.listen_table())?);
             }
             // SAFETY: no other threads can read or write `self.local_addr` as we
             // have changed the state to `BUSY`.
@@ -209,7 +218,7 @@ impl TcpSocket {
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint)?;
+            self.stack.listen_table().listen(bound_endpoint)?;
             debug!("TCP socket listening on {}", bound_endpoint);
             Ok(())
         })
@@ -230,9 +239,17 @@ impl TcpSocket {
         // SAFETY: `self.local_addr` should be initialized after `bind()`.
         let local_port = unsafe { self.local_addr.get().read().port };
         self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+            let (handle, (local_addr, peer_addr)) = self
+                .stack
+                .listen_table()
+                .accept(local_port, self.stack.sockets())?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            Ok(TcpSocket::new_connected(
+                handle,
+                local_addr,
+                peer_addr,
+                self.stack.clone(),
+            ))
         })
     }
 
@@ -243,12 +260,14 @@ impl TcpSocket {
             // SAFETY: `self.handle` should be initialized in a connected socket, and
             // no other threads can read or write it.
             let handle = unsafe { self.handle.get().read().unwrap() };
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                debug!("TCP socket {}: shutting down", handle);
-                socket.close();
-            });
+            self.stack
+                .sockets()
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    debug!("TCP socket {}: shutting down", handle);
+                    socket.close();
+                });
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            SOCKET_SET.poll_interfaces();
+            self.stack.poll_interfaces();
             Ok(())
         })
         .unwrap_or(Ok(()))?;
@@ -259,8 +278,10 @@ impl TcpSocket {
             // and no other threads can read or write it.
             let local_port = unsafe { self.local_addr.get().read().port };
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            LISTEN_TABLE.unlisten(local_port);
-            SOCKET_SET.poll_interfaces();
+            self.stack
+                .listen_table()
+                .unlisten(local_port, self.stack.sockets());
+            self.stack.poll_interfaces();
             Ok(())
         })
         .unwrap_or(Ok(()))?;
@@ -280,25 +301,27 @@ impl TcpSocket {
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
         self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+            self.stack
+                .sockets()
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    if !socket.is_active() {
+                        // not open
+                        ax_err!(ConnectionRefused, "socket recv() failed")
+                    } else if !socket.may_recv() {
+                        // connection closed
+                        Ok(0)
+                    } else if socket.recv_queue() > 0 {
+                        // data available
+                        // TODO: use socket.recv(|buf| {...})
+                        let len = socket
+                            .recv_slice(buf)
+                            .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                        Ok(len)
+                    } else {
+                        // no more data
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
     }
 
@@ -313,22 +336,24 @@ impl TcpSocket {
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
         self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
-                    // closed by remote
-                    ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
+            self.stack
+                .sockets()
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    if !socket.is_active() || !socket.may_send() {
+                        // closed by remote
+                        ax_err!(ConnectionReset, "socket send() failed")
+                    } else if socket.can_send() {
+                        // connected, and the tx buffer is not full
+                        // TODO: use socket.send(|buf| {...})
+                        let len = socket
+                            .send_slice(buf)
+                            .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                        Ok(len)
+                    } else {
+                        // tx buffer is full
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
     }
 
@@ -408,7 +433,7 @@ impl TcpSocket {
         let port = if local_addr.port != 0 {
             local_addr.port
         } else {
-            get_ephemeral_port()?
+            get_ephemeral_port(self.stack.listen_table())?
         };
         assert_ne!(port, 0);
         let addr = if !is_unspecified(local_addr.addr) {
@@ -422,8 +447,10 @@ impl TcpSocket {
     fn poll_connect(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized above.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        let writable =
-            SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| match socket.state() {
+        let writable = self
+            .stack
+            .sockets()
+            .with_socket::<tcp::Socket, _, _>(handle, |socket| match socket.state() {
                 State::SynSent => false, // wait for connection
                 State::Established => {
                     self.set_state(STATE_CONNECTED); // connected
@@ -452,19 +479,24 @@ impl TcpSocket {
     fn poll_stream(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-            Ok(PollState {
-                readable: !socket.may_recv() || socket.can_recv(),
-                writable: !socket.may_send() || socket.can_send(),
+        self.stack
+            .sockets()
+            .with_socket::<tcp::Socket, _, _>(handle, |socket| {
+                Ok(PollState {
+                    readable: !socket.may_recv() || socket.can_recv(),
+                    writable: !socket.may_send() || socket.can_send(),
+                })
             })
-        })
     }
 
     fn poll_listener(&self) -> AxResult<PollState> {
         // SAFETY: `self.local_addr` should be initialized in a listening socket.
         let local_addr = unsafe { self.local_addr.get().read() };
         Ok(PollState {
-            readable: LISTEN_TABLE.can_accept(local_addr.port)?,
+            readable: self
+                .stack
+                .listen_table()
+                .can_accept(local_addr.port, self.stack.sockets())?,
             writable: false,
         })
     }
@@ -482,7 +514,7 @@ impl TcpSocket {
             f()
         } else {
             loop {
-                SOCKET_SET.poll_interfaces();
+                self.stack.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
                     Err(AxError::WouldBlock) => axtask::yield_now(),
@@ -498,12 +530,12 @@ impl Drop for TcpSocket {
         self.shutdown().ok();
         // Safe because we have mut reference to `self`.
         if let Some(handle) = unsafe { self.handle.get().read() } {
-            SOCKET_SET.remove(handle);
+            self.stack.sockets().remove(handle);
         }
     }
 }
 
-fn get_ephemeral_port() -> AxResult<u16> {
+fn get_ephemeral_port(listen_table: &ListenTable) -> AxResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
     static CURR: Mutex<u16> = Mutex::new(PORT_START);
@@ -518,7 +550,7 @@ fn get_ephemeral_port() -> AxResult<u16> {
         } else {
             *curr += 1;
         }
-        if LISTEN_TABLE.can_listen(port) {
+        if listen_table.can_listen(port) {
             return Ok(port);
         }
         tries += 1;
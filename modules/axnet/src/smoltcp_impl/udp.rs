@@ -11,7 +11,7 @@ use smoltcp::socket::udp::{self, BindError, SendError};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
-use super::{SOCKET_SET, SocketSetWrapper};
+use super::{NetStack, SocketSetWrapper};
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
@@ -19,19 +19,23 @@ pub struct UdpSocket {
     local_addr: RwLock<Option<IpEndpoint>>,
     peer_addr: RwLock<Option<IpEndpoint>>,
     nonblock: AtomicBool,
+    /// The network stack of the namespace the socket is created in.
+    stack: NetStack,
 }
 
 impl UdpSocket {
-    /// Creates a new UDP socket.
+    /// Creates a new UDP socket, in the network namespace of the current task.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let socket = SocketSetWrapper::new_udp_socket();
-        let handle = SOCKET_SET.add(socket);
+        let stack = NetStack::current();
+        let handle = stack.sockets().add(socket);
         Self {
             handle,
             local_addr: RwLock::new(None),
             peer_addr: RwLock::new(None),
             nonblock: AtomicBool::new(false),
+            stack,
         }
     }
 
@@ -88,12 +92,14 @@ impl UdpSocket {
             addr: (!is_unspecified(local_endpoint.addr)).then_some(local_endpoint.addr),
             port: local_endpoint.port,
         };
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            socket.bind(endpoint).or_else(|e| match e {
-                BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
-                BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
-            })
-        })?;
+        self.stack
+            .sockets()
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                socket.bind(endpoint).or_else(|e| match e {
+                    BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
+                    BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
+                })
+            })?;
 
         *self_local_addr = Some(local_endpoint);
         debug!("UDP socket {}: bound on {}", self.handle, endpoint);
@@ -172,11 +178,13 @@ impl UdpSocket {
 
     /// Close the socket.
     pub fn shutdown(&self) -> AxResult {
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            debug!("UDP socket {}: shutting down", self.handle);
-            socket.close();
-        });
-        SOCKET_SET.poll_interfaces();
+        self.stack
+            .sockets()
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                debug!("UDP socket {}: shutting down", self.handle);
+                socket.close();
+            });
+        self.stack.poll_interfaces();
         Ok(())
     }
 
@@ -188,12 +196,14 @@ impl UdpSocket {
                 writable: false,
             });
         }
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            Ok(PollState {
-                readable: socket.can_recv(),
-                writable: socket.can_send(),
+        self.stack
+            .sockets()
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                Ok(PollState {
+                    readable: socket.can_recv(),
+                    writable: socket.can_send(),
+                })
             })
-        })
     }
 }
 
@@ -212,22 +222,24 @@ impl UdpSocket {
         }
 
         self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_send() {
-                    socket
-                        .send_slice(buf, remote_endpoint)
-                        .map_err(|e| match e {
-                            SendError::BufferFull => AxError::WouldBlock,
-                            SendError::Unaddressable => {
-                                ax_err_type!(ConnectionRefused, "socket send() failed")
-                            }
-                        })?;
-                    Ok(buf.len())
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
+            self.stack
+                .sockets()
+                .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                    if socket.can_send() {
+                        socket
+                            .send_slice(buf, remote_endpoint)
+                            .map_err(|e| match e {
+                                SendError::BufferFull => AxError::WouldBlock,
+                                SendError::Unaddressable => {
+                                    ax_err_type!(ConnectionRefused, "socket send() failed")
+                                }
+                            })?;
+                        Ok(buf.len())
+                    } else {
+                        // tx buffer is full
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
     }
 
@@ -240,15 +252,17 @@ impl UdpSocket {
         }
 
         self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_recv() {
-                    // data available
-                    op(socket)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+            self.stack
+                .sockets()
+                .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                    if socket.can_recv() {
+                        // data available
+                        op(socket)
+                    } else {
+                        // no more data
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
     }
 
@@ -260,7 +274,7 @@ impl UdpSocket {
             f()
         } else {
             loop {
-                SOCKET_SET.poll_interfaces();
+                self.stack.poll_interfaces();
                 match f() {
                     Ok(t) => return Ok(t),
                     Err(AxError::WouldBlock) => axtask::yield_now(),
@@ -274,7 +288,7 @@ impl UdpSocket {
 impl Drop for UdpSocket {
     fn drop(&mut self) {
         self.shutdown().ok();
-        SOCKET_SET.remove(self.handle);
+        self.stack.sockets().remove(self.handle);
     }
 }
 
@@ -0,0 +1,139 @@
+//! A minimal parser of ELF64 executables, enough to load them.
+
+use axerrno::{AxResult, ax_err};
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+#[cfg(target_arch = "x86_64")]
+const EM_CURRENT: u16 = 62;
+#[cfg(target_arch = "aarch64")]
+const EM_CURRENT: u16 = 183;
+#[cfg(target_arch = "riscv64")]
+const EM_CURRENT: u16 = 243;
+#[cfg(target_arch = "loongarch64")]
+const EM_CURRENT: u16 = 258;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+/// A loadable segment.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    /// The virtual address of the segment.
+    pub vaddr: usize,
+    /// The size of the segment in memory. The part beyond the file data is
+    /// zeroed.
+    pub mem_size: usize,
+    /// The offset of the segment data in the file.
+    pub offset: usize,
+    /// The size of the segment data in the file.
+    pub file_size: usize,
+    /// Whether the segment is readable.
+    pub readable: bool,
+    /// Whether the segment is writable.
+    pub writable: bool,
+    /// Whether the segment is executable.
+    pub executable: bool,
+}
+
+/// A parsed ELF executable.
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    /// Whether it is position independent, i.e., it must be loaded at a base
+    /// address.
+    pub is_pie: bool,
+    /// The entry point, relative to the load base for PIE executables.
+    pub entry: usize,
+    phoff: usize,
+    phnum: usize,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses the ELF header, and checks the executable is for the current
+    /// architecture.
+    pub fn parse(data: &'a [u8]) -> AxResult<Self> {
+        if data.len() < EHDR_SIZE || data[..4] != ELF_MAGIC {
+            return ax_err!(InvalidData, "not an ELF file");
+        }
+        if data[4] != ELFCLASS64 || data[5] != ELFDATA2LSB {
+            return ax_err!(Unsupported, "not a little-endian ELF64 file");
+        }
+        let is_pie = match read_u16(data, 16) {
+            ET_EXEC => false,
+            ET_DYN => true,
+            _ => return ax_err!(InvalidData, "not an executable"),
+        };
+        if read_u16(data, 18) != EM_CURRENT {
+            return ax_err!(Unsupported, "executable for another architecture");
+        }
+        let phoff = read_u64(data, 32);
+        let phnum = read_u16(data, 56) as usize;
+        if read_u16(data, 54) as usize != PHDR_SIZE
+            || phoff
+                .checked_add(phnum * PHDR_SIZE)
+                .is_none_or(|end| end > data.len())
+        {
+            return ax_err!(InvalidData, "bad program headers");
+        }
+        Ok(Self {
+            data,
+            is_pie,
+            entry: read_u64(data, 24),
+            phoff,
+            phnum,
+        })
+    }
+
+    /// Returns the loadable segments, in the order of the program headers.
+    pub fn segments(&self) -> impl Iterator<Item = AxResult<Segment>> + '_ {
+        (0..self.phnum)
+            .map(|i| self.phoff + i * PHDR_SIZE)
+            .filter(|&ph| read_u32(self.data, ph) == PT_LOAD)
+            .map(|ph| {
+                let flags = read_u32(self.data, ph + 4);
+                let seg = Segment {
+                    offset: read_u64(self.data, ph + 8),
+                    vaddr: read_u64(self.data, ph + 16),
+                    file_size: read_u64(self.data, ph + 32),
+                    mem_size: read_u64(self.data, ph + 40),
+                    readable: flags & PF_R != 0,
+                    writable: flags & PF_W != 0,
+                    executable: flags & PF_X != 0,
+                };
+                let file_end = seg.offset.checked_add(seg.file_size);
+                if seg.file_size > seg.mem_size || file_end.is_none_or(|end| end > self.data.len())
+                {
+                    return ax_err!(InvalidData, "bad segment");
+                }
+                Ok(seg)
+            })
+    }
+
+    /// Returns the file data of a segment.
+    pub fn segment_data(&self, seg: &Segment) -> &'a [u8] {
+        &self.data[seg.offset..seg.offset + seg.file_size]
+    }
+}
@@ -0,0 +1,111 @@
+//! File descriptor tables of processes.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::{AxError, AxResult};
+
+/// An open file, or any object a file descriptor can refer to.
+pub trait FileLike: Send + Sync {
+    /// Reads data into `buf`, returns the number of bytes read.
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize>;
+
+    /// Writes data from `buf`, returns the number of bytes written.
+    fn write(&self, buf: &[u8]) -> AxResult<usize>;
+}
+
+/// The console, as the standard input, output and error of processes.
+pub struct Stdio;
+
+impl FileLike for Stdio {
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        Ok(axhal::console::read_bytes(buf))
+    }
+
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+}
+
+/// The file descriptor table of a process.
+///
+/// Forked processes get a copy of the table, with the files shared.
+#[derive(Clone)]
+pub struct FdTable {
+    files: Vec<Option<Arc<dyn FileLike>>>,
+    /// The number of descriptors that can be opened, see
+    /// [`Resource::OpenFiles`](crate::Resource::OpenFiles).
+    max_files: usize,
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            max_files: Self::MAX_FILES,
+        }
+    }
+}
+
+impl FdTable {
+    /// The maximum number of open files of a process.
+    pub const MAX_FILES: usize = 1024;
+
+    /// Creates a table with the standard input, output and error open on the
+    /// console.
+    pub fn with_stdio() -> Self {
+        let stdio: Arc<dyn FileLike> = Arc::new(Stdio);
+        Self {
+            files: alloc::vec![Some(stdio.clone()), Some(stdio.clone()), Some(stdio)],
+            ..Self::default()
+        }
+    }
+
+    /// Returns the file of a descriptor.
+    pub fn get(&self, fd: usize) -> AxResult<Arc<dyn FileLike>> {
+        self.files
+            .get(fd)
+            .and_then(|f| f.clone())
+            .ok_or(AxError::BadFileDescriptor)
+    }
+
+    /// Adds a file with the lowest free descriptor, returns the descriptor.
+    ///
+    /// Returns [`AxError::StorageFull`] if all descriptors under the limit of
+    /// the process are in use.
+    pub fn add(&mut self, file: Arc<dyn FileLike>) -> AxResult<usize> {
+        let files = self.files.iter().take(self.max_files);
+        if let Some(fd) = files.position(Option::is_none) {
+            self.files[fd] = Some(file);
+            return Ok(fd);
+        }
+        if self.files.len() >= self.max_files {
+            return Err(AxError::StorageFull);
+        }
+        self.files.push(Some(file));
+        Ok(self.files.len() - 1)
+    }
+
+    /// Closes a descriptor.
+    pub fn close(&mut self, fd: usize) -> AxResult {
+        match self.files.get_mut(fd) {
+            Some(file @ Some(_)) => {
+                *file = None;
+                Ok(())
+            }
+            _ => Err(AxError::BadFileDescriptor),
+        }
+    }
+
+    /// Sets the number of descriptors that can be opened. Descriptors already
+    /// open above it are kept open.
+    pub(crate) fn set_max_files(&mut self, max_files: usize) {
+        self.max_files = max_files;
+    }
+
+    /// Closes all descriptors.
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
@@ -0,0 +1,86 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) user process management.
+//!
+//! A process runs an ELF executable in user mode, in its own address space
+//! and with its own file descriptor table, so several user programs can run
+//! at the same time without seeing each other. It provides:
+//!
+//! - [`spawn`]: Starts a new program, from an ELF image in memory (or from a
+//!   file with the `fs` feature, see `spawn_path`).
+//! - [`fork`]: Duplicates the current process.
+//! - [`wait`]: Waits for a child to exit and reaps it, like `waitpid`.
+//! - [`exit`]: Exits the current process, freeing its resources.
+//!
+//! The usage of memory, child processes, file descriptors and CPU time by a
+//! process can be limited (see [`Resource`] and [`Process::set_limit`]), so
+//! a misbehaving program cannot exhaust the resources of the kernel.
+//!
+//! Page faults of processes are resolved in their address spaces, and
+//! processes making invalid accesses are terminated rather than panicking
+//! the kernel.
+//!
+//! When the kernel heap is exhausted, the process with the largest memory
+//! footprint, adjusted by [`Process::set_oom_score_adj`], is terminated with
+//! [`OOM_EXIT_CODE`] and its memory freed, rather than panicking the kernel.
+//!
+//! The system calls of a process can be traced, like with `strace`, see
+//! [`Process::set_strace`], by a system call layer calling
+//! [`syscall_enter`]. There is none in ArceOS itself: `axcpu` dispatches the
+//! system calls to the handler registered by the application.
+//!
+//! Each process runs in a single task, whose task extended data
+//! ([`ProcessTaskExt`]) refers to the process, so applications using this
+//! crate cannot define their own task extended data.
+//!
+//! # Cargo Features
+//!
+//! - `fs`: Spawn programs from files on the filesystem.
+//! - `verify`: Only spawn programs from files with valid signatures, made by
+//!   the trusted key (see [`axverify`]), in files with `.sig` appended to
+//!   their paths.
+//! - `swap`: Swap out pages of the processes under memory pressure, to the
+//!   swap device set by `axmm::swap::swap_on`, rather than failing their
+//!   allocations. The clean caches registered with
+//!   `axmm::swap::register_clean_cache` are evicted first.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod elf;
+mod fd_table;
+mod limits;
+mod loader;
+mod oom;
+mod process;
+mod strace;
+#[cfg(feature = "swap")]
+mod swap;
+
+pub use self::fd_table::{FdTable, FileLike, Stdio};
+pub use self::limits::{CPU_LIMIT_EXIT_CODE, Resource, ResourceLimits, UNLIMITED};
+pub use self::oom::{OOM_EXIT_CODE, OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN};
+#[cfg(feature = "fs")]
+pub use self::process::spawn_path;
+pub use self::process::{
+    Pid, Process, ProcessTaskExt, check_cpu_time, current, exit, fork, processes, spawn, wait,
+};
+pub use self::strace::{
+    STRACE_BUF_LEN, SyscallRecord, SyscallTrace, strace_records, syscall_enter,
+};
+
+/// The base address of user address spaces.
+pub const USER_SPACE_BASE: usize = 0x1000;
+
+/// The size of user address spaces.
+pub const USER_SPACE_SIZE: usize = 0x3f_ffff_f000;
+
+/// The top of the user stack.
+pub const USER_STACK_TOP: usize = USER_SPACE_BASE + USER_SPACE_SIZE;
+
+/// The size of the user stack.
+pub const USER_STACK_SIZE: usize = 0x10000;
+
+/// The load address of position independent executables.
+pub const PIE_BASE: usize = 0x40_0000;
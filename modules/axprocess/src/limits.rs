@@ -0,0 +1,70 @@
+//! Resource limits of processes.
+
+use crate::fd_table::FdTable;
+
+/// The value of a resource limit meaning no limit.
+pub const UNLIMITED: u64 = u64::MAX;
+
+/// The exit code of processes terminated for exceeding their CPU time limit,
+/// as if killed by `SIGXCPU` (128 + 24).
+pub const CPU_LIMIT_EXIT_CODE: i32 = 152;
+
+/// A resource whose usage by a process can be limited, like the `RLIMIT_*`
+/// resources of `setrlimit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// The total size of the user memory mapped by the process, in bytes.
+    ///
+    /// Mappings exceeding it fail with [`AxError::NoMemory`].
+    ///
+    /// [`AxError::NoMemory`]: axerrno::AxError::NoMemory
+    Memory = 0,
+    /// The number of child processes not reaped yet.
+    ///
+    /// Spawning or forking more fails with [`AxError::WouldBlock`], like
+    /// `EAGAIN` of `fork`.
+    ///
+    /// [`AxError::WouldBlock`]: axerrno::AxError::WouldBlock
+    Processes = 1,
+    /// The number of file descriptors, i.e., one more than the largest
+    /// descriptor that can be opened. It cannot exceed
+    /// [`FdTable::MAX_FILES`].
+    ///
+    /// Opening more fails with [`AxError::StorageFull`].
+    ///
+    /// [`AxError::StorageFull`]: axerrno::AxError::StorageFull
+    OpenFiles = 2,
+    /// The CPU time of the process, in milliseconds.
+    ///
+    /// The process is terminated with [`CPU_LIMIT_EXIT_CODE`] once it exceeds
+    /// it, on the next timer tick or page fault, see [`check_cpu_time`].
+    ///
+    /// [`check_cpu_time`]: crate::check_cpu_time
+    CpuTime = 3,
+}
+
+/// The resource limits of a process, inherited by its children.
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceLimits([u64; 4]);
+
+impl ResourceLimits {
+    /// Returns the limit of a resource, or [`UNLIMITED`].
+    pub fn get(&self, res: Resource) -> u64 {
+        self.0[res as usize]
+    }
+
+    /// Sets the limit of a resource.
+    pub(crate) fn set(&mut self, res: Resource, limit: u64) {
+        self.0[res as usize] = limit;
+    }
+}
+
+impl Default for ResourceLimits {
+    /// No limits, except for the number of files limited to
+    /// [`FdTable::MAX_FILES`].
+    fn default() -> Self {
+        let mut limits = Self([UNLIMITED; 4]);
+        limits.set(Resource::OpenFiles, FdTable::MAX_FILES as u64);
+        limits
+    }
+}
@@ -0,0 +1,99 @@
+//! Loading of ELF executables into user address spaces.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxResult, ax_err};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+
+use crate::elf::{ElfFile, Segment};
+use crate::{PIE_BASE, USER_STACK_SIZE, USER_STACK_TOP};
+
+fn segment_flags(seg: &Segment) -> MappingFlags {
+    let mut flags = MappingFlags::USER;
+    if seg.readable {
+        flags |= MappingFlags::READ;
+    }
+    if seg.writable {
+        flags |= MappingFlags::WRITE;
+    }
+    if seg.executable {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
+
+/// Maps the loadable segments of an ELF executable, returns its entry point.
+pub(crate) fn load_elf(aspace: &mut AddrSpace, data: &[u8]) -> AxResult<VirtAddr> {
+    let elf = ElfFile::parse(data)?;
+    let base = if elf.is_pie { PIE_BASE } else { 0 };
+    // The end of the pages mapped so far, and the flags of the last one.
+    let mut mapped_end = VirtAddr::from(0);
+    let mut last_flags = MappingFlags::empty();
+    for seg in elf.segments() {
+        let seg = seg?;
+        if seg.mem_size == 0 {
+            continue;
+        }
+        let flags = segment_flags(&seg);
+        let vaddr = VirtAddr::from(base + seg.vaddr);
+        let start = vaddr.align_down_4k();
+        let end = (vaddr + seg.mem_size).align_up_4k();
+        if start + PAGE_SIZE_4K < mapped_end {
+            return ax_err!(InvalidData, "overlapping segments");
+        }
+        if start < mapped_end {
+            // The first page is shared with the previous segment, so it gets
+            // the permissions of both.
+            aspace.protect(start, PAGE_SIZE_4K, last_flags | flags)?;
+        }
+        let map_start = start.max(mapped_end);
+        if map_start < end {
+            aspace.map_alloc(map_start, end - map_start, flags, true)?;
+            mapped_end = end;
+        }
+        last_flags = flags;
+        // The rest of the segment is already zeroed.
+        aspace.write(vaddr, elf.segment_data(&seg))?;
+    }
+    Ok(VirtAddr::from(base + elf.entry))
+}
+
+/// Maps the user stack, and pushes the arguments to it as the System V ABI
+/// specifies (`argc`, `argv`, an empty `envp` and an empty auxiliary vector).
+///
+/// Returns the initial stack pointer.
+pub(crate) fn init_user_stack(aspace: &mut AddrSpace, args: &[&str]) -> AxResult<VirtAddr> {
+    let top = VirtAddr::from(USER_STACK_TOP);
+    aspace.map_alloc(
+        top - USER_STACK_SIZE,
+        USER_STACK_SIZE,
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        true,
+    )?;
+
+    let strings_size: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    let strings_start = top - strings_size;
+    // argc, argv[], NULL, envp NULL, AT_NULL
+    let num_words = 1 + args.len() + 1 + 1 + 2;
+    let sp = (strings_start - num_words * 8).align_down(16usize);
+    if top - sp > USER_STACK_SIZE / 2 {
+        return ax_err!(InvalidInput, "arguments too long");
+    }
+
+    let mut image = Vec::with_capacity(top - sp);
+    image.extend_from_slice(&args.len().to_ne_bytes());
+    let mut str_addr = strings_start;
+    for arg in args {
+        image.extend_from_slice(&str_addr.as_usize().to_ne_bytes());
+        str_addr += arg.len() + 1;
+    }
+    image.resize(top - sp - strings_size, 0);
+    for arg in args {
+        image.extend_from_slice(arg.as_bytes());
+        image.push(0);
+    }
+    aspace.write(sp, &image)?;
+    Ok(sp)
+}
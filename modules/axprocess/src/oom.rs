@@ -0,0 +1,133 @@
+//! Terminating processes when the kernel runs out of memory.
+
+use alloc::sync::Weak;
+
+use axalloc::global_allocator;
+
+use crate::process::{PROCESSES, Process, current};
+
+/// The exit code of processes terminated to free memory, as if killed by
+/// `SIGKILL` (128 + 9).
+pub const OOM_EXIT_CODE: i32 = 137;
+
+/// The lowest adjustment of the OOM score, for processes never terminated to
+/// free memory.
+pub const OOM_SCORE_ADJ_MIN: i32 = -1000;
+
+/// The highest adjustment of the OOM score.
+pub const OOM_SCORE_ADJ_MAX: i32 = 1000;
+
+/// Returns the OOM score of a process, the higher the sooner it is
+/// terminated, or `None` if it cannot be.
+///
+/// It is the number of pages of the process, in memory or swapped out, plus
+/// its adjustment in thousandths of all the pages.
+fn oom_score(process: &Process, total_pages: usize) -> Option<usize> {
+    let adj = process.oom_score_adj();
+    if adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let aspace = process.aspace().try_lock()?;
+    #[cfg(feature = "swap")]
+    let pages = aspace.resident_pages() + aspace.swapped_pages();
+    #[cfg(not(feature = "swap"))]
+    let pages = aspace.resident_pages();
+    let score = pages as isize + adj as isize * total_pages as isize / 1000;
+    Some(score.max(1) as usize)
+}
+
+/// Returns the candidate with the highest OOM score, and its score.
+///
+/// The candidates whose address spaces are in use on another CPU are
+/// skipped: their memory cannot be freed while stale TLB entries of that CPU
+/// may still map it.
+fn select_victim<P>(
+    candidates: impl Iterator<Item = P>,
+    score: impl Fn(&P) -> Option<usize>,
+    in_use_elsewhere: impl Fn(&P) -> bool,
+) -> Option<(usize, P)> {
+    candidates
+        .filter(|p| !in_use_elsewhere(p))
+        .filter_map(|p| Some((score(&p)?, p)))
+        .max_by_key(|(score, _)| *score)
+}
+
+/// Terminates the process with the highest OOM score, freeing its user
+/// memory at once. Returns whether a process is terminated.
+///
+/// The current process is spared, as the kernel may be using its memory on
+/// its behalf, and so are the processes running on other CPUs, see
+/// [`select_victim`]. Nothing is allocated, so that it can be called on
+/// allocation failures.
+fn kill_largest(size: usize) -> bool {
+    // It may be held by the allocation that failed.
+    let Some(processes) = PROCESSES.try_lock() else {
+        return false;
+    };
+    let allocator = global_allocator();
+    let total_pages = allocator.used_pages() + allocator.available_pages();
+    let curr_pid = current().map(|curr| curr.pid());
+    let candidates = processes
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|p| Some(p.pid()) != curr_pid && p.exit_code().is_none() && !p.oom_killed());
+    let victim = select_victim(
+        candidates,
+        |p| oom_score(p, total_pages),
+        |p| p.aspace_in_use_elsewhere(),
+    );
+    drop(processes);
+
+    let Some((score, victim)) = victim else {
+        warn!(
+            "out of memory allocating {} bytes, no process to kill",
+            size
+        );
+        return false;
+    };
+    if !victim.oom_kill() {
+        return false;
+    }
+    error!(
+        "process {} ({}) killed: out of memory (score {})",
+        victim.pid(),
+        victim.name(),
+        score
+    );
+    true
+}
+
+fn init() {
+    global_allocator().set_oom_killer(kill_largest);
+}
+
+axruntime::register_subsystem!(OOM_KILLER, "oom-killer", [], init);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Processes as `(pid, score, running on another CPU)`.
+    fn select(candidates: &[(u32, Option<usize>, bool)]) -> Option<(usize, u32)> {
+        select_victim(candidates.iter(), |c| c.1, |c| c.2).map(|(score, c)| (score, c.0))
+    }
+
+    #[test]
+    fn test_select_largest() {
+        let candidates = [(1, Some(10), false), (2, Some(30), false), (3, None, false)];
+        assert_eq!(select(&candidates), Some((30, 2)));
+        assert_eq!(select(&[]), None);
+    }
+
+    #[test]
+    fn test_skip_running_elsewhere() {
+        // The largest process is running on another CPU while memory runs
+        // out: the next one is terminated instead.
+        let mut candidates = [(1, Some(10), false), (2, Some(30), true)];
+        assert_eq!(select(&candidates), Some((10, 1)));
+
+        // Nothing is terminated if they all are running.
+        candidates[0].2 = true;
+        assert_eq!(select(&candidates), None);
+    }
+}
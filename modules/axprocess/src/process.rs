@@ -0,0 +1,547 @@
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult, ax_err};
+use axhal::context::{TrapFrame, UspaceContext};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use axruntime::uspace::{FAULT_EXIT_CODE, USER_EXCEPTION_HANDLERS, USER_FAULT_HANDLERS, UserFault};
+use axsync::Mutex;
+use axtask::{TaskExtRef, TaskInner, TaskState, WaitQueue, WeakAxTaskRef};
+use memory_addr::{VirtAddr, va};
+
+use crate::fd_table::FdTable;
+use crate::limits::{CPU_LIMIT_EXIT_CODE, Resource, ResourceLimits, UNLIMITED};
+use crate::loader::{init_user_stack, load_elf};
+use crate::oom::{OOM_EXIT_CODE, OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN};
+use crate::{USER_SPACE_BASE, USER_SPACE_SIZE};
+
+/// Process ID.
+pub type Pid = u64;
+
+/// A user process, running in its own address space with its own file
+/// descriptor table.
+///
+/// Each process runs in a single task.
+pub struct Process {
+    pid: Pid,
+    name: String,
+    parent: Mutex<Weak<Process>>,
+    children: Mutex<Vec<Arc<Process>>>,
+    aspace: Mutex<AddrSpace>,
+    /// The task of the process, once started.
+    task: Mutex<WeakAxTaskRef>,
+    fd_table: Mutex<FdTable>,
+    limits: Mutex<ResourceLimits>,
+    /// The adjustment of the OOM score, see [`Process::set_oom_score_adj`].
+    oom_score_adj: AtomicI32,
+    /// Whether the process is terminated to free memory.
+    oom_killed: AtomicBool,
+    /// Whether the system calls of the process are traced.
+    strace: AtomicBool,
+    /// The exit code, set when the process exits.
+    exit_code: Mutex<Option<i32>>,
+    /// Woken when the process exits.
+    exit_wq: WaitQueue,
+    /// Woken when a child exits.
+    child_exit_wq: WaitQueue,
+}
+
+/// The processes which have not been dropped, in the order of their creation.
+pub(crate) static PROCESSES: Mutex<Vec<Weak<Process>>> = Mutex::new(Vec::new());
+
+/// Returns the processes which have not been dropped yet, in the order of
+/// their creation, including the ones which exited but are not reaped.
+pub fn processes() -> Vec<Arc<Process>> {
+    PROCESSES.lock().iter().filter_map(Weak::upgrade).collect()
+}
+
+/// The task extended data of process tasks.
+pub struct ProcessTaskExt {
+    process: Arc<Process>,
+    /// Whether the task is handling a trap of the process in the kernel,
+    /// where it may hold locks, so it is not terminated there when it exceeds
+    /// its CPU time limit, see [`on_cpu_limit`].
+    in_kernel: AtomicBool,
+}
+
+axtask::def_task_ext!(ProcessTaskExt);
+
+impl Process {
+    fn new(
+        name: &str,
+        aspace: AddrSpace,
+        mut fd_table: FdTable,
+        limits: ResourceLimits,
+    ) -> Arc<Self> {
+        static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+
+        fd_table.set_max_files(limits.get(Resource::OpenFiles) as usize);
+        let parent = current();
+        let oom_score_adj = parent.as_ref().map_or(0, |parent| parent.oom_score_adj());
+        let strace = parent.as_ref().is_some_and(|parent| parent.strace());
+        let process = Arc::new(Self {
+            pid: NEXT_PID.fetch_add(1, Ordering::Relaxed),
+            name: name.into(),
+            parent: Mutex::new(Weak::new()),
+            children: Mutex::new(Vec::new()),
+            aspace: Mutex::new(aspace),
+            task: Mutex::new(WeakAxTaskRef::new()),
+            fd_table: Mutex::new(fd_table),
+            limits: Mutex::new(limits),
+            oom_score_adj: AtomicI32::new(oom_score_adj),
+            oom_killed: AtomicBool::new(false),
+            strace: AtomicBool::new(strace),
+            exit_code: Mutex::new(None),
+            exit_wq: WaitQueue::new(),
+            child_exit_wq: WaitQueue::new(),
+        });
+        if let Some(parent) = parent {
+            *process.parent.lock() = Arc::downgrade(&parent);
+            parent.children.lock().push(process.clone());
+        }
+        let mut processes = PROCESSES.lock();
+        processes.retain(|process| process.strong_count() > 0);
+        processes.push(Arc::downgrade(&process));
+        drop(processes);
+        process
+    }
+
+    /// Returns the process ID.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Returns the process name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the parent process, or `None` if it was not spawned by a
+    /// process, or its parent has exited.
+    pub fn parent(&self) -> Option<Arc<Process>> {
+        self.parent.lock().upgrade()
+    }
+
+    /// Returns the address space of the process.
+    pub fn aspace(&self) -> &Mutex<AddrSpace> {
+        &self.aspace
+    }
+
+    /// Whether the address space of the process may be in use on another
+    /// CPU, i.e., its task is running there, or it is not started or has
+    /// exited.
+    pub(crate) fn aspace_in_use_elsewhere(&self) -> bool {
+        match self.task.lock().upgrade() {
+            Some(task) => task.state() == TaskState::Running && axtask::current().id() != task.id(),
+            None => true,
+        }
+    }
+
+    /// Returns the file descriptor table of the process.
+    pub fn fd_table(&self) -> &Mutex<FdTable> {
+        &self.fd_table
+    }
+
+    /// Returns the resource limits of the process.
+    pub fn limits(&self) -> ResourceLimits {
+        *self.limits.lock()
+    }
+
+    /// Sets the limit of a resource, or removes it with [`UNLIMITED`].
+    ///
+    /// It applies to later allocations only, e.g., memory already mapped is
+    /// not unmapped. Children spawned afterwards inherit the new limit.
+    pub fn set_limit(&self, res: Resource, limit: u64) -> AxResult {
+        if res == Resource::OpenFiles {
+            if limit > FdTable::MAX_FILES as u64 {
+                return ax_err!(InvalidInput, "too many files");
+            }
+            self.fd_table.lock().set_max_files(limit as usize);
+        }
+        self.limits.lock().set(res, limit);
+        if res == Resource::CpuTime {
+            if let Some(task) = self.task.lock().upgrade() {
+                task.set_cpu_limit(cpu_limit(&self.limits()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the adjustment of the OOM score, see
+    /// [`set_oom_score_adj`](Self::set_oom_score_adj).
+    pub fn oom_score_adj(&self) -> i32 {
+        self.oom_score_adj.load(Ordering::Relaxed)
+    }
+
+    /// Sets the adjustment of the OOM score, from [`OOM_SCORE_ADJ_MIN`] to
+    /// [`OOM_SCORE_ADJ_MAX`], like `/proc/<pid>/oom_score_adj` on Linux.
+    ///
+    /// It is added to the score in thousandths of the memory, so a process
+    /// with 500 is terminated before one using up to half of the memory
+    /// more. A process with [`OOM_SCORE_ADJ_MIN`] is never terminated.
+    /// Children spawned afterwards inherit it.
+    pub fn set_oom_score_adj(&self, adj: i32) -> AxResult {
+        if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&adj) {
+            return ax_err!(InvalidInput, "OOM score adjustment out of range");
+        }
+        self.oom_score_adj.store(adj, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns whether the process is terminated to free memory.
+    pub fn oom_killed(&self) -> bool {
+        self.oom_killed.load(Ordering::Relaxed)
+    }
+
+    /// Marks the process as terminated to free memory, and frees its user
+    /// memory. Returns `false` if its address space is held, e.g., by its own
+    /// page fault, or in use on another CPU, whose TLB may still map the
+    /// memory freed, or if it is already terminated.
+    pub(crate) fn oom_kill(&self) -> bool {
+        let Some(mut aspace) = self.aspace.try_lock() else {
+            return false;
+        };
+        if self.aspace_in_use_elsewhere() || self.oom_killed.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        aspace.clear();
+        true
+    }
+
+    /// Returns whether the system calls of the process are traced.
+    pub fn strace(&self) -> bool {
+        self.strace.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the tracing of the system calls of the process,
+    /// see [`syscall_enter`](crate::syscall_enter). Children spawned
+    /// afterwards inherit it.
+    pub fn set_strace(&self, enable: bool) {
+        self.strace.store(enable, Ordering::Relaxed);
+    }
+
+    /// Maps user memory in the address space of the process, populated on
+    /// access, within the limit of [`Resource::Memory`].
+    ///
+    /// It is the allocation point of user memory after the process starts,
+    /// e.g., for the `mmap` and `brk` system calls.
+    pub fn map_alloc(&self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        let mut aspace = self.aspace.lock();
+        check_memory(&self.limits(), aspace.mapped_size().saturating_add(size))?;
+        aspace.map_alloc(start, size, flags | MappingFlags::USER, false)
+    }
+
+    /// Returns [`AxError::WouldBlock`] if the process cannot have more
+    /// children.
+    fn check_new_child(&self) -> AxResult {
+        let max = self.limits().get(Resource::Processes);
+        if self.children.lock().len() as u64 >= max {
+            return ax_err!(WouldBlock, "too many processes");
+        }
+        Ok(())
+    }
+
+    /// Returns the exit code, or `None` if the process is still running.
+    pub fn exit_code(&self) -> Option<i32> {
+        *self.exit_code.lock()
+    }
+
+    /// Waits for the process to exit, returns its exit code.
+    ///
+    /// Unlike [`wait`], it does not reap the process, and can be called by
+    /// kernel tasks, e.g., to run a program to completion.
+    pub fn wait_exit(&self) -> i32 {
+        self.exit_wq.wait_until(|| self.exit_code().is_some());
+        self.exit_code().unwrap()
+    }
+
+    /// Creates the task of the process, which enters user space with the
+    /// given context.
+    fn start(self: &Arc<Self>, uctx: UspaceContext) {
+        let mut task = TaskInner::new(
+            move || {
+                let curr = axtask::current();
+                let kstack_top = curr.kernel_stack_top().unwrap();
+                unsafe { uctx.enter_uspace(kstack_top) }
+            },
+            self.name.clone(),
+            axconfig::TASK_STACK_SIZE,
+        );
+        task.ctx_mut()
+            .set_page_table_root(self.aspace.lock().page_table_root());
+        task.init_task_ext(ProcessTaskExt {
+            process: self.clone(),
+            in_kernel: AtomicBool::new(false),
+        });
+        task.set_cpu_limit(cpu_limit(&self.limits()));
+        *self.task.lock() = Arc::downgrade(&axtask::spawn_task(task));
+    }
+}
+
+/// Returns the process of the current task, or `None` for kernel tasks.
+pub fn current() -> Option<Arc<Process>> {
+    let curr = axtask::current();
+    if unsafe { curr.task_ext_ptr() }.is_null() {
+        return None;
+    }
+    Some(curr.task_ext().process.clone())
+}
+
+fn new_user_aspace() -> AxResult<AddrSpace> {
+    axmm::new_user_aspace(va!(USER_SPACE_BASE), USER_SPACE_SIZE)
+}
+
+/// Returns [`AxError::NoMemory`] if `size` bytes of mapped memory exceed the
+/// limit.
+fn check_memory(limits: &ResourceLimits, size: usize) -> AxResult {
+    if size as u64 > limits.get(Resource::Memory) {
+        return ax_err!(NoMemory, "memory limit exceeded");
+    }
+    Ok(())
+}
+
+/// Spawns a process running the given ELF executable, with its standard
+/// input and output on the console.
+///
+/// `args` are passed to the program as `argv`. If called by a process, the
+/// new process is its child, and inherits its resource limits.
+pub fn spawn(name: &str, elf_data: &[u8], args: &[&str]) -> AxResult<Arc<Process>> {
+    let parent = current();
+    if let Some(parent) = &parent {
+        parent.check_new_child()?;
+    }
+    let limits = parent.map_or_else(ResourceLimits::default, |p| p.limits());
+    let mut aspace = new_user_aspace()?;
+    let entry = load_elf(&mut aspace, elf_data)?;
+    let sp = init_user_stack(&mut aspace, args)?;
+    check_memory(&limits, aspace.mapped_size())?;
+    let process = Process::new(name, aspace, FdTable::with_stdio(), limits);
+    info!("spawn process {} ({}) @ {:#x}", process.pid, name, entry);
+    process.start(UspaceContext::new(entry.as_usize(), sp, 0));
+    Ok(process)
+}
+
+/// Spawns a process running the ELF executable at `path`, see [`spawn`].
+///
+/// With the `verify` feature, the executable is refused unless its signature
+/// at `path` with `.sig` appended is valid.
+#[cfg(feature = "fs")]
+pub fn spawn_path(path: &str, args: &[&str]) -> AxResult<Arc<Process>> {
+    let elf_data = axfs::api::read(path)?;
+    #[cfg(feature = "verify")]
+    {
+        let signature = axfs::api::read(&alloc::format!("{path}.sig"))?;
+        if let Err(e) = axverify::verify(&elf_data, &signature) {
+            warn!("refusing to spawn {}, bad signature: {:?}", path, e);
+            return Err(e);
+        }
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    spawn(name, &elf_data, args)
+}
+
+/// Forks the current process.
+///
+/// The child gets a copy of the address space, of the file descriptor table
+/// and of the resource limits, and resumes from the trap frame `tf` of the parent (usually that of
+/// the `fork` system call), with a return value of 0.
+///
+/// Returns the child process.
+pub fn fork(tf: &TrapFrame) -> AxResult<Arc<Process>> {
+    let Some(parent) = current() else {
+        return ax_err!(BadState, "not called by a process");
+    };
+    parent.check_new_child()?;
+    let mut aspace = new_user_aspace()?;
+    aspace.clone_areas_from(&parent.aspace.lock())?;
+    let fd_table = parent.fd_table.lock().clone();
+    let child = Process::new(&parent.name, aspace, fd_table, parent.limits());
+
+    let mut uctx = UspaceContext::from(tf);
+    uctx.set_retval(0);
+    child.start(uctx);
+    Ok(child)
+}
+
+/// Waits for a child of the current process to exit, and reaps it.
+///
+/// Waits for any child if `pid` is `None`. Returns the process ID and the
+/// exit code of the child, or `None` if `nohang` is `true` and no such child
+/// has exited yet.
+///
+/// Returns [`AxError::NotFound`] if the current process has no such child.
+pub fn wait(pid: Option<Pid>, nohang: bool) -> AxResult<Option<(Pid, i32)>> {
+    let Some(curr) = current() else {
+        return ax_err!(BadState, "not called by a process");
+    };
+    let matches = |child: &Arc<Process>| pid.is_none_or(|pid| child.pid == pid);
+    let exited = |child: &Arc<Process>| matches(child) && child.exit_code().is_some();
+    loop {
+        let mut children = curr.children.lock();
+        if !children.iter().any(matches) {
+            return Err(AxError::NotFound);
+        }
+        if let Some(idx) = children.iter().position(exited) {
+            let child = children.remove(idx);
+            return Ok(Some((child.pid, child.exit_code().unwrap())));
+        }
+        drop(children);
+        if nohang {
+            return Ok(None);
+        }
+        curr.child_exit_wq
+            .wait_until(|| curr.children.lock().iter().any(exited));
+    }
+}
+
+/// Exits the current process with the given exit code.
+///
+/// All its file descriptors are closed and its memory is freed. Its children
+/// are orphaned, and reaped by nobody.
+///
+/// # Panics
+///
+/// Panics if the current task is not a process.
+pub fn exit(exit_code: i32) -> ! {
+    let curr = current().expect("not called by a process");
+    debug!("process {} exited with {}", curr.pid, exit_code);
+    curr.fd_table.lock().clear();
+    curr.aspace.lock().clear();
+    for child in curr.children.lock().drain(..) {
+        *child.parent.lock() = Weak::new();
+    }
+    *curr.exit_code.lock() = Some(exit_code);
+    curr.exit_wq.notify_all(false);
+    if let Some(parent) = curr.parent() {
+        parent.child_exit_wq.notify_all(false);
+    }
+    drop(curr);
+    axtask::exit(exit_code)
+}
+
+/// Terminates the current process if it has exceeded its CPU time limit (see
+/// [`Resource::CpuTime`]).
+///
+/// It is checked on user page faults, and by the task of the process when it
+/// is preempted over its limit, on the timer tick or when it is switched to
+/// (see [`axtask::set_cpu_limit_handler`]), which needs the `preempt` feature
+/// of `axtask`. It should also be called on other entries from user space,
+/// e.g., by the system call handler.
+pub fn check_cpu_time() {
+    let Some(curr) = current() else {
+        return;
+    };
+    let limit = curr.limits().get(Resource::CpuTime);
+    if limit == UNLIMITED || (axtask::current().cpu_time().as_millis() as u64) < limit {
+        return;
+    }
+    error!(
+        "process {} ({}) killed: CPU time limit exceeded",
+        curr.pid, curr.name
+    );
+    drop(curr);
+    exit(CPU_LIMIT_EXIT_CODE)
+}
+
+/// Returns the CPU time limit of the task of a process.
+fn cpu_limit(limits: &ResourceLimits) -> Option<Duration> {
+    match limits.get(Resource::CpuTime) {
+        UNLIMITED => None,
+        limit => Some(Duration::from_millis(limit)),
+    }
+}
+
+/// Marks the current process task as handling a trap in the kernel, or not,
+/// returns whether it was.
+fn set_in_kernel(in_kernel: bool) -> bool {
+    let curr = axtask::current();
+    curr.task_ext().in_kernel.swap(in_kernel, Ordering::AcqRel)
+}
+
+/// Terminates the current process over its CPU time limit, at the preemption
+/// point of its task, unless it is handling a trap of the process in the
+/// kernel: it is then terminated on the next timer tick.
+fn on_cpu_limit() {
+    if current().is_none() || set_in_kernel(true) {
+        return;
+    }
+    // It is interrupted in user space, and the interrupt is handled, so it
+    // exits as it would from the kernel.
+    let irqs_enabled = axhal::asm::irqs_enabled();
+    axhal::asm::enable_irqs();
+    check_cpu_time();
+    if !irqs_enabled {
+        axhal::asm::disable_irqs();
+    }
+    set_in_kernel(false);
+}
+
+fn init() {
+    axtask::set_cpu_limit_handler(on_cpu_limit);
+}
+
+axruntime::register_subsystem!(CPU_LIMIT, "cpu-limit", [], init);
+
+/// Resolves page faults of lazily populated (or swapped out) memory of the
+/// current process, and terminates it on other faults, or if it was
+/// terminated to free memory.
+///
+/// With the `swap` feature, pages of the processes are swapped out first if
+/// the memory is under pressure, or if no frame can be allocated.
+#[linkme::distributed_slice(USER_FAULT_HANDLERS)]
+fn handle_user_fault(fault: &UserFault) -> bool {
+    if current().is_none() {
+        return false;
+    }
+    set_in_kernel(true);
+    check_cpu_time();
+    let handled = resolve_user_fault(fault);
+    set_in_kernel(false);
+    handled
+}
+
+fn resolve_user_fault(fault: &UserFault) -> bool {
+    let Some(curr) = current() else {
+        return false;
+    };
+    // Its memory is freed, so it faults on its next access.
+    if curr.oom_killed() {
+        drop(curr);
+        exit(OOM_EXIT_CODE)
+    }
+    let handle = || {
+        curr.aspace
+            .lock()
+            .handle_page_fault(fault.vaddr, fault.access_flags)
+    };
+    #[cfg(feature = "swap")]
+    crate::swap::reclaim();
+    if handle() {
+        return true;
+    }
+    // Other tasks may have taken the memory left since.
+    #[cfg(feature = "swap")]
+    if crate::swap::reclaim() && handle() {
+        return true;
+    }
+    error!(
+        "process {} ({}) killed: unhandled page fault @ {:#x} ({:?})",
+        curr.pid, curr.name, fault.vaddr, fault.access_flags,
+    );
+    drop(curr);
+    exit(FAULT_EXIT_CODE)
+}
+
+/// Exits the current process on an unhandled user exception, e.g., an
+/// illegal instruction, with the exit code of the terminated task.
+#[linkme::distributed_slice(USER_EXCEPTION_HANDLERS)]
+fn exit_on_user_exception(exit_code: i32) {
+    if current().is_some() {
+        set_in_kernel(true);
+        exit(exit_code)
+    }
+}
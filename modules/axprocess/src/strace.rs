@@ -0,0 +1,327 @@
+//! Tracing of the system calls of processes, like `strace`.
+//!
+//! ArceOS has no system call layer of its own: the system calls are
+//! dispatched by `axcpu` to the `SYSCALL` trap handler registered by the
+//! application. To trace them, the handler calls [`syscall_enter`] before
+//! handling a system call, and [`SyscallTrace::exit`] with its result. If
+//! tracing is enabled for the process (see [`Process::set_strace`]), a
+//! [`SyscallRecord`] is then kept in a ring buffer of the last
+//! [`STRACE_BUF_LEN`] records, read with [`strace_records`], and logged at
+//! the trace level.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use axhal::time::{TimeValue, monotonic_time};
+use axring::MpscRing;
+use memory_addr::VirtAddr;
+
+use crate::process::{Pid, Process, current};
+
+/// The number of system call records kept.
+pub const STRACE_BUF_LEN: usize = 256;
+
+/// The maximum length of the strings shown in the arguments.
+const MAX_STR_LEN: usize = 64;
+
+/// The last system call records, the oldest first, pushed by the processes
+/// on all the CPUs without locking.
+static RECORDS: MpscRing<SyscallRecord, STRACE_BUF_LEN> = MpscRing::new();
+
+/// How an argument of a system call is shown.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// A signed integer, e.g., a file descriptor or a length.
+    Int,
+    /// An address or flags, in hexadecimal.
+    Hex,
+    /// A pointer to a NUL-terminated string, e.g., a path.
+    Str,
+}
+
+use Arg::*;
+
+/// The names and the arguments of the system calls decoded, by number.
+#[cfg(target_arch = "x86_64")]
+#[rustfmt::skip]
+const SYSCALLS: &[(usize, &str, &[Arg])] = &[
+    (0, "read", &[Int, Hex, Int]),
+    (1, "write", &[Int, Hex, Int]),
+    (2, "open", &[Str, Hex, Hex]),
+    (3, "close", &[Int]),
+    (5, "fstat", &[Int, Hex]),
+    (8, "lseek", &[Int, Int, Int]),
+    (9, "mmap", &[Hex, Int, Hex, Hex, Int, Int]),
+    (11, "munmap", &[Hex, Int]),
+    (12, "brk", &[Hex]),
+    (16, "ioctl", &[Int, Hex, Hex]),
+    (20, "writev", &[Int, Hex, Int]),
+    (24, "sched_yield", &[]),
+    (32, "dup", &[Int]),
+    (35, "nanosleep", &[Hex, Hex]),
+    (39, "getpid", &[]),
+    (56, "clone", &[Hex, Hex, Hex, Hex, Hex]),
+    (57, "fork", &[]),
+    (59, "execve", &[Str, Hex, Hex]),
+    (60, "exit", &[Int]),
+    (61, "wait4", &[Int, Hex, Hex, Hex]),
+    (62, "kill", &[Int, Int]),
+    (79, "getcwd", &[Hex, Int]),
+    (80, "chdir", &[Str]),
+    (83, "mkdir", &[Str, Hex]),
+    (87, "unlink", &[Str]),
+    (231, "exit_group", &[Int]),
+    (257, "openat", &[Int, Str, Hex, Hex]),
+    (258, "mkdirat", &[Int, Str, Hex]),
+    (263, "unlinkat", &[Int, Str, Hex]),
+    (292, "dup3", &[Int, Int, Hex]),
+    (293, "pipe2", &[Hex, Hex]),
+];
+
+/// The names and the arguments of the system calls decoded, by number.
+#[cfg(not(target_arch = "x86_64"))]
+#[rustfmt::skip]
+const SYSCALLS: &[(usize, &str, &[Arg])] = &[
+    (17, "getcwd", &[Hex, Int]),
+    (23, "dup", &[Int]),
+    (24, "dup3", &[Int, Int, Hex]),
+    (29, "ioctl", &[Int, Hex, Hex]),
+    (34, "mkdirat", &[Int, Str, Hex]),
+    (35, "unlinkat", &[Int, Str, Hex]),
+    (49, "chdir", &[Str]),
+    (56, "openat", &[Int, Str, Hex, Hex]),
+    (57, "close", &[Int]),
+    (59, "pipe2", &[Hex, Hex]),
+    (62, "lseek", &[Int, Int, Int]),
+    (63, "read", &[Int, Hex, Int]),
+    (64, "write", &[Int, Hex, Int]),
+    (66, "writev", &[Int, Hex, Int]),
+    (80, "fstat", &[Int, Hex]),
+    (93, "exit", &[Int]),
+    (94, "exit_group", &[Int]),
+    (101, "nanosleep", &[Hex, Hex]),
+    (124, "sched_yield", &[]),
+    (129, "kill", &[Int, Int]),
+    (172, "getpid", &[]),
+    (214, "brk", &[Hex]),
+    (215, "munmap", &[Hex, Int]),
+    (220, "clone", &[Hex, Hex, Hex, Hex, Hex]),
+    (221, "execve", &[Str, Hex, Hex]),
+    (222, "mmap", &[Hex, Int, Hex, Hex, Int, Int]),
+    (260, "wait4", &[Int, Hex, Hex, Hex]),
+];
+
+/// A system call made by a process.
+#[derive(Debug, Clone)]
+pub struct SyscallRecord {
+    /// The process which made the system call.
+    pub pid: Pid,
+    /// The system call number.
+    pub nr: usize,
+    /// The name of the system call, or `None` if it is not decoded.
+    pub name: Option<&'static str>,
+    /// The decoded arguments, e.g., `3, 0x1000, 64`, all of them in
+    /// hexadecimal if the system call is not decoded.
+    pub args: String,
+    /// The return value, a negated error number on errors.
+    pub ret: isize,
+    /// The time spent handling the system call.
+    pub latency: Duration,
+}
+
+impl fmt::Display for SyscallRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "[{}] {}({})", self.pid, name, self.args)?,
+            None => write!(f, "[{}] syscall_{}({})", self.pid, self.nr, self.args)?,
+        }
+        write!(f, " = {} <{}us>", self.ret, self.latency.as_micros())
+    }
+}
+
+/// A system call being traced, see [`syscall_enter`].
+pub struct SyscallTrace {
+    process: Arc<Process>,
+    nr: usize,
+    name: Option<&'static str>,
+    args: String,
+    start: TimeValue,
+}
+
+impl SyscallTrace {
+    /// Records the end of the system call, with its return value.
+    pub fn exit(self, ret: isize) {
+        let record = SyscallRecord {
+            pid: self.process.pid(),
+            nr: self.nr,
+            name: self.name,
+            args: self.args,
+            ret,
+            latency: monotonic_time() - self.start,
+        };
+        trace!("{}", record);
+        push_record(record);
+    }
+}
+
+/// Keeps a record, dropping the oldest ones to make room if full.
+fn push_record(mut record: SyscallRecord) {
+    while let Err(r) = RECORDS.push(record) {
+        record = r;
+        RECORDS.pop();
+    }
+}
+
+/// Starts tracing the system call `nr` with the arguments `args` made by the
+/// current process, if tracing is enabled for it.
+///
+/// The arguments are decoded now, as the system call may change the memory
+/// they point to.
+pub fn syscall_enter(nr: usize, args: [usize; 6]) -> Option<SyscallTrace> {
+    let process = current().filter(|process| process.strace())?;
+    let (name, args) = decode_syscall(nr, args, |addr| {
+        read_user_str(&process, VirtAddr::from(addr))
+    });
+    Some(SyscallTrace {
+        nr,
+        name,
+        args,
+        start: monotonic_time(),
+        process,
+    })
+}
+
+/// Returns the last system call records, the oldest first, and clears them.
+pub fn strace_records() -> Vec<SyscallRecord> {
+    core::iter::from_fn(|| RECORDS.pop()).collect()
+}
+
+/// Returns the name of the system call `nr`, and its arguments decoded,
+/// the strings being read with `read_str`.
+fn decode_syscall(
+    nr: usize,
+    args: [usize; 6],
+    read_str: impl Fn(usize) -> Option<String>,
+) -> (Option<&'static str>, String) {
+    match SYSCALLS.iter().find(|(n, ..)| *n == nr) {
+        Some((_, name, kinds)) => {
+            let args = kinds
+                .iter()
+                .zip(args)
+                .map(|(kind, arg)| decode_arg(*kind, arg, &read_str))
+                .collect::<Vec<_>>()
+                .join(", ");
+            (Some(*name), args)
+        }
+        None => (None, args.map(|arg| format!("{:#x}", arg)).join(", ")),
+    }
+}
+
+fn decode_arg(kind: Arg, arg: usize, read_str: impl Fn(usize) -> Option<String>) -> String {
+    match kind {
+        Int => format!("{}", arg as isize),
+        Hex => format!("{:#x}", arg),
+        Str => match read_str(arg) {
+            Some(s) => format!("{:?}", s),
+            None => format!("{:#x}", arg),
+        },
+    }
+}
+
+/// Reads a NUL-terminated string from the memory of the process, up to
+/// [`MAX_STR_LEN`] bytes, with `...` appended if it is longer. Returns `None`
+/// if its start is not mapped.
+fn read_user_str(process: &Process, addr: VirtAddr) -> Option<String> {
+    let aspace = process.aspace().lock();
+    let mut buf = [0; MAX_STR_LEN];
+    let mut len = 0;
+    // Byte by byte, as the string may end just before an unmapped page.
+    while len < MAX_STR_LEN && aspace.read(addr + len, &mut buf[len..len + 1]).is_ok() {
+        if buf[len] == 0 {
+            return Some(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+        len += 1;
+    }
+    match len {
+        0 => None,
+        MAX_STR_LEN => Some(String::from_utf8_lossy(&buf).into_owned() + "..."),
+        _ => Some(String::from_utf8_lossy(&buf[..len]).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nr_of(name: &str) -> usize {
+        SYSCALLS.iter().find(|(_, n, _)| *n == name).unwrap().0
+    }
+
+    fn record(nr: usize) -> SyscallRecord {
+        SyscallRecord {
+            pid: 3,
+            nr,
+            name: None,
+            args: String::new(),
+            ret: 0,
+            latency: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_decode_args() {
+        let read_str = |addr| (addr == 0x1000).then(|| String::from("/etc/passwd"));
+        let args = [-100isize as usize, 0x1000, 0x241, 0o644, 7, 8];
+        let (name, args) = decode_syscall(nr_of("openat"), args, read_str);
+        assert_eq!(name, Some("openat"));
+        assert_eq!(args, r#"-100, "/etc/passwd", 0x241, 0x1a4"#);
+
+        // An unreadable string is shown as its address.
+        let (_, args) = decode_syscall(nr_of("chdir"), [0x2000, 0, 0, 0, 0, 0], read_str);
+        assert_eq!(args, "0x2000");
+
+        let (_, args) = decode_syscall(nr_of("getpid"), [1, 2, 3, 4, 5, 6], read_str);
+        assert_eq!(args, "");
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        let (name, args) = decode_syscall(9999, [0, 1, 0x10, 3, 4, 5], |_| None);
+        assert_eq!(name, None);
+        assert_eq!(args, "0x0, 0x1, 0x10, 0x3, 0x4, 0x5");
+    }
+
+    #[test]
+    fn test_record_display() {
+        let mut r = record(nr_of("write"));
+        r.name = Some("write");
+        r.args = String::from("1, 0x1000, 5");
+        r.ret = 5;
+        r.latency = Duration::from_micros(12);
+        assert_eq!(r.to_string(), "[3] write(1, 0x1000, 5) = 5 <12us>");
+
+        let mut r = record(9999);
+        r.args = String::from("0x0");
+        r.ret = -38;
+        assert_eq!(r.to_string(), "[3] syscall_9999(0x0) = -38 <0us>");
+    }
+
+    #[test]
+    fn test_record_ring() {
+        const EXTRA: usize = 10;
+        for nr in 0..STRACE_BUF_LEN + EXTRA {
+            push_record(record(nr));
+        }
+        // The oldest records are dropped.
+        let records = strace_records();
+        assert_eq!(records.len(), STRACE_BUF_LEN);
+        for (i, r) in records.iter().enumerate() {
+            assert_eq!(r.nr, EXTRA + i);
+        }
+        assert!(strace_records().is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+//! Swapping out the memory of the processes under memory pressure.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axmm::swap;
+
+use crate::process::processes;
+
+/// The number of pages swapped out at once.
+const SWAP_OUT_BATCH: usize = 64;
+
+/// The index of the process to swap out next.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Frees memory if it is under pressure: evicts the clean caches first,
+/// then swaps out pages of the processes, up to [`SWAP_OUT_BATCH`] pages in
+/// all. Returns whether any page is freed.
+///
+/// The processes are taken in turn, from the one after the process swapped
+/// out last. The processes running on other CPUs are skipped, as their TLBs
+/// may still map the pages swapped out.
+pub(crate) fn reclaim() -> bool {
+    if !swap::below_low_watermark() {
+        return false;
+    }
+    let evicted = swap::evict_clean_caches(SWAP_OUT_BATCH);
+    if evicted > 0 {
+        debug!("evicted {} clean cache pages", evicted);
+    }
+    if evicted >= SWAP_OUT_BATCH || !swap::under_pressure() {
+        return evicted > 0;
+    }
+    let processes = processes();
+    let start = NEXT.load(Ordering::Relaxed);
+    let mut count = 0;
+    for i in (0..processes.len()).map(|i| (start + i) % processes.len()) {
+        if evicted + count == SWAP_OUT_BATCH {
+            break;
+        }
+        let process = &processes[i];
+        // The address space may be held by its process, e.g., handling its
+        // own fault, so it is skipped rather than waited for.
+        if let Some(mut aspace) = process.aspace().try_lock() {
+            count += aspace.swap_out(SWAP_OUT_BATCH - evicted - count, || {
+                process.aspace_in_use_elsewhere()
+            });
+            NEXT.store(i + 1, Ordering::Relaxed);
+        }
+    }
+    debug!("swapped out {} pages", count);
+    evicted + count > 0
+}
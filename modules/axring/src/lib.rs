@@ -0,0 +1,27 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) lock-free ring buffers.
+//!
+//! The buffers have a fixed capacity, and their storage is inline, so they
+//! can be `static`s and never allocate. They never wait either, so they can
+//! be used in IRQ handlers:
+//!
+//! - [`SpscRing`]: a ring with a single producer and a single consumer,
+//!   e.g., of the bytes received by an IRQ handler and read by tasks, which
+//!   supports copying slices in and out.
+//! - [`MpscRing`]: a ring of values with any number of producers, e.g., of
+//!   the records of events logged by all the CPUs.
+//!
+//! # Memory ordering
+//!
+//! A producer writes an item to its slot, then publishes it with a
+//! `Release` store of the position it reached, which the consumer reads with
+//! `Acquire` before reading the item, so the item is seen once it is
+//! published. The consumer publishes the slots it freed the same way, so
+//! the producer never overwrites an item being read.
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+mod mpsc;
+mod spsc;
+
+pub use mpsc::MpscRing;
+pub use spsc::SpscRing;
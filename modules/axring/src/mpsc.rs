@@ -0,0 +1,165 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A slot of [`MpscRing`].
+struct Slot<T> {
+    /// The sequence number of the slot, minus its index so that all the
+    /// slots start at 0. It is the position of the next push into the slot
+    /// while the slot is free, and that position plus one once the item
+    /// pushed is published.
+    stamp: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A ring buffer of up to `N` items, with any number of producers.
+///
+/// Producers claim a position with a compare-and-swap and publish the item
+/// with a `Release` store of the sequence number of its slot, so they never
+/// wait for each other, and can push from IRQ handlers. Consumers claim
+/// items the same way, so [`pop`](Self::pop) can be called by several
+/// callers at a time too.
+///
+/// An item being pushed blocks the items after it until it is published, as
+/// they are popped in order, i.e., [`pop`](Self::pop) returns `None` in the
+/// meantime.
+pub struct MpscRing<T, const N: usize> {
+    slots: [Slot<T>; N],
+    /// The position of the next pop.
+    head: AtomicUsize,
+    /// The position of the next push.
+    tail: AtomicUsize,
+}
+
+// SAFETY: the items are sent from the producers to the consumers, and each
+// slot is accessed by the one which claimed it.
+unsafe impl<T: Send, const N: usize> Sync for MpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for MpscRing<T, N> {}
+
+impl<T, const N: usize> MpscRing<T, N> {
+    /// Creates an empty ring.
+    ///
+    /// It fails to build if `N` is 0.
+    pub const fn new() -> Self {
+        const { assert!(N > 0) };
+        Self {
+            slots: [const {
+                Slot {
+                    stamp: AtomicUsize::new(0),
+                    item: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of items.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of items pushed and not popped, including those
+    /// being pushed or popped.
+    ///
+    /// It is only a snapshot if the ring is used at the same time.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    /// Returns `true` if there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the slot of the position `pos`, with its sequence number.
+    fn slot(&self, pos: usize) -> (&Slot<T>, usize) {
+        let index = pos % N;
+        let slot = &self.slots[index];
+        // Acquire: the item is written (or read) before the sequence number
+        // is published.
+        let seq = slot.stamp.load(Ordering::Acquire).wrapping_add(index);
+        (slot, seq)
+    }
+
+    /// Appends an item, or returns it back if the ring is full.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let (slot, seq) = self.slot(pos);
+            match seq.wrapping_sub(pos) as isize {
+                // The slot is free: claim the position.
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the position is claimed, and the slot is
+                        // not read until it is published.
+                        unsafe { (*slot.item.get()).write(item) };
+                        // Release: the item is written before it is
+                        // published.
+                        let stamp = pos.wrapping_add(1).wrapping_sub(pos % N);
+                        slot.stamp.store(stamp, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(tail) => pos = tail,
+                },
+                // The item pushed `N` positions before is not popped yet.
+                diff if diff < 0 => return Err(item),
+                // Another producer claimed the position.
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Removes the first item, or returns `None` if the ring is empty or the
+    /// first item is still being pushed.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let (slot, seq) = self.slot(pos);
+            match seq.wrapping_sub(pos.wrapping_add(1)) as isize {
+                // The item is published: claim it.
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the item is claimed, and the slot is not
+                        // written until it is freed.
+                        let item = unsafe { (*slot.item.get()).assume_init_read() };
+                        // Release: the item is read before the slot is freed
+                        // for the push `N` positions later.
+                        let stamp = pos.wrapping_add(N).wrapping_sub(pos % N);
+                        slot.stamp.store(stamp, Ordering::Release);
+                        return Some(item);
+                    }
+                    Err(head) => pos = head,
+                },
+                // The slot is free, or its item is being pushed.
+                diff if diff < 0 => return None,
+                // Another consumer claimed the item.
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Default for MpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for MpscRing<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
@@ -0,0 +1,181 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A ring buffer with a single producer and a single consumer, of up to `N`
+/// items.
+///
+/// The producer side ([`push`](Self::push), [`push_slice`](Self::push_slice))
+/// and the consumer side ([`pop`](Self::pop), [`pop_slice`](Self::pop_slice))
+/// can run at the same time without locking, but each side must be used by
+/// one caller at a time, which is why they are `unsafe`. Users with several
+/// producers or consumers serialize them, e.g., with a lock only taken by
+/// that side, so a producer in an IRQ handler never waits for a consumer.
+///
+/// The positions of the producer and the consumer are kept modulo `2 * N`,
+/// so a full ring is told apart from an empty one without wasting a slot,
+/// for any `N`.
+pub struct SpscRing<T: Copy, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// The position of the first item, only written by the consumer.
+    head: AtomicUsize,
+    /// The position after the last item, only written by the producer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: the items are sent between the producer and the consumer, and
+// each slot is accessed by one side at a time, as published by the positions.
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T: Copy, const N: usize> SpscRing<T, N> {
+    /// Creates an empty ring.
+    ///
+    /// It fails to build if `N` is 0 or too large to keep the positions.
+    pub const fn new() -> Self {
+        const { assert!(N > 0 && N <= usize::MAX / 4) };
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of items.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of items between the positions `head` and `tail`.
+    const fn distance(head: usize, tail: usize) -> usize {
+        (tail + 2 * N - head) % (2 * N)
+    }
+
+    /// Returns the position `n` items after `pos`.
+    const fn advance(pos: usize, n: usize) -> usize {
+        (pos + n) % (2 * N)
+    }
+
+    /// Returns the number of items.
+    ///
+    /// It is only a snapshot if the other side is running.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        // The consumer may have advanced after `head` was read, and the
+        // producer filled the freed slots before `tail` was read.
+        Self::distance(head, tail).min(N)
+    }
+
+    /// Returns `true` if there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if there are `N` items.
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Appends an item, or returns it back if the ring is full.
+    ///
+    /// # Safety
+    ///
+    /// It must not run at the same time as another call of `push` or
+    /// [`push_slice`](Self::push_slice) on the ring, e.g., on another CPU or
+    /// in an IRQ handler interrupting it.
+    pub unsafe fn push(&self, item: T) -> Result<(), T> {
+        // SAFETY: the caller guarantees a single producer.
+        match unsafe { self.push_slice(core::slice::from_ref(&item)) } {
+            0 => Err(item),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends as many items from `items` as there are free slots for, and
+    /// returns the number of items appended.
+    ///
+    /// # Safety
+    ///
+    /// See [`push`](Self::push).
+    pub unsafe fn push_slice(&self, items: &[T]) -> usize {
+        // Only the producer writes `tail`.
+        let tail = self.tail.load(Ordering::Relaxed);
+        // Acquire: the consumer has finished reading the slots it freed.
+        let head = self.head.load(Ordering::Acquire);
+        let n = items.len().min(N - Self::distance(head, tail));
+        for (i, &item) in items[..n].iter().enumerate() {
+            let slot = &self.slots[Self::advance(tail, i) % N];
+            // SAFETY: the slot is free, so the consumer does not access it.
+            unsafe { (*slot.get()).write(item) };
+        }
+        if n > 0 {
+            // Release: the items are written before they are published.
+            self.tail.store(Self::advance(tail, n), Ordering::Release);
+        }
+        n
+    }
+
+    /// Removes the first item, or returns `None` if the ring is empty.
+    ///
+    /// # Safety
+    ///
+    /// It must not run at the same time as another call of `pop` or
+    /// [`pop_slice`](Self::pop_slice) on the ring, e.g., on another CPU or
+    /// in an IRQ handler interrupting it.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let mut item = MaybeUninit::uninit();
+        // SAFETY: the caller guarantees a single consumer.
+        match unsafe { self.pop_slice(core::slice::from_mut(&mut item)) } {
+            // SAFETY: the item was copied from the ring.
+            1 => Some(unsafe { item.assume_init() }),
+            _ => None,
+        }
+    }
+
+    /// Moves the first items to `out`, as many as fit, and returns the number
+    /// of items moved.
+    ///
+    /// # Safety
+    ///
+    /// See [`pop`](Self::pop).
+    pub unsafe fn pop_slice(&self, out: &mut [MaybeUninit<T>]) -> usize {
+        // Only the consumer writes `head`.
+        let head = self.head.load(Ordering::Relaxed);
+        // Acquire: the producer has finished writing the items it published.
+        let tail = self.tail.load(Ordering::Acquire);
+        let n = out.len().min(Self::distance(head, tail));
+        for (i, item) in out[..n].iter_mut().enumerate() {
+            let slot = &self.slots[Self::advance(head, i) % N];
+            // SAFETY: the slot holds an item, which the producer does not
+            // overwrite until it is freed.
+            *item = unsafe { *slot.get() };
+        }
+        if n > 0 {
+            // Release: the items are read before their slots are freed.
+            self.head.store(Self::advance(head, n), Ordering::Release);
+        }
+        n
+    }
+}
+
+impl<const N: usize> SpscRing<u8, N> {
+    /// Moves the first bytes to `buf`, as many as fit, and returns the number
+    /// of bytes moved.
+    ///
+    /// # Safety
+    ///
+    /// See [`pop`](Self::pop).
+    pub unsafe fn read(&self, buf: &mut [u8]) -> usize {
+        // SAFETY: `u8` and `MaybeUninit<u8>` have the same layout, and only
+        // initialized bytes are written.
+        let out = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        // SAFETY: the caller guarantees a single consumer.
+        unsafe { self.pop_slice(out) }
+    }
+}
+
+impl<T: Copy, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use axring::MpscRing;
+
+#[test]
+fn test_push_pop() {
+    let ring = MpscRing::<String, 3>::new();
+    assert_eq!(ring.capacity(), 3);
+    assert_eq!(ring.pop(), None);
+    for round in 0..5 {
+        for i in 0..3 {
+            assert_eq!(ring.push(format!("{round}.{i}")), Ok(()));
+        }
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.push("full".into()), Err("full".into()));
+        for i in 0..3 {
+            assert_eq!(ring.pop(), Some(format!("{round}.{i}")));
+        }
+        assert!(ring.is_empty());
+    }
+}
+
+#[test]
+fn test_drop() {
+    let item = Arc::new(());
+    let ring = MpscRing::<Arc<()>, 4>::new();
+    for _ in 0..3 {
+        ring.push(item.clone()).unwrap();
+    }
+    assert_eq!(Arc::strong_count(&item), 4);
+    drop(ring);
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn test_threads() {
+    const PRODUCERS: usize = 4;
+    const COUNT: usize = 20_000;
+    let ring = Arc::new(MpscRing::<(usize, usize), 16>::new());
+    let done = Arc::new(AtomicUsize::new(0));
+    let producers = (0..PRODUCERS)
+        .map(|p| {
+            let ring = ring.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                for i in 0..COUNT {
+                    while ring.push((p, i)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+                done.fetch_add(1, Ordering::Release);
+            })
+        })
+        .collect::<Vec<_>>();
+    // The items of each producer are popped in order.
+    let mut next = [0; PRODUCERS];
+    loop {
+        let finished = done.load(Ordering::Acquire) == PRODUCERS;
+        match ring.pop() {
+            Some((p, i)) => {
+                assert_eq!(i, next[p]);
+                next[p] += 1;
+            }
+            None if finished => break,
+            None => thread::yield_now(),
+        }
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    assert_eq!(next, [COUNT; PRODUCERS]);
+}
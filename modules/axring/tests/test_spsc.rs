@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::thread;
+
+use axring::SpscRing;
+
+#[test]
+fn test_push_pop() {
+    let ring = SpscRing::<u32, 3>::new();
+    assert_eq!(ring.capacity(), 3);
+    assert!(ring.is_empty());
+    unsafe {
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.push(1), Ok(()));
+        assert_eq!(ring.push(2), Ok(()));
+        assert_eq!(ring.push(3), Ok(()));
+        assert!(ring.is_full());
+        assert_eq!(ring.push(4), Err(4));
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.push(4), Ok(()));
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(3));
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), None);
+    }
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn test_slices() {
+    let ring = SpscRing::<u8, 5>::new();
+    let mut buf = [0; 4];
+    unsafe {
+        // Go around the ring several times, so the positions wrap.
+        for round in 0..10u8 {
+            assert_eq!(ring.push_slice(&[round, round + 1, round + 2]), 3);
+            assert_eq!(ring.len(), 3);
+            assert_eq!(ring.read(&mut buf), 3);
+            assert_eq!(buf[..3], [round, round + 1, round + 2]);
+        }
+        assert_eq!(ring.push_slice(b"abcdefg"), 5);
+        assert_eq!(ring.push_slice(b"h"), 0);
+        assert_eq!(ring.read(&mut buf), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(ring.push_slice(b"hij"), 3);
+        assert_eq!(ring.read(&mut buf[..2]), 2);
+        assert_eq!(&buf[..2], b"eh");
+        assert_eq!(ring.read(&mut buf), 2);
+        assert_eq!(&buf[..2], b"ij");
+    }
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn test_threads() {
+    const COUNT: u32 = 100_000;
+    let ring = Arc::new(SpscRing::<u32, 64>::new());
+    let producer = {
+        let ring = ring.clone();
+        thread::spawn(move || {
+            for i in 0..COUNT {
+                // SAFETY: this thread is the only producer.
+                while unsafe { ring.push(i) }.is_err() {
+                    thread::yield_now();
+                }
+            }
+        })
+    };
+    let mut next = 0;
+    while next < COUNT {
+        // SAFETY: this thread is the only consumer.
+        match unsafe { ring.pop() } {
+            Some(i) => {
+                assert_eq!(i, next);
+                next += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    producer.join().unwrap();
+    assert!(ring.is_empty());
+}
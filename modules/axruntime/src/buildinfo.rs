@@ -0,0 +1,80 @@
+//! Build metadata embedded in the image, identifying the code running, e.g.,
+//! in the bug reports of users running prebuilt images.
+//!
+//! It is printed at boot, and returned by [`build_info`]. The git commit,
+//! the build time and the compiler version are set by the build scripts (as
+//! `AX_GIT_COMMIT`, `AX_BUILD_TIME` and `AX_RUSTC_VERSION`), so they are
+//! empty if the kernel is not built with `make`. The build time is taken
+//! from `SOURCE_DATE_EPOCH` if it is set, so the builds can be reproducible.
+
+use core::fmt;
+
+/// The metadata of the build of the running kernel.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildInfo {
+    /// The version of ArceOS, i.e., of its crates.
+    pub version: &'static str,
+    /// The abbreviated git commit built, followed by `-dirty` if the tree
+    /// had uncommitted changes, or empty if unknown.
+    pub git_commit: &'static str,
+    /// The name of the application.
+    pub app: &'static str,
+    /// The target architecture.
+    pub arch: &'static str,
+    /// The platform name.
+    pub platform: &'static str,
+    /// The Rust target triple.
+    pub target: &'static str,
+    /// The build mode (`release` or `debug`).
+    pub build_mode: &'static str,
+    /// The build time, in UTC, formatted as in RFC 3339.
+    pub build_time: &'static str,
+    /// The version of the Rust compiler.
+    pub rustc: &'static str,
+    /// The runtime features enabled at build time.
+    pub features: &'static [&'static str],
+}
+
+const fn env_or_empty(value: Option<&'static str>) -> &'static str {
+    match value {
+        Some(value) => value,
+        None => "",
+    }
+}
+
+static BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_commit: env_or_empty(option_env!("AX_GIT_COMMIT")),
+    app: env_or_empty(option_env!("AX_APP")),
+    arch: axconfig::ARCH,
+    platform: axconfig::PLATFORM,
+    target: env_or_empty(option_env!("AX_TARGET")),
+    build_mode: env_or_empty(option_env!("AX_MODE")),
+    build_time: env_or_empty(option_env!("AX_BUILD_TIME")),
+    rustc: env_or_empty(option_env!("AX_RUSTC_VERSION")),
+    features: crate::sysinfo::FEATURES,
+};
+
+/// Returns the metadata of the build of the running kernel.
+pub fn build_info() -> &'static BuildInfo {
+    &BUILD_INFO
+}
+
+impl fmt::Display for BuildInfo {
+    /// Formats the version, the commit, the application, the platform and
+    /// the build time on one line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ArceOS {}", self.version)?;
+        if !self.git_commit.is_empty() {
+            write!(f, " ({})", self.git_commit)?;
+        }
+        if !self.app.is_empty() {
+            write!(f, " {}", self.app)?;
+        }
+        write!(f, " {} {}", self.platform, self.build_mode)?;
+        if !self.build_time.is_empty() {
+            write!(f, ", built {}", self.build_time)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,642 @@
+//! A subset of the [QEMU guest agent] protocol over a VirtIO console.
+//!
+//! When the `guest-agent` feature is enabled and a VirtIO console is present,
+//! a background task serves the following commands on it:
+//!
+//! - `guest-sync`, `guest-sync-delimited`, `guest-ping`, `guest-info`
+//! - `guest-shutdown`: `powerdown` (the default) powers the machine off,
+//!   and `halt` stops the CPU running the agent, leaving it on
+//! - `guest-file-open`, `guest-file-close`, `guest-file-read`,
+//!   `guest-file-write`, `guest-file-seek`, `guest-file-flush` (requires the
+//!   `fs` feature)
+//! - `guest-exec`, `guest-exec-status`: run a command registered by
+//!   [`register_command`]. The command runs in a task of its own, whose exit
+//!   code is reported once it exits: 0 if the command returns, or the code
+//!   it gives to `axtask::exit`. Its output goes to the system console and is
+//!   not captured. Only the last 64 exit codes not read yet are kept.
+//!
+//! The host side can be connected with:
+//!
+//! ```text
+//! -chardev socket,path=/tmp/qga.sock,server=on,wait=off,id=qga0
+//! -device virtio-serial-pci -device virtconsole,chardev=qga0
+//! ```
+//!
+//! [QEMU guest agent]: https://www.qemu.org/docs/master/interop/qemu-ga-ref.html
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use core::time::Duration;
+
+use axdriver::virtio_console;
+use axtask::{AxTaskRef, TaskState};
+use kspin::SpinNoIrq;
+
+use crate::json::{JsonStr, Value, parse};
+
+/// A command that can be run by `guest-exec`. It receives the arguments
+/// joined by spaces.
+pub type AgentCommand = fn(args: &str);
+
+/// The maximum size of a request.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// The maximum number of bytes read by one `guest-file-read`.
+const MAX_READ_SIZE: usize = 48 * 1024;
+
+/// The maximum number of exit codes of the `guest-exec` commands kept until
+/// they are read by `guest-exec-status`.
+const MAX_EXEC_CODES: usize = 64;
+
+/// The interval of polling the console when there is no input.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const SUPPORTED_COMMANDS: &[&str] = &[
+    "guest-sync",
+    "guest-sync-delimited",
+    "guest-ping",
+    "guest-info",
+    "guest-shutdown",
+    #[cfg(feature = "fs")]
+    "guest-file-open",
+    #[cfg(feature = "fs")]
+    "guest-file-close",
+    #[cfg(feature = "fs")]
+    "guest-file-read",
+    #[cfg(feature = "fs")]
+    "guest-file-write",
+    #[cfg(feature = "fs")]
+    "guest-file-seek",
+    #[cfg(feature = "fs")]
+    "guest-file-flush",
+    "guest-exec",
+    "guest-exec-status",
+];
+
+static COMMANDS: SpinNoIrq<Vec<(&'static str, AgentCommand)>> = SpinNoIrq::new(Vec::new());
+
+/// Registers a command that can be run by `guest-exec`, replacing the
+/// command with the same name.
+pub fn register_command(name: &'static str, cmd: AgentCommand) {
+    let mut commands = COMMANDS.lock();
+    match commands.iter_mut().find(|(n, _)| *n == name) {
+        Some(entry) => entry.1 = cmd,
+        None => commands.push((name, cmd)),
+    }
+}
+
+fn find_command(name: &str) -> Option<AgentCommand> {
+    let commands = COMMANDS.lock();
+    commands
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, cmd)| *cmd)
+}
+
+/// Splits the input stream into top-level JSON objects.
+#[derive(Default)]
+struct Framer {
+    buf: Vec<u8>,
+    depth: usize,
+    in_string: bool,
+    escape: bool,
+    too_large: bool,
+}
+
+impl Framer {
+    fn push(&mut self, b: u8) -> Option<Result<Vec<u8>, &'static str>> {
+        if b == 0xff {
+            // The host resets the stream before `guest-sync-delimited`.
+            *self = Self::default();
+            return None;
+        }
+        if self.depth == 0 && b != b'{' {
+            // Skip whitespace and garbage between requests.
+            return None;
+        }
+        if self.buf.len() < MAX_REQUEST_SIZE {
+            self.buf.push(b);
+        } else {
+            self.too_large = true;
+        }
+        if self.in_string {
+            if self.escape {
+                self.escape = false;
+            } else if b == b'\\' {
+                self.escape = true;
+            } else if b == b'"' {
+                self.in_string = false;
+            }
+            return None;
+        }
+        match b {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => self.depth -= 1,
+            _ => {}
+        }
+        if self.depth > 0 {
+            return None;
+        }
+        let request = core::mem::take(&mut self.buf);
+        if core::mem::take(&mut self.too_large) {
+            Some(Err("request too large"))
+        } else {
+            Some(Ok(request))
+        }
+    }
+}
+
+type CmdResult = Result<Option<String>, String>;
+
+fn arg<'a>(args: Option<&'a Value>, key: &str) -> Option<&'a Value> {
+    args?.get(key)
+}
+
+fn int_arg(args: Option<&Value>, key: &str) -> Result<i64, String> {
+    arg(args, key)
+        .and_then(Value::as_i64)
+        .ok_or_else(|| format!("parameter '{}' is missing or not an integer", key))
+}
+
+fn str_arg<'a>(args: Option<&'a Value>, key: &str) -> Result<&'a str, String> {
+    arg(args, key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("parameter '{}' is missing or not a string", key))
+}
+
+struct Agent {
+    #[cfg(feature = "fs")]
+    files: BTreeMap<i64, axfs::api::File>,
+    #[cfg(feature = "fs")]
+    next_handle: i64,
+    /// The tasks running the commands of `guest-exec`, until they exit.
+    exec_tasks: BTreeMap<i64, AxTaskRef>,
+    /// The exit codes of the commands of `guest-exec`, until they are read.
+    exec_codes: BTreeMap<i64, i32>,
+    next_pid: i64,
+}
+
+impl Agent {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "fs")]
+            files: BTreeMap::new(),
+            #[cfg(feature = "fs")]
+            next_handle: 1,
+            exec_tasks: BTreeMap::new(),
+            exec_codes: BTreeMap::new(),
+            next_pid: 1,
+        }
+    }
+
+    fn run(mut self) -> ! {
+        let mut framer = Framer::default();
+        let mut buf = [0; 256];
+        loop {
+            let len = virtio_console::read_bytes(&mut buf);
+            if len == 0 {
+                axtask::sleep(POLL_INTERVAL);
+                continue;
+            }
+            for &b in &buf[..len] {
+                let response = match framer.push(b) {
+                    None => continue,
+                    Some(Ok(request)) => self.handle(&request),
+                    Some(Err(e)) => Some(error_response(e)),
+                };
+                if let Some(response) = response {
+                    virtio_console::write_bytes(response.as_bytes());
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, request: &[u8]) -> Option<String> {
+        let request = match parse(request) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(&format!("invalid JSON: {}", e))),
+        };
+        let Some(cmd) = request.get("execute").and_then(Value::as_str) else {
+            return Some(error_response("missing 'execute'"));
+        };
+        debug!("guest agent: {}", cmd);
+        match self.execute(cmd, request.get("arguments")) {
+            Ok(Some(ret)) => Some(format!("{{\"return\":{}}}\n", ret)),
+            Ok(None) => None,
+            Err(e) => Some(error_response(&e)),
+        }
+    }
+
+    fn execute(&mut self, cmd: &str, args: Option<&Value>) -> CmdResult {
+        match cmd {
+            "guest-sync" => Ok(Some(int_arg(args, "id")?.to_string())),
+            "guest-sync-delimited" => {
+                let id = int_arg(args, "id")?;
+                virtio_console::write_bytes(&[0xff]);
+                Ok(Some(id.to_string()))
+            }
+            "guest-ping" => Ok(Some("{}".into())),
+            "guest-info" => Ok(Some(info())),
+            "guest-shutdown" => shutdown(args),
+            #[cfg(feature = "fs")]
+            "guest-file-open" => self.file_open(args),
+            #[cfg(feature = "fs")]
+            "guest-file-close" => {
+                let handle = int_arg(args, "handle")?;
+                self.files.remove(&handle).ok_or("invalid handle")?;
+                Ok(Some("{}".into()))
+            }
+            #[cfg(feature = "fs")]
+            "guest-file-read" => self.file_read(args),
+            #[cfg(feature = "fs")]
+            "guest-file-write" => self.file_write(args),
+            #[cfg(feature = "fs")]
+            "guest-file-seek" => self.file_seek(args),
+            #[cfg(feature = "fs")]
+            "guest-file-flush" => {
+                use axio::Write;
+                self.file(args)?.flush().map_err(|e| format!("{:?}", e))?;
+                Ok(Some("{}".into()))
+            }
+            "guest-exec" => self.exec(args),
+            "guest-exec-status" => self.exec_status(args),
+            _ => Err(format!("the command {} has not been found", cmd)),
+        }
+    }
+
+    fn exec(&mut self, args: Option<&Value>) -> CmdResult {
+        self.reap_exec_tasks();
+        let path = str_arg(args, "path")?;
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let command = find_command(name).ok_or_else(|| format!("command '{}' not found", path))?;
+        let mut cmd_args = String::new();
+        if let Some(list) = arg(args, "arg").and_then(Value::as_array) {
+            for (i, a) in list.iter().enumerate() {
+                let a = a
+                    .as_str()
+                    .ok_or("parameter 'arg' must be a list of strings")?;
+                if i > 0 {
+                    cmd_args.push(' ');
+                }
+                cmd_args.push_str(a);
+            }
+        }
+
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        info!("guest agent: exec {} {}", name, cmd_args);
+        let task = axtask::spawn_raw(
+            move || command(&cmd_args),
+            format!("guest-exec-{}", pid),
+            axconfig::TASK_STACK_SIZE,
+        );
+        self.exec_tasks.insert(pid, task);
+        Ok(Some(format!("{{\"pid\":{}}}", pid)))
+    }
+
+    fn exec_status(&mut self, args: Option<&Value>) -> CmdResult {
+        let pid = int_arg(args, "pid")?;
+        self.reap_exec_tasks();
+        if self.exec_tasks.contains_key(&pid) {
+            return Ok(Some("{\"exited\":false}".into()));
+        }
+        let code = self.exec_codes.remove(&pid).ok_or("invalid pid")?;
+        Ok(Some(format!("{{\"exited\":true,\"exitcode\":{}}}", code)))
+    }
+
+    /// Keeps the exit codes of the `guest-exec` tasks which exited, and drops
+    /// the tasks, so that they are freed even if their status is never read.
+    fn reap_exec_tasks(&mut self) {
+        self.exec_tasks.retain(|&pid, task| {
+            if task.state() != TaskState::Exited {
+                return true;
+            }
+            // It has exited, so it does not block.
+            self.exec_codes.insert(pid, task.join().unwrap_or(0));
+            false
+        });
+        while self.exec_codes.len() > MAX_EXEC_CODES {
+            self.exec_codes.pop_first();
+        }
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Agent {
+    fn file(&mut self, args: Option<&Value>) -> Result<&mut axfs::api::File, String> {
+        let handle = int_arg(args, "handle")?;
+        Ok(self.files.get_mut(&handle).ok_or("invalid handle")?)
+    }
+
+    fn file_open(&mut self, args: Option<&Value>) -> CmdResult {
+        let path = str_arg(args, "path")?;
+        let mode = match arg(args, "mode") {
+            Some(mode) => mode.as_str().ok_or("parameter 'mode' must be a string")?,
+            None => "r",
+        };
+        let mut opts = axfs::api::File::options();
+        // The 'b' flag makes no difference.
+        let mode: String = mode.chars().filter(|&c| c != 'b').collect();
+        match mode.as_str() {
+            "r" => opts.read(true),
+            "r+" => opts.read(true).write(true),
+            "w" => opts.write(true).create(true).truncate(true),
+            "w+" => opts.read(true).write(true).create(true).truncate(true),
+            "a" => opts.append(true).create(true),
+            "a+" => opts.read(true).append(true).create(true),
+            _ => return Err(format!("invalid file open mode '{}'", mode)),
+        };
+        let file = opts
+            .open(path)
+            .map_err(|e| format!("failed to open file '{}': {:?}", path, e))?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.files.insert(handle, file);
+        Ok(Some(handle.to_string()))
+    }
+
+    fn file_read(&mut self, args: Option<&Value>) -> CmdResult {
+        use axio::Read;
+
+        let count = match arg(args, "count") {
+            Some(count) => count
+                .as_i64()
+                .ok_or("parameter 'count' must be an integer")?,
+            None => 4096,
+        };
+        if !(0..=MAX_READ_SIZE as i64).contains(&count) {
+            return Err(format!("value '{}' is invalid for argument count", count));
+        }
+        let file = self.file(args)?;
+        let mut buf = alloc::vec![0; count as usize];
+        let mut len = 0;
+        let mut eof = false;
+        while len < buf.len() {
+            match file.read(&mut buf[len..]) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(n) => len += n,
+                Err(e) => return Err(format!("failed to read file: {:?}", e)),
+            }
+        }
+        Ok(Some(format!(
+            "{{\"count\":{},\"buf-b64\":\"{}\",\"eof\":{}}}",
+            len,
+            base64_encode(&buf[..len]),
+            eof
+        )))
+    }
+
+    fn file_write(&mut self, args: Option<&Value>) -> CmdResult {
+        use axio::Write;
+
+        let data = base64_decode(str_arg(args, "buf-b64")?)?;
+        let count = match arg(args, "count") {
+            Some(count) => count
+                .as_i64()
+                .ok_or("parameter 'count' must be an integer")?,
+            None => data.len() as i64,
+        };
+        if !(0..=data.len() as i64).contains(&count) {
+            return Err(format!("value '{}' is invalid for argument count", count));
+        }
+        self.file(args)?
+            .write_all(&data[..count as usize])
+            .map_err(|e| format!("failed to write file: {:?}", e))?;
+        Ok(Some(format!("{{\"count\":{},\"eof\":false}}", count)))
+    }
+
+    fn file_seek(&mut self, args: Option<&Value>) -> CmdResult {
+        use axio::{Seek, SeekFrom};
+
+        let offset = int_arg(args, "offset")?;
+        let whence = match arg(args, "whence") {
+            Some(Value::Number(n)) => *n,
+            Some(Value::String(s)) if s == "set" => 0,
+            Some(Value::String(s)) if s == "cur" => 1,
+            Some(Value::String(s)) if s == "end" => 2,
+            _ => return Err("parameter 'whence' is missing or invalid".into()),
+        };
+        let pos = match whence {
+            0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| "invalid offset")?),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(format!("invalid whence code {}", whence)),
+        };
+        let position = self
+            .file(args)?
+            .seek(pos)
+            .map_err(|e| format!("failed to seek file: {:?}", e))?;
+        Ok(Some(format!("{{\"position\":{},\"eof\":false}}", position)))
+    }
+}
+
+fn error_response(desc: &str) -> String {
+    format!(
+        "{{\"error\":{{\"class\":\"GenericError\",\"desc\":{}}}}}\n",
+        JsonStr(desc)
+    )
+}
+
+fn info() -> String {
+    let mut s = format!(
+        "{{\"version\":{},\"supported_commands\":[",
+        JsonStr(env!("CARGO_PKG_VERSION"))
+    );
+    for (i, name) in SUPPORTED_COMMANDS.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        let success_response = *name != "guest-shutdown";
+        let _ = write!(
+            s,
+            "{{\"name\":{},\"enabled\":true,\"success-response\":{}}}",
+            JsonStr(name),
+            success_response
+        );
+    }
+    s.push_str("]}");
+    s
+}
+
+fn shutdown(args: Option<&Value>) -> CmdResult {
+    let mode = match arg(args, "mode") {
+        Some(mode) => mode.as_str().ok_or("parameter 'mode' must be a string")?,
+        None => "powerdown",
+    };
+    match mode {
+        "halt" => {
+            info!("guest agent: halting...");
+            axhal::misc::halt();
+        }
+        "powerdown" => {
+            info!("guest agent: powering down...");
+            axhal::misc::exit(0);
+        }
+        "reboot" => Err("reboot is not supported".into()),
+        _ => Err(format!("invalid shutdown mode '{}'", mode)),
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64_CHARS[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    const INVALID: &str = "invalid base64 data";
+    let s = s.trim_end_matches('=').as_bytes();
+    let mut data = Vec::with_capacity(s.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut nbits = 0;
+    for &c in s {
+        let v = BASE64_CHARS.iter().position(|&x| x == c).ok_or(INVALID)?;
+        bits = bits << 6 | v as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            data.push((bits >> nbits) as u8);
+            bits &= (1 << nbits) - 1;
+        }
+    }
+    if nbits >= 6 {
+        return Err(INVALID.into());
+    }
+    Ok(data)
+}
+
+/// Starts the guest agent task if a VirtIO console is present.
+pub(crate) fn init() {
+    if !virtio_console::is_present() {
+        warn!("guest agent: no VirtIO console found");
+        return;
+    }
+    info!("Starting guest agent...");
+    axtask::spawn_raw(
+        || Agent::new().run(),
+        "guest-agent".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the chunks to a framer, as read one after another from the
+    /// console, and returns the requests framed.
+    fn frame(chunks: &[&[u8]]) -> Vec<Result<Vec<u8>, &'static str>> {
+        let mut framer = Framer::default();
+        let mut requests = Vec::new();
+        for chunk in chunks {
+            requests.extend(chunk.iter().filter_map(|&b| framer.push(b)));
+        }
+        requests
+    }
+
+    #[test]
+    fn test_framing_partial_reads() {
+        let requests = frame(&[
+            b"\n {\"execute\":\"guest-",
+            b"ping\"}{\"execute\":\"guest-sync\",\"argu",
+            b"ments\":{\"id\":1}",
+            b"}\n",
+        ]);
+        assert_eq!(
+            requests,
+            [
+                Ok(b"{\"execute\":\"guest-ping\"}".to_vec()),
+                Ok(b"{\"execute\":\"guest-sync\",\"arguments\":{\"id\":1}}".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_framing_strings() {
+        // Brackets and escaped quotes in strings, split after a backslash.
+        let requests = frame(&[br#"{"a":"}]{\"#, br#""\\","b":[1]}"#]);
+        assert_eq!(requests, [Ok(br#"{"a":"}]{\"\\","b":[1]}"#.to_vec())]);
+    }
+
+    #[test]
+    fn test_framing_reset() {
+        // 0xff drops the partial request, as before `guest-sync-delimited`.
+        let requests = frame(&[b"{\"execute\":", b"\xff", b"{}"]);
+        assert_eq!(requests, [Ok(b"{}".to_vec())]);
+    }
+
+    #[test]
+    fn test_framing_too_large() {
+        let mut request = b"{\"a\":\"".to_vec();
+        request.resize(MAX_REQUEST_SIZE + 10, b'x');
+        request.extend_from_slice(b"\"}{}");
+        let requests = frame(&request.chunks(256).collect::<Vec<_>>());
+        assert_eq!(requests, [Err("request too large"), Ok(b"{}".to_vec())]);
+    }
+
+    #[test]
+    fn test_base64() {
+        let cases: [(&[u8], &str); 5] = [
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"\x00\xff\xfe\x80", "AP/+gA=="),
+        ];
+        for (data, encoded) in cases {
+            assert_eq!(base64_encode(data), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), data);
+        }
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..data.len() {
+            assert_eq!(
+                base64_decode(&base64_encode(&data[..len])).unwrap(),
+                &data[..len]
+            );
+        }
+        assert!(base64_decode("Zm9v!").is_err());
+        // A single character left over has less than 8 bits.
+        assert!(base64_decode("Zm9vZ").is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut agent = Agent::new();
+        let response = agent.handle(br#"{"execute":"guest-sync","arguments":{"id":42}}"#);
+        assert_eq!(response.as_deref(), Some("{\"return\":42}\n"));
+        let response = agent.handle(br#"{"execute":"guest-ping"}"#);
+        assert_eq!(response.as_deref(), Some("{\"return\":{}}\n"));
+
+        let error = agent.handle(br#"{"execute":"guest-sync"}"#).unwrap();
+        assert!(error.starts_with("{\"error\":"), "{}", error);
+        let error = agent.handle(br#"{"execute":"guest-nope"}"#).unwrap();
+        assert!(error.contains("has not been found"), "{}", error);
+        let error = agent.handle(br#"{"execute":"guest-exec-status","arguments":{"pid":7}}"#);
+        assert!(error.unwrap().contains("invalid pid"));
+        let error = agent.handle(b"{\"execute\":}").unwrap();
+        assert!(error.contains("invalid JSON"), "{}", error);
+    }
+}
@@ -0,0 +1,340 @@
+//! Minimal JSON support for the machine-readable interfaces of the runtime.
+
+use core::fmt;
+
+/// Formats a string as a JSON string literal.
+pub(crate) struct JsonStr<'a>(pub &'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(feature = "guest-agent")]
+pub(crate) use self::parse::{Value, parse};
+
+#[cfg(feature = "guest-agent")]
+mod parse {
+    use alloc::{string::String, vec::Vec};
+
+    /// The maximum nesting depth of arrays and objects.
+    const MAX_DEPTH: usize = 16;
+
+    /// A parsed JSON value.
+    ///
+    /// Only integer numbers are supported.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) enum Value {
+        Null,
+        Bool(bool),
+        Number(i64),
+        String(String),
+        Array(Vec<Value>),
+        Object(Vec<(String, Value)>),
+    }
+
+    impl Value {
+        /// Returns the value of the given key if `self` is an object.
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            match self {
+                Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        pub fn as_str(&self) -> Option<&str> {
+            match self {
+                Value::String(s) => Some(s),
+                _ => None,
+            }
+        }
+
+        pub fn as_i64(&self) -> Option<i64> {
+            match self {
+                Value::Number(n) => Some(*n),
+                _ => None,
+            }
+        }
+
+        pub fn as_array(&self) -> Option<&[Value]> {
+            match self {
+                Value::Array(items) => Some(items),
+                _ => None,
+            }
+        }
+    }
+
+    struct Parser<'a> {
+        input: &'a [u8],
+        pos: usize,
+    }
+
+    impl Parser<'_> {
+        fn peek(&self) -> Option<u8> {
+            self.input.get(self.pos).copied()
+        }
+
+        fn next(&mut self) -> Option<u8> {
+            let c = self.peek()?;
+            self.pos += 1;
+            Some(c)
+        }
+
+        fn skip_whitespace(&mut self) {
+            while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+                self.pos += 1;
+            }
+        }
+
+        fn expect(&mut self, s: &str) -> Result<(), &'static str> {
+            if self.input[self.pos..].starts_with(s.as_bytes()) {
+                self.pos += s.len();
+                Ok(())
+            } else {
+                Err("invalid literal")
+            }
+        }
+
+        fn value(&mut self, depth: usize) -> Result<Value, &'static str> {
+            if depth > MAX_DEPTH {
+                return Err("nested too deeply");
+            }
+            self.skip_whitespace();
+            match self.peek().ok_or("unexpected end of input")? {
+                b'n' => self.expect("null").map(|_| Value::Null),
+                b't' => self.expect("true").map(|_| Value::Bool(true)),
+                b'f' => self.expect("false").map(|_| Value::Bool(false)),
+                b'"' => self.string().map(Value::String),
+                b'[' => self.array(depth),
+                b'{' => self.object(depth),
+                b'-' | b'0'..=b'9' => self.number(),
+                _ => Err("unexpected character"),
+            }
+        }
+
+        fn number(&mut self) -> Result<Value, &'static str> {
+            let start = self.pos;
+            if self.peek() == Some(b'-') {
+                self.pos += 1;
+            }
+            while matches!(self.peek(), Some(b'0'..=b'9')) {
+                self.pos += 1;
+            }
+            if matches!(self.peek(), Some(b'.' | b'e' | b'E')) {
+                return Err("only integers are supported");
+            }
+            // The slice only contains ASCII digits and '-'.
+            let s = core::str::from_utf8(&self.input[start..self.pos]).unwrap();
+            s.parse().map(Value::Number).map_err(|_| "invalid number")
+        }
+
+        fn hex4(&mut self) -> Result<u32, &'static str> {
+            let mut code = 0;
+            for _ in 0..4 {
+                let c = self.next().ok_or("unexpected end of input")?;
+                let digit = (c as char).to_digit(16).ok_or("invalid escape")?;
+                code = code * 16 + digit;
+            }
+            Ok(code)
+        }
+
+        fn string(&mut self) -> Result<String, &'static str> {
+            self.pos += 1; // skip '"'
+            let mut s = String::new();
+            loop {
+                let start = self.pos;
+                while !matches!(self.peek(), None | Some(b'"' | b'\\')) {
+                    self.pos += 1;
+                }
+                let chunk = core::str::from_utf8(&self.input[start..self.pos])
+                    .map_err(|_| "invalid UTF-8")?;
+                s.push_str(chunk);
+                match self.next().ok_or("unterminated string")? {
+                    b'"' => return Ok(s),
+                    _ => {
+                        let c = match self.next().ok_or("unterminated string")? {
+                            b'"' => '"',
+                            b'\\' => '\\',
+                            b'/' => '/',
+                            b'b' => '\x08',
+                            b'f' => '\x0c',
+                            b'n' => '\n',
+                            b'r' => '\r',
+                            b't' => '\t',
+                            b'u' => {
+                                let mut code = self.hex4()?;
+                                if (0xd800..0xdc00).contains(&code) {
+                                    self.expect("\\u").map_err(|_| "invalid surrogate")?;
+                                    let low = self.hex4()?;
+                                    if !(0xdc00..0xe000).contains(&low) {
+                                        return Err("invalid surrogate");
+                                    }
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                }
+                                char::from_u32(code).ok_or("invalid escape")?
+                            }
+                            _ => return Err("invalid escape"),
+                        };
+                        s.push(c);
+                    }
+                }
+            }
+        }
+
+        fn array(&mut self, depth: usize) -> Result<Value, &'static str> {
+            self.pos += 1; // skip '['
+            let mut items = Vec::new();
+            self.skip_whitespace();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            loop {
+                items.push(self.value(depth + 1)?);
+                self.skip_whitespace();
+                match self.next() {
+                    Some(b',') => continue,
+                    Some(b']') => return Ok(Value::Array(items)),
+                    _ => return Err("expected ',' or ']'"),
+                }
+            }
+        }
+
+        fn object(&mut self, depth: usize) -> Result<Value, &'static str> {
+            self.pos += 1; // skip '{'
+            let mut members = Vec::new();
+            self.skip_whitespace();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Value::Object(members));
+            }
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') {
+                    return Err("expected a key");
+                }
+                let key = self.string()?;
+                self.skip_whitespace();
+                if self.next() != Some(b':') {
+                    return Err("expected ':'");
+                }
+                members.push((key, self.value(depth + 1)?));
+                self.skip_whitespace();
+                match self.next() {
+                    Some(b',') => continue,
+                    Some(b'}') => return Ok(Value::Object(members)),
+                    _ => return Err("expected ',' or '}'"),
+                }
+            }
+        }
+    }
+
+    /// Parses a JSON document.
+    pub(crate) fn parse(input: &[u8]) -> Result<Value, &'static str> {
+        let mut parser = Parser { input, pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != input.len() {
+            return Err("trailing characters");
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(JsonStr("plain").to_string(), r#""plain""#);
+        assert_eq!(JsonStr("a\"b\\c").to_string(), r#""a\"b\\c""#);
+        assert_eq!(JsonStr("\n\t\x01é").to_string(), r#""\u000a\u0009\u0001é""#);
+    }
+
+    #[cfg(feature = "guest-agent")]
+    #[test]
+    fn test_parse() {
+        use alloc::{string::String, vec};
+
+        let value = parse(br#" {"a": [1, -2, true, false, null], "b": {"c": "d"}} "#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                (
+                    "a".into(),
+                    Value::Array(vec![
+                        Value::Number(1),
+                        Value::Number(-2),
+                        Value::Bool(true),
+                        Value::Bool(false),
+                        Value::Null,
+                    ])
+                ),
+                (
+                    "b".into(),
+                    Value::Object(vec![("c".into(), Value::String("d".into()))])
+                ),
+            ])
+        );
+        assert_eq!(
+            value
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Value::as_str),
+            Some("d")
+        );
+        assert_eq!(parse(b"[]").unwrap(), Value::Array(vec![]));
+        assert_eq!(parse(b"{}").unwrap(), Value::Object(vec![]));
+
+        let deep = String::from("[").repeat(20) + &String::from("]").repeat(20);
+        for bad in [
+            &b"{"[..],
+            b"[1,]",
+            b"{\"a\" 1}",
+            b"{1:2}",
+            b"1.5",
+            b"tru",
+            b"[] []",
+            deep.as_bytes(),
+        ] {
+            assert!(parse(bad).is_err(), "{}", String::from_utf8_lossy(bad));
+        }
+    }
+
+    #[cfg(feature = "guest-agent")]
+    #[test]
+    fn test_parse_escapes() {
+        let value = parse(r#""\"\\\/\b\f\n\r\té\u00e9\ud83d\ude00""#.as_bytes()).unwrap();
+        assert_eq!(value.as_str(), Some("\"\\/\x08\x0c\n\r\téé\u{1f600}"));
+        for bad in [
+            &br#""\x""#[..],
+            br#""\u12""#,
+            br#""\ud83d""#,
+            br#""\ud83dA""#,
+            br#""unterminated"#,
+        ] {
+            assert!(
+                parse(bad).is_err(),
+                "{}",
+                core::str::from_utf8(bad).unwrap()
+            );
+        }
+        // Escaped strings round-trip through `JsonStr`.
+        let s = "q\"b\\s\n\u{7f}é";
+        let json = JsonStr(s).to_string();
+        assert_eq!(parse(json.as_bytes()).unwrap().as_str(), Some(s));
+    }
+}
@@ -2,6 +2,14 @@ use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // Exceptions raised in user space only terminate the faulting task.
+    #[cfg(feature = "uspace")]
+    crate::uspace::handle_panic(info);
+    // The logger may not be initialized yet.
+    #[cfg(feature = "earlycon")]
+    if axhal::earlycon::is_enabled() {
+        axhal::earlycon::write_fmt(format_args!("{}\n", info));
+    }
     error!("{}", info);
-    axhal::misc::terminate()
+    axhal::misc::exit(1)
 }
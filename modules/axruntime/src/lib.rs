@@ -7,14 +7,66 @@
 //!
 //! - `alloc`: Enable global memory allocator.
 //! - `paging`: Enable page table manipulation support.
+//! - `uspace`: Enable user space support, terminating tasks on unhandled user
+//!   faults and exceptions (see [`uspace`]).
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fs`: Enable filesystem support.
+//! - `kv`: Enable the key-value store on the last block device (see
+//!   [`axkv`]).
+//! - `update`: Enable A/B image updates with rollback, the image slots being
+//!   on the last block device (see [`axupdate`]).
 //! - `net`: Enable networking support.
+//! - `netconsole`: Also send the console output over UDP and accept input
+//!   over TCP (see [`netconsole`]).
 //! - `display`: Enable graphics support.
+//! - `fbcon`: Also show the console output on the display (see
+//!   [`axhal::fbcon`]).
+//! - `selftest`: Run HAL self-tests after platform initialization.
+//! - `earlycon`: Write the console output to the boot UART by polling until
+//!   the logger is initialized, so early panics are shown (see
+//!   [`axhal::earlycon`]).
+//! - `harden`: Seed the stack canary and enable pointer authentication at
+//!   boot, for kernels built with `HARDEN=y` (see [`axhal::harden`]).
+//! - `pm`: Runtime power management of devices (see [`axhal::pm`]), with a
+//!   `pm-idle` task suspending the devices idle for a while, if `multitask`
+//!   is enabled.
+//! - `cpuidle`: The idle tasks enter the deepest idle state of the CPUs (see
+//!   [`axhal::cpuidle`]).
+//! - `sysrq`: Add magic keys on the console showing the tasks and the memory
+//!   statistics, or preempting the current task (see
+//!   [`axhal::console::register_sysrq`]).
+//! - `log-file`: Also write logs to a file on the filesystem (see [`logfile`]).
+//! - `virtio-balloon`: Enable the VirtIO memory balloon driver.
+//! - `virtio-console`: Use VirtIO consoles as console ports, the first one
+//!   replacing the console device of the platform.
+//! - `guest-agent`: Serve a subset of the QEMU guest agent protocol on a
+//!   VirtIO console (see [`guest_agent`]).
 //!
 //! All the features are optional and disabled by default.
+//!
+//! The version, the git commit and the other metadata of the build are
+//! printed at boot (see [`buildinfo`]).
+//!
+//! The initialization steps run in the order of their dependencies, with the
+//! drivers and subsystems registered by other crates, and the time each step
+//! takes is recorded (see [`startup`]).
+//!
+//! Logs can also be written to a secondary serial port, by setting
+//! `AX_LOG_PORT` at build time (see [`logport`]).
+//!
+//! The output written to the console device, and to the framebuffer console
+//! with the `fbcon` feature, can be selected by setting `AX_CONSOLE_OUTPUT`
+//! and `AX_FBCON_OUTPUT` at build time to `log` (kernel logs and messages),
+//! `stdout` (output of applications), `all` (the default) or `none`.
+//!
+//! With the `irq` feature, the IRQ handlers running longer than
+//! `AX_IRQ_BUDGET_US` microseconds, if it is set at build time, are reported
+//! (see [`axhal::irq::set_budget`]). If `AX_IRQ_BUDGET_DEFER` is also set to
+//! `y`, with the `multitask` feature, their IRQs are then handled by the
+//! `irq-deferred` task. The IRQ handlers can also defer their work to
+//! tasklets, run after them (see [`softirq`]).
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_auto_cfg)]
@@ -22,12 +74,42 @@
 #[macro_use]
 extern crate axlog;
 
+#[cfg(any(feature = "log-file", feature = "guest-agent"))]
+extern crate alloc;
+
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "log-file")]
+pub mod logfile;
+
+pub mod logport;
+
+#[cfg(feature = "uspace")]
+pub mod uspace;
+
+#[cfg(feature = "guest-agent")]
+pub mod guest_agent;
+
+#[cfg(feature = "netconsole")]
+pub mod netconsole;
+
+mod json;
+
+#[cfg(feature = "sysrq")]
+mod sysrq;
+
+#[cfg(feature = "irq")]
+pub mod softirq;
+
+pub mod buildinfo;
+pub mod plugin;
+pub mod startup;
+pub mod sysinfo;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -51,7 +133,14 @@ struct LogIfImpl;
 #[crate_interface::impl_interface]
 impl axlog::LogIf for LogIfImpl {
     fn console_write_str(s: &str) {
-        axhal::console::write_bytes(s.as_bytes());
+        // Buffer the output if possible, and block only if the buffer is full.
+        use axhal::console::{self, ConsoleSource};
+
+        let bytes = s.as_bytes();
+        let len = console::write_bytes_nonblocking_from(ConsoleSource::LOG, bytes);
+        if len < bytes.len() {
+            console::write_bytes_from(ConsoleSource::LOG, &bytes[len..]);
+        }
     }
 
     fn current_time() -> core::time::Duration {
@@ -102,7 +191,45 @@ fn is_init_ok() -> bool {
 /// and the secondary CPUs call [`rust_main_secondary`].
 #[cfg_attr(not(test), unsafe(no_mangle))]
 pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
+    // First, as the functions returning after would fail their checks.
+    #[cfg(feature = "harden")]
+    axhal::harden::init_primary();
+
+    // The initialization steps run in dependency order, followed by the
+    // registered drivers and subsystems.
+    startup::run(&mut startup::BootState::new(cpu_id, dtb));
+
+    ctor_bare::call_ctors();
+
+    info!("Primary CPU {} init OK.", cpu_id);
+    INITED_CPUS.fetch_add(1, Ordering::Release);
+
+    while !is_init_ok() {
+        core::hint::spin_loop();
+    }
+
+    // The device IRQs are routed to this CPU until all the CPUs are up.
+    #[cfg(all(feature = "irq", feature = "smp"))]
+    axhal::irq::spread_irqs(axconfig::SMP);
+
+    sysinfo::boot_finished();
+    ax_println!("{}", sysinfo::system_info().json());
+
+    unsafe { main() };
+
+    #[cfg(feature = "multitask")]
+    axtask::exit(0);
+    #[cfg(not(feature = "multitask"))]
+    {
+        debug!("main task exited: exit_code={}", 0);
+        axhal::misc::exit(0);
+    }
+}
+
+/// Prints the logo and the build information, on the console.
+fn print_banner() {
     ax_println!("{}", LOGO);
+    ax_println!("{}\n", buildinfo::build_info());
     ax_println!(
         "\
         arch = {}\n\
@@ -124,11 +251,20 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         "Boot at {}\n",
         chrono::DateTime::from_timestamp_nanos(axhal::time::wall_time_nanos() as _),
     );
+}
 
+fn init_logging(state: &mut startup::BootState) {
     axlog::init();
     axlog::set_max_level(option_env!("AX_LOG").unwrap_or("")); // no effect if set `log-level-*` features
+    // The console device and the logger are up, and the early console must
+    // not be used after the kernel page table is set up.
+    #[cfg(feature = "earlycon")]
+    axhal::earlycon::disable();
     info!("Logging is enabled.");
-    info!("Primary CPU {} started, dtb = {:#x}.", cpu_id, dtb);
+    info!(
+        "Primary CPU {} started, dtb = {:#x}.",
+        state.cpu_id, state.dtb
+    );
 
     info!("Found physcial memory regions:");
     for r in axhal::mem::memory_regions() {
@@ -140,67 +276,16 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
             r.flags
         );
     }
+}
 
-    #[cfg(feature = "alloc")]
-    init_allocator();
-
-    #[cfg(feature = "paging")]
-    axmm::init_memory_management();
-
+fn init_platform() {
     info!("Initialize platform devices...");
     axhal::platform_init();
-
-    #[cfg(feature = "multitask")]
-    axtask::init_scheduler();
-
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
-    {
-        #[allow(unused_variables)]
-        let all_devices = axdriver::init_drivers();
-
-        #[cfg(feature = "fs")]
-        axfs::init_filesystems(all_devices.block);
-
-        #[cfg(feature = "net")]
-        axnet::init_network(all_devices.net);
-
-        #[cfg(feature = "display")]
-        axdisplay::init_display(all_devices.display);
-    }
-
-    #[cfg(feature = "smp")]
-    self::mp::start_secondary_cpus(cpu_id);
-
-    #[cfg(feature = "irq")]
-    {
-        info!("Initialize interrupt handlers...");
-        init_interrupt();
-    }
-
-    #[cfg(all(feature = "tls", not(feature = "multitask")))]
-    {
-        info!("Initialize thread local storage...");
-        init_tls();
-    }
-
-    ctor_bare::call_ctors();
-
-    info!("Primary CPU {} init OK.", cpu_id);
-    INITED_CPUS.fetch_add(1, Ordering::Release);
-
-    while !is_init_ok() {
-        core::hint::spin_loop();
-    }
-
-    unsafe { main() };
-
-    #[cfg(feature = "multitask")]
-    axtask::exit(0);
-    #[cfg(not(feature = "multitask"))]
-    {
-        debug!("main task exited: exit_code={}", 0);
-        axhal::misc::terminate();
-    }
+    logport::init();
+    axhal::console::set_console_sources(console_sources(
+        "AX_CONSOLE_OUTPUT",
+        option_env!("AX_CONSOLE_OUTPUT"),
+    ));
 }
 
 #[cfg(feature = "alloc")]
@@ -232,6 +317,138 @@ fn init_allocator() {
     }
 }
 
+/// Spawns the idle detector of runtime power management, which suspends the
+/// devices idle for 5 seconds.
+#[cfg(all(feature = "pm", feature = "multitask"))]
+fn init_pm() {
+    use core::time::Duration;
+
+    const PM_AUTOSUSPEND_DELAY: Duration = Duration::from_secs(5);
+    const PM_IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+    axtask::spawn_raw(
+        || loop {
+            axtask::sleep(PM_IDLE_INTERVAL);
+            axhal::pm::suspend_idle(PM_AUTOSUSPEND_DELAY.as_nanos() as u64);
+        },
+        "pm-idle".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+#[cfg(feature = "virtio-balloon")]
+fn init_balloon() {
+    use axdriver::virtio_balloon;
+
+    if !virtio_balloon::is_present() {
+        return;
+    }
+    virtio_balloon::update();
+    // Follows the target of the host every second, and deflates the balloon
+    // when memory runs low at most every `RECLAIM_INTERVAL`.
+    #[cfg(feature = "multitask")]
+    axtask::spawn_raw(
+        || {
+            const RECLAIM_INTERVAL: core::time::Duration = core::time::Duration::from_millis(100);
+            const UPDATE_PERIOD: u32 = 10;
+            loop {
+                for _ in 0..UPDATE_PERIOD {
+                    axtask::sleep(RECLAIM_INTERVAL);
+                    axalloc::global_allocator().reclaim();
+                }
+                virtio_balloon::update();
+            }
+        },
+        "virtio-balloon".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+/// Initializes A/B updates, and checks the watchdog periodically if the
+/// booted slot is on trial.
+#[cfg(feature = "update")]
+fn init_update(blk_devs: &mut axdriver::AxDeviceContainer<axdriver::AxBlockDevice>) {
+    axupdate::init_update(blk_devs);
+    if axupdate::is_on_trial() != Ok(true) {
+        return;
+    }
+    axtask::spawn_raw(
+        || loop {
+            axtask::sleep(core::time::Duration::from_secs(1));
+            axupdate::check_watchdog();
+        },
+        "update-watchdog".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+/// Parses the console sources set at build time by the variable `var`, a
+/// comma-separated list of `log` and `stdout`, or `all` or `none`.
+fn console_sources(var: &str, value: Option<&str>) -> axhal::console::ConsoleSource {
+    use axhal::console::ConsoleSource;
+
+    let value = value.unwrap_or_default();
+    let mut sources = ConsoleSource::empty();
+    for name in value.split(',').map(str::trim) {
+        sources |= match name {
+            "" | "all" => ConsoleSource::all(),
+            "none" => ConsoleSource::empty(),
+            "log" => ConsoleSource::LOG,
+            "stdout" => ConsoleSource::STDOUT,
+            _ => {
+                warn!("invalid {} {:?}, writing all output", var, value);
+                return ConsoleSource::all();
+            }
+        };
+    }
+    sources
+}
+
+#[cfg(feature = "fbcon")]
+fn init_fbcon() {
+    use axhal::fbcon::{self, Framebuffer};
+
+    let info = axdisplay::framebuffer_info();
+    let fb = Framebuffer {
+        base_vaddr: info.fb_base_vaddr,
+        width: info.width as usize,
+        height: info.height as usize,
+        stride: info.fb_size / 4 / info.height as usize,
+    };
+    // SAFETY: the framebuffer is mapped by the display driver, and the
+    // application should not draw to it when the console is shown on it.
+    let sources = console_sources("AX_FBCON_OUTPUT", option_env!("AX_FBCON_OUTPUT"));
+    unsafe { fbcon::init(fb, axdisplay::framebuffer_flush, sources) };
+    axtask::spawn_raw(
+        || loop {
+            axtask::sleep(core::time::Duration::from_millis(20));
+            fbcon::flush();
+        },
+        "fbcon".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+#[cfg(feature = "virtio-console")]
+fn init_virtio_console() {
+    use axdriver::virtio_console;
+    use axhal::console::{self, Console};
+
+    // The first device is used by the guest agent.
+    let first = if cfg!(feature = "guest-agent") { 1 } else { 0 };
+    for idx in first..virtio_console::num_devices() {
+        let dev = virtio_console::console_port(idx).unwrap();
+        let Some(port) = console::register_port(dev) else {
+            warn!("virtio-console: too many console ports");
+            break;
+        };
+        info!("VirtIO console {} is console port {}", idx, port);
+        if idx == first {
+            console::set_console(Console::port(port).unwrap());
+        }
+    }
+}
+
 #[cfg(feature = "irq")]
 fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
@@ -240,35 +457,118 @@ fn init_interrupt() {
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
+    /// The deadline of the next periodic tick.
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
-    fn update_timer() {
+    /// Advances the periodic tick, returns whether it is due.
+    fn update_tick() -> bool {
         let now_ns = axhal::time::monotonic_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
-        let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
-        if now_ns >= deadline {
-            deadline = now_ns + PERIODIC_INTERVAL_NANOS;
+        let deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
+        if now_ns < deadline {
+            return false;
+        }
+        let mut next_deadline = deadline + PERIODIC_INTERVAL_NANOS;
+        if next_deadline <= now_ns {
+            next_deadline = now_ns + PERIODIC_INTERVAL_NANOS;
         }
-        unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
+        unsafe { NEXT_DEADLINE.write_current_raw(next_deadline) };
+        true
+    }
+
+    fn update_timer() {
+        let deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
+        // The timer fires earlier if a timer event is due before the tick.
+        #[cfg(feature = "multitask")]
+        axtask::arm_timer(deadline);
+        #[cfg(not(feature = "multitask"))]
         axhal::time::set_oneshot_timer(deadline);
     }
 
     axlog::ax_println!("--------------irq init here---------------------");
 
     // 注册时钟中断
-    axhal::irq::register_handler(TIMER_IRQ_NUM, || {
+    axhal::irq::register_handler(TIMER_IRQ_NUM, &|_| {
+        if update_tick() {
+            #[cfg(feature = "multitask")]
+            axtask::on_timer_tick();
+        } else {
+            #[cfg(feature = "multitask")]
+            axtask::check_timer_events();
+        }
+        // The watchpoints set on another CPU are loaded here.
+        #[cfg(feature = "watch")]
+        axhal::watch::sync_this_cpu();
         update_timer();
-        #[cfg(feature = "multitask")]
-        axtask::on_timer_tick();
     });
 
+    init_irq_budget();
+    softirq::init();
+
     // Enable IRQs before starting app
     axhal::asm::enable_irqs();
     // axhal::arch::enable_irqs();
     axlog::ax_println!("--------------random stuff---------------------");
 }
 
+/// Enforces the maximum run time of the IRQ handlers set at build time by
+/// `AX_IRQ_BUDGET_US`, see [`axhal::irq::set_budget`].
+#[cfg(feature = "irq")]
+fn init_irq_budget() {
+    let Some(value) = option_env!("AX_IRQ_BUDGET_US").filter(|v| !v.is_empty()) else {
+        return;
+    };
+    let Ok(budget_us) = value.parse::<u64>() else {
+        warn!("invalid AX_IRQ_BUDGET_US {:?}", value);
+        return;
+    };
+    // The handlers overrunning the budget are deferred to a task.
+    let defer = cfg!(feature = "multitask") && option_env!("AX_IRQ_BUDGET_DEFER") == Some("y");
+    info!("IRQ handler budget: {}us (defer: {})", budget_us, defer);
+    axhal::irq::set_budget(Some(core::time::Duration::from_micros(budget_us)), defer);
+
+    #[cfg(feature = "multitask")]
+    if defer {
+        use axtask::WaitQueue;
+
+        static DEFERRED_WQ: WaitQueue = WaitQueue::new();
+
+        axhal::irq::set_defer_notifier(|| {
+            DEFERRED_WQ.notify_one(false);
+        });
+        axtask::spawn_raw(
+            || loop {
+                DEFERRED_WQ.wait_until(axhal::irq::deferred_pending);
+                axhal::irq::handle_deferred();
+            },
+            "irq-deferred".into(),
+            axconfig::TASK_STACK_SIZE,
+        );
+    }
+}
+
+/// Prints the task, the PC and the backtrace of a write to a range watched
+/// with [`axhal::watch`].
+#[cfg(feature = "watch")]
+fn report_watch_hit(hit: &axhal::watch::WatchHit) {
+    #[cfg(feature = "multitask")]
+    let task = axtask::current_may_uninit().map_or_else(|| "-".into(), |curr| curr.id_name());
+    #[cfg(not(feature = "multitask"))]
+    let task = "main";
+    ax_println!(
+        "watchpoint {}: {:#x} ({} bytes) written by task {} at {:#x}",
+        hit.index,
+        hit.watchpoint.addr,
+        hit.watchpoint.len,
+        task,
+        hit.pc
+    );
+    for (depth, addr) in hit.backtrace().enumerate() {
+        ax_println!("  #{} {:#x}", depth, addr);
+    }
+}
+
 #[cfg(all(feature = "tls", not(feature = "multitask")))]
 fn init_tls() {
     let main_tls = axhal::tls::TlsArea::alloc();
@@ -0,0 +1,219 @@
+//! Log streaming to a file on the mounted filesystem.
+//!
+//! When the `log-file` feature is enabled, log records are also appended to
+//! the file specified by the `AX_LOG_FILE` environment variable at build time
+//! (`/arceos.log` by default). When the file exceeds [`MAX_FILE_SIZE`], it is
+//! rotated to `<path>.1`, `<path>.1` to `<path>.2`, and so on, keeping at
+//! most [`MAX_ROTATED_FILES`] old files.
+//!
+//! Records are formatted into fixed buffers, queued in a lock-free ring, and
+//! written to the file periodically by a background task (or only when the
+//! log is flushed if `multitask` is disabled), and flushed at shutdown,
+//! including after a panic. The sink never allocates memory, takes locks nor
+//! writes the file itself, as records are logged by the allocator with its
+//! locks held, and by IRQ handlers.
+
+use alloc::format;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axfs::api::File;
+use axio::Write;
+use axring::MpscRing;
+use axsync::Mutex;
+
+/// The maximum size of the log file before it is rotated.
+pub const MAX_FILE_SIZE: u64 = 256 * 1024;
+
+/// The maximum number of rotated log files to keep.
+pub const MAX_ROTATED_FILES: usize = 3;
+
+/// Records are dropped if this many are not written yet.
+const MAX_RECORDS: usize = 256;
+
+/// Longer records are truncated.
+const MAX_RECORD_LEN: usize = 256;
+
+const LOG_FILE_PATH: &str = match option_env!("AX_LOG_FILE") {
+    Some(path) => path,
+    None => "/arceos.log",
+};
+
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open() -> axio::Result<Self> {
+        let file = File::options()
+            .append(true)
+            .create(true)
+            .open(LOG_FILE_PATH)?;
+        let size = file.metadata()?.len();
+        Ok(Self { file, size })
+    }
+
+    fn rotate(&mut self) -> axio::Result {
+        for i in (1..MAX_ROTATED_FILES).rev() {
+            let from = format!("{}.{}", LOG_FILE_PATH, i);
+            let to = format!("{}.{}", LOG_FILE_PATH, i + 1);
+            if axfs::api::metadata(&from).is_ok() {
+                axfs::api::rename(&from, &to)?;
+            }
+        }
+        self.file.flush()?;
+        axfs::api::rename(LOG_FILE_PATH, &format!("{}.1", LOG_FILE_PATH))?;
+        *self = Self::open()?;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> axio::Result {
+        if self.size > 0 && self.size + data.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        self.file.write_all(data)?;
+        self.file.flush()?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+}
+
+/// A fixed buffer records are formatted into.
+struct RecordBuf<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> RecordBuf<N> {
+    const fn new() -> Self {
+        Self {
+            data: [0; N],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl<const N: usize> fmt::Write for RecordBuf<N> {
+    /// Appends as much of `s` as fits, and fails if it does not all fit.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(N - self.len);
+        self.data[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        if len < s.len() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+/// The log file, and the records being written to it.
+struct FileState {
+    file: Option<LogFile>,
+    out: RecordBuf<{ MAX_RECORDS * MAX_RECORD_LEN }>,
+}
+
+struct FileSink {
+    /// The records not written yet, pushed by all the CPUs.
+    records: MpscRing<RecordBuf<MAX_RECORD_LEN>, MAX_RECORDS>,
+    dropped: AtomicUsize,
+    file: Mutex<FileState>,
+}
+
+impl FileSink {
+    /// Writes the buffered records to the file.
+    ///
+    /// Gives up if another flush is in progress, so it never blocks.
+    fn flush_buffer(&self) {
+        let Some(mut state) = self.file.try_lock() else {
+            return;
+        };
+        let FileState { file, out } = &mut *state;
+        let Some(file) = file.as_mut() else {
+            return;
+        };
+        // At most as many records as the ring holds, so they fit in `out`
+        // even if more are pushed meanwhile.
+        out.len = 0;
+        for record in core::iter::from_fn(|| self.records.pop()).take(MAX_RECORDS) {
+            let end = out.len + record.len;
+            out.data[out.len..end].copy_from_slice(record.as_bytes());
+            out.len = end;
+        }
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        let mut res = Ok(());
+        if dropped > 0 {
+            let mut msg = RecordBuf::<64>::new();
+            let _ = fmt::Write::write_fmt(
+                &mut msg,
+                format_args!("[log-file] {} records dropped\n", dropped),
+            );
+            res = file.write(msg.as_bytes());
+        }
+        if out.len > 0 {
+            res = res.and_then(|_| file.write(out.as_bytes()));
+        }
+        if let Err(e) = res {
+            ax_println!("failed to write log file {}: {:?}", LOG_FILE_PATH, e);
+        }
+    }
+}
+
+impl axlog::LogSink for FileSink {
+    fn write_record(&self, record: fmt::Arguments) {
+        let mut buf = RecordBuf::<MAX_RECORD_LEN>::new();
+        if fmt::Write::write_fmt(&mut buf, record).is_err() {
+            // Truncated, but still ended.
+            buf.data[MAX_RECORD_LEN - 1] = b'\n';
+        }
+        if self.records.push(buf).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {
+        self.flush_buffer();
+    }
+}
+
+static FILE_SINK: FileSink = FileSink {
+    records: MpscRing::new(),
+    dropped: AtomicUsize::new(0),
+    file: Mutex::new(FileState {
+        file: None,
+        out: RecordBuf::new(),
+    }),
+};
+
+#[linkme::distributed_slice(axhal::misc::SHUTDOWN_HOOKS)]
+fn flush_log_file() {
+    FILE_SINK.flush_buffer();
+}
+
+/// Opens the log file and starts writing log records to it.
+pub(crate) fn init() {
+    let file = match LogFile::open() {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("failed to open log file {}: {:?}", LOG_FILE_PATH, e);
+            return;
+        }
+    };
+    info!("Logging to file {}...", LOG_FILE_PATH);
+    FILE_SINK.file.lock().file = Some(file);
+    axlog::set_sink(&FILE_SINK);
+
+    #[cfg(feature = "multitask")]
+    axtask::spawn_raw(
+        || loop {
+            axtask::sleep(core::time::Duration::from_secs(1));
+            FILE_SINK.flush_buffer();
+        },
+        "log-file".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
@@ -0,0 +1,65 @@
+//! Log streaming to a secondary serial port.
+//!
+//! When the `AX_LOG_PORT` environment variable is set at build time to the
+//! number of a serial port (see [`axhal::console::Console::port`]), log
+//! records are also written to that port, without colors. This keeps the logs
+//! on a separate line while the console serves the interactive shell.
+//!
+//! Only one secondary destination of log records is supported, so the log
+//! file of the `log-file` feature replaces the port if both are enabled.
+
+use core::fmt;
+
+use axhal::console::Console;
+use kspin::SpinNoIrq;
+
+struct PortSink {
+    port: SpinNoIrq<Option<Console>>,
+}
+
+impl axlog::LogSink for PortSink {
+    fn write_record(&self, record: fmt::Arguments) {
+        let Some(port) = *self.port.lock() else {
+            return;
+        };
+        let _ = fmt::Write::write_fmt(&mut PortWriter(port), record);
+    }
+
+    fn flush(&self) {}
+}
+
+static PORT_SINK: PortSink = PortSink {
+    port: SpinNoIrq::new(None),
+};
+
+struct PortWriter(Console);
+
+impl fmt::Write for PortWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Initializes the serial port specified by `AX_LOG_PORT`, and starts writing
+/// log records to it.
+pub(crate) fn init() {
+    let Some(port) = option_env!("AX_LOG_PORT").filter(|s| !s.is_empty()) else {
+        return;
+    };
+    let console = match port.parse().ok().and_then(Console::port) {
+        Some(console) => console,
+        None => {
+            warn!(
+                "invalid log port {:?}, the platform has {} serial ports",
+                port,
+                Console::num_ports()
+            );
+            return;
+        }
+    };
+    console.init();
+    info!("Logging to serial port {}...", port);
+    *PORT_SINK.port.lock() = Some(console);
+    axlog::set_sink(&PORT_SINK);
+}
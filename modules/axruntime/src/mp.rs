@@ -33,6 +33,9 @@ pub fn start_secondary_cpus(primary_cpu_id: usize) {
 /// It is called from the bootstrapping code in [axhal].
 #[unsafe(no_mangle)]
 pub extern "C" fn rust_main_secondary(cpu_id: usize) -> ! {
+    #[cfg(feature = "harden")]
+    axhal::harden::init_secondary();
+
     ENTERED_CPUS.fetch_add(1, Ordering::Release);
     info!("Secondary CPU {:x} started.", cpu_id);
 
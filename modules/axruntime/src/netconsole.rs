@@ -0,0 +1,237 @@
+//! Console over the network, for boards without a serial line.
+//!
+//! When the `netconsole` feature is enabled, the console output is sent in
+//! UDP datagrams to the address set by `AX_NETCONSOLE` at build time, which
+//! is `10.0.2.2:6666` by default, i.e., the host of QEMU user networking.
+//! It can be received with `nc -ul 6666`.
+//!
+//! Input is accepted from one TCP connection at a time on port
+//! [`INPUT_PORT`], e.g., with `nc` or `telnet`, and read after that of the
+//! console device. Telnet commands are discarded.
+
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axhal::console::{self, ConsoleDriver, ConsoleSource};
+use axnet::{TcpSocket, UdpSocket};
+use kspin::SpinNoIrq;
+
+/// The TCP port accepting input.
+pub const INPUT_PORT: u16 = 2323;
+
+/// The destination of the output if `AX_NETCONSOLE` is not set.
+const DEFAULT_DEST: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 2, 2)), 6666);
+
+/// The maximum size of the output sent in one datagram.
+const MAX_DATAGRAM_SIZE: usize = 1024;
+
+/// The interval of sending the output when there is none.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const BUFFER_SIZE: usize = 4096;
+
+/// A queue of bytes, dropping the new bytes when it is full.
+struct Fifo {
+    buf: [u8; BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl Fifo {
+    const fn new() -> Self {
+        Self {
+            buf: [0; BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends bytes, returns the number of bytes dropped.
+    fn push(&mut self, bytes: &[u8]) -> usize {
+        for (i, &c) in bytes.iter().enumerate() {
+            if self.len == BUFFER_SIZE {
+                return bytes.len() - i;
+            }
+            self.buf[(self.head + self.len) % BUFFER_SIZE] = c;
+            self.len += 1;
+        }
+        0
+    }
+
+    fn pop(&mut self, bytes: &mut [u8]) -> usize {
+        let len = self.len.min(bytes.len());
+        for c in &mut bytes[..len] {
+            *c = self.buf[self.head];
+            self.head = (self.head + 1) % BUFFER_SIZE;
+        }
+        self.len -= len;
+        len
+    }
+}
+
+/// The console sink, queuing the output and the input for the tasks that
+/// send and receive them, as the network stack can not be used when the
+/// console is written to, e.g., by the logs of the network stack itself.
+struct NetConsole {
+    output: SpinNoIrq<Fifo>,
+    input: SpinNoIrq<Fifo>,
+}
+
+static NET_CONSOLE: NetConsole = NetConsole {
+    output: SpinNoIrq::new(Fifo::new()),
+    input: SpinNoIrq::new(Fifo::new()),
+};
+
+impl ConsoleDriver for NetConsole {
+    fn write(&self, bytes: &[u8]) {
+        self.output.lock().push(bytes);
+    }
+
+    fn try_read(&self, bytes: &mut [u8]) -> usize {
+        self.input.lock().pop(bytes)
+    }
+}
+
+/// Filters the input received from telnet clients.
+#[derive(Default)]
+struct TelnetFilter {
+    state: TelnetState,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum TelnetState {
+    #[default]
+    Data,
+    /// After a carriage return, which is followed by a line feed or a null.
+    Cr,
+    /// After `IAC`, the start of a command.
+    Iac,
+    /// After `WILL`, `WONT`, `DO` or `DONT`, followed by an option.
+    Option,
+    /// In a subnegotiation, ended by `IAC SE`.
+    Sub,
+    SubIac,
+}
+
+impl TelnetFilter {
+    const IAC: u8 = 255;
+    const SB: u8 = 250;
+    const SE: u8 = 240;
+
+    /// Removes the telnet commands from `bytes`, and the byte after each
+    /// carriage return, as it is the end of a line itself. Returns the
+    /// number of bytes left.
+    fn filter(&mut self, bytes: &mut [u8]) -> usize {
+        use TelnetState::*;
+
+        let mut len = 0;
+        for i in 0..bytes.len() {
+            let c = bytes[i];
+            let (keep, next) = match (self.state, c) {
+                (Data | Cr, Self::IAC) => (false, Iac),
+                (Cr, b'\n' | 0) => (false, Data),
+                (Data | Cr, b'\r') => (true, Cr),
+                (Data | Cr, _) => (true, Data),
+                // An escaped 0xff.
+                (Iac, Self::IAC) => (true, Data),
+                (Iac, Self::SB) => (false, Sub),
+                (Iac, 251..=254) => (false, Option),
+                (Iac | Option, _) => (false, Data),
+                (Sub, Self::IAC) => (false, SubIac),
+                (SubIac, Self::SE) => (false, Data),
+                (Sub | SubIac, _) => (false, Sub),
+            };
+            if keep {
+                bytes[len] = c;
+                len += 1;
+            }
+            self.state = next;
+        }
+        len
+    }
+}
+
+fn dest_addr() -> SocketAddr {
+    let Some(dest) = option_env!("AX_NETCONSOLE").filter(|s| !s.is_empty()) else {
+        return DEFAULT_DEST;
+    };
+    dest.parse().unwrap_or_else(|_| {
+        warn!(
+            "invalid netconsole address {:?}, using {}",
+            dest, DEFAULT_DEST
+        );
+        DEFAULT_DEST
+    })
+}
+
+fn send_output(dest: SocketAddr) {
+    let socket = UdpSocket::new();
+    if let Err(e) = socket.bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)) {
+        warn!("netconsole: failed to bind the UDP socket: {:?}", e);
+        return;
+    }
+    let mut buf = [0; MAX_DATAGRAM_SIZE];
+    loop {
+        let len = NET_CONSOLE.output.lock().pop(&mut buf);
+        if len == 0 {
+            axtask::sleep(POLL_INTERVAL);
+            continue;
+        }
+        // The output is dropped if it can not be sent.
+        let _ = socket.send_to(&buf[..len], dest);
+    }
+}
+
+fn receive_input() {
+    let listener = TcpSocket::new();
+    let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), INPUT_PORT);
+    if let Err(e) = listener.bind(addr).and_then(|_| listener.listen()) {
+        warn!(
+            "netconsole: failed to listen on port {}: {:?}",
+            INPUT_PORT, e
+        );
+        return;
+    }
+    loop {
+        let Ok(stream) = listener.accept() else {
+            continue;
+        };
+        if let Ok(peer) = stream.peer_addr() {
+            info!("netconsole: input from {}", peer);
+        }
+        let mut filter = TelnetFilter::default();
+        let mut buf = [0; 256];
+        while let Ok(len @ 1..) = stream.recv(&mut buf) {
+            let len = filter.filter(&mut buf[..len]);
+            let dropped = NET_CONSOLE.input.lock().push(&buf[..len]);
+            console::count_rx_dropped(dropped);
+            #[cfg(feature = "irq")]
+            console::notify_rx();
+        }
+        let _ = stream.shutdown();
+    }
+}
+
+/// Adds the network console as a console sink, and starts the tasks sending
+/// the output and receiving the input.
+pub(crate) fn init() {
+    let dest = dest_addr();
+    if console::add_sink(&NET_CONSOLE, ConsoleSource::all()).is_none() {
+        warn!("netconsole: too many console sinks");
+        return;
+    }
+    info!(
+        "Console output sent to udp:{}, input accepted on tcp port {}",
+        dest, INPUT_PORT
+    );
+    axtask::spawn_raw(
+        move || send_output(dest),
+        "netconsole-tx".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+    axtask::spawn_raw(
+        receive_input,
+        "netconsole-rx".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
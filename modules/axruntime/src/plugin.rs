@@ -0,0 +1,87 @@
+//! Link-time registration of optional components.
+//!
+//! Crates that want to hook into the boot process declare their entries with
+//! [`register_subsystem!`] or [`register_driver!`]. The entries are collected
+//! into a linker section, so linking the crate is enough to have it
+//! initialized; [`rust_main`](crate::rust_main) does not need to know about it.
+//!
+//! Entries are initialized in dependency order: an entry runs only after all
+//! entries named in its dependency list have run. The list can also name the
+//! initialization steps of the runtime, e.g., `"net"`, see [`startup`].
+//!
+//! [`startup`]: crate::startup
+//!
+//! # Examples
+//!
+//! ```ignore
+//! fn init_ramfs() { /* ... */ }
+//! fn init_shell_builtins() { /* ... */ }
+//!
+//! axruntime::register_subsystem!(RAMFS, "ramfs", [], init_ramfs);
+//! axruntime::register_subsystem!(BUILTINS, "builtins", ["ramfs"], init_shell_builtins);
+//! ```
+
+#[doc(hidden)]
+pub use linkme as __linkme;
+
+/// The kind of a registered entry, which determines when it is initialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginKind {
+    /// A device driver, initialized right after the built-in drivers.
+    Driver,
+    /// A subsystem (filesystem type, shell builtin, service, ...),
+    /// initialized after all drivers and interrupts are set up.
+    Subsystem,
+}
+
+/// An entry registered by [`register_subsystem!`] or [`register_driver!`].
+pub struct Plugin {
+    /// The unique name of the entry, used in dependency lists.
+    pub name: &'static str,
+    /// The kind of the entry.
+    pub kind: PluginKind,
+    /// Names of the entries that must be initialized before this one.
+    pub deps: &'static [&'static str],
+    /// The initialization function.
+    pub init: fn(),
+}
+
+/// All registered entries, collected at link time.
+#[linkme::distributed_slice]
+pub static PLUGINS: [Plugin];
+
+/// Registers a subsystem to be initialized at boot.
+///
+/// The arguments are the name of the generated static, the subsystem name,
+/// the list of names it depends on, and the initialization function.
+#[macro_export]
+macro_rules! register_subsystem {
+    ($ident:ident, $name:literal, [$($dep:literal),* $(,)?], $init:expr $(,)?) => {
+        $crate::__register_plugin!($ident, $name, Subsystem, [$($dep),*], $init);
+    };
+}
+
+/// Registers a device driver to be initialized at boot.
+///
+/// The arguments are the same as [`register_subsystem!`].
+#[macro_export]
+macro_rules! register_driver {
+    ($ident:ident, $name:literal, [$($dep:literal),* $(,)?], $init:expr $(,)?) => {
+        $crate::__register_plugin!($ident, $name, Driver, [$($dep),*], $init);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_plugin {
+    ($ident:ident, $name:literal, $kind:ident, [$($dep:literal),*], $init:expr) => {
+        #[$crate::plugin::__linkme::distributed_slice($crate::plugin::PLUGINS)]
+        #[linkme(crate = $crate::plugin::__linkme)]
+        static $ident: $crate::plugin::Plugin = $crate::plugin::Plugin {
+            name: $name,
+            kind: $crate::plugin::PluginKind::$kind,
+            deps: &[$($dep),*],
+            init: $init,
+        };
+    };
+}
@@ -0,0 +1,115 @@
+//! Softirqs: the work deferred by the IRQ handlers, run right after them.
+//!
+//! An IRQ handler doing more than acknowledging its device, e.g., taking the
+//! packets received by a NIC, or running the expired timers, can schedule a
+//! [`Tasklet`] doing the rest after all the handlers of the IRQ, so it does
+//! not delay the other IRQs for long, and without switching to a task as the
+//! threaded IRQs of `axtask` do.
+//!
+//! The tasklets run on the CPU they are scheduled on, in the order they are
+//! scheduled, with the IRQs disabled, and before the current task is
+//! preempted, if it must be. A tasklet scheduled several times before it
+//! runs only runs once. Scheduled by a task, it runs after the next IRQ, at
+//! the latest the next timer tick.
+//!
+//! ```ignore
+//! static RX_TASKLET: Tasklet = Tasklet::new(&|| NIC.receive());
+//!
+//! fn nic_irq_handler(_irq: usize) {
+//!     NIC.ack_irq();
+//!     RX_TASKLET.schedule();
+//! }
+//! ```
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+
+use axhal::cpu::this_cpu_id;
+
+/// The tasklets scheduled on each CPU, the last one first.
+static PENDING: [AtomicPtr<Tasklet>; axconfig::SMP] =
+    [const { AtomicPtr::new(ptr::null_mut()) }; axconfig::SMP];
+
+/// The number of tasklets run since boot.
+static RUN_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Work deferred by an IRQ handler, run after the IRQ handlers.
+pub struct Tasklet {
+    func: &'static (dyn Fn() + Send + Sync),
+    /// Whether it is scheduled and did not run yet.
+    scheduled: AtomicBool,
+    /// The tasklet scheduled before it on the same CPU.
+    next: AtomicPtr<Tasklet>,
+}
+
+impl Tasklet {
+    /// Creates a tasklet running `func`.
+    pub const fn new(func: &'static (dyn Fn() + Send + Sync)) -> Self {
+        Self {
+            func,
+            scheduled: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Whether the tasklet is scheduled and did not run yet.
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled.load(Ordering::Acquire)
+    }
+
+    /// Schedules the tasklet on the current CPU. Returns `false` if it is
+    /// already scheduled, and did not run yet.
+    ///
+    /// It can be called from IRQ handlers, as well as by the tasklet itself
+    /// to run again after the next IRQ.
+    pub fn schedule(&'static self) -> bool {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        // Pushed without disabling the IRQs, as it is still run if the task
+        // moves to another CPU in between.
+        let pending = &PENDING[this_cpu_id()];
+        let this = self as *const Self as *mut Self;
+        let mut head = pending.load(Ordering::Relaxed);
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+            match pending.compare_exchange_weak(head, this, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// Returns the number of tasklets run since boot, on all the CPUs.
+pub fn run_count() -> u64 {
+    RUN_COUNT.load(Ordering::Relaxed)
+}
+
+/// Runs the tasklets scheduled on the current CPU, after the IRQ handlers.
+fn run_pending() {
+    let mut head = PENDING[this_cpu_id()].swap(ptr::null_mut(), Ordering::Acquire);
+    if head.is_null() {
+        return;
+    }
+    // Reversed, so they run in the order they were scheduled.
+    let mut list = ptr::null_mut();
+    // SAFETY: the tasklets are static, and not scheduled again until they
+    // run, so they are only in this list.
+    while let Some(tasklet) = unsafe { head.as_ref() } {
+        head = tasklet.next.swap(list, Ordering::Relaxed);
+        list = tasklet as *const Tasklet as *mut Tasklet;
+    }
+    while let Some(tasklet) = unsafe { list.as_ref() } {
+        list = tasklet.next.load(Ordering::Relaxed);
+        // Cleared first, so it can schedule itself again.
+        tasklet.scheduled.store(false, Ordering::Release);
+        (tasklet.func)();
+        RUN_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs the tasklets after the IRQ handlers.
+pub(crate) fn init() {
+    axhal::irq::set_exit_hook(run_pending);
+}
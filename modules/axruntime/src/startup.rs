@@ -0,0 +1,439 @@
+//! The startup dependency graph of the runtime.
+//!
+//! The initialization of the runtime is split into steps, each naming the
+//! steps it depends on, e.g., the logger depends on the console, the drivers
+//! on the allocator, and the network on the drivers. A step runs once all of
+//! its dependencies ran. Among the steps ready to run, they run in the order
+//! they are listed in, then the drivers and the subsystems registered with
+//! [`register_driver!`](crate::register_driver) and
+//! [`register_subsystem!`](crate::register_subsystem) (see [`plugin`]).
+//!
+//! The registered entries can depend on the steps by their names as well:
+//! the drivers run after the `drivers` step, and the subsystems after all the
+//! steps and the drivers. A dependency on a step disabled at build time is
+//! ignored, but one on an unknown name, or a cycle, is a panic, which names
+//! the cycle.
+//!
+//! The time each step took is logged, and returned by [`step_timings`].
+//!
+//! [`plugin`]: crate::plugin
+
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+
+use crate::plugin::{PLUGINS, Plugin, PluginKind};
+
+/// The maximum number of steps, including the registered entries.
+const MAX_NODES: usize = 96;
+
+/// A step of the initialization of the runtime.
+struct Step {
+    name: &'static str,
+    deps: &'static [&'static str],
+    init: fn(&mut BootState),
+}
+
+/// The state passed from a step to the next ones.
+pub(crate) struct BootState {
+    pub cpu_id: usize,
+    pub dtb: usize,
+    /// The devices found by the `drivers` step, taken by the steps using
+    /// them.
+    #[cfg(feature = "axdriver")]
+    pub devices: Option<axdriver::AllDevices>,
+}
+
+impl BootState {
+    pub fn new(cpu_id: usize, dtb: usize) -> Self {
+        Self {
+            cpu_id,
+            dtb,
+            #[cfg(feature = "axdriver")]
+            devices: None,
+        }
+    }
+
+    #[cfg(feature = "axdriver")]
+    fn devices(&mut self) -> &mut axdriver::AllDevices {
+        self.devices.as_mut().expect("drivers are not initialized")
+    }
+}
+
+/// The names of all the steps, including those disabled at build time.
+const STEP_NAMES: &[&str] = &[
+    "console",
+    "logging",
+    "alloc",
+    "paging",
+    "platform",
+    "selftest",
+    "watch",
+    "scheduler",
+    "sysrq",
+    "drivers",
+    "update",
+    "kv",
+    "fs",
+    "log-file",
+    "net",
+    "netconsole",
+    "display",
+    "fbcon",
+    "virtio-balloon",
+    "virtio-console",
+    "guest-agent",
+    "smp",
+    "irq",
+    "pm",
+    "tls",
+];
+
+/// The steps enabled at build time, in the order they run when they are
+/// ready at the same time.
+static STEPS: &[Step] = &[
+    Step {
+        name: "console",
+        deps: &[],
+        init: |_| crate::print_banner(),
+    },
+    Step {
+        name: "logging",
+        deps: &["console"],
+        init: crate::init_logging,
+    },
+    #[cfg(feature = "alloc")]
+    Step {
+        name: "alloc",
+        deps: &["logging"],
+        init: |_| crate::init_allocator(),
+    },
+    #[cfg(feature = "paging")]
+    Step {
+        name: "paging",
+        deps: &["alloc"],
+        init: |_| axmm::init_memory_management(),
+    },
+    Step {
+        name: "platform",
+        deps: &["logging", "alloc", "paging"],
+        init: |_| crate::init_platform(),
+    },
+    #[cfg(feature = "selftest")]
+    Step {
+        name: "selftest",
+        deps: &["platform"],
+        init: |_| axhal::selftest::run(),
+    },
+    #[cfg(feature = "watch")]
+    Step {
+        name: "watch",
+        deps: &["platform"],
+        init: |_| axhal::watch::set_hit_handler(crate::report_watch_hit),
+    },
+    #[cfg(feature = "multitask")]
+    Step {
+        name: "scheduler",
+        deps: &["platform", "alloc"],
+        init: |_| {
+            axtask::init_scheduler();
+            // `Ctrl-C` on the console cancels the foreground task.
+            axhal::console::set_interrupt_handler(axtask::cancel_foreground);
+        },
+    },
+    #[cfg(feature = "sysrq")]
+    Step {
+        name: "sysrq",
+        deps: &["platform", "scheduler"],
+        init: |_| crate::sysrq::init(),
+    },
+    Step {
+        name: "drivers",
+        deps: &["platform", "alloc", "scheduler"],
+        init: |_state| {
+            #[cfg(feature = "axdriver")]
+            {
+                let devices = axdriver::init_drivers();
+                crate::sysinfo::add_devices(&devices);
+                _state.devices = Some(devices);
+            }
+        },
+    },
+    // Before the filesystem, which takes the first block device.
+    #[cfg(feature = "update")]
+    Step {
+        name: "update",
+        deps: &["drivers", "scheduler"],
+        init: |state| crate::init_update(&mut state.devices().block),
+    },
+    #[cfg(feature = "kv")]
+    Step {
+        name: "kv",
+        deps: &["drivers", "update"],
+        init: |state| axkv::init_kv(&mut state.devices().block),
+    },
+    #[cfg(feature = "fs")]
+    Step {
+        name: "fs",
+        deps: &["drivers", "update", "kv"],
+        init: |state| axfs::init_filesystems(core::mem::take(&mut state.devices().block)),
+    },
+    #[cfg(feature = "log-file")]
+    Step {
+        name: "log-file",
+        deps: &["fs"],
+        init: |_| crate::logfile::init(),
+    },
+    #[cfg(feature = "net")]
+    Step {
+        name: "net",
+        deps: &["drivers"],
+        init: |state| axnet::init_network(core::mem::take(&mut state.devices().net)),
+    },
+    #[cfg(feature = "netconsole")]
+    Step {
+        name: "netconsole",
+        deps: &["net", "scheduler"],
+        init: |_| crate::netconsole::init(),
+    },
+    #[cfg(feature = "display")]
+    Step {
+        name: "display",
+        deps: &["drivers"],
+        init: |state| axdisplay::init_display(core::mem::take(&mut state.devices().display)),
+    },
+    #[cfg(feature = "fbcon")]
+    Step {
+        name: "fbcon",
+        deps: &["display", "scheduler"],
+        init: |_| crate::init_fbcon(),
+    },
+    #[cfg(feature = "virtio-balloon")]
+    Step {
+        name: "virtio-balloon",
+        deps: &["drivers", "scheduler"],
+        init: |_| crate::init_balloon(),
+    },
+    #[cfg(feature = "virtio-console")]
+    Step {
+        name: "virtio-console",
+        deps: &["drivers"],
+        init: |_| crate::init_virtio_console(),
+    },
+    #[cfg(feature = "guest-agent")]
+    Step {
+        name: "guest-agent",
+        deps: &["drivers", "scheduler"],
+        init: |_| crate::guest_agent::init(),
+    },
+    #[cfg(feature = "smp")]
+    Step {
+        name: "smp",
+        deps: &["platform", "scheduler"],
+        init: |state| crate::mp::start_secondary_cpus(state.cpu_id),
+    },
+    #[cfg(feature = "irq")]
+    Step {
+        name: "irq",
+        deps: &["platform", "scheduler"],
+        init: |_| {
+            info!("Initialize interrupt handlers...");
+            crate::init_interrupt();
+        },
+    },
+    #[cfg(all(feature = "pm", feature = "multitask"))]
+    Step {
+        name: "pm",
+        deps: &["drivers", "scheduler"],
+        init: |_| crate::init_pm(),
+    },
+    #[cfg(all(feature = "tls", not(feature = "multitask")))]
+    Step {
+        name: "tls",
+        deps: &["platform", "alloc"],
+        init: |_| {
+            info!("Initialize thread local storage...");
+            crate::init_tls();
+        },
+    },
+];
+
+/// A node of the graph: a step, or a registered entry.
+#[derive(Clone, Copy)]
+enum Node {
+    Step(&'static Step),
+    Plugin(&'static Plugin),
+}
+
+impl Node {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Step(step) => step.name,
+            Self::Plugin(plugin) => plugin.name,
+        }
+    }
+
+    fn deps(self) -> &'static [&'static str] {
+        match self {
+            Self::Step(step) => step.deps,
+            Self::Plugin(plugin) => plugin.deps,
+        }
+    }
+
+    /// Whether it must run after `other`, which is a different node.
+    fn depends_on(self, other: Node) -> bool {
+        if self.deps().contains(&other.name()) {
+            return true;
+        }
+        match (self, other) {
+            (Self::Plugin(p), Self::Step(step)) => {
+                p.kind == PluginKind::Subsystem || step.name == "drivers"
+            }
+            (Self::Plugin(p), Self::Plugin(dep)) => {
+                p.kind == PluginKind::Subsystem && dep.kind == PluginKind::Driver
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The time each node took, in nanoseconds, in the order they ran.
+static TIMINGS: SpinNoIrq<([(&str, u64); MAX_NODES], usize)> =
+    SpinNoIrq::new(([("", 0); MAX_NODES], 0));
+
+/// Returns the nodes in the order they run when they are ready at the same
+/// time: the steps, then the drivers, then the subsystems.
+fn nodes() -> impl Iterator<Item = Node> {
+    let plugins = |kind| {
+        PLUGINS
+            .iter()
+            .filter(move |p| p.kind == kind)
+            .map(Node::Plugin)
+    };
+    STEPS
+        .iter()
+        .map(Node::Step)
+        .chain(plugins(PluginKind::Driver))
+        .chain(plugins(PluginKind::Subsystem))
+}
+
+/// Checks the names of the nodes and their dependencies.
+fn check(nodes: &[Node]) {
+    for (i, node) in nodes.iter().enumerate() {
+        if nodes[..i].iter().any(|n| n.name() == node.name()) {
+            panic!("startup step {:?} is defined twice", node.name());
+        }
+        for dep in node.deps() {
+            if *dep == node.name() {
+                panic!("{:?} depends on itself", dep);
+            }
+            match nodes.iter().find(|n| n.name() == *dep) {
+                None if STEP_NAMES.contains(dep) => {}
+                None => panic!("{:?} depends on unknown step {:?}", node.name(), dep),
+                Some(Node::Plugin(d))
+                    if d.kind == PluginKind::Subsystem
+                        && matches!(node, Node::Plugin(p) if p.kind == PluginKind::Driver) =>
+                {
+                    panic!(
+                        "driver {:?} cannot depend on subsystem {:?}",
+                        node.name(),
+                        dep
+                    )
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Finds a cycle among the nodes not done, each of which waits for another.
+fn find_cycle(nodes: &[Node], done: &[bool]) -> ! {
+    let waiting_for = |i: usize| {
+        (0..nodes.len())
+            .find(|&j| j != i && !done[j] && nodes[i].depends_on(nodes[j]))
+            .unwrap()
+    };
+    let mut visited = [false; MAX_NODES];
+    let mut i = done.iter().position(|&done| !done).unwrap();
+    while !visited[i] {
+        visited[i] = true;
+        i = waiting_for(i);
+    }
+    let start = i;
+    loop {
+        let next = waiting_for(i);
+        error!(
+            "  {} is waiting for {}",
+            nodes[i].name(),
+            nodes[next].name()
+        );
+        i = next;
+        if i == start {
+            break;
+        }
+    }
+    panic!(
+        "dependency cycle among the startup steps, through {:?}",
+        nodes[start].name()
+    );
+}
+
+/// Runs all the steps and the registered entries in dependency order.
+///
+/// # Panics
+///
+/// Panics if a node depends on an unknown name, or if the dependencies
+/// contain a cycle.
+pub(crate) fn run(state: &mut BootState) {
+    let mut all = [Node::Step(&STEPS[0]); MAX_NODES];
+    let mut len = 0;
+    for node in nodes() {
+        assert!(len < MAX_NODES, "too many startup steps");
+        all[len] = node;
+        len += 1;
+    }
+    let nodes = &all[..len];
+    check(nodes);
+
+    let mut done = [false; MAX_NODES];
+    let done = &mut done[..len];
+    while let Some(i) =
+        (0..len).find(|&i| !done[i] && (0..len).all(|j| done[j] || !nodes[i].depends_on(nodes[j])))
+    {
+        let node = nodes[i];
+        let start = axhal::time::monotonic_time_nanos();
+        match node {
+            Node::Step(step) => (step.init)(state),
+            Node::Plugin(plugin) => {
+                info!("Initialize {:?} {}...", plugin.kind, plugin.name);
+                (plugin.init)();
+                if plugin.kind == PluginKind::Driver {
+                    crate::sysinfo::add_driver(plugin.name);
+                }
+            }
+        }
+        let elapsed = axhal::time::monotonic_time_nanos() - start;
+        // The logger is not up before the `logging` step.
+        if node.name() != "console" {
+            debug!("startup step {} took {}us", node.name(), elapsed / 1000);
+        }
+        let mut timings = TIMINGS.lock();
+        let (entries, count) = &mut *timings;
+        entries[*count] = (node.name(), elapsed);
+        *count += 1;
+        done[i] = true;
+    }
+    if done.iter().any(|&done| !done) {
+        find_cycle(nodes, done);
+    }
+}
+
+/// Returns the startup steps and the registered entries which ran, with the
+/// time each took, in the order they ran.
+pub fn step_timings() -> impl Iterator<Item = (&'static str, Duration)> {
+    let (entries, count) = *TIMINGS.lock();
+    entries
+        .into_iter()
+        .take(count)
+        .map(|(name, nanos)| (name, Duration::from_nanos(nanos)))
+}
@@ -0,0 +1,289 @@
+//! System information collected during boot.
+//!
+//! At the end of boot, [`rust_main`](crate::rust_main) prints the information
+//! as a single line of JSON, e.g.:
+//!
+//! ```text
+//! {"event":"boot","arch":"x86_64","platform":"x86_64-qemu-q35","cpus":1,"cpus_online":1,...}
+//! ```
+//!
+//! so tools launching ArceOS instances can check them by parsing the serial
+//! output. The same data is returned by [`system_info`].
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::mem::{MemRegionFlags, memory_regions};
+use kspin::SpinNoIrq;
+
+use crate::json::JsonStr;
+
+/// The maximum number of drivers recorded in [`SystemInfo::drivers`].
+pub const MAX_DRIVERS: usize = 16;
+
+const MAX_NAME_LEN: usize = 32;
+
+/// Runtime features enabled at build time.
+pub(crate) const FEATURES: &[&str] = &[
+    #[cfg(feature = "alloc")]
+    "alloc",
+    #[cfg(feature = "paging")]
+    "paging",
+    #[cfg(feature = "uspace")]
+    "uspace",
+    #[cfg(feature = "irq")]
+    "irq",
+    #[cfg(feature = "multitask")]
+    "multitask",
+    #[cfg(feature = "smp")]
+    "smp",
+    #[cfg(feature = "tls")]
+    "tls",
+    #[cfg(feature = "fs")]
+    "fs",
+    #[cfg(feature = "kv")]
+    "kv",
+    #[cfg(feature = "update")]
+    "update",
+    #[cfg(feature = "net")]
+    "net",
+    #[cfg(feature = "netconsole")]
+    "netconsole",
+    #[cfg(feature = "display")]
+    "display",
+    #[cfg(feature = "fbcon")]
+    "fbcon",
+    #[cfg(feature = "rtc")]
+    "rtc",
+    #[cfg(feature = "selftest")]
+    "selftest",
+    #[cfg(feature = "earlycon")]
+    "earlycon",
+    #[cfg(feature = "harden")]
+    "harden",
+    #[cfg(feature = "pm")]
+    "pm",
+    #[cfg(feature = "watch")]
+    "watch",
+    #[cfg(feature = "sysrq")]
+    "sysrq",
+    #[cfg(feature = "log-file")]
+    "log-file",
+    #[cfg(feature = "virtio-balloon")]
+    "virtio-balloon",
+    #[cfg(feature = "virtio-console")]
+    "virtio-console",
+    #[cfg(feature = "guest-agent")]
+    "guest-agent",
+];
+
+/// A list of driver names with a fixed capacity.
+#[derive(Clone, Copy)]
+pub struct DriverList {
+    names: [[u8; MAX_NAME_LEN]; MAX_DRIVERS],
+    lens: [u8; MAX_DRIVERS],
+    len: usize,
+}
+
+impl DriverList {
+    const fn new() -> Self {
+        Self {
+            names: [[0; MAX_NAME_LEN]; MAX_DRIVERS],
+            lens: [0; MAX_DRIVERS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, name: &str) {
+        if self.len == MAX_DRIVERS {
+            warn!("too many drivers, {:?} is not recorded", name);
+            return;
+        }
+        // Truncate at a character boundary.
+        let mut len = name.len().min(MAX_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.names[self.len][..len].copy_from_slice(&name.as_bytes()[..len]);
+        self.lens[self.len] = len as u8;
+        self.len += 1;
+    }
+
+    /// Returns the number of drivers.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no drivers.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns an iterator over the driver names.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.len).map(|i| {
+            // SAFETY: the names are copied from `&str`s and truncated at
+            // character boundaries.
+            unsafe { core::str::from_utf8_unchecked(&self.names[i][..self.lens[i] as usize]) }
+        })
+    }
+}
+
+impl fmt::Debug for DriverList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A summary of the running system.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemInfo {
+    /// The target architecture.
+    pub arch: &'static str,
+    /// The platform name.
+    pub platform: &'static str,
+    /// The build mode (`release` or `debug`).
+    pub build_mode: &'static str,
+    /// The number of CPUs configured.
+    pub cpus: usize,
+    /// The number of CPUs that have finished initialization.
+    pub cpus_online: usize,
+    /// The total size of RAM in bytes.
+    pub total_memory: usize,
+    /// The size of free memory in bytes, i.e., the available bytes in the
+    /// global allocator, or the size of free memory regions if the `alloc`
+    /// feature is not enabled.
+    pub free_memory: usize,
+    /// The runtime features enabled at build time.
+    pub features: &'static [&'static str],
+    /// The names of the initialized drivers.
+    pub drivers: DriverList,
+    /// The time from power-on to the end of boot, or zero if the boot has
+    /// not finished.
+    pub boot_time: Duration,
+}
+
+fn write_json_list<'a>(
+    f: &mut fmt::Formatter,
+    items: impl Iterator<Item = &'a str>,
+) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+        write!(f, "{}", JsonStr(item))?;
+    }
+    f.write_str("]")
+}
+
+impl SystemInfo {
+    /// Returns a value that formats the information as one line of JSON.
+    pub fn json(&self) -> impl fmt::Display + '_ {
+        struct Json<'a>(&'a SystemInfo);
+
+        impl fmt::Display for Json<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let info = self.0;
+                write!(
+                    f,
+                    "{{\"event\":\"boot\",\"arch\":{},\"platform\":{},\"build_mode\":{},\
+                    \"cpus\":{},\"cpus_online\":{},\"total_memory\":{},\"free_memory\":{},\
+                    \"features\":",
+                    JsonStr(info.arch),
+                    JsonStr(info.platform),
+                    JsonStr(info.build_mode),
+                    info.cpus,
+                    info.cpus_online,
+                    info.total_memory,
+                    info.free_memory,
+                )?;
+                write_json_list(f, info.features.iter().copied())?;
+                f.write_str(",\"drivers\":")?;
+                write_json_list(f, info.drivers.iter())?;
+                write!(f, ",\"boot_time_us\":{}}}", info.boot_time.as_micros())
+            }
+        }
+
+        Json(self)
+    }
+}
+
+static DRIVERS: SpinNoIrq<DriverList> = SpinNoIrq::new(DriverList::new());
+static BOOT_TIME_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Records the name of an initialized driver.
+pub(crate) fn add_driver(name: &str) {
+    DRIVERS.lock().push(name);
+}
+
+/// Records the devices found by [`axdriver`].
+#[cfg(any(
+    feature = "fs",
+    feature = "kv",
+    feature = "update",
+    feature = "net",
+    feature = "display",
+    feature = "virtio-balloon",
+    feature = "guest-agent"
+))]
+#[allow(unused_imports, unused_variables)]
+pub(crate) fn add_devices(all_devices: &axdriver::AllDevices) {
+    use axdriver::prelude::BaseDriverOps;
+
+    #[cfg(feature = "net")]
+    for dev in all_devices.net.iter() {
+        add_driver(dev.device_name());
+    }
+    #[cfg(any(feature = "fs", feature = "kv", feature = "update"))]
+    for dev in all_devices.block.iter() {
+        add_driver(dev.device_name());
+    }
+    #[cfg(feature = "display")]
+    for dev in all_devices.display.iter() {
+        add_driver(dev.device_name());
+    }
+    #[cfg(feature = "virtio-balloon")]
+    if axdriver::virtio_balloon::is_present() {
+        add_driver("virtio-balloon");
+    }
+    #[cfg(feature = "guest-agent")]
+    if axdriver::virtio_console::is_present() {
+        add_driver("virtio-console");
+    }
+}
+
+/// Records the end of boot.
+pub(crate) fn boot_finished() {
+    let now = axhal::time::monotonic_time_nanos();
+    BOOT_TIME_NANOS.store(now, Ordering::Release);
+}
+
+/// Returns a summary of the running system.
+pub fn system_info() -> SystemInfo {
+    let total_memory = memory_regions()
+        .filter(|r| !r.flags.contains(MemRegionFlags::DEVICE))
+        .map(|r| r.size)
+        .sum();
+    #[cfg(feature = "alloc")]
+    let free_memory = axalloc::global_allocator().available_bytes();
+    #[cfg(not(feature = "alloc"))]
+    let free_memory = memory_regions()
+        .filter(|r| r.flags.contains(MemRegionFlags::FREE))
+        .map(|r| r.size)
+        .sum();
+
+    SystemInfo {
+        arch: axconfig::ARCH,
+        platform: axconfig::PLATFORM,
+        build_mode: option_env!("AX_MODE").unwrap_or(""),
+        cpus: axconfig::SMP,
+        cpus_online: crate::INITED_CPUS.load(Ordering::Acquire),
+        total_memory,
+        free_memory,
+        features: FEATURES,
+        drivers: *DRIVERS.lock(),
+        boot_time: Duration::from_nanos(BOOT_TIME_NANOS.load(Ordering::Acquire)),
+    }
+}
@@ -0,0 +1,75 @@
+//! The magic keys of the runtime on the console (see
+//! [`axhal::console::register_sysrq`]).
+//!
+//! - `t`: the tasks, with their stacks, with the `multitask` feature.
+//! - `m`: the memory statistics, with the `alloc` feature.
+//! - `r`: preempts the task running on the CPU receiving the key, with the
+//!   `multitask` feature and a preemptive scheduler.
+//!
+//! The task list and the memory statistics take the locks of the tasks and
+//! of the allocator, so they hang if the code interrupted holds them.
+
+use axhal::console::register_sysrq;
+
+pub(crate) fn init() {
+    #[cfg(feature = "multitask")]
+    {
+        register_sysrq(b't', "show the tasks", show_tasks);
+        register_sysrq(b'r', "preempt the current task", resched);
+    }
+    #[cfg(feature = "alloc")]
+    register_sysrq(b'm', "show the memory statistics", show_memory);
+}
+
+#[cfg(feature = "multitask")]
+fn show_tasks() {
+    ax_println!("SysRq: tasks");
+    for task in axtask::tasks() {
+        ax_print!(
+            "  {:<24} {:?}, priority {}, CPU time {:?}",
+            task.id_name(),
+            task.state(),
+            task.priority(),
+            task.cpu_time()
+        );
+        match (task.kernel_stack_top(), task.saved_stack_pointer()) {
+            (Some(top), Some(sp)) => ax_println!(
+                ", stack top {:#x}, sp {:#x} ({} bytes used)",
+                top.as_usize(),
+                sp,
+                top.as_usize().saturating_sub(sp)
+            ),
+            (Some(top), None) => ax_println!(", stack top {:#x}", top.as_usize()),
+            (None, _) => ax_println!(", boot stack"),
+        }
+    }
+}
+
+#[cfg(feature = "multitask")]
+fn resched() {
+    if axtask::resched_current() {
+        ax_println!("SysRq: preempting the current task");
+    } else {
+        ax_println!("SysRq: preemption is not enabled");
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn show_memory() {
+    let stats = axalloc::global_allocator().stats();
+    ax_println!("SysRq: memory");
+    ax_println!(
+        "  heap: {} of {} bytes used, peak {}, largest free {}",
+        stats.heap_used,
+        stats.heap_total,
+        stats.heap_peak,
+        stats.heap_largest_free
+    );
+    ax_println!(
+        "  pages: {} of {} used, peak {}, largest free {}",
+        stats.used_pages,
+        stats.total_pages,
+        stats.peak_pages,
+        stats.largest_free_pages
+    );
+}
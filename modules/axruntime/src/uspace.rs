@@ -0,0 +1,149 @@
+//! Handling of faults raised by user-space code.
+//!
+//! A fault in user mode is not a kernel bug, so it must not panic the whole
+//! kernel. User page faults are first offered to the
+//! [`USER_FAULT_HANDLERS`], e.g., a process layer that resolves them in the
+//! address space of the process, or delivers them to it as a signal. If no handler takes
+//! the fault, the faulting task is terminated with a report of the fault,
+//! and the rest of the system keeps running. The trap handler of `axcpu`
+//! does not pass the trap frame to page fault handlers, so the report only
+//! includes the faulting address and access.
+//!
+//! The other exceptions, such as illegal instructions, divide errors or
+//! misaligned accesses, are not dispatched by `axcpu`: its trap handler
+//! panics, with a dump of the registers of the trap frame. The panic handler
+//! recognizes these panics for the exceptions raised at user addresses, and
+//! terminates the faulting task instead, after calling the
+//! [`USER_EXCEPTION_HANDLERS`], with the dump as the report.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use axhal::mem::VirtAddr;
+use axhal::trap::{PAGE_FAULT, PageFaultFlags, register_trap_handler};
+
+/// The exit code of tasks terminated by a fault, as a shell reports a
+/// process killed by `SIGSEGV`.
+pub const FAULT_EXIT_CODE: i32 = 128 + 11;
+
+/// A fault raised by user-space code.
+#[derive(Debug, Clone, Copy)]
+pub struct UserFault {
+    /// The ID of the faulting task.
+    pub task_id: u64,
+    /// The faulting address.
+    pub vaddr: VirtAddr,
+    /// The access that caused the fault.
+    pub access_flags: PageFaultFlags,
+}
+
+/// Functions called on user faults, in the context of the faulting task.
+///
+/// A handler returns `true` if it took the fault, e.g., it resolved it or
+/// arranged a signal to be delivered, and the user code can resume.
+///
+/// Register a handler with `#[linkme::distributed_slice(USER_FAULT_HANDLERS)]`.
+#[linkme::distributed_slice]
+pub static USER_FAULT_HANDLERS: [fn(&UserFault) -> bool];
+
+/// Functions called by a task terminated by an unhandled user exception
+/// other than a page fault, with its exit code, before it exits, e.g., to exit
+/// its process instead. They may not return.
+///
+/// Register a handler with
+/// `#[linkme::distributed_slice(USER_EXCEPTION_HANDLERS)]`.
+#[linkme::distributed_slice]
+pub static USER_EXCEPTION_HANDLERS: [fn(i32)];
+
+#[register_trap_handler(PAGE_FAULT)]
+fn handle_page_fault(vaddr: VirtAddr, access_flags: PageFaultFlags, is_user: bool) -> bool {
+    if !is_user {
+        // Leave kernel faults to the default handler, which panics.
+        return false;
+    }
+    let curr = axtask::current();
+    let fault = UserFault {
+        task_id: curr.id().as_u64(),
+        vaddr,
+        access_flags,
+    };
+    if USER_FAULT_HANDLERS.iter().any(|handler| handler(&fault)) {
+        return true;
+    }
+    error!(
+        "task {} terminated: unhandled user page fault @ {:#x} ({:?})",
+        curr.id_name(),
+        fault.vaddr,
+        fault.access_flags,
+    );
+    drop(curr);
+    axtask::exit(FAULT_EXIT_CODE)
+}
+
+/// Terminates the current task if the panic is that of the trap handler of
+/// `axcpu` on an exception raised in user space, returns otherwise.
+///
+/// The exception is raised in user space if the faulting instruction, whose
+/// address follows `@` in the message (e.g., `Unhandled trap
+/// Exception(IllegalInstruction) @ 0x10078:`), is below the kernel.
+pub(crate) fn handle_panic(info: &PanicInfo) {
+    let in_trap_handler = info
+        .location()
+        .is_some_and(|loc| loc.file().contains("axcpu") && loc.file().ends_with("trap.rs"));
+    if !in_trap_handler {
+        return;
+    }
+    match exception_pc(info) {
+        Some(pc) if pc < axconfig::plat::PHYS_VIRT_OFFSET => {}
+        _ => return,
+    }
+    let Some(curr) = axtask::current_may_uninit() else {
+        return;
+    };
+    error!(
+        "task {} terminated: unhandled user exception: {}",
+        curr.id_name(),
+        info.message()
+    );
+    drop(curr);
+    for handler in USER_EXCEPTION_HANDLERS {
+        handler(FAULT_EXIT_CODE);
+    }
+    axtask::exit(FAULT_EXIT_CODE)
+}
+
+/// Returns the address following `@ ` in the panic message.
+fn exception_pc(info: &PanicInfo) -> Option<usize> {
+    /// The beginning of the message, which is followed by the trap frame.
+    struct Head {
+        buf: [u8; 128],
+        len: usize,
+    }
+
+    impl Write for Head {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let len = s.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+            self.len += len;
+            if len < s.len() {
+                // Stop formatting the rest.
+                return Err(fmt::Error);
+            }
+            Ok(())
+        }
+    }
+
+    let mut head = Head {
+        buf: [0; 128],
+        len: 0,
+    };
+    write!(head, "{}", info.message()).ok();
+    let head = &head.buf[..head.len];
+    let start = head.windows(4).position(|w| w == b"@ 0x")? + 4;
+    let hex = &head[start..];
+    let end = hex
+        .iter()
+        .position(|c| !c.is_ascii_hexdigit())
+        .unwrap_or(hex.len());
+    usize::from_str_radix(core::str::from_utf8(&hex[..end]).ok()?, 16).ok()
+}
@@ -0,0 +1,294 @@
+//! Bounded message queues with per-message priorities.
+//!
+//! The semantics follow POSIX message queues: each queue holds at most
+//! `max_msgs` messages of at most `msg_size` bytes, and a receive always
+//! returns the oldest message of the highest priority.
+
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use axerrno::{AxResult, ax_err};
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+
+/// The maximum priority (exclusive) of a message.
+pub const MQ_PRIO_MAX: u32 = 32;
+
+struct Message {
+    prio: u32,
+    seq: u64,
+    data: Vec<u8>,
+}
+
+impl Ord for Message {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first, then FIFO order within the same priority.
+        self.prio
+            .cmp(&other.prio)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Message {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Message {}
+
+struct MqInner {
+    msgs: BinaryHeap<Message>,
+    next_seq: u64,
+}
+
+/// Readiness of a [`MessageQueue`], returned by [`MessageQueue::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MqPollState {
+    /// At least one message can be received without blocking.
+    pub readable: bool,
+    /// At least one message can be sent without blocking.
+    pub writable: bool,
+}
+
+/// A bounded message queue with per-message priorities.
+///
+/// Senders block when the queue is full and receivers block when it is empty.
+/// The non-blocking variants ([`try_send`], [`try_receive`]) return
+/// [`AxError::WouldBlock`] instead.
+///
+/// [`try_send`]: MessageQueue::try_send
+/// [`try_receive`]: MessageQueue::try_receive
+/// [`AxError::WouldBlock`]: axerrno::AxError::WouldBlock
+pub struct MessageQueue {
+    inner: SpinNoIrq<MqInner>,
+    max_msgs: usize,
+    msg_size: usize,
+    recv_wq: WaitQueue,
+    send_wq: WaitQueue,
+}
+
+impl MessageQueue {
+    /// Creates an empty message queue that holds at most `max_msgs` messages,
+    /// each of which is at most `msg_size` bytes long.
+    pub fn new(max_msgs: usize, msg_size: usize) -> Self {
+        Self {
+            inner: SpinNoIrq::new(MqInner {
+                msgs: BinaryHeap::with_capacity(max_msgs),
+                next_seq: 0,
+            }),
+            max_msgs,
+            msg_size,
+            recv_wq: WaitQueue::new(),
+            send_wq: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the maximum number of messages in the queue.
+    pub const fn max_msgs(&self) -> usize {
+        self.max_msgs
+    }
+
+    /// Returns the maximum size of a message in bytes.
+    pub const fn msg_size(&self) -> usize {
+        self.msg_size
+    }
+
+    /// Returns the number of messages currently in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.lock().msgs.len()
+    }
+
+    /// Returns `true` if there are no messages in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the queue has reached its capacity.
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.max_msgs
+    }
+
+    /// Returns whether the queue is readable or writable.
+    pub fn poll(&self) -> MqPollState {
+        let len = self.len();
+        MqPollState {
+            readable: len > 0,
+            writable: len < self.max_msgs,
+        }
+    }
+
+    /// Sends a message with the given priority, without blocking.
+    ///
+    /// Returns [`WouldBlock`](axerrno::AxError::WouldBlock) if the queue is
+    /// full.
+    pub fn try_send(&self, data: &[u8], prio: u32) -> AxResult {
+        if data.len() > self.msg_size {
+            return ax_err!(InvalidInput, "message too long");
+        }
+        if prio >= MQ_PRIO_MAX {
+            return ax_err!(InvalidInput, "invalid message priority");
+        }
+        let mut inner = self.inner.lock();
+        if inner.msgs.len() >= self.max_msgs {
+            return ax_err!(WouldBlock);
+        }
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner.msgs.push(Message {
+            prio,
+            seq,
+            data: data.to_vec(),
+        });
+        drop(inner);
+        self.recv_wq.notify_one(true);
+        Ok(())
+    }
+
+    /// Sends a message with the given priority, blocking the current task
+    /// while the queue is full.
+    pub fn send(&self, data: &[u8], prio: u32) -> AxResult {
+        loop {
+            match self.try_send(data, prio) {
+                Err(axerrno::AxError::WouldBlock) => self.send_wq.wait_until(|| !self.is_full()),
+                res => return res,
+            }
+        }
+    }
+
+    /// Receives the highest-priority message into `buf`, without blocking.
+    ///
+    /// On success, returns the message length and its priority. `buf` must be
+    /// at least [`msg_size`](MessageQueue::msg_size) bytes long. Returns
+    /// [`WouldBlock`](axerrno::AxError::WouldBlock) if the queue is empty.
+    pub fn try_receive(&self, buf: &mut [u8]) -> AxResult<(usize, u32)> {
+        if buf.len() < self.msg_size {
+            return ax_err!(InvalidInput, "receive buffer too small");
+        }
+        let msg = match self.inner.lock().msgs.pop() {
+            Some(msg) => msg,
+            None => return ax_err!(WouldBlock),
+        };
+        self.send_wq.notify_one(true);
+        buf[..msg.data.len()].copy_from_slice(&msg.data);
+        Ok((msg.data.len(), msg.prio))
+    }
+
+    /// Receives the highest-priority message into `buf`, blocking the current
+    /// task while the queue is empty.
+    ///
+    /// On success, returns the message length and its priority.
+    pub fn receive(&self, buf: &mut [u8]) -> AxResult<(usize, u32)> {
+        loop {
+            match self.try_receive(buf) {
+                Err(axerrno::AxError::WouldBlock) => self.recv_wq.wait_until(|| !self.is_empty()),
+                res => return res,
+            }
+        }
+    }
+}
+
+static NAMED_QUEUES: SpinNoIrq<BTreeMap<String, Arc<MessageQueue>>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// Opens the named message queue, or creates it with the given capacity if
+/// `create` is `true` and no queue with that name exists.
+///
+/// When opening an existing queue, `max_msgs` and `msg_size` are ignored.
+pub fn mq_open(
+    name: &str,
+    create: bool,
+    max_msgs: usize,
+    msg_size: usize,
+) -> AxResult<Arc<MessageQueue>> {
+    let mut queues = NAMED_QUEUES.lock();
+    if let Some(mq) = queues.get(name) {
+        return Ok(mq.clone());
+    }
+    if !create {
+        return ax_err!(NotFound, "message queue not found");
+    }
+    if max_msgs == 0 || msg_size == 0 {
+        return ax_err!(InvalidInput, "invalid message queue attributes");
+    }
+    let mq = Arc::new(MessageQueue::new(max_msgs, msg_size));
+    queues.insert(String::from(name), mq.clone());
+    Ok(mq)
+}
+
+/// Removes the named message queue.
+///
+/// Tasks that still hold a reference to the queue can keep using it; the
+/// queue is freed when the last reference is dropped.
+pub fn mq_unlink(name: &str) -> AxResult {
+    match NAMED_QUEUES.lock().remove(name) {
+        Some(_) => Ok(()),
+        None => ax_err!(NotFound, "message queue not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageQueue, mq_open, mq_unlink};
+    use axerrno::AxError;
+    use axtask as thread;
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+
+    #[test]
+    fn priority_order() {
+        INIT.call_once(thread::init_scheduler);
+
+        let mq = MessageQueue::new(4, 8);
+        mq.try_send(b"low", 1).unwrap();
+        mq.try_send(b"high", 7).unwrap();
+        mq.try_send(b"low2", 1).unwrap();
+        mq.try_send(b"mid", 3).unwrap();
+        assert_eq!(mq.try_send(b"full", 0), Err(AxError::WouldBlock));
+
+        let mut buf = [0; 8];
+        let mut recv = || {
+            let (len, prio) = mq.try_receive(&mut buf).unwrap();
+            (buf[..len].to_vec(), prio)
+        };
+        assert_eq!(recv(), (b"high".to_vec(), 7));
+        assert_eq!(recv(), (b"mid".to_vec(), 3));
+        assert_eq!(recv(), (b"low".to_vec(), 1));
+        assert_eq!(recv(), (b"low2".to_vec(), 1));
+        assert_eq!(mq.try_receive(&mut buf), Err(AxError::WouldBlock));
+    }
+
+    #[test]
+    fn blocking_send_receive() {
+        INIT.call_once(thread::init_scheduler);
+
+        const NUM_MSGS: u32 = 100;
+        let mq = mq_open("/test-blocking", true, 2, 4).unwrap();
+
+        thread::spawn(|| {
+            let mq = mq_open("/test-blocking", false, 0, 0).unwrap();
+            for i in 0..NUM_MSGS {
+                mq.send(&i.to_le_bytes(), 0).unwrap();
+            }
+        });
+
+        let mut buf = [0; 4];
+        for i in 0..NUM_MSGS {
+            let (len, _) = mq.receive(&mut buf).unwrap();
+            assert_eq!(len, 4);
+            assert_eq!(u32::from_le_bytes(buf), i);
+        }
+        mq_unlink("/test-blocking").unwrap();
+        assert!(mq_open("/test-blocking", false, 0, 0).is_err());
+    }
+}
@@ -1,13 +1,19 @@
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 
 use kernel_guard::NoPreemptIrqSave;
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
 
 pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::task::{CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
@@ -16,23 +22,13 @@ pub use crate::wait_queue::WaitQueue;
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
+/// A weak reference to a task, which does not keep it alive.
+pub type WeakAxTaskRef = Weak<AxTask>;
+
 /// The wrapper type for [`cpumask::CpuMask`] with SMP configuration.
 pub type AxCpuMask = cpumask::CpuMask<{ axconfig::SMP }>;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "sched-rr")] {
-        const MAX_TIME_SLICE: usize = 5;
-        pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
-    } else if #[cfg(feature = "sched-cfs")] {
-        pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
-    } else {
-        // If no scheduler features are set, use FIFO as the default.
-        pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
-    }
-}
+pub(crate) use crate::sched::AxTask;
 
 #[cfg(feature = "preempt")]
 struct KernelGuardIfImpl;
@@ -76,7 +72,17 @@ pub fn init_scheduler() {
     #[cfg(feature = "irq")]
     crate::timers::init();
 
-    info!("  use {} scheduler.", Scheduler::scheduler_name());
+    let policy = crate::sched::policy();
+    if let Some(name) = axhal::cmdline::get("sched").filter(|&name| name != policy.name) {
+        warn!("  unknown scheduler {:?}, ignored.", name);
+    }
+    info!("  use {} scheduler.", policy.description);
+    if policy.name == "det" {
+        info!("  seed: {}", crate::sched::det_seed());
+    }
+    if policy.preemptive && !cfg!(feature = "preempt") {
+        warn!("  the tasks are not preempted without the `preempt` feature.");
+    }
 }
 
 /// Initializes the task scheduler for secondary CPUs.
@@ -99,6 +105,27 @@ pub fn on_timer_tick() {
     current_run_queue::<NoOp>().scheduler_timer_tick();
 }
 
+/// Processes the expired timer events, without ticking the scheduler.
+///
+/// It is called on timer interrupts that are not periodic ticks, e.g., the
+/// ones programmed for a timer event by [`arm_timer`].
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub fn check_timer_events() {
+    crate::timers::check_events();
+}
+
+/// Programs the timer of the current CPU to fire at `deadline` (in monotonic
+/// nanoseconds), or at the earliest pending timer event if it is earlier.
+///
+/// It should be called with IRQs disabled, usually by the timer interrupt
+/// handler to program the next periodic tick.
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub fn arm_timer(deadline: u64) {
+    crate::timers::arm_timer(deadline);
+}
+
 /// Adds the given task to the run queue, returns the task reference.
 pub fn spawn_task(task: TaskInner) -> AxTaskRef {
     let task_ref = task.into_arc();
@@ -129,6 +156,73 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Returns the tasks which have not been dropped yet, in the order of their
+/// creation. They include the ones which exited, but have not been joined or
+/// cleaned up.
+pub fn tasks() -> Vec<AxTaskRef> {
+    crate::task::all_tasks()
+}
+
+/// Moves the current task to a new task ID namespace, where it has the ID 1,
+/// and the tasks it creates afterwards the next IDs (see
+/// [`TaskInner::ns_id`]).
+///
+/// The tasks in a namespace only see the ones in the same namespace, see
+/// [`ns_tasks`]. The namespaces are not nested: the tasks outside any see all
+/// the tasks, by their global IDs.
+pub fn unshare_task_namespace() {
+    current().enter_namespace(Arc::new(crate::task::TaskNamespace::new()));
+}
+
+/// Returns the tasks seen from the task ID namespace of the current task, in
+/// the order of their creation, see [`tasks`].
+pub fn ns_tasks() -> Vec<AxTaskRef> {
+    let curr = current();
+    let mut tasks = tasks();
+    tasks.retain(|task| task.is_visible_from(&curr));
+    tasks
+}
+
+/// The task receiving the cancellation requests of the console, see
+/// [`set_foreground`].
+static FOREGROUND: SpinNoIrq<Option<AxTaskRef>> = SpinNoIrq::new(None);
+
+/// Sets the foreground task, i.e., the one cancelled by
+/// [`cancel_foreground`], or unsets it with `None`.
+///
+/// It is typically the shell, whose commands run in its task, or the task
+/// running the command in the foreground.
+pub fn set_foreground(task: Option<AxTaskRef>) {
+    // The old task is dropped after the lock is released.
+    let _old = core::mem::replace(&mut *FOREGROUND.lock(), task);
+}
+
+/// Returns the foreground task, see [`set_foreground`].
+pub fn foreground() -> Option<AxTaskRef> {
+    FOREGROUND.lock().clone()
+}
+
+/// Requests the foreground task to stop what it is doing (see
+/// [`TaskInner::cancel`]), and returns `false` if there is none.
+///
+/// It is called when `Ctrl-C` is received by the console, which may be in
+/// interrupt context.
+pub fn cancel_foreground() -> bool {
+    let fg = FOREGROUND.lock();
+    fg.as_ref().inspect(|task| task.cancel()).is_some()
+}
+
+/// Makes the current task be preempted at the next preemption point, e.g.,
+/// when the IRQ handler calling it returns, as if its time slice ran out, so
+/// a task hogging the CPU lets the others run.
+///
+/// Returns `false` if preemption is not enabled (the `preempt` feature).
+pub fn resched_current() -> bool {
+    #[cfg(feature = "preempt")]
+    current().set_preempt_pending(true);
+    cfg!(feature = "preempt")
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -182,6 +276,18 @@ pub fn yield_now() {
     current_run_queue::<NoPreemptIrqSave>().yield_current()
 }
 
+/// Current task gives up the CPU time to the given task, which runs next if it
+/// is ready on the run queue of this CPU, e.g., a task serving the requests
+/// the current task just queued. Otherwise, it yields like [`yield_now`].
+///
+/// The given task is given the rest of the time slice of the current task,
+/// with the preemptive scheduling policies.
+///
+/// Returns `true` if it switched to the given task.
+pub fn yield_to(task: &AxTaskRef) -> bool {
+    current_run_queue::<NoPreemptIrqSave>().yield_to_current(task)
+}
+
 /// Current task is going to sleep for the given duration.
 ///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
@@ -199,8 +305,40 @@ pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
 
+/// The functions called by the tasks when they exit, see [`on_exit`].
+static EXIT_HOOKS: SpinNoIrq<Vec<fn(TaskId)>> = SpinNoIrq::new(Vec::new());
+
+/// Registers a function called by each task when it exits, with its ID, e.g.,
+/// to release the resources it holds.
+///
+/// The function is called by the exiting task, which can still block.
+pub fn on_exit(hook: fn(TaskId)) {
+    EXIT_HOOKS.lock().push(hook);
+}
+
+/// The function called by the tasks over their CPU time limit, see
+/// [`set_cpu_limit_handler`].
+pub(crate) static CPU_LIMIT_HANDLER: LazyInit<fn()> = LazyInit::new();
+
+/// Sets the function called by the tasks which have exceeded their CPU time
+/// limit (see [`TaskInner::set_cpu_limit`]), at their next preemption point.
+///
+/// It is called with preemption enabled, but maybe with IRQs disabled, e.g.,
+/// on the return from the timer interrupt. If it returns, the task is
+/// preempted, and calls it again on the next timer tick.
+///
+/// It can only be set once.
+pub fn set_cpu_limit_handler(handler: fn()) {
+    CPU_LIMIT_HANDLER.init_once(handler);
+}
+
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
+    let hooks = EXIT_HOOKS.lock().clone();
+    let id = current().id();
+    for hook in hooks {
+        hook(id);
+    }
     current_run_queue::<NoPreemptIrqSave>().exit_current(exit_code)
 }
 
@@ -211,7 +349,9 @@ pub fn run_idle() -> ! {
     loop {
         yield_now();
         debug!("idle task: waiting for IRQs...");
-        #[cfg(feature = "irq")]
+        #[cfg(feature = "cpuidle")]
+        axhal::cpuidle::enter_idle();
+        #[cfg(all(feature = "irq", not(feature = "cpuidle")))]
         axhal::asm::wait_for_irqs();
     }
 }
@@ -0,0 +1,222 @@
+//! Timer futures and a minimal executor to run them.
+//!
+//! Timer expirations wake the futures directly from the timer interrupt
+//! handler, and the timer is programmed to fire at the deadline rather than
+//! at the next periodic tick, so [`sleep`] and [`timeout`] have a
+//! sub-millisecond accuracy. Nearby deadlines are coalesced to expire in one
+//! interrupt.
+//!
+//! Blocking functions, e.g., disk I/O, are run by [`spawn_blocking`] on a pool
+//! of tasks, not to stall the task polling the futures.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::{Pin, pin};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use axhal::time::{TimeValue, wall_time};
+use kspin::SpinNoIrq;
+
+use crate::WaitQueue;
+
+/// A future that completes at a deadline.
+///
+/// It is created by [`sleep`] and [`sleep_until`].
+pub struct Sleep {
+    deadline: TimeValue,
+    waker: Option<Waker>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if wall_time() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Only set a new timer if the waker changed since the last poll.
+        if !self.waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+            self.waker = Some(cx.waker().clone());
+            crate::timers::set_waker_wakeup(self.deadline, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Returns a future that completes after the given duration.
+pub fn sleep(dur: Duration) -> Sleep {
+    sleep_until(wall_time() + dur)
+}
+
+/// Returns a future that completes at the given deadline (in
+/// [`axhal::time::wall_time`]).
+pub fn sleep_until(deadline: TimeValue) -> Sleep {
+    Sleep {
+        deadline,
+        waker: None,
+    }
+}
+
+/// The error returned by [`Timeout`] if the future did not complete in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+/// A future that completes with the output of another future, or with
+/// [`Elapsed`] if it does not complete before a deadline.
+///
+/// It is created by [`timeout`].
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is pinned as long as `self` is, and never moved.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep).poll(cx).map(|_| Err(Elapsed))
+    }
+}
+
+/// Runs the future for at most the given duration.
+pub fn timeout<F: Future>(dur: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(dur),
+    }
+}
+
+struct TaskWaker {
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+/// Runs the future to completion on the current task.
+///
+/// The task is blocked while the future is pending, until it is woken.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let task_waker = Arc::new(TaskWaker {
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let waker = Waker::from(task_waker.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        task_waker
+            .wq
+            .wait_until(|| task_waker.woken.swap(false, Ordering::AcqRel));
+    }
+}
+
+/// The number of tasks running the functions of [`spawn_blocking`].
+const NUM_BLOCKING_WORKERS: usize = 2;
+
+type BlockingJob = Box<dyn FnOnce() + Send>;
+
+static BLOCKING_JOBS: SpinNoIrq<VecDeque<BlockingJob>> = SpinNoIrq::new(VecDeque::new());
+static BLOCKING_WQ: WaitQueue = WaitQueue::new();
+static BLOCKING_WORKERS_STARTED: AtomicBool = AtomicBool::new(false);
+
+fn blocking_worker() {
+    loop {
+        let job = BLOCKING_JOBS.lock().pop_front();
+        match job {
+            Some(job) => job(),
+            None => BLOCKING_WQ.wait_until(|| !BLOCKING_JOBS.lock().is_empty()),
+        }
+    }
+}
+
+struct BlockingState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// A future that completes with the output of a function run on another task.
+///
+/// It is created by [`spawn_blocking`]. Dropping it does not cancel the
+/// function, whose output is then discarded.
+pub struct Blocking<T> {
+    state: Arc<SpinNoIrq<BlockingState<T>>>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs a blocking function on a pool of tasks, returning a future that
+/// completes with its output.
+///
+/// The functions are run one at a time by each of the tasks of the pool, in
+/// the order they are spawned. The tasks are spawned on the first call.
+pub fn spawn_blocking<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    if !BLOCKING_WORKERS_STARTED.swap(true, Ordering::AcqRel) {
+        for i in 0..NUM_BLOCKING_WORKERS {
+            crate::spawn_raw(
+                blocking_worker,
+                format!("blocking-{i}"),
+                axconfig::TASK_STACK_SIZE,
+            );
+        }
+    }
+    let state = Arc::new(SpinNoIrq::new(BlockingState {
+        output: None,
+        waker: None,
+    }));
+    let job_state = state.clone();
+    BLOCKING_JOBS.lock().push_back(Box::new(move || {
+        let output = f();
+        let waker = {
+            let mut state = job_state.lock();
+            state.output = Some(output);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }));
+    BLOCKING_WQ.notify_one(false);
+    Blocking { state }
+}
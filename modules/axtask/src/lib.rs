@@ -2,7 +2,8 @@
 //!
 //! This module provides primitives for task management, including task
 //! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
-//! is configurable by cargo features.
+//! is selected on the boot command line by `sched=`, the default being
+//! configurable by cargo features.
 //!
 //! # Cargo Features
 //!
@@ -11,19 +12,32 @@
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!   APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!   [`WaitQueue::wait_timeout`].
+//!   [`WaitQueue::wait_timeout`]. With `multitask`, the timer futures in
+//!   [`future`] and the threaded IRQ handlers ([`request_threaded_irq`]) are
+//!   also available.
 //! - `preempt`: Enable preemptive scheduling.
-//! - `sched-fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
-//!   `multitask` feature if it is enabled. This feature is enabled by default,
-//!   and it can be overriden by other scheduler features.
-//! - `sched-rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
-//!   the `multitask` and `preempt` features if it is enabled.
-//! - `sched-cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
-//!   the `multitask` and `preempt` features if it is enabled.
+//! - `cpuidle`: The idle task enters the deepest idle state of the CPU (see
+//!   `axhal::cpuidle`).
+//! - `harden`: Give each task its own stack canary (without `smp`, as the tasks
+//!   on all CPUs share one then).
+//! - `sched-fifo`: Use the FIFO cooperative scheduler (`sched=fifo`) by
+//!   default. It also enables the `multitask` feature if it is enabled. This
+//!   feature is enabled by default, and it can be overriden by other scheduler
+//!   features.
+//! - `sched-rr`: Use the Round-robin preemptive scheduler (`sched=rr`) by
+//!   default. It also enables the `multitask` and `preempt` features if it is
+//!   enabled.
+//! - `sched-cfs`: Use the Completely Fair Scheduler (`sched=cfs`) by default.
+//!   It also enables the the `multitask` and `preempt` features if it is
+//!   enabled.
+//! - `sched-det`: Use a deterministic scheduler (`sched=det`) by default,
+//!   which interleaves the tasks in a pseudo-random order given by
+//!   `sched_seed` on the boot command line, or `AX_SCHED_SEED` at build time,
+//!   to reproduce concurrency bugs. It also enables the `multitask` and
+//!   `preempt` features if it is enabled.
 //!
-//! [1]: scheduler::FifoScheduler
-//! [2]: scheduler::RRScheduler
-//! [3]: scheduler::CFScheduler
+//! Whatever the default, any of the schedulers can be selected at boot, but
+//! the preemptive ones only preempt the tasks with the `preempt` feature.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -45,13 +59,21 @@ cfg_if::cfg_if! {
         mod task_ext;
         mod api;
         mod wait_queue;
+        mod sched;
 
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "irq")]
+        pub mod future;
+        #[cfg(feature = "irq")]
+        mod threaded_irq;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
         pub use self::api::{sleep, sleep_until, yield_now};
+        #[cfg(feature = "irq")]
+        #[doc(cfg(feature = "irq"))]
+        pub use self::threaded_irq::request_threaded_irq;
     } else {
         mod api_s;
         pub use self::api_s::{sleep, sleep_until, yield_now};
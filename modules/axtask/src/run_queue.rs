@@ -1,3 +1,4 @@
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use core::mem::MaybeUninit;
@@ -8,13 +9,13 @@ use alloc::sync::Weak;
 use kernel_guard::BaseGuard;
 use kspin::SpinRaw;
 use lazyinit::LazyInit;
-use scheduler::BaseScheduler;
 
 use axhal::cpu::this_cpu_id;
 
+use crate::sched::Scheduler;
 use crate::task::{CurrentTask, TaskState};
 use crate::wait_queue::WaitQueueGuard;
-use crate::{AxCpuMask, AxTaskRef, Scheduler, TaskInner, WaitQueue};
+use crate::{AxCpuMask, AxTaskRef, TaskInner, WaitQueue};
 
 macro_rules! percpu_static {
     ($(
@@ -36,7 +37,7 @@ percpu_static! {
     IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new(),
     /// Stores the weak reference to the previous task that is running on this CPU.
     #[cfg(feature = "smp")]
-    PREV_TASK: Weak<crate::AxTask> = Weak::new(),
+    PREV_TASK: Weak<crate::sched::AxTask> = Weak::new(),
 }
 
 /// An array of references to run queues, one for each CPU, indexed by cpu_id.
@@ -185,7 +186,7 @@ pub(crate) struct AxRunQueue {
     /// The core scheduler of this run queue.
     /// Since irq and preempt are preserved by the kernel guard hold by `AxRunQueueRef`,
     /// we just use a simple raw spin lock here.
-    scheduler: SpinRaw<Scheduler>,
+    scheduler: SpinRaw<Box<dyn Scheduler>>,
 }
 
 /// A reference to the run queue with specific guard.
@@ -237,7 +238,7 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
             self.inner.cpu_id
         );
         assert!(task.is_ready());
-        self.inner.scheduler.lock().add_task(task);
+        self.inner.scheduler.lock().enqueue(task);
     }
 
     /// Unblock one task by inserting it into the run queue.
@@ -273,7 +274,11 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     #[cfg(feature = "irq")]
     pub fn scheduler_timer_tick(&mut self) {
         let curr = &self.current_task;
-        if !curr.is_idle() && self.inner.scheduler.lock().task_tick(curr.as_task_ref()) {
+        if curr.is_idle() {
+            return;
+        }
+        // A task over its CPU time limit is preempted to call the handler.
+        if self.inner.scheduler.lock().tick(curr.as_task_ref()) || curr.cpu_limit_exceeded() {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
@@ -293,6 +298,50 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         self.inner.resched();
     }
 
+    /// Yield the current task to `target`, which runs next instead of the task picked by
+    /// the scheduler, if it is ready on this run queue. Otherwise, it yields like
+    /// [`yield_current`](Self::yield_current).
+    ///
+    /// Returns `true` if it switched to `target`.
+    pub fn yield_to_current(&mut self, target: &AxTaskRef) -> bool {
+        let curr = &self.current_task;
+        trace!("task yield to: {} -> {}", curr.id_name(), target.id_name());
+        assert!(curr.is_running());
+        if curr.ptr_eq(target) {
+            return false;
+        }
+
+        // Taken out of the scheduler under its lock, so it is not picked again, nor woken up
+        // or migrated meanwhile. Only a ready task queued on this run queue is found there:
+        // a task running, blocked, or queued on another CPU is left alone.
+        let target = {
+            let mut scheduler = self.inner.scheduler.lock();
+            #[cfg(feature = "smp")]
+            let on_cpu = target.on_cpu();
+            #[cfg(not(feature = "smp"))]
+            let on_cpu = false;
+            match target.state() {
+                TaskState::Ready if !on_cpu => scheduler.remove(target).inspect(|target| {
+                    // It runs for the rest of the time slice of the current task.
+                    scheduler.donate(curr.as_task_ref(), target)
+                }),
+                _ => None,
+            }
+        };
+        self.inner
+            .put_task_with_state(curr.clone(), TaskState::Running, false);
+        match target {
+            Some(target) => {
+                self.inner.switch_to(crate::current(), target);
+                true
+            }
+            None => {
+                self.inner.resched();
+                false
+            }
+        }
+    }
+
     /// Migrate the current task to a new run queue matching its CPU affinity and reschedule.
     /// This function will spawn a new `migration_task` to perform the migration, which will set
     /// current task to `Ready` state and select a proper run queue for it according to its CPU affinity,
@@ -363,7 +412,7 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             unsafe {
                 EXITED_TASKS.current_ref_mut_raw().clear();
             }
-            axhal::misc::terminate();
+            axhal::misc::exit(exit_code);
         } else {
             curr.set_state(TaskState::Exited);
 
@@ -435,10 +484,12 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     }
 
     pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.inner
-            .scheduler
-            .lock()
-            .set_priority(self.current_task.as_task_ref(), prio)
+        let curr = self.current_task.as_task_ref();
+        let ok = self.inner.scheduler.lock().set_priority(curr, prio);
+        if ok {
+            curr.set_priority(prio);
+        }
+        ok
     }
 }
 
@@ -450,8 +501,8 @@ impl AxRunQueue {
         // gc task should be pinned to the current CPU.
         gc_task.set_cpumask(AxCpuMask::one_shot(cpu_id));
 
-        let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
+        let mut scheduler = crate::sched::new_scheduler();
+        scheduler.enqueue(gc_task);
         Self {
             cpu_id,
             scheduler: SpinRaw::new(scheduler),
@@ -493,8 +544,12 @@ impl AxRunQueue {
                     core::hint::spin_loop();
                 }
             }
-            // TODO: priority
-            self.scheduler.lock().put_prev_task(task, preempt);
+            let mut scheduler = self.scheduler.lock();
+            if current_state == TaskState::Blocked {
+                scheduler.wake(task);
+            } else {
+                scheduler.put_prev(task, preempt);
+            }
             true
         } else {
             false
@@ -504,14 +559,10 @@ impl AxRunQueue {
     /// Core reschedule subroutine.
     /// Pick the next task to run and switch to it.
     fn resched(&mut self) {
-        let next = self
-            .scheduler
-            .lock()
-            .pick_next_task()
-            .unwrap_or_else(|| unsafe {
-                // Safety: IRQs must be disabled at this time.
-                IDLE_TASK.current_ref_raw().get_unchecked().clone()
-            });
+        let next = self.scheduler.lock().pick_next().unwrap_or_else(|| unsafe {
+            // Safety: IRQs must be disabled at this time.
+            IDLE_TASK.current_ref_raw().get_unchecked().clone()
+        });
         assert!(
             next.is_ready(),
             "next {} is not ready: {:?}",
@@ -539,6 +590,7 @@ impl AxRunQueue {
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        prev_task.account_switch(&next_task, axhal::time::monotonic_time_nanos());
 
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
@@ -560,6 +612,9 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            #[cfg(all(feature = "harden", not(feature = "smp")))]
+            axhal::harden::set_stack_canary(next_task.stack_canary());
+
             CurrentTask::set_current(prev_task, next_task);
 
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
@@ -574,8 +629,12 @@ impl AxRunQueue {
 
 fn gc_entry() {
     loop {
+        // Free the memory freed in interrupt context on this CPU.
+        axalloc::global_allocator().free_deferred();
+
         // Drop all exited tasks and recycle resources.
         let n = EXITED_TASKS.with_current(|exited_tasks| exited_tasks.len());
+        let mut kept = 0;
         for _ in 0..n {
             // Do not do the slow drops in the critical section.
             let task = EXITED_TASKS.with_current(|exited_tasks| exited_tasks.pop_front());
@@ -587,13 +646,20 @@ fn gc_entry() {
                     // Otherwise (e.g, `switch_to` is not compeleted, held by the
                     // joiner, etc), push it back and wait for them to drop first.
                     EXITED_TASKS.with_current(|exited_tasks| exited_tasks.push_back(task));
+                    kept += 1;
                 }
             }
         }
         // Note: we cannot block current task with preemption disabled,
         // use `current_ref_raw` to get the `WAIT_FOR_EXIT`'s reference here to avoid the use of `NoPreemptGuard`.
         // Since gc task is pinned to the current CPU, there is no affection if the gc task is preempted during the process.
-        unsafe { WAIT_FOR_EXIT.current_ref_raw() }.wait();
+        // The condition is checked under the lock of the wait queue, so the notifications sent
+        // since the tasks and blocks were taken above are not lost. The tasks pushed back are
+        // not waited for, or it would never sleep while one of them is held.
+        unsafe { WAIT_FOR_EXIT.current_ref_raw() }.wait_until(|| {
+            axalloc::global_allocator().deferred_count() > 0
+                || EXITED_TASKS.with_current(|exited_tasks| exited_tasks.len()) > kept
+        });
     }
 }
 
@@ -607,7 +673,7 @@ pub(crate) fn migrate_entry(migrated_task: AxTaskRef) {
         .inner
         .scheduler
         .lock()
-        .put_prev_task(migrated_task, false)
+        .enqueue(migrated_task)
 }
 
 /// Clear the `on_cpu` field of previous task running on this CPU.
@@ -624,6 +690,12 @@ pub(crate) unsafe fn clear_prev_task_on_cpu() {
 pub(crate) fn init() {
     let cpu_id = this_cpu_id();
 
+    // The gc task frees the memory queued on its CPU.
+    axalloc::global_allocator().set_deferred_notifier(|| {
+        // Safety: it is called with preemption disabled.
+        unsafe { WAIT_FOR_EXIT.current_ref_raw() }.notify_one(false);
+    });
+
     // Create the `idle` task (not current task).
     const IDLE_TASK_STACK_SIZE: usize = 4096;
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
@@ -0,0 +1,101 @@
+//! The Completely Fair Scheduler (CFS): the task which ran the least, in
+//! time weighted by its priority, runs next.
+//!
+//! The priority of a task is its nice value, from -20 (the highest) to 19,
+//! 0 by default. A task with a nice value lower by one gets about 25% more
+//! CPU time than another.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+
+use super::Scheduler;
+use crate::AxTaskRef;
+
+/// The weight of each nice value, from -20 to 19, as in Linux.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, // -20
+    29154, 23254, 18705, 14949, 11916, // -15
+    9548, 7620, 6100, 4904, 3906, // -10
+    3121, 2501, 1991, 1586, 1277, // -5
+    1024, 820, 655, 526, 423, // 0
+    335, 272, 215, 172, 137, // 5
+    110, 87, 70, 56, 45, // 10
+    36, 29, 23, 18, 15, // 15
+];
+
+/// The virtual run time of a timer tick at nice 0.
+const TICK_VRUNTIME: u64 = 1 << 20;
+
+fn weight(nice: isize) -> u64 {
+    NICE_TO_WEIGHT[(nice.clamp(-20, 19) + 20) as usize]
+}
+
+pub(crate) struct CFScheduler {
+    /// The ready tasks by their virtual run time, and their ID, so the keys
+    /// are unique.
+    ready: BTreeMap<(u64, u64), AxTaskRef>,
+    /// The virtual run time of the last task picked, which only increases.
+    /// The tasks added start there, so they do not run for long before the
+    /// others.
+    min_vruntime: u64,
+}
+
+impl CFScheduler {
+    pub const fn new() -> Self {
+        Self {
+            ready: BTreeMap::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    fn insert(&mut self, task: AxTaskRef) {
+        let key = (task.vruntime.load(Ordering::Acquire), task.id().as_u64());
+        self.ready.insert(key, task);
+    }
+}
+
+impl Scheduler for CFScheduler {
+    fn enqueue(&mut self, task: AxTaskRef) {
+        task.vruntime.fetch_max(self.min_vruntime, Ordering::AcqRel);
+        self.insert(task);
+    }
+
+    fn put_prev(&mut self, prev: AxTaskRef, _preempt: bool) {
+        self.insert(prev);
+    }
+
+    fn remove(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let key = (task.vruntime.load(Ordering::Acquire), task.id().as_u64());
+        if !self.ready.get(&key).is_some_and(|t| Arc::ptr_eq(t, task)) {
+            return None;
+        }
+        self.ready.remove(&key)
+    }
+
+    fn donate(&mut self, from: &AxTaskRef, to: &AxTaskRef) {
+        // It runs at least until it ran as long as `from`.
+        to.vruntime
+            .fetch_min(from.vruntime.load(Ordering::Acquire), Ordering::AcqRel);
+    }
+
+    fn pick_next(&mut self) -> Option<AxTaskRef> {
+        let ((vruntime, _), task) = self.ready.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+
+    fn tick(&mut self, current: &AxTaskRef) -> bool {
+        let delta = TICK_VRUNTIME * NICE_TO_WEIGHT[20] / weight(current.priority());
+        let vruntime = current.vruntime.fetch_add(delta, Ordering::AcqRel) + delta;
+        // Preempted once another task ran less.
+        self.ready
+            .first_key_value()
+            .is_some_and(|(&(min, _), _)| min < vruntime)
+    }
+
+    fn set_priority(&mut self, _task: &AxTaskRef, prio: isize) -> bool {
+        // The nice value is the priority of the task, set by the caller.
+        (-20..=19).contains(&prio)
+    }
+}
@@ -0,0 +1,93 @@
+//! A deterministic scheduler, to reproduce concurrency bugs.
+//!
+//! The next task is picked at random among the ready ones, and the current
+//! task is preempted after a random number of timer ticks, both from a
+//! pseudo-random sequence seeded with `sched_seed` on the boot command line,
+//! or else `AX_SCHED_SEED` at build time. So the interleaving of the tasks
+//! depends only on the seed, and an interleaving that triggers a bug is
+//! taken again by running with the same seed, while other seeds explore
+//! other interleavings.
+//!
+//! Only the scheduling decisions are deterministic. The whole run is if the
+//! timer ticks and the device interrupts also happen at the same points, i.e.,
+//! with one CPU, under QEMU with instruction counting (`ICOUNT=y`), which
+//! makes the time virtual.
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::Scheduler;
+use crate::AxTaskRef;
+
+/// The maximum number of timer ticks a task runs before it is preempted.
+const MAX_TIME_SLICE: u64 = 5;
+
+/// Returns the seed of the interleaving, set by `sched_seed` on the boot
+/// command line, or `AX_SCHED_SEED` at build time, or `0`.
+pub fn seed() -> u64 {
+    axhal::cmdline::get("sched_seed")
+        .or(option_env!("AX_SCHED_SEED"))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+/// A scheduler picking the tasks in a pseudo-random order, which is the same
+/// in all runs with the same seed.
+pub struct DetScheduler {
+    ready: VecDeque<AxTaskRef>,
+    /// The state of the pseudo-random sequence.
+    state: u64,
+    /// The timer ticks left before the current task is preempted.
+    ticks_left: u64,
+}
+
+impl DetScheduler {
+    /// Creates a scheduler with the seed set at build time.
+    pub fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+            state: seed(),
+            ticks_left: 0,
+        }
+    }
+
+    /// Returns the next number of the sequence (SplitMix64).
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Scheduler for DetScheduler {
+    fn enqueue(&mut self, task: AxTaskRef) {
+        self.ready.push_back(task);
+    }
+
+    fn put_prev(&mut self, prev: AxTaskRef, _preempt: bool) {
+        self.ready.push_back(prev);
+    }
+
+    fn remove(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let index = self.ready.iter().position(|t| Arc::ptr_eq(t, task))?;
+        self.ready.remove(index)
+    }
+
+    fn pick_next(&mut self) -> Option<AxTaskRef> {
+        if self.ready.is_empty() {
+            return None;
+        }
+        let index = (self.next_random() % self.ready.len() as u64) as usize;
+        self.ticks_left = 1 + self.next_random() % MAX_TIME_SLICE;
+        self.ready.swap_remove_back(index)
+    }
+
+    // No `donate`: `ticks_left` is not reset when the current task yields to
+    // another, which runs for the rest of them.
+
+    fn tick(&mut self, _current: &AxTaskRef) -> bool {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+        self.ticks_left == 0
+    }
+}
@@ -0,0 +1,43 @@
+//! The FIFO cooperative scheduler: the tasks run in the order they become
+//! ready, until they block or yield.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use super::Scheduler;
+use crate::AxTaskRef;
+
+pub(crate) struct FifoScheduler {
+    ready: VecDeque<AxTaskRef>,
+}
+
+impl FifoScheduler {
+    pub const fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for FifoScheduler {
+    fn enqueue(&mut self, task: AxTaskRef) {
+        self.ready.push_back(task);
+    }
+
+    fn put_prev(&mut self, prev: AxTaskRef, _preempt: bool) {
+        self.ready.push_back(prev);
+    }
+
+    fn remove(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let index = self.ready.iter().position(|t| Arc::ptr_eq(t, task))?;
+        self.ready.remove(index)
+    }
+
+    fn pick_next(&mut self) -> Option<AxTaskRef> {
+        self.ready.pop_front()
+    }
+
+    fn tick(&mut self, _current: &AxTaskRef) -> bool {
+        false
+    }
+}
@@ -0,0 +1,164 @@
+//! Scheduling policies, selected at boot.
+//!
+//! Each run queue picks the tasks to run with a [`Scheduler`], the policy
+//! being given on the boot command line (see [`axhal::cmdline`]) by
+//! `sched=fifo`, `sched=rr`, `sched=cfs` or `sched=det`, so trying another
+//! policy does not need another build. Without it, the policy is that of
+//! the `sched-*` feature enabled, or FIFO.
+//!
+//! The preemptive policies (all but FIFO) only preempt the tasks if the
+//! kernel is built with the `preempt` feature, which the `sched-rr`,
+//! `sched-cfs` and `sched-det` features enable.
+
+mod cfs;
+mod det;
+mod fifo;
+mod rr;
+
+use alloc::boxed::Box;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicIsize, AtomicU64};
+
+use crate::{AxTaskRef, TaskInner};
+
+pub(crate) use self::det::seed as det_seed;
+
+/// A scheduling policy, picking the task to run next among the ready tasks
+/// of a run queue.
+///
+/// Each run queue has its own instance, whose methods are called with the
+/// run queue locked, and the IRQs disabled.
+pub(crate) trait Scheduler: Send {
+    /// Adds a new task, or one moved from another run queue.
+    fn enqueue(&mut self, task: AxTaskRef);
+
+    /// Adds a task woken up after it blocked.
+    fn wake(&mut self, task: AxTaskRef) {
+        self.enqueue(task);
+    }
+
+    /// Adds back the task which was running, `preempt` if it is preempted
+    /// rather than it yielded.
+    fn put_prev(&mut self, prev: AxTaskRef, preempt: bool);
+
+    /// Removes a ready task, and returns it, or `None` if it is not in this
+    /// scheduler.
+    fn remove(&mut self, task: &AxTaskRef) -> Option<AxTaskRef>;
+
+    /// Removes the task to run next, and returns it, or `None` if there is
+    /// no ready task.
+    fn pick_next(&mut self) -> Option<AxTaskRef>;
+
+    /// Gives what is left of the time slice of `from`, which yields to `to`,
+    /// to the latter. `to` was just removed, and `from` is added back next.
+    fn donate(&mut self, _from: &AxTaskRef, _to: &AxTaskRef) {}
+
+    /// Accounts a timer tick to the running task, and returns whether it
+    /// must be preempted.
+    fn tick(&mut self, current: &AxTaskRef) -> bool;
+
+    /// Sets the priority of a task. Returns `false` if the policy has no
+    /// priorities, or `prio` is not valid.
+    fn set_priority(&mut self, _task: &AxTaskRef, _prio: isize) -> bool {
+        false
+    }
+}
+
+/// A task, with its state in the scheduling policies.
+pub struct AxTask {
+    inner: TaskInner,
+    /// The timer ticks left before the task is preempted (RR).
+    pub(crate) time_slice: AtomicIsize,
+    /// The virtual run time of the task (CFS).
+    pub(crate) vruntime: AtomicU64,
+}
+
+impl AxTask {
+    pub(crate) const fn new(inner: TaskInner) -> Self {
+        Self {
+            inner,
+            time_slice: AtomicIsize::new(0),
+            vruntime: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the task itself.
+    pub const fn inner(&self) -> &TaskInner {
+        &self.inner
+    }
+}
+
+impl Deref for AxTask {
+    type Target = TaskInner;
+
+    fn deref(&self) -> &TaskInner {
+        &self.inner
+    }
+}
+
+/// A scheduling policy which can be selected.
+pub(crate) struct Policy {
+    /// The name given on the command line.
+    pub name: &'static str,
+    /// The name shown in the logs.
+    pub description: &'static str,
+    /// Whether it preempts the tasks.
+    pub preemptive: bool,
+    new: fn() -> Box<dyn Scheduler>,
+}
+
+const POLICIES: &[Policy] = &[
+    Policy {
+        name: "fifo",
+        description: "FIFO",
+        preemptive: false,
+        new: || Box::new(fifo::FifoScheduler::new()),
+    },
+    Policy {
+        name: "rr",
+        description: "Round-robin",
+        preemptive: true,
+        new: || Box::new(rr::RRScheduler::new()),
+    },
+    Policy {
+        name: "cfs",
+        description: "CFS",
+        preemptive: true,
+        new: || Box::new(cfs::CFScheduler::new()),
+    },
+    Policy {
+        name: "det",
+        description: "Deterministic",
+        preemptive: true,
+        new: || Box::new(det::DetScheduler::new()),
+    },
+];
+
+/// The policy used if none is given on the command line.
+const DEFAULT_POLICY: &str = if cfg!(feature = "sched-rr") {
+    "rr"
+} else if cfg!(feature = "sched-cfs") {
+    "cfs"
+} else if cfg!(feature = "sched-det") {
+    "det"
+} else {
+    "fifo"
+};
+
+fn find_policy(name: &str) -> Option<&'static Policy> {
+    POLICIES.iter().find(|policy| policy.name == name)
+}
+
+/// Returns the policy selected on the command line, or the default one if
+/// none or an unknown one is.
+pub(crate) fn policy() -> &'static Policy {
+    axhal::cmdline::get("sched")
+        .and_then(find_policy)
+        .or_else(|| find_policy(DEFAULT_POLICY))
+        .unwrap()
+}
+
+/// Creates a scheduler of the selected policy, for a run queue.
+pub(crate) fn new_scheduler() -> Box<dyn Scheduler> {
+    (policy().new)()
+}
@@ -0,0 +1,59 @@
+//! The round-robin preemptive scheduler: the tasks run in turn, each for at
+//! most [`MAX_TIME_SLICE`] timer ticks.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+
+use super::Scheduler;
+use crate::AxTaskRef;
+
+/// The number of timer ticks a task runs before it is preempted.
+const MAX_TIME_SLICE: isize = 5;
+
+pub(crate) struct RRScheduler {
+    ready: VecDeque<AxTaskRef>,
+}
+
+impl RRScheduler {
+    pub const fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RRScheduler {
+    fn enqueue(&mut self, task: AxTaskRef) {
+        task.time_slice.store(MAX_TIME_SLICE, Ordering::Release);
+        self.ready.push_back(task);
+    }
+
+    fn put_prev(&mut self, prev: AxTaskRef, preempt: bool) {
+        // Preempted before the end of its time slice, e.g., by a task woken
+        // up, it runs again first for the rest of it.
+        if preempt && prev.time_slice.load(Ordering::Acquire) > 0 {
+            self.ready.push_front(prev);
+        } else {
+            self.enqueue(prev);
+        }
+    }
+
+    fn remove(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let index = self.ready.iter().position(|t| Arc::ptr_eq(t, task))?;
+        self.ready.remove(index)
+    }
+
+    fn donate(&mut self, from: &AxTaskRef, to: &AxTaskRef) {
+        let left = from.time_slice.swap(0, Ordering::AcqRel);
+        to.time_slice.fetch_add(left.max(0), Ordering::AcqRel);
+    }
+
+    fn pick_next(&mut self) -> Option<AxTaskRef> {
+        self.ready.pop_front()
+    }
+
+    fn tick(&mut self, current: &AxTaskRef) -> bool {
+        current.time_slice.fetch_sub(1, Ordering::AcqRel) <= 1
+    }
+}
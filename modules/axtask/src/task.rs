@@ -1,6 +1,9 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use core::any::{Any, TypeId};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicIsize, AtomicU8, AtomicU64, Ordering};
+use core::time::Duration;
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
 #[cfg(feature = "preempt")]
@@ -16,6 +19,36 @@ use axhal::tls::TlsArea;
 use crate::task_ext::AxTaskExt;
 use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
 
+/// The tasks which have not been dropped, in the order of their creation.
+static TASKS: SpinNoIrq<Vec<Weak<AxTask>>> = SpinNoIrq::new(Vec::new());
+
+/// Returns the tasks which have not been dropped yet, in the order of their
+/// creation, including the ones which exited.
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    TASKS.lock().iter().filter_map(Weak::upgrade).collect()
+}
+
+/// A task ID namespace, in which the tasks are numbered from 1, see
+/// [`unshare_task_namespace`](crate::unshare_task_namespace).
+///
+/// It is carried as a task-local value, so the tasks created by a task in a
+/// namespace are in the same one.
+pub(crate) struct TaskNamespace {
+    next_id: AtomicU64,
+}
+
+impl TaskNamespace {
+    pub(crate) const fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn alloc_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
@@ -23,7 +56,7 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
     /// Task is running on some CPU.
     Running = 1,
     /// Task is ready to run on some scheduler's ready queue.
@@ -69,12 +102,34 @@ pub struct TaskInner {
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    /// The priority last set, see [`crate::set_priority`].
+    priority: AtomicIsize,
+    /// The CPU time consumed before the task was last switched to, in
+    /// nanoseconds.
+    cpu_time_ns: AtomicU64,
+    /// The monotonic time when the task was last switched to, in nanoseconds.
+    switch_in_ns: AtomicU64,
+    /// The CPU time the task can consume, in nanoseconds, or `u64::MAX`, see
+    /// [`set_cpu_limit`](Self::set_cpu_limit).
+    cpu_limit_ns: AtomicU64,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
+    /// The task-local values, by type.
+    locals: SpinNoIrq<BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+    /// The ID in the task ID namespace, or 0 outside any.
+    ns_id: AtomicU64,
+    /// Whether the task is requested to stop what it is doing, see
+    /// [`cancel`](Self::cancel).
+    cancelled: AtomicBool,
 
     #[cfg(feature = "tls")]
     tls: TlsArea,
+
+    /// The stack canary of the task, switched to with the task.
+    #[cfg(all(feature = "harden", not(feature = "smp")))]
+    stack_canary: usize,
 }
 
 impl TaskId {
@@ -120,6 +175,13 @@ impl TaskInner {
         #[cfg(not(feature = "tls"))]
         let tls = VirtAddr::from(0);
 
+        if let Some(curr) = crate::current_may_uninit() {
+            t.locals = SpinNoIrq::new(curr.locals.lock().clone());
+        }
+        if let Some(ns) = t.local::<TaskNamespace>() {
+            t.ns_id = AtomicU64::new(ns.alloc_id());
+        }
+
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
@@ -134,6 +196,48 @@ impl TaskInner {
         self.id
     }
 
+    /// Gets the ID of the task in its task ID namespace (see
+    /// [`unshare_task_namespace`](crate::unshare_task_namespace)), the same
+    /// as [`id`](Self::id) outside any.
+    pub fn ns_id(&self) -> u64 {
+        match self.ns_id.load(Ordering::Relaxed) {
+            0 => self.id.as_u64(),
+            id => id,
+        }
+    }
+
+    /// Whether the task is seen from the task ID namespace of `other`, i.e.,
+    /// if `other` is in no namespace, or both are in the same one.
+    pub fn is_visible_from(&self, other: &TaskInner) -> bool {
+        let Some(other_ns) = other.local::<TaskNamespace>() else {
+            return true;
+        };
+        self.local::<TaskNamespace>()
+            .is_some_and(|ns| Arc::ptr_eq(&ns, &other_ns))
+    }
+
+    /// Requests the task to stop what it is doing, e.g., when `Ctrl-C` is
+    /// pressed while it is the foreground task (see
+    /// [`set_foreground`](crate::set_foreground)).
+    ///
+    /// It is only a request: the task checks it with
+    /// [`is_cancelled`](Self::is_cancelled) where it can stop, and clears it
+    /// with [`clear_cancelled`](Self::clear_cancelled) once handled.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether the task is requested to stop, see [`cancel`](Self::cancel).
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Clears the cancellation request of the task, and returns whether it
+    /// was cancelled.
+    pub fn clear_cancelled(&self) -> bool {
+        self.cancelled.swap(false, Ordering::AcqRel)
+    }
+
     /// Gets the name of the task.
     pub fn name(&self) -> &str {
         self.name.as_str()
@@ -178,6 +282,21 @@ impl TaskInner {
         }
     }
 
+    /// Returns the task-local value of type `T` of the task, if it is set.
+    ///
+    /// A task starts with the task-local values of the task creating it,
+    /// shared with it. Setting one with [`set_local`](Self::set_local) then
+    /// only changes it for the task.
+    pub fn local<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let value = self.locals.lock().get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+
+    /// Sets the task-local value of type `T` of the task.
+    pub fn set_local<T: Any + Send + Sync>(&self, value: Arc<T>) {
+        self.locals.lock().insert(TypeId::of::<T>(), value);
+    }
+
     /// Returns a mutable reference to the task context.
     #[inline]
     pub const fn ctx_mut(&mut self) -> &mut TaskContext {
@@ -193,6 +312,63 @@ impl TaskInner {
         }
     }
 
+    /// Returns the stack pointer saved when the task was last switched out,
+    /// or `None` if it is running.
+    pub fn saved_stack_pointer(&self) -> Option<usize> {
+        if self.is_running() {
+            return None;
+        }
+        // SAFETY: the context is only written when the task is switched out.
+        let ctx = unsafe { &*self.ctx_mut_ptr() };
+        #[cfg(target_arch = "x86_64")]
+        let sp = ctx.rsp as usize;
+        #[cfg(not(target_arch = "x86_64"))]
+        let sp = ctx.sp as usize;
+        Some(sp)
+    }
+
+    /// Returns the state of the task.
+    #[inline]
+    pub fn state(&self) -> TaskState {
+        self.state.load(Ordering::Acquire).into()
+    }
+
+    /// Returns the priority of the task, as last set by
+    /// [`set_priority`](crate::set_priority), or 0.
+    pub fn priority(&self) -> isize {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    /// Returns the CPU time consumed by the task, i.e., the total time it has
+    /// been running.
+    pub fn cpu_time(&self) -> Duration {
+        let mut nanos = self.cpu_time_ns.load(Ordering::Acquire);
+        if self.is_running() {
+            let now = axhal::time::monotonic_time_nanos();
+            nanos += now.saturating_sub(self.switch_in_ns.load(Ordering::Acquire));
+        }
+        Duration::from_nanos(nanos)
+    }
+
+    /// Sets the CPU time the task can consume, or removes the limit with
+    /// `None`.
+    ///
+    /// Once the task exceeds it, it is preempted on the next timer tick, or
+    /// as soon as it is switched to, and it calls the handler set by
+    /// [`set_cpu_limit_handler`](crate::set_cpu_limit_handler) at the
+    /// preemption point, e.g., to exit. It needs the `preempt` feature.
+    pub fn set_cpu_limit(&self, limit: Option<Duration>) {
+        let limit_ns = limit.map_or(u64::MAX, |limit| limit.as_nanos() as u64);
+        self.cpu_limit_ns.store(limit_ns, Ordering::Release);
+    }
+
+    /// Whether the task has consumed its CPU time limit, see
+    /// [`set_cpu_limit`](Self::set_cpu_limit).
+    pub fn cpu_limit_exceeded(&self) -> bool {
+        let limit_ns = self.cpu_limit_ns.load(Ordering::Acquire);
+        limit_ns != u64::MAX && self.cpu_time().as_nanos() as u64 >= limit_ns
+    }
+
     /// Gets the cpu affinity mask of the task.
     ///
     /// Returns the cpu affinity mask of the task in type [`AxCpuMask`].
@@ -234,11 +410,20 @@ impl TaskInner {
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            priority: AtomicIsize::new(0),
+            cpu_time_ns: AtomicU64::new(0),
+            switch_in_ns: AtomicU64::new(0),
+            cpu_limit_ns: AtomicU64::new(u64::MAX),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
+            locals: SpinNoIrq::new(BTreeMap::new()),
+            ns_id: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
             #[cfg(feature = "tls")]
             tls: TlsArea::alloc(),
+            #[cfg(all(feature = "harden", not(feature = "smp")))]
+            stack_canary: axhal::harden::new_stack_canary(),
         }
     }
 
@@ -255,6 +440,11 @@ impl TaskInner {
         t.is_init = true;
         #[cfg(feature = "smp")]
         t.set_on_cpu(true);
+        // The code running is entered with the current canary.
+        #[cfg(all(feature = "harden", not(feature = "smp")))]
+        {
+            t.stack_canary = axhal::harden::stack_canary();
+        }
         if t.name == "idle" {
             t.is_idle = true;
         }
@@ -262,12 +452,21 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let task = Arc::new(AxTask::new(self));
+        let mut tasks = TASKS.lock();
+        tasks.retain(|task| task.strong_count() > 0);
+        tasks.push(Arc::downgrade(&task));
+        task
     }
 
-    #[inline]
-    pub(crate) fn state(&self) -> TaskState {
-        self.state.load(Ordering::Acquire).into()
+    /// Moves the task to a new task ID namespace.
+    pub(crate) fn enter_namespace(&self, ns: Arc<TaskNamespace>) {
+        self.ns_id.store(ns.alloc_id(), Ordering::Relaxed);
+        self.set_local(ns);
+    }
+
+    pub(crate) fn set_priority(&self, prio: isize) {
+        self.priority.store(prio, Ordering::Relaxed);
     }
 
     #[inline]
@@ -295,6 +494,20 @@ impl TaskInner {
         matches!(self.state(), TaskState::Running)
     }
 
+    /// Accounts the CPU time of a switch from this task to `next` at `now`.
+    ///
+    /// If `next` has exceeded its CPU time limit, it is preempted at its first
+    /// preemption point, where it calls the CPU limit handler.
+    pub(crate) fn account_switch(&self, next: &TaskInner, now: u64) {
+        let ran = now.saturating_sub(self.switch_in_ns.load(Ordering::Acquire));
+        self.cpu_time_ns.fetch_add(ran, Ordering::AcqRel);
+        next.switch_in_ns.store(now, Ordering::Release);
+        #[cfg(feature = "preempt")]
+        if next.cpu_limit_exceeded() {
+            next.set_preempt_pending(true);
+        }
+    }
+
     #[inline]
     pub(crate) fn is_ready(&self) -> bool {
         matches!(self.state(), TaskState::Ready)
@@ -378,6 +591,12 @@ impl TaskInner {
         use kernel_guard::NoPreemptIrqSave;
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
+            // The handler may not return, e.g., if it exits the task.
+            if curr.cpu_limit_exceeded() {
+                if let Some(handler) = crate::api::CPU_LIMIT_HANDLER.get() {
+                    handler();
+                }
+            }
             // Note: if we want to print log msg during `preempt_resched`, we have to
             // disable preemption here, because the axlog may cause preemption.
             let mut rq = crate::current_run_queue::<NoPreemptIrqSave>();
@@ -398,6 +617,12 @@ impl TaskInner {
         self.ctx.get()
     }
 
+    #[cfg(all(feature = "harden", not(feature = "smp")))]
+    #[inline]
+    pub(crate) const fn stack_canary(&self) -> usize {
+        self.stack_canary
+    }
+
     /// Returns whether the task is running on a CPU.
     ///
     /// It is used to protect the task from being moved to a different run queue
@@ -1,6 +1,13 @@
+use core::pin::pin;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, Once};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use std::sync::{Arc, Mutex, Once};
+use std::task::Wake;
 
+use axhal::{dummy, time::TIMER_IRQ_NUM};
+
+use crate::future::{Elapsed, block_on, sleep, timeout};
 use crate::{WaitQueue, api as axtask, current};
 
 static INIT: Once = Once::new();
@@ -128,3 +135,61 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+/// Counts the wake-ups of a future.
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn test_timer_futures() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+    // The timer interrupt handler of the runtime, without the periodic tick.
+    static TIMER_IRQ: Once = Once::new();
+    TIMER_IRQ.call_once(|| {
+        assert!(axhal::irq::register_handler(TIMER_IRQ_NUM, &|_| {
+            axtask::check_timer_events();
+            axtask::arm_timer(u64::MAX);
+        }));
+    });
+
+    const DELAY: Duration = Duration::from_millis(1);
+    // Past the deadline once rounded up to coalesce the timer events.
+    const PAST_DEADLINE: u64 = 1_050_000;
+
+    let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+    let wakeups = || count.0.load(Ordering::Relaxed);
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    // The timer interrupt wakes the future once its deadline is passed.
+    let mut sleep1 = pin!(sleep(DELAY));
+    assert!(sleep1.as_mut().poll(&mut cx).is_pending());
+    dummy::advance(999_000);
+    assert!(sleep1.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(wakeups(), 0);
+    dummy::advance(PAST_DEADLINE - 999_000);
+    assert_eq!(wakeups(), 1);
+    assert!(sleep1.as_mut().poll(&mut cx).is_ready());
+
+    let mut timeout1 = pin!(timeout(DELAY, core::future::pending::<()>()));
+    assert!(timeout1.as_mut().poll(&mut cx).is_pending());
+    dummy::advance(PAST_DEADLINE);
+    assert_eq!(wakeups(), 2);
+    assert_eq!(timeout1.as_mut().poll(&mut cx), Poll::Ready(Err(Elapsed)));
+    let mut timeout2 = pin!(timeout(DELAY, core::future::ready(42)));
+    assert_eq!(timeout2.as_mut().poll(&mut cx), Poll::Ready(Ok(42)));
+
+    // `block_on` waits until the future is woken, here by another task
+    // advancing the clock.
+    let ticker = axtask::spawn(|| dummy::advance(PAST_DEADLINE));
+    let start = axhal::time::monotonic_time();
+    block_on(sleep(DELAY));
+    assert!(axhal::time::monotonic_time() - start >= DELAY);
+    ticker.join();
+}
@@ -0,0 +1,79 @@
+//! Threaded IRQ handlers, whose heavy work runs in a task rather than in
+//! interrupt context.
+
+use alloc::boxed::Box;
+use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axhal::irq::{IrqHandler, SharedIrqHandler, register_handler, set_enable};
+
+use crate::WaitQueue;
+
+/// The state shared by the top half and the task of a threaded IRQ.
+struct ThreadedIrq {
+    quick_check: SharedIrqHandler,
+    /// Whether the bottom half must run.
+    pending: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl ThreadedIrq {
+    /// Runs the quick check in interrupt context, and wakes up the task if
+    /// the bottom half must run.
+    fn top_half(&self, irq_num: usize) {
+        if !(self.quick_check)(irq_num) {
+            return;
+        }
+        // Masked until the bottom half ran, as it may be level-triggered.
+        set_enable(irq_num, false);
+        self.pending.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+
+    /// Runs the bottom half each time the top half asks for it.
+    fn run(&self, irq_num: usize, thread_fn: IrqHandler) -> ! {
+        loop {
+            self.wq.wait_until(|| self.pending.load(Ordering::Acquire));
+            self.pending.store(false, Ordering::Release);
+            thread_fn(irq_num);
+            set_enable(irq_num, true);
+        }
+    }
+}
+
+/// Registers a threaded handler of an IRQ, split into a top half,
+/// `quick_check`, and a bottom half, `thread_fn`.
+///
+/// `quick_check` runs in interrupt context, and returns whether `thread_fn`
+/// must run, e.g., after it checked that the device raised the IRQ and
+/// silenced it. `thread_fn` then runs in the `irq/<irq_num>` task, where the
+/// interrupts are enabled and which can be preempted and block, the IRQ being
+/// masked until it returns.
+///
+/// Returns `false` if the IRQ can not be registered, e.g., it already has a
+/// handler. The handler can not be removed.
+pub fn request_threaded_irq(
+    irq_num: usize,
+    quick_check: SharedIrqHandler,
+    thread_fn: IrqHandler,
+) -> bool {
+    let irq: &'static ThreadedIrq = Box::leak(Box::new(ThreadedIrq {
+        quick_check,
+        pending: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    }));
+    // The top half may run before the task is spawned, which then finds the
+    // bottom half pending.
+    if !register_handler(
+        irq_num,
+        Box::leak(Box::new(move |irq_num| irq.top_half(irq_num))),
+    ) {
+        return false;
+    }
+    crate::spawn_raw(
+        move || irq.run(irq_num, thread_fn),
+        format!("irq/{}", irq_num),
+        axconfig::TASK_STACK_SIZE,
+    );
+    true
+}
@@ -1,17 +1,30 @@
 use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::Waker;
 
 use kernel_guard::NoOp;
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use axhal::time::wall_time;
+use axhal::time::{epochoffset_nanos, wall_time};
 
 use crate::{AxTaskRef, select_run_queue};
 
+/// Deadlines of timer events are rounded up to a multiple of it, so that
+/// nearby deadlines expire in one timer interrupt.
+const TIMER_SLACK_NANOS: u64 = 50_000;
+
 static TIMER_TICKET_ID: AtomicU64 = AtomicU64::new(1);
 
 percpu_static! {
-    TIMER_LIST: LazyInit<TimerList<TaskWakeupEvent>> = LazyInit::new(),
+    TIMER_LIST: LazyInit<TimerList<WakeupEvent>> = LazyInit::new(),
+    /// The deadline the timer of this CPU is programmed to, in monotonic
+    /// nanoseconds.
+    ARMED_DEADLINE: u64 = u64::MAX,
+}
+
+enum WakeupEvent {
+    Task(TaskWakeupEvent),
+    Future(Waker),
 }
 
 struct TaskWakeupEvent {
@@ -35,12 +48,64 @@ impl TimerEvent for TaskWakeupEvent {
     }
 }
 
+impl TimerEvent for WakeupEvent {
+    fn callback(self, now: TimeValue) {
+        match self {
+            Self::Task(event) => event.callback(now),
+            // Wake the future directly from the timer interrupt.
+            Self::Future(waker) => waker.wake(),
+        }
+    }
+}
+
+fn coalesce(deadline: TimeValue) -> TimeValue {
+    let nanos = (deadline.as_nanos() as u64).div_ceil(TIMER_SLACK_NANOS) * TIMER_SLACK_NANOS;
+    TimeValue::from_nanos(nanos)
+}
+
+/// Adds an event to the timer list of the current CPU, and reprograms the
+/// timer if the event is due before the programmed deadline.
+///
+/// IRQs must be disabled.
+fn set_event(deadline: TimeValue, event: WakeupEvent) {
+    let deadline = coalesce(deadline);
+    TIMER_LIST.with_current(|timer_list| timer_list.set(deadline, event));
+    let deadline_ns = (deadline.as_nanos() as u64).saturating_sub(epochoffset_nanos());
+    // Safety: IRQs are disabled at this time.
+    if deadline_ns < unsafe { ARMED_DEADLINE.read_current_raw() } {
+        unsafe { ARMED_DEADLINE.write_current_raw(deadline_ns) };
+        axhal::time::set_oneshot_timer(deadline_ns);
+    }
+}
+
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    TIMER_LIST.with_current(|timer_list| {
-        let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
-        task.set_timer_ticket(ticket_id);
-        timer_list.set(deadline, TaskWakeupEvent { ticket_id, task });
-    })
+    let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
+    task.set_timer_ticket(ticket_id);
+    set_event(
+        deadline,
+        WakeupEvent::Task(TaskWakeupEvent { ticket_id, task }),
+    );
+}
+
+/// Wakes the future of `waker` at `deadline`.
+pub fn set_waker_wakeup(deadline: TimeValue, waker: Waker) {
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    set_event(deadline, WakeupEvent::Future(waker));
+}
+
+/// Programs the timer of the current CPU to fire at `deadline` (in monotonic
+/// nanoseconds), or at the earliest timer event if it is earlier.
+///
+/// IRQs must be disabled.
+pub fn arm_timer(deadline: u64) {
+    // Safety: IRQs are disabled at this time.
+    let next_event = unsafe { TIMER_LIST.current_ref_raw() }.next_deadline();
+    let deadline = match next_event {
+        Some(next) => deadline.min((next.as_nanos() as u64).saturating_sub(epochoffset_nanos())),
+        None => deadline,
+    };
+    unsafe { ARMED_DEADLINE.write_current_raw(deadline) };
+    axhal::time::set_oneshot_timer(deadline);
 }
 
 pub fn check_events() {
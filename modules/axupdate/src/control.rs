@@ -0,0 +1,422 @@
+use axcrypto::crc32::{Crc32, crc32};
+use axdriver::prelude::*;
+use axerrno::{AxError, AxResult, ax_err};
+
+const BLOCK_SIZE: usize = 512;
+
+/// `"AXAB"` in little-endian.
+const MAGIC: u32 = 0x4241_5841;
+
+/// The size of the encoded metadata, including its CRC.
+const METADATA_SIZE: usize = 40;
+/// The number of blocks of the two copies of the metadata.
+const METADATA_BLOCKS: u64 = 2;
+/// No trial slot, in the encoded metadata.
+const NO_TRIAL: u8 = 0xff;
+
+/// A slot holding an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// The first slot.
+    A,
+    /// The second slot.
+    B,
+}
+
+impl Slot {
+    /// Returns the other slot.
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(Self::A),
+            1 => Some(Self::B),
+            _ => None,
+        }
+    }
+}
+
+/// The image written to a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotImage {
+    /// The size of the image, in bytes.
+    pub size: u64,
+    /// The CRC-32 of the image.
+    pub crc: u32,
+}
+
+/// The boot state, written to the device on each change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Metadata {
+    /// Increased by each write, to find the latest copy.
+    seq: u32,
+    /// The slot that boots by default, known to be healthy.
+    active: Slot,
+    /// The slot with a new image to try, if any.
+    trial: Option<Slot>,
+    /// The number of boots left to try [`Metadata::trial`].
+    tries: u8,
+    images: [Option<SlotImage>; 2],
+}
+
+impl Metadata {
+    /// Encodes the metadata with the layout below (little-endian), an image
+    /// of size 0 meaning there is no image.
+    ///
+    /// | Offset | Size | Field                                   |
+    /// |--------|------|-----------------------------------------|
+    /// | 0      | 4    | Magic number, `"AXAB"`                  |
+    /// | 4      | 4    | Sequence number                         |
+    /// | 8      | 1    | Active slot, 0 (A) or 1 (B)             |
+    /// | 9      | 1    | Trial slot, or `0xff` if there is none  |
+    /// | 10     | 1    | Boots left to try the trial slot        |
+    /// | 11     | 1    | Reserved                                |
+    /// | 12     | 8    | Size of the image of slot A             |
+    /// | 20     | 4    | CRC-32 of the image of slot A           |
+    /// | 24     | 8    | Size of the image of slot B             |
+    /// | 32     | 4    | CRC-32 of the image of slot B           |
+    /// | 36     | 4    | CRC-32 of the fields above              |
+    fn encode(&self) -> [u8; METADATA_SIZE] {
+        let mut buf = [0; METADATA_SIZE];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        buf[8] = self.active.index() as u8;
+        buf[9] = self.trial.map_or(NO_TRIAL, |slot| slot.index() as u8);
+        buf[10] = self.tries;
+        for (i, image) in self.images.iter().enumerate() {
+            let image = image.unwrap_or(SlotImage { size: 0, crc: 0 });
+            let offset = 12 + i * 12;
+            buf[offset..offset + 8].copy_from_slice(&image.size.to_le_bytes());
+            buf[offset + 8..offset + 12].copy_from_slice(&image.crc.to_le_bytes());
+        }
+        let crc = crc32(&buf[..36]);
+        buf[36..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// Decodes the metadata, or returns `None` if it is not valid.
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let le32 = |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let le64 = |offset: usize| u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+        if le32(0) != MAGIC || le32(36) != crc32(&buf[..36]) {
+            return None;
+        }
+        let trial = match buf[9] {
+            NO_TRIAL => None,
+            index => Some(Slot::from_index(index)?),
+        };
+        let image = |offset: usize| {
+            let size = le64(offset);
+            (size != 0).then(|| SlotImage {
+                size,
+                crc: le32(offset + 8),
+            })
+        };
+        Some(Self {
+            seq: le32(4),
+            active: Slot::from_index(buf[8])?,
+            trial,
+            tries: buf[10],
+            images: [image(12), image(24)],
+        })
+    }
+}
+
+/// The boot control of two image slots on a block device, for A/B updates
+/// with rollback.
+///
+/// The first two blocks of the device hold two copies of the metadata, each
+/// write going to the copy with the older sequence number, so a write torn
+/// by a crash leaves the other copy valid. The rest of the device is split
+/// into the two slots of the same size.
+///
+/// One slot is active, i.e., it boots by default. An update writes the new
+/// image to the other slot, and then marks it as the trial slot for one
+/// boot. The next boot (see [`BootControl::boot`]) consumes the try and
+/// boots the trial slot, which is then made active if it reports healthy
+/// with [`BootControl::mark_healthy`]. Otherwise, the boot after that finds
+/// the try consumed and rolls back to the active slot.
+///
+/// The bootloader loads the image of the slot returned by
+/// [`BootControl::boot`], with [`BootControl::read_image`].
+pub struct BootControl<D> {
+    dev: D,
+    /// The number of blocks of each slot.
+    slot_blocks: u64,
+    meta: Metadata,
+    /// The slot booted, set by [`BootControl::boot`].
+    booted: Option<Slot>,
+    /// The slot being written by an update.
+    updating: Option<Slot>,
+}
+
+impl<D: BlockDriverOps> BootControl<D> {
+    /// Opens the boot control on the given device, and formats the device
+    /// if it does not hold any.
+    ///
+    /// A formatted device has no images, and slot A is active, e.g., to
+    /// boot the image that is not updated yet from somewhere else.
+    pub fn open(dev: D) -> AxResult<Self> {
+        if dev.block_size() != BLOCK_SIZE {
+            return ax_err!(InvalidInput, "unsupported block size");
+        }
+        let slot_blocks = dev.num_blocks().saturating_sub(METADATA_BLOCKS) / 2;
+        if slot_blocks == 0 {
+            return ax_err!(StorageFull, "block device too small");
+        }
+        let mut ctrl = Self {
+            dev,
+            slot_blocks,
+            meta: Metadata {
+                seq: 0,
+                active: Slot::A,
+                trial: None,
+                tries: 0,
+                images: [None; 2],
+            },
+            booted: None,
+            updating: None,
+        };
+
+        let copies = [ctrl.read_metadata(0)?, ctrl.read_metadata(1)?];
+        match copies.into_iter().flatten().max_by_key(|meta| meta.seq) {
+            Some(meta) => ctrl.meta = meta,
+            None => ctrl.write_metadata()?,
+        }
+        Ok(ctrl)
+    }
+
+    /// Returns the underlying device.
+    pub fn into_device(self) -> D {
+        self.dev
+    }
+
+    /// Returns the capacity of each slot, in bytes.
+    pub fn slot_capacity(&self) -> u64 {
+        self.slot_blocks * BLOCK_SIZE as u64
+    }
+
+    /// Returns the slot that boots by default.
+    pub fn active_slot(&self) -> Slot {
+        self.meta.active
+    }
+
+    /// Returns the slot with a new image to try, if any.
+    pub fn trial_slot(&self) -> Option<Slot> {
+        self.meta.trial
+    }
+
+    /// Returns the slot booted, or `None` if [`BootControl::boot`] is not
+    /// called yet.
+    pub fn booted_slot(&self) -> Option<Slot> {
+        self.booted
+    }
+
+    /// Whether the booted slot is on trial, i.e., it must be marked healthy
+    /// with [`BootControl::mark_healthy`] to be kept.
+    pub fn is_on_trial(&self) -> bool {
+        self.booted.is_some() && self.booted == self.meta.trial
+    }
+
+    /// Returns the image of the given slot, or `None` if it has none.
+    pub fn image(&self, slot: Slot) -> Option<SlotImage> {
+        self.meta.images[slot.index()]
+    }
+
+    /// Chooses the slot to boot, and writes the choice to the device.
+    ///
+    /// It is the trial slot if it has a try left, which is consumed.
+    /// Otherwise, a trial slot without tries left has failed to report
+    /// healthy, so it is dropped and the active slot is booted, as well as
+    /// when its image is corrupted.
+    pub fn boot(&mut self) -> AxResult<Slot> {
+        if let Some(trial) = self.meta.trial {
+            if self.meta.tries > 0 && self.verify_image(trial)? {
+                self.meta.tries -= 1;
+            } else {
+                warn!(
+                    "slot {:?} failed to boot, rolling back to slot {:?}",
+                    trial, self.meta.active
+                );
+                self.meta.trial = None;
+                self.meta.tries = 0;
+            }
+            self.write_metadata()?;
+        }
+        let slot = self.meta.trial.unwrap_or(self.meta.active);
+        self.booted = Some(slot);
+        Ok(slot)
+    }
+
+    /// Makes the booted slot active, if it is on trial, so it keeps booting
+    /// by default.
+    pub fn mark_healthy(&mut self) -> AxResult {
+        if !self.is_on_trial() {
+            return Ok(());
+        }
+        self.meta.active = self.meta.trial.take().unwrap();
+        self.meta.tries = 0;
+        self.write_metadata()
+    }
+
+    /// Starts an update, which writes a new image to the slot that is not
+    /// active, and returns that slot.
+    ///
+    /// The image of the slot, and an update not booted yet, are discarded.
+    /// Returns [`AxError::BadState`] if the booted slot is on trial, as the
+    /// other slot is the one to roll back to.
+    pub fn begin_update(&mut self) -> AxResult<Slot> {
+        if self.is_on_trial() {
+            return ax_err!(BadState, "the booted slot is not marked healthy yet");
+        }
+        let slot = self.meta.active.other();
+        self.meta.images[slot.index()] = None;
+        self.meta.trial = None;
+        self.meta.tries = 0;
+        self.write_metadata()?;
+        self.updating = Some(slot);
+        Ok(slot)
+    }
+
+    /// Writes a part of the new image at the given offset, see
+    /// [`BootControl::begin_update`].
+    ///
+    /// Returns [`AxError::StorageFull`] if it does not fit in the slot.
+    pub fn write_image(&mut self, offset: u64, data: &[u8]) -> AxResult {
+        let Some(slot) = self.updating else {
+            return ax_err!(BadState, "no update started");
+        };
+        if offset + data.len() as u64 > self.slot_capacity() {
+            return ax_err!(StorageFull, "image too large for the slot");
+        }
+        self.write_at(slot, offset, data)
+    }
+
+    /// Finishes the update with an image of the given size, which is the
+    /// trial slot for the next boot.
+    pub fn finish_update(&mut self, size: u64) -> AxResult<Slot> {
+        let Some(slot) = self.updating else {
+            return ax_err!(BadState, "no update started");
+        };
+        if size == 0 || size > self.slot_capacity() {
+            return ax_err!(InvalidInput, "invalid image size");
+        }
+        self.flush()?;
+        let crc = self.image_crc(slot, size)?;
+        self.meta.images[slot.index()] = Some(SlotImage { size, crc });
+        self.meta.trial = Some(slot);
+        self.meta.tries = 1;
+        self.write_metadata()?;
+        self.updating = None;
+        Ok(slot)
+    }
+
+    /// Reads the image of the given slot at the given offset.
+    ///
+    /// Returns the number of bytes read, which is less than `buf.len()` at
+    /// the end of the image.
+    pub fn read_image(&mut self, slot: Slot, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let Some(image) = self.image(slot) else {
+            return ax_err!(NotFound, "no image in the slot");
+        };
+        let len = image.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+        self.read_at(slot, offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    /// Whether the image of the given slot matches its CRC.
+    fn verify_image(&mut self, slot: Slot) -> AxResult<bool> {
+        Ok(match self.image(slot) {
+            Some(image) => self.image_crc(slot, image.size)? == image.crc,
+            None => false,
+        })
+    }
+
+    fn image_crc(&mut self, slot: Slot, size: u64) -> AxResult<u32> {
+        let mut block = [0; BLOCK_SIZE];
+        let mut crc = Crc32::new();
+        let mut offset = 0;
+        while offset < size {
+            let len = (size - offset).min(BLOCK_SIZE as u64) as usize;
+            self.read_at(slot, offset, &mut block[..len])?;
+            crc.update(&block[..len]);
+            offset += len as u64;
+        }
+        Ok(crc.finish())
+    }
+
+    fn read_metadata(&mut self, copy: u64) -> AxResult<Option<Metadata>> {
+        let mut block = [0; BLOCK_SIZE];
+        self.dev.read_block(copy, &mut block).map_err(dev_err)?;
+        Ok(Metadata::decode(&block))
+    }
+
+    /// Writes the metadata with the next sequence number, over the older
+    /// copy.
+    fn write_metadata(&mut self) -> AxResult {
+        self.meta.seq = self.meta.seq.wrapping_add(1);
+        let mut block = [0; BLOCK_SIZE];
+        block[..METADATA_SIZE].copy_from_slice(&self.meta.encode());
+        self.dev
+            .write_block((self.meta.seq % 2) as u64, &block)
+            .map_err(dev_err)?;
+        self.flush()
+    }
+
+    fn read_at(&mut self, slot: Slot, offset: u64, buf: &mut [u8]) -> AxResult {
+        let mut block = [0; BLOCK_SIZE];
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let start = (pos % BLOCK_SIZE as u64) as usize;
+            let count = (buf.len() - done).min(BLOCK_SIZE - start);
+            self.dev
+                .read_block(self.block_id(slot, pos), &mut block)
+                .map_err(dev_err)?;
+            buf[done..done + count].copy_from_slice(&block[start..start + count]);
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, slot: Slot, offset: u64, buf: &[u8]) -> AxResult {
+        let mut block = [0; BLOCK_SIZE];
+        let mut pos = offset;
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let block_id = self.block_id(slot, pos);
+            let start = (pos % BLOCK_SIZE as u64) as usize;
+            let count = rest.len().min(BLOCK_SIZE - start);
+            if count < BLOCK_SIZE {
+                self.dev.read_block(block_id, &mut block).map_err(dev_err)?;
+            }
+            block[start..start + count].copy_from_slice(&rest[..count]);
+            self.dev.write_block(block_id, &block).map_err(dev_err)?;
+            rest = &rest[count..];
+            pos += count as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> AxResult {
+        self.dev.flush().map_err(dev_err)
+    }
+
+    fn block_id(&self, slot: Slot, offset: u64) -> u64 {
+        METADATA_BLOCKS + slot.index() as u64 * self.slot_blocks + offset / BLOCK_SIZE as u64
+    }
+}
+
+fn dev_err(err: DevError) -> AxError {
+    warn!("update device error: {:?}", err);
+    AxError::Io
+}
@@ -0,0 +1,129 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) A/B image update module.
+//!
+//! It writes a new image, e.g., of the kernel with the application, to the
+//! inactive one of two slots on a block device, and boots it once (see
+//! [`BootControl`]). The new image must report healthy with
+//! [`mark_healthy`] within a timeout, otherwise the watchdog reboots the
+//! system, and the previous image boots again. So an update over the network
+//! can not leave an appliance that does not boot.
+//!
+//! The timeout is 60 seconds by default, and can be set in seconds by
+//! `AX_UPDATE_TIMEOUT` at build time.
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+#[macro_use]
+extern crate log;
+
+mod control;
+
+use core::time::Duration;
+
+use axdriver::{AxDeviceContainer, prelude::*};
+use axerrno::{AxError, AxResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+
+pub use self::control::{BootControl, Slot, SlotImage};
+
+static BOOT_CONTROL: LazyInit<Mutex<BootControl<AxBlockDevice>>> = LazyInit::new();
+
+/// The time by which the booted slot must be marked healthy, if it is on
+/// trial.
+static DEADLINE: LazyInit<Duration> = LazyInit::new();
+
+/// The default timeout for a slot on trial to report healthy.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn health_timeout() -> Duration {
+    option_env!("AX_UPDATE_TIMEOUT")
+        .and_then(|secs| secs.parse().ok())
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+}
+
+/// Initializes the boot control on the last block device, and chooses the
+/// slot booted (see [`BootControl::boot`]).
+///
+/// If the booted slot is on trial, the watchdog is armed, see
+/// [`check_watchdog`].
+pub fn init_update(blk_devs: &mut AxDeviceContainer<AxBlockDevice>) {
+    info!("Initialize A/B update...");
+
+    let Some(dev) = blk_devs.take_last() else {
+        warn!("  no block device found for the image slots");
+        return;
+    };
+    info!("  use block device: {:?}", dev.device_name());
+    let mut ctrl = match BootControl::open(dev) {
+        Ok(ctrl) => ctrl,
+        Err(e) => {
+            warn!("  failed to open the boot control: {:?}", e);
+            return;
+        }
+    };
+    match ctrl.boot() {
+        Ok(slot) if ctrl.is_on_trial() => {
+            let timeout = health_timeout();
+            info!("  booted slot {:?} on trial, for {:?}", slot, timeout);
+            DEADLINE.init_once(axhal::time::monotonic_time() + timeout);
+        }
+        Ok(slot) => info!("  booted slot {:?}", slot),
+        Err(e) => warn!("  failed to choose the slot to boot: {:?}", e),
+    }
+    BOOT_CONTROL.init_once(Mutex::new(ctrl));
+}
+
+fn with_control<T>(f: impl FnOnce(&mut BootControl<AxBlockDevice>) -> AxResult<T>) -> AxResult<T> {
+    let ctrl = BOOT_CONTROL.get().ok_or(AxError::BadState)?;
+    f(&mut ctrl.lock())
+}
+
+/// Reboots the system to roll back, if the booted slot is on trial and not
+/// marked healthy by the deadline.
+///
+/// It should be called periodically, e.g., by a task of the runtime.
+pub fn check_watchdog() {
+    let Some(&deadline) = DEADLINE.get() else {
+        return;
+    };
+    if axhal::time::monotonic_time() < deadline {
+        return;
+    }
+    if with_control(|ctrl| Ok(ctrl.is_on_trial())).unwrap_or(false) {
+        error!("the booted slot is not marked healthy in time, rebooting to roll back");
+        axhal::misc::reboot();
+    }
+}
+
+/// Returns the slot booted.
+pub fn booted_slot() -> AxResult<Slot> {
+    with_control(|ctrl| ctrl.booted_slot().ok_or(AxError::BadState))
+}
+
+/// Whether the booted slot is on trial, i.e., it must be marked healthy
+/// before the watchdog times out.
+pub fn is_on_trial() -> AxResult<bool> {
+    with_control(|ctrl| Ok(ctrl.is_on_trial()))
+}
+
+/// Reports that the booted slot is healthy, which makes it boot by default
+/// and disarms the watchdog.
+pub fn mark_healthy() -> AxResult {
+    with_control(|ctrl| ctrl.mark_healthy())
+}
+
+/// Starts an update, see [`BootControl::begin_update`].
+pub fn begin_update() -> AxResult<Slot> {
+    with_control(|ctrl| ctrl.begin_update())
+}
+
+/// Writes a part of the new image at the given offset.
+pub fn write_image(offset: u64, data: &[u8]) -> AxResult {
+    with_control(|ctrl| ctrl.write_image(offset, data))
+}
+
+/// Finishes the update with an image of the given size, which boots once
+/// after the next reboot.
+pub fn finish_update(size: u64) -> AxResult<Slot> {
+    with_control(|ctrl| ctrl.finish_update(size))
+}
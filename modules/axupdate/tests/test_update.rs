@@ -0,0 +1,113 @@
+use axdriver_block::BlockDriverOps;
+use axdriver_block::ramdisk::RamDisk;
+use axerrno::AxError;
+use axupdate::{BootControl, Slot};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Reopens the boot control and boots, as after a reboot.
+fn reboot(ctrl: BootControl<RamDisk>) -> (BootControl<RamDisk>, Slot) {
+    let mut ctrl = BootControl::open(ctrl.into_device()).expect("failed to reopen");
+    let slot = ctrl.boot().unwrap();
+    (ctrl, slot)
+}
+
+fn update(ctrl: &mut BootControl<RamDisk>, image: &[u8]) -> Slot {
+    ctrl.begin_update().unwrap();
+    // Write in parts not aligned to blocks.
+    for (i, part) in image.chunks(300).enumerate() {
+        ctrl.write_image(i as u64 * 300, part).unwrap();
+    }
+    ctrl.finish_update(image.len() as u64).unwrap()
+}
+
+fn image(seed: u8) -> Vec<u8> {
+    (0..2000).map(|i| (i as u8).wrapping_mul(seed)).collect()
+}
+
+#[test]
+fn test_update() {
+    let mut ctrl = BootControl::open(RamDisk::new(32 * BLOCK_SIZE)).unwrap();
+    assert_eq!(ctrl.slot_capacity(), 15 * BLOCK_SIZE as u64);
+    assert_eq!(ctrl.boot(), Ok(Slot::A));
+    assert!(!ctrl.is_on_trial());
+    assert_eq!(ctrl.mark_healthy(), Ok(()));
+
+    let new_image = image(3);
+    assert_eq!(update(&mut ctrl, &new_image), Slot::B);
+    assert_eq!(ctrl.trial_slot(), Some(Slot::B));
+
+    // The new image boots once, and is kept when marked healthy.
+    let (mut ctrl, slot) = reboot(ctrl);
+    assert_eq!(slot, Slot::B);
+    assert!(ctrl.is_on_trial());
+    let mut buf = vec![0; 4096];
+    assert_eq!(ctrl.read_image(Slot::B, 100, &mut buf), Ok(1900));
+    assert_eq!(buf[..1900], new_image[100..]);
+    ctrl.mark_healthy().unwrap();
+    assert_eq!(ctrl.active_slot(), Slot::B);
+
+    let (mut ctrl, slot) = reboot(ctrl);
+    assert_eq!(slot, Slot::B);
+    assert!(!ctrl.is_on_trial());
+
+    // The next update goes to the other slot.
+    assert_eq!(update(&mut ctrl, &image(5)), Slot::A);
+    assert_eq!(reboot(ctrl).1, Slot::A);
+}
+
+#[test]
+fn test_rollback() {
+    let mut ctrl = BootControl::open(RamDisk::new(32 * BLOCK_SIZE)).unwrap();
+    ctrl.boot().unwrap();
+    update(&mut ctrl, &image(3));
+
+    let (mut ctrl, slot) = reboot(ctrl);
+    assert_eq!(slot, Slot::B);
+    // Updating again is refused until the booted slot is marked healthy.
+    assert_eq!(ctrl.begin_update(), Err(AxError::BadState));
+
+    // Not marked healthy, e.g., rebooted by the watchdog.
+    let (ctrl, slot) = reboot(ctrl);
+    assert_eq!(slot, Slot::A);
+    assert_eq!(ctrl.trial_slot(), None);
+    assert_eq!(reboot(ctrl).1, Slot::A);
+}
+
+#[test]
+fn test_corrupted() {
+    let mut ctrl = BootControl::open(RamDisk::new(32 * BLOCK_SIZE)).unwrap();
+    ctrl.boot().unwrap();
+    assert_eq!(ctrl.write_image(0, b"x"), Err(AxError::BadState));
+    ctrl.begin_update().unwrap();
+    assert_eq!(
+        ctrl.write_image(ctrl.slot_capacity() - 1, b"xx"),
+        Err(AxError::StorageFull)
+    );
+    update(&mut ctrl, &image(3));
+
+    // A corrupted image is not booted.
+    let mut disk = ctrl.into_device();
+    let mut block = [0; BLOCK_SIZE];
+    disk.read_block(17, &mut block).unwrap();
+    block[0] ^= 1;
+    disk.write_block(17, &block).unwrap();
+    let mut ctrl = BootControl::open(disk).unwrap();
+    assert_eq!(ctrl.boot(), Ok(Slot::A));
+
+    // A torn write of the metadata leaves the previous state.
+    update(&mut ctrl, &image(3));
+    let mut disk = ctrl.into_device();
+    let latest = (0..2)
+        .max_by_key(|&id| {
+            disk.read_block(id, &mut block).unwrap();
+            u32::from_le_bytes(block[4..8].try_into().unwrap())
+        })
+        .unwrap();
+    disk.read_block(latest, &mut block).unwrap();
+    block[10] ^= 1;
+    disk.write_block(latest, &block).unwrap();
+    let mut ctrl = BootControl::open(disk).unwrap();
+    assert_eq!(ctrl.trial_slot(), None);
+    assert_eq!(ctrl.boot(), Ok(Slot::A));
+}
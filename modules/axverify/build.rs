@@ -0,0 +1,22 @@
+/// The size of an ed25519 public key.
+const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Copies the trusted public key to `OUT_DIR`, to be embedded.
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_VERIFY_KEY");
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let out_path = std::path::Path::new(&out_dir).join("verify_key.bin");
+    match std::env::var("AX_VERIFY_KEY") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={path}");
+            let key = std::fs::read(&path).unwrap();
+            assert_eq!(
+                key.len(),
+                PUBLIC_KEY_SIZE,
+                "{path} is not a raw ed25519 public key"
+            );
+            std::fs::write(out_path, key).unwrap();
+        }
+        _ => std::fs::write(out_path, "").unwrap(),
+    }
+}
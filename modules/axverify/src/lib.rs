@@ -0,0 +1,88 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) verification of signed
+//! images.
+//!
+//! Images loaded at boot or at run time, e.g., the initramfs, executables or
+//! images written by the A/B updater, can be signed with ed25519, and are only
+//! used if their signatures are made by the key trusted by the kernel. So a
+//! tampered image is refused rather than run. The signatures are checked with
+//! [`axcrypto::ed25519`].
+//!
+//! The trusted public key is embedded in the kernel from the file at the path
+//! in `AX_VERIFY_KEY` at build time, in the raw form of 32 bytes. Signatures
+//! are detached, in the raw form of 64 bytes. With OpenSSL, they can be made
+//! by:
+//!
+//! ```sh
+//! openssl genpkey -algorithm ed25519 -out key.pem
+//! openssl pkey -in key.pem -pubout -outform DER | tail -c 32 > key.pub
+//! openssl pkeyutl -sign -rawin -inkey key.pem -in image -out image.sig
+//! ```
+//!
+//! If no key is embedded, nothing can be verified.
+
+#![cfg_attr(all(not(test), not(doc)), no_std)]
+
+#[macro_use]
+extern crate log;
+
+use axcrypto::ed25519;
+use axerrno::{AxResult, ax_err};
+
+pub use axcrypto::ed25519::{PUBLIC_KEY_SIZE, SIGNATURE_SIZE};
+
+/// The key at the path in `AX_VERIFY_KEY` at build time, or empty.
+static TRUSTED_KEY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/verify_key.bin"));
+
+/// Returns the public key trusted, if one is embedded.
+pub fn trusted_key() -> Option<&'static [u8]> {
+    (!TRUSTED_KEY.is_empty()).then_some(TRUSTED_KEY)
+}
+
+/// Verifies the signature of `data` made by the trusted key.
+///
+/// Returns [`AxError::BadState`](axerrno::AxError::BadState) if no key is
+/// embedded, and [`AxError::InvalidData`](axerrno::AxError::InvalidData) if
+/// the signature does not match.
+pub fn verify(data: &[u8], signature: &[u8]) -> AxResult {
+    let mut verifier = Verifier::new(signature)?;
+    verifier.update(data);
+    verifier.finish()
+}
+
+/// Verifies the signature of `data` made by the given public key.
+pub fn verify_with_key(key: &[u8], data: &[u8], signature: &[u8]) -> AxResult {
+    ed25519::verify(key, data, signature)
+}
+
+/// Verifies a signature over data given in parts, e.g., an image read from a
+/// block device.
+pub struct Verifier {
+    inner: ed25519::Verifier,
+}
+
+impl Verifier {
+    /// Starts verifying a signature made by the trusted key.
+    pub fn new(signature: &[u8]) -> AxResult<Self> {
+        let Some(key) = trusted_key() else {
+            return ax_err!(BadState, "no trusted key embedded, set `AX_VERIFY_KEY`");
+        };
+        Self::with_key(key, signature)
+    }
+
+    /// Starts verifying a signature made by the given public key.
+    pub fn with_key(key: &[u8], signature: &[u8]) -> AxResult<Self> {
+        Ok(Self {
+            inner: ed25519::Verifier::new(key, signature)?,
+        })
+    }
+
+    /// Appends the next part of the data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Checks the signature over all the data appended.
+    pub fn finish(self) -> AxResult {
+        self.inner.finish()
+    }
+}
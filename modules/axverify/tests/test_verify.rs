@@ -0,0 +1,85 @@
+use axerrno::AxError;
+use axverify::Verifier;
+
+/// Test vectors from RFC 8032, section 7.1.
+struct TestVector {
+    key: &'static str,
+    message: &'static str,
+    signature: &'static str,
+}
+
+const TEST_2: TestVector = TestVector {
+    key: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+    message: "72",
+    signature: "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+};
+
+const TEST_3: TestVector = TestVector {
+    key: "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+    message: "af82",
+    signature: "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+                18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_verify() {
+    for test in [TEST_2, TEST_3] {
+        let (key, message, signature) = (hex(test.key), hex(test.message), hex(test.signature));
+        assert_eq!(
+            axverify::verify_with_key(&key, &message, &signature),
+            Ok(())
+        );
+
+        // In parts.
+        let mut verifier = Verifier::with_key(&key, &signature).unwrap();
+        for part in message.chunks(1) {
+            verifier.update(part);
+        }
+        assert_eq!(verifier.finish(), Ok(()));
+    }
+}
+
+#[test]
+fn test_tampered() {
+    let (key, message, signature) = (hex(TEST_3.key), hex(TEST_3.message), hex(TEST_3.signature));
+
+    let mut tampered = message.clone();
+    tampered[1] ^= 1;
+    assert_eq!(
+        axverify::verify_with_key(&key, &tampered, &signature),
+        Err(AxError::InvalidData)
+    );
+    let mut tampered = signature.clone();
+    tampered[0] ^= 1;
+    assert!(axverify::verify_with_key(&key, &message, &tampered).is_err());
+
+    // Signed by another key.
+    let other_key = hex(TEST_2.key);
+    assert_eq!(
+        axverify::verify_with_key(&other_key, &message, &signature),
+        Err(AxError::InvalidData)
+    );
+    assert_eq!(
+        axverify::verify_with_key(&key, &message, &signature[..63]),
+        Err(AxError::InvalidInput)
+    );
+}
+
+#[test]
+fn test_no_trusted_key() {
+    // No key is embedded in the tests.
+    assert_eq!(axverify::trusted_key(), None);
+    let signature = hex(TEST_2.signature);
+    assert_eq!(
+        axverify::verify(&hex(TEST_2.message), &signature),
+        Err(AxError::BadState)
+    );
+}
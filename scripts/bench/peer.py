@@ -0,0 +1,57 @@
+#!/usr/bin/env python3
+"""The host peer of the `tcp` benchmark of `examples/bench`.
+
+It echoes the connections to the echo port, and on the sink port, reads the
+number of bytes given by an 8-byte big-endian header, then sends a byte back
+once it has received them all.
+"""
+
+import socket
+import socketserver
+import struct
+import sys
+import threading
+
+ECHO_PORT = 5556
+SINK_PORT = 5557
+
+
+class EchoHandler(socketserver.BaseRequestHandler):
+    def handle(self):
+        self.request.setsockopt(socket.IPPROTO_TCP, socket.TCP_NODELAY, 1)
+        while data := self.request.recv(4096):
+            self.request.sendall(data)
+
+
+class SinkHandler(socketserver.BaseRequestHandler):
+    def handle(self):
+        header = b""
+        while len(header) < 8:
+            data = self.request.recv(8 - len(header))
+            if not data:
+                return
+            header += data
+        (left,) = struct.unpack(">Q", header)
+        while left > 0:
+            data = self.request.recv(min(left, 1 << 16))
+            if not data:
+                return
+            left -= len(data)
+        self.request.sendall(b"\0")
+
+
+class Server(socketserver.ThreadingTCPServer):
+    allow_reuse_address = True
+    daemon_threads = True
+
+
+def main():
+    host = sys.argv[1] if len(sys.argv) > 1 else "0.0.0.0"
+    servers = [Server((host, ECHO_PORT), EchoHandler), Server((host, SINK_PORT), SinkHandler)]
+    for server in servers[1:]:
+        threading.Thread(target=server.serve_forever, daemon=True).start()
+    servers[0].serve_forever()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,34 @@
+#!/bin/bash
+# Runs the benchmarks of `examples/bench` on QEMU, and writes their results
+# to `bench.jsonl` (or `$BENCH_OUT`), one JSON object per line, to be
+# compared with those of other releases.
+#
+# The arguments are passed to `make`, e.g.:
+#
+#   scripts/bench/run.sh ARCH=riscv64 APP_FEATURES=fs,net BLK=y NET=y
+#
+# The `qemu-exit` feature makes QEMU exit when the benchmarks are done, so
+# `FEATURES`, if given, must include it. With `NET=y`, the peer of the `tcp`
+# benchmark is started on the host.
+#
+# The exit status is 0 if all the benchmarks succeeded.
+
+set -o pipefail
+cd "$(dirname "$0")/../.." || exit 1
+
+out=${BENCH_OUT:-bench.jsonl}
+
+if [[ " $* " == *" NET=y "* ]]; then
+    python3 scripts/bench/peer.py &
+    peer=$!
+    trap 'kill $peer' EXIT
+fi
+
+make A=examples/bench FEATURES=qemu-exit "$@" run \
+    | tee /dev/stderr \
+    | tr -d '\r' \
+    | sed -n 's/^BENCH //p' > "$out"
+
+echo "Results written to $out"
+# The last line is the summary, present only if all the benchmarks ran.
+tail -n 1 "$out" | grep -q '"failed":0}'
@@ -19,6 +19,20 @@ build_args := \
 
 RUSTFLAGS:= -A unsafe_op_in_unsafe_fn
 RUSTFLAGS_LINK_ARGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-no-pie -C link-arg=-znostart-stop-gc
+
+ifeq ($(HARDEN), y)
+  RUSTFLAGS += -Z stack-protector=strong
+  ifeq ($(ARCH), aarch64)
+    RUSTFLAGS += -Z branch-protection=bti,pac-ret
+  else ifeq ($(ARCH), x86_64)
+    RUSTFLAGS += -Z cf-protection=full
+  endif
+endif
+
+ifeq ($(FRAME_POINTERS), y)
+  RUSTFLAGS += -C force-frame-pointers=yes
+endif
+
 RUSTDOCFLAGS := -Z unstable-options --enable-index-page -D rustdoc::broken_intra_doc_links
 
 ifeq ($(MAKECMDGOALS), doc_check_missing)
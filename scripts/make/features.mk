@@ -48,6 +48,33 @@ ifeq ($(BUS),mmio)
   ax_feat += bus-mmio
 endif
 
+ifneq ($(REPLAY),)
+  ax_feat += console-replay
+endif
+
+ifneq ($(INITRAMFS),)
+  ax_feat += initramfs
+  ifneq ($(VERIFY_KEY),)
+    ax_feat += initramfs-verify
+  endif
+endif
+
+ifeq ($(HARDEN),y)
+  ax_feat += harden
+endif
+
+ifneq ($(SCHED_SEED),)
+  ax_feat += sched-det
+endif
+
+ifneq ($(NETCONSOLE),)
+  ax_feat += netconsole
+endif
+
+ifneq ($(NET_IRQ),)
+  ax_feat += net-napi
+endif
+
 ifeq ($(shell test $(SMP) -gt 1; echo $$?),0)
   lib_feat += smp
 endif
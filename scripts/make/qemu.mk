@@ -14,12 +14,18 @@ ifeq ($(ARCH), x86_64)
   machine := q35
 else ifeq ($(ARCH), riscv64)
   machine := virt
+  ifneq ($(AIA),)
+    machine := $(machine),aia=$(AIA)
+  endif
 else ifeq ($(ARCH), aarch64)
   ifeq ($(PLAT_NAME), aarch64-raspi4)
     machine := raspi4b
     override MEM := 2G
   else
     machine := virt
+    ifeq ($(EL2), y)
+      machine := $(machine),virtualization=on
+    endif
   endif
 else ifeq ($(ARCH), loongarch64)
   machine := virt
@@ -44,17 +50,38 @@ qemu_args-loongarch64 := \
   -machine $(machine) \
   -kernel $(OUT_ELF)
 
+ifneq ($(filter qemu-exit,$(FEATURES)),)
+  qemu_args-x86_64 += -device isa-debug-exit,iobase=0xf4,iosize=0x04
+  qemu_args-aarch64 += -semihosting
+endif
+
 qemu_args-y := -m $(MEM) -smp $(SMP) $(qemu_args-$(ARCH))
 
+ifneq ($(BOOTARGS),)
+  qemu_args-y += -append "$(BOOTARGS)"
+endif
+
 qemu_args-$(BLK) += \
   -device virtio-blk-$(vdev-suffix),drive=disk0 \
   -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
 
+ifneq ($(KV_IMG),)
+  qemu_args-y += \
+    -device virtio-blk-$(vdev-suffix),drive=disk1 \
+    -drive id=disk1,if=none,format=raw,file=$(KV_IMG)
+endif
+
+ifneq ($(UPDATE_IMG),)
+  qemu_args-y += \
+    -device virtio-blk-$(vdev-suffix),drive=disk2 \
+    -drive id=disk2,if=none,format=raw,file=$(UPDATE_IMG)
+endif
+
 qemu_args-$(NET) += \
   -device virtio-net-$(vdev-suffix),netdev=net0
 
 ifeq ($(NET_DEV), user)
-  qemu_args-$(NET) += -netdev user,id=net0,hostfwd=tcp::5555-:5555,hostfwd=udp::5555-:5555
+  qemu_args-$(NET) += -netdev user,id=net0,hostfwd=tcp::5555-:5555,hostfwd=udp::5555-:5555,hostfwd=tcp::2323-:2323
 else ifeq ($(NET_DEV), tap)
   qemu_args-$(NET) += -netdev tap,id=net0,script=scripts/net/qemu-ifup.sh,downscript=no,vhost=$(VHOST),vhostforce=$(VHOST)
   QEMU := sudo $(QEMU)
@@ -74,6 +101,8 @@ ifeq ($(NET_DUMP), y)
   qemu_args-$(NET) += -object filter-dump,id=dump0,netdev=net0,file=netdump.pcap
 endif
 
+qemu_args-$(USB) += -usb
+
 qemu_args-$(GRAPHIC) += \
   -device virtio-gpu-$(vdev-suffix) -vga none \
   -serial mon:stdio
@@ -86,6 +115,13 @@ ifeq ($(QEMU_LOG), y)
   qemu_args-y += -D qemu.log -d in_asm,int,mmu,pcall,cpu_reset,guest_errors
 endif
 
+ifeq ($(ICOUNT), y)
+  # The virtual clock runs with the instructions executed, and skips ahead
+  # when the guest is idle.
+  qemu_args-y += -icount shift=0,sleep=off
+  override ACCEL := n
+endif
+
 qemu_args-debug := $(qemu_args-y) -s -S
 
 ifeq ($(ACCEL),)
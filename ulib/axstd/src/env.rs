@@ -1,10 +1,22 @@
 //! Inspection and manipulation of the process’s environment.
 
-#[cfg(feature = "fs")]
+#[cfg(any(feature = "alloc", feature = "fs"))]
 extern crate alloc;
 
 #[cfg(feature = "fs")]
-use {crate::io, alloc::string::String};
+use crate::io;
+#[cfg(any(feature = "alloc", feature = "fs"))]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use {
+    crate::sync::Mutex,
+    alloc::{collections::BTreeMap, vec::Vec},
+    core::fmt,
+};
+
+/// The environment variables, shared by all the tasks.
+#[cfg(feature = "alloc")]
+static VARS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
 
 /// Returns the current working directory as a [`String`].
 #[cfg(feature = "fs")]
@@ -17,3 +29,98 @@ pub fn current_dir() -> io::Result<String> {
 pub fn set_current_dir(path: &str) -> io::Result<()> {
     arceos_api::fs::ax_set_current_dir(path)
 }
+
+/// The error type for operations interacting with environment variables.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarError {
+    /// The specified environment variable was not present in the current
+    /// process's environment.
+    NotPresent,
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::NotPresent => f.write_str("environment variable not found"),
+        }
+    }
+}
+
+/// Fetches the environment variable `key` from the current process.
+#[cfg(feature = "alloc")]
+pub fn var<K: AsRef<str>>(key: K) -> Result<String, VarError> {
+    VARS.lock()
+        .get(key.as_ref())
+        .cloned()
+        .ok_or(VarError::NotPresent)
+}
+
+/// An iterator over a snapshot of the environment variables of this process.
+///
+/// It is created by [`vars`].
+#[cfg(feature = "alloc")]
+pub struct Vars {
+    inner: alloc::vec::IntoIter<(String, String)>,
+}
+
+#[cfg(feature = "alloc")]
+impl Iterator for Vars {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<(String, String)> {
+        self.inner.next()
+    }
+}
+
+/// Returns an iterator of (variable, value) pairs of strings, for all the
+/// environment variables of the current process, sorted by variable.
+///
+/// The returned iterator contains a snapshot of the process's environment
+/// variables at the time of this invocation.
+#[cfg(feature = "alloc")]
+pub fn vars() -> Vars {
+    let vars: Vec<_> = VARS
+        .lock()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    Vars {
+        inner: vars.into_iter(),
+    }
+}
+
+/// Sets the environment variable `key` to the value `value` for the currently
+/// running process.
+///
+/// # Safety
+///
+/// The environment is protected by a lock, so this function is always safe to
+/// call on ArceOS. It is `unsafe` as in `std`, for the same code to build on
+/// both.
+///
+/// # Panics
+///
+/// This function panics if `key` is empty, or contains the `=` or NUL
+/// characters, or if `value` contains the NUL character.
+#[cfg(feature = "alloc")]
+pub unsafe fn set_var<K: AsRef<str>, V: AsRef<str>>(key: K, value: V) {
+    let (key, value) = (key.as_ref(), value.as_ref());
+    assert!(
+        !key.is_empty() && !key.contains(['=', '\0']) && !value.contains('\0'),
+        "invalid environment variable: {key:?}={value:?}"
+    );
+    VARS.lock().insert(key.into(), value.into());
+}
+
+/// Removes an environment variable from the environment of the currently
+/// running process.
+///
+/// # Safety
+///
+/// As for [`set_var`], this function is always safe to call on ArceOS.
+#[cfg(feature = "alloc")]
+pub unsafe fn remove_var<K: AsRef<str>>(key: K) {
+    VARS.lock().remove(key.as_ref());
+}
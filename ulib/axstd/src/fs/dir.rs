@@ -1,6 +1,6 @@
 extern crate alloc;
 
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use core::fmt;
 
 use super::FileType;
@@ -82,13 +82,16 @@ impl<'a> Iterator for ReadDir<'a> {
                 continue;
             }
             let entry_name = unsafe { core::str::from_utf8_unchecked(name_bytes).into() };
-            let entry_type = entry.entry_type();
-
-            return Some(Ok(DirEntry {
+            let mut entry = DirEntry {
                 dir_path: self.path,
                 entry_name,
-                entry_type,
-            }));
+                entry_type: entry.entry_type(),
+            };
+            // The symbolic links are backed by regular files.
+            if entry.entry_type == FileType::File && api::ax_read_link(&entry.path()).is_ok() {
+                entry.entry_type = FileType::SymLink;
+            }
+            return Some(Ok(entry));
         }
     }
 }
@@ -120,6 +123,98 @@ impl fmt::Debug for DirEntry<'_> {
     }
 }
 
+/// Iterator over the entries in a directory and, recursively, in its
+/// subdirectories, see [`walk_dir`](super::walk_dir).
+///
+/// The entries of a directory come right after it, depth first. Symbolic
+/// links are returned, but not followed.
+pub struct WalkDir {
+    /// The entries not returned yet, the next one last.
+    stack: Vec<WalkEntry>,
+    /// The directory whose entries are returned next, with their depth.
+    next_dir: Option<(String, usize)>,
+}
+
+/// Entries returned by the [`WalkDir`] iterator.
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    path: String,
+    entry_type: FileType,
+    depth: usize,
+}
+
+impl WalkDir {
+    pub(super) fn new(path: &str) -> Self {
+        Self {
+            stack: Vec::new(),
+            next_dir: Some((String::from(path), 1)),
+        }
+    }
+
+    fn push_entries(&mut self, dir: &str, depth: usize) -> Result<()> {
+        let start = self.stack.len();
+        let res = ReadDir::new(dir).and_then(|entries| {
+            for entry in entries {
+                let entry = entry?;
+                self.stack.push(WalkEntry {
+                    path: entry.path(),
+                    entry_type: entry.file_type(),
+                    depth,
+                });
+            }
+            Ok(())
+        });
+        match res {
+            Ok(()) => self.stack[start..].reverse(),
+            Err(_) => self.stack.truncate(start),
+        }
+        res
+    }
+}
+
+impl Iterator for WalkDir {
+    type Item = Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Result<WalkEntry>> {
+        if let Some((dir, depth)) = self.next_dir.take() {
+            if let Err(e) = self.push_entries(&dir, depth) {
+                return Some(Err(e));
+            }
+        }
+        let entry = self.stack.pop()?;
+        if entry.entry_type.is_dir() {
+            self.next_dir = Some((entry.path.clone(), entry.depth + 1));
+        }
+        Some(Ok(entry))
+    }
+}
+
+impl WalkEntry {
+    /// Returns the full path to the file that this entry represents, the
+    /// path given to [`walk_dir`](super::walk_dir) joined with the path of
+    /// the file in it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the bare file name of this entry without any other leading
+    /// path component.
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Returns the file type for the file that this entry points at.
+    pub fn file_type(&self) -> FileType {
+        self.entry_type
+    }
+
+    /// Returns the depth of the entry, 1 for the entries of the directory
+    /// given to [`walk_dir`](super::walk_dir).
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
 impl DirBuilder {
     /// Creates a new set of options with default mode/security settings for all
     /// platforms and also non-recursive.
@@ -64,6 +64,17 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the option for direct I/O, bypassing any buffering, like
+    /// `O_DIRECT`.
+    ///
+    /// Only block device files, e.g., `/dev/sda`, can be opened for direct
+    /// I/O. Their reads and writes must then be at offsets and of lengths that
+    /// are multiples of the block size, 512 bytes.
+    pub fn direct(&mut self, direct: bool) -> &mut Self {
+        self.0.direct(direct);
+        self
+    }
+
     /// Opens a file at `path` with the options specified by `self`.
     pub fn open(&self, path: &str) -> Result<File> {
         api::ax_open_file(path, &self.0).map(|inner| File { inner })
@@ -162,6 +173,39 @@ impl File {
     pub fn metadata(&self) -> Result<Metadata> {
         api::ax_file_attr(&self.inner).map(Metadata)
     }
+
+    /// Acquires an exclusive advisory lock on the file, blocking until it can
+    /// be acquired.
+    ///
+    /// The lock is held by this file handle, and released when it is
+    /// unlocked or dropped, or when the task which acquired it exits. A lock
+    /// already held by this handle is replaced.
+    pub fn lock(&self) -> Result<()> {
+        api::ax_lock_file(&self.inner, true, true)
+    }
+
+    /// Acquires a shared advisory lock on the file, blocking until it can be
+    /// acquired.
+    pub fn lock_shared(&self) -> Result<()> {
+        api::ax_lock_file(&self.inner, false, true)
+    }
+
+    /// Tries to acquire an exclusive advisory lock on the file, returning a
+    /// `WouldBlock` error if another handle holds a lock on it.
+    pub fn try_lock(&self) -> Result<()> {
+        api::ax_lock_file(&self.inner, true, false)
+    }
+
+    /// Tries to acquire a shared advisory lock on the file, returning a
+    /// `WouldBlock` error if another handle holds an exclusive lock on it.
+    pub fn try_lock_shared(&self) -> Result<()> {
+        api::ax_lock_file(&self.inner, false, false)
+    }
+
+    /// Releases the lock held by this file handle, if any.
+    pub fn unlock(&self) -> Result<()> {
+        api::ax_unlock_file(&self.inner)
+    }
 }
 
 impl Read for File {
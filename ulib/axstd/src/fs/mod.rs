@@ -8,7 +8,7 @@ use crate::io::{self, prelude::*};
 #[cfg(feature = "alloc")]
 use alloc::{string::String, vec::Vec};
 
-pub use self::dir::{DirBuilder, DirEntry, ReadDir};
+pub use self::dir::{DirBuilder, DirEntry, ReadDir, WalkDir, WalkEntry};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
 /// Read the entire contents of a file into a bytes vector.
@@ -47,6 +47,12 @@ pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
 }
 
+/// Returns an iterator over the entries within a directory and,
+/// recursively, within its subdirectories, e.g., to search for files.
+pub fn walk_dir(path: &str) -> WalkDir {
+    WalkDir::new(path)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -75,3 +81,25 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     arceos_api::fs::ax_rename(old, new)
 }
+
+/// Creates a new hard link on the filesystem.
+///
+/// The `link` path will be a link pointing to the `original` path. Hard links
+/// are only supported between the files of a same RAM filesystem, e.g., in
+/// `/tmp`.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_hard_link(original, link)
+}
+
+/// Creates a new symbolic link on the filesystem.
+///
+/// The `link` path will be a symbolic link pointing to the `original` path,
+/// which does not need to exist.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_symlink(original, link)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    arceos_api::fs::ax_read_link(path)
+}
@@ -0,0 +1,236 @@
+//! Decoding of the keys sent by terminals.
+//!
+//! Terminals send keys other than characters, e.g., arrow keys, as ANSI
+//! escape sequences like `ESC [ A`. [`KeyDecoder`] turns the input bytes back
+//! into [`Key`]s.
+//!
+//! This module only depends on `core`.
+
+const ESC: u8 = 0x1b;
+
+/// The maximum length of an escape sequence, longer ones are reported as
+/// [`Key::Unknown`].
+const MAX_SEQ_LEN: usize = 16;
+
+/// A key pressed on the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A byte not in an escape sequence, including control characters such
+    /// as `\n` and `\x7f` (backspace). Non-ASCII characters arrive as several
+    /// bytes of UTF-8.
+    Char(u8),
+    /// A byte preceded by `ESC`, which most terminals send for the byte typed
+    /// with Alt.
+    Alt(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    /// The start of pasted text, in the bracketed paste mode (`ESC [ ? 2004 h`).
+    PasteStart,
+    /// The end of pasted text, in the bracketed paste mode.
+    PasteEnd,
+    /// An escape sequence not recognized.
+    Unknown,
+}
+
+/// Decodes input bytes into [`Key`]s.
+///
+/// A lone `ESC` is only decoded after the next byte is received, since it
+/// starts the escape sequences.
+pub struct KeyDecoder {
+    seq: [u8; MAX_SEQ_LEN],
+    len: usize,
+}
+
+impl KeyDecoder {
+    /// Creates a new decoder.
+    pub const fn new() -> Self {
+        Self {
+            seq: [0; MAX_SEQ_LEN],
+            len: 0,
+        }
+    }
+
+    /// Whether an escape sequence is partially received.
+    pub fn is_pending(&self) -> bool {
+        self.len > 0
+    }
+
+    /// Feeds a byte to the decoder, returns the key if it completes one.
+    pub fn push(&mut self, c: u8) -> Option<Key> {
+        let key = match self.len {
+            0 if c == ESC => None,
+            0 => Some(Key::Char(c)),
+            // `ESC [` starts a control sequence, and `ESC O` is followed by
+            // a single byte.
+            1 if c == b'[' || c == b'O' => None,
+            1 => Some(Key::Alt(c)),
+            _ if self.seq[1] == b'O' => Some(ss3_key(c)),
+            // The final byte of a control sequence.
+            _ if (0x40..=0x7e).contains(&c) => Some(csi_key(&self.seq[2..self.len], c)),
+            MAX_SEQ_LEN => Some(Key::Unknown),
+            _ => None,
+        };
+        if key.is_some() {
+            self.len = 0;
+        } else {
+            self.seq[self.len] = c;
+            self.len += 1;
+        }
+        key
+    }
+}
+
+impl Default for KeyDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes `ESC O <c>`, sent for some keys in the application cursor mode.
+fn ss3_key(c: u8) -> Key {
+    match c {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        _ => Key::Unknown,
+    }
+}
+
+/// Decodes `ESC [ <params> <c>`. Modifiers (the second parameter) are
+/// ignored, e.g., Ctrl+Up is decoded as [`Key::Up`].
+fn csi_key(params: &[u8], c: u8) -> Key {
+    let first = params.split(|&c| c == b';').next().unwrap_or_default();
+    let param = first.iter().try_fold(0u32, |n, &d| {
+        d.is_ascii_digit()
+            .then(|| n.saturating_mul(10).saturating_add((d - b'0') as u32))
+    });
+    match (c, param) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) => Key::Home,
+        (b'F', _) => Key::End,
+        (b'~', Some(1 | 7)) => Key::Home,
+        (b'~', Some(2)) => Key::Insert,
+        (b'~', Some(3)) => Key::Delete,
+        (b'~', Some(4 | 8)) => Key::End,
+        (b'~', Some(5)) => Key::PageUp,
+        (b'~', Some(6)) => Key::PageDown,
+        (b'~', Some(200)) => Key::PasteStart,
+        (b'~', Some(201)) => Key::PasteEnd,
+        _ => Key::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the bytes one by one, returning the keys decoded.
+    fn decode(decoder: &mut KeyDecoder, bytes: &[u8]) -> Vec<Key> {
+        bytes.iter().filter_map(|&c| decoder.push(c)).collect()
+    }
+
+    #[test]
+    fn test_csi() {
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[A\x1b[B\x1b[C\x1b[D"),
+            [Key::Up, Key::Down, Key::Right, Key::Left]
+        );
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[H\x1b[F\x1b[1~\x1b[4~\x1b[7~\x1b[8~"),
+            [
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End
+            ]
+        );
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[2~\x1b[3~\x1b[5~\x1b[6~"),
+            [Key::Insert, Key::Delete, Key::PageUp, Key::PageDown]
+        );
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[200~\x1b[201~"),
+            [Key::PasteStart, Key::PasteEnd]
+        );
+        // The modifiers are ignored.
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[1;5A\x1b[3;2~"),
+            [Key::Up, Key::Delete]
+        );
+        assert!(!decoder.is_pending());
+    }
+
+    #[test]
+    fn test_ss3() {
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, b"\x1bOA\x1bOB\x1bOC\x1bOD\x1bOH\x1bOF"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::Left,
+                Key::Home,
+                Key::End
+            ]
+        );
+        assert_eq!(decode(&mut decoder, b"\x1bOx"), [Key::Unknown]);
+        assert!(!decoder.is_pending());
+    }
+
+    #[test]
+    fn test_split() {
+        let mut decoder = KeyDecoder::new();
+        for &c in b"\x1b[3" {
+            assert_eq!(decoder.push(c), None);
+            assert!(decoder.is_pending());
+        }
+        assert_eq!(decoder.push(b'~'), Some(Key::Delete));
+        assert!(!decoder.is_pending());
+
+        // A lone `ESC` waits for the next byte.
+        assert_eq!(decoder.push(ESC), None);
+        assert!(decoder.is_pending());
+        assert_eq!(decoder.push(b'x'), Some(Key::Alt(b'x')));
+        assert_eq!(
+            decode(&mut decoder, b"a\n\x7f"),
+            [Key::Char(b'a'), Key::Char(b'\n'), Key::Char(0x7f)]
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[99~\x1b[Z\x1b[?1~"),
+            [Key::Unknown, Key::Unknown, Key::Unknown]
+        );
+
+        // A sequence too long is cut, and the bytes following it are keys.
+        let mut seq = [b'1'; MAX_SEQ_LEN + 1];
+        seq[..2].copy_from_slice(b"\x1b[");
+        assert_eq!(decode(&mut decoder, &seq), [Key::Unknown]);
+        assert!(!decoder.is_pending());
+        assert_eq!(
+            decode(&mut decoder, b"1A"),
+            [Key::Char(b'1'), Key::Char(b'A')]
+        );
+    }
+}
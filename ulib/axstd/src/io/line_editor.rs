@@ -0,0 +1,520 @@
+//! Editing of a line typed on a terminal.
+//!
+//! [`LineEditor`] keeps the line being typed and a cursor in it, and handles
+//! the editing keys, writing the escape sequences that redraw the line on
+//! the terminal. It is driven by the [`Key`]s of a [`KeyDecoder`], the
+//! application handling the other keys, e.g., `Enter` or `Tab`. The lines
+//! entered can be kept in a [`History`] to be recalled with `Up` and `Down`.
+//!
+//! Each character is assumed to take one column, and the line to fit on one
+//! row of the terminal.
+//!
+//! This module only depends on `core` and on the `key` module.
+//!
+//! [`KeyDecoder`]: super::key::KeyDecoder
+
+use super::key::Key;
+
+const ESC: u8 = 0x1b;
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+
+/// Erases from the cursor to the end of the row.
+const ERASE_END: &[u8] = b"\x1b[K";
+
+const CTRL_A: u8 = 0x01;
+const CTRL_B: u8 = 0x02;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_F: u8 = 0x06;
+const CTRL_K: u8 = 0x0b;
+const CTRL_U: u8 = 0x15;
+
+/// Whether a byte is the first one of a UTF-8 character, i.e., is not a
+/// continuation byte.
+const fn is_char_start(b: u8) -> bool {
+    b & 0xc0 != 0x80
+}
+
+/// The number of columns taken by some UTF-8 bytes.
+fn columns(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| is_char_start(b)).count()
+}
+
+/// Writes `ESC [ <n> <c>`, which moves the cursor by `n` columns or rows.
+fn move_cursor(n: usize, c: u8, out: &mut impl FnMut(&[u8])) {
+    if n == 0 {
+        return;
+    }
+    let mut seq = [0; 24];
+    let mut digits = [0; 20];
+    let mut m = n;
+    let mut num_digits = 0;
+    while m > 0 {
+        digits[num_digits] = b'0' + (m % 10) as u8;
+        m /= 10;
+        num_digits += 1;
+    }
+    seq[..2].copy_from_slice(&[ESC, b'[']);
+    for i in 0..num_digits {
+        seq[2 + i] = digits[num_digits - 1 - i];
+    }
+    seq[2 + num_digits] = c;
+    out(&seq[..3 + num_digits]);
+}
+
+/// A line of at most `N` bytes being edited.
+///
+/// The output, i.e., the echo and the escape sequences, is written with the
+/// `out` argument of the methods.
+pub struct LineEditor<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// The position of the cursor in `buf`, always at the start of a
+    /// character.
+    cursor: usize,
+    /// The bytes received of an incomplete UTF-8 character.
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+impl<const N: usize> LineEditor<N> {
+    /// Creates an empty line.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            cursor: 0,
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    /// Returns the line.
+    pub fn line(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Returns the position of the cursor in the line, in bytes.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Empties the line, e.g., once it is entered. Nothing is written.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.cursor = 0;
+        self.partial_len = 0;
+    }
+
+    /// Replaces the line with `bytes`, as much as fits, and moves the cursor
+    /// to its end, e.g., to recall a line from the [`History`].
+    pub fn set_line(&mut self, bytes: &[u8], out: &mut impl FnMut(&[u8])) {
+        self.move_home(out);
+        self.clear();
+        let len = self.insert_bytes(bytes);
+        out(&self.buf[..len]);
+        out(ERASE_END);
+    }
+
+    /// Handles an editing key. Returns `false` if the key is not one, for the
+    /// caller to handle it.
+    ///
+    /// The editing keys are the printable characters, `Backspace`, `Delete`,
+    /// the arrows `Left` and `Right`, `Home` and `End`, and the Emacs-like
+    /// `Ctrl-A`, `Ctrl-E`, `Ctrl-B`, `Ctrl-F`, `Ctrl-D`, `Ctrl-K` (erasing to
+    /// the end of the line) and `Ctrl-U` (erasing to its start).
+    pub fn edit(&mut self, key: Key, out: &mut impl FnMut(&[u8])) -> bool {
+        match key {
+            Key::Char(c) if c >= 0x80 => self.input_utf8(c, out),
+            Key::Char(c) if c >= b' ' && c != DEL => self.insert(&[c], out),
+            Key::Char(BS | DEL) => self.backspace(out),
+            Key::Delete | Key::Char(CTRL_D) => self.delete(out),
+            Key::Left | Key::Char(CTRL_B) => self.move_left(out),
+            Key::Right | Key::Char(CTRL_F) => self.move_right(out),
+            Key::Home | Key::Char(CTRL_A) => self.move_home(out),
+            Key::End | Key::Char(CTRL_E) => self.move_end(out),
+            Key::Char(CTRL_K) => self.erase_to_end(out),
+            Key::Char(CTRL_U) => self.erase_to_start(out),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Inserts bytes at the cursor, as much as fits.
+    pub fn insert(&mut self, bytes: &[u8], out: &mut impl FnMut(&[u8])) {
+        let len = self.insert_bytes(bytes);
+        out(&self.buf[self.cursor - len..self.cursor]);
+        if self.cursor < self.len {
+            self.redraw_tail(out);
+        }
+    }
+
+    /// Replaces the bytes from `start` to the cursor with `bytes`, e.g., to
+    /// complete a word.
+    pub fn replace_before_cursor(
+        &mut self,
+        start: usize,
+        bytes: &[u8],
+        out: &mut impl FnMut(&[u8]),
+    ) {
+        let start = start.min(self.cursor);
+        move_cursor(columns(&self.buf[start..self.cursor]), b'D', out);
+        self.remove(start, self.cursor);
+        self.cursor = start;
+        let len = self.insert_bytes(bytes);
+        out(&self.buf[start..start + len]);
+        self.redraw_tail(out);
+    }
+
+    /// Inserts bytes at the cursor in the buffer, as much as fits at the
+    /// start of a character, and moves the cursor after them. Returns the
+    /// number of bytes inserted.
+    fn insert_bytes(&mut self, bytes: &[u8]) -> usize {
+        let mut len = bytes.len().min(N - self.len);
+        while len < bytes.len() && len > 0 && !is_char_start(bytes[len]) {
+            len -= 1;
+        }
+        self.buf
+            .copy_within(self.cursor..self.len, self.cursor + len);
+        self.buf[self.cursor..self.cursor + len].copy_from_slice(&bytes[..len]);
+        self.cursor += len;
+        self.len += len;
+        len
+    }
+
+    /// Removes the bytes from `start` to `end`, leaving the cursor to the
+    /// caller.
+    fn remove(&mut self, start: usize, end: usize) {
+        self.buf.copy_within(end..self.len, start);
+        self.len -= end - start;
+    }
+
+    /// Writes the line from the cursor, erasing the rest of the row, and
+    /// moves back to the cursor.
+    fn redraw_tail(&self, out: &mut impl FnMut(&[u8])) {
+        let tail = &self.buf[self.cursor..self.len];
+        out(tail);
+        out(ERASE_END);
+        move_cursor(columns(tail), b'D', out);
+    }
+
+    /// Buffers the bytes of a UTF-8 character until it is complete.
+    fn input_utf8(&mut self, c: u8, out: &mut impl FnMut(&[u8])) {
+        if is_char_start(c) {
+            self.partial_len = 0;
+        } else if self.partial_len == 0 {
+            // A continuation byte without a first one.
+            return;
+        }
+        self.partial[self.partial_len] = c;
+        self.partial_len += 1;
+        let char_len = match self.partial[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        if self.partial_len == char_len {
+            let partial = self.partial;
+            self.insert(&partial[..char_len], out);
+            self.partial_len = 0;
+        }
+    }
+
+    /// Returns the start of the character before the cursor.
+    fn prev_char(&self) -> usize {
+        (0..self.cursor)
+            .rev()
+            .find(|&i| is_char_start(self.buf[i]))
+            .unwrap_or(0)
+    }
+
+    /// Returns the end of the character at the cursor.
+    fn next_char(&self) -> usize {
+        (self.cursor + 1..self.len)
+            .find(|&i| is_char_start(self.buf[i]))
+            .unwrap_or(self.len)
+    }
+
+    fn backspace(&mut self, out: &mut impl FnMut(&[u8])) {
+        if self.cursor == 0 {
+            return;
+        }
+        let start = self.prev_char();
+        self.remove(start, self.cursor);
+        self.cursor = start;
+        out(&[BS]);
+        self.redraw_tail(out);
+    }
+
+    fn delete(&mut self, out: &mut impl FnMut(&[u8])) {
+        if self.cursor == self.len {
+            return;
+        }
+        self.remove(self.cursor, self.next_char());
+        self.redraw_tail(out);
+    }
+
+    fn move_left(&mut self, out: &mut impl FnMut(&[u8])) {
+        if self.cursor > 0 {
+            self.cursor = self.prev_char();
+            move_cursor(1, b'D', out);
+        }
+    }
+
+    fn move_right(&mut self, out: &mut impl FnMut(&[u8])) {
+        if self.cursor < self.len {
+            self.cursor = self.next_char();
+            move_cursor(1, b'C', out);
+        }
+    }
+
+    fn move_home(&mut self, out: &mut impl FnMut(&[u8])) {
+        move_cursor(columns(&self.buf[..self.cursor]), b'D', out);
+        self.cursor = 0;
+    }
+
+    fn move_end(&mut self, out: &mut impl FnMut(&[u8])) {
+        move_cursor(columns(&self.buf[self.cursor..self.len]), b'C', out);
+        self.cursor = self.len;
+    }
+
+    fn erase_to_end(&mut self, out: &mut impl FnMut(&[u8])) {
+        self.len = self.cursor;
+        out(ERASE_END);
+    }
+
+    fn erase_to_start(&mut self, out: &mut impl FnMut(&[u8])) {
+        move_cursor(columns(&self.buf[..self.cursor]), b'D', out);
+        self.remove(0, self.cursor);
+        self.cursor = 0;
+        self.redraw_tail(out);
+    }
+}
+
+impl<const N: usize> Default for LineEditor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The `M` last lines entered, of at most `N` bytes each.
+///
+/// The lines are recalled from the latest one with [`History::older`] and
+/// back with [`History::newer`], which returns the line being typed when the
+/// recall started once past the latest line. The lines are copied, so editing
+/// a recalled line does not change the history.
+pub struct History<const N: usize, const M: usize> {
+    lines: [[u8; N]; M],
+    lens: [usize; M],
+    /// The number of lines kept.
+    count: usize,
+    /// The slot of the next line pushed.
+    next: usize,
+    /// How many lines back the line recalled is, 0 if none is.
+    pos: usize,
+    /// The line being typed when the recall started.
+    draft: [u8; N],
+    draft_len: usize,
+}
+
+impl<const N: usize, const M: usize> History<N, M> {
+    /// Creates an empty history.
+    pub const fn new() -> Self {
+        Self {
+            lines: [[0; N]; M],
+            lens: [0; M],
+            count: 0,
+            next: 0,
+            pos: 0,
+            draft: [0; N],
+            draft_len: 0,
+        }
+    }
+
+    /// Adds an entered line, dropping the oldest one if the history is full,
+    /// and ends the recall. Empty lines and repeats of the latest line are
+    /// not added.
+    pub fn push(&mut self, line: &[u8]) {
+        self.pos = 0;
+        if line.is_empty() || M == 0 || (self.count > 0 && self.get(1) == line) {
+            return;
+        }
+        let len = line.len().min(N);
+        self.lines[self.next][..len].copy_from_slice(&line[..len]);
+        self.lens[self.next] = len;
+        self.next = (self.next + 1) % M;
+        self.count = (self.count + 1).min(M);
+    }
+
+    /// Recalls the line before the one recalled, or the latest line if none
+    /// is, `line` being the line being typed. Returns `None` at the oldest
+    /// line.
+    pub fn older(&mut self, line: &[u8]) -> Option<&[u8]> {
+        if self.pos == self.count {
+            return None;
+        }
+        if self.pos == 0 {
+            self.draft_len = line.len().min(N);
+            self.draft[..self.draft_len].copy_from_slice(&line[..self.draft_len]);
+        }
+        self.pos += 1;
+        Some(self.get(self.pos))
+    }
+
+    /// Recalls the line after the one recalled, or the line being typed when
+    /// the recall started after the latest line. Returns `None` if no line is
+    /// recalled.
+    pub fn newer(&mut self) -> Option<&[u8]> {
+        match self.pos {
+            0 => None,
+            1 => {
+                self.pos = 0;
+                Some(&self.draft[..self.draft_len])
+            }
+            _ => {
+                self.pos -= 1;
+                Some(self.get(self.pos))
+            }
+        }
+    }
+
+    /// Returns the line `back` lines before the next one, in `1..=count`.
+    fn get(&self, back: usize) -> &[u8] {
+        let slot = (self.next + M - back) % M;
+        &self.lines[slot][..self.lens[slot]]
+    }
+}
+
+impl<const N: usize, const M: usize> Default for History<N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the keys to the editor, returning the output.
+    fn edit<const N: usize>(editor: &mut LineEditor<N>, keys: &[Key]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &key in keys {
+            assert!(editor.edit(key, &mut |bytes| out.extend_from_slice(bytes)));
+        }
+        out
+    }
+
+    fn chars(s: &str) -> Vec<Key> {
+        s.bytes().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut editor = LineEditor::<16>::new();
+        assert_eq!(edit(&mut editor, &chars("helo")), b"helo");
+        assert_eq!(edit(&mut editor, &[Key::Left]), b"\x1b[1D");
+        // The tail is redrawn after the character inserted.
+        assert_eq!(edit(&mut editor, &chars("l")), b"lo\x1b[K\x1b[1D");
+        assert_eq!(editor.line(), b"hello");
+        assert_eq!(editor.cursor(), 4);
+
+        assert_eq!(edit(&mut editor, &[Key::Home]), b"\x1b[4D");
+        edit(&mut editor, &chars("> "));
+        assert_eq!(editor.line(), b"> hello");
+        assert_eq!(editor.cursor(), 2);
+
+        // UTF-8 characters are inserted once complete, and are one column.
+        edit(&mut editor, &[Key::End, Key::Left]);
+        edit(&mut editor, &chars("é"));
+        assert_eq!(editor.line(), "> helléo".as_bytes());
+        assert_eq!(edit(&mut editor, &[Key::Left]), b"\x1b[1D");
+        assert_eq!(editor.cursor(), 6);
+
+        // The bytes not fitting are dropped.
+        edit(&mut editor, &[Key::End]);
+        edit(&mut editor, &chars("0123456789"));
+        assert_eq!(editor.line(), "> helléo0123456".as_bytes());
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut editor = LineEditor::<16>::new();
+        edit(&mut editor, &chars("hello world"));
+        edit(&mut editor, &[Key::Left; 6]);
+        assert_eq!(
+            edit(&mut editor, &[Key::Char(DEL)]),
+            b"\x08 world\x1b[K\x1b[6D"
+        );
+        assert_eq!(editor.line(), b"hell world");
+        assert_eq!(editor.cursor(), 4);
+
+        assert_eq!(edit(&mut editor, &[Key::Delete]), b"world\x1b[K\x1b[5D");
+        assert_eq!(editor.line(), b"hellworld");
+        assert_eq!(editor.cursor(), 4);
+
+        // Nothing is before the start or after the end.
+        edit(&mut editor, &[Key::Home]);
+        assert!(edit(&mut editor, &[Key::Char(DEL), Key::Left]).is_empty());
+        edit(&mut editor, &[Key::End]);
+        assert!(edit(&mut editor, &[Key::Delete, Key::Right]).is_empty());
+        assert_eq!(editor.line(), b"hellworld");
+
+        edit(&mut editor, &[Key::Left; 5]);
+        edit(&mut editor, &[Key::Char(CTRL_K)]);
+        assert_eq!(editor.line(), b"hell");
+        edit(&mut editor, &[Key::Left; 2]);
+        edit(&mut editor, &[Key::Char(CTRL_U)]);
+        assert_eq!(editor.line(), b"ll");
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::<16, 3>::new();
+        assert_eq!(history.older(b"typed"), None);
+        for line in ["a", "b", "", "b", "c", "d"] {
+            history.push(line.as_bytes());
+        }
+
+        // The oldest line is dropped, and so are the empty and repeated ones.
+        assert_eq!(history.older(b"typed"), Some(&b"d"[..]));
+        assert_eq!(history.older(b"d"), Some(&b"c"[..]));
+        assert_eq!(history.older(b"c"), Some(&b"b"[..]));
+        assert_eq!(history.older(b"b"), None);
+        assert_eq!(history.newer(), Some(&b"c"[..]));
+        assert_eq!(history.newer(), Some(&b"d"[..]));
+        assert_eq!(history.newer(), Some(&b"typed"[..]));
+        assert_eq!(history.newer(), None);
+
+        // Entering a line ends the recall.
+        history.older(b"");
+        history.push(b"e");
+        assert_eq!(history.older(b""), Some(&b"e"[..]));
+    }
+
+    #[test]
+    fn test_edit_recalled() {
+        let mut editor = LineEditor::<16>::new();
+        let mut history = History::<16, 4>::new();
+        history.push(b"ls /");
+        edit(&mut editor, &chars("cat"));
+        edit(&mut editor, &[Key::Left]);
+
+        let mut out = Vec::new();
+        let line = history.older(editor.line()).unwrap();
+        editor.set_line(line, &mut |bytes| out.extend_from_slice(bytes));
+        assert_eq!(out, b"\x1b[2Dls /\x1b[K");
+        assert_eq!(editor.cursor(), 4);
+
+        edit(&mut editor, &chars("tmp"));
+        edit(&mut editor, &[Key::Home, Key::Delete, Key::Delete]);
+        edit(&mut editor, &chars("cd"));
+        assert_eq!(editor.line(), b"cd /tmp");
+
+        // The recalled line is unchanged, and the edited one is added.
+        history.push(editor.line());
+        assert_eq!(history.older(b""), Some(&b"cd /tmp"[..]));
+        assert_eq!(history.older(b""), Some(&b"ls /"[..]));
+    }
+}
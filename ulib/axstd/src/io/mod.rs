@@ -1,10 +1,14 @@
 //! Traits, helpers, and type definitions for core I/O functionality.
 
+mod key;
+mod line_editor;
 mod stdio;
 
 pub use axio::prelude;
 pub use axio::{BufRead, BufReader, Error, Read, Seek, SeekFrom, Write};
 
+pub use self::key::{Key, KeyDecoder};
+pub use self::line_editor::{History, LineEditor};
 #[doc(hidden)]
 pub use self::stdio::__print_impl;
 pub use self::stdio::{Stdin, StdinLock, Stdout, StdoutLock, stdin, stdout};
@@ -1,4 +1,6 @@
-use crate::io::{self, BufReader, prelude::*};
+use core::time::Duration;
+
+use crate::io::{self, BufReader, Key, KeyDecoder, prelude::*};
 use crate::sync::{Mutex, MutexGuard};
 
 #[cfg(feature = "alloc")]
@@ -62,6 +64,38 @@ impl Stdin {
         }
     }
 
+    /// Blocks until a key is pressed, and returns it.
+    ///
+    /// The escape sequences sent for keys like arrows are decoded, see
+    /// [`KeyDecoder`]. The console should be in the raw mode, otherwise the
+    /// keys are only received after a line is entered.
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        let mut decoder = KeyDecoder::new();
+        let mut c = [0];
+        loop {
+            if self.read(&mut c)? == 0 {
+                continue;
+            }
+            if let Some(key) = decoder.push(c[0]) {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Reads input like [`read`](Read::read), but gives up when nothing is
+    /// read within `timeout`.
+    ///
+    /// Returns [`Error::TimedOut`](io::Error::TimedOut) on timeout. A zero
+    /// timeout only reads the input already received, so programs can poll
+    /// for input while doing other work.
+    pub fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let read_len = self.inner.lock().read(buf)?;
+        if buf.is_empty() || read_len > 0 {
+            return Ok(read_len);
+        }
+        arceos_api::stdio::ax_console_read_bytes_timeout(buf, timeout)
+    }
+
     /// Locks this handle and reads a line of input, appending it to the specified buffer.
     #[cfg(feature = "alloc")]
     pub fn read_line(&self, buf: &mut String) -> io::Result<usize> {
@@ -84,16 +118,8 @@ impl Read for Stdin {
         }
 
         // 如果首次没读到东西则:
-        // try again until we got something
-        loop {
-            let read_len = self.inner.lock().read(buf)?;
-            if read_len > 0 {
-                return Ok(read_len);
-            }
-            // 这里每次循环没有读到输入就放弃线程, 也就是阻塞当前线程直到读到为止
-            // 所以理论上当前线程如果没有输入就不会做任何事
-            crate::thread::yield_now();
-        }
+        // 缓冲区已空, 阻塞当前线程直到收到输入 (有中断时由串口中断唤醒)
+        arceos_api::stdio::ax_console_read_bytes_blocking(buf)
     }
 }
 
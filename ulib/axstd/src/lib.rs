@@ -15,6 +15,12 @@
 //!     - `fp-simd`: Enable floating point and SIMD support.
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support.
+//!     - `nested-irq`: Let the IRQs with a higher priority, e.g., the timer IRQ, interrupt the
+//!       IRQ handlers.
+//! - Power management
+//!     - `pm`: Suspend the devices idle for a while at run time.
+//!     - `cpuidle`: Enter the deepest idle state of the CPUs when idle, the timer IRQs of the
+//!       CPUs whose timer stops being broadcast by CPU 0.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
 //!     - `alloc-tlsf`: Use the TLSF allocator.
@@ -27,18 +33,48 @@
 //!     - `sched-fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched-rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched-cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched-det`: Use the deterministic scheduler, to reproduce concurrency bugs.
+//!       Each is the default one, another being selected at boot by `sched=fifo`,
+//!       `sched=rr`, `sched=cfs` or `sched=det` on the command line.
+//!     - `uspace`: Run ELF executables from the file system as user processes.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `fsck`: Check the main FAT filesystem and repair it at boot, before mounting it.
+//!     - `fs-aio`: Enable asynchronous file operations, run on a pool of tasks.
+//!     - `log-file`: Also write logs to a file on the filesystem, with size-based rotation.
+//!     - `kv`: Enable the key-value store on a block device, without a filesystem.
+//!     - `update`: Enable A/B image updates, rolled back if not reported healthy in time.
 //!     - `net`: Enable networking support.
+//!     - `net-rss`: Steer received packets to per-CPU queues by their flow hash.
+//!     - `net-napi`: Poll the NIC with its interrupt masked under load.
+//!     - `netconsole`: Also send the console output over UDP, and accept input over TCP.
 //!     - `dns`: Enable DNS lookup support.
 //!     - `display`: Enable graphics support.
+//!     - `fbcon`: Also show the console output on the display.
+//! - Debugging
+//!     - `selftest`: Run HAL self-tests after platform initialization.
+//!     - `qemu-exit`: Report the exit code to QEMU on shutdown.
+//!     - `earlycon`: Write the console output to the boot UART until the console is up.
+//!     - `harden`: Run-time support of stack protectors and pointer authentication.
+//!     - `console-replay`: Replay console input from a script embedded at build time.
+//!     - `vt`: Multiplex virtual terminals over the console, switched with `Ctrl-A` and a number.
+//!     - `sysrq`: Run diagnostics with `Ctrl-\` and a key on the console, even when the application is stuck.
+//!     - `uart-pio`: Receive from the UART by programmed I/O rather than by DMA.
+//!     - `usb-acm`: Add a USB serial console (CDC-ACM) on the OTG port, as a console sink.
+//!     - `guest-agent`: Serve a subset of the QEMU guest agent protocol on a VirtIO console.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
 //!     - `driver-ramdisk`: Use the RAM disk to emulate the block device.
+//!     - `initramfs`: Use the RAM disk with the image embedded at build time (`AX_INITRAMFS`),
+//!       decompressed at boot if it is compressed, as the root filesystem.
+//!     - `initramfs-verify`: Refuse to boot unless the initramfs image is signed by the key
+//!       embedded at build time (`AX_VERIFY_KEY`).
 //!     - `driver-ixgbe`: Enable the Intel 82599 10Gbit NIC driver.
 //!     - `driver-bcm2835-sdhci`: Enable the BCM2835 SDHCI driver (Raspberry Pi SD card).
+//!     - `driver-virtio-balloon`: Enable the VirtIO memory balloon driver.
+//!     - `driver-virtio-console`: Use VirtIO consoles as the console and extra console ports.
 //! - Logging
 //!     - `log-level-off`: Disable all logging.
 //!     - `log-level-error`, `log-level-warn`, `log-level-info`, `log-level-debug`,
@@ -5,6 +5,8 @@
 //! will shutdown the whole system.
 
 /// Shutdown the whole system.
-pub fn exit(_exit_code: i32) -> ! {
-    arceos_api::sys::ax_terminate();
+///
+/// The exit code is reported to QEMU if the `qemu-exit` feature is enabled.
+pub fn exit(exit_code: i32) -> ! {
+    arceos_api::sys::ax_terminate(exit_code);
 }
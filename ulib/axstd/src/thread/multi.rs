@@ -173,6 +173,15 @@ impl<T> JoinHandle<T> {
         &self.thread
     }
 
+    /// Gives up the CPU time to the associated thread, which runs next if it
+    /// is ready on the current CPU, e.g., after sending it a request, instead
+    /// of a blind [`yield_now`](super::yield_now).
+    ///
+    /// Returns `true` if it switched to the thread.
+    pub fn yield_to(&self) -> bool {
+        api::ax_yield_to(&self.native)
+    }
+
     /// Waits for the associated thread to finish.
     ///
     /// This function will return immediately if the associated thread has
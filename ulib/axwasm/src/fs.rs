@@ -0,0 +1,87 @@
+//! The host functions to access files.
+
+use arceos_api::fs::{self as api, AxOpenOptions};
+use axerrno::LinuxError;
+use wasmi::{Caller, Error, Linker};
+
+use crate::host::{
+    HOST_MODULE, HostState, buf_range, get, insert, linux_err, remove, ret, with_memory,
+};
+
+/// Flag of `fs_open` to open the file for reading.
+pub const FS_READ: i32 = 1 << 0;
+/// Flag of `fs_open` to open the file for writing, created if it does not
+/// exist, and truncated otherwise.
+pub const FS_WRITE: i32 = 1 << 1;
+/// Flag of `fs_open` to open the file for writing at its end, created if it
+/// does not exist.
+pub const FS_APPEND: i32 = 1 << 2;
+
+fn open_options(flags: i32) -> Option<AxOpenOptions> {
+    if flags & !(FS_READ | FS_WRITE | FS_APPEND) != 0 || flags == 0 {
+        return None;
+    }
+    let mut opts = AxOpenOptions::new();
+    opts.read(flags & FS_READ != 0);
+    if flags & FS_APPEND != 0 {
+        opts.append(true);
+        opts.create(true);
+    } else if flags & FS_WRITE != 0 {
+        opts.write(true);
+        opts.create(true);
+        opts.truncate(true);
+    }
+    Some(opts)
+}
+
+/// Adds the `fs_*` host functions to `linker`.
+pub(crate) fn link(linker: &mut Linker<HostState>) -> Result<(), Error> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "fs_open",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, flags: i32| {
+            with_memory(&mut caller, |data, state| {
+                let path = core::str::from_utf8(&data[buf_range(data, ptr, len)?]);
+                let res = match (path, open_options(flags)) {
+                    (Ok(path), Some(opts)) => api::ax_open_file(path, &opts)
+                        .map(|file| insert(&mut state.files, file))
+                        .map_err(linux_err),
+                    _ => Err(LinuxError::EINVAL),
+                };
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "fs_read",
+        |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let range = buf_range(data, ptr, len)?;
+                let res = get(&mut state.files, fd)
+                    .and_then(|file| api::ax_read_file(file, &mut data[range]).map_err(linux_err));
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "fs_write",
+        |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let buf = &data[buf_range(data, ptr, len)?];
+                let res = get(&mut state.files, fd)
+                    .and_then(|file| api::ax_write_file(file, buf).map_err(linux_err));
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "fs_close",
+        |mut caller: Caller<'_, HostState>, fd: i32| {
+            ret(remove(&mut caller.data_mut().files, fd).map(|_| 0))
+        },
+    )?;
+    Ok(())
+}
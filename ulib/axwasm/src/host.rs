@@ -0,0 +1,167 @@
+//! The host functions available to all the modules.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::time::Duration;
+
+use arceos_api::{stdio, task, time};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use wasmi::{Caller, Error, Extern, Linker};
+
+/// The name of the module the host functions are imported from.
+pub(crate) const HOST_MODULE: &str = "arceos";
+
+/// The state of a running module, kept by the store.
+pub(crate) struct HostState {
+    args: Vec<String>,
+    #[cfg(feature = "fs")]
+    pub(crate) files: Vec<Option<arceos_api::fs::AxFileHandle>>,
+    #[cfg(feature = "net")]
+    pub(crate) sockets: Vec<Option<arceos_api::net::AxTcpSocketHandle>>,
+}
+
+impl HostState {
+    pub(crate) fn new(args: Vec<String>) -> Self {
+        Self {
+            args,
+            #[cfg(feature = "fs")]
+            files: Vec::new(),
+            #[cfg(feature = "net")]
+            sockets: Vec::new(),
+        }
+    }
+}
+
+/// Runs `f` with the exported memory of the module and the host state.
+///
+/// Traps if the module exports no memory.
+pub(crate) fn with_memory<R>(
+    caller: &mut Caller<'_, HostState>,
+    f: impl FnOnce(&mut [u8], &mut HostState) -> R,
+) -> Result<R, Error> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Error::new("no exported memory"))?;
+    let (data, state) = memory.data_and_store_mut(caller);
+    Ok(f(data, state))
+}
+
+/// Returns the range of a buffer of the module, at `ptr` with `len` bytes.
+///
+/// Traps if it is out of the memory.
+pub(crate) fn buf_range(data: &[u8], ptr: i32, len: i32) -> Result<Range<usize>, Error> {
+    let (ptr, len) = (ptr as u32 as usize, len as u32 as usize);
+    match ptr.checked_add(len) {
+        Some(end) if end <= data.len() => Ok(ptr..end),
+        _ => Err(Error::new("out of bounds memory access")),
+    }
+}
+
+/// Converts the result of a host function to its return value, a negative
+/// Linux error number on errors.
+pub(crate) fn ret(res: LinuxResult<usize>) -> i32 {
+    match res {
+        Ok(n) => n.min(i32::MAX as usize) as i32,
+        Err(e) => -e.code(),
+    }
+}
+
+/// Converts an error of the ArceOS APIs to a Linux error.
+pub(crate) fn linux_err(e: AxError) -> LinuxError {
+    LinuxError::from(e)
+}
+
+/// Adds a table entry (a file or a socket), and returns its descriptor.
+pub(crate) fn insert<T>(table: &mut Vec<Option<T>>, entry: T) -> usize {
+    match table.iter().position(Option::is_none) {
+        Some(fd) => {
+            table[fd] = Some(entry);
+            fd
+        }
+        None => {
+            table.push(Some(entry));
+            table.len() - 1
+        }
+    }
+}
+
+/// Returns the table entry with the given descriptor.
+pub(crate) fn get<T>(table: &mut [Option<T>], fd: i32) -> LinuxResult<&mut T> {
+    usize::try_from(fd)
+        .ok()
+        .and_then(|fd| table.get_mut(fd)?.as_mut())
+        .ok_or(LinuxError::EBADF)
+}
+
+/// Removes the table entry with the given descriptor.
+pub(crate) fn remove<T>(table: &mut [Option<T>], fd: i32) -> LinuxResult<T> {
+    usize::try_from(fd)
+        .ok()
+        .and_then(|fd| table.get_mut(fd)?.take())
+        .ok_or(LinuxError::EBADF)
+}
+
+/// Adds the host functions available to all the modules to `linker`.
+pub(crate) fn link(linker: &mut Linker<HostState>) -> Result<(), Error> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "args_count",
+        |caller: Caller<'_, HostState>| caller.data().args.len() as i32,
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "args_get",
+        |mut caller: Caller<'_, HostState>, index: i32, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let Some(arg) = usize::try_from(index).ok().and_then(|i| state.args.get(i)) else {
+                    return Ok(ret(Err(LinuxError::EINVAL)));
+                };
+                let range = buf_range(data, ptr, len)?;
+                let n = arg.len().min(range.len());
+                data[range.start..range.start + n].copy_from_slice(&arg.as_bytes()[..n]);
+                Ok(ret(Ok(arg.len())))
+            })?
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "console_write",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, _| {
+                let buf = &data[buf_range(data, ptr, len)?];
+                Ok(ret(stdio::ax_console_write_bytes(buf).map_err(linux_err)))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "console_read",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, _| {
+                let range = buf_range(data, ptr, len)?;
+                let buf = &mut data[range];
+                Ok(ret(
+                    stdio::ax_console_read_bytes_blocking(buf).map_err(linux_err)
+                ))
+            })?
+        },
+    )?;
+
+    linker.func_wrap(HOST_MODULE, "time_monotonic_ns", || {
+        time::ax_monotonic_time().as_nanos() as i64
+    })?;
+    linker.func_wrap(HOST_MODULE, "time_wall_ns", || {
+        time::ax_wall_time().as_nanos() as i64
+    })?;
+    linker.func_wrap(HOST_MODULE, "sleep_ns", |ns: i64| {
+        let deadline = time::ax_monotonic_time() + Duration::from_nanos(ns.max(0) as u64);
+        task::ax_sleep_until(deadline);
+    })?;
+    linker.func_wrap(HOST_MODULE, "exit", |code: i32| -> Result<(), Error> {
+        Err(Error::i32_exit(code))
+    })?;
+    Ok(())
+}
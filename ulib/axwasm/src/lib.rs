@@ -0,0 +1,150 @@
+//! WebAssembly application runtime for [ArceOS](https://github.com/arceos-org/arceos).
+//!
+//! It runs WebAssembly modules in the [wasmi] interpreter, so that untrusted
+//! or frequently updated application logic can be deployed to a running
+//! kernel, e.g., downloaded to its filesystem, without reflashing the kernel
+//! image. A module can only reach the kernel through the host functions it
+//! imports, and the ones to access files or the network are only provided if
+//! its [`Sandbox`] allows them, so a module importing others fails to load.
+//!
+//! # Host functions
+//!
+//! The host functions are imported from the `arceos` module. Buffers are
+//! given as an offset in the exported `memory` of the module and a length,
+//! and an access out of it traps. The functions return a negative Linux
+//! error number on errors.
+//!
+//! - `args_count() -> i32`: Returns the number of arguments.
+//! - `args_get(index: i32, buf: i32, len: i32) -> i32`: Copies an argument to
+//!   the buffer, and returns its length, which may be larger than the buffer.
+//! - `console_write(buf: i32, len: i32) -> i32`: Writes to the console.
+//! - `console_read(buf: i32, len: i32) -> i32`: Reads from the console,
+//!   waiting for at least one byte.
+//! - `time_monotonic_ns() -> i64`: Returns the time since boot.
+//! - `time_wall_ns() -> i64`: Returns the time since the Unix epoch.
+//! - `sleep_ns(ns: i64)`: Sleeps for the given time.
+//! - `exit(code: i32)`: Exits with the given exit code.
+//! - `fs_open(path: i32, path_len: i32, flags: i32) -> i32`: Opens a file,
+//!   with the `FS_*` flags, and returns its descriptor.
+//! - `fs_read(fd: i32, buf: i32, len: i32) -> i32`, `fs_write(fd: i32, buf:
+//!   i32, len: i32) -> i32`, `fs_close(fd: i32) -> i32`: Reads, writes and
+//!   closes a file.
+//! - `tcp_connect(addr: i32, addr_len: i32) -> i32`: Connects to an address
+//!   given as `ip:port`, and returns the descriptor of the socket.
+//! - `tcp_send(fd: i32, buf: i32, len: i32) -> i32`, `tcp_recv(fd: i32, buf:
+//!   i32, len: i32) -> i32`, `tcp_close(fd: i32) -> i32`: Sends to, receives
+//!   from, and closes a socket.
+//!
+//! The module is run by calling its exported `_start` function.
+//!
+//! # Cargo Features
+//!
+//! - `fs`: Provide the `fs_*` host functions to modules with
+//!   [`Sandbox::fs`].
+//! - `net`: Provide the `tcp_*` host functions to modules with
+//!   [`Sandbox::net`].
+//!
+//! [wasmi]: https://github.com/wasmi-labs/wasmi
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(feature = "fs")]
+mod fs;
+mod host;
+#[cfg(feature = "net")]
+mod net;
+
+use alloc::string::String;
+
+use arceos_api::modules::axlog;
+use axerrno::{AxResult, ax_err, ax_err_type};
+use wasmi::core::TrapCode;
+use wasmi::{Config, Engine, Linker, Module, Store};
+
+use self::host::HostState;
+
+#[cfg(feature = "fs")]
+pub use self::fs::{FS_APPEND, FS_READ, FS_WRITE};
+
+/// The exit code of a module which traps, e.g., on an out of bounds memory
+/// access.
+pub const TRAP_EXIT_CODE: i32 = 128 + 6;
+
+/// The exit code of a module which runs out of fuel, see [`Sandbox::fuel`].
+pub const FUEL_EXIT_CODE: i32 = 128 + 24;
+
+/// What a WebAssembly module is allowed to do.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    /// Whether the module can access files (with the `fs` feature).
+    pub fs: bool,
+    /// Whether the module can make TCP connections (with the `net` feature).
+    pub net: bool,
+    /// The fuel of the module, roughly the number of instructions it can run
+    /// before it is stopped with [`FUEL_EXIT_CODE`], or `None` to run it
+    /// until it exits.
+    pub fuel: Option<u64>,
+}
+
+/// Runs the WebAssembly module `wasm` in `sandbox`, with the given arguments,
+/// and returns its exit code.
+///
+/// The exit code is that given to the `exit` host function, or 0 if the
+/// `_start` function returns, or [`TRAP_EXIT_CODE`] if the module traps.
+///
+/// Returns [`AxError::InvalidData`](axerrno::AxError::InvalidData) if the
+/// module is invalid or imports a function which is not provided, and
+/// [`AxError::Unsupported`](axerrno::AxError::Unsupported) if `sandbox`
+/// allows something that needs a feature which is not enabled.
+pub fn run(wasm: &[u8], args: &[&str], sandbox: &Sandbox) -> AxResult<i32> {
+    if (sandbox.fs && !cfg!(feature = "fs")) || (sandbox.net && !cfg!(feature = "net")) {
+        return ax_err!(Unsupported, "sandbox needs a feature not enabled");
+    }
+
+    let mut config = Config::default();
+    config.consume_fuel(sandbox.fuel.is_some());
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, wasm).map_err(invalid_module)?;
+    let args = args.iter().map(|&arg| String::from(arg)).collect();
+    let mut store = Store::new(&engine, HostState::new(args));
+    if let Some(fuel) = sandbox.fuel {
+        store.set_fuel(fuel).unwrap();
+    }
+
+    let mut linker = Linker::new(&engine);
+    host::link(&mut linker).map_err(invalid_module)?;
+    #[cfg(feature = "fs")]
+    if sandbox.fs {
+        fs::link(&mut linker).map_err(invalid_module)?;
+    }
+    #[cfg(feature = "net")]
+    if sandbox.net {
+        net::link(&mut linker).map_err(invalid_module)?;
+    }
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .and_then(|instance| instance.start(&mut store))
+        .map_err(invalid_module)?;
+    let start = instance
+        .get_typed_func::<(), ()>(&store, "_start")
+        .map_err(|_| ax_err_type!(NotFound, "no _start function"))?;
+
+    match start.call(&mut store, ()) {
+        Ok(()) => Ok(0),
+        Err(e) => Ok(match (e.i32_exit_status(), e.as_trap_code()) {
+            (Some(code), _) => code,
+            (None, Some(TrapCode::OutOfFuel)) => FUEL_EXIT_CODE,
+            (None, _) => {
+                axlog::warn!("wasm: trapped: {}", e);
+                TRAP_EXIT_CODE
+            }
+        }),
+    }
+}
+
+fn invalid_module(e: wasmi::Error) -> axerrno::AxError {
+    axlog::warn!("wasm: invalid module: {}", e);
+    axerrno::AxError::InvalidData
+}
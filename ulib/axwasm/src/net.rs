@@ -0,0 +1,69 @@
+//! The host functions to make TCP connections.
+
+use core::net::SocketAddr;
+
+use arceos_api::net as api;
+use axerrno::LinuxError;
+use wasmi::{Caller, Error, Linker};
+
+use crate::host::{
+    HOST_MODULE, HostState, buf_range, get, insert, linux_err, remove, ret, with_memory,
+};
+
+/// Adds the `tcp_*` host functions to `linker`.
+pub(crate) fn link(linker: &mut Linker<HostState>) -> Result<(), Error> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_connect",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let addr = core::str::from_utf8(&data[buf_range(data, ptr, len)?]);
+                let res = match addr.ok().and_then(|addr| addr.parse::<SocketAddr>().ok()) {
+                    Some(addr) => {
+                        let socket = api::ax_tcp_socket();
+                        api::ax_tcp_connect(&socket, addr)
+                            .map(|_| insert(&mut state.sockets, socket))
+                            .map_err(linux_err)
+                    }
+                    None => Err(LinuxError::EINVAL),
+                };
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_send",
+        |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let buf = &data[buf_range(data, ptr, len)?];
+                let res = get(&mut state.sockets, fd)
+                    .and_then(|socket| api::ax_tcp_send(socket, buf).map_err(linux_err));
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_recv",
+        |mut caller: Caller<'_, HostState>, fd: i32, ptr: i32, len: i32| {
+            with_memory(&mut caller, |data, state| {
+                let range = buf_range(data, ptr, len)?;
+                let res = get(&mut state.sockets, fd).and_then(|socket| {
+                    api::ax_tcp_recv(socket, &mut data[range]).map_err(linux_err)
+                });
+                Ok(ret(res))
+            })?
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_close",
+        |mut caller: Caller<'_, HostState>, fd: i32| {
+            let res = remove(&mut caller.data_mut().sockets, fd)
+                .and_then(|socket| api::ax_tcp_shutdown(&socket).map_err(linux_err));
+            ret(res.map(|_| 0))
+        },
+    )?;
+    Ok(())
+}